@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use d3_core::graphics::drawing_3d::{legacy_soft::SoftRenderSetup, ClippingCode, CustomClipStack, Point3};
+use d3_core::math::matrix::Matrix4;
+
+const FAR_Z: f32 = 100.0;
+
+fn soft_setup() -> SoftRenderSetup {
+    SoftRenderSetup {
+        aspect_override: None,
+        aspect: 4.0 / 3.0,
+        projection: Default::default(),
+        window_width: 640,
+        window_height: 480,
+        window_width_2: 320.0,
+        window_height_2: 240.0,
+        clipper_custom: CustomClipStack::default(),
+        xform_pipeline: Default::default(),
+        xform: Matrix4::identity(),
+        clipper_far_z: FAR_Z,
+    }
+}
+
+/// Regular polygon in view space big enough to cross every side of the view
+fn polygon(vertices: usize) -> Vec<Point3> {
+    (0..vertices)
+        .map(|i| {
+            let a = i as f32 / vertices as f32 * std::f32::consts::TAU;
+            let mut p = Point3::new(a.cos() * 30.0, a.sin() * 30.0, 10.0 + a.sin() * 5.0);
+            p.compute_clipcode(FAR_Z, &CustomClipStack::default());
+            p
+        })
+        .collect()
+}
+
+fn benchmark_clip_polygon(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clip_polygon");
+    let mut setup = soft_setup();
+
+    for vertices in [3usize, 4, 8, 16, 32, 64] {
+        let points = polygon(vertices);
+
+        let cc_or = points
+            .iter()
+            .fold(ClippingCode::empty(), |cc, p| cc | p.clipping_codes);
+        let cc_and = points
+            .iter()
+            .fold(ClippingCode::all(), |cc, p| cc & p.clipping_codes);
+
+        group.bench_with_input(BenchmarkId::from_parameter(vertices), &points, |bench, points| {
+            bench.iter_batched(
+                || (points.clone(), cc_or, cc_and),
+                |(points, mut cc_or, mut cc_and)| setup.clipper_clip_polygon(points, &mut cc_or, &mut cc_and),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_clip_polygon);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use d3_core::graphics::color_conversion::{
+    convert_1555_to_32, convert_1555_to_32_into, convert_4444_to_32_into, pixel_1555_to_32,
+};
+
+/// Procedural sized surfaces up to a full 640x480 frame
+const SIZES: [(usize, usize); 3] = [(128, 128), (256, 256), (640, 480)];
+
+fn surface(width: usize, height: usize) -> Vec<u16> {
+    (0..width * height).map(|i| (i as u32).wrapping_mul(2654435761) as u16).collect()
+}
+
+fn benchmark_convert_1555(c: &mut Criterion) {
+    let mut group = c.benchmark_group("convert_1555_to_32");
+
+    for (width, height) in SIZES {
+        let source = surface(width, height);
+        let mut dest = vec![0u32; source.len()];
+        let id = format!("{}x{}", width, height);
+
+        // The per-pixel arithmetic the tables replaced
+        group.bench_with_input(BenchmarkId::new("arithmetic", &id), &source, |b, source| {
+            b.iter(|| {
+                for (d, &s) in dest.iter_mut().zip(source.iter()) {
+                    *d = pixel_1555_to_32(s);
+                }
+                black_box(&dest);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("table", &id), &source, |b, source| {
+            b.iter(|| black_box(convert_1555_to_32(source)))
+        });
+
+        group.bench_with_input(BenchmarkId::new("table_into", &id), &source, |b, source| {
+            b.iter(|| {
+                convert_1555_to_32_into(source, &mut dest);
+                black_box(&dest);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn benchmark_convert_4444(c: &mut Criterion) {
+    let source = surface(128, 128);
+    let mut dest = vec![0u32; source.len()];
+
+    c.bench_function("convert_4444_to_32_into_128x128", |b| {
+        b.iter(|| {
+            convert_4444_to_32_into(&source, &mut dest);
+            black_box(&dest);
+        })
+    });
+}
+
+criterion_group!(benches, benchmark_convert_1555, benchmark_convert_4444);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use d3_core::graphics::drawing_3d::{Camera, CustomClipStack, Point3};
+use d3_core::math::angle::{Angle, EulerAngle};
+use d3_core::math::matrix::Matrix;
+use d3_core::math::vector::Vector;
+
+fn rotation(pitch: u16, heading: u16, bank: u16) -> Matrix {
+    Matrix::compute_rotation_3d(&EulerAngle {
+        pitch: Angle(pitch),
+        heading: Angle(heading),
+        bank: Angle(bank),
+    })
+}
+
+fn benchmark_matrix_multiply(c: &mut Criterion) {
+    let a = rotation(0x1000, 0x2000, 0x0800);
+    let b = rotation(0x3000, 0x0400, 0x1800);
+
+    c.bench_function("matrix_multiply", |bench| {
+        bench.iter(|| black_box(a) * black_box(b))
+    });
+}
+
+fn benchmark_point_transform(c: &mut Criterion) {
+    let mut group = c.benchmark_group("point3_transform");
+
+    let camera = Camera {
+        position: Vector::new(10.0, 20.0, -30.0),
+        orientation: rotation(0x0800, 0x1000, 0),
+        ..Default::default()
+    };
+
+    for count in [64usize, 1024, 16384] {
+        let points: Vec<Vector> = (0..count)
+            .map(|i| Vector::new((i % 64) as f32, (i / 64) as f32, (i % 7) as f32 * 10.0))
+            .collect();
+
+        let mut out = vec![Point3::new(0.0, 0.0, 0.0); count];
+
+        group.bench_with_input(BenchmarkId::from_parameter(count), &points, |bench, points| {
+            bench.iter(|| {
+                for (p, v) in out.iter_mut().zip(points) {
+                    p.apply_view_transform(v, &camera, (1000.0, &CustomClipStack::default()));
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_matrix_multiply, benchmark_point_transform);
+criterion_main!(benches);
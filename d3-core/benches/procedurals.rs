@@ -0,0 +1,80 @@
+use std::sync::{atomic::AtomicUsize, Arc};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use d3_core::common::{new_shared_mut_ref, StdSystemClock};
+use d3_core::graphics::detail_settings::DetailSettings;
+use d3_core::graphics::generic_bitmap::GenericBitmap16;
+use d3_core::graphics::procedural::{
+    EmitterSettings, EmitterType, FireEmitterType, ProceduralBitmap16, ProceduralBitmap16Builder,
+    WaterEmitterType,
+};
+use d3_core::graphics::FrameCounter;
+
+const PROC_SIZE: usize = 128;
+
+fn new_procedural(frame_counter: &FrameCounter) -> ProceduralBitmap16 {
+    let base = GenericBitmap16::new(vec![0u16; PROC_SIZE * PROC_SIZE], PROC_SIZE, PROC_SIZE);
+
+    ProceduralBitmap16Builder::default()
+        .name("bench_proc")
+        .dest_bitmap(PROC_SIZE, PROC_SIZE)
+        .detail_settings_ref(new_shared_mut_ref(DetailSettings {}))
+        .frame_counter_ref(frame_counter.clone())
+        .base_bitmap_ref(new_shared_mut_ref(base))
+        .system_clock_ref(Arc::new(StdSystemClock))
+        .build()
+        .unwrap()
+}
+
+fn bench_emitters(c: &mut Criterion, name: &str, kinds: &[EmitterType]) {
+    let frame_counter = FrameCounter::new(AtomicUsize::new(0));
+    let mut proc_bitmap = new_procedural(&frame_counter);
+
+    let settings: Vec<EmitterSettings> = kinds
+        .iter()
+        .map(|&kind| EmitterSettings {
+            kind,
+            ..Default::default()
+        })
+        .collect();
+
+    proc_bitmap.set_water(8, 4);
+    proc_bitmap.set_emitters(&settings);
+
+    let mut time = 0.0;
+
+    c.bench_function(name, |b| {
+        b.iter(|| {
+            proc_bitmap.step(time);
+            time += 1.0 / 30.0;
+            frame_counter.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        })
+    });
+}
+
+fn benchmark_fire_step(c: &mut Criterion) {
+    bench_emitters(
+        c,
+        "procedural_fire_step",
+        &[
+            EmitterType::Fire(FireEmitterType::LineLightning),
+            EmitterType::Fire(FireEmitterType::RisingEmber),
+            EmitterType::Fire(FireEmitterType::Fountain),
+            EmitterType::Fire(FireEmitterType::Cone),
+        ],
+    );
+}
+
+fn benchmark_water_step(c: &mut Criterion) {
+    bench_emitters(
+        c,
+        "procedural_water_step",
+        &[
+            EmitterType::Water(WaterEmitterType::HeightBlob),
+            EmitterType::Water(WaterEmitterType::RainDrops),
+        ],
+    );
+}
+
+criterion_group!(benches, benchmark_fire_step, benchmark_water_step);
+criterion_main!(benches);
@@ -0,0 +1,35 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use d3_core::game::terrain::{HeightMapSettings, Terrain};
+
+fn benchmark_terrain_lod_rebuild(c: &mut Criterion) {
+    let mut terrain = Box::new(Terrain::default());
+    terrain.generate_height_map(&HeightMapSettings::default());
+
+    let mut bump = false;
+
+    // Changing one cell rebuilds the min/max tree, LOD deltas, normals and lighting for the whole map
+    c.bench_function("terrain_lod_rebuild", |b| {
+        b.iter(|| {
+            bump = !bump;
+            terrain.set_cell_heights([(128, 128, if bump { 200 } else { 10 })]);
+        })
+    });
+}
+
+fn benchmark_terrain_generate(c: &mut Criterion) {
+    let mut terrain = Box::new(Terrain::default());
+    let mut seed = 0;
+
+    c.bench_function("terrain_generate", |b| {
+        b.iter(|| {
+            seed += 1;
+            terrain.generate_height_map(&HeightMapSettings {
+                seed,
+                ..Default::default()
+            });
+        })
+    });
+}
+
+criterion_group!(benches, benchmark_terrain_lod_rebuild, benchmark_terrain_generate);
+criterion_main!(benches);
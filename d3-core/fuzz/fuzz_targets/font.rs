@@ -0,0 +1,16 @@
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use d3_core::graphics::drawing_2d::font::Font;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BufReader::new(Cursor::new(data));
+
+    if let Ok(font) = Font::new_from_steam("fuzz".to_string(), &mut reader) {
+        for c in font.get_ascii_range() {
+            let _ = font.get_char_data(c);
+        }
+    }
+});
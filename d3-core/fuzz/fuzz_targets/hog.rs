@@ -0,0 +1,11 @@
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use d3_core::filesystem::hog::Hog;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BufReader::new(Cursor::new(data));
+    let _ = Hog::new_from_stream(&mut reader, "fuzz.hog".to_string());
+});
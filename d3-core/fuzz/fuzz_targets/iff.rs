@@ -0,0 +1,16 @@
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use d3_core::graphics::bitmap::image_format_iff;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BufReader::new(Cursor::new(data));
+
+    if let Ok(resource) = image_format_iff::new(&mut reader, data.len() as u64) {
+        for frame in resource.frames() {
+            let _ = frame.to_argb32();
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use d3_core::graphics::bitmap::{BitmapFormat, image_format_ogf::OgfBitmap};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for format in [BitmapFormat::Fmt1555, BitmapFormat::Fmt4444] {
+        let mut reader = BufReader::new(Cursor::new(data));
+        let _ = OgfBitmap::new(&mut reader, format);
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use std::io::{BufReader, Cursor};
+
+use d3_core::graphics::bitmap::image_format_pcx::PcxBitmap;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BufReader::new(Cursor::new(data));
+    let _ = PcxBitmap::new(&mut reader);
+});
@@ -12,6 +12,70 @@ pub fn new_shared_mut_ref<T>(value: T) -> SharedMutRef<T> {
     Rc::new(RefCell::new(value))
 }
 
+/// Points at something without keeping it alive, for objects referring to rooms and to
+/// each other where a strong reference would make a cycle. Empty when it points at nothing
+pub struct WeakHandle<T> {
+    inner: Weak<RefCell<T>>,
+}
+
+impl<T> WeakHandle<T> {
+    pub fn new() -> Self {
+        Self { inner: Weak::new() }
+    }
+
+    pub fn to(target: &SharedMutRef<T>) -> Self {
+        Self { inner: Rc::downgrade(target) }
+    }
+
+    /// The target if it's still around
+    pub fn upgrade(&self) -> Option<SharedMutRef<T>> {
+        self.inner.upgrade()
+    }
+
+    /// Points at something that's still around
+    pub fn is_valid(&self) -> bool {
+        self.inner.strong_count() > 0
+    }
+
+    /// Points at this exact target
+    pub fn is(&self, target: &SharedMutRef<T>) -> bool {
+        std::ptr::eq(self.inner.as_ptr(), Rc::as_ptr(target))
+    }
+
+    pub fn clear(&mut self) {
+        self.inner = Weak::new();
+    }
+
+    /// Runs `f` on the target if it's still around
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.upgrade().map(|target| f(&target.borrow()))
+    }
+}
+
+impl<T> Default for WeakHandle<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<T> std::fmt::Debug for WeakHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WeakHandle").field("valid", &self.is_valid()).finish()
+    }
+}
+
+impl<T> From<&SharedMutRef<T>> for WeakHandle<T> {
+    fn from(target: &SharedMutRef<T>) -> Self {
+        Self::to(target)
+    }
+}
+
 pub fn unsigned_safe_sub<T>(a: T, b: T) -> T
 where
     T: PartialOrd + Sub<Output = T> + From<u8> + SubAssign,
@@ -27,9 +91,13 @@ pub trait SystemClock: std::fmt::Debug + Send + Sync {
     fn get_ticks(&self) -> u128;
 }
 
+/// Reads the operating system's clock. Browsers don't give wasm32-unknown-unknown one, there
+/// `ManualSystemClock` is fed from the page instead
+#[cfg(not(target_arch = "wasm32"))]
 #[derive(Debug)]
 pub struct StdSystemClock;
 
+#[cfg(not(target_arch = "wasm32"))]
 impl SystemClock for StdSystemClock {
     fn get_ticks(&self) -> u128 {
         let duration_since_epoch = std::time::SystemTime::now()
@@ -38,4 +106,71 @@ impl SystemClock for StdSystemClock {
 
         duration_since_epoch.as_micros()
     }
-}
\ No newline at end of file
+}
+
+/// A clock that only moves when it's told to, by the host's frame timer on targets without a
+/// system clock or by a test that wants time to stand still
+#[derive(Debug, Default)]
+pub struct ManualSystemClock {
+    micros: std::sync::atomic::AtomicU64,
+}
+
+impl ManualSystemClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_seconds(&self, seconds: f64) {
+        self.micros.store((seconds.max(0.0) * 1_000_000.0) as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.micros.fetch_add((seconds.max(0.0) * 1_000_000.0) as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+impl SystemClock for ManualSystemClock {
+    fn get_ticks(&self) -> u128 {
+        self.micros.load(std::sync::atomic::Ordering::Relaxed) as u128
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_test() {
+        crate::test_common::setup();
+
+        let clock = ManualSystemClock::new();
+        assert_eq!(clock.get_ticks(), 0);
+
+        clock.set_seconds(1.5);
+        clock.advance(0.25);
+        assert_eq!(clock.get_ticks(), 1_750_000);
+    }
+
+    #[test]
+    fn weak_handle_test() {
+        crate::test_common::setup();
+
+        let target = new_shared_mut_ref(5);
+        let other = new_shared_mut_ref(5);
+        let mut handle = WeakHandle::to(&target);
+
+        assert!(handle.is_valid());
+        assert!(handle.is(&target) && !handle.is(&other));
+        assert_eq!(handle.with(|v| v + 1), Some(6));
+
+        // Doesn't keep the target alive
+        drop(target);
+        assert!(!handle.is_valid());
+        assert!(handle.upgrade().is_none());
+
+        handle = WeakHandle::from(&other);
+        handle.clear();
+        assert!(!handle.is_valid());
+        assert!(!WeakHandle::<i32>::default().is_valid());
+    }
+}
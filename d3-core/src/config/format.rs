@@ -0,0 +1,151 @@
+//! Just enough TOML for a settings file: `[section]` headers and `key = value` lines holding
+//! integers, floats, booleans and basic strings.
+
+use anyhow::Result;
+
+use super::{ConfigTable, ConfigValue};
+
+fn parse_string(text: &str, line: usize) -> Result<String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .ok_or_else(|| anyhow!("line {}: unterminated string", line))?;
+
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            other => return Err(anyhow!("line {}: bad escape {:?}", line, other)),
+        }
+    }
+
+    Ok(result)
+}
+
+fn parse_value(text: &str, line: usize) -> Result<ConfigValue> {
+    if text.starts_with('"') {
+        return Ok(ConfigValue::String(parse_string(text, line)?));
+    }
+
+    match text {
+        "true" => return Ok(ConfigValue::Bool(true)),
+        "false" => return Ok(ConfigValue::Bool(false)),
+        _ => {}
+    }
+
+    let number = text.replace('_', "");
+
+    if let Ok(v) = number.parse::<i64>() {
+        return Ok(ConfigValue::Int(v));
+    }
+
+    if let Ok(v) = number.parse::<f64>() {
+        return Ok(ConfigValue::Float(v));
+    }
+
+    Err(anyhow!("line {}: can't read value {}", line, text))
+}
+
+/// Drops a trailing comment, leaving `#` inside strings alone
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == '#' {
+            return &line[..i];
+        }
+    }
+
+    line
+}
+
+pub fn parse(text: &str) -> Result<ConfigTable> {
+    let mut table = ConfigTable::new();
+    let mut section = String::new();
+
+    for (i, raw) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let line = strip_comment(raw).trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("line {}: bad section header", line_num))?;
+            section = name.trim().to_string();
+            table.entry(section.clone()).or_default();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {}: expected key = value", line_num))?;
+
+        let value = parse_value(value.trim(), line_num)?;
+
+        table
+            .entry(section.clone())
+            .or_default()
+            .insert(key.trim().to_string(), value);
+    }
+
+    Ok(table)
+}
+
+fn write_value(value: &ConfigValue) -> String {
+    match value {
+        ConfigValue::String(s) => {
+            let escaped = s
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+                .replace('\t', "\\t");
+            format!("\"{}\"", escaped)
+        }
+        // Keep floats looking like floats so they read back the same type
+        ConfigValue::Float(v) if v.fract() == 0.0 && v.is_finite() => format!("{:.1}", v),
+        other => other.to_string(),
+    }
+}
+
+pub fn write(table: &ConfigTable) -> String {
+    let mut text = String::new();
+
+    for (name, section) in table.iter() {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+
+        text.push_str(&format!("[{}]\n", name));
+
+        for (key, value) in section.iter() {
+            text.push_str(&format!("{} = {}\n", key, write_value(value)));
+        }
+    }
+
+    text
+}
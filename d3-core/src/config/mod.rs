@@ -0,0 +1,675 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+
+pub mod format;
+pub mod registry;
+
+/// A single setting as it is stored on disk
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl ConfigValue {
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            ConfigValue::Int(v) => Some(*v),
+            ConfigValue::Bool(v) => Some(*v as i64),
+            ConfigValue::Float(v) => Some(*v as i64),
+            ConfigValue::String(v) => v.trim().parse().ok(),
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            ConfigValue::Int(v) => Some(*v as f64),
+            ConfigValue::Float(v) => Some(*v),
+            ConfigValue::Bool(_) => None,
+            ConfigValue::String(v) => v.trim().parse().ok(),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            ConfigValue::Bool(v) => Some(*v),
+            ConfigValue::Int(v) => Some(*v != 0),
+            ConfigValue::Float(_) => None,
+            ConfigValue::String(v) => match v.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Some(true),
+                "false" | "no" | "off" | "0" => Some(false),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ConfigValue::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Guesses the type of a value typed on the command line
+    pub fn parse_loose(text: &str) -> Self {
+        if let Ok(v) = text.parse::<i64>() {
+            ConfigValue::Int(v)
+        } else if let Ok(v) = text.parse::<f64>() {
+            ConfigValue::Float(v)
+        } else if let Ok(v) = text.parse::<bool>() {
+            ConfigValue::Bool(v)
+        } else {
+            ConfigValue::String(text.to_string())
+        }
+    }
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValue::Int(v) => write!(f, "{}", v),
+            ConfigValue::Float(v) => write!(f, "{}", v),
+            ConfigValue::Bool(v) => write!(f, "{}", v),
+            ConfigValue::String(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Converts a settings field to and from what is stored on disk
+pub trait ConfigField: Sized {
+    fn to_value(&self) -> ConfigValue;
+    fn from_value(value: &ConfigValue) -> Option<Self>;
+}
+
+macro_rules! int_config_field {
+    ($($t:ty),*) => {
+        $(
+            impl ConfigField for $t {
+                fn to_value(&self) -> ConfigValue {
+                    ConfigValue::Int(*self as i64)
+                }
+
+                fn from_value(value: &ConfigValue) -> Option<Self> {
+                    value.as_int().and_then(|v| <$t>::try_from(v).ok())
+                }
+            }
+        )*
+    };
+}
+
+int_config_field!(i32, u32, u16, u8, usize);
+
+impl ConfigField for f32 {
+    fn to_value(&self) -> ConfigValue {
+        ConfigValue::Float(*self as f64)
+    }
+
+    fn from_value(value: &ConfigValue) -> Option<Self> {
+        value.as_float().map(|v| v as f32)
+    }
+}
+
+impl ConfigField for bool {
+    fn to_value(&self) -> ConfigValue {
+        ConfigValue::Bool(*self)
+    }
+
+    fn from_value(value: &ConfigValue) -> Option<Self> {
+        value.as_bool()
+    }
+}
+
+impl ConfigField for String {
+    fn to_value(&self) -> ConfigValue {
+        ConfigValue::String(self.clone())
+    }
+
+    fn from_value(value: &ConfigValue) -> Option<Self> {
+        Some(value.to_string())
+    }
+}
+
+/// Settings by section then key, the form both file formats read and write
+pub type ConfigTable = BTreeMap<String, BTreeMap<String, ConfigValue>>;
+
+/// The groups of settings, systems subscribe to the ones they care about
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ConfigSection {
+    Render,
+    Audio,
+    Input,
+    Network,
+    Detail,
+}
+
+impl ConfigSection {
+    pub const ALL: [ConfigSection; 5] = [
+        ConfigSection::Render,
+        ConfigSection::Audio,
+        ConfigSection::Input,
+        ConfigSection::Network,
+        ConfigSection::Detail,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfigSection::Render => "render",
+            ConfigSection::Audio => "audio",
+            ConfigSection::Input => "input",
+            ConfigSection::Network => "network",
+            ConfigSection::Detail => "detail",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|s| s.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Declares a section of settings with its defaults, and how it maps to keys in the table
+macro_rules! config_section {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident : $t:ty = $default:expr
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name {
+            $(
+                $(#[$field_meta])*
+                pub $field: $t,
+            )*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self {
+                    $($field: $default,)*
+                }
+            }
+        }
+
+        impl $name {
+            pub const KEYS: &'static [&'static str] = &[$(stringify!($field)),*];
+
+            pub fn get(&self, key: &str) -> Option<ConfigValue> {
+                match key {
+                    $(stringify!($field) => Some(self.$field.to_value()),)*
+                    _ => None,
+                }
+            }
+
+            pub fn set(&mut self, key: &str, value: &ConfigValue) -> Result<()> {
+                match key {
+                    $(
+                        stringify!($field) => {
+                            self.$field = <$t as ConfigField>::from_value(value)
+                                .ok_or_else(|| anyhow!("bad value {} for {}", value, key))?;
+                        }
+                    )*
+                    _ => return Err(anyhow!("unknown setting {}", key)),
+                }
+
+                Ok(())
+            }
+
+            fn read(&mut self, section: &BTreeMap<String, ConfigValue>) {
+                for (key, value) in section.iter() {
+                    if let Err(e) = self.set(key, value) {
+                        warn!(target: "fs", "config: {}", e);
+                    }
+                }
+            }
+
+            fn write(&self, section: &mut BTreeMap<String, ConfigValue>) {
+                $(section.insert(stringify!($field).to_string(), self.$field.to_value());)*
+            }
+        }
+    };
+}
+
+config_section! {
+    pub struct RenderSettings {
+        width: u32 = 640,
+        height: u32 = 480,
+        bit_depth: u32 = 16,
+        windowed: bool = false,
+        vsync: bool = true,
+        /// Horizontal field of view in degrees
+        fov: f32 = 72.0,
+        gamma: f32 = 1.0,
+        renderer: String = String::from("software"),
+    }
+}
+
+config_section! {
+    pub struct AudioSettings {
+        enabled: bool = true,
+        master_volume: f32 = 1.0,
+        sfx_volume: f32 = 1.0,
+        music_volume: f32 = 0.5,
+        /// Briefings and robot taunts
+        voice_volume: f32 = 1.0,
+        /// Turn music and sound effects down while a voice is playing
+        ducking: bool = true,
+        /// How far the music drops while ducked
+        music_duck_volume: f32 = 0.3,
+        /// How far sound effects drop while ducked
+        sfx_duck_volume: f32 = 0.7,
+        max_voices: usize = 32,
+        /// Voice lines shown as text on the HUD while they play
+        captions: bool = false,
+    }
+}
+
+config_section! {
+    pub struct InputSettings {
+        mouse_sensitivity: f32 = 1.0,
+        invert_mouse_y: bool = false,
+        joystick_enabled: bool = true,
+        force_feedback: bool = true,
+        force_auto_center: bool = true,
+        force_gain: f32 = 1.0,
+        /// Keys bound to the flight controls, by name
+        key_forward: String = String::from("a"),
+        key_reverse: String = String::from("z"),
+        key_fire_primary: String = String::from("ctrl"),
+        key_fire_secondary: String = String::from("space"),
+        /// Bends shots toward a target near the crosshair, for gamepads
+        aim_assist: bool = false,
+        /// 0 leaves shots alone, 1 snaps them onto the target
+        aim_assist_strength: f32 = 0.5,
+        /// Half angle of the aim assist cone, in degrees
+        aim_assist_cone: f32 = 8.0,
+    }
+}
+
+config_section! {
+    pub struct NetworkSettings {
+        player_name: String = String::from("Pilot"),
+        port: u16 = 2092,
+        /// Bytes per second the server sends each client
+        bandwidth_cap: u32 = 4096,
+        packets_per_second: u32 = 7,
+        /// Whether a server lets its players use aim assist
+        allow_aim_assist: bool = false,
+    }
+}
+
+config_section! {
+    pub struct DetailSettings {
+        /// 0 low, 1 medium, 2 high, 3 very high, 4 custom
+        preset: u8 = 2,
+        terrain_render_distance: f32 = 120.0,
+        pixel_error: f32 = 12.0,
+        object_complexity: u8 = 1,
+        specular_lighting: bool = true,
+        fast_headlight: bool = true,
+        mirrored_surfaces: bool = true,
+        dynamic_lighting: bool = true,
+        fog_enabled: bool = true,
+        coronas_enabled: bool = true,
+        procedurals_enabled: bool = true,
+        powerup_halos: bool = true,
+        scorches_enabled: bool = true,
+        weapon_coronas_enabled: bool = true,
+        /// Dark spots on the floor under objects
+        blob_shadows: bool = true,
+        /// The player ship's model flattened onto the floor, costs a pass over the model
+        ship_shadow: bool = false,
+        /// How sounds played at another rate are resampled, 0 nearest, 1 linear, 2 cubic
+        sound_quality: u8 = 1,
+    }
+}
+
+/// Every setting the game has
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Settings {
+    pub render: RenderSettings,
+    pub audio: AudioSettings,
+    pub input: InputSettings,
+    pub network: NetworkSettings,
+    pub detail: DetailSettings,
+}
+
+impl Settings {
+    pub fn get(&self, section: ConfigSection, key: &str) -> Option<ConfigValue> {
+        match section {
+            ConfigSection::Render => self.render.get(key),
+            ConfigSection::Audio => self.audio.get(key),
+            ConfigSection::Input => self.input.get(key),
+            ConfigSection::Network => self.network.get(key),
+            ConfigSection::Detail => self.detail.get(key),
+        }
+    }
+
+    pub fn set(&mut self, section: ConfigSection, key: &str, value: &ConfigValue) -> Result<()> {
+        match section {
+            ConfigSection::Render => self.render.set(key, value),
+            ConfigSection::Audio => self.audio.set(key, value),
+            ConfigSection::Input => self.input.set(key, value),
+            ConfigSection::Network => self.network.set(key, value),
+            ConfigSection::Detail => self.detail.set(key, value),
+        }
+    }
+
+    /// Which sections differ between two sets of settings
+    pub fn changed_sections(&self, other: &Settings) -> Vec<ConfigSection> {
+        let mut changed = Vec::new();
+
+        if self.render != other.render {
+            changed.push(ConfigSection::Render);
+        }
+        if self.audio != other.audio {
+            changed.push(ConfigSection::Audio);
+        }
+        if self.input != other.input {
+            changed.push(ConfigSection::Input);
+        }
+        if self.network != other.network {
+            changed.push(ConfigSection::Network);
+        }
+        if self.detail != other.detail {
+            changed.push(ConfigSection::Detail);
+        }
+
+        changed
+    }
+
+    pub fn to_table(&self) -> ConfigTable {
+        let mut table = ConfigTable::new();
+
+        self.render.write(table.entry("render".to_string()).or_default());
+        self.audio.write(table.entry("audio".to_string()).or_default());
+        self.input.write(table.entry("input".to_string()).or_default());
+        self.network.write(table.entry("network".to_string()).or_default());
+        self.detail.write(table.entry("detail".to_string()).or_default());
+
+        table
+    }
+
+    /// Reads what it can from a table, unknown sections and keys are skipped with a warning
+    pub fn from_table(table: &ConfigTable) -> Self {
+        let mut settings = Settings::default();
+
+        for (name, section) in table.iter() {
+            match ConfigSection::from_name(name) {
+                Some(ConfigSection::Render) => settings.render.read(section),
+                Some(ConfigSection::Audio) => settings.audio.read(section),
+                Some(ConfigSection::Input) => settings.input.read(section),
+                Some(ConfigSection::Network) => settings.network.read(section),
+                Some(ConfigSection::Detail) => settings.detail.read(section),
+                None => warn!(target: "fs", "config: unknown section {}", name),
+            }
+        }
+
+        settings
+    }
+}
+
+/// How the settings are written to disk
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    /// The original game's text registry
+    Registry,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Registry,
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Result<ConfigTable> {
+        match self {
+            ConfigFormat::Toml => format::parse(text),
+            ConfigFormat::Registry => registry::parse(text),
+        }
+    }
+
+    pub fn write(&self, table: &ConfigTable) -> String {
+        match self {
+            ConfigFormat::Toml => format::write(table),
+            ConfigFormat::Registry => registry::write(table),
+        }
+    }
+}
+
+type ConfigListener = Box<dyn FnMut(&Settings)>;
+
+/// The live settings.  Changes go through `update` so the systems that care can apply them
+/// right away.
+pub struct Config {
+    settings: Settings,
+    path: Option<PathBuf>,
+    listeners: Vec<(ConfigSection, ConfigListener)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            settings: Settings::default(),
+            path: None,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("settings", &self.settings)
+            .field("path", &self.path)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+impl Config {
+    /// Loads settings from a file, a missing file just means defaults
+    pub fn load(path: &Path) -> Result<Self> {
+        let settings = if path.exists() {
+            let text = std::fs::read_to_string(path)?;
+            Settings::from_table(&ConfigFormat::from_path(path).parse(&text)?)
+        } else {
+            debug!(target: "fs", "no config at {}, using defaults", path.display());
+            Settings::default()
+        };
+
+        Ok(Self {
+            settings,
+            path: Some(path.to_path_buf()),
+            listeners: Vec::new(),
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| anyhow!("config has no file to save to"))?;
+        self.save_as(path)
+    }
+
+    pub fn save_as(&self, path: &Path) -> Result<()> {
+        let text = ConfigFormat::from_path(path).write(&self.settings.to_table());
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Calls `listener` with the new settings whenever something in `section` changes
+    pub fn subscribe(&mut self, section: ConfigSection, listener: impl FnMut(&Settings) + 'static) {
+        self.listeners.push((section, Box::new(listener)));
+    }
+
+    /// Changes settings and tells whoever is listening to the sections that changed
+    pub fn update(&mut self, change: impl FnOnce(&mut Settings)) -> Vec<ConfigSection> {
+        let before = self.settings.clone();
+        change(&mut self.settings);
+
+        let changed = before.changed_sections(&self.settings);
+
+        for (section, listener) in self.listeners.iter_mut() {
+            if changed.contains(section) {
+                listener(&self.settings);
+            }
+        }
+
+        changed
+    }
+
+    pub fn set(&mut self, section: ConfigSection, key: &str, value: &ConfigValue) -> Result<()> {
+        let mut result = Ok(());
+        self.update(|s| result = s.set(section, key, value));
+        result
+    }
+
+    /// Applies command line overrides.  Takes `--section.key=value` for any setting, plus the
+    /// original game's `-width`, `-height`, `-windowed`, `-nosound` and `-nomusic` switches.
+    /// Returns the args it didn't recognize.
+    pub fn apply_args<'a>(&mut self, args: &'a [String]) -> Vec<&'a str> {
+        let mut unused = Vec::new();
+        let mut i = 0;
+
+        while i < args.len() {
+            let arg = args[i].as_str();
+            let next = args.get(i + 1).map(|s| s.as_str());
+
+            let handled = if let Some(setting) = arg.strip_prefix("--") {
+                self.apply_override(setting)
+            } else {
+                match (arg.to_ascii_lowercase().as_str(), next) {
+                    ("-width", Some(v)) => {
+                        i += 1;
+                        self.set(ConfigSection::Render, "width", &ConfigValue::parse_loose(v)).is_ok()
+                    }
+                    ("-height", Some(v)) => {
+                        i += 1;
+                        self.set(ConfigSection::Render, "height", &ConfigValue::parse_loose(v)).is_ok()
+                    }
+                    ("-windowed", _) => {
+                        self.update(|s| s.render.windowed = true);
+                        true
+                    }
+                    ("-nosound", _) => {
+                        self.update(|s| s.audio.enabled = false);
+                        true
+                    }
+                    ("-nomusic", _) => {
+                        self.update(|s| s.audio.music_volume = 0.0);
+                        true
+                    }
+                    _ => false,
+                }
+            };
+
+            if !handled {
+                unused.push(arg);
+            }
+
+            i += 1;
+        }
+
+        unused
+    }
+
+    fn apply_override(&mut self, setting: &str) -> bool {
+        let Some((name, value)) = setting.split_once('=') else {
+            return false;
+        };
+
+        let Some((section, key)) = name.split_once('.') else {
+            return false;
+        };
+
+        let Some(section) = ConfigSection::from_name(section) else {
+            return false;
+        };
+
+        match self.set(section, key, &ConfigValue::parse_loose(value)) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(target: "fs", "command line: {}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    #[test]
+    fn listeners_hear_their_sections() {
+        crate::test_common::setup();
+
+        let mut config = Config::default();
+        let render_changes = Rc::new(Cell::new(0));
+
+        let counter = render_changes.clone();
+        config.subscribe(ConfigSection::Render, move |s| {
+            assert_eq!(s.render.width, 1024);
+            counter.set(counter.get() + 1);
+        });
+
+        config.update(|s| s.audio.music_volume = 0.0);
+        assert_eq!(render_changes.get(), 0);
+
+        let changed = config.update(|s| s.render.width = 1024);
+        assert_eq!(changed, vec![ConfigSection::Render]);
+        assert_eq!(render_changes.get(), 1);
+    }
+
+    #[test]
+    fn command_line_overrides() {
+        crate::test_common::setup();
+
+        let mut config = Config::default();
+        let args: Vec<String> = ["-width", "800", "--detail.fog_enabled=false", "-nosound", "-launcher"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let unused = config.apply_args(&args);
+
+        assert_eq!(unused, vec!["-launcher"]);
+        assert_eq!(config.settings().render.width, 800);
+        assert!(!config.settings().detail.fog_enabled);
+        assert!(!config.settings().audio.enabled);
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        crate::test_common::setup();
+
+        let mut settings = Settings::default();
+        settings.network.player_name = String::from("Dravis \"the\" Pilot");
+        settings.render.gamma = 1.5;
+        settings.detail.scorches_enabled = false;
+
+        for format in [ConfigFormat::Toml, ConfigFormat::Registry] {
+            let text = format.write(&settings.to_table());
+            let loaded = Settings::from_table(&format.parse(&text).unwrap());
+            assert_eq!(loaded, settings, "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! The text registry the original game kept its settings in on Linux.
+//! Keys look like `[name]`, strings like `"name"="value"` and numbers like `"name"=dword:1F`.
+//! There are only strings and dwords, so floats are kept as strings and bools as dwords.
+
+use anyhow::Result;
+
+use super::{ConfigTable, ConfigValue};
+
+/// Reads a quoted string, returns it and whatever follows the closing quote
+fn parse_quoted(text: &str) -> Option<(&str, &str)> {
+    let rest = text.strip_prefix('"')?;
+    let end = rest.find('"')?;
+    Some((&rest[..end], &rest[end + 1..]))
+}
+
+pub fn parse(text: &str) -> Result<ConfigTable> {
+    let mut table = ConfigTable::new();
+    let mut key = String::new();
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim_end();
+
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("line {}: bad key", i + 1))?;
+            key = name.to_string();
+            table.entry(key.clone()).or_default();
+            continue;
+        }
+
+        if !line.starts_with('"') {
+            // Expected [ or "
+            continue;
+        }
+
+        let (name, rest) = parse_quoted(line).ok_or_else(|| anyhow!("line {}: bad record name", i + 1))?;
+
+        let rest = rest
+            .strip_prefix('=')
+            .ok_or_else(|| anyhow!("line {}: expected =", i + 1))?;
+
+        let value = if let Some(hex) = rest.strip_prefix("dword:") {
+            let dword = u32::from_str_radix(hex.trim(), 16).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+            ConfigValue::Int(dword as i32 as i64)
+        } else {
+            // The original had no escapes, strings run to the last quote on the line
+            let inner = rest
+                .strip_prefix('"')
+                .and_then(|r| r.strip_suffix('"'))
+                .ok_or_else(|| anyhow!("line {}: bad string record", i + 1))?;
+            ConfigValue::String(inner.to_string())
+        };
+
+        table.entry(key.clone()).or_default().insert(name.to_string(), value);
+    }
+
+    Ok(table)
+}
+
+pub fn write(table: &ConfigTable) -> String {
+    let mut text = String::new();
+
+    for (name, section) in table.iter() {
+        text.push_str(&format!("[{}]\n", name));
+
+        for (key, value) in section.iter() {
+            let record = match value {
+                ConfigValue::Int(v) => format!("\"{}\"=dword:{:X}\n", key, *v as i32 as u32),
+                ConfigValue::Bool(v) => format!("\"{}\"=dword:{:X}\n", key, *v as u32),
+                ConfigValue::Float(v) => format!("\"{}\"=\"{}\"\n", key, v),
+                ConfigValue::String(v) => format!("\"{}\"=\"{}\"\n", key, v),
+            };
+
+            text.push_str(&record);
+        }
+    }
+
+    text
+}
@@ -0,0 +1,350 @@
+/*
+
+Log categories
+
+Log calls name what part of the game they come from with the target,
+`warn!(target: "net", ...)`, and each category has its own verbosity that
+the console can change while the game runs. Calls without a target fall
+into a category by their module path. Everything let through goes into
+a ring of recent lines, which the console window shows and crash reports
+take the tail of.
+
+*/
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::Result;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Log lines kept for the console and crash dumps
+pub const LOG_RING_LINES: usize = 256;
+
+const LEVEL_FILTERS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+lazy_static! {
+    static ref RECENT_LOG: Mutex<LogRing> = Mutex::new(LogRing::new(LOG_RING_LINES));
+}
+
+/// Verbosity of each category, as an index into `LEVEL_FILTERS`
+static CATEGORY_LEVELS: [AtomicUsize; LogCategory::ALL.len()] = [const { AtomicUsize::new(LevelFilter::Info as usize) }; LogCategory::ALL.len()];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LogCategory {
+    Render,
+    Physics,
+    Ai,
+    Net,
+    Fs,
+    Audio,
+    Input,
+    /// Everything else
+    Game,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 8] = [
+        LogCategory::Render,
+        LogCategory::Physics,
+        LogCategory::Ai,
+        LogCategory::Net,
+        LogCategory::Fs,
+        LogCategory::Audio,
+        LogCategory::Input,
+        LogCategory::Game,
+    ];
+
+    /// The target log calls of this category use
+    pub fn target(&self) -> &'static str {
+        match self {
+            LogCategory::Render => "render",
+            LogCategory::Physics => "physics",
+            LogCategory::Ai => "ai",
+            LogCategory::Net => "net",
+            LogCategory::Fs => "fs",
+            LogCategory::Audio => "audio",
+            LogCategory::Input => "input",
+            LogCategory::Game => "game",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.target() == name)
+    }
+
+    /// The category of a record's target, going by module path when it isn't a category name
+    pub fn from_target(target: &str) -> Self {
+        if let Some(category) = Self::from_name(target) {
+            return category;
+        }
+
+        let path = target.strip_prefix("d3_core::").unwrap_or(target);
+
+        match path.split("::").next().unwrap_or("") {
+            "graphics" | "ui" => LogCategory::Render,
+            "net" => LogCategory::Net,
+            "filesystem" | "config" => LogCategory::Fs,
+            "input" => LogCategory::Input,
+            "game" => match path.split("::").nth(1).unwrap_or("") {
+                "physics" | "object_physics" | "timestep" => LogCategory::Physics,
+                "ai" | "squad" | "node" | "robot_animation" => LogCategory::Ai,
+                "audio" | "audio_stream" | "voice_manager" | "caption" | "sound_occlusion" | "sound_table" => LogCategory::Audio,
+                _ => LogCategory::Game,
+            },
+            _ => LogCategory::Game,
+        }
+    }
+
+    pub fn level(&self) -> LevelFilter {
+        LEVEL_FILTERS[CATEGORY_LEVELS[*self as usize].load(Ordering::Relaxed)]
+    }
+
+    /// Changes the category's verbosity, taking the global max level up with it if needs be
+    pub fn set_level(&self, level: LevelFilter) {
+        CATEGORY_LEVELS[*self as usize].store(level as usize, Ordering::Relaxed);
+
+        if level > log::max_level() {
+            log::set_max_level(level);
+        }
+    }
+}
+
+impl fmt::Display for LogCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.target())
+    }
+}
+
+/// Sets verbosity from a list like `net=debug,render=warn`, a bare level sets every category.
+/// This is what the console's `log` command takes
+pub fn apply_log_spec(spec: &str) -> Result<()> {
+    let mut changes = Vec::new();
+
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (categories, level) = match part.split_once('=') {
+            Some((name, level)) => {
+                let category = LogCategory::from_name(name.trim()).ok_or_else(|| anyhow!("unknown log category {}", name))?;
+                (vec![category], level.trim())
+            }
+            None => (LogCategory::ALL.to_vec(), part),
+        };
+
+        let level: LevelFilter = level.parse().map_err(|_| anyhow!("unknown log level {}", level))?;
+        changes.extend(categories.into_iter().map(|c| (c, level)));
+    }
+
+    // Only once the whole spec made sense
+    for (category, level) in changes {
+        category.set_level(level);
+    }
+
+    Ok(())
+}
+
+/// Applies `RUST_LOG` as a spec, so categories can be set before there's a console to do it.
+/// It takes category names, not module paths
+pub fn apply_env_log_spec() {
+    let Ok(spec) = std::env::var("RUST_LOG") else {
+        return;
+    };
+
+    if let Err(e) = apply_log_spec(&spec) {
+        warn!(target: "game", "ignoring RUST_LOG: {:#}", e);
+    }
+}
+
+/// A line of the log as the console shows it
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    /// Counts up from the first line logged, for picking up where a reader left off
+    pub sequence: u64,
+    pub level: Level,
+    pub category: LogCategory,
+    pub text: String,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<5} {}: {}", self.level, self.category, self.text)
+    }
+}
+
+/// The last few lines logged, oldest first, dropping the oldest once full
+#[derive(Debug, Clone, Default)]
+pub struct LogRing {
+    lines: VecDeque<LogLine>,
+    capacity: usize,
+    next_sequence: u64,
+}
+
+impl LogRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+            next_sequence: 0,
+        }
+    }
+
+    pub fn push(&mut self, level: Level, category: LogCategory, text: String) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(LogLine { sequence, level, category, text });
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &LogLine> {
+        self.lines.iter()
+    }
+
+    /// Lines from `sequence` on that are still in the ring
+    pub fn since(&self, sequence: u64) -> impl Iterator<Item = &LogLine> {
+        self.lines.iter().filter(move |l| l.sequence >= sequence)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+/// What's in the global ring. Doesn't wait on the lock, a panic while logging would otherwise hang the dump
+pub fn recent_log() -> Vec<String> {
+    match RECENT_LOG.try_lock() {
+        Ok(ring) => ring.lines().map(|l| l.to_string()).collect(),
+        Err(_) => vec!["(log unavailable)".to_string()],
+    }
+}
+
+/// Reads new lines out of the global ring for the console window, each reader keeps its own place
+#[derive(Debug, Clone, Default)]
+pub struct LogReader {
+    next: u64,
+    /// Only lines of these categories, all of them when empty
+    pub categories: Vec<LogCategory>,
+}
+
+impl LogReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lines logged since the last read. Lines that dropped out of the ring in between are lost
+    pub fn read(&mut self) -> Vec<LogLine> {
+        let ring = RECENT_LOG.lock().unwrap_or_else(|e| e.into_inner());
+        let lines: Vec<LogLine> = ring
+            .since(self.next)
+            .filter(|l| self.categories.is_empty() || self.categories.contains(&l.category))
+            .cloned()
+            .collect();
+
+        self.next = ring.next_sequence;
+        lines
+    }
+}
+
+/// Filters records by category, keeps them in the ring and passes them on to another logger
+pub struct RingLogger {
+    inner: Box<dyn Log>,
+}
+
+impl RingLogger {
+    /// The inner logger should let everything through, the categories decide what gets logged
+    pub fn new(inner: Box<dyn Log>) -> Self {
+        Self { inner }
+    }
+
+    /// Makes this the global logger, with every category starting at `level`
+    pub fn install(self, level: LevelFilter) -> Result<(), log::SetLoggerError> {
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+
+        for category in LogCategory::ALL {
+            category.set_level(level);
+        }
+
+        Ok(())
+    }
+}
+
+impl Log for RingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LogCategory::from_target(metadata.target()).level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Ok(mut ring) = RECENT_LOG.lock() {
+            ring.push(record.level(), LogCategory::from_target(record.target()), record.args().to_string());
+        }
+
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn log_category_test() {
+        crate::test_common::setup();
+
+        assert_eq!(LogCategory::from_target("net"), LogCategory::Net);
+        assert_eq!(LogCategory::from_target("d3_core::graphics::render_context"), LogCategory::Render);
+        assert_eq!(LogCategory::from_target("d3_core::game::timestep"), LogCategory::Physics);
+        assert_eq!(LogCategory::from_target("d3_core::game::door"), LogCategory::Game);
+        assert_eq!(LogCategory::from_target("gilrs"), LogCategory::Game);
+
+        // A bad spec changes nothing
+        assert!(apply_log_spec("ai=trace,render=loud").is_err());
+        assert_eq!(LogCategory::Ai.level(), LevelFilter::Info);
+
+        apply_log_spec("ai=trace, physics=off").unwrap();
+        assert_eq!(LogCategory::Ai.level(), LevelFilter::Trace);
+        assert_eq!(LogCategory::Physics.level(), LevelFilter::Off);
+        assert_eq!(LogCategory::Net.level(), LevelFilter::Info);
+    }
+
+    #[test]
+    fn log_ring_test() {
+        crate::test_common::setup();
+
+        let mut ring = LogRing::new(2);
+        for text in ["one", "two", "three"] {
+            ring.push(Level::Info, LogCategory::Fs, text.to_string());
+        }
+
+        assert_eq!(ring.lines().map(|l| l.text.as_str()).collect::<Vec<_>>(), vec!["two", "three"]);
+        assert_eq!(ring.since(2).count(), 1);
+        assert_eq!(ring.lines().last().unwrap().to_string(), "INFO  fs: three");
+    }
+}
@@ -0,0 +1,288 @@
+/*
+
+Crash diagnostics
+
+When the game goes down it leaves a text file behind with what it was doing:
+the level, how many of each kind of object there were, the last frame's render
+stats, what the code said it was in the middle of, and the tail of the log.
+That's something a player can attach to a bug report, where a bare backtrace
+from a release build says very little.
+
+The game keeps the state up to date as it goes, it's cheap, and the panic hook
+or the fatal error path writes it out.
+
+*/
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, BufWriter, Write},
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+
+pub mod logging;
+
+lazy_static! {
+    static ref STATE: Mutex<DiagnosticState> = Mutex::new(DiagnosticState::default());
+}
+
+thread_local! {
+    static ERROR_CONTEXT: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// What the renderer got through last frame
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RenderStats {
+    pub frame: u32,
+    pub frame_ms: f32,
+    pub rooms: usize,
+    pub objects: usize,
+    pub polygons: usize,
+}
+
+/// What the game was up to, for the dump
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DiagnosticState {
+    pub level: Option<String>,
+    /// Count of each class of object in the level
+    pub object_counts: Vec<(String, usize)>,
+    pub render_stats: Option<RenderStats>,
+}
+
+/// Changes the state kept for the dump
+pub fn update_state(change: impl FnOnce(&mut DiagnosticState)) {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    change(&mut state);
+}
+
+/// Takes the level name and a count of each class of object from the game, once a level loads and now and then after
+pub fn record_level(context: &crate::game::context::GameContext, level: &str) {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+
+    for binding in context.objects.bindings() {
+        let class = format!("{:?}", binding.inner().borrow().typedef().class);
+
+        match counts.iter_mut().find(|(c, _)| *c == class) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((class, 1)),
+        }
+    }
+
+    update_state(|s| {
+        s.level = Some(level.to_string());
+        s.object_counts = counts;
+    });
+}
+
+/// Says what the code is doing until the guard drops, shows up in the dump if it goes down meanwhile
+#[must_use]
+pub fn error_context(what: impl Into<String>) -> ErrorContextGuard {
+    ERROR_CONTEXT.with(|c| c.borrow_mut().push(what.into()));
+    ErrorContextGuard { _private: () }
+}
+
+/// Context of the current thread, outermost first
+pub fn current_context() -> Vec<String> {
+    ERROR_CONTEXT.with(|c| c.try_borrow().map(|c| c.clone()).unwrap_or_default())
+}
+
+pub struct ErrorContextGuard {
+    _private: (),
+}
+
+impl Drop for ErrorContextGuard {
+    fn drop(&mut self) {
+        ERROR_CONTEXT.with(|c| c.borrow_mut().pop());
+    }
+}
+
+/// Everything that goes in a dump
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrashReport {
+    pub reason: String,
+    pub version: &'static str,
+    pub thread: String,
+    pub context: Vec<String>,
+    pub state: DiagnosticState,
+    pub log: Vec<String>,
+}
+
+impl CrashReport {
+    /// Gathers up the state as it is now, from the thread that's going down
+    pub fn capture(reason: &str) -> Self {
+        let state = match STATE.try_lock() {
+            Ok(state) => state.clone(),
+            Err(_) => DiagnosticState::default(),
+        };
+
+        Self {
+            reason: reason.to_string(),
+            version: crate::get_version(),
+            thread: std::thread::current().name().unwrap_or("unnamed").to_string(),
+            context: current_context(),
+            state,
+            log: logging::recent_log(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "flare.rs crash report")?;
+        writeln!(out, "version: {}", self.version)?;
+        writeln!(out, "thread: {}", self.thread)?;
+        writeln!(out)?;
+        writeln!(out, "{}", self.reason)?;
+
+        if !self.context.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "while:")?;
+
+            for what in self.context.iter().rev() {
+                writeln!(out, "  {}", what)?;
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "level: {}", self.state.level.as_deref().unwrap_or("none"))?;
+
+        for (class, count) in &self.state.object_counts {
+            writeln!(out, "  {}: {}", class, count)?;
+        }
+
+        if let Some(stats) = &self.state.render_stats {
+            writeln!(
+                out,
+                "last frame: {} ({:.1}ms), {} rooms, {} objects, {} polygons",
+                stats.frame, stats.frame_ms, stats.rooms, stats.objects, stats.polygons
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "recent log:")?;
+
+        for line in &self.log {
+            writeln!(out, "  {}", line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `crash-<seconds since epoch>.txt` into `dir`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, dir: &Path) -> Result<PathBuf> {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let path = dir.join(format!("crash-{}.txt", seconds));
+
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let mut out = BufWriter::new(File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?);
+        self.write(&mut out)?;
+        out.flush()?;
+
+        Ok(path)
+    }
+
+    /// Browsers have no clock or filesystem to save into, the report only goes to the log there
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self, _dir: &Path) -> Result<PathBuf> {
+        let mut out = Vec::new();
+        self.write(&mut out)?;
+        error!(target: "game", "{}", String::from_utf8_lossy(&out));
+
+        Err(anyhow!("No filesystem to write a crash report to"))
+    }
+}
+
+fn panic_reason(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+
+    match info.location() {
+        Some(location) => format!("panicked at {}:{}: {}", location.file(), location.line(), message),
+        None => format!("panicked: {}", message),
+    }
+}
+
+/// Writes a dump into `dir` on any panic, then carries on with the hook that was there before
+pub fn install_panic_hook(dir: PathBuf) {
+    let previous = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        match CrashReport::capture(&panic_reason(info)).save(&dir) {
+            Ok(path) => eprintln!("crash report written to {}", path.display()),
+            Err(e) => eprintln!("failed to write a crash report: {:#}", e),
+        }
+
+        previous(info);
+    }));
+}
+
+/// For errors that end the game without a panic, writes the dump and says where it went
+pub fn report_fatal(error: &anyhow::Error, dir: &Path) -> Option<PathBuf> {
+    error!(target: "game", "fatal: {:#}", error);
+
+    match CrashReport::capture(&format!("fatal error: {:#}", error)).save(dir) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            error!(target: "game", "failed to write a crash report: {:#}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn error_context_test() {
+        crate::test_common::setup();
+
+        {
+            let _level = error_context("loading level 4");
+            let _room = error_context("reading room 12");
+            assert_eq!(current_context(), vec!["loading level 4", "reading room 12"]);
+        }
+
+        assert!(current_context().is_empty());
+    }
+
+    #[test]
+    fn crash_report_test() {
+        crate::test_common::setup();
+
+        let report = CrashReport {
+            reason: "panicked at room.rs:10: bad portal".to_string(),
+            version: "test",
+            thread: "main".to_string(),
+            context: vec!["loading level 4".to_string(), "reading room 12".to_string()],
+            state: DiagnosticState {
+                level: Some("Level 4".to_string()),
+                object_counts: vec![("Robot".to_string(), 12)],
+                render_stats: Some(RenderStats { frame: 90, frame_ms: 16.0, rooms: 5, objects: 20, polygons: 1500 }),
+            },
+            log: vec!["INFO  game: loaded".to_string()],
+        };
+
+        let mut out = Vec::new();
+        report.write(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+
+        // Innermost context first, like a backtrace
+        assert!(text.contains("while:\n  reading room 12\n  loading level 4\n"));
+        assert!(text.contains("level: Level 4\n  Robot: 12\n"));
+        assert!(text.contains("last frame: 90 (16.0ms), 5 rooms, 20 objects, 1500 polygons"));
+        assert!(text.ends_with("recent log:\n  INFO  game: loaded\n"));
+
+        let dir = std::env::temp_dir().join("flare_crash_report_test");
+        let path = report.save(&dir).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -53,14 +53,18 @@ mod internal {
    pub(crate) fn new<R: Read + Seek>(name: String, reader: &mut BufReader<R>) -> Result<Hog> {
         let mut magic = [0u8; MAGIC.len()];
         reader.read_exact(&mut magic).context("Failed to read magic")?;
-        let magic_str = std::str::from_utf8(&magic).unwrap();
+        let magic_str = std::str::from_utf8(&magic).context("Magic is not text")?;
 
-        trace!("Hog magic: {}", magic_str);
+        trace!(target: "fs", "Hog magic: {}", magic_str);
+
+        if magic_str != MAGIC {
+            return Err(anyhow!("Not a {} file, magic is {:?}", MAGIC, magic_str));
+        }
 
         let mut hog = Hog::default();
         hog.name = name;
 
-        let num_entries = reader.read_u32::<LittleEndian>().unwrap();
+        let num_entries = reader.read_u32::<LittleEndian>().context("Failed to read entry count")?;
         let mut header_info = [0u8; HEADER_SIZE - 4]; // NFILES is part of the header
         reader.read_exact(&mut header_info).context("Failed to read header info")?;
 
@@ -72,22 +76,27 @@ mod internal {
 
             let entry_header = HogFileEntry {
                 name: D3String::from_slice(&entry_name),
-                flags: reader.read_u32::<LittleEndian>().unwrap(),
-                size: reader.read_u32::<LittleEndian>().unwrap() as usize,
-                timestamp: reader.read_u32::<LittleEndian>().unwrap()
+                flags: reader.read_u32::<LittleEndian>().context("Failed to read entry flags")?,
+                size: reader.read_u32::<LittleEndian>().context("Failed to read entry size")? as usize,
+                timestamp: reader.read_u32::<LittleEndian>().context("Failed to read entry timestamp")?
             };
 
-            trace!("entry name: {}", entry_header.name);
+            trace!(target: "fs", "entry name: {}", entry_header.name);
 
             table.push(entry_header);
         }
 
         for entry in table.iter() {
-            let mut entry_data = vec![0u8; entry.size];
-            reader.read_exact(&mut entry_data).context("Failed to read entry data")?;
+            // Read through take so a bogus size can't allocate more than the file holds
+            let mut entry_data = Vec::default();
+            reader.by_ref().take(entry.size as u64).read_to_end(&mut entry_data).context("Failed to read entry data")?;
+
+            if entry_data.len() != entry.size {
+                return Err(anyhow!("Entry {} is truncated, expected {} bytes but found {}", entry.name, entry.size, entry_data.len()));
+            }
 
             /* Add the entry to the hog */
-            hog.entries.insert(entry.name.to_string().unwrap(), HogEntry {
+            hog.entries.insert(entry.name.to_string().context("Entry name is not text")?, HogEntry {
                 flags: entry.flags,
                 data: entry_data.as_slice().into()
             });
@@ -234,4 +243,19 @@ pub mod tests {
             "458c8f1506a91596fd01004ea62ef654"
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn hog_corrupt_test() {
+        crate::test_common::setup();
+
+        let data = std::fs::read(testdata!("test.hog")).unwrap();
+
+        let mut bad_magic = data.clone();
+        bad_magic[..4].copy_from_slice(b"HOG1");
+        let mut reader = BufReader::new(std::io::Cursor::new(bad_magic));
+        assert!(Hog::new_from_stream(&mut reader, "bad_magic.hog".to_string()).is_err());
+
+        let mut reader = BufReader::new(std::io::Cursor::new(&data[..data.len() - 1]));
+        assert!(Hog::new_from_stream(&mut reader, "truncated.hog".to_string()).is_err());
+    }
+}
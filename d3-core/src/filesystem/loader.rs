@@ -0,0 +1,400 @@
+/*
+
+Background asset loading
+
+Requests are queued on a pool of worker threads which read and decode
+the data off the main thread. Each request hands back a LoadHandle
+that the caches poll once a frame and swap in when it's ready,
+so paging in data during gameplay doesn't stall a frame.
+
+*/
+use std::{io::{BufReader, Cursor}, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}, sync::{atomic::{AtomicUsize, Ordering}, mpsc::{self, Receiver, Sender}, Arc, Condvar, Mutex}, thread::{self, JoinHandle}};
+
+use anyhow::{Context, Result};
+
+use crate::graphics::bitmap::{image_format_ogf::OgfBitmap, image_format_pcx::PcxBitmap, Bitmap16, BitmapFormat};
+
+use super::{hog::Hog, progress::{LoadStage, ProgressReporter}};
+
+/// Decoded bitmap from the loader, ready for the bitmap cache
+pub type LoadedBitmap = Box<dyn Bitmap16 + Send>;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Where a request's data comes from
+pub enum AssetSource {
+    Memory(String, Box<[u8]>),
+    File(PathBuf),
+    Hog(Arc<Hog>, String),
+}
+
+impl AssetSource {
+    /// Name the asset is cached under
+    pub fn name(&self) -> String {
+        match self {
+            AssetSource::Memory(name, _) => name.clone(),
+            AssetSource::File(path) => path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            AssetSource::Hog(_, name) => name.clone(),
+        }
+    }
+
+    fn read(self) -> Result<Box<[u8]>> {
+        match self {
+            AssetSource::Memory(_, data) => Ok(data),
+            #[cfg(target_arch = "wasm32")]
+            AssetSource::File(path) => Err(anyhow!("No filesystem to read {} from, mount it in a MemoryFileProvider", path.display())),
+            #[cfg(not(target_arch = "wasm32"))]
+            AssetSource::File(path) => {
+                let data = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+                Ok(data.into_boxed_slice())
+            },
+            AssetSource::Hog(hog, name) => {
+                match hog.borrow_entries().get(&name) {
+                    Some(entry) => Ok(entry.data.clone()),
+                    None => Err(anyhow!("{} is not in the hog", name))
+                }
+            }
+        }
+    }
+}
+
+enum LoadState<T> {
+    Pending,
+    Ready(Result<T>),
+    Taken,
+}
+
+/// Shared between a handle and the job filling it in
+struct LoadSlot<T> {
+    state: Mutex<LoadState<T>>,
+    /// Signalled once the state goes to Ready
+    ready: Condvar,
+}
+
+impl<T> LoadSlot<T> {
+    fn finish(&self, result: Result<T>) {
+        *self.state.lock().unwrap() = LoadState::Ready(result);
+        self.ready.notify_all();
+    }
+}
+
+/// Result of a queued request, filled in by a worker once it's decoded
+pub struct LoadHandle<T> {
+    name: String,
+    slot: Arc<LoadSlot<T>>,
+}
+
+impl<T> std::fmt::Debug for LoadHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoadHandle")
+            .field("name", &self.name)
+            .field("pending", &self.is_pending())
+            .finish()
+    }
+}
+
+impl<T> LoadHandle<T> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_pending(&self) -> bool {
+        matches!(*self.slot.state.lock().unwrap(), LoadState::Pending)
+    }
+
+    /// The decoded asset once the worker is done with it, None while pending or after it was taken
+    pub fn try_take(&self) -> Option<Result<T>> {
+        let mut state = self.slot.state.lock().unwrap();
+
+        match std::mem::replace(&mut *state, LoadState::Taken) {
+            LoadState::Ready(result) => Some(result),
+            other => {
+                *state = other;
+                None
+            }
+        }
+    }
+
+    /// Blocks until the worker is done, for loading screens where stalling is fine
+    pub fn wait(&self) -> Result<T> {
+        let state = self.slot.state.lock().unwrap();
+        let mut state = self.slot.ready.wait_while(state, |state| matches!(state, LoadState::Pending)).unwrap();
+
+        match std::mem::replace(&mut *state, LoadState::Taken) {
+            LoadState::Ready(result) => result,
+            _ => Err(anyhow!("{} was already taken", self.name)),
+        }
+    }
+}
+
+/// Whether the target can spawn worker threads, wasm32 in a browser can't
+const THREADS_AVAILABLE: bool = cfg!(not(target_arch = "wasm32"));
+
+/// Pool of worker threads that read and decode assets in the background. Where there are no
+/// threads requests are loaded as they're queued and their handles are ready straight away
+pub struct AssetLoader {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    queued: Arc<AtomicUsize>,
+    progress: Option<ProgressReporter>,
+}
+
+impl std::fmt::Debug for AssetLoader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AssetLoader")
+            .field("workers", &self.workers.len())
+            .field("queued", &self.queued())
+            .finish()
+    }
+}
+
+impl Default for AssetLoader {
+    fn default() -> Self {
+        let workers = thread::available_parallelism().map(|n| n.get().saturating_sub(1)).unwrap_or(1);
+        Self::new(workers.max(1))
+    }
+}
+
+impl AssetLoader {
+    pub fn new(num_workers: usize) -> Self {
+        if !THREADS_AVAILABLE {
+            return Self {
+                sender: None,
+                workers: Vec::new(),
+                queued: Arc::new(AtomicUsize::new(0)),
+                progress: None,
+            };
+        }
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..num_workers.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+
+                thread::Builder::new()
+                    .name(format!("asset_loader_{}", i))
+                    .spawn(move || worker_loop(receiver))
+                    .expect("Failed to spawn asset loader thread")
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+            queued: Arc::new(AtomicUsize::new(0)),
+            progress: None,
+        }
+    }
+
+    /// Reports each request from here on as it's queued and finished, for the loading screen
+    pub fn set_progress(&mut self, progress: Option<ProgressReporter>) {
+        self.progress = progress;
+    }
+
+    /// Requests that haven't finished yet
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Queues a request with its own decoder, it runs on a worker with the raw data of the source
+    pub fn load_with<T, F>(&self, source: AssetSource, decode: F) -> LoadHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&str, Box<[u8]>) -> Result<T> + Send + 'static,
+    {
+        self.queue(source, LoadStage::Data, decode)
+    }
+
+    fn queue<T, F>(&self, source: AssetSource, stage: LoadStage, decode: F) -> LoadHandle<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&str, Box<[u8]>) -> Result<T> + Send + 'static,
+    {
+        let name = source.name();
+        let slot = Arc::new(LoadSlot { state: Mutex::new(LoadState::Pending), ready: Condvar::new() });
+
+        let handle = LoadHandle {
+            name: name.clone(),
+            slot: slot.clone(),
+        };
+
+        let queued = self.queued.clone();
+        queued.fetch_add(1, Ordering::SeqCst);
+
+        let progress = self.progress.clone();
+
+        if let Some(progress) = &progress {
+            progress.queued(stage, 1);
+        }
+
+        let job: Job = Box::new(move || {
+            // A panicking decoder shouldn't take the worker down with it
+            let result = panic::catch_unwind(AssertUnwindSafe(|| decode(&name, source.read()?)))
+                .unwrap_or_else(|_| Err(anyhow!("Decoder panicked while loading {}", name)));
+
+            if let Err(e) = &result {
+                warn!(target: "fs", "Failed to load {}: {:#}", name, e);
+            }
+
+            // Failed loads count too, the bar shouldn't hang on them
+            if let Some(progress) = progress {
+                progress.finished(stage, 1);
+            }
+
+            queued.fetch_sub(1, Ordering::SeqCst);
+
+            // Last, so whoever's woken up by it sees the counts already done
+            slot.finish(result);
+        });
+
+        match &self.sender {
+            Some(sender) => sender.send(job).expect("Asset loader workers have stopped"),
+            None => job()
+        }
+
+        handle
+    }
+
+    /// Queues a bitmap, decoded as OGF/TGA or PCX by its extension
+    pub fn load_bitmap(&self, source: AssetSource, format: BitmapFormat) -> LoadHandle<LoadedBitmap> {
+        self.queue(source, LoadStage::Bitmaps, move |name, data| decode_bitmap(name, data, format))
+    }
+
+    /// Queues the raw data of a sound
+    pub fn load_sound(&self, source: AssetSource) -> LoadHandle<Box<[u8]>> {
+        self.queue(source, LoadStage::Sounds, |_, data| Ok(data))
+    }
+
+    /// Queues the raw data of a polymodel
+    pub fn load_model(&self, source: AssetSource) -> LoadHandle<Box<[u8]>> {
+        self.queue(source, LoadStage::Models, |_, data| Ok(data))
+    }
+}
+
+impl Drop for AssetLoader {
+    fn drop(&mut self) {
+        // Closing the channel lets the workers finish what's queued and exit
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker_loop(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = receiver.lock().unwrap().recv();
+
+        match job {
+            Ok(job) => job(),
+            Err(_) => break
+        }
+    }
+}
+
+fn decode_bitmap(name: &str, data: Box<[u8]>, format: BitmapFormat) -> Result<LoadedBitmap> {
+    let extension = Path::new(name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let mut reader = BufReader::new(Cursor::new(data));
+
+    match extension.as_str() {
+        "ogf" | "tga" => Ok(Box::new(OgfBitmap::new(&mut reader, format).with_context(|| format!("Failed to decode {}", name))?)),
+        "pcx" => Ok(Box::new(PcxBitmap::new(&mut reader).with_context(|| format!("Failed to decode {}", name))?)),
+        _ => Err(anyhow!("Unknown bitmap type: {}", name))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::fs::File;
+
+    use crate::{filesystem::progress::progress_channel, testdata};
+
+    use super::*;
+
+    fn test_hog() -> Arc<Hog> {
+        let file = File::open(testdata!("test.hog")).unwrap();
+        let mut reader = BufReader::new(file);
+        Arc::new(Hog::new_from_stream(&mut reader, "test.hog".to_string()).unwrap())
+    }
+
+    #[test]
+    fn loader_bitmap_test() {
+        crate::test_common::setup();
+
+        let loader = AssetLoader::new(2);
+        let hog = test_hog();
+
+        let ogf = loader.load_bitmap(AssetSource::Hog(hog.clone(), "badapple_4444_1mm.ogf".to_string()), BitmapFormat::Fmt4444);
+        let pcx = loader.load_bitmap(AssetSource::Hog(hog.clone(), "badapple.pcx".to_string()), BitmapFormat::Fmt1555);
+        let missing = loader.load_bitmap(AssetSource::Hog(hog, "missing.ogf".to_string()), BitmapFormat::Fmt1555);
+
+        let ogf = ogf.wait().unwrap();
+        assert_eq!(ogf.format(), BitmapFormat::Fmt4444);
+        assert!(ogf.width() > 0);
+
+        let pcx = pcx.wait().unwrap();
+        assert_eq!((pcx.width(), pcx.height()), (480, 360));
+
+        assert!(missing.wait().is_err());
+        assert_eq!(loader.queued(), 0);
+    }
+
+    #[test]
+    fn loader_handle_test() {
+        crate::test_common::setup();
+
+        let loader = AssetLoader::new(1);
+
+        let handle = loader.load_with(AssetSource::Memory("numbers".to_string(), vec![1, 2, 3].into_boxed_slice()), |_, data| {
+            Ok(data.iter().map(|&b| b as u32).sum::<u32>())
+        });
+
+        assert_eq!(handle.name(), "numbers");
+        assert_eq!(handle.wait().unwrap(), 6);
+
+        // Results can only be taken once
+        assert!(handle.try_take().is_none());
+        assert!(handle.wait().is_err());
+
+        let panicked = loader.load_with(AssetSource::Memory("bad".to_string(), Box::default()), |_, _| -> Result<u32> {
+            panic!("bad decoder");
+        });
+
+        assert!(panicked.wait().is_err());
+
+        // The worker survived the panic
+        let after = loader.load_with(AssetSource::Memory("after".to_string(), Box::default()), |_, data| Ok(data.len()));
+        assert_eq!(after.wait().unwrap(), 0);
+    }
+
+    #[test]
+    fn loader_progress_test() {
+        crate::test_common::setup();
+
+        let (reporter, mut progress) = progress_channel();
+        let mut loader = AssetLoader::new(2);
+        loader.set_progress(Some(reporter));
+
+        let model = loader.load_model(AssetSource::Memory("ship.oof".to_string(), vec![0; 16].into_boxed_slice()));
+        let missing = loader.load_bitmap(AssetSource::File(PathBuf::from("missing.ogf")), BitmapFormat::Fmt1555);
+
+        assert!(model.wait().is_ok());
+        assert!(missing.wait().is_err());
+
+        while loader.queued() > 0 {
+            thread::yield_now();
+        }
+
+        progress.poll();
+        assert_eq!(progress.stage(LoadStage::Models), (1, 1));
+        assert_eq!(progress.stage(LoadStage::Bitmaps), (1, 1));
+        assert!(progress.is_done());
+    }
+}
@@ -0,0 +1,153 @@
+/*
+
+In-memory filesystem
+
+Files held in memory instead of on disk, either byte slices compiled into the
+binary with include_bytes! or data handed over at runtime such as assets a
+browser build downloaded. Tests use it to feed the loaders without touching
+the disk. Names are looked up without regard to case, the same as the game's
+data files.
+
+*/
+use std::{borrow::Cow, collections::HashMap};
+
+use super::{gamefs::{GameFile, GameFilesystem}, hog::Hog, loader::AssetSource};
+
+pub struct MemoryFile {
+    name: String,
+    data: Cow<'static, [u8]>,
+}
+
+impl MemoryFile {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl GameFile for MemoryFile {
+    fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryFileProvider {
+    files: HashMap<String, MemoryFile>,
+}
+
+impl MemoryFileProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a provider out of files compiled into the binary
+    pub fn with_embedded(files: &[(&str, &'static [u8])]) -> Self {
+        let mut provider = Self::new();
+
+        for (name, data) in files {
+            provider.add_embedded(name, data);
+        }
+
+        provider
+    }
+
+    /// Adds a file compiled into the binary, nothing is copied
+    pub fn add_embedded(&mut self, name: &str, data: &'static [u8]) {
+        self.insert(name, Cow::Borrowed(data));
+    }
+
+    /// Adds a file loaded at runtime, replacing any file of the same name
+    pub fn add(&mut self, name: &str, data: Vec<u8>) {
+        self.insert(name, Cow::Owned(data));
+    }
+
+    /// Copies in every file in a hog
+    pub fn mount_hog(&mut self, hog: &Hog) {
+        for (name, entry) in hog.borrow_entries() {
+            self.add(name, entry.data.to_vec());
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.files.remove(&name.to_lowercase()).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(&name.to_lowercase())
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Names of the files, as they were added
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.files.values().map(|f| f.name())
+    }
+
+    /// A copy of a file to queue on the asset loader
+    pub fn asset_source(&self, name: &str) -> Option<AssetSource> {
+        self.files.get(&name.to_lowercase()).map(|f| AssetSource::Memory(f.name.clone(), f.data.to_vec().into_boxed_slice()))
+    }
+
+    fn insert(&mut self, name: &str, data: Cow<'static, [u8]>) {
+        self.files.insert(name.to_lowercase(), MemoryFile { name: name.to_string(), data });
+    }
+}
+
+impl GameFilesystem for MemoryFileProvider {
+    fn find_file(&self, name: &str) -> Option<&dyn GameFile> {
+        self.files.get(&name.to_lowercase()).map(|f| f as &dyn GameFile)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use crate::{filesystem::loader::AssetLoader, graphics::bitmap::BitmapFormat};
+
+    use super::*;
+
+    static TEST_HOG: &[u8] = include_bytes!("testdata/test.hog");
+
+    #[test]
+    fn memory_provider_test() {
+        crate::test_common::setup();
+
+        let mut provider = MemoryFileProvider::with_embedded(&[("Level1.D3L", b"level"), ("notes.txt", b"")]);
+
+        assert_eq!(provider.find_file("level1.d3l").unwrap().get_data(), b"level");
+        assert!(provider.find_file("missing.d3l").is_none());
+        assert_eq!(provider.len(), 2);
+
+        provider.add("LEVEL1.d3l", b"newer".to_vec());
+        assert_eq!(provider.find_file("Level1.D3L").unwrap().get_data(), b"newer");
+        assert_eq!(provider.len(), 2);
+
+        assert!(provider.remove("notes.TXT"));
+        assert!(!provider.contains("notes.txt"));
+        assert_eq!(provider.names().collect::<Vec<_>>(), vec!["LEVEL1.d3l"]);
+    }
+
+    #[test]
+    fn memory_provider_loader_test() {
+        crate::test_common::setup();
+
+        // The test hog without going to the disk for it
+        let hog = Hog::new_from_stream(&mut BufReader::new(Cursor::new(TEST_HOG)), "test.hog".to_string()).unwrap();
+        let mut provider = MemoryFileProvider::new();
+        provider.mount_hog(&hog);
+        assert_eq!(provider.len(), hog.borrow_entries().len());
+
+        let loader = AssetLoader::new(1);
+        let pcx = loader.load_bitmap(provider.asset_source("BADAPPLE.PCX").unwrap(), BitmapFormat::Fmt1555).wait().unwrap();
+        assert_eq!((pcx.width(), pcx.height()), (480, 360));
+
+        assert!(provider.asset_source("missing.ogf").is_none());
+    }
+}
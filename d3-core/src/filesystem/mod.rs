@@ -4,4 +4,7 @@ use hog::Hog;
 
 pub mod hog;
 pub mod gamefs;
-pub mod lazy;
\ No newline at end of file
+pub mod lazy;
+pub mod loader;
+pub mod memory;
+pub mod progress;
\ No newline at end of file
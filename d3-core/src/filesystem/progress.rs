@@ -0,0 +1,185 @@
+/*
+
+Load progress
+
+Whatever's loading a level reports how far along it is over a channel,
+the loader's workers as they finish each request and CPU work like
+lightmap baking as it goes. The loading screen drains it once a frame,
+so the window keeps drawing while the workers do the real work.
+
+*/
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// The kinds of work a load is made of, each gets its own share of the bar
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LoadStage {
+    Bitmaps,
+    Models,
+    Sounds,
+    Lightmaps,
+    /// Anything queued with its own decoder
+    Data,
+}
+
+impl LoadStage {
+    pub const ALL: [LoadStage; 5] = [LoadStage::Bitmaps, LoadStage::Models, LoadStage::Sounds, LoadStage::Lightmaps, LoadStage::Data];
+
+    /// What the loading screen says is going on
+    pub fn name(&self) -> &'static str {
+        match self {
+            LoadStage::Bitmaps => "Loading textures",
+            LoadStage::Models => "Loading models",
+            LoadStage::Sounds => "Loading sounds",
+            LoadStage::Lightmaps => "Building lightmaps",
+            LoadStage::Data => "Loading data",
+        }
+    }
+
+    /// How much of the bar the stage is worth against the others, roughly how long each takes
+    pub fn weight(&self) -> f32 {
+        match self {
+            LoadStage::Bitmaps => 4.0,
+            LoadStage::Models => 2.0,
+            LoadStage::Sounds => 1.0,
+            LoadStage::Lightmaps => 3.0,
+            LoadStage::Data => 1.0,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ProgressUpdate {
+    /// More work of a stage is on its way
+    Queued(LoadStage, usize),
+    Finished(LoadStage, usize),
+}
+
+/// The sending half, cheap to clone and hand to worker threads
+#[derive(Debug, Clone)]
+pub struct ProgressReporter {
+    sender: Sender<ProgressUpdate>,
+}
+
+impl ProgressReporter {
+    /// Adds `count` pieces of work to a stage's total
+    pub fn queued(&self, stage: LoadStage, count: usize) {
+        // Nobody listening anymore is fine, the load carries on without a screen
+        let _ = self.sender.send(ProgressUpdate::Queued(stage, count));
+    }
+
+    pub fn finished(&self, stage: LoadStage, count: usize) {
+        let _ = self.sender.send(ProgressUpdate::Finished(stage, count));
+    }
+}
+
+/// Done and total work of each stage, as seen by the loading screen
+#[derive(Debug)]
+pub struct LoadProgress {
+    receiver: Receiver<ProgressUpdate>,
+    done: [usize; LoadStage::ALL.len()],
+    total: [usize; LoadStage::ALL.len()],
+    /// The stage that last finished something
+    current: Option<LoadStage>,
+}
+
+/// A reporter for the loading code and the progress it feeds
+pub fn progress_channel() -> (ProgressReporter, LoadProgress) {
+    let (sender, receiver) = mpsc::channel();
+
+    let progress = LoadProgress {
+        receiver,
+        done: Default::default(),
+        total: Default::default(),
+        current: None,
+    };
+
+    (ProgressReporter { sender }, progress)
+}
+
+impl LoadProgress {
+    /// Takes in what was reported since the last call, meant to be called once a frame
+    pub fn poll(&mut self) {
+        while let Ok(update) = self.receiver.try_recv() {
+            match update {
+                ProgressUpdate::Queued(stage, count) => self.total[stage.index()] += count,
+                ProgressUpdate::Finished(stage, count) => {
+                    let i = stage.index();
+                    self.done[i] = (self.done[i] + count).min(self.total[i]);
+                    self.current = Some(stage);
+                }
+            }
+        }
+    }
+
+    pub fn stage(&self, stage: LoadStage) -> (usize, usize) {
+        (self.done[stage.index()], self.total[stage.index()])
+    }
+
+    /// The stage to name on screen, the one last heard from unless it's through
+    pub fn current_stage(&self) -> Option<LoadStage> {
+        let unfinished = |s: &LoadStage| self.done[s.index()] < self.total[s.index()];
+
+        self.current.filter(unfinished).or_else(|| LoadStage::ALL.into_iter().find(unfinished))
+    }
+
+    /// How far along the load is from 0 to 1, stages without any work don't count
+    pub fn fraction(&self) -> f32 {
+        let (done, total) = LoadStage::ALL
+            .iter()
+            .filter(|s| self.total[s.index()] > 0)
+            .fold((0.0, 0.0), |(done, total), s| {
+                let i = s.index();
+                (done + s.weight() * self.done[i] as f32 / self.total[i] as f32, total + s.weight())
+            });
+
+        if total > 0.0 { done / total } else { 0.0 }
+    }
+
+    /// Everything reported has finished. Work can still be queued after this, so
+    /// only trust it once the loading code has queued everything it's going to
+    pub fn is_done(&self) -> bool {
+        self.done == self.total
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn load_progress_test() {
+        crate::test_common::setup();
+
+        let (reporter, mut progress) = progress_channel();
+        assert_eq!(progress.fraction(), 0.0);
+
+        reporter.queued(LoadStage::Bitmaps, 4);
+        reporter.queued(LoadStage::Lightmaps, 2);
+
+        // Reporters work from any thread
+        let worker = reporter.clone();
+        std::thread::spawn(move || worker.finished(LoadStage::Bitmaps, 2)).join().unwrap();
+
+        progress.poll();
+        assert_eq!(progress.stage(LoadStage::Bitmaps), (2, 4));
+        assert_eq!(progress.current_stage(), Some(LoadStage::Bitmaps));
+        assert!((progress.fraction() - 2.0 / 7.0).abs() < 1e-6);
+
+        // Once the textures are in, the lightmaps are what's left to name
+        reporter.finished(LoadStage::Bitmaps, 2);
+        reporter.finished(LoadStage::Lightmaps, 1);
+        progress.poll();
+        assert_eq!(progress.current_stage(), Some(LoadStage::Lightmaps));
+        assert!(!progress.is_done());
+
+        reporter.finished(LoadStage::Lightmaps, 1);
+        progress.poll();
+        assert!(progress.is_done());
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(progress.current_stage(), None);
+    }
+}
@@ -0,0 +1,227 @@
+/*
+
+Aim assist
+
+Helps gamepad players, who can't aim as finely as a mouse, by bending a shot
+toward a target that's already close to the crosshair. Only targets inside a
+small cone around where the gun points are considered, and a target's own size
+counts, so a big robot at the edge of the cone still gets picked up. Of those,
+the highest priority target wins, then the one closest to the crosshair, then
+the nearest. Targets the gun can't see are passed over, the caller decides
+what blocks a shot, normally with FVI like the turrets do.
+
+The strength says how far the shot bends, 0 leaves it alone and 1 snaps it
+right onto the target. It's off in multiplayer unless the server allows it.
+
+*/
+
+use crate::config::{InputSettings, NetworkSettings};
+
+use super::{prelude::*, GameMode};
+use vector::Vector;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AimTarget {
+    pub position: Vector,
+    pub radius: f32,
+    /// Higher is picked first, whatever the angle or distance
+    pub priority: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AimAssist {
+    pub enabled: bool,
+    /// 0 leaves the shot alone, 1 snaps it onto the target
+    pub strength: f32,
+    /// Half angle of the cone around the gun, in degrees
+    pub cone_degrees: f32,
+    /// Whether the server lets players use it in multiplayer
+    pub server_allows: bool,
+}
+
+impl Default for AimAssist {
+    fn default() -> Self {
+        Self::from_settings(&InputSettings::default(), &NetworkSettings::default())
+    }
+}
+
+impl AimAssist {
+    pub fn from_settings(input: &InputSettings, network: &NetworkSettings) -> Self {
+        Self {
+            enabled: input.aim_assist,
+            strength: input.aim_assist_strength.clamp(0.0, 1.0),
+            cone_degrees: input.aim_assist_cone.clamp(0.0, 90.0),
+            server_allows: network.allow_aim_assist,
+        }
+    }
+
+    /// Checks if aim assist can be used in the current game mode
+    pub fn is_allowed(&self, mode: GameMode) -> bool {
+        if !self.enabled || self.strength <= 0.0 {
+            return false;
+        }
+
+        !mode.intersects(GameMode::MULTI) || self.server_allows
+    }
+
+    /// The best target inside the cone the gun can see, as an index into `targets`
+    pub fn pick_target(
+        &self,
+        gun_point: &Vector,
+        fire_dir: &Vector,
+        targets: &[AimTarget],
+        mut visible: impl FnMut(&Vector, &Vector) -> bool,
+    ) -> Option<usize> {
+        let cone = self.cone_degrees.to_radians();
+        let fire_dir = fire_dir.normalized();
+
+        let mut candidates = targets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, target)| {
+                let dist = Vector::distance(gun_point, &target.position);
+
+                if dist <= target.radius {
+                    // Right on top of the gun, any direction hits it
+                    return Some((i, 0.0, dist));
+                }
+
+                let to_target = (target.position - *gun_point) / dist;
+                let angle = fire_dir.dot(to_target).clamp(-1.0, 1.0).acos();
+                let angle = (angle - (target.radius / dist).asin()).max(0.0);
+
+                (angle <= cone).then_some((i, angle, dist))
+            })
+            .collect::<Vec<_>>();
+
+        candidates.sort_by(|a, b| {
+            targets[b.0]
+                .priority
+                .cmp(&targets[a.0].priority)
+                .then(a.1.total_cmp(&b.1))
+                .then(a.2.total_cmp(&b.2))
+        });
+
+        // Only pay for line of sight checks until one passes
+        candidates
+            .into_iter()
+            .map(|(i, _, _)| i)
+            .find(|&i| visible(gun_point, &targets[i].position))
+    }
+
+    /// The direction to fire in, bent toward the best target if there is one
+    pub fn assist(
+        &self,
+        mode: GameMode,
+        gun_point: &Vector,
+        fire_dir: &Vector,
+        targets: &[AimTarget],
+        visible: impl FnMut(&Vector, &Vector) -> bool,
+    ) -> Vector {
+        if !self.is_allowed(mode) {
+            return *fire_dir;
+        }
+
+        let Some(i) = self.pick_target(gun_point, fire_dir, targets, visible) else {
+            return *fire_dir;
+        };
+
+        let to_target = targets[i].position - *gun_point;
+
+        if Vector::magnitude(&to_target) < Vector::EPSILON {
+            return *fire_dir;
+        }
+
+        let blended = fire_dir.normalized() * (1.0 - self.strength) + to_target.normalized() * self.strength;
+
+        if Vector::magnitude(&blended) < Vector::EPSILON {
+            return *fire_dir;
+        }
+
+        blended.normalized()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn target(x: f32, z: f32, priority: u8) -> AimTarget {
+        AimTarget {
+            position: Vector::new(x, 0.0, z),
+            radius: 0.0,
+            priority,
+        }
+    }
+
+    #[test]
+    fn aim_assist_picks_target() {
+        crate::test_common::setup();
+
+        let assist = AimAssist {
+            enabled: true,
+            strength: 1.0,
+            cone_degrees: 10.0,
+            server_allows: false,
+        };
+
+        let gun = Vector::ZERO;
+        let forward = Vector::new(0.0, 0.0, 1.0);
+
+        // 5 degrees off is inside the cone, 20 isn't unless it's big enough to reach in
+        let near_miss = target(5f32.to_radians().tan() * 100.0, 100.0, 0);
+        let mut wide = target(20f32.to_radians().tan() * 100.0, 100.0, 0);
+        assert_eq!(assist.pick_target(&gun, &forward, &[wide, near_miss], |_, _| true), Some(1));
+        assert_eq!(assist.pick_target(&gun, &forward, &[wide], |_, _| true), None);
+        wide.radius = 30.0;
+        assert_eq!(assist.pick_target(&gun, &forward, &[wide], |_, _| true), Some(0));
+
+        // Priority beats angle, angle beats distance
+        let important = target(8f32.to_radians().tan() * 50.0, 50.0, 1);
+        let centered_far = target(0.0, 200.0, 0);
+        let centered_near = target(0.0, 20.0, 0);
+        let targets = [near_miss, centered_far, important, centered_near];
+        assert_eq!(assist.pick_target(&gun, &forward, &targets, |_, _| true), Some(2));
+        assert_eq!(assist.pick_target(&gun, &forward, &targets[..2], |_, _| true), Some(1));
+        assert_eq!(assist.pick_target(&gun, &forward, &[centered_far, centered_near], |_, _| true), Some(1));
+
+        // Blocked targets are skipped for the next best
+        let blocked = important.position;
+        assert_eq!(assist.pick_target(&gun, &forward, &targets, |_, to| *to != blocked), Some(3));
+
+        // Full strength snaps onto the target
+        let dir = assist.assist(GameMode::SINGLE, &gun, &forward, &[near_miss], |_, _| true);
+        assert!(dir.approx_eq(&near_miss.position.normalized(), 0.0001));
+    }
+
+    #[test]
+    fn aim_assist_strength_and_gating() {
+        crate::test_common::setup();
+
+        let mut assist = AimAssist {
+            enabled: true,
+            strength: 0.5,
+            cone_degrees: 10.0,
+            server_allows: false,
+        };
+
+        let gun = Vector::ZERO;
+        let forward = Vector::new(0.0, 0.0, 1.0);
+        let targets = [target(8f32.to_radians().tan() * 100.0, 100.0, 0)];
+
+        // Half strength bends the shot about half way
+        let dir = assist.assist(GameMode::SINGLE, &gun, &forward, &targets, |_, _| true);
+        let angle = forward.dot(dir).acos().to_degrees();
+        assert!((angle - 4.0).abs() < 0.1);
+
+        // Nothing in multiplayer unless the server allows it
+        assert_eq!(assist.assist(GameMode::NETWORK, &gun, &forward, &targets, |_, _| true), forward);
+        assist.server_allows = true;
+        assert_ne!(assist.assist(GameMode::NETWORK, &gun, &forward, &targets, |_, _| true), forward);
+
+        // Off by default
+        assert!(!AimAssist::default().is_allowed(GameMode::SINGLE));
+        assist.strength = 0.0;
+        assert!(!assist.is_allowed(GameMode::SINGLE));
+    }
+}
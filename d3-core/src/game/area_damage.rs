@@ -0,0 +1,271 @@
+use super::{
+    physics::intersection::{FqFlags, HitType, Query},
+    prelude::*,
+    room::Room,
+};
+use angle::Angle;
+use matrix::Matrix;
+use vector::Vector;
+
+/// Objects heavier than this shrug off blasts, they still take the damage
+pub const MAX_KNOCKBACK_MASS: f32 = 20.0;
+
+/// Force of the push per point of damage
+pub const SPLASH_FORCE_SCALE: f32 = 4.0;
+
+/// How splash damage drops off from the center to the edge of the blast
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DamageFalloff {
+    /// Full damage anywhere in the radius
+    None,
+    #[default]
+    Linear,
+    /// Holds up near the center then drops off quickly
+    Quadratic,
+}
+
+impl DamageFalloff {
+    /// Damage scale `distance` from the center of a blast of `radius`
+    pub fn scale(&self, distance: f32, radius: f32) -> f32 {
+        if radius <= 0.0 || distance >= radius {
+            return 0.0;
+        }
+
+        let t = distance.max(0.0) / radius;
+
+        match self {
+            DamageFalloff::None => 1.0,
+            DamageFalloff::Linear => 1.0 - t,
+            DamageFalloff::Quadratic => 1.0 - t * t,
+        }
+    }
+}
+
+/// An object the broadphase found near a blast
+#[derive(Debug, Copy, Clone)]
+pub struct SplashTarget {
+    pub handle: usize,
+    pub position: Vector,
+    pub size: f32,
+    /// 0 for objects that can't be pushed
+    pub mass: f32,
+}
+
+/// What a blast did to one object
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SplashHit {
+    pub handle: usize,
+    pub damage: f32,
+    /// Force pushing it away from the blast, zero for heavy objects
+    pub force: Vector,
+}
+
+/// The parts of the game a blast needs to reach
+pub trait SplashWorld {
+    /// Objects whose bounds are within `radius` of `center`, from `quick_dist_object_list`
+    fn objects_near(&mut self, center: &Vector, radius: f32) -> Vec<SplashTarget>;
+
+    /// Whether a wall stands between the blast and the object
+    fn is_obstructed(&mut self, center: &Vector, target: &SplashTarget) -> bool;
+
+    fn apply_damage(&mut self, handle: usize, damage: f32, owner: Option<usize>);
+
+    fn apply_force(&mut self, handle: usize, force: &Vector);
+}
+
+/// Damages and pushes everything in reach of a blast, `owner` gets the credit for any kills
+pub fn apply_area_damage<W: SplashWorld>(
+    world: &mut W,
+    center: &Vector,
+    radius: f32,
+    damage: f32,
+    falloff: DamageFalloff,
+    owner: Option<usize>,
+) -> Vec<SplashHit> {
+    let mut hits = Vec::new();
+
+    if radius <= 0.0 || damage <= 0.0 {
+        return hits;
+    }
+
+    for target in world.objects_near(center, radius) {
+        // Measured to the edge of the object so big things get caught by blasts near their side
+        let distance = Vector::distance(&target.position, center) - target.size;
+        let scale = falloff.scale(distance, radius);
+
+        if scale <= 0.0 || world.is_obstructed(center, &target) {
+            continue;
+        }
+
+        let amount = damage * scale;
+        world.apply_damage(target.handle, amount, owner);
+
+        let mut force = Vector::default();
+
+        if target.mass > 0.0 && target.mass <= MAX_KNOCKBACK_MASS {
+            let mut dir = Vector::default();
+
+            // Something sitting right on the blast has no direction to go, it just takes the damage
+            if Vector::compute_normalized_direction(&mut dir, &target.position, center) > 0.0 {
+                force = dir * (amount * SPLASH_FORCE_SCALE);
+                world.apply_force(target.handle, &force);
+            }
+        }
+
+        hits.push(SplashHit {
+            handle: target.handle,
+            damage: amount,
+            force,
+        });
+    }
+
+    hits
+}
+
+/// Line of sight from a blast to an object, for `SplashWorld::is_obstructed`.  `cast` runs the
+/// FVI query, anything it hits is in the way
+pub fn is_splash_obstructed(
+    start_room: &SharedMutRef<Room>,
+    center: &Vector,
+    target: &Vector,
+    cast: impl FnOnce(&Query) -> HitType,
+) -> bool {
+    let query = Query {
+        p0: *center,
+        p1: *target,
+        start_room: start_room.clone(),
+        rad: 0.0,
+        this_obj: None,
+        ignore_obj_list: (),
+        flags: FqFlags::NO_RELINK,
+        bbox_orientation: Matrix::IDENTITY,
+        bbox_rotvel: Vector::ZERO,
+        bbox_rotthrust: Vector::ZERO,
+        bbox_velocity: Vector::ZERO,
+        bbox_turnroll: Angle(0),
+        bbox_thrust: Vector::ZERO,
+        frametime: 0.0,
+    };
+
+    !matches!(cast(&query), HitType::None)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct TestWorld {
+        objects: Vec<SplashTarget>,
+        /// Handles behind a wall
+        hidden: Vec<usize>,
+        /// A wall across the x axis here, found by casting rays at it
+        wall_x: Option<f32>,
+        room: SharedMutRef<Room>,
+        damage: HashMap<usize, (f32, Option<usize>)>,
+        forces: HashMap<usize, Vector>,
+    }
+
+    impl SplashWorld for TestWorld {
+        fn objects_near(&mut self, center: &Vector, radius: f32) -> Vec<SplashTarget> {
+            self.objects
+                .iter()
+                .filter(|o| Vector::distance(&o.position, center) - o.size < radius)
+                .copied()
+                .collect()
+        }
+
+        fn is_obstructed(&mut self, center: &Vector, target: &SplashTarget) -> bool {
+            if self.hidden.contains(&target.handle) {
+                return true;
+            }
+
+            let Some(wall_x) = self.wall_x else {
+                return false;
+            };
+
+            is_splash_obstructed(&self.room, center, &target.position, |query| {
+                if (query.p0.x - wall_x) * (query.p1.x - wall_x) < 0.0 {
+                    HitType::Wall
+                } else {
+                    HitType::None
+                }
+            })
+        }
+
+        fn apply_damage(&mut self, handle: usize, damage: f32, owner: Option<usize>) {
+            self.damage.insert(handle, (damage, owner));
+        }
+
+        fn apply_force(&mut self, handle: usize, force: &Vector) {
+            self.forces.insert(handle, *force);
+        }
+    }
+
+    fn target(handle: usize, x: f32, mass: f32) -> SplashTarget {
+        SplashTarget {
+            handle,
+            position: Vector::new(x, 0.0, 0.0),
+            size: 1.0,
+            mass,
+        }
+    }
+
+    #[test]
+    fn damage_falloff_test() {
+        assert_eq!(DamageFalloff::None.scale(5.0, 10.0), 1.0);
+        assert_eq!(DamageFalloff::Linear.scale(5.0, 10.0), 0.5);
+        assert_eq!(DamageFalloff::Quadratic.scale(5.0, 10.0), 0.75);
+        assert_eq!(DamageFalloff::Linear.scale(-1.0, 10.0), 1.0);
+        assert_eq!(DamageFalloff::None.scale(10.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn area_damage_test() {
+        crate::test_common::setup();
+
+        let mut world = TestWorld {
+            objects: vec![target(1, 3.0, 1.0), target(2, 6.0, 100.0), target(3, 3.0, 1.0), target(4, 50.0, 1.0)],
+            hidden: vec![3],
+            ..Default::default()
+        };
+
+        let hits = apply_area_damage(&mut world, &Vector::new(0.0, 0.0, 0.0), 10.0, 40.0, DamageFalloff::Linear, Some(7));
+
+        // Closer takes more, with the credit going to the owner
+        assert_eq!(hits.len(), 2);
+        let (near, owner) = world.damage[&1];
+        let (far, _) = world.damage[&2];
+        assert!(near > far);
+        assert_eq!(owner, Some(7));
+
+        // Behind a wall or out of reach
+        assert!(!world.damage.contains_key(&3));
+        assert!(!world.damage.contains_key(&4));
+
+        // Light things get pushed away, heavy ones stay put
+        assert!(world.forces[&1].x > 0.0);
+        assert!(!world.forces.contains_key(&2));
+    }
+
+    #[test]
+    fn area_damage_line_of_sight_test() {
+        crate::test_common::setup();
+
+        // Both in the radius, a wall between the blast and the second
+        let mut world = TestWorld {
+            objects: vec![target(1, 4.0, 1.0), target(2, -4.0, 1.0)],
+            wall_x: Some(-2.0),
+            ..Default::default()
+        };
+
+        let hits = apply_area_damage(&mut world, &Vector::new(0.0, 0.0, 0.0), 10.0, 40.0, DamageFalloff::Linear, None);
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].handle, 1);
+        assert!(!world.damage.contains_key(&2));
+        assert!(!world.forces.contains_key(&2));
+    }
+}
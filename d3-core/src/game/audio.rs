@@ -1,3 +1,22 @@
+use crate::math::vector::Vector;
+
+use super::sound_occlusion::SoundPlayInfo;
+
+/// Index into the game's sound table
+pub type SoundId = usize;
+
+pub const SOUND_REFUELING: SoundId = 1;
+
 pub trait AudioSystem {
     fn stop_sound_immediate(&mut self, sound: ());
-}
\ No newline at end of file
+
+    fn play_2d_sound(&mut self, sound: SoundId);
+
+    fn play_3d_sound(&mut self, sound: SoundId, position: &Vector);
+
+    /// Plays a 3d sound that has already been through occlusion and rolloff.  Mixers that can't
+    /// filter just play it at the apparent position.
+    fn play_3d_sound_with_info(&mut self, sound: SoundId, info: &SoundPlayInfo) {
+        self.play_3d_sound(sound, &info.position);
+    }
+}
@@ -0,0 +1,183 @@
+/*
+
+Audio buses
+
+Every voice the mixer plays goes through a bus, music, sound effects or
+voice, and those all go through the master bus. A bus's gain is its volume
+from the settings, times a volume scripts can set for themselves so a
+cinematic can fade the music without touching the player's settings, times
+how far it's ducked, times the master bus's gain.
+
+While a briefing line or a robot taunt is playing, music and sound effects
+duck down so the words come through, then come back up after it ends. They go
+down quickly and come back slowly, so a pause between two lines doesn't make
+the music pump.
+
+*/
+
+use crate::config::AudioSettings;
+
+/// Seconds to duck all the way down
+pub const DUCK_ATTACK_TIME: f32 = 0.15;
+
+/// Seconds to come all the way back up
+pub const DUCK_RELEASE_TIME: f32 = 0.8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum AudioBus {
+    Master,
+    Music,
+    #[default]
+    Sfx,
+    /// Briefings, robot taunts and anything else spoken
+    Voice,
+}
+
+impl AudioBus {
+    pub const ALL: [AudioBus; 4] = [AudioBus::Master, AudioBus::Music, AudioBus::Sfx, AudioBus::Voice];
+
+    pub fn parent(self) -> Option<AudioBus> {
+        match self {
+            AudioBus::Master => None,
+            _ => Some(AudioBus::Master),
+        }
+    }
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBuses {
+    /// From the settings
+    volumes: [f32; 4],
+    /// Set by scripts
+    script_volumes: [f32; 4],
+    /// What each bus drops to while a voice plays
+    duck_levels: [f32; 4],
+    /// How far each bus is ducked right now, 1 for not at all
+    ducked: [f32; 4],
+    ducking: bool,
+}
+
+impl Default for AudioBuses {
+    fn default() -> Self {
+        Self::new(&AudioSettings::default())
+    }
+}
+
+impl AudioBuses {
+    pub fn new(settings: &AudioSettings) -> Self {
+        let mut buses = Self {
+            volumes: [1.0; 4],
+            script_volumes: [1.0; 4],
+            duck_levels: [1.0; 4],
+            ducked: [1.0; 4],
+            ducking: true,
+        };
+
+        buses.apply_settings(settings);
+        buses
+    }
+
+    /// Picks up changed settings, the script volumes stay as they are
+    pub fn apply_settings(&mut self, settings: &AudioSettings) {
+        self.volumes = [settings.master_volume, settings.music_volume, settings.sfx_volume, settings.voice_volume].map(|v| v.clamp(0.0, 1.0));
+        self.duck_levels[AudioBus::Music.index()] = settings.music_duck_volume.clamp(0.0, 1.0);
+        self.duck_levels[AudioBus::Sfx.index()] = settings.sfx_duck_volume.clamp(0.0, 1.0);
+        self.ducking = settings.ducking;
+    }
+
+    pub fn volume(&self, bus: AudioBus) -> f32 {
+        self.volumes[bus.index()]
+    }
+
+    pub fn script_volume(&self, bus: AudioBus) -> f32 {
+        self.script_volumes[bus.index()]
+    }
+
+    /// For scripts, scales a bus on top of the player's volume for it
+    pub fn set_script_volume(&mut self, bus: AudioBus, volume: f32) {
+        self.script_volumes[bus.index()] = volume.clamp(0.0, 1.0);
+    }
+
+    /// For scripts that mix a scene themselves, turning it off lets ducked buses come back up
+    pub fn set_ducking(&mut self, enabled: bool) {
+        self.ducking = enabled;
+    }
+
+    pub fn is_ducking(&self) -> bool {
+        self.ducking
+    }
+
+    /// Moves the ducking along, `voice_playing` is whether anything on the voice bus is
+    pub fn frame(&mut self, frametime: f32, voice_playing: bool) {
+        for bus in AudioBus::ALL {
+            let i = bus.index();
+            let target = if self.ducking && voice_playing { self.duck_levels[i] } else { 1.0 };
+
+            if self.ducked[i] > target {
+                self.ducked[i] = (self.ducked[i] - frametime / DUCK_ATTACK_TIME).max(target);
+            } else {
+                self.ducked[i] = (self.ducked[i] + frametime / DUCK_RELEASE_TIME).min(target);
+            }
+        }
+    }
+
+    /// What a voice on `bus` is scaled by, all the way up through the master bus
+    pub fn gain(&self, bus: AudioBus) -> f32 {
+        let i = bus.index();
+        let own = self.volumes[i] * self.script_volumes[i] * self.ducked[i];
+
+        match bus.parent() {
+            Some(parent) => own * self.gain(parent),
+            None => own,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn audio_bus_ducking_test() {
+        crate::test_common::setup();
+
+        let settings = AudioSettings {
+            master_volume: 0.5,
+            music_volume: 0.8,
+            music_duck_volume: 0.25,
+            sfx_duck_volume: 0.5,
+            ..Default::default()
+        };
+
+        let mut buses = AudioBuses::new(&settings);
+        assert_eq!(buses.gain(AudioBus::Music), 0.4);
+        assert_eq!(buses.gain(AudioBus::Voice), 0.5);
+
+        // Down quickly once a voice starts, the voice itself left alone
+        buses.frame(DUCK_ATTACK_TIME, true);
+        assert_eq!(buses.gain(AudioBus::Music), 0.1);
+        assert_eq!(buses.gain(AudioBus::Sfx), 0.25);
+        assert_eq!(buses.gain(AudioBus::Voice), 0.5);
+
+        // Back up slowly after
+        buses.frame(DUCK_RELEASE_TIME / 3.0, false);
+        assert!((buses.gain(AudioBus::Sfx) - 0.5 * (0.5 + 1.0 / 3.0)).abs() < 1e-5);
+        buses.frame(DUCK_RELEASE_TIME, false);
+        assert_eq!(buses.gain(AudioBus::Music), 0.4);
+
+        // Scripts fade a bus on top of the settings and can turn ducking off
+        buses.set_script_volume(AudioBus::Music, 0.5);
+        buses.set_ducking(false);
+        buses.frame(1.0, true);
+        assert_eq!(buses.gain(AudioBus::Music), 0.2);
+
+        buses.apply_settings(&AudioSettings::default());
+        assert_eq!((buses.script_volume(AudioBus::Music), buses.is_ducking()), (0.5, true));
+        buses.set_script_volume(AudioBus::Master, 0.0);
+        assert!(AudioBus::ALL.iter().all(|&bus| buses.gain(bus) == 0.0));
+    }
+}
@@ -0,0 +1,387 @@
+/*
+
+Software mixer
+
+Mixes the voices the VoiceManager hands out into one stereo buffer at
+whatever rate the output device runs at. Each voice steps through its sample
+at its own rate, the sample's rate over the output's times the voice's pitch,
+so 11khz and 22khz samples, a 44khz music cue and a sound doppler shifted up a
+few percent all come out at the right speed together.
+
+Between the sample's frames the mixer resamples with the interpolation the
+detail settings ask for: nearest is cheapest and sounds gritty when pitched,
+linear is what retail's software mixer did, cubic is smoothest.
+
+*/
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+
+use crate::{config::DetailSettings, math::vector::Vector};
+
+use super::{
+    audio_bus::{AudioBus, AudioBuses},
+    audio_stream::StreamFormat,
+    prelude::*,
+};
+
+/// In game units a second, a unit is about a meter
+pub const SOUND_SPEED: f32 = 343.0;
+
+/// Voices can't be slowed or sped up past these
+pub const MIN_PITCH: f32 = 0.25;
+pub const MAX_PITCH: f32 = 4.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum InterpolationQuality {
+    Nearest,
+    #[default]
+    Linear,
+    /// Catmull-Rom through the four nearest frames
+    Cubic,
+}
+
+impl InterpolationQuality {
+    pub fn from_detail(detail: &DetailSettings) -> Self {
+        match detail.sound_quality {
+            0 => InterpolationQuality::Nearest,
+            1 => InterpolationQuality::Linear,
+            _ => InterpolationQuality::Cubic,
+        }
+    }
+}
+
+/// Decoded sound data the mixer plays from, shared between every voice playing it
+#[derive(Debug, Clone)]
+pub struct MixerSample {
+    format: StreamFormat,
+    data: Arc<[i16]>,
+}
+
+impl MixerSample {
+    /// `data` is interleaved, mono and stereo are supported
+    pub fn new(format: StreamFormat, data: Vec<i16>) -> Result<Self> {
+        if !(1..=2).contains(&format.channels) {
+            return Err(anyhow!("the mixer can't play {} channel sounds", format.channels));
+        }
+
+        if format.sample_rate == 0 {
+            return Err(anyhow!("sound has no sample rate"));
+        }
+
+        Ok(Self { format, data: data.into() })
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Length in frames
+    pub fn frames(&self) -> usize {
+        self.data.len() / self.format.channels as usize
+    }
+
+    /// Left and right of a frame, mono plays the same on both sides
+    fn frame(&self, index: usize) -> (f32, f32) {
+        if self.format.channels == 2 {
+            (self.data[index * 2] as f32, self.data[index * 2 + 1] as f32)
+        } else {
+            let sample = self.data[index] as f32;
+            (sample, sample)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MixerVoice {
+    sample: MixerSample,
+    /// Where in the sample the next output frame comes from, in frames
+    position: f64,
+    volume: f32,
+    pitch: f32,
+    looped: bool,
+    bus: AudioBus,
+}
+
+impl MixerVoice {
+    /// A frame, wrapping around for looped sounds and holding the ends otherwise
+    fn frame(&self, index: isize) -> (f32, f32) {
+        let frames = self.sample.frames() as isize;
+
+        let index = if self.looped { index.rem_euclid(frames) } else { index.clamp(0, frames - 1) };
+
+        self.sample.frame(index as usize)
+    }
+
+    fn sample_at(&self, quality: InterpolationQuality) -> (f32, f32) {
+        let whole = self.position.floor();
+        let index = whole as isize;
+        let t = (self.position - whole) as f32;
+
+        match quality {
+            InterpolationQuality::Nearest => self.frame(self.position.round() as isize),
+            InterpolationQuality::Linear => {
+                let (a, b) = (self.frame(index), self.frame(index + 1));
+                (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+            }
+            InterpolationQuality::Cubic => {
+                let p = [self.frame(index - 1), self.frame(index), self.frame(index + 1), self.frame(index + 2)];
+                (catmull_rom(p.map(|f| f.0), t), catmull_rom(p.map(|f| f.1), t))
+            }
+        }
+    }
+}
+
+fn catmull_rom(p: [f32; 4], t: f32) -> f32 {
+    p[1] + 0.5 * t * (p[2] - p[0] + t * (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3] + t * (3.0 * (p[1] - p[2]) + p[3] - p[0])))
+}
+
+/// Plays voices into a stereo buffer, voices are known by the uids the VoiceManager gave them
+#[derive(Debug)]
+pub struct AudioMixer {
+    output_rate: u32,
+    quality: InterpolationQuality,
+    voices: HashMap<u32, MixerVoice>,
+    /// Each bus's gain as of the last `set_bus_gains`
+    bus_gains: [f32; AudioBus::ALL.len()],
+}
+
+impl AudioMixer {
+    pub fn new(output_rate: u32, quality: InterpolationQuality) -> Self {
+        Self {
+            output_rate: output_rate.max(1),
+            quality,
+            voices: HashMap::new(),
+            bus_gains: [1.0; AudioBus::ALL.len()],
+        }
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    pub fn quality(&self) -> InterpolationQuality {
+        self.quality
+    }
+
+    /// For when the detail settings change, voices already playing carry on with the new one
+    pub fn set_quality(&mut self, quality: InterpolationQuality) {
+        self.quality = quality;
+    }
+
+    /// Starts a sound effect, replacing whatever `uid` was playing
+    pub fn play(&mut self, uid: u32, sample: &MixerSample, volume: f32, pitch: f32, looped: bool) {
+        self.play_on_bus(uid, sample, AudioBus::Sfx, volume, pitch, looped);
+    }
+
+    /// Starts a voice on a bus, replacing whatever `uid` was playing
+    pub fn play_on_bus(&mut self, uid: u32, sample: &MixerSample, bus: AudioBus, volume: f32, pitch: f32, looped: bool) {
+        if sample.frames() == 0 {
+            return;
+        }
+
+        self.voices.insert(
+            uid,
+            MixerVoice {
+                sample: sample.clone(),
+                position: 0.0,
+                volume,
+                pitch: pitch.clamp(MIN_PITCH, MAX_PITCH),
+                looped,
+                bus,
+            },
+        );
+    }
+
+    pub fn stop(&mut self, uid: u32) -> bool {
+        self.voices.remove(&uid).is_some()
+    }
+
+    pub fn set_volume(&mut self, uid: u32, volume: f32) {
+        if let Some(voice) = self.voices.get_mut(&uid) {
+            voice.volume = volume;
+        }
+    }
+
+    /// Changes the playback rate of a voice as it plays, for doppler
+    pub fn set_pitch(&mut self, uid: u32, pitch: f32) {
+        if let Some(voice) = self.voices.get_mut(&uid) {
+            voice.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        }
+    }
+
+    pub fn is_playing(&self, uid: u32) -> bool {
+        self.voices.contains_key(&uid)
+    }
+
+    pub fn voice_count(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Whether anything is playing on a bus, the voice bus being busy is what ducks the others
+    pub fn is_bus_playing(&self, bus: AudioBus) -> bool {
+        self.voices.values().any(|v| v.bus == bus)
+    }
+
+    /// Takes the buses' gains for mixing from here on, once a frame after they've moved
+    pub fn set_bus_gains(&mut self, buses: &AudioBuses) {
+        for bus in AudioBus::ALL {
+            self.bus_gains[bus.index()] = buses.gain(bus);
+        }
+    }
+
+    /// Fills interleaved stereo `out` with every voice mixed together. Returns the voices that
+    /// came to an end, for releasing from the VoiceManager
+    pub fn mix(&mut self, out: &mut [i16]) -> Vec<u32> {
+        let frames = out.len() / 2;
+        let mut mixed = vec![0.0f32; frames * 2];
+        let mut finished = Vec::new();
+
+        for (&uid, voice) in self.voices.iter_mut() {
+            let length = voice.sample.frames() as f64;
+            let step = voice.sample.format.sample_rate as f64 / self.output_rate as f64 * voice.pitch as f64;
+            let volume = voice.volume * self.bus_gains[voice.bus.index()];
+
+            for frame in mixed.chunks_exact_mut(2) {
+                if !voice.looped && voice.position >= length {
+                    break;
+                }
+
+                let (left, right) = voice.sample_at(self.quality);
+                frame[0] += left * volume;
+                frame[1] += right * volume;
+
+                voice.position += step;
+
+                if voice.looped {
+                    voice.position %= length;
+                }
+            }
+
+            if !voice.looped && voice.position >= length {
+                finished.push(uid);
+            }
+        }
+
+        for uid in finished.iter() {
+            self.voices.remove(uid);
+        }
+
+        for (sample, mixed) in out.iter_mut().zip(mixed) {
+            *sample = mixed.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+
+        out[frames * 2..].fill(0);
+
+        finished
+    }
+}
+
+/// Pitch of a sound as heard with the source and listener moving, above 1 when they're closing in.
+/// Sounds flagged FIXED_FREQ in the sound table shouldn't be shifted
+pub fn doppler_pitch(listener_pos: &Vector, listener_velocity: &Vector, source_pos: &Vector, source_velocity: &Vector) -> f32 {
+    let to_listener = *listener_pos - *source_pos;
+    let distance = Vector::magnitude(&to_listener);
+
+    if distance <= f32::EPSILON {
+        return 1.0;
+    }
+
+    let direction = to_listener * (1.0 / distance);
+
+    // Speeds along the line between them, positive for moving the way the sound travels
+    let listener_speed = listener_velocity.dot(direction);
+    let source_speed = source_velocity.dot(direction);
+
+    let pitch = (SOUND_SPEED - listener_speed) / (SOUND_SPEED - source_speed).max(f32::EPSILON);
+
+    pitch.clamp(MIN_PITCH, MAX_PITCH)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn ramp(sample_rate: u32) -> MixerSample {
+        MixerSample::new(StreamFormat { channels: 1, sample_rate }, vec![0, 1000, 2000, 3000]).unwrap()
+    }
+
+    /// The left channel of a mix
+    fn left(out: &[i16]) -> Vec<i16> {
+        out.iter().step_by(2).copied().collect()
+    }
+
+    #[test]
+    fn mixer_resample_test() {
+        crate::test_common::setup();
+
+        // An 11khz sound on a 22khz output takes twice the frames
+        let mut mixer = AudioMixer::new(22050, InterpolationQuality::Linear);
+        mixer.play(1, &ramp(11025), 1.0, 1.0, false);
+
+        let mut out = [1i16; 20];
+        assert_eq!(mixer.mix(&mut out), vec![1]);
+        assert_eq!(left(&out), [0, 500, 1000, 1500, 2000, 2500, 3000, 3000, 0, 0]);
+        assert_eq!(out[2..4], [500, 500]);
+        assert!(!mixer.is_playing(1));
+
+        mixer.set_quality(InterpolationQuality::Nearest);
+        mixer.play(1, &ramp(11025), 0.5, 1.0, false);
+        mixer.mix(&mut out);
+        assert_eq!(left(&out)[..4], [0, 500, 500, 1000]);
+
+        mixer.set_quality(InterpolationQuality::Cubic);
+        mixer.play(1, &ramp(11025), 1.0, 1.0, false);
+        mixer.mix(&mut out);
+        assert_eq!(left(&out)[2..4], [1000, 1500]);
+
+        // Twice the pitch gets through in half the time, a loop just keeps going
+        mixer.play(1, &ramp(22050), 1.0, 2.0, false);
+        mixer.play(2, &ramp(22050), 1.0, 1.0, true);
+        let mut out = [0i16; 12];
+        assert_eq!(mixer.mix(&mut out), vec![1]);
+        assert_eq!(left(&out), [0, 3000, 2000, 3000, 0, 1000]);
+        assert!(mixer.is_playing(2));
+
+        mixer.set_pitch(2, 100.0);
+        mixer.set_volume(2, 0.0);
+        mixer.mix(&mut out);
+        assert_eq!(out, [0; 12]);
+        assert!(mixer.stop(2) && mixer.voice_count() == 0);
+
+        // Buses scale what's on them
+        let mut buses = AudioBuses::default();
+        buses.set_script_volume(AudioBus::Music, 0.5);
+        mixer.set_quality(InterpolationQuality::Linear);
+        mixer.set_bus_gains(&buses);
+        mixer.play_on_bus(3, &ramp(22050), AudioBus::Music, 1.0, 1.0, false);
+        assert!(mixer.is_bus_playing(AudioBus::Music) && !mixer.is_bus_playing(AudioBus::Voice));
+        mixer.mix(&mut out);
+        assert_eq!(left(&out)[..4], [0, 250, 500, 750]);
+
+        assert!(MixerSample::new(StreamFormat { channels: 6, sample_rate: 22050 }, Vec::new()).is_err());
+
+        let mut detail = DetailSettings::default();
+        assert_eq!(InterpolationQuality::from_detail(&detail), InterpolationQuality::Linear);
+        detail.sound_quality = 2;
+        assert_eq!(InterpolationQuality::from_detail(&detail), InterpolationQuality::Cubic);
+    }
+
+    #[test]
+    fn doppler_test() {
+        crate::test_common::setup();
+
+        let ear = Vector::ZERO;
+        let source = Vector::new(0.0, 0.0, 100.0);
+        let toward = Vector::new(0.0, 0.0, -50.0);
+
+        assert_eq!(doppler_pitch(&ear, &Vector::ZERO, &source, &Vector::ZERO), 1.0);
+        assert!(doppler_pitch(&ear, &Vector::ZERO, &source, &toward) > 1.0);
+        assert!(doppler_pitch(&ear, &Vector::ZERO, &source, &(toward * -1.0)) < 1.0);
+
+        // The listener flying at the sound works the same way
+        assert!(doppler_pitch(&ear, &(toward * -1.0), &source, &Vector::ZERO) > 1.0);
+        assert_eq!(doppler_pitch(&ear, &Vector::ZERO, &source, &Vector::new(0.0, 0.0, -10000.0)), MAX_PITCH);
+    }
+}
@@ -0,0 +1,544 @@
+/*
+
+Streamed audio
+
+Music and briefing voiceovers run for minutes, decoding them whole would
+hold tens of megabytes of samples for something that plays once. A stream
+instead has a worker thread read its source a chunk at a time into a ring
+buffer that the mixer drains, keeping only a few seconds in memory. Where
+there are no threads, wasm32 in a browser, the mixer reads the chunks itself
+as it drains the buffer.
+
+Seeking clears the buffer and bumps a generation count, so a chunk the
+worker was already reading from the old position gets thrown away rather
+than played.
+
+*/
+
+use std::{
+    collections::VecDeque,
+    io::{Read, Seek, SeekFrom},
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+
+/// Frames the worker reads at a time
+pub const STREAM_CHUNK_FRAMES: usize = 4096;
+
+/// Frames held ahead of the mixer, a little under a second and a half at 44khz
+pub const STREAM_BUFFER_FRAMES: usize = 16 * STREAM_CHUNK_FRAMES;
+
+/// Whether the target can spawn worker threads, wasm32 in a browser can't
+const THREADS_AVAILABLE: bool = cfg!(not(target_arch = "wasm32"));
+
+/// Sounds bigger than this are streamed rather than decoded whole
+pub const STREAM_MIN_BYTES: usize = 1024 * 1024;
+
+/// Whether a sound of `size` bytes should be streamed
+pub fn should_stream(size: usize) -> bool {
+    size >= STREAM_MIN_BYTES
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StreamFormat {
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+/// Something a stream can decode from a chunk at a time, samples come out as interleaved 16 bit
+pub trait StreamSource: Send {
+    fn format(&self) -> StreamFormat;
+
+    /// Length in frames
+    fn length(&self) -> usize;
+
+    /// Fills as much of `out` as it can, whole frames only, returns how many samples. 0 at the end
+    fn read(&mut self, out: &mut [i16]) -> Result<usize>;
+
+    fn seek(&mut self, frame: usize) -> Result<()>;
+}
+
+/// 8 or 16 bit PCM out of a RIFF wave file
+pub struct WavSource<R: Read + Seek + Send> {
+    reader: R,
+    format: StreamFormat,
+    bits: u16,
+    data_start: u64,
+    frames: usize,
+    position: usize,
+}
+
+impl<R: Read + Seek + Send> WavSource<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut tag = [0u8; 4];
+
+        reader.read_exact(&mut tag)?;
+        if &tag != b"RIFF" {
+            return Err(anyhow!("Not a RIFF file"));
+        }
+
+        reader.read_u32::<LittleEndian>()?;
+
+        reader.read_exact(&mut tag)?;
+        if &tag != b"WAVE" {
+            return Err(anyhow!("Not a wave file"));
+        }
+
+        let mut format = None;
+
+        loop {
+            reader.read_exact(&mut tag).context("No data chunk in wave file")?;
+            let size = reader.read_u32::<LittleEndian>()? as u64;
+
+            match &tag {
+                b"fmt " => {
+                    let format_tag = reader.read_u16::<LittleEndian>()?;
+                    let channels = reader.read_u16::<LittleEndian>()?;
+                    let sample_rate = reader.read_u32::<LittleEndian>()?;
+                    reader.read_u32::<LittleEndian>()?;
+                    reader.read_u16::<LittleEndian>()?;
+                    let bits = reader.read_u16::<LittleEndian>()?;
+
+                    if format_tag != 1 || !(bits == 8 || bits == 16) || channels == 0 {
+                        return Err(anyhow!("Unsupported wave format {} with {} bits", format_tag, bits));
+                    }
+
+                    reader.seek(SeekFrom::Current(size as i64 - 16 + (size & 1) as i64))?;
+                    format = Some((StreamFormat { channels, sample_rate }, bits));
+                }
+                b"data" => {
+                    let (format, bits) = format.ok_or_else(|| anyhow!("Wave data before its format"))?;
+                    let data_start = reader.stream_position()?;
+                    let frame_size = (format.channels * bits / 8) as u64;
+
+                    return Ok(Self {
+                        reader,
+                        format,
+                        bits,
+                        data_start,
+                        frames: (size / frame_size) as usize,
+                        position: 0,
+                    });
+                }
+                // Chunks are padded to even sizes
+                _ => {
+                    reader.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+                }
+            }
+        }
+    }
+
+    fn frame_size(&self) -> usize {
+        (self.format.channels * self.bits / 8) as usize
+    }
+}
+
+impl<R: Read + Seek + Send> StreamSource for WavSource<R> {
+    fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    fn length(&self) -> usize {
+        self.frames
+    }
+
+    fn read(&mut self, out: &mut [i16]) -> Result<usize> {
+        let channels = self.format.channels as usize;
+        let frames = (out.len() / channels).min(self.frames - self.position);
+        let samples = frames * channels;
+
+        if self.bits == 16 {
+            self.reader.read_i16_into::<LittleEndian>(&mut out[..samples])?;
+        } else {
+            let mut bytes = vec![0u8; samples];
+            self.reader.read_exact(&mut bytes)?;
+
+            // 8 bit wave data is unsigned
+            for (sample, byte) in out.iter_mut().zip(bytes) {
+                *sample = (byte as i16 - 128) << 8;
+            }
+        }
+
+        self.position += frames;
+
+        Ok(samples)
+    }
+
+    fn seek(&mut self, frame: usize) -> Result<()> {
+        self.position = frame.min(self.frames);
+        self.reader.seek(SeekFrom::Start(self.data_start + (self.position * self.frame_size()) as u64))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct StreamState {
+    samples: VecDeque<i16>,
+    /// Frame of the source the front of `samples` came from
+    position: usize,
+    /// Bumped on every seek so chunks read from before it are dropped
+    generation: u32,
+    seek: Option<usize>,
+    /// The source has run out, everything left is in `samples`
+    finished: bool,
+    stop: bool,
+}
+
+#[derive(Debug, Default)]
+struct StreamShared {
+    state: Mutex<StreamState>,
+    wake: Condvar,
+}
+
+/// A long sound being decoded in the background, drained by the mixer with `fill`
+pub struct AudioStream {
+    name: String,
+    format: StreamFormat,
+    length: usize,
+    looped: bool,
+    shared: Arc<StreamShared>,
+    worker: Option<JoinHandle<()>>,
+    /// The reader when there's no worker, `fill` reads with it
+    inline: Option<Mutex<StreamReader>>,
+}
+
+impl std::fmt::Debug for AudioStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioStream")
+            .field("name", &self.name)
+            .field("format", &self.format)
+            .field("position", &self.position())
+            .finish()
+    }
+}
+
+impl AudioStream {
+    /// Starts the worker on a source, looped streams go back to the start when they run out
+    pub fn new(name: &str, source: Box<dyn StreamSource>, looped: bool) -> Result<Self> {
+        Self::with_worker(name, source, looped, THREADS_AVAILABLE)
+    }
+
+    fn with_worker(name: &str, source: Box<dyn StreamSource>, looped: bool, threaded: bool) -> Result<Self> {
+        let format = source.format();
+        let length = source.length();
+        let shared = Arc::new(StreamShared::default());
+        let reader = StreamReader::new(name, source, looped);
+
+        let (worker, inline) = if threaded {
+            let shared = shared.clone();
+
+            let worker = thread::Builder::new()
+                .name(format!("audio_stream_{}", name))
+                .spawn(move || stream_worker(reader, &shared))
+                .context("Failed to spawn audio stream thread")?;
+
+            (Some(worker), None)
+        } else {
+            (None, Some(Mutex::new(reader)))
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            format,
+            length,
+            looped,
+            shared,
+            worker,
+            inline,
+        })
+    }
+
+    pub fn open_wav<R: Read + Seek + Send + 'static>(name: &str, reader: R, looped: bool) -> Result<Self> {
+        let source = WavSource::new(reader).with_context(|| format!("Failed to open {}", name))?;
+        Self::new(name, Box::new(source), looped)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        self.format
+    }
+
+    /// Length in frames
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// The frame the mixer will get next
+    pub fn position(&self) -> usize {
+        self.shared.state.lock().unwrap().position
+    }
+
+    /// Moves the stream, the mixer gets silence until the worker has read from the new spot
+    pub fn seek(&self, frame: usize) {
+        let mut state = self.shared.state.lock().unwrap();
+        let frame = frame.min(self.length);
+
+        state.samples.clear();
+        state.position = frame;
+        state.generation = state.generation.wrapping_add(1);
+        state.seek = Some(frame);
+        state.finished = false;
+
+        self.shared.wake.notify_all();
+    }
+
+    /// Copies out as many buffered samples as fit, whole frames only. Never blocks, coming up
+    /// short means the worker has fallen behind or the stream is over
+    pub fn fill(&self, out: &mut [i16]) -> usize {
+        let channels = self.format.channels as usize;
+        let mut state = self.shared.state.lock().unwrap();
+
+        if let Some(reader) = &self.inline {
+            let mut reader = reader.lock().unwrap();
+
+            while state.samples.len() < out.len() && reader.has_work(&state) {
+                if let Some(generation) = reader.prepare(&mut state) {
+                    let read = reader.read();
+                    reader.store(&mut state, generation, read);
+                }
+            }
+        }
+
+        let count = (out.len().min(state.samples.len()) / channels) * channels;
+
+        for (sample, buffered) in out.iter_mut().zip(state.samples.drain(..count)) {
+            *sample = buffered;
+        }
+
+        state.position += count / channels;
+
+        if self.looped && self.length > 0 {
+            state.position %= self.length;
+        }
+
+        self.shared.wake.notify_all();
+
+        count
+    }
+
+    /// Everything has been played
+    pub fn is_finished(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        state.finished && state.samples.is_empty()
+    }
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().stop = true;
+        self.shared.wake.notify_all();
+
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A stream's source and the chunk it reads into, run by the worker or by `fill` when there's none
+struct StreamReader {
+    name: String,
+    source: Box<dyn StreamSource>,
+    looped: bool,
+    chunk: Vec<i16>,
+    capacity: usize,
+}
+
+impl StreamReader {
+    fn new(name: &str, source: Box<dyn StreamSource>, looped: bool) -> Self {
+        let channels = source.format().channels as usize;
+
+        Self {
+            name: name.to_string(),
+            source,
+            looped,
+            chunk: vec![0i16; STREAM_CHUNK_FRAMES * channels],
+            capacity: STREAM_BUFFER_FRAMES * channels,
+        }
+    }
+
+    /// There's a seek to do or room for another chunk
+    fn has_work(&self, state: &StreamState) -> bool {
+        state.seek.is_some() || (!state.finished && state.samples.len() + self.chunk.len() <= self.capacity)
+    }
+
+    /// Does any pending seek, then gives the generation the next chunk is read for
+    fn prepare(&mut self, state: &mut StreamState) -> Option<u32> {
+        if let Some(frame) = state.seek.take() {
+            if let Err(e) = self.source.seek(frame) {
+                warn!(target: "audio", "Failed to seek stream {}: {:#}", self.name, e);
+                state.finished = true;
+                return None;
+            }
+        }
+
+        Some(state.generation)
+    }
+
+    fn read(&mut self) -> Result<usize> {
+        self.source.read(&mut self.chunk)
+    }
+
+    /// Adds a chunk to the buffer, unless there was a seek since it was read
+    fn store(&mut self, state: &mut StreamState, generation: u32, read: Result<usize>) {
+        if state.generation != generation {
+            return;
+        }
+
+        match read {
+            Ok(0) if self.looped && self.source.length() > 0 => {
+                if let Err(e) = self.source.seek(0) {
+                    warn!(target: "audio", "Failed to loop stream {}: {:#}", self.name, e);
+                    state.finished = true;
+                }
+            }
+            Ok(0) => state.finished = true,
+            Ok(count) => state.samples.extend(&self.chunk[..count]),
+            Err(e) => {
+                warn!(target: "audio", "Failed to read stream {}: {:#}", self.name, e);
+                state.finished = true;
+            }
+        }
+    }
+}
+
+fn stream_worker(mut reader: StreamReader, shared: &StreamShared) {
+    loop {
+        let generation = {
+            let mut state = shared.state.lock().unwrap();
+
+            while !state.stop && !reader.has_work(&state) {
+                state = shared.wake.wait(state).unwrap();
+            }
+
+            if state.stop {
+                return;
+            }
+
+            match reader.prepare(&mut state) {
+                Some(generation) => generation,
+                None => continue,
+            }
+        };
+
+        // Read without the lock so the mixer is never held up by the disk
+        let read = reader.read();
+
+        let mut state = shared.state.lock().unwrap();
+        reader.store(&mut state, generation, read);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::Cursor;
+
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+
+    fn wave(samples: &[i16]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let data_size = samples.len() as u32 * 2;
+
+        data.extend_from_slice(b"RIFF");
+        data.write_u32::<LittleEndian>(36 + data_size).unwrap();
+        data.extend_from_slice(b"WAVE");
+
+        data.extend_from_slice(b"fmt ");
+        data.write_u32::<LittleEndian>(16).unwrap();
+        data.write_u16::<LittleEndian>(1).unwrap();
+        data.write_u16::<LittleEndian>(2).unwrap();
+        data.write_u32::<LittleEndian>(22050).unwrap();
+        data.write_u32::<LittleEndian>(22050 * 4).unwrap();
+        data.write_u16::<LittleEndian>(4).unwrap();
+        data.write_u16::<LittleEndian>(16).unwrap();
+
+        data.extend_from_slice(b"data");
+        data.write_u32::<LittleEndian>(data_size).unwrap();
+
+        for sample in samples {
+            data.write_i16::<LittleEndian>(*sample).unwrap();
+        }
+
+        data
+    }
+
+    /// Drains a stream until it's over or has given `limit` samples
+    fn drain(stream: &AudioStream, limit: usize) -> Vec<i16> {
+        let mut played = Vec::new();
+        let mut buffer = [0i16; 1000];
+
+        while played.len() < limit && !stream.is_finished() {
+            // Never take more than asked for, the stream's position has to match
+            let wanted = (limit - played.len()).min(buffer.len());
+            let count = stream.fill(&mut buffer[..wanted]);
+            played.extend_from_slice(&buffer[..count]);
+
+            if count == 0 {
+                thread::yield_now();
+            }
+        }
+
+        played
+    }
+
+    #[test]
+    fn audio_stream_test() {
+        crate::test_common::setup();
+
+        // Several chunks of stereo, longer than the ring buffer holds
+        let frames = STREAM_BUFFER_FRAMES * 2 + 123;
+        let samples: Vec<i16> = (0..frames * 2).map(|i| (i % 30000) as i16).collect();
+
+        let stream = AudioStream::open_wav("music.wav", Cursor::new(wave(&samples)), false).unwrap();
+        assert_eq!(stream.format(), StreamFormat { channels: 2, sample_rate: 22050 });
+        assert_eq!(stream.length(), frames);
+
+        assert_eq!(drain(&stream, usize::MAX), samples);
+        assert!(stream.is_finished());
+
+        // Seeking picks up from the new frame, even after it's over
+        stream.seek(1000);
+        assert_eq!(drain(&stream, 4), samples[2000..2004]);
+        assert_eq!(stream.position(), 1002);
+    }
+
+    #[test]
+    fn audio_stream_loop_test() {
+        crate::test_common::setup();
+
+        let samples: Vec<i16> = (0..200).collect();
+        let stream = AudioStream::open_wav("loop.wav", Cursor::new(wave(&samples)), true).unwrap();
+
+        let played = drain(&stream, 500);
+        assert_eq!(played[..200], samples[..]);
+        assert_eq!(played[200..400], samples[..]);
+        assert!(!stream.is_finished());
+
+        assert!(AudioStream::open_wav("bad.wav", Cursor::new(b"RIFX".to_vec()), false).is_err());
+    }
+
+    #[test]
+    fn audio_stream_without_worker_test() {
+        crate::test_common::setup();
+
+        // As on wasm32, the stream is read as it's drained
+        let frames = STREAM_BUFFER_FRAMES + 123;
+        let samples: Vec<i16> = (0..frames * 2).map(|i| (i % 30000) as i16).collect();
+        let source = WavSource::new(Cursor::new(wave(&samples))).unwrap();
+        let stream = AudioStream::with_worker("music.wav", Box::new(source), false, false).unwrap();
+
+        assert_eq!(drain(&stream, usize::MAX), samples);
+        assert!(stream.is_finished());
+
+        stream.seek(1000);
+        assert_eq!(drain(&stream, 4), samples[2000..2004]);
+    }
+}
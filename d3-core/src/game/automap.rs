@@ -0,0 +1,614 @@
+use std::collections::HashSet;
+
+use angle::{Angle, EulerAngle};
+use matrix::Matrix;
+use vector::Vector;
+
+use crate::{
+    gr_rgb,
+    graphics::{ddgr_color, drawing_3d::ScreenViewPort, GR_GREEN, GR_WHITE},
+};
+
+use super::{
+    prelude::*,
+    room::{Room, RoomFlags},
+    terrain::{Terrain, TERRAIN_DEPTH, TERRAIN_SIZE, TERRAIN_WIDTH},
+};
+
+pub const AUTOMAP_MIN_DISTANCE: f32 = 50.0;
+pub const AUTOMAP_MAX_DISTANCE: f32 = 5000.0;
+const AUTOMAP_DEFAULT_DISTANCE: f32 = 800.0;
+
+/// Anything closer to the eye than this gets clipped away
+const AUTOMAP_NEAR_Z: f32 = 1.0;
+
+/// Only every Nth terrain cell gets an outline, so the map doesn't turn into a solid sheet
+const TERRAIN_CELL_STEP: usize = 4;
+
+pub const AUTOMAP_WALL_COLOR: ddgr_color = GR_GREEN;
+pub const AUTOMAP_DOOR_COLOR: ddgr_color = gr_rgb!(0, 128, 255);
+pub const AUTOMAP_FUELCEN_COLOR: ddgr_color = gr_rgb!(255, 255, 0);
+pub const AUTOMAP_GOAL_COLOR: ddgr_color = gr_rgb!(255, 0, 255);
+pub const AUTOMAP_TERRAIN_COLOR: ddgr_color = gr_rgb!(0, 96, 0);
+pub const AUTOMAP_PLAYER_COLOR: ddgr_color = GR_WHITE;
+pub const AUTOMAP_MARKER_COLOR: ddgr_color = gr_rgb!(255, 128, 0);
+
+/// Remembers which parts of the level the player has seen.
+#[derive(Debug, Clone)]
+pub struct AutomapVisMap {
+    rooms: HashSet<usize>,
+    terrain_cells: Vec<bool>,
+}
+
+impl Default for AutomapVisMap {
+    fn default() -> Self {
+        Self {
+            rooms: HashSet::new(),
+            terrain_cells: vec![false; TERRAIN_WIDTH * TERRAIN_DEPTH],
+        }
+    }
+}
+
+impl AutomapVisMap {
+    pub fn mark_room(&mut self, room_id: usize) {
+        self.rooms.insert(room_id);
+    }
+
+    pub fn is_room_visited(&self, room_id: usize) -> bool {
+        self.rooms.contains(&room_id)
+    }
+
+    pub fn mark_terrain_cell(&mut self, seg: usize) {
+        if seg < self.terrain_cells.len() {
+            self.terrain_cells[seg] = true;
+        }
+    }
+
+    pub fn is_terrain_cell_visited(&self, seg: usize) -> bool {
+        self.terrain_cells.get(seg).copied().unwrap_or(false)
+    }
+
+    /// Marks the cells around a position on the terrain, `radius` is in cells
+    pub fn mark_terrain_position(&mut self, position: &Vector, radius: usize) {
+        if position.x < 0.0 || position.z < 0.0 {
+            return;
+        }
+
+        let cx = (position.x / TERRAIN_SIZE) as usize;
+        let cz = (position.z / TERRAIN_SIZE) as usize;
+
+        if cx >= TERRAIN_WIDTH || cz >= TERRAIN_DEPTH {
+            return;
+        }
+
+        for z in cz.saturating_sub(radius)..(cz + radius + 1).min(TERRAIN_DEPTH) {
+            for x in cx.saturating_sub(radius)..(cx + radius + 1).min(TERRAIN_WIDTH) {
+                self.terrain_cells[z * TERRAIN_WIDTH + x] = true;
+            }
+        }
+    }
+
+    /// Reveals every room that isn't secret, used by the full map cheat
+    pub fn reveal_rooms<'a>(&mut self, rooms: impl Iterator<Item = &'a Room>) {
+        for room in rooms {
+            if !room.flags.contains(RoomFlags::SECRET) {
+                self.rooms.insert(room.id());
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rooms.clear();
+        self.terrain_cells.iter_mut().for_each(|c| *c = false);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AutomapPolygon {
+    pub points: Vec<Vector>,
+    pub color: ddgr_color,
+}
+
+/// A point of interest drawn on top of the map.
+#[derive(Debug, Clone)]
+pub struct AutomapMarker {
+    pub position: Vector,
+    pub color: ddgr_color,
+    pub label: Option<String>,
+}
+
+/// Simplified geometry for the visited parts of the level.
+#[derive(Debug, Clone, Default)]
+pub struct AutomapMesh {
+    pub edges: Vec<(Vector, Vector, ddgr_color)>,
+    pub polygons: Vec<AutomapPolygon>,
+}
+
+impl AutomapMesh {
+    pub fn clear(&mut self) {
+        self.edges.clear();
+        self.polygons.clear();
+    }
+
+    /// Adds the non portal faces of a room, shared edges are only added once
+    pub fn add_room(&mut self, room: &Room) {
+        let color = room_color(room);
+        let mut seen_edges = HashSet::new();
+
+        for face in room.faces.iter() {
+            if face.portal.is_some() || face.face_verts.len() < 3 {
+                continue;
+            }
+
+            let count = face.face_verts.len();
+
+            for i in 0..count {
+                let a = face.face_verts[i];
+                let b = face.face_verts[(i + 1) % count];
+                let key = if a < b { (a, b) } else { (b, a) };
+
+                if seen_edges.insert(key) {
+                    self.edges.push((room.vertices[a], room.vertices[b], color));
+                }
+            }
+
+            self.polygons.push(AutomapPolygon {
+                points: face.face_verts.iter().map(|v| room.vertices[*v]).collect(),
+                color,
+            });
+        }
+    }
+
+    /// Adds an outline for the visited terrain cells
+    pub fn add_terrain(&mut self, terrain: &Terrain, vis_map: &AutomapVisMap) {
+        let height_at = |x: usize, z: usize| terrain.segments[z * TERRAIN_WIDTH + x].y;
+
+        for z in (0..TERRAIN_DEPTH - TERRAIN_CELL_STEP).step_by(TERRAIN_CELL_STEP) {
+            for x in (0..TERRAIN_WIDTH - TERRAIN_CELL_STEP).step_by(TERRAIN_CELL_STEP) {
+                if !vis_map.is_terrain_cell_visited(z * TERRAIN_WIDTH + x) {
+                    continue;
+                }
+
+                let corner = |cx: usize, cz: usize| Vector {
+                    x: cx as f32 * TERRAIN_SIZE,
+                    y: height_at(cx, cz),
+                    z: cz as f32 * TERRAIN_SIZE,
+                };
+
+                let a = corner(x, z);
+                let b = corner(x + TERRAIN_CELL_STEP, z);
+                let c = corner(x, z + TERRAIN_CELL_STEP);
+
+                self.edges.push((a, b, AUTOMAP_TERRAIN_COLOR));
+                self.edges.push((a, c, AUTOMAP_TERRAIN_COLOR));
+            }
+        }
+    }
+}
+
+fn room_color(room: &Room) -> ddgr_color {
+    if room.flags.contains(RoomFlags::FUELCEN) {
+        AUTOMAP_FUELCEN_COLOR
+    } else if room
+        .flags
+        .intersects(RoomFlags::GOAL1 | RoomFlags::GOAL2 | RoomFlags::GOAL3 | RoomFlags::GOAL4)
+    {
+        AUTOMAP_GOAL_COLOR
+    } else if room.flags.contains(RoomFlags::DOOR) {
+        AUTOMAP_DOOR_COLOR
+    } else {
+        AUTOMAP_WALL_COLOR
+    }
+}
+
+/// Orbit camera used while the automap is up.
+#[derive(Debug, Copy, Clone)]
+pub struct AutomapCamera {
+    pub target: Vector,
+    pub heading: Angle,
+    pub pitch: Angle,
+    pub distance: f32,
+}
+
+impl Default for AutomapCamera {
+    fn default() -> Self {
+        Self {
+            target: Vector::ZERO,
+            heading: Angle(0),
+            pitch: Angle(0),
+            distance: AUTOMAP_DEFAULT_DISTANCE,
+        }
+    }
+}
+
+impl AutomapCamera {
+    /// Spins the camera around the target, amounts are in angle units
+    pub fn rotate(&mut self, heading: i32, pitch: i32) {
+        self.heading = Angle(self.heading.0.wrapping_add(heading as u16));
+        self.pitch = Angle(self.pitch.0.wrapping_add(pitch as u16));
+    }
+
+    /// Zooms in for factors below 1 and out for factors above 1
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).clamp(AUTOMAP_MIN_DISTANCE, AUTOMAP_MAX_DISTANCE);
+    }
+
+    pub fn orientation(&self) -> Matrix {
+        Matrix::compute_rotation_3d(&EulerAngle {
+            pitch: self.pitch,
+            heading: self.heading,
+            bank: Angle(0),
+        })
+    }
+
+    pub fn position(&self) -> Vector {
+        self.target - self.orientation().forward * self.distance
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ScreenLine {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+    pub color: ddgr_color,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScreenPolygon {
+    pub points: Vec<(f32, f32)>,
+    pub color: ddgr_color,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScreenMarker {
+    pub x: f32,
+    pub y: f32,
+    pub color: ddgr_color,
+    pub label: Option<String>,
+}
+
+/// Everything the automap wants drawn this frame, already projected to the screen.
+#[derive(Debug, Clone, Default)]
+pub struct AutomapDrawList {
+    pub lines: Vec<ScreenLine>,
+    pub polygons: Vec<ScreenPolygon>,
+    pub markers: Vec<ScreenMarker>,
+}
+
+struct AutomapProjector {
+    position: Vector,
+    orientation: Matrix,
+    center_x: f32,
+    center_y: f32,
+    scale_x: f32,
+    scale_y: f32,
+}
+
+impl AutomapProjector {
+    fn new(camera: &AutomapCamera, viewport: &ScreenViewPort) -> Self {
+        let half_w = viewport.width as f32 / 2.0;
+        let half_h = viewport.height as f32 / 2.0;
+
+        Self {
+            position: camera.position(),
+            orientation: camera.orientation(),
+            center_x: viewport.x as f32 + half_w,
+            center_y: viewport.y as f32 + half_h,
+            scale_x: half_w,
+            scale_y: half_w * viewport.aspect.max(f32::EPSILON),
+        }
+    }
+
+    fn to_view(&self, point: &Vector) -> Vector {
+        self.orientation * (*point - self.position)
+    }
+
+    fn to_screen(&self, view: &Vector) -> (f32, f32) {
+        (
+            self.center_x + (view.x / view.z) * self.scale_x,
+            self.center_y - (view.y / view.z) * self.scale_y,
+        )
+    }
+
+    fn project_point(&self, point: &Vector) -> Option<(f32, f32)> {
+        let view = self.to_view(point);
+
+        if view.z < AUTOMAP_NEAR_Z {
+            return None;
+        }
+
+        Some(self.to_screen(&view))
+    }
+
+    /// Projects a line, clipping it against the near plane
+    fn project_line(&self, a: &Vector, b: &Vector) -> Option<((f32, f32), (f32, f32))> {
+        let mut va = self.to_view(a);
+        let mut vb = self.to_view(b);
+
+        if va.z < AUTOMAP_NEAR_Z && vb.z < AUTOMAP_NEAR_Z {
+            return None;
+        }
+
+        if va.z < AUTOMAP_NEAR_Z || vb.z < AUTOMAP_NEAR_Z {
+            let t = (AUTOMAP_NEAR_Z - va.z) / (vb.z - va.z);
+            let clipped = va + (vb - va) * t;
+
+            if va.z < AUTOMAP_NEAR_Z {
+                va = clipped;
+            } else {
+                vb = clipped;
+            }
+        }
+
+        Some((self.to_screen(&va), self.to_screen(&vb)))
+    }
+}
+
+/// The automap mode: the player's visited map, the geometry built from it and the camera looking at it.
+#[derive(Debug, Clone, Default)]
+pub struct Automap {
+    pub vis_map: AutomapVisMap,
+    pub mesh: AutomapMesh,
+    pub camera: AutomapCamera,
+    pub markers: Vec<AutomapMarker>,
+    pub draw_solid: bool,
+}
+
+impl Automap {
+    /// Rebuilds the map geometry from the visited rooms and terrain, call when the map is brought up
+    pub fn rebuild<'a>(&mut self, rooms: impl Iterator<Item = &'a Room>, terrain: Option<&Terrain>) {
+        self.mesh.clear();
+
+        for room in rooms {
+            if self.vis_map.is_room_visited(room.id()) {
+                self.mesh.add_room(room);
+            }
+        }
+
+        if let Some(terrain) = terrain {
+            self.mesh.add_terrain(terrain, &self.vis_map);
+        }
+    }
+
+    /// Points the camera at the player, keeping its current rotation and zoom
+    pub fn center_on(&mut self, position: &Vector) {
+        self.camera.target = *position;
+    }
+
+    pub fn render(&self, viewport: &ScreenViewPort) -> AutomapDrawList {
+        let projector = AutomapProjector::new(&self.camera, viewport);
+        let mut list = AutomapDrawList::default();
+
+        if self.draw_solid {
+            for polygon in self.mesh.polygons.iter() {
+                let points: Option<Vec<(f32, f32)>> =
+                    polygon.points.iter().map(|p| projector.project_point(p)).collect();
+
+                // Polygons that cross the near plane are just dropped, their edges still get drawn
+                if let Some(points) = points {
+                    list.polygons.push(ScreenPolygon {
+                        points,
+                        color: polygon.color,
+                    });
+                }
+            }
+        }
+
+        for (a, b, color) in self.mesh.edges.iter() {
+            if let Some(((x0, y0), (x1, y1))) = projector.project_line(a, b) {
+                list.lines.push(ScreenLine { x0, y0, x1, y1, color: *color });
+            }
+        }
+
+        let player = AutomapMarker {
+            position: self.camera.target,
+            color: AUTOMAP_PLAYER_COLOR,
+            label: None,
+        };
+
+        for marker in self.markers.iter().chain(std::iter::once(&player)) {
+            if let Some((x, y)) = projector.project_point(&marker.position) {
+                list.markers.push(ScreenMarker {
+                    x,
+                    y,
+                    color: marker.color,
+                    label: marker.label.clone(),
+                });
+            }
+        }
+
+        list
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{game::room::{Face, FaceFlags, Portal, PortalFlags, RoomStore}, graphics::UVCoord};
+
+    use super::*;
+
+    fn face(verts: &[usize], portal: bool) -> Face {
+        Face {
+            flags: FaceFlags::empty(),
+            num_verts: verts.len(),
+            portal: portal.then(|| Rc::new(Portal {
+                flags: PortalFlags::empty(),
+                portal_face: None,
+                connected_room: None,
+                connected_portal: None,
+                bnode_index: (),
+                combine_master: (),
+                path_point: Vector::ZERO,
+            })),
+            face_verts: verts.to_vec(),
+            face_uvls: vec![UVCoord { u: 0.0, v: 0.0 }; verts.len()],
+            normal: Vector::new(0.0, 0.0, 1.0),
+            lightmap: None,
+            special_faces: (),
+            render_frame: (),
+            tmap: None,
+            light_muliple: 0,
+            min_xyz: Vector::ZERO,
+            max_xyz: Vector::ZERO,
+        }
+    }
+
+    /// Two triangles sharing an edge, plus a portal face
+    fn test_room(flags: RoomFlags) -> Room {
+        let mut room = Room::default();
+        room.flags = flags;
+        room.vertices = vec![
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(10.0, 0.0, 0.0),
+            Vector::new(10.0, 10.0, 0.0),
+            Vector::new(0.0, 10.0, 0.0),
+        ];
+        room.faces = vec![face(&[0, 1, 2], false), face(&[2, 3, 0], false), face(&[0, 1, 2, 3], true)];
+        room
+    }
+
+    fn viewport() -> ScreenViewPort {
+        ScreenViewPort { x: 0, y: 0, width: 640, height: 480, aspect: 1.0 }
+    }
+
+    #[test]
+    fn automap_vis_map_test() {
+        crate::test_common::setup();
+
+        let mut vis_map = AutomapVisMap::default();
+        vis_map.mark_room(3);
+        assert!(vis_map.is_room_visited(3));
+        assert!(!vis_map.is_room_visited(4));
+
+        // A block of cells around the position, cut off at the edge of the terrain
+        let cell = |x: usize, z: usize| z * TERRAIN_WIDTH + x;
+        vis_map.mark_terrain_position(&Vector::new(TERRAIN_SIZE * 0.5, 0.0, TERRAIN_SIZE * 5.5), 1);
+        assert!(vis_map.is_terrain_cell_visited(cell(0, 4)));
+        assert!(vis_map.is_terrain_cell_visited(cell(1, 6)));
+        assert!(!vis_map.is_terrain_cell_visited(cell(2, 5)));
+        assert!(!vis_map.is_terrain_cell_visited(cell(0, 7)));
+        assert_eq!(vis_map.terrain_cells.iter().filter(|c| **c).count(), 6);
+
+        // Off the terrain marks nothing
+        vis_map.mark_terrain_position(&Vector::new(-1.0, 0.0, 0.0), 1);
+        vis_map.mark_terrain_position(&Vector::new(TERRAIN_SIZE * TERRAIN_WIDTH as f32, 0.0, 0.0), 1);
+        vis_map.mark_terrain_cell(TERRAIN_WIDTH * TERRAIN_DEPTH);
+        assert_eq!(vis_map.terrain_cells.iter().filter(|c| **c).count(), 6);
+        assert!(!vis_map.is_terrain_cell_visited(TERRAIN_WIDTH * TERRAIN_DEPTH));
+
+        // The full map cheat leaves secret rooms hidden
+        let mut store = RoomStore::new();
+        let open = store.insert(test_room(RoomFlags::empty()));
+        let secret = store.insert(test_room(RoomFlags::SECRET));
+        let rooms = [store.get(open).unwrap().borrow(), store.get(secret).unwrap().borrow()];
+        vis_map.reveal_rooms(rooms.iter().map(|r| &**r));
+        assert!(vis_map.is_room_visited(open));
+        assert!(!vis_map.is_room_visited(secret));
+
+        vis_map.clear();
+        assert!(!vis_map.is_room_visited(3));
+        assert!(!vis_map.is_room_visited(open));
+        assert!(!vis_map.is_terrain_cell_visited(cell(0, 4)));
+    }
+
+    #[test]
+    fn automap_rebuild_test() {
+        crate::test_common::setup();
+
+        let mut store = RoomStore::new();
+        let visited = store.insert(test_room(RoomFlags::FUELCEN));
+        let unvisited = store.insert(test_room(RoomFlags::empty()));
+        let rooms = [store.get(visited).unwrap().borrow(), store.get(unvisited).unwrap().borrow()];
+
+        let mut automap = Automap::default();
+        automap.vis_map.mark_room(visited);
+        automap.rebuild(rooms.iter().map(|r| &**r), None);
+
+        // The shared edge is only added once, the portal face not at all
+        assert_eq!(automap.mesh.edges.len(), 5);
+        assert_eq!(automap.mesh.polygons.len(), 2);
+        assert!(automap.mesh.edges.iter().all(|(_, _, color)| *color == AUTOMAP_FUELCEN_COLOR));
+
+        // Visited terrain adds an outline for its cell, rebuilding starts over
+        let terrain = Terrain::default();
+        automap.vis_map.mark_terrain_cell(0);
+        automap.rebuild(rooms.iter().map(|r| &**r), Some(&terrain));
+        assert_eq!(automap.mesh.edges.len(), 7);
+        assert_eq!(automap.mesh.polygons.len(), 2);
+        assert!(automap.mesh.edges[5..].iter().all(|(_, _, color)| *color == AUTOMAP_TERRAIN_COLOR));
+    }
+
+    #[test]
+    fn automap_camera_test() {
+        crate::test_common::setup();
+
+        let mut camera = AutomapCamera {
+            target: Vector::new(0.0, 0.0, 100.0),
+            ..Default::default()
+        };
+        assert!(camera.position().approx_eq(&Vector::new(0.0, 0.0, 100.0 - AUTOMAP_DEFAULT_DISTANCE), 0.01));
+
+        camera.zoom(0.5);
+        assert_eq!(camera.distance, AUTOMAP_DEFAULT_DISTANCE * 0.5);
+        camera.zoom(0.0);
+        assert_eq!(camera.distance, AUTOMAP_MIN_DISTANCE);
+        camera.zoom(1000.0);
+        assert_eq!(camera.distance, AUTOMAP_MAX_DISTANCE);
+
+        camera.rotate(-1, 0x4000);
+        assert_eq!(camera.heading.0, 0xFFFF);
+        assert_eq!(camera.pitch.0, 0x4000);
+
+        // The camera always sits back along its forward vector from the target
+        let forward = camera.orientation().forward;
+        assert!(camera.position().approx_eq(&(camera.target - forward * AUTOMAP_MAX_DISTANCE), 0.01));
+    }
+
+    #[test]
+    fn automap_render_test() {
+        crate::test_common::setup();
+
+        let mut automap = Automap::default();
+        automap.mesh.edges = vec![
+            (Vector::ZERO, Vector::new(100.0, 0.0, 0.0), AUTOMAP_WALL_COLOR),
+            // Crosses the near plane
+            (Vector::new(0.0, 100.0, 0.0), Vector::new(0.0, 100.0, -1600.0), AUTOMAP_WALL_COLOR),
+            // Entirely behind the camera
+            (Vector::new(0.0, 0.0, -900.0), Vector::new(10.0, 0.0, -900.0), AUTOMAP_WALL_COLOR),
+        ];
+        automap.mesh.polygons = vec![AutomapPolygon {
+            points: vec![Vector::ZERO, Vector::new(100.0, 0.0, 0.0), Vector::new(0.0, 100.0, 0.0)],
+            color: AUTOMAP_WALL_COLOR,
+        }];
+        automap.markers = vec![AutomapMarker {
+            position: Vector::new(0.0, 0.0, -900.0),
+            color: AUTOMAP_MARKER_COLOR,
+            label: None,
+        }];
+
+        let list = automap.render(&viewport());
+        assert_eq!(list.lines.len(), 2);
+        assert!(list.polygons.is_empty());
+
+        // The camera looks down z at the target, which lands in the middle of the screen
+        let line = list.lines[0];
+        assert!((line.x0 - 320.0).abs() < 0.01 && (line.y0 - 240.0).abs() < 0.01);
+        assert!((line.x1 - 360.0).abs() < 0.01 && (line.y1 - 240.0).abs() < 0.01);
+
+        // The clipped end is pushed up to the near plane, far above the screen
+        let clipped = list.lines[1];
+        assert!((clipped.y0 - 200.0).abs() < 0.01);
+        assert!(clipped.y1 < -1000.0);
+
+        // Only the player marker is in front of the camera
+        assert_eq!(list.markers.len(), 1);
+        assert_eq!(list.markers[0].color, AUTOMAP_PLAYER_COLOR);
+
+        automap.draw_solid = true;
+        let list = automap.render(&viewport());
+        assert_eq!(list.polygons.len(), 1);
+        assert_eq!(list.polygons[0].points.len(), 3);
+    }
+}
@@ -0,0 +1,339 @@
+use super::{audio::SoundId, prelude::*};
+
+/// What moves a boss on to its next phase
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BossTrigger {
+    /// Health drops below this fraction of full
+    HealthBelow(f32),
+    /// Seconds spent in the previous phase
+    After(f32),
+    /// A level script says so, by id
+    Script(u32),
+}
+
+/// Jumping between path nodes on a timer
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeleportRule<E> {
+    /// Nodes it hops between, in order
+    pub nodes: Vec<usize>,
+    pub interval: f32,
+    /// Played where it leaves and where it arrives
+    pub effect: Option<E>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BossPhase<E> {
+    pub trigger: BossTrigger,
+    /// How long it shrugs off damage after the phase starts, `f32::INFINITY` for the whole phase
+    pub invulnerable_time: f32,
+    pub teleport: Option<TeleportRule<E>>,
+    /// Matcens switched on when the phase starts
+    pub summon: Vec<usize>,
+}
+
+impl<E> Default for BossPhase<E> {
+    fn default() -> Self {
+        Self {
+            trigger: BossTrigger::After(0.0),
+            invulnerable_time: 0.0,
+            teleport: None,
+            summon: Vec::new(),
+        }
+    }
+}
+
+/// One burst of the death sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeathStep<E> {
+    /// Seconds after the killing blow
+    pub delay: f32,
+    pub effect: E,
+    pub size: f32,
+    pub sound: Option<SoundId>,
+}
+
+/// Everything that sets a boss apart from a regular robot, the first phase is active from the start
+#[derive(Debug, Clone, PartialEq)]
+pub struct BossInfo<E> {
+    pub name: D3String,
+    pub phases: Vec<BossPhase<E>>,
+    pub death_sequence: Vec<DeathStep<E>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BossEvent<E> {
+    PhaseStarted { phase: usize },
+    Vulnerable(bool),
+    Teleport { node: usize, effect: Option<E> },
+    Summon { matcen: usize },
+    DeathEffect { effect: E, size: f32, sound: Option<SoundId> },
+    /// The sequence has played out and the object can go
+    DeathFinished,
+}
+
+/// A boss in play
+#[derive(Debug, Clone)]
+pub struct Boss<E> {
+    info: Rc<BossInfo<E>>,
+    phase: usize,
+    phase_time: f32,
+    teleport_time: f32,
+    next_node: usize,
+    vulnerable: bool,
+    /// Seconds since the killing blow
+    dying: Option<f32>,
+    death_step: usize,
+    pending: Vec<BossEvent<E>>,
+}
+
+impl<E: Copy> Boss<E> {
+    pub fn new(info: Rc<BossInfo<E>>) -> Self {
+        let mut boss = Self {
+            info,
+            phase: 0,
+            phase_time: 0.0,
+            teleport_time: 0.0,
+            next_node: 0,
+            vulnerable: true,
+            dying: None,
+            death_step: 0,
+            pending: Vec::new(),
+        };
+
+        if !boss.info.phases.is_empty() {
+            boss.enter_phase(0);
+        }
+
+        boss
+    }
+
+    pub fn phase(&self) -> usize {
+        self.phase
+    }
+
+    pub fn is_vulnerable(&self) -> bool {
+        self.vulnerable && self.dying.is_none()
+    }
+
+    pub fn is_dying(&self) -> bool {
+        self.dying.is_some()
+    }
+
+    /// How much of the damage gets through, nothing while it's shielded
+    pub fn filter_damage(&self, damage: f32) -> f32 {
+        if self.is_vulnerable() { damage } else { 0.0 }
+    }
+
+    /// Starts the death sequence, returns false if it was already going
+    pub fn kill(&mut self) -> bool {
+        if self.dying.is_some() {
+            return false;
+        }
+
+        // Whatever it was about to start doing won't happen now
+        self.pending.clear();
+        self.dying = Some(0.0);
+        self.death_step = 0;
+        true
+    }
+
+    /// Lets a level script move things along
+    pub fn script_trigger(&mut self, id: u32) {
+        if let Some(next) = self.info.phases.get(self.phase + 1) {
+            if next.trigger == BossTrigger::Script(id) {
+                self.enter_phase(self.phase + 1);
+            }
+        }
+    }
+
+    pub fn do_frame(&mut self, frametime: f32, health_fraction: f32) -> Vec<BossEvent<E>> {
+        if let Some(time) = &mut self.dying {
+            *time += frametime;
+            let time = *time;
+            self.play_death(time);
+            return std::mem::take(&mut self.pending);
+        }
+
+        self.phase_time += frametime;
+
+        // Big hits can skip straight past a phase, each one still gets started
+        while let Some(next) = self.info.phases.get(self.phase + 1) {
+            let ready = match next.trigger {
+                BossTrigger::HealthBelow(fraction) => health_fraction < fraction,
+                BossTrigger::After(seconds) => self.phase_time >= seconds,
+                BossTrigger::Script(_) => false,
+            };
+
+            if !ready {
+                break;
+            }
+
+            self.enter_phase(self.phase + 1);
+        }
+
+        if let Some(phase) = self.info.phases.get(self.phase) {
+            if !self.vulnerable && self.phase_time >= phase.invulnerable_time {
+                self.set_vulnerable(true);
+            }
+        }
+
+        self.update_teleport(frametime);
+
+        std::mem::take(&mut self.pending)
+    }
+
+    fn enter_phase(&mut self, index: usize) {
+        let info = self.info.clone();
+        let phase = &info.phases[index];
+
+        self.phase = index;
+        self.phase_time = 0.0;
+        self.teleport_time = 0.0;
+        self.next_node = 0;
+
+        self.pending.push(BossEvent::PhaseStarted { phase: index });
+        self.set_vulnerable(phase.invulnerable_time <= 0.0);
+
+        for &matcen in &phase.summon {
+            self.pending.push(BossEvent::Summon { matcen });
+        }
+    }
+
+    fn set_vulnerable(&mut self, vulnerable: bool) {
+        if self.vulnerable != vulnerable {
+            self.vulnerable = vulnerable;
+            self.pending.push(BossEvent::Vulnerable(vulnerable));
+        }
+    }
+
+    fn update_teleport(&mut self, frametime: f32) {
+        let info = self.info.clone();
+        let Some(rule) = info.phases.get(self.phase).and_then(|p| p.teleport.as_ref()) else {
+            return;
+        };
+
+        if rule.nodes.is_empty() || rule.interval <= 0.0 {
+            return;
+        }
+
+        self.teleport_time += frametime;
+
+        if self.teleport_time >= rule.interval {
+            self.teleport_time -= rule.interval;
+
+            let node = rule.nodes[self.next_node % rule.nodes.len()];
+            self.next_node = (self.next_node + 1) % rule.nodes.len();
+
+            self.pending.push(BossEvent::Teleport {
+                node,
+                effect: rule.effect,
+            });
+        }
+    }
+
+    fn play_death(&mut self, time: f32) {
+        let info = self.info.clone();
+
+        while let Some(step) = info.death_sequence.get(self.death_step) {
+            if step.delay > time {
+                return;
+            }
+
+            self.pending.push(BossEvent::DeathEffect {
+                effect: step.effect,
+                size: step.size,
+                sound: step.sound,
+            });
+
+            self.death_step += 1;
+        }
+
+        // Only reported once, the step count runs one past the end after that
+        if self.death_step == info.death_sequence.len() {
+            self.pending.push(BossEvent::DeathFinished);
+            self.death_step += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn boss() -> Boss<u32> {
+        Boss::new(Rc::new(BossInfo {
+            name: D3String::from("Test Boss"),
+            phases: vec![
+                BossPhase {
+                    invulnerable_time: 2.0,
+                    ..Default::default()
+                },
+                BossPhase {
+                    trigger: BossTrigger::HealthBelow(0.5),
+                    teleport: Some(TeleportRule {
+                        nodes: vec![4, 9],
+                        interval: 1.0,
+                        effect: Some(7),
+                    }),
+                    summon: vec![2],
+                    ..Default::default()
+                },
+                BossPhase {
+                    trigger: BossTrigger::Script(42),
+                    invulnerable_time: f32::INFINITY,
+                    ..Default::default()
+                },
+            ],
+            death_sequence: vec![
+                DeathStep { delay: 0.0, effect: 1, size: 10.0, sound: None },
+                DeathStep { delay: 1.0, effect: 2, size: 40.0, sound: None },
+            ],
+        }))
+    }
+
+    #[test]
+    fn boss_phase_test() {
+        crate::test_common::setup();
+
+        let mut boss = boss();
+        assert!(!boss.is_vulnerable());
+        assert_eq!(boss.filter_damage(10.0), 0.0);
+
+        // The opening shield wears off
+        assert_eq!(boss.do_frame(2.5, 1.0).last(), Some(&BossEvent::Vulnerable(true)));
+        assert_eq!(boss.filter_damage(10.0), 10.0);
+
+        let events = boss.do_frame(0.1, 0.4);
+        assert!(events.contains(&BossEvent::PhaseStarted { phase: 1 }));
+        assert!(events.contains(&BossEvent::Summon { matcen: 2 }));
+
+        // Hops between its nodes in order
+        assert_eq!(boss.do_frame(1.0, 0.4), vec![BossEvent::Teleport { node: 4, effect: Some(7) }]);
+        assert_eq!(boss.do_frame(1.0, 0.4), vec![BossEvent::Teleport { node: 9, effect: Some(7) }]);
+
+        // Wrong script id does nothing
+        boss.script_trigger(1);
+        assert_eq!(boss.phase(), 1);
+        boss.script_trigger(42);
+        assert_eq!(boss.phase(), 2);
+        assert!(!boss.is_vulnerable());
+        boss.do_frame(100.0, 0.4);
+        assert!(!boss.is_vulnerable());
+    }
+
+    #[test]
+    fn boss_death_test() {
+        crate::test_common::setup();
+
+        let mut boss = boss();
+        assert!(boss.kill());
+        assert!(!boss.kill());
+
+        assert_eq!(boss.do_frame(0.1, 0.0), vec![BossEvent::DeathEffect { effect: 1, size: 10.0, sound: None }]);
+        assert_eq!(
+            boss.do_frame(1.0, 0.0),
+            vec![BossEvent::DeathEffect { effect: 2, size: 40.0, sound: None }, BossEvent::DeathFinished]
+        );
+        assert!(boss.do_frame(1.0, 0.0).is_empty());
+    }
+}
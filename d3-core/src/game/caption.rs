@@ -0,0 +1,237 @@
+/*
+
+Captions
+
+A voice sound can name a line of the string table to show while it plays,
+so it can be localized along with the rest of the text. Captions queue up
+one after another rather than piling on top of each other, each staying up
+for its duration or until its voice is stopped, and fade in and out on the HUD.
+
+*/
+
+use std::collections::VecDeque;
+
+use super::{sound_table::SoundInfo, statistics::StringTable};
+
+/// Captions waiting behind the one on screen, more than this are dropped
+pub const MAX_QUEUED_CAPTIONS: usize = 4;
+
+/// Seconds a caption takes to fade in and out
+pub const CAPTION_FADE_TIME: f32 = 0.25;
+
+/// The least time a caption stays up, enough to read a short line
+pub const CAPTION_MIN_TIME: f32 = 1.5;
+
+/// Longest line before a caption wraps
+pub const CAPTION_LINE_CHARS: usize = 48;
+
+/// The caption of a sound table entry
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct SoundCaption {
+    /// Index of the text in the string table
+    pub text: usize,
+    /// Seconds it stays up
+    pub duration: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Caption {
+    voice: u32,
+    text: String,
+    duration: f32,
+    /// Set once it makes it to the front of the queue
+    shown_at: Option<f32>,
+}
+
+impl Caption {
+    fn ends_at(&self) -> Option<f32> {
+        self.shown_at.map(|t| t + self.duration)
+    }
+}
+
+/// A line of caption text for the HUD
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptionLine {
+    pub text: String,
+    pub alpha: u8,
+}
+
+/// Breaks text into lines of at most `width` characters at spaces, longer words get a line to themselves
+pub fn wrap_caption(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+
+        line.push_str(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Captions for the voices playing, front of the queue on screen
+#[derive(Debug, Clone, Default)]
+pub struct CaptionQueue {
+    captions: VecDeque<Caption>,
+    enabled: bool,
+}
+
+impl CaptionQueue {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            captions: VecDeque::new(),
+            enabled,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Follows the audio captions setting, turning them off clears what's up
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.captions.clear();
+        }
+    }
+
+    /// Call when the mixer starts a voice, with the uid it was given. Returns whether a caption was queued
+    pub fn voice_started(&mut self, voice: u32, info: &SoundInfo, strings: &StringTable, gametime: f32) -> bool {
+        let Some(caption) = info.caption.filter(|_| self.enabled) else {
+            return false;
+        };
+
+        let text = strings.get(caption.text);
+
+        if text.is_empty() {
+            return false;
+        }
+
+        if self.captions.len() > MAX_QUEUED_CAPTIONS {
+            trace!(target: "audio", "dropping caption {}, too many queued", caption.text);
+            return false;
+        }
+
+        self.captions.push_back(Caption {
+            voice,
+            text: text.to_string(),
+            duration: caption.duration.max(CAPTION_MIN_TIME),
+            shown_at: None,
+        });
+
+        self.update(gametime);
+
+        true
+    }
+
+    /// A stopped voice takes its captions with it, the one on screen fades out from here
+    pub fn voice_stopped(&mut self, voice: u32, gametime: f32) {
+        self.captions.retain_mut(|c| {
+            if c.voice != voice {
+                return true;
+            }
+
+            match c.shown_at {
+                Some(shown_at) => {
+                    c.duration = c.duration.min(gametime - shown_at + CAPTION_FADE_TIME);
+                    true
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// Takes down the caption on screen once it's over and puts up the next
+    pub fn update(&mut self, gametime: f32) {
+        while let Some(front) = self.captions.front_mut() {
+            match front.ends_at() {
+                None => {
+                    front.shown_at = Some(gametime);
+                    break;
+                }
+                Some(ends_at) if ends_at <= gametime => {
+                    self.captions.pop_front();
+                }
+                Some(_) => break,
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.captions.clear();
+    }
+
+    /// What the HUD draws this frame, wrapped and faded
+    pub fn hud_lines(&self, gametime: f32) -> Vec<CaptionLine> {
+        let Some(caption) = self.captions.front().filter(|_| self.enabled) else {
+            return Vec::new();
+        };
+
+        let (Some(shown_at), Some(ends_at)) = (caption.shown_at, caption.ends_at()) else {
+            return Vec::new();
+        };
+
+        let fade = ((gametime - shown_at).min(ends_at - gametime) / CAPTION_FADE_TIME).clamp(0.0, 1.0);
+        let alpha = (fade * 255.0).round() as u8;
+
+        wrap_caption(&caption.text, CAPTION_LINE_CHARS)
+            .into_iter()
+            .map(|text| CaptionLine { text, alpha })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn voice_line(text: usize, duration: f32) -> SoundInfo {
+        SoundInfo {
+            caption: Some(SoundCaption { text, duration }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn caption_queue_test() {
+        crate::test_common::setup();
+
+        let strings = StringTable::parse("Get to the exit\nThe reactor is going critical, you have thirty seconds to get out of the mine\n");
+        let mut captions = CaptionQueue::new(true);
+
+        assert!(captions.voice_started(1, &voice_line(0, 2.0), &strings, 0.0));
+        assert!(captions.voice_started(2, &voice_line(1, 3.0), &strings, 0.5));
+        assert!(!captions.voice_started(3, &SoundInfo::default(), &strings, 0.5));
+
+        // The first fades in, then the second waits its turn
+        assert_eq!(captions.hud_lines(0.1)[0].alpha, 102);
+        assert_eq!(captions.hud_lines(1.0)[0], CaptionLine { text: "Get to the exit".to_string(), alpha: 255 });
+
+        captions.update(2.0);
+        let lines = captions.hud_lines(3.0);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.text.len() <= CAPTION_LINE_CHARS));
+
+        // Stopping the voice cuts it short
+        captions.voice_stopped(2, 3.0);
+        captions.update(3.3);
+        assert!(captions.hud_lines(3.3).is_empty());
+
+        // Turned off, nothing gets queued
+        captions.set_enabled(false);
+        assert!(!captions.voice_started(4, &voice_line(0, 2.0), &strings, 4.0));
+    }
+}
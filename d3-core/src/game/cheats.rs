@@ -0,0 +1,373 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use crate::diagnostics::logging::apply_log_spec;
+
+use super::{prelude::*, GameMode};
+
+/// How many typed keys are remembered when looking for a cheat sequence
+const CHEAT_BUFFER_LEN: usize = 15;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheatCode {
+    /// Gives the player every weapon, full ammo, energy and shields.
+    AllWeapons, // IVEGOTIT
+    /// Toggles cloaking on the player.
+    Cloak, // TESTICUS
+    /// Kills all the robots in the level.
+    KillRobots, // DEADOFNIGHT
+    /// Toggles invulnerability on the player.
+    Invulnerable, // BURGERGOD
+    /// Toggles the framerate display.
+    Framelength, // FRAMELENGTH
+    /// Toggles the 3rd person camera.
+    Camera, // BYEBYEMONKEY
+    /// Replaces all textures with the bad bitmap.
+    CoolTextures, // SHENANIGANS
+    /// Blows up the player.
+    Suicide, // TUBERACER
+    /// Brings up the level warp dialog.
+    LevelWarp, // MORECLANG
+    /// Swaps the sun texture.
+    Teletubbies, // TELETUBBIES
+    /// Marks every non secret room as visited on the automap.
+    FullMap, // TREESQUID
+    /// Toggles the renderer stats display.
+    RenderStats, // RENDERSTAT
+    /// Cycles through the outline modes.
+    OutlineMode, // OUTLINEM
+    /// Moves the player to their newest marker, or anyone's in cooperative games.
+    MarkerWarp, // BEAMMEUP
+}
+
+impl CheatCode {
+    pub const ALL: [CheatCode; 14] = [
+        CheatCode::AllWeapons,
+        CheatCode::Cloak,
+        CheatCode::KillRobots,
+        CheatCode::Invulnerable,
+        CheatCode::Framelength,
+        CheatCode::Camera,
+        CheatCode::CoolTextures,
+        CheatCode::Suicide,
+        CheatCode::LevelWarp,
+        CheatCode::Teletubbies,
+        CheatCode::FullMap,
+        CheatCode::RenderStats,
+        CheatCode::OutlineMode,
+        CheatCode::MarkerWarp,
+    ];
+
+    /// The key sequence typed during gameplay
+    pub fn key_sequence(&self) -> &'static str {
+        match self {
+            CheatCode::AllWeapons => "ivegotit",
+            CheatCode::Cloak => "testicus",
+            CheatCode::KillRobots => "deadofnight",
+            CheatCode::Invulnerable => "burgergod",
+            CheatCode::Framelength => "framelength",
+            CheatCode::Camera => "byebyemonkey",
+            CheatCode::CoolTextures => "shenanigans",
+            CheatCode::Suicide => "tuberacer",
+            CheatCode::LevelWarp => "moreclang",
+            CheatCode::Teletubbies => "teletubbies",
+            CheatCode::FullMap => "treesquid",
+            CheatCode::RenderStats => "renderstat",
+            CheatCode::OutlineMode => "outlinem",
+            CheatCode::MarkerWarp => "beammeup",
+        }
+    }
+
+    /// The name used for the cheat on the developer console
+    pub fn console_name(&self) -> &'static str {
+        match self {
+            CheatCode::AllWeapons => "allweapons",
+            CheatCode::Cloak => "cloak",
+            CheatCode::KillRobots => "killrobots",
+            CheatCode::Invulnerable => "invuln",
+            CheatCode::Framelength => "framelength",
+            CheatCode::Camera => "camera",
+            CheatCode::CoolTextures => "badtextures",
+            CheatCode::Suicide => "suicide",
+            CheatCode::LevelWarp => "warp",
+            CheatCode::Teletubbies => "teletubbies",
+            CheatCode::FullMap => "fullmap",
+            CheatCode::RenderStats => "renderstats",
+            CheatCode::OutlineMode => "outline",
+            CheatCode::MarkerWarp => "gotomarker",
+        }
+    }
+
+    pub fn from_console_name(name: &str) -> Option<CheatCode> {
+        let name = name.trim().to_ascii_lowercase();
+        Self::ALL.iter().copied().find(|c| c.console_name() == name)
+    }
+
+    /// Display only cheats that don't affect gameplay, these work in multiplayer too
+    pub fn is_cosmetic(&self) -> bool {
+        matches!(
+            self,
+            CheatCode::Framelength
+                | CheatCode::Teletubbies
+                | CheatCode::RenderStats
+                | CheatCode::OutlineMode
+        )
+    }
+
+    /// The toggle that this cheat flips, if it is a toggle at all
+    pub fn toggle_flag(&self) -> Option<CheatFlags> {
+        match self {
+            CheatCode::Cloak => Some(CheatFlags::CLOAKED),
+            CheatCode::Invulnerable => Some(CheatFlags::INVULNERABLE),
+            CheatCode::Framelength => Some(CheatFlags::SHOW_FRAMELENGTH),
+            CheatCode::Camera => Some(CheatFlags::THIRD_PERSON),
+            CheatCode::RenderStats => Some(CheatFlags::RENDER_STATS),
+            _ => None,
+        }
+    }
+}
+
+bitflags! {
+    /// Cheat toggles that are currently on, visible to scripts.
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub struct CheatFlags: u32 {
+        /// The player can't be damaged.
+        const INVULNERABLE = 0b0000_0001;
+
+        /// The player is cloaked.
+        const CLOAKED = 0b0000_0010;
+
+        /// The framerate is being shown.
+        const SHOW_FRAMELENGTH = 0b0000_0100; // Display_framerate
+
+        /// The 3rd person camera is on.
+        const THIRD_PERSON = 0b0000_1000;
+
+        /// The renderer stats are being shown.
+        const RENDER_STATS = 0b0001_0000; // Display_renderer_stats
+    }
+}
+
+bitflags! {
+    /// What the server lets players do in a multiplayer game.
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
+    pub struct CheatPermissions: u32 {
+        /// Gameplay cheats are allowed in multiplayer.
+        const MULTIPLAYER_CHEATS = 0b0000_0001;
+
+        /// Developer console commands are allowed.
+        const DEV_COMMANDS = 0b0000_0010;
+    }
+}
+
+/// Outline modes cycled through by the outline cheat.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum OutlineMode {
+    #[default]
+    Disabled,
+    Mine,
+    Object,
+    MineAndObject,
+}
+
+impl OutlineMode {
+    pub fn next(&self) -> OutlineMode {
+        match self {
+            OutlineMode::Disabled => OutlineMode::Mine,
+            OutlineMode::Mine => OutlineMode::Object,
+            OutlineMode::Object => OutlineMode::MineAndObject,
+            OutlineMode::MineAndObject => OutlineMode::Disabled,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheatOutcome {
+    /// The cheat went through, the game should apply its effect.
+    Activated(CheatCode),
+
+    /// The cheat isn't allowed right now, in multiplayer everyone should be told about the attempt.
+    Denied(CheatCode),
+
+    /// A console command that isn't a cheat ran, there's nothing left for the game to do.
+    Handled,
+}
+
+#[derive(Debug, Default)]
+pub struct CheatSystem {
+    buffer: VecDeque<char>,
+    flags: CheatFlags,
+    pub permissions: CheatPermissions,
+    pub outline_mode: OutlineMode,
+
+    /// Once a gameplay cheat is used the player's score is zeroed and stays that way
+    is_cheater: bool, // IsCheater
+
+    activated: VecDeque<CheatCode>,
+}
+
+impl CheatSystem {
+    pub fn flags(&self) -> CheatFlags {
+        self.flags
+    }
+
+    pub fn is_active(&self, flag: CheatFlags) -> bool {
+        self.flags.contains(flag)
+    }
+
+    pub fn is_cheater(&self) -> bool {
+        self.is_cheater
+    }
+
+    /// Checks if the cheat can be used in the current game mode
+    pub fn is_allowed(&self, cheat: CheatCode, mode: GameMode) -> bool {
+        if cheat.is_cosmetic() || !mode.intersects(GameMode::MULTI) {
+            return true;
+        }
+
+        self.permissions.contains(CheatPermissions::MULTIPLAYER_CHEATS)
+    }
+
+    /// Feeds a typed key into the cheat buffer, returns the outcome if it completed a sequence
+    pub fn key_pressed(&mut self, key: char, mode: GameMode) -> Option<CheatOutcome> {
+        if self.buffer.len() == CHEAT_BUFFER_LEN {
+            self.buffer.pop_front();
+        }
+
+        self.buffer.push_back(key.to_ascii_lowercase());
+
+        let typed: String = self.buffer.iter().collect();
+        let cheat = CheatCode::ALL
+            .iter()
+            .copied()
+            .find(|c| typed.ends_with(c.key_sequence()))?;
+
+        self.buffer.clear();
+        Some(self.activate(cheat, mode))
+    }
+
+    /// Runs a cheat from the developer console, or `log <spec>` to change log verbosity
+    pub fn console_command(&mut self, command: &str, mode: GameMode) -> Result<CheatOutcome> {
+        if let Some(("log", spec)) = command.trim().split_once(char::is_whitespace) {
+            apply_log_spec(spec)?;
+            return Ok(CheatOutcome::Handled);
+        }
+
+        let cheat = CheatCode::from_console_name(command)
+            .ok_or_else(|| anyhow!("unknown cheat command: {}", command))?;
+
+        if mode.intersects(GameMode::MULTI) && !self.permissions.contains(CheatPermissions::DEV_COMMANDS) {
+            return Ok(CheatOutcome::Denied(cheat));
+        }
+
+        Ok(self.activate(cheat, mode))
+    }
+
+    fn activate(&mut self, cheat: CheatCode, mode: GameMode) -> CheatOutcome {
+        if !self.is_allowed(cheat, mode) {
+            debug!(target: "game", "cheat {:?} denied", cheat);
+            return CheatOutcome::Denied(cheat);
+        }
+
+        if let Some(flag) = cheat.toggle_flag() {
+            self.flags.toggle(flag);
+        }
+
+        if cheat == CheatCode::OutlineMode {
+            self.outline_mode = self.outline_mode.next();
+        }
+
+        if !cheat.is_cosmetic() {
+            self.is_cheater = true;
+        }
+
+        debug!(target: "game", "cheat {:?} activated", cheat);
+        self.activated.push_back(cheat);
+        CheatOutcome::Activated(cheat)
+    }
+
+    /// Cheats that went through and still need their effect applied by the game
+    pub fn drain_activated(&mut self) -> impl Iterator<Item = CheatCode> + '_ {
+        self.activated.drain(..)
+    }
+
+    /// Clears all toggles, used when starting a new game
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.flags = CheatFlags::empty();
+        self.outline_mode = OutlineMode::Disabled;
+        self.is_cheater = false;
+        self.activated.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use log::LevelFilter;
+
+    use crate::diagnostics::logging::LogCategory;
+
+    use super::*;
+
+    fn type_keys(cheats: &mut CheatSystem, keys: &str, mode: GameMode) -> Option<CheatOutcome> {
+        let mut outcome = None;
+
+        for key in keys.chars() {
+            if let Some(o) = cheats.key_pressed(key, mode) {
+                outcome = Some(o);
+            }
+        }
+
+        outcome
+    }
+
+    #[test]
+    fn key_sequence_toggles_invulnerability() {
+        crate::test_common::setup();
+
+        let mut cheats = CheatSystem::default();
+
+        let outcome = type_keys(&mut cheats, "xyzBURGERGOD", GameMode::SINGLE);
+        assert_eq!(outcome, Some(CheatOutcome::Activated(CheatCode::Invulnerable)));
+        assert!(cheats.is_active(CheatFlags::INVULNERABLE));
+        assert!(cheats.is_cheater());
+
+        type_keys(&mut cheats, "burgergod", GameMode::SINGLE);
+        assert!(!cheats.is_active(CheatFlags::INVULNERABLE));
+    }
+
+    #[test]
+    fn multiplayer_gating() {
+        crate::test_common::setup();
+
+        let mut cheats = CheatSystem::default();
+
+        let outcome = type_keys(&mut cheats, "ivegotit", GameMode::NETWORK);
+        assert_eq!(outcome, Some(CheatOutcome::Denied(CheatCode::AllWeapons)));
+
+        // Cosmetic cheats still work
+        let outcome = type_keys(&mut cheats, "framelength", GameMode::NETWORK);
+        assert_eq!(outcome, Some(CheatOutcome::Activated(CheatCode::Framelength)));
+        assert!(!cheats.is_cheater());
+
+        cheats.permissions |= CheatPermissions::MULTIPLAYER_CHEATS | CheatPermissions::DEV_COMMANDS;
+        let outcome = cheats.console_command("allweapons", GameMode::NETWORK).unwrap();
+        assert_eq!(outcome, CheatOutcome::Activated(CheatCode::AllWeapons));
+    }
+
+    #[test]
+    fn console_log_command() {
+        crate::test_common::setup();
+
+        let mut cheats = CheatSystem::default();
+
+        let outcome = cheats.console_command("log input=debug", GameMode::NETWORK).unwrap();
+        assert_eq!(outcome, CheatOutcome::Handled);
+        assert_eq!(LogCategory::Input.level(), LevelFilter::Debug);
+
+        assert!(cheats.console_command("log input=loud", GameMode::SINGLE).is_err());
+        assert_eq!(LogCategory::Input.level(), LevelFilter::Debug);
+        assert!(!cheats.is_cheater());
+    }
+}
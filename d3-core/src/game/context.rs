@@ -11,8 +11,10 @@ pub struct GameContext {
     debug_mode: bool,
     min_allowed_framecap: i32,
     min_allowed_frametime: i32,
-    gametime: f32,
-    frametime: f32,
+    /// Game and real clocks, with pause and time scaling
+    pub time: super::game_time::GameTime,
+    /// Splits the game frametime into fixed physics ticks, see `core::do_game_frame`
+    pub timestep: super::timestep::FixedTimestep,
     pub mode: GameMode,
 
     pub player_object_ref: SharedMutRef<Object>,
@@ -24,14 +26,25 @@ pub struct GameContext {
     pub doorways: BindingStore<super::door::Doorway>,
 
 
-    pub rooms: BindingStore<super::room::Room>,
-    
-    // Only putting this here for a debug condition
-    pub room_highest_index: usize,
+    pub rooms: super::room::RoomStore,
 
     /// Global mask for all keys held by all players
     pub world_keys: super::door::KeyFlags,
 
+    /// Cheat toggles and permissions, scripts can query these
+    pub cheats: super::cheats::CheatSystem,
+
+    /// What the player has seen of the level
+    pub automap: super::automap::Automap,
+
+    /// Markers dropped by the players, drawn on the automap
+    pub markers: super::marker::MarkerList,
+
+    pub level_goals: super::level_goals::LevelGoals,
+
+    /// Robot groups, scripts assign robots to these by name
+    pub squads: super::squad::SquadManager,
+
     pub terrain: BindingStore<super::terrain::Terrain>,
     pub terrain_nodes: Vec<Vec<Node>>,
     pub weather: BindingStore<super::weather::Weather>,
@@ -53,7 +66,7 @@ impl Default for GameContext {
             debug_mode: false,
             min_allowed_framecap: ((1.0f32 / 60.0f32) as i32) * 1000,
             min_allowed_frametime: 0,
-            gametime: 0.0,
+            time: Default::default(),
             terrain_nodes: vec![Vec::default(); 8],
             ..Default::default()
         }
@@ -71,11 +84,11 @@ impl GameContext {
 // For the setters and getters
 impl GameContext {
     pub fn gametime(&self) -> f32 {
-        self.gametime
+        self.time.gametime()
     }
 
     pub fn frametime(&self) -> f32 {
-        self.frametime
+        self.time.frametime()
     }
 }
 
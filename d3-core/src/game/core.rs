@@ -8,8 +8,8 @@ pub fn remove_active_doorway(context: &mut GameContext, doorway: &SharedMutRef<D
     context.doorways.remove_by_ref(doorway);
 
     /* We need to remove the door info from the room that had its door destroyed */
-    for bounded_room in context.rooms.bindings() {
-        let mut room = bounded_room.inner().borrow_mut();
+    for (_, room) in context.rooms.iter() {
+        let mut room = room.borrow_mut();
         
         if room.assigned_door_data.is_some() {
             let door_data = room.assigned_door_data.as_ref().unwrap();
@@ -21,6 +21,26 @@ pub fn remove_active_doorway(context: &mut GameContext, doorway: &SharedMutRef<D
     }
 }
 
+/// Moves the game on by a frame that really took `real_frametime` seconds.  The simulation runs
+/// in as many fixed ticks as the frame covers, `tick` running one of them, and each object's
+/// transform is kept before every tick so the renderer can blend between the last two with
+/// `context.timestep.alpha()`.  Returns how many ticks ran
+pub fn do_game_frame(context: &mut GameContext, real_frametime: f32, mut tick: impl FnMut(&mut GameContext, f32)) -> usize {
+    let frametime = context.time.advance(real_frametime);
+    let ticks = context.timestep.advance(frametime);
+    let step = context.timestep.step();
+
+    for _ in 0..ticks {
+        for object in context.objects.bindings() {
+            object.inner().borrow_mut().store_previous_transform();
+        }
+
+        tick(context, step);
+    }
+
+    ticks
+}
+
 pub fn update_doorway_animation(room: SharedMutRef<Room>) {
     todo!()
     // DoorwayUpdateAnimation
@@ -95,7 +115,7 @@ pub fn do_frame_doorways(context: &mut GameContext) {
                     let door_obj = door_obj_ref.borrow();
 
                     /* Start closing the door, check if anything is in the way */
-                    if door_obj.link_prev_obj.is_none() && door_obj.link_next_obj.is_none() {
+                    if !door_obj.link_prev_obj.is_valid() && !door_obj.link_next_obj.is_valid() {
                         doorway.dest_pos = 0.0;
                         doorway.state = DoorwayState::Closing;
 
@@ -208,10 +228,10 @@ pub fn get_node_list(context: &mut GameContext, region: Option<RegionRef>) -> Op
                 return Some(room.nodes.clone());
             },
             RegionRef::Terrain((t,i)) => {
-                debug!("terrain node list requested");
+                debug!(target: "game", "terrain node list requested");
 
                 if !super::room::is_room_outside(i) {
-                    if i <= (context.room_highest_index + 8) {
+                    if i < (context.rooms.id_bound() + 8) {
                         panic!("We hit this spot...");
                     }
                 }
@@ -0,0 +1,361 @@
+use std::collections::BTreeMap;
+
+use super::prelude::*;
+use vector::Vector;
+use matrix::Matrix;
+
+pub type CountermeasureHandle = u32;
+
+/// Countermeasures can't go off until they've been out this long, so they don't blow up the player dropping them
+pub const COUNTERMEASURE_ARM_TIME: f32 = 1.0;
+
+/// How far behind the ship a countermeasure is dropped, on top of the ship's size
+const DROP_DISTANCE: f32 = 2.0;
+
+/// Homing weapons only consider decoys within this cone (cosine of the half angle)
+pub const DECOY_HOMING_FOV: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CountermeasureKind {
+    /// Decoy that pulls homing weapons away from the ship that dropped it.
+    Chaff,
+    /// Sits still and blows apart into several bomblets when something gets close.
+    BettyBomb,
+    /// Sits still and explodes when something gets close.
+    ProximityMine,
+    /// Chases down anything that gets close, then explodes.
+    SeekerMine,
+}
+
+/// Tuning values for a kind of countermeasure.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CountermeasureInfo {
+    pub lifetime: f32,
+    /// Distance at which a target sets it off
+    pub trigger_radius: f32,
+    /// Distance at which a seeker starts chasing
+    pub seek_radius: f32,
+    pub seek_speed: f32,
+    pub drop_speed: f32,
+    pub damage: f32,
+    pub blast_radius: f32,
+    /// How strongly this pulls homing weapons, 0 means not a decoy
+    pub decoy_strength: f32,
+    /// Bomblets spawned on detonation
+    pub spawn_count: usize,
+}
+
+impl CountermeasureKind {
+    pub fn info(&self) -> CountermeasureInfo {
+        match self {
+            CountermeasureKind::Chaff => CountermeasureInfo {
+                lifetime: 8.0,
+                trigger_radius: 0.0,
+                seek_radius: 0.0,
+                seek_speed: 0.0,
+                drop_speed: 10.0,
+                damage: 0.0,
+                blast_radius: 0.0,
+                decoy_strength: 2.0,
+                spawn_count: 0,
+            },
+            CountermeasureKind::BettyBomb => CountermeasureInfo {
+                lifetime: 60.0,
+                trigger_radius: 30.0,
+                seek_radius: 0.0,
+                seek_speed: 0.0,
+                drop_speed: 5.0,
+                damage: 10.0,
+                blast_radius: 20.0,
+                decoy_strength: 0.0,
+                spawn_count: 4,
+            },
+            CountermeasureKind::ProximityMine => CountermeasureInfo {
+                lifetime: 120.0,
+                trigger_radius: 20.0,
+                seek_radius: 0.0,
+                seek_speed: 0.0,
+                drop_speed: 5.0,
+                damage: 40.0,
+                blast_radius: 30.0,
+                decoy_strength: 0.0,
+                spawn_count: 0,
+            },
+            CountermeasureKind::SeekerMine => CountermeasureInfo {
+                lifetime: 60.0,
+                trigger_radius: 8.0,
+                seek_radius: 100.0,
+                seek_speed: 40.0,
+                drop_speed: 5.0,
+                damage: 30.0,
+                blast_radius: 20.0,
+                decoy_strength: 0.0,
+                spawn_count: 0,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Countermeasure {
+    pub kind: CountermeasureKind,
+    pub info: CountermeasureInfo,
+    /// Handle of the object that dropped it, it ignores its owner
+    pub owner: usize,
+    pub position: Vector,
+    pub velocity: Vector,
+    pub age: f32,
+}
+
+impl Countermeasure {
+    pub fn is_armed(&self) -> bool {
+        self.age >= COUNTERMEASURE_ARM_TIME
+    }
+}
+
+/// Something a countermeasure can go after.
+#[derive(Debug, Copy, Clone)]
+pub struct CountermeasureTarget {
+    pub handle: usize,
+    pub position: Vector,
+    pub size: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CountermeasureEvent {
+    /// The countermeasure blew up, everything within `radius` takes damage.
+    Detonated {
+        kind: CountermeasureKind,
+        owner: usize,
+        position: Vector,
+        damage: f32,
+        radius: f32,
+        spawn_count: usize,
+    },
+    /// The countermeasure ran out of time.
+    Expired { kind: CountermeasureKind, owner: usize },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CountermeasureManager {
+    active: BTreeMap<CountermeasureHandle, Countermeasure>,
+    next_handle: CountermeasureHandle,
+}
+
+impl CountermeasureManager {
+    pub fn active(&self) -> impl Iterator<Item = &Countermeasure> {
+        self.active.values()
+    }
+
+    pub fn get(&self, handle: CountermeasureHandle) -> Option<&Countermeasure> {
+        self.active.get(&handle)
+    }
+
+    /// Drops a countermeasure behind a ship
+    pub fn drop_countermeasure(
+        &mut self,
+        kind: CountermeasureKind,
+        owner: usize,
+        position: &Vector,
+        orientation: &Matrix,
+        size: f32,
+    ) -> CountermeasureHandle {
+        let info = kind.info();
+        let behind = -orientation.forward;
+
+        // Handles are never reused, so one stays good while the others come and go
+        self.next_handle = self.next_handle.wrapping_add(1);
+
+        self.active.insert(
+            self.next_handle,
+            Countermeasure {
+                kind,
+                info,
+                owner,
+                position: *position + behind * (size + DROP_DISTANCE),
+                velocity: behind * info.drop_speed,
+                age: 0.0,
+            },
+        );
+
+        self.next_handle
+    }
+
+    /// Moves, arms and detonates the countermeasures
+    pub fn do_frame(&mut self, frametime: f32, targets: &[CountermeasureTarget]) -> Vec<CountermeasureEvent> {
+        let mut events = Vec::new();
+
+        self.active.retain(|_, cm| {
+            cm.age += frametime;
+
+            if cm.age >= cm.info.lifetime {
+                events.push(CountermeasureEvent::Expired {
+                    kind: cm.kind,
+                    owner: cm.owner,
+                });
+                return false;
+            }
+
+            // Dropped countermeasures drift to a stop
+            cm.position += cm.velocity * frametime;
+            cm.velocity *= (1.0 - frametime).max(0.0);
+
+            if !cm.is_armed() || cm.info.trigger_radius <= 0.0 {
+                return true;
+            }
+
+            let closest = targets
+                .iter()
+                .filter(|t| t.handle != cm.owner)
+                .map(|t| (t, Vector::distance(&t.position, &cm.position) - t.size))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            let Some((target, dist)) = closest else {
+                return true;
+            };
+
+            if dist <= cm.info.trigger_radius {
+                events.push(CountermeasureEvent::Detonated {
+                    kind: cm.kind,
+                    owner: cm.owner,
+                    position: cm.position,
+                    damage: cm.info.damage,
+                    radius: cm.info.blast_radius,
+                    spawn_count: cm.info.spawn_count,
+                });
+                return false;
+            }
+
+            if dist <= cm.info.seek_radius {
+                let mut dir = Vector::ZERO;
+                Vector::compute_normalized_direction(&mut dir, &target.position, &cm.position);
+                cm.velocity = dir * cm.info.seek_speed;
+            }
+
+            true
+        });
+
+        events
+    }
+
+    /// Picks the point a homing weapon should chase. A decoy wins over the real target when it
+    /// is inside the weapon's view cone and pulls harder, taking distance into account.
+    pub fn find_homing_target(
+        &self,
+        weapon_owner: usize,
+        weapon_position: &Vector,
+        weapon_forward: &Vector,
+        target_position: &Vector,
+    ) -> Vector {
+        let target_dist = Vector::distance(weapon_position, target_position).max(1.0);
+        let mut best = *target_position;
+        let mut best_pull = 1.0 / target_dist;
+
+        for cm in self.active.values() {
+            // Your own chaff doesn't fool your own missiles
+            if cm.info.decoy_strength <= 0.0 || cm.owner == weapon_owner {
+                continue;
+            }
+
+            let mut dir = Vector::ZERO;
+            let dist = Vector::compute_normalized_direction(&mut dir, &cm.position, weapon_position).max(1.0);
+
+            if dir.dot(*weapon_forward) < DECOY_HOMING_FOV {
+                continue;
+            }
+
+            let pull = cm.info.decoy_strength / dist;
+
+            if pull > best_pull {
+                best_pull = pull;
+                best = cm.position;
+            }
+        }
+
+        best
+    }
+
+    pub fn clear(&mut self) {
+        self.active.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn proximity_mine_waits_to_arm() {
+        crate::test_common::setup();
+
+        let mut manager = CountermeasureManager::default();
+        manager.drop_countermeasure(
+            CountermeasureKind::ProximityMine,
+            1,
+            &Vector { x: 0.0, y: 0.0, z: 0.0 },
+            &Matrix::IDENTITY,
+            1.0,
+        );
+
+        let target = CountermeasureTarget {
+            handle: 2,
+            position: Vector { x: 0.0, y: 0.0, z: -5.0 },
+            size: 1.0,
+        };
+
+        assert!(manager.do_frame(0.5, &[target]).is_empty());
+
+        let events = manager.do_frame(0.6, &[target]);
+        assert!(matches!(events[0], CountermeasureEvent::Detonated { owner: 1, .. }));
+        assert!(manager.active().next().is_none());
+    }
+
+    #[test]
+    fn countermeasure_handles_stay_valid() {
+        crate::test_common::setup();
+
+        let mut manager = CountermeasureManager::default();
+        let drop_at = |manager: &mut CountermeasureManager, kind, z| {
+            manager.drop_countermeasure(kind, 1, &Vector { x: 0.0, y: 0.0, z }, &Matrix::IDENTITY, 1.0)
+        };
+
+        let chaff = drop_at(&mut manager, CountermeasureKind::Chaff, 0.0);
+        let mine = drop_at(&mut manager, CountermeasureKind::ProximityMine, 100.0);
+        assert_ne!(chaff, mine);
+
+        // The chaff running out doesn't shift the mine's handle
+        let events = manager.do_frame(CountermeasureKind::Chaff.info().lifetime, &[]);
+        assert_eq!(events, vec![CountermeasureEvent::Expired { kind: CountermeasureKind::Chaff, owner: 1 }]);
+        assert!(manager.get(chaff).is_none());
+        assert_eq!(manager.get(mine).unwrap().kind, CountermeasureKind::ProximityMine);
+
+        // Nor does a new one take the old handle
+        let seeker = drop_at(&mut manager, CountermeasureKind::SeekerMine, 0.0);
+        assert!(seeker != chaff && seeker != mine);
+    }
+
+    #[test]
+    fn chaff_pulls_enemy_missiles() {
+        crate::test_common::setup();
+
+        let mut manager = CountermeasureManager::default();
+        manager.drop_countermeasure(
+            CountermeasureKind::Chaff,
+            1,
+            &Vector { x: 0.0, y: 0.0, z: 50.0 },
+            &Matrix::IDENTITY,
+            1.0,
+        );
+
+        let forward = Vector { x: 0.0, y: 0.0, z: 1.0 };
+        let missile = Vector { x: 0.0, y: 0.0, z: 0.0 };
+        let ship = Vector { x: 0.0, y: 0.0, z: 60.0 };
+
+        let target = manager.find_homing_target(2, &missile, &forward, &ship);
+        assert!(target.z < 50.0);
+
+        // The owner's missiles ignore it
+        let target = manager.find_homing_target(1, &missile, &forward, &ship);
+        assert_eq!(target, ship);
+    }
+}
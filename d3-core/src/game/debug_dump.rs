@@ -0,0 +1,335 @@
+/*
+
+Debug dumps of a loaded level
+
+Room geometry goes into a Wavefront OBJ that any model viewer can open,
+with a JSON sidecar for what the OBJ can't hold (flags, portals, objects).
+Meant for checking level conversion while the native renderer matures.
+
+D3 is left handed, the OBJ flips z and the face winding so it shows up
+the right way round in right handed tools. The JSON keeps game coordinates.
+
+*/
+
+use std::{cell::Ref, fs::File, io::{self, BufWriter, Write}, path::Path};
+
+use anyhow::{Context, Result};
+
+use crate::math::vector::Vector;
+
+use super::{context::GameContext, prelude::*, room::{Face, Room}};
+
+/// Writes `<path>.obj` and `<path>.json` for every room and object in the level
+pub fn dump_level(context: &GameContext, path: &Path) -> Result<()> {
+    let rooms: Vec<Ref<Room>> = context.rooms.iter().map(|(_, room)| room.borrow()).collect();
+    let objects: Vec<Ref<Object>> = context.objects.bindings().iter().map(|b| b.inner().borrow()).collect();
+
+    let rooms: Vec<&Room> = rooms.iter().map(|r| &**r).collect();
+    let objects: Vec<&Object> = objects.iter().map(|o| &**o).collect();
+
+    let obj_path = path.with_extension("obj");
+    let mut out = BufWriter::new(File::create(&obj_path).with_context(|| format!("Failed to create {}", obj_path.display()))?);
+    write_obj(&rooms, &mut out).context("Failed to write the OBJ dump")?;
+    out.flush()?;
+
+    let json_path = path.with_extension("json");
+    let mut out = BufWriter::new(File::create(&json_path).with_context(|| format!("Failed to create {}", json_path.display()))?);
+    write_json(&rooms, &objects, &mut out).context("Failed to write the JSON dump")?;
+    out.flush()?;
+
+    info!(target: "game", "dumped {} rooms and {} objects to {}", rooms.len(), objects.len(), path.display());
+
+    Ok(())
+}
+
+/// One OBJ object per room, portal faces are kept in their own group
+pub fn write_obj<W: Write>(rooms: &[&Room], out: &mut W) -> io::Result<()> {
+    writeln!(out, "# flare.rs level dump, {} rooms", rooms.len())?;
+
+    let mut base = ObjIndices::default();
+
+    for (i, room) in rooms.iter().enumerate() {
+        let name = room.name.as_ref().map(|n| n.to_string().unwrap_or_default()).unwrap_or_default();
+        writeln!(out, "o room_{}{}{}", i, if name.is_empty() { "" } else { "_" }, name.replace(char::is_whitespace, "_"))?;
+
+        write_room_geometry(out, &room.vertices, &room.faces, &mut base)?;
+    }
+
+    Ok(())
+}
+
+/// Running 1-based index bases, OBJ indices are global to the file
+#[derive(Debug, Default, Clone, Copy)]
+struct ObjIndices {
+    vertices: usize,
+    uvs: usize,
+    normals: usize,
+}
+
+fn write_room_geometry<W: Write>(out: &mut W, vertices: &[Vector], faces: &[Face], base: &mut ObjIndices) -> io::Result<()> {
+    for v in vertices {
+        writeln!(out, "v {} {} {}", v.x, v.y, flip(v.z))?;
+    }
+
+    for face in faces {
+        for uv in &face.face_uvls {
+            // OBJ textures have v going up
+            writeln!(out, "vt {} {}", uv.u, 1.0 - uv.v)?;
+        }
+
+        writeln!(out, "vn {} {} {}", face.normal.x, face.normal.y, flip(face.normal.z))?;
+    }
+
+    for portals in [false, true] {
+        if portals {
+            if !faces.iter().any(|f| f.portal.is_some()) {
+                break;
+            }
+
+            writeln!(out, "g portals")?;
+        }
+        else {
+            writeln!(out, "g faces")?;
+        }
+
+        let mut uv_base = base.uvs;
+
+        for (n, face) in faces.iter().enumerate() {
+            let uv_count = face.face_uvls.len();
+
+            if face.portal.is_some() == portals {
+                write!(out, "f")?;
+
+                for (k, &vert) in face.face_verts.iter().enumerate().rev() {
+                    if k < uv_count {
+                        write!(out, " {}/{}/{}", base.vertices + vert + 1, uv_base + k + 1, base.normals + n + 1)?;
+                    }
+                    else {
+                        write!(out, " {}//{}", base.vertices + vert + 1, base.normals + n + 1)?;
+                    }
+                }
+
+                writeln!(out)?;
+            }
+
+            uv_base += uv_count;
+        }
+    }
+
+    base.vertices += vertices.len();
+    base.uvs += faces.iter().map(|f| f.face_uvls.len()).sum::<usize>();
+    base.normals += faces.len();
+
+    Ok(())
+}
+
+/// Mirrors z, without printing -0
+fn flip(z: f32) -> f32 {
+    0.0 - z
+}
+
+/// Rooms with their faces and portals plus every object, in game coordinates
+pub fn write_json<W: Write>(rooms: &[&Room], objects: &[&Object], out: &mut W) -> io::Result<()> {
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"rooms\": [")?;
+
+    for (i, room) in rooms.iter().enumerate() {
+        let name = room.name.as_ref().map(|n| n.to_string().unwrap_or_default());
+
+        writeln!(out, "    {{")?;
+        writeln!(out, "      \"index\": {},", i)?;
+        writeln!(out, "      \"id\": {},", room.id())?;
+        writeln!(out, "      \"name\": {},", name.map_or("null".to_string(), |n| json_string(&n)))?;
+        writeln!(out, "      \"flags\": {},", room.flags.bits())?;
+        writeln!(out, "      \"outside\": {},", room.is_outside)?;
+        writeln!(out, "      \"min\": {},", json_vector(&room.min_xyz))?;
+        writeln!(out, "      \"max\": {},", json_vector(&room.max_xyz))?;
+        writeln!(out, "      \"vertex_count\": {},", room.vertices.len())?;
+
+        writeln!(out, "      \"faces\": [")?;
+        for (n, face) in room.faces.iter().enumerate() {
+            let verts: Vec<String> = face.face_verts.iter().map(|v| v.to_string()).collect();
+
+            writeln!(
+                out,
+                "        {{ \"flags\": {}, \"verts\": [{}], \"normal\": {}, \"portal\": {} }}{}",
+                face.flags.bits(),
+                verts.join(", "),
+                json_vector(&face.normal),
+                face.portal.is_some(),
+                comma(n, room.faces.len())
+            )?;
+        }
+        writeln!(out, "      ],")?;
+
+        writeln!(out, "      \"portals\": [")?;
+        for (n, portal) in room.portals.iter().enumerate() {
+            // The room being dumped can be connected to itself, it's already borrowed
+            let connected = portal
+                .connected_room
+                .as_ref()
+                .and_then(|r| r.try_borrow().ok().map(|r| r.id().to_string()))
+                .unwrap_or("null".to_string());
+
+            writeln!(
+                out,
+                "        {{ \"flags\": {}, \"connected_room\": {}, \"path_point\": {} }}{}",
+                portal.flags.bits(),
+                connected,
+                json_vector(&portal.path_point),
+                comma(n, room.portals.len())
+            )?;
+        }
+        writeln!(out, "      ],")?;
+
+        let object_names: Vec<String> = room
+            .objects
+            .iter()
+            .filter_map(|o| o.try_borrow().ok().map(|o| json_string(&format!("{}", o.name))))
+            .collect();
+        writeln!(out, "      \"objects\": [{}]", object_names.join(", "))?;
+
+        writeln!(out, "    }}{}", comma(i, rooms.len()))?;
+    }
+
+    writeln!(out, "  ],")?;
+    writeln!(out, "  \"objects\": [")?;
+
+    for (i, object) in objects.iter().enumerate() {
+        let room = object.parent_room.upgrade().and_then(|r| r.try_borrow().ok().map(|r| r.id().to_string())).unwrap_or("null".to_string());
+
+        writeln!(
+            out,
+            "    {{ \"name\": {}, \"class\": \"{:?}\", \"room\": {}, \"position\": {}, \"forward\": {}, \"up\": {}, \"size\": {}, \"shields\": {} }}{}",
+            json_string(&format!("{}", object.name)),
+            object.typedef().class,
+            room,
+            json_vector(&object.position),
+            json_vector(&object.orientation.forward),
+            json_vector(&object.orientation.up),
+            json_number(object.size),
+            json_number(object.shields),
+            comma(i, objects.len())
+        )?;
+    }
+
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+fn comma(i: usize, len: usize) -> &'static str {
+    if i + 1 < len { "," } else { "" }
+}
+
+/// JSON has no NaN or infinity
+fn json_number(value: f32) -> String {
+    if value.is_finite() { value.to_string() } else { "null".to_string() }
+}
+
+fn json_vector(v: &Vector) -> String {
+    format!("[{}, {}, {}]", json_number(v.x), json_number(v.y), json_number(v.z))
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{game::room::{FaceFlags, PortalFlags}, graphics::UVCoord};
+
+    use super::*;
+
+    fn quad(verts: [usize; 4], portal: bool) -> Face {
+        Face {
+            flags: FaceFlags::empty(),
+            num_verts: 4,
+            portal: portal.then(|| Rc::new(crate::game::room::Portal {
+                flags: PortalFlags::empty(),
+                portal_face: None,
+                connected_room: None,
+                connected_portal: None,
+                bnode_index: (),
+                combine_master: (),
+                path_point: Vector::ZERO,
+            })),
+            face_verts: verts.to_vec(),
+            face_uvls: vec![UVCoord { u: 0.0, v: 0.0 }, UVCoord { u: 1.0, v: 0.0 }, UVCoord { u: 1.0, v: 1.0 }],
+            normal: Vector::new(0.0, 0.0, 1.0),
+            lightmap: None,
+            special_faces: (),
+            render_frame: (),
+            tmap: None,
+            light_muliple: 0,
+            min_xyz: Vector::ZERO,
+            max_xyz: Vector::ZERO,
+        }
+    }
+
+    #[test]
+    fn dump_obj_geometry_test() {
+        crate::test_common::setup();
+
+        let vertices = vec![
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(1.0, 0.0, 0.0),
+            Vector::new(1.0, 1.0, 0.0),
+            Vector::new(0.0, 1.0, 2.0),
+        ];
+
+        let faces = vec![quad([0, 1, 2, 3], false), quad([3, 2, 1, 0], true)];
+
+        let mut out = Vec::new();
+        let mut base = ObjIndices::default();
+        write_room_geometry(&mut out, &vertices, &faces, &mut base).unwrap();
+
+        // A second room's indices carry on from the first
+        write_room_geometry(&mut out, &vertices, &faces[..1], &mut base).unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let faces: Vec<&str> = text.lines().filter(|l| l.starts_with("f ")).collect();
+
+        assert!(text.contains("v 0 1 -2"));
+        assert!(text.contains("vn 0 0 -1"));
+        assert!(text.contains("g portals"));
+
+        // Winding is reversed, the 4th vertex has no uv
+        assert_eq!(faces[0], "f 4//1 3/3/1 2/2/1 1/1/1");
+        assert_eq!(faces[1], "f 1//2 2/6/2 3/5/2 4/4/2");
+        assert_eq!(faces[2], "f 8//3 7/9/3 6/8/3 5/7/3");
+
+        assert_eq!(base.vertices, 8);
+        assert_eq!(base.uvs, 9);
+        assert_eq!(base.normals, 3);
+    }
+
+    #[test]
+    fn dump_json_helpers_test() {
+        assert_eq!(json_string("door \"A\"\\1\n"), "\"door \\\"A\\\"\\\\1\\n\"");
+        assert_eq!(json_string("\u{1}"), "\"\\u0001\"");
+        assert_eq!(json_number(f32::NAN), "null");
+        assert_eq!(json_vector(&Vector::new(1.5, -2.0, 0.0)), "[1.5, -2, 0]");
+
+        let mut out = Vec::new();
+        write_json(&[], &[], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\n  \"rooms\": [\n  ],\n  \"objects\": [\n  ]\n}\n");
+    }
+}
@@ -0,0 +1,496 @@
+/*
+
+Decals
+
+Bullet holes, blood splats and the like. A decal is a textured quad laid
+flat on whatever it hit and clipped to the edges of that surface, a room
+face or one triangle of a terrain cell, so it never hangs off a corner.
+Unlike scorch marks they don't touch the lightmap and are drawn in the
+transparent pass over the world.
+
+Decals are kept with the room or terrain cell they sit in so rendering
+only has to look at what's visible. There's a fixed budget of them and
+once it's used up the oldest ones are recycled.
+
+*/
+
+use std::collections::{HashMap, VecDeque};
+
+use super::physics::intersection::{HitType, IntersectionFinderResult};
+use super::prelude::*;
+use super::room::{Face, Room};
+use super::terrain::{Terrain, TERRAIN_DEPTH, TERRAIN_SIZE, TERRAIN_WIDTH};
+use crate::graphics::{bitmap::Bitmap16, drawing_3d::Point3, rendering::AlphaType};
+use vector::Vector;
+
+/// Decals alive at once across the whole level
+pub const MAX_DECALS: usize = 512;
+
+/// Decals a single room or terrain cell holds before its oldest is recycled
+pub const MAX_DECALS_PER_LOCATION: usize = 64;
+
+/// How far decals are lifted off the surface so they don't z-fight with it
+pub const DECAL_OFFSET: f32 = 0.05;
+
+/// Decals are blended with their texture's alpha
+pub const DECAL_ALPHA_TYPE: AlphaType = AlphaType::TEXTURE;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DecalLocation {
+    Room(usize),
+    /// Terrain cell index, z * TERRAIN_WIDTH + x
+    TerrainCell(usize),
+}
+
+/// What a decal was spawned on
+#[derive(Debug, Copy, Clone)]
+pub enum DecalSurface<'a> {
+    Face { room: &'a Room, face: usize },
+    Terrain { terrain: &'a Terrain, cell: usize },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DecalVertex {
+    pub position: Vector,
+    pub u: f32,
+    pub v: f32,
+}
+
+impl DecalVertex {
+    fn lerp(&self, other: &DecalVertex, t: f32) -> DecalVertex {
+        DecalVertex {
+            position: self.position + (other.position - self.position) * t,
+            u: self.u + (other.u - self.u) * t,
+            v: self.v + (other.v - self.v) * t,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Decal {
+    pub texture: SharedMutRef<dyn Bitmap16>,
+    pub location: DecalLocation,
+    /// The quad after clipping, already lifted off the surface
+    pub vertices: Vec<DecalVertex>,
+    pub normal: Vector,
+    /// Spawn order, lower is older
+    serial: u64,
+}
+
+impl Decal {
+    pub fn center(&self) -> Vector {
+        let sum = self.vertices.iter().fold(Vector::ZERO, |sum, v| sum + v.position);
+        sum * (1.0 / self.vertices.len().max(1) as f32)
+    }
+}
+
+/// Keeps the part of a polygon in front of a plane
+fn clip_polygon(polygon: &[DecalVertex], point: &Vector, normal: &Vector) -> Vec<DecalVertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        let da = (a.position - *point) * *normal;
+        let db = (b.position - *point) * *normal;
+
+        if da >= 0.0 {
+            clipped.push(*a);
+        }
+
+        if (da >= 0.0) != (db >= 0.0) {
+            clipped.push(a.lerp(b, da / (da - db)));
+        }
+    }
+
+    clipped
+}
+
+/// Lays a quad on the plane of a convex surface and clips it to the surface's edges, None if nothing's left
+fn build_decal_polygon(corners: &[Vector], surface_normal: &Vector, hit_point: &Vector, normal: &Vector, size: f32) -> Option<Vec<DecalVertex>> {
+    if corners.len() < 3 || size <= 0.0 {
+        return None;
+    }
+
+    let n = normal.normalized();
+
+    // Hit from behind
+    if n * *surface_normal <= 0.0 {
+        return None;
+    }
+
+    let up = if n.y.abs() < 0.9 { Vector::new(0.0, 1.0, 0.0) } else { Vector::new(1.0, 0.0, 0.0) };
+    let right = up.cross(&n).normalized();
+    let up = n.cross(&right);
+    let half = size * 0.5;
+
+    let origin = corners[0];
+    let onto_surface = |p: Vector| p - *surface_normal * ((p - origin) * *surface_normal) + *surface_normal * DECAL_OFFSET;
+
+    let mut polygon: Vec<DecalVertex> = [(-1.0, -1.0, 0.0, 1.0), (1.0, -1.0, 1.0, 1.0), (1.0, 1.0, 1.0, 0.0), (-1.0, 1.0, 0.0, 0.0)]
+        .iter()
+        .map(|&(x, y, u, v)| DecalVertex {
+            position: onto_surface(*hit_point + right * (x * half) + up * (y * half)),
+            u,
+            v,
+        })
+        .collect();
+
+    let centroid = corners.iter().fold(Vector::ZERO, |sum, c| sum + *c) * (1.0 / corners.len() as f32);
+
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        let mut inward = surface_normal.cross(&(b - *a));
+
+        // Works for either winding
+        if (centroid - *a) * inward < 0.0 {
+            inward = inward * -1.0;
+        }
+
+        polygon = clip_polygon(&polygon, a, &inward);
+
+        if polygon.len() < 3 {
+            return None;
+        }
+    }
+
+    Some(polygon)
+}
+
+/// The triangle of a terrain cell under a point, with its normal
+fn terrain_triangle(terrain: &Terrain, cell: usize, point: &Vector) -> Option<([Vector; 3], Vector)> {
+    let (x, z) = (cell % TERRAIN_WIDTH, cell / TERRAIN_WIDTH);
+
+    if x + 1 >= TERRAIN_WIDTH || z + 1 >= TERRAIN_DEPTH {
+        return None;
+    }
+
+    let fx = point.x / TERRAIN_SIZE - x as f32;
+    let fz = point.z / TERRAIN_SIZE - z as f32;
+    let (upper_left, lower_right) = terrain.cell_normals(x, z);
+
+    let a = terrain.cell_position(x, z);
+    let c = terrain.cell_position(x + 1, z + 1);
+
+    // Cells are split corner to corner, the upper left triangle has the x, z + 1 corner
+    if fz >= fx {
+        Some(([a, terrain.cell_position(x, z + 1), c], upper_left))
+    } else {
+        Some(([a, c, terrain.cell_position(x + 1, z)], lower_right))
+    }
+}
+
+/// A decal ready for the transparent pass, points are in world space with their texture coordinates
+#[derive(Debug, Clone)]
+pub struct DecalPolygon {
+    pub texture: SharedMutRef<dyn Bitmap16>,
+    pub points: Vec<Point3>,
+    pub distance: f32,
+}
+
+/// Drawn back to front after the opaque world, with `alpha_type`
+#[derive(Debug, Clone)]
+pub struct DecalDrawList {
+    pub alpha_type: AlphaType,
+    pub polygons: Vec<DecalPolygon>,
+}
+
+impl Default for DecalDrawList {
+    fn default() -> Self {
+        Self {
+            alpha_type: DECAL_ALPHA_TYPE,
+            polygons: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DecalManager {
+    locations: HashMap<DecalLocation, VecDeque<Decal>>,
+    count: usize,
+    next_serial: u64,
+}
+
+impl DecalManager {
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn decals_in(&self, location: DecalLocation) -> impl Iterator<Item = &Decal> {
+        self.locations.get(&location).into_iter().flat_map(|d| d.iter())
+    }
+
+    /// Puts a decal of `size` world units where something hit a surface, false if it didn't land on it
+    pub fn spawn_decal(
+        &mut self,
+        surface: DecalSurface,
+        texture: SharedMutRef<dyn Bitmap16>,
+        hit_point: &Vector,
+        normal: &Vector,
+        size: f32,
+    ) -> bool {
+        match surface {
+            DecalSurface::Face { room, face } => match room.faces.get(face) {
+                Some(f) => self.spawn_on_face(DecalLocation::Room(room.id()), &room.vertices, f, texture, hit_point, normal, size),
+                None => false,
+            },
+            DecalSurface::Terrain { terrain, cell } => match terrain_triangle(terrain, cell, hit_point) {
+                Some((corners, surface_normal)) => {
+                    self.spawn_on_polygon(DecalLocation::TerrainCell(cell), &corners, &surface_normal, texture, hit_point, normal, size)
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Spawns a decal at one of the hits from an intersection query, for walls and terrain
+    pub fn spawn_decal_at_hit(
+        &mut self,
+        hit: &IntersectionFinderResult,
+        index: usize,
+        terrain: Option<&Terrain>,
+        texture: SharedMutRef<dyn Bitmap16>,
+        size: f32,
+    ) -> bool {
+        if index >= hit.hit_count.min(hit.hit_type.len()) {
+            return false;
+        }
+
+        let point = &hit.hit_face_point[index];
+        let normal = &hit.hit_wall_normal[index];
+        let face = hit.hit_face[index];
+
+        match (hit.hit_type[index], &hit.hit_face_room[index], terrain) {
+            (HitType::Wall, Some(room), _) => {
+                self.spawn_decal(DecalSurface::Face { room: &room.borrow(), face }, texture, point, normal, size)
+            }
+            (HitType::Terrain, _, Some(terrain)) => {
+                self.spawn_decal(DecalSurface::Terrain { terrain, cell: face }, texture, point, normal, size)
+            }
+            _ => false,
+        }
+    }
+
+    fn spawn_on_face(
+        &mut self,
+        location: DecalLocation,
+        vertices: &[Vector],
+        face: &Face,
+        texture: SharedMutRef<dyn Bitmap16>,
+        hit_point: &Vector,
+        normal: &Vector,
+        size: f32,
+    ) -> bool {
+        let corners: Vec<Vector> = face.face_verts.iter().filter_map(|&i| vertices.get(i).copied()).collect();
+        self.spawn_on_polygon(location, &corners, &face.normal, texture, hit_point, normal, size)
+    }
+
+    fn spawn_on_polygon(
+        &mut self,
+        location: DecalLocation,
+        corners: &[Vector],
+        surface_normal: &Vector,
+        texture: SharedMutRef<dyn Bitmap16>,
+        hit_point: &Vector,
+        normal: &Vector,
+        size: f32,
+    ) -> bool {
+        let Some(vertices) = build_decal_polygon(corners, surface_normal, hit_point, normal, size) else {
+            return false;
+        };
+
+        let local = self.locations.get(&location).map_or(0, |d| d.len());
+
+        if local >= MAX_DECALS_PER_LOCATION {
+            self.recycle_in(location);
+        } else if self.count >= MAX_DECALS {
+            self.recycle_oldest();
+        }
+
+        let decal = Decal {
+            texture,
+            location,
+            vertices,
+            normal: *surface_normal,
+            serial: self.next_serial,
+        };
+
+        self.next_serial += 1;
+        self.count += 1;
+        self.locations.entry(location).or_default().push_back(decal);
+
+        true
+    }
+
+    fn recycle_in(&mut self, location: DecalLocation) {
+        if let Some(decals) = self.locations.get_mut(&location) {
+            if decals.pop_front().is_some() {
+                self.count -= 1;
+            }
+        }
+    }
+
+    fn recycle_oldest(&mut self) {
+        let oldest = self
+            .locations
+            .iter()
+            .filter_map(|(location, decals)| decals.front().map(|d| (*location, d.serial)))
+            .min_by_key(|(_, serial)| *serial)
+            .map(|(location, _)| location);
+
+        if let Some(location) = oldest {
+            self.recycle_in(location);
+
+            if self.locations.get(&location).is_some_and(|d| d.is_empty()) {
+                self.locations.remove(&location);
+            }
+        }
+    }
+
+    /// Removes the decals in a room or cell, for when its geometry changes
+    pub fn clear_location(&mut self, location: DecalLocation) {
+        if let Some(decals) = self.locations.remove(&location) {
+            self.count -= decals.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.locations.clear();
+        self.count = 0;
+    }
+
+    /// Collects the decals in the visible rooms and cells for the transparent pass, farthest first
+    pub fn render(&self, visible: impl IntoIterator<Item = DecalLocation>, eye: &Vector) -> DecalDrawList {
+        let mut list = DecalDrawList::default();
+
+        for location in visible {
+            for decal in self.decals_in(location) {
+                // Seen from behind
+                if (*eye - decal.vertices[0].position) * decal.normal <= 0.0 {
+                    continue;
+                }
+
+                let points = decal
+                    .vertices
+                    .iter()
+                    .map(|v| {
+                        let mut point = Point3::new(v.position.x, v.position.y, v.position.z);
+                        point.origin = v.position;
+                        point.set_u(v.u);
+                        point.set_v(v.v);
+                        point.set_light(1.0);
+                        point
+                    })
+                    .collect();
+
+                list.polygons.push(DecalPolygon {
+                    texture: decal.texture.clone(),
+                    points,
+                    distance: Vector::distance(eye, &decal.center()),
+                });
+            }
+        }
+
+        list.polygons.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+
+        list
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::graphics::generic_bitmap::GenericBitmap16;
+
+    use super::*;
+
+    fn texture() -> SharedMutRef<dyn Bitmap16> {
+        new_shared_mut_ref(GenericBitmap16::new(vec![0u16; 8 * 8], 8, 8))
+    }
+
+    /// 10x10 wall on the z = 0 plane facing +z
+    fn wall() -> [Vector; 4] {
+        [
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(10.0, 0.0, 0.0),
+            Vector::new(10.0, 10.0, 0.0),
+            Vector::new(0.0, 10.0, 0.0),
+        ]
+    }
+
+    const FACING: Vector = Vector { x: 0.0, y: 0.0, z: 1.0 };
+
+    #[test]
+    fn decal_clip_test() {
+        crate::test_common::setup();
+
+        // In the middle nothing gets cut
+        let polygon = build_decal_polygon(&wall(), &FACING, &Vector::new(5.0, 5.0, 0.0), &FACING, 2.0).unwrap();
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|v| (v.position.z - DECAL_OFFSET).abs() < 1e-4));
+
+        // On the corner only the quarter on the wall is left, with its texture coordinates cut to match
+        let polygon = build_decal_polygon(&wall(), &FACING, &Vector::new(0.0, 0.0, 0.0), &FACING, 2.0).unwrap();
+        assert!(polygon.iter().all(|v| v.position.x >= -1e-4 && v.position.y >= -1e-4));
+        assert!(polygon.iter().all(|v| v.position.x <= 1.0 + 1e-4 && v.position.y <= 1.0 + 1e-4));
+        assert!(polygon.iter().any(|v| (v.u - 0.5).abs() < 1e-4));
+
+        // Off the wall, or hit from behind
+        assert!(build_decal_polygon(&wall(), &FACING, &Vector::new(20.0, 5.0, 0.0), &FACING, 2.0).is_none());
+        assert!(build_decal_polygon(&wall(), &FACING, &Vector::new(5.0, 5.0, 0.0), &(FACING * -1.0), 2.0).is_none());
+    }
+
+    #[test]
+    fn decal_budget_test() {
+        crate::test_common::setup();
+
+        let mut decals = DecalManager::default();
+        let room = DecalLocation::Room(0);
+        let hit = Vector::new(5.0, 5.0, 0.0);
+
+        for _ in 0..MAX_DECALS_PER_LOCATION + 3 {
+            assert!(decals.spawn_on_polygon(room, &wall(), &FACING, texture(), &hit, &FACING, 1.0));
+        }
+
+        // The room recycles its own oldest
+        assert_eq!(decals.decals_in(room).count(), MAX_DECALS_PER_LOCATION);
+        assert_eq!(decals.decals_in(room).next().unwrap().serial, 3);
+
+        // Filling the level recycles the oldest anywhere
+        let mut cell = 0;
+        while decals.len() < MAX_DECALS {
+            decals.spawn_on_polygon(DecalLocation::TerrainCell(cell / 8), &wall(), &FACING, texture(), &hit, &FACING, 1.0);
+            cell += 1;
+        }
+
+        decals.spawn_on_polygon(DecalLocation::TerrainCell(1000), &wall(), &FACING, texture(), &hit, &FACING, 1.0);
+        assert_eq!(decals.len(), MAX_DECALS);
+        assert_eq!(decals.decals_in(room).count(), MAX_DECALS_PER_LOCATION - 1);
+
+        decals.clear_location(room);
+        assert_eq!(decals.len(), MAX_DECALS - MAX_DECALS_PER_LOCATION + 1);
+    }
+
+    #[test]
+    fn decal_render_test() {
+        crate::test_common::setup();
+
+        let mut decals = DecalManager::default();
+        decals.spawn_on_polygon(DecalLocation::Room(0), &wall(), &FACING, texture(), &Vector::new(2.0, 2.0, 0.0), &FACING, 1.0);
+        decals.spawn_on_polygon(DecalLocation::Room(0), &wall(), &FACING, texture(), &Vector::new(8.0, 8.0, 0.0), &FACING, 1.0);
+        decals.spawn_on_polygon(DecalLocation::Room(1), &wall(), &FACING, texture(), &Vector::new(5.0, 5.0, 0.0), &FACING, 1.0);
+
+        let eye = Vector::new(8.0, 8.0, 10.0);
+        let list = decals.render([DecalLocation::Room(0)], &eye);
+
+        // Only the visible room, farthest first
+        assert_eq!(list.polygons.len(), 2);
+        assert!(list.polygons[0].distance > list.polygons[1].distance);
+        assert_eq!(list.alpha_type, DECAL_ALPHA_TYPE);
+
+        // Nothing from behind the wall
+        assert!(decals.render([DecalLocation::Room(0)], &Vector::new(5.0, 5.0, -10.0)).polygons.is_empty());
+    }
+}
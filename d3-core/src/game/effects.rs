@@ -1,5 +1,4 @@
-use std::rc::Rc;
-use crate::math::vector::Vector;
+use crate::{common::WeakHandle, math::vector::Vector};
 use super::object::Object;
 use bitflags::bitflags;
 
@@ -20,7 +19,7 @@ pub struct DamageEffect {
     pub time: f32,
     pub per_second: f32,
     pub last_time: f32,
-    pub last_owner: Rc<Object>,
+    pub last_owner: WeakHandle<Object>,
 }
 
 #[derive(Debug, Clone)]
@@ -34,7 +33,7 @@ pub struct AudibleEffect {
 #[derive(Debug, Clone)]
 pub struct PowerupEffect {
     pub last_obect_hit_time: f32,
-    pub last_object_hit: Option<Rc<Object>>,
+    pub last_object_hit: WeakHandle<Object>,
 }
 
 #[derive(Debug, Clone)]
@@ -97,5 +96,5 @@ pub struct ColoredEffect {
 
 #[derive(Debug, Clone)]
 pub struct AttachmentEffect {
-    pub attached_object: Rc<Object>
+    pub attached_object: WeakHandle<Object>
 }
\ No newline at end of file
@@ -0,0 +1,214 @@
+use crate::{gr_rgb, graphics::ddgr_color};
+
+use super::{
+    audio::SOUND_REFUELING,
+    context::GameContext,
+    player::{Player, INITIAL_ENERGY, INITIAL_SHIELDS},
+    prelude::*,
+    room::RoomFlags,
+    scripting::EventType,
+};
+
+/// play every quarter second
+pub const FUELCEN_SOUND_DELAY: f32 = 0.25;
+/// give 25 units per second
+pub const FUELCEN_GIVE_RATE: f32 = 25.0;
+
+/// How fast the screen glow fades in and out, per second
+const FUELCEN_GLOW_RATE: f32 = 2.0;
+
+pub const FUELCEN_GLOW_COLOR: ddgr_color = gr_rgb!(255, 255, 0);
+pub const SHIELDCEN_GLOW_COLOR: ddgr_color = gr_rgb!(0, 128, 255);
+
+/// What a recharging room gives the player.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RechargeKind {
+    /// A refueling center, tops up energy.
+    Energy,
+    /// Tops up shields, only used by mods.
+    Shields,
+}
+
+impl RechargeKind {
+    pub fn from_room_flags(flags: RoomFlags) -> Option<Self> {
+        if flags.contains(RoomFlags::FUELCEN) {
+            Some(RechargeKind::Energy)
+        } else if flags.contains(RoomFlags::SHIELDCEN) {
+            Some(RechargeKind::Shields)
+        } else {
+            None
+        }
+    }
+
+    /// The value the center fills up to, anything above this came from powerups and is left alone
+    pub fn limit(&self) -> f32 {
+        match self {
+            RechargeKind::Energy => INITIAL_ENERGY,
+            RechargeKind::Shields => INITIAL_SHIELDS,
+        }
+    }
+
+    pub fn glow_color(&self) -> ddgr_color {
+        match self {
+            RechargeKind::Energy => FUELCEN_GLOW_COLOR,
+            RechargeKind::Shields => SHIELDCEN_GLOW_COLOR,
+        }
+    }
+}
+
+/// What happened during one frame of recharging.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RechargeFrame {
+    pub amount_given: f32,
+    pub play_sound: bool,
+    pub entered: Option<RechargeKind>,
+    pub exited: Option<RechargeKind>,
+}
+
+/// Tracks a single player's use of energy centers.
+#[derive(Debug, Clone, Default)]
+pub struct EnergyCenterState {
+    current: Option<RechargeKind>,
+    last_kind: Option<RechargeKind>,
+    sound_timer: f32,
+    glow: f32,
+}
+
+impl EnergyCenterState {
+    pub fn current(&self) -> Option<RechargeKind> {
+        self.current
+    }
+
+    /// The color and intensity (0 to 1) of the screen glow to draw this frame
+    pub fn glow(&self) -> Option<(ddgr_color, f32)> {
+        if self.glow <= 0.0 {
+            return None;
+        }
+
+        self.last_kind.map(|k| (k.glow_color(), self.glow))
+    }
+
+    /// Gives the player fuel.  Called each frame with the kind of center the player is in, if any
+    pub fn update(
+        &mut self,
+        kind: Option<RechargeKind>,
+        player: &mut Player,
+        shields: &mut f32,
+        frametime: f32,
+    ) -> RechargeFrame {
+        let mut frame = RechargeFrame::default();
+
+        if kind != self.current {
+            frame.exited = self.current;
+            frame.entered = kind;
+            self.current = kind;
+            self.sound_timer = 0.0;
+        }
+
+        if let Some(kind) = kind {
+            self.last_kind = Some(kind);
+
+            let value = match kind {
+                RechargeKind::Energy => &mut player.energy,
+                RechargeKind::Shields => shields,
+            };
+
+            let max = kind.limit() - *value;
+            let amount = (FUELCEN_GIVE_RATE * frametime).min(max);
+
+            if amount > 0.0 {
+                *value += amount;
+                frame.amount_given = amount;
+
+                self.sound_timer -= frametime;
+
+                if self.sound_timer <= 0.0 {
+                    frame.play_sound = true;
+                    self.sound_timer = FUELCEN_SOUND_DELAY;
+                }
+            }
+        }
+
+        let target = if frame.amount_given > 0.0 { 1.0 } else { 0.0 };
+
+        if self.glow < target {
+            self.glow = (self.glow + FUELCEN_GLOW_RATE * frametime).min(target);
+        } else {
+            self.glow = (self.glow - FUELCEN_GLOW_RATE * frametime).max(target);
+        }
+
+        frame
+    }
+}
+
+/// Do refueling centers for a player, plays the refueling hum and lets the scripts know
+/// when the player comes and goes
+pub fn do_energy_center(context: &mut GameContext, state: &mut EnergyCenterState, player: &mut Player) {
+    let Some(object_ref) = player.object.clone() else {
+        return;
+    };
+
+    let frametime = context.frametime();
+
+    let (kind, position) = {
+        let object = object_ref.borrow();
+
+        let kind = object
+            .parent_room
+            .upgrade()
+            .filter(|room| !room.borrow().is_outside)
+            .and_then(|room| RechargeKind::from_room_flags(room.borrow().flags));
+
+        (kind, object.position)
+    };
+
+    let frame = {
+        let mut object = object_ref.borrow_mut();
+        state.update(kind, player, &mut object.shields, frametime)
+    };
+
+    if frame.play_sound {
+        context.audio_system.play_3d_sound(SOUND_REFUELING, &position);
+    }
+
+    if frame.exited.is_some() {
+        context.script_runtime.signal_event(EventType::EnergyCenterExit, None, object_ref.clone());
+    }
+
+    if frame.entered.is_some() {
+        context.script_runtime.signal_event(EventType::EnergyCenterEnter, None, object_ref);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn refuels_up_to_initial_energy() {
+        crate::test_common::setup();
+
+        let mut state = EnergyCenterState::default();
+        let mut player = Player::default();
+        let mut shields = INITIAL_SHIELDS;
+        player.energy = INITIAL_ENERGY - 10.0;
+
+        let frame = state.update(Some(RechargeKind::Energy), &mut player, &mut shields, 0.25);
+        assert_eq!(frame.entered, Some(RechargeKind::Energy));
+        assert!(frame.play_sound);
+        assert_eq!(player.energy, INITIAL_ENERGY - 10.0 + FUELCEN_GIVE_RATE * 0.25);
+
+        let frame = state.update(Some(RechargeKind::Energy), &mut player, &mut shields, 1.0);
+        assert_eq!(player.energy, INITIAL_ENERGY);
+        assert!(frame.amount_given > 0.0);
+        assert!(state.glow().is_some());
+
+        let frame = state.update(Some(RechargeKind::Energy), &mut player, &mut shields, 1.0);
+        assert_eq!(frame.amount_given, 0.0);
+        assert!(!frame.play_sound);
+
+        let frame = state.update(None, &mut player, &mut shields, 1.0);
+        assert_eq!(frame.exited, Some(RechargeKind::Energy));
+        assert!(state.glow().is_none());
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::{
+    audio::SoundId,
+    prelude::*,
+    visual_effects::fireball::{FireballEffectInfo, FireballEffectType},
+};
+use crate::graphics::texture::TextureSizeType;
+use vector::Vector;
+
+pub const MAX_EXPLODER_TYPES: usize = 100;
+
+/// Index into the exploder table
+pub type ExploderId = usize;
+
+/// A destructible clutter or mine type, read from the table files
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExploderInfo {
+    pub name: D3String,
+    /// Damage it takes to set it off
+    pub hit_points: f32,
+    pub fireball: FireballEffectType,
+    pub fireball_size: f32,
+    /// How long the fireball lasts (in seconds)
+    pub fireball_life: f32,
+    /// Damage at the center of the blast, falling off to nothing at `blast_radius`
+    pub damage: f32,
+    pub blast_radius: f32,
+    /// Whether its blast can set off other exploders
+    pub chain_reacts: bool,
+    /// How long it burns after being set off by another blast before it goes too
+    pub chain_delay: f32,
+    pub sound: Option<SoundId>,
+}
+
+impl Default for ExploderInfo {
+    fn default() -> Self {
+        Self {
+            name: D3String::new(),
+            hit_points: 10.0,
+            fireball: FireballEffectType::Explosion,
+            fireball_size: 10.0,
+            fireball_life: 1.0,
+            damage: 20.0,
+            blast_radius: 30.0,
+            chain_reacts: true,
+            chain_delay: 0.25,
+            sound: None,
+        }
+    }
+}
+
+impl ExploderInfo {
+    pub fn fireball_info(&self) -> FireballEffectInfo {
+        FireballEffectInfo {
+            filename: None,
+            effect_type: self.fireball,
+            texture_size: TextureSizeType::Normal,
+            total_life: self.fireball_life,
+            size: self.fireball_size,
+        }
+    }
+
+    /// Blast damage to something `distance` away from the center
+    pub fn damage_at(&self, distance: f32) -> f32 {
+        if self.blast_radius <= 0.0 || distance >= self.blast_radius {
+            return 0.0;
+        }
+
+        self.damage * (1.0 - distance.max(0.0) / self.blast_radius)
+    }
+}
+
+/// Every exploder type, looked up by id or name
+#[derive(Debug, Clone, Default)]
+pub struct ExploderTable {
+    exploders: Vec<ExploderInfo>,
+    names: HashMap<String, ExploderId>,
+}
+
+impl ExploderTable {
+    pub fn add(&mut self, info: ExploderInfo) -> Result<ExploderId> {
+        if self.exploders.len() >= MAX_EXPLODER_TYPES {
+            return Err(anyhow!("exploder table is full"));
+        }
+
+        let name = info
+            .name
+            .to_string()
+            .map_err(|e| anyhow!("bad exploder name: {}", e))?
+            .to_lowercase();
+
+        if self.names.contains_key(&name) {
+            return Err(anyhow!("exploder {} is already in the table", name));
+        }
+
+        let id = self.exploders.len();
+        self.names.insert(name, id);
+        self.exploders.push(info);
+
+        Ok(id)
+    }
+
+    pub fn get(&self, id: ExploderId) -> Option<&ExploderInfo> {
+        self.exploders.get(id)
+    }
+
+    /// Names aren't case sensitive
+    pub fn find(&self, name: &str) -> Option<ExploderId> {
+        self.names.get(&name.to_lowercase()).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.exploders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exploders.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Exploder {
+    /// Handle of the object it belongs to
+    pub handle: usize,
+    pub kind: ExploderId,
+    pub position: Vector,
+    pub hit_points: f32,
+    /// Time left before it goes off, once something has set it off
+    pub fuse: Option<f32>,
+    /// Who gets the credit for the kill
+    pub killer: Option<usize>,
+}
+
+/// Something caught in a blast, from a radius query around it
+#[derive(Debug, Copy, Clone)]
+pub struct BlastTarget {
+    pub handle: usize,
+    pub position: Vector,
+    pub size: f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExploderEvent {
+    /// It blew up, spawn the fireball and remove the object
+    Exploded {
+        handle: usize,
+        kind: ExploderId,
+        position: Vector,
+        fireball: FireballEffectInfo,
+        sound: Option<SoundId>,
+        killer: Option<usize>,
+    },
+    /// Blast damage to an object that isn't an exploder
+    Damaged {
+        handle: usize,
+        damage: f32,
+        killer: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExploderManager {
+    exploders: Vec<Exploder>,
+}
+
+impl ExploderManager {
+    pub fn exploders(&self) -> &[Exploder] {
+        &self.exploders
+    }
+
+    pub fn get(&self, handle: usize) -> Option<&Exploder> {
+        self.exploders.iter().find(|e| e.handle == handle)
+    }
+
+    /// Makes an object an exploder of the given type
+    pub fn add(&mut self, table: &ExploderTable, handle: usize, kind: ExploderId, position: &Vector) -> Result<()> {
+        let info = table.get(kind).ok_or_else(|| anyhow!("no exploder type {}", kind))?;
+
+        if self.get(handle).is_some() {
+            return Err(anyhow!("object {} is already an exploder", handle));
+        }
+
+        self.exploders.push(Exploder {
+            handle,
+            kind,
+            position: *position,
+            hit_points: info.hit_points,
+            fuse: None,
+            killer: None,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, handle: usize) {
+        self.exploders.retain(|e| e.handle != handle);
+    }
+
+    /// Keeps an exploder that's being carried or knocked around where its object is
+    pub fn set_position(&mut self, handle: usize, position: &Vector) {
+        if let Some(exploder) = self.exploders.iter_mut().find(|e| e.handle == handle) {
+            exploder.position = *position;
+        }
+    }
+
+    /// Damage from a weapon or a collision, true if that set it off. Shooting one sets it off straight away.
+    pub fn apply_damage(&mut self, handle: usize, damage: f32, killer: Option<usize>) -> bool {
+        let Some(exploder) = self.exploders.iter_mut().find(|e| e.handle == handle) else {
+            return false;
+        };
+
+        if exploder.fuse.is_some() {
+            return false;
+        }
+
+        exploder.hit_points -= damage;
+
+        if exploder.hit_points > 0.0 {
+            return false;
+        }
+
+        exploder.fuse = Some(0.0);
+        exploder.killer = killer;
+
+        true
+    }
+
+    /// Burns down the fuses and blows up what's due. `nearby` finds what's within a radius of a
+    /// point, with `quick_dist_object_list`.
+    pub fn do_frame(
+        &mut self,
+        frametime: f32,
+        table: &ExploderTable,
+        mut nearby: impl FnMut(&Vector, f32) -> Vec<BlastTarget>,
+    ) -> Vec<ExploderEvent> {
+        let mut events = Vec::new();
+
+        for exploder in self.exploders.iter_mut() {
+            if let Some(fuse) = exploder.fuse.as_mut() {
+                *fuse -= frametime;
+            }
+        }
+
+        // Exploders with no delay set each other off in the same frame
+        while let Some(i) = self.exploders.iter().position(|e| e.fuse.is_some_and(|f| f <= 0.0)) {
+            let exploder = self.exploders.swap_remove(i);
+
+            let Some(info) = table.get(exploder.kind) else {
+                continue;
+            };
+
+            events.push(ExploderEvent::Exploded {
+                handle: exploder.handle,
+                kind: exploder.kind,
+                position: exploder.position,
+                fireball: info.fireball_info(),
+                sound: info.sound,
+                killer: exploder.killer,
+            });
+
+            if info.blast_radius <= 0.0 {
+                continue;
+            }
+
+            for target in nearby(&exploder.position, info.blast_radius) {
+                if target.handle == exploder.handle {
+                    continue;
+                }
+
+                let distance = Vector::distance(&target.position, &exploder.position) - target.size;
+                let damage = info.damage_at(distance);
+
+                if damage <= 0.0 {
+                    continue;
+                }
+
+                match self.exploders.iter_mut().find(|e| e.handle == target.handle) {
+                    Some(other) => {
+                        if !info.chain_reacts || other.fuse.is_some() {
+                            continue;
+                        }
+
+                        other.hit_points -= damage;
+
+                        if other.hit_points <= 0.0 {
+                            other.fuse = Some(table.get(other.kind).map_or(0.0, |i| i.chain_delay));
+                            other.killer = exploder.killer;
+                        }
+                    }
+                    None => events.push(ExploderEvent::Damaged {
+                        handle: target.handle,
+                        damage,
+                        killer: exploder.killer,
+                    }),
+                }
+            }
+        }
+
+        events
+    }
+
+    pub fn clear(&mut self) {
+        self.exploders.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn barrel_table() -> (ExploderTable, ExploderId, ExploderId) {
+        let mut table = ExploderTable::default();
+
+        let barrel = table
+            .add(ExploderInfo {
+                name: D3String::from("Barrel"),
+                chain_delay: 0.5,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let crate_id = table
+            .add(ExploderInfo {
+                name: D3String::from("Crate"),
+                chain_reacts: false,
+                chain_delay: 0.0,
+                ..Default::default()
+            })
+            .unwrap();
+
+        (table, barrel, crate_id)
+    }
+
+    /// Everything in the manager plus a player at handle 100
+    fn everything(manager: &ExploderManager) -> Vec<BlastTarget> {
+        manager
+            .exploders()
+            .iter()
+            .map(|e| BlastTarget { handle: e.handle, position: e.position, size: 1.0 })
+            .chain(std::iter::once(BlastTarget { handle: 100, position: Vector::new(0.0, 0.0, 5.0), size: 1.0 }))
+            .collect()
+    }
+
+    #[test]
+    fn exploder_table_test() {
+        crate::test_common::setup();
+
+        let (table, barrel, _) = barrel_table();
+        assert_eq!(table.find("BARREL"), Some(barrel));
+        assert!(table.clone().add(ExploderInfo { name: D3String::from("barrel"), ..Default::default() }).is_err());
+
+        let info = table.get(barrel).unwrap();
+        assert_eq!(info.damage_at(0.0), info.damage);
+        assert_eq!(info.damage_at(info.blast_radius), 0.0);
+        assert!(info.damage_at(info.blast_radius * 0.5) < info.damage);
+    }
+
+    #[test]
+    fn exploder_chain_reaction_test() {
+        crate::test_common::setup();
+
+        let (table, barrel, crate_id) = barrel_table();
+        let mut manager = ExploderManager::default();
+
+        manager.add(&table, 1, barrel, &Vector::new(0.0, 0.0, 0.0)).unwrap();
+        manager.add(&table, 2, barrel, &Vector::new(5.0, 0.0, 0.0)).unwrap();
+        manager.add(&table, 3, barrel, &Vector::new(500.0, 0.0, 0.0)).unwrap();
+        assert!(manager.add(&table, 1, barrel, &Vector::new(0.0, 0.0, 0.0)).is_err());
+
+        // Not enough to set it off
+        assert!(!manager.apply_damage(1, 5.0, Some(100)));
+        assert!(manager.apply_damage(1, 5.0, Some(100)));
+
+        let snapshot = manager.clone();
+        let events = manager.do_frame(0.1, &table, |_, _| everything(&snapshot));
+
+        // The first goes off, hurts the player and lights the one next to it
+        assert!(matches!(events[0], ExploderEvent::Exploded { handle: 1, killer: Some(100), .. }));
+        assert!(events.iter().any(|e| matches!(e, ExploderEvent::Damaged { handle: 100, .. })));
+        assert_eq!(manager.get(2).unwrap().fuse, Some(0.5));
+        assert!(manager.get(3).unwrap().fuse.is_none());
+
+        // It goes after its delay, with the credit passed along
+        let snapshot = manager.clone();
+        assert!(manager.do_frame(0.25, &table, |_, _| everything(&snapshot)).is_empty());
+        let events = manager.do_frame(0.25, &table, |_, _| everything(&snapshot));
+        assert!(matches!(events[0], ExploderEvent::Exploded { handle: 2, killer: Some(100), .. }));
+        assert!(manager.get(2).is_none());
+
+        // Crates don't set anything else off
+        let mut manager = ExploderManager::default();
+        manager.add(&table, 1, crate_id, &Vector::new(0.0, 0.0, 0.0)).unwrap();
+        manager.add(&table, 2, barrel, &Vector::new(5.0, 0.0, 0.0)).unwrap();
+        manager.apply_damage(1, 100.0, None);
+
+        let snapshot = manager.clone();
+        manager.do_frame(0.1, &table, |_, _| everything(&snapshot));
+        assert!(manager.get(2).unwrap().fuse.is_none());
+    }
+
+    #[test]
+    fn exploder_instant_chain_test() {
+        crate::test_common::setup();
+
+        let mut table = ExploderTable::default();
+        let mine = table
+            .add(ExploderInfo {
+                name: D3String::from("Mine"),
+                chain_delay: 0.0,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut manager = ExploderManager::default();
+        for i in 0..5 {
+            manager.add(&table, i, mine, &Vector::new(i as f32 * 4.0, 0.0, 0.0)).unwrap();
+        }
+
+        manager.apply_damage(0, 100.0, None);
+
+        let snapshot = manager.clone();
+        let events = manager.do_frame(0.01, &table, |_, _| everything(&snapshot));
+
+        // The whole row goes up in one frame
+        let exploded = events.iter().filter(|e| matches!(e, ExploderEvent::Exploded { .. })).count();
+        assert_eq!(exploded, 5);
+        assert!(manager.exploders().is_empty());
+    }
+}
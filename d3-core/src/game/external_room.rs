@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+
+use super::{
+    physics::intersection::{check_vector_to_sphere, FqFlags},
+    prelude::*,
+    room::{Room, RoomFlags},
+    terrain::{TERRAIN_DEPTH, TERRAIN_SIZE, TERRAIN_WIDTH},
+};
+use crate::math::bounds::{Aabb, Sphere};
+use vector::Vector;
+
+/// A building sitting on the terrain, what the terrain pass and terrain collision need of it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ExternalRoom {
+    pub room: usize,
+    pub bounds: Aabb,
+}
+
+impl ExternalRoom {
+    /// `None` for rooms that aren't external
+    pub fn from_room(room: &Room) -> Option<Self> {
+        room.flags.contains(RoomFlags::EXTERNAL).then(|| Self {
+            room: room.id(),
+            bounds: room.bounds(),
+        })
+    }
+
+    pub fn sphere(&self) -> Sphere {
+        Sphere::from(&self.bounds)
+    }
+}
+
+/// The range of terrain cells an area covers, clamped to the terrain
+fn cell_range(bounds: &Aabb) -> Option<(usize, usize, usize, usize)> {
+    let to_cell = |v: f32, count: usize| ((v / TERRAIN_SIZE).floor().max(0.0) as usize).min(count - 1);
+
+    let max_x = TERRAIN_WIDTH as f32 * TERRAIN_SIZE;
+    let max_z = TERRAIN_DEPTH as f32 * TERRAIN_SIZE;
+
+    if bounds.max.x < 0.0 || bounds.max.z < 0.0 || bounds.min.x >= max_x || bounds.min.z >= max_z {
+        return None;
+    }
+
+    Some((
+        to_cell(bounds.min.x, TERRAIN_WIDTH),
+        to_cell(bounds.min.z, TERRAIN_DEPTH),
+        to_cell(bounds.max.x, TERRAIN_WIDTH),
+        to_cell(bounds.max.z, TERRAIN_DEPTH),
+    ))
+}
+
+/// Which external rooms stand on which terrain cells, so terrain collision
+/// can pick up the building surfaces along with the ground
+#[derive(Debug, Clone, Default)]
+pub struct ExternalRoomLinks {
+    rooms: BTreeMap<usize, ExternalRoom>,
+    cells: BTreeMap<usize, Vec<usize>>,
+}
+
+impl ExternalRoomLinks {
+    pub fn build<'a>(rooms: impl IntoIterator<Item = &'a Room>) -> Self {
+        let mut links = Self::default();
+
+        for room in rooms {
+            if let Some(external) = ExternalRoom::from_room(room) {
+                links.link(external);
+            }
+        }
+
+        links
+    }
+
+    pub fn link(&mut self, external: ExternalRoom) {
+        self.unlink(external.room);
+
+        if let Some((x0, z0, x1, z1)) = cell_range(&external.bounds) {
+            for z in z0..=z1 {
+                for x in x0..=x1 {
+                    self.cells.entry(z * TERRAIN_WIDTH + x).or_default().push(external.room);
+                }
+            }
+        }
+
+        self.rooms.insert(external.room, external);
+    }
+
+    pub fn unlink(&mut self, room: usize) {
+        if self.rooms.remove(&room).is_none() {
+            return;
+        }
+
+        self.cells.retain(|_, rooms| {
+            rooms.retain(|r| *r != room);
+            !rooms.is_empty()
+        });
+    }
+
+    pub fn get(&self, room: usize) -> Option<&ExternalRoom> {
+        self.rooms.get(&room)
+    }
+
+    /// External rooms standing on a cell
+    pub fn rooms_in_cell(&self, cell: usize) -> &[usize] {
+        self.cells.get(&cell).map(|r| r.as_slice()).unwrap_or(&[])
+    }
+
+    /// External rooms with bounds touching an area, each once
+    pub fn rooms_near(&self, area: &Aabb) -> Vec<usize> {
+        let mut found: Vec<usize> = Vec::new();
+
+        let Some((x0, z0, x1, z1)) = cell_range(area) else {
+            return found;
+        };
+
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                for room in self.rooms_in_cell(z * TERRAIN_WIDTH + x) {
+                    if !found.contains(room) && self.rooms[room].bounds.overlaps(area) {
+                        found.push(*room);
+                    }
+                }
+            }
+        }
+
+        found
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExternalRoomCheck {
+    /// Nowhere near it, or external rooms are being ignored
+    Miss,
+    /// Close enough that its faces need checking
+    CheckFaces,
+    /// Hit the room's bounding sphere, with the hit point and distance along the move
+    SphereHit { point: Vector, distance: f32 },
+}
+
+/// The first FVI step against an external room, a sphere test standing in for
+/// the room itself with `FqFlags::EXTERNAL_ROOMS_AS_SPHERE`, otherwise a cull before the faces
+pub fn check_vector_to_external_room(
+    p0: &Vector,
+    p1: &Vector,
+    rad: f32,
+    external: &ExternalRoom,
+    flags: FqFlags,
+) -> ExternalRoomCheck {
+    if flags.contains(FqFlags::IGNORE_EXTERNAL_ROOMS) {
+        return ExternalRoomCheck::Miss;
+    }
+
+    let mut sphere = external.sphere();
+    sphere.radius += rad;
+
+    if flags.contains(FqFlags::EXTERNAL_ROOMS_AS_SPHERE) {
+        let mut point = Vector::default();
+        let mut distance = 0.0;
+
+        return if check_vector_to_sphere(&mut point, &mut distance, p0, p1, &sphere, false, false) {
+            ExternalRoomCheck::SphereHit { point, distance }
+        } else {
+            ExternalRoomCheck::Miss
+        };
+    }
+
+    let mut movement = Aabb::from_point(p0);
+    movement.add_point(p1);
+    movement.expand(rad);
+
+    if movement.overlaps(&external.bounds) {
+        ExternalRoomCheck::CheckFaces
+    } else {
+        ExternalRoomCheck::Miss
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TerrainPassItem {
+    Cell(usize),
+    Room(usize),
+}
+
+/// Orders a terrain pass with the external rooms worked in among the cells.
+/// Cells come far to near, and each room goes after the furthest of the cells it stands on
+/// so the ground around its base is already in the zbuffer, and before anything nearer
+/// that could cover it. Rooms not standing on a visible cell go last, far to near.
+pub fn terrain_pass_order(visible_cells: &[usize], visible_rooms: &[usize], links: &ExternalRoomLinks, viewer: &Vector) -> Vec<TerrainPassItem> {
+    let cell_center = |cell: usize| {
+        Vector::new(
+            ((cell % TERRAIN_WIDTH) as f32 + 0.5) * TERRAIN_SIZE,
+            viewer.y,
+            ((cell / TERRAIN_WIDTH) as f32 + 0.5) * TERRAIN_SIZE,
+        )
+    };
+
+    let mut cells = visible_cells.to_vec();
+    cells.sort_by(|a, b| Vector::distance(&cell_center(*b), viewer).total_cmp(&Vector::distance(&cell_center(*a), viewer)));
+
+    let mut order = Vec::with_capacity(cells.len() + visible_rooms.len());
+    let mut placed: Vec<usize> = Vec::new();
+
+    for cell in cells {
+        order.push(TerrainPassItem::Cell(cell));
+
+        for room in links.rooms_in_cell(cell) {
+            if visible_rooms.contains(room) && !placed.contains(room) {
+                placed.push(*room);
+                order.push(TerrainPassItem::Room(*room));
+            }
+        }
+    }
+
+    let mut rest: Vec<&ExternalRoom> = visible_rooms
+        .iter()
+        .filter(|r| !placed.contains(*r))
+        .filter_map(|r| links.get(*r))
+        .collect();
+
+    rest.sort_by(|a, b| Vector::distance(&b.bounds.center(), viewer).total_cmp(&Vector::distance(&a.bounds.center(), viewer)));
+    order.extend(rest.into_iter().map(|r| TerrainPassItem::Room(r.room)));
+
+    order
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn building(room: usize, x: f32, z: f32) -> ExternalRoom {
+        ExternalRoom {
+            room,
+            bounds: Aabb::new(Vector::new(x, 0.0, z), Vector::new(x + 20.0, 30.0, z + 20.0)),
+        }
+    }
+
+    #[test]
+    fn external_room_links_test() {
+        crate::test_common::setup();
+
+        let mut links = ExternalRoomLinks::default();
+        links.link(building(1, 100.0, 100.0));
+        links.link(building(2, 1000.0, 1000.0));
+
+        // 100 to 120 covers cells 6 and 7 each way
+        let cell = |x: usize, z: usize| z * TERRAIN_WIDTH + x;
+        assert_eq!(links.rooms_in_cell(cell(6, 6)), &[1]);
+        assert_eq!(links.rooms_in_cell(cell(7, 7)), &[1]);
+        assert!(links.rooms_in_cell(cell(8, 8)).is_empty());
+
+        let near = Aabb::from_center_radius(&Vector::new(125.0, 10.0, 110.0), 8.0);
+        assert_eq!(links.rooms_near(&near), vec![1]);
+
+        links.unlink(1);
+        assert!(links.rooms_in_cell(cell(6, 6)).is_empty());
+        assert!(links.rooms_near(&near).is_empty());
+    }
+
+    #[test]
+    fn external_room_fvi_test() {
+        crate::test_common::setup();
+
+        let room = building(1, 100.0, 100.0);
+        let p0 = Vector::new(0.0, 15.0, 110.0);
+        let p1 = Vector::new(200.0, 15.0, 110.0);
+
+        assert_eq!(check_vector_to_external_room(&p0, &p1, 1.0, &room, FqFlags::empty()), ExternalRoomCheck::CheckFaces);
+        assert_eq!(check_vector_to_external_room(&p0, &p1, 1.0, &room, FqFlags::IGNORE_EXTERNAL_ROOMS), ExternalRoomCheck::Miss);
+
+        match check_vector_to_external_room(&p0, &p1, 1.0, &room, FqFlags::EXTERNAL_ROOMS_AS_SPHERE) {
+            ExternalRoomCheck::SphereHit { point, .. } => assert!(point.x < 100.0 && point.x > 50.0),
+            other => panic!("expected a sphere hit, got {:?}", other),
+        }
+
+        // Passing well overhead misses either way
+        let high0 = Vector::new(0.0, 200.0, 110.0);
+        let high1 = Vector::new(200.0, 200.0, 110.0);
+        assert_eq!(check_vector_to_external_room(&high0, &high1, 1.0, &room, FqFlags::EXTERNAL_ROOMS_AS_SPHERE), ExternalRoomCheck::Miss);
+        assert_eq!(check_vector_to_external_room(&high0, &high1, 1.0, &room, FqFlags::empty()), ExternalRoomCheck::Miss);
+    }
+
+    #[test]
+    fn terrain_pass_order_test() {
+        crate::test_common::setup();
+
+        let mut links = ExternalRoomLinks::default();
+        links.link(building(1, 100.0, 100.0));
+        links.link(building(2, 2000.0, 2000.0));
+
+        let cell = |x: usize, z: usize| z * TERRAIN_WIDTH + x;
+        let viewer = Vector::new(8.0, 50.0, 8.0);
+        let order = terrain_pass_order(&[cell(0, 0), cell(6, 6), cell(7, 7), cell(10, 10)], &[1, 2], &links, &viewer);
+
+        // Far cell, then the building's far corner with the building after it, then nearer ground
+        assert_eq!(
+            order,
+            vec![
+                TerrainPassItem::Cell(cell(10, 10)),
+                TerrainPassItem::Cell(cell(7, 7)),
+                TerrainPassItem::Room(1),
+                TerrainPassItem::Cell(cell(6, 6)),
+                TerrainPassItem::Cell(cell(0, 0)),
+                TerrainPassItem::Room(2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+
+use super::{area_damage::DamageFalloff, prelude::*};
+use crate::math::bounds::{Aabb, Sphere};
+use vector::Vector;
+
+/// Handle scripts use to change a field they placed
+pub type ForceFieldId = u32;
+
+/// The space a force field covers
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ForceVolume {
+    /// All of a room, by room id
+    Room(usize),
+    Box(Aabb),
+    Sphere(Sphere),
+}
+
+impl ForceVolume {
+    pub fn contains(&self, room: usize, position: &Vector) -> bool {
+        match self {
+            ForceVolume::Room(id) => *id == room,
+            ForceVolume::Box(aabb) => aabb.contains_point(position),
+            ForceVolume::Sphere(sphere) => sphere.contains_point(position),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ForceKind {
+    /// Pushes everything the same way, like a wind tunnel
+    Wind { direction: Vector },
+    /// Pulls towards (or pushes away from, with a negative strength) a point
+    Well { center: Vector, radius: f32, falloff: DamageFalloff },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ForceField {
+    pub volume: ForceVolume,
+    pub kind: ForceKind,
+    /// Acceleration in units per second squared
+    pub strength: f32,
+    pub enabled: bool,
+}
+
+impl ForceField {
+    /// Acceleration this field puts on something at `position`, zero outside of it
+    pub fn acceleration(&self, room: usize, position: &Vector) -> Vector {
+        if !self.enabled || !self.volume.contains(room, position) {
+            return Vector::default();
+        }
+
+        match self.kind {
+            ForceKind::Wind { direction } => direction.normalized() * self.strength,
+            ForceKind::Well { center, radius, falloff } => {
+                let mut dir = Vector::default();
+                let distance = Vector::compute_normalized_direction(&mut dir, &center, position);
+
+                // Right at the center there's no way to pull
+                if distance <= 0.0 {
+                    return Vector::default();
+                }
+
+                dir * (self.strength * falloff.scale(distance, radius))
+            }
+        }
+    }
+}
+
+/// Every force field in the level, from the level data and from scripts
+#[derive(Debug, Clone, Default)]
+pub struct ForceFields {
+    fields: BTreeMap<ForceFieldId, ForceField>,
+    /// Fields made for room wind from the level data, by room id
+    room_wind: BTreeMap<usize, ForceFieldId>,
+    next_id: ForceFieldId,
+}
+
+impl ForceFields {
+    pub fn add(&mut self, field: ForceField) -> ForceFieldId {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.fields.insert(self.next_id, field);
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: ForceFieldId) -> bool {
+        self.room_wind.retain(|_, f| *f != id);
+        self.fields.remove(&id).is_some()
+    }
+
+    pub fn get(&self, id: ForceFieldId) -> Option<&ForceField> {
+        self.fields.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: ForceFieldId) -> Option<&mut ForceField> {
+        self.fields.get_mut(&id)
+    }
+
+    /// Switches a field on or off, false if there's no such field
+    pub fn set_enabled(&mut self, id: ForceFieldId, enabled: bool) -> bool {
+        self.get_mut(id).map(|f| f.enabled = enabled).is_some()
+    }
+
+    pub fn set_strength(&mut self, id: ForceFieldId, strength: f32) -> bool {
+        self.get_mut(id).map(|f| f.strength = strength).is_some()
+    }
+
+    /// Sets a room's wind, its length is the strength, a zero vector calms it
+    pub fn set_room_wind(&mut self, room: usize, wind: &Vector) {
+        let strength = Vector::magnitude(wind);
+
+        if strength <= 0.0 {
+            if let Some(id) = self.room_wind.remove(&room) {
+                self.fields.remove(&id);
+            }
+            return;
+        }
+
+        let field = ForceField {
+            volume: ForceVolume::Room(room),
+            kind: ForceKind::Wind { direction: *wind },
+            strength,
+            enabled: true,
+        };
+
+        match self.room_wind.get(&room) {
+            Some(id) => {
+                self.fields.insert(*id, field);
+            }
+            None => {
+                let id = self.add(field);
+                self.room_wind.insert(room, id);
+            }
+        }
+    }
+
+    pub fn room_wind(&self, room: usize) -> Option<&ForceField> {
+        self.room_wind.get(&room).and_then(|id| self.fields.get(id))
+    }
+
+    /// Total acceleration on something at `position` in `room`
+    pub fn acceleration_at(&self, room: usize, position: &Vector) -> Vector {
+        let mut total = Vector::default();
+
+        for field in self.fields.values() {
+            total += field.acceleration(room, position);
+        }
+
+        total
+    }
+
+    /// Applies the fields to an object's velocity for one physics step
+    pub fn apply(&self, velocity: &mut Vector, room: usize, position: &Vector, frametime: f32) {
+        *velocity += self.acceleration_at(room, position) * frametime;
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.fields.clear();
+        self.room_wind.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn room_wind_test() {
+        crate::test_common::setup();
+
+        let mut fields = ForceFields::default();
+        fields.set_room_wind(3, &Vector::new(0.0, 0.0, 10.0));
+
+        let origin = Vector::new(0.0, 0.0, 0.0);
+        let mut velocity = Vector::default();
+        fields.apply(&mut velocity, 3, &origin, 0.5);
+        assert_eq!(velocity, Vector::new(0.0, 0.0, 5.0));
+
+        // Other rooms are calm
+        assert_eq!(fields.acceleration_at(4, &origin), Vector::default());
+
+        // Changing it keeps the one field, calming it takes it away
+        fields.set_room_wind(3, &Vector::new(20.0, 0.0, 0.0));
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields.room_wind(3).unwrap().strength, 20.0);
+        fields.set_room_wind(3, &Vector::default());
+        assert_eq!(fields.len(), 0);
+    }
+
+    #[test]
+    fn gravity_well_test() {
+        crate::test_common::setup();
+
+        let mut fields = ForceFields::default();
+        let center = Vector::new(0.0, 0.0, 0.0);
+        let id = fields.add(ForceField {
+            volume: ForceVolume::Sphere(Sphere::new(center, 50.0)),
+            kind: ForceKind::Well {
+                center,
+                radius: 50.0,
+                falloff: DamageFalloff::Linear,
+            },
+            strength: 100.0,
+            enabled: true,
+        });
+
+        // Pulls in, harder the closer it gets
+        let near = fields.acceleration_at(0, &Vector::new(10.0, 0.0, 0.0));
+        let far = fields.acceleration_at(0, &Vector::new(40.0, 0.0, 0.0));
+        assert!(near.x < far.x && far.x < 0.0);
+        assert_eq!(fields.acceleration_at(0, &Vector::new(60.0, 0.0, 0.0)), Vector::default());
+
+        // Scripts can turn it off
+        assert!(fields.set_enabled(id, false));
+        assert_eq!(fields.acceleration_at(0, &Vector::new(10.0, 0.0, 0.0)), Vector::default());
+        assert!(!fields.set_enabled(99, true));
+    }
+}
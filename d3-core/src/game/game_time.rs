@@ -0,0 +1,190 @@
+/*
+
+Game time
+
+Real time is what the clock on the wall says, game time is what the
+simulation runs on. They part ways when the game is paused, when a hitch
+gets clamped so physics doesn't take one enormous step, and when a script
+slows time down for effect. The UI animates off real time so menus still
+move while the game is paused.
+
+*/
+
+/// The longest frame the simulation is given, anything over is a hitch and gets cut down to this
+pub const DEFAULT_MAX_FRAMETIME: f32 = 0.1;
+
+/// Scripts can speed time up to this much
+pub const MAX_TIME_SCALE: f32 = 4.0;
+
+#[derive(Debug, Clone)]
+pub struct GameTime {
+    real_time: f32,
+    real_frametime: f32,
+    gametime: f32,
+    frametime: f32,
+    frame_count: u32,
+    max_frametime: f32,
+    /// Anything can pause, it stays paused until everything that paused it resumes
+    pause_count: u32,
+    time_scale: f32,
+    target_scale: f32,
+    /// Change of scale per real second while easing toward the target
+    scale_rate: f32,
+}
+
+impl Default for GameTime {
+    fn default() -> Self {
+        Self {
+            real_time: 0.0,
+            real_frametime: 0.0,
+            gametime: 0.0,
+            frametime: 0.0,
+            frame_count: 0,
+            max_frametime: DEFAULT_MAX_FRAMETIME,
+            pause_count: 0,
+            time_scale: 1.0,
+            target_scale: 1.0,
+            scale_rate: 0.0,
+        }
+    }
+}
+
+impl GameTime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seconds the simulation has run
+    pub fn gametime(&self) -> f32 {
+        self.gametime
+    }
+
+    /// Seconds the simulation moves this frame, 0 while paused
+    pub fn frametime(&self) -> f32 {
+        self.frametime
+    }
+
+    pub fn real_time(&self) -> f32 {
+        self.real_time
+    }
+
+    /// Seconds this frame really took, for things that keep going while paused
+    pub fn real_frametime(&self) -> f32 {
+        self.real_frametime
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    pub fn set_max_frametime(&mut self, max_frametime: f32) {
+        self.max_frametime = max_frametime.max(0.0);
+    }
+
+    /// Moves both clocks on by a frame that took `real_frametime` seconds, returns the game frametime
+    pub fn advance(&mut self, real_frametime: f32) -> f32 {
+        let real_frametime = real_frametime.max(0.0);
+
+        self.real_frametime = real_frametime;
+        self.real_time += real_frametime;
+        self.frame_count = self.frame_count.wrapping_add(1);
+
+        let clamped = real_frametime.min(self.max_frametime);
+
+        if clamped < real_frametime {
+            trace!(target: "game", "clamping a {:.3}s frame to {:.3}s", real_frametime, clamped);
+        }
+
+        if self.time_scale != self.target_scale {
+            let step = self.scale_rate * clamped;
+
+            self.time_scale = if step == 0.0 || (self.target_scale - self.time_scale).abs() <= step {
+                self.target_scale
+            } else {
+                self.time_scale + step.copysign(self.target_scale - self.time_scale)
+            };
+        }
+
+        self.frametime = if self.is_paused() { 0.0 } else { clamped * self.time_scale };
+        self.gametime += self.frametime;
+
+        self.frametime
+    }
+
+    pub fn pause(&mut self) {
+        self.pause_count += 1;
+    }
+
+    pub fn resume(&mut self) {
+        self.pause_count = self.pause_count.saturating_sub(1);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause_count > 0
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Has game time run at `scale` times real time, easing there over `ramp` real seconds.
+    /// 0.25 is slow motion, 0 stops the simulation like a pause the UI doesn't know about
+    pub fn set_time_scale(&mut self, scale: f32, ramp: f32) {
+        self.target_scale = scale.clamp(0.0, MAX_TIME_SCALE);
+
+        if ramp > 0.0 {
+            self.scale_rate = (self.target_scale - self.time_scale).abs() / ramp;
+        } else {
+            self.scale_rate = 0.0;
+            self.time_scale = self.target_scale;
+        }
+    }
+
+    /// Back to running at real time on a new level, the clocks start over
+    pub fn reset(&mut self) {
+        *self = Self {
+            max_frametime: self.max_frametime,
+            ..Self::default()
+        };
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn game_time_test() {
+        crate::test_common::setup();
+
+        let mut time = GameTime::new();
+
+        // A hitch is cut down, real time still counts all of it
+        assert_eq!(time.advance(0.5), DEFAULT_MAX_FRAMETIME);
+        assert_eq!(time.real_time(), 0.5);
+
+        // Paused twice over, it takes both to resume
+        time.pause();
+        time.pause();
+        assert_eq!(time.advance(0.05), 0.0);
+        assert_eq!(time.real_frametime(), 0.05);
+        time.resume();
+        assert!(time.is_paused());
+        time.resume();
+        assert_eq!(time.advance(0.05), 0.05);
+        assert!((time.gametime() - 0.15).abs() < 1e-6);
+
+        // Eases down to half speed over a fifth of a second
+        time.set_time_scale(0.5, 0.2);
+        time.advance(0.1);
+        assert!((time.time_scale() - 0.75).abs() < 1e-6);
+        assert!((time.frametime() - 0.075).abs() < 1e-6);
+        time.advance(0.1);
+        time.advance(0.1);
+        assert_eq!(time.time_scale(), 0.5);
+        assert!((time.frametime() - 0.05).abs() < 1e-6);
+
+        time.set_time_scale(10.0, 0.0);
+        assert_eq!(time.time_scale(), MAX_TIME_SCALE);
+    }
+}
@@ -0,0 +1,459 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+
+use super::{prelude::*, scripting::EventType};
+
+pub const MAX_LEVEL_GOALS: usize = 32;
+pub const MAX_GOAL_ITEMS: usize = 12;
+
+bitflags! {
+    /// Flags representing the state and completion rules of a level goal.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct LevelGoalFlags: u32 {
+        const BLANK1 = 0x00000001; // LGF_BLANK1
+        /// This is a secondary goal, otherwise it is a primary.
+        const SECONDARY_GOAL = 0x00000002; // LGF_SECONDARY_GOAL
+        /// The goal is active and shown to the player.
+        const ENABLED = 0x00000004; // LGF_ENABLED
+        /// The goal has been completed.
+        const COMPLETED = 0x00000008; // LGF_COMPLETED
+        /// The goal is listed in the telcom.
+        const TELCOM_LISTS = 0x00000010; // LGF_TELCOM_LISTS
+        /// The guidebot doesn't know where this goal is.
+        const GB_DOESNT_KNOW_LOC = 0x00000020; // LGF_GB_DOESNT_KNOW_LOC
+        /// The goal isn't tied to a location.
+        const NOT_LOC_BASED = 0x00000040; // LGF_NOT_LOC_BASED
+        /// The goal has been failed.
+        const FAILED = 0x00000080; // LGF_FAILED
+        /// Completed by activating a trigger.
+        const COMP_ACTIVATE = 0x00000100; // LGF_COMP_ACTIVATE
+        /// Completed by entering a room or terrain cell.
+        const COMP_ENTER = 0x00000200; // LGF_COMP_ENTER
+        /// Completed by destroying an object.
+        const COMP_DESTROY = 0x00000400; // LGF_COMP_DESTROY
+        /// Completed when a player's weapon hits an object.
+        const COMP_PLAYER_WEAPON = 0x00000800; // LGF_COMP_PLAYER_WEAPON
+        /// Completed when a player touches an object.
+        const COMP_PLAYER = 0x00001000; // LGF_COMP_PLAYER
+        /// Completed by a script.
+        const COMP_DALLAS = 0x00002000; // LGF_COMP_DALLAS
+
+        const COMP_MASK = Self::COMP_ACTIVATE.bits() | Self::COMP_ENTER.bits() | Self::COMP_DESTROY.bits()
+            | Self::COMP_PLAYER_WEAPON.bits() | Self::COMP_PLAYER.bits() | Self::COMP_DALLAS.bits(); // LGF_COMP_MASK
+    }
+}
+
+bitflags! {
+    /// Flags for the level goal system as a whole.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+    pub struct LevelFlags: u32 {
+        /// End the level as soon as all the primaries are done.
+        const AUTO_END_LEVEL = 0x01; // LF_AUTO_END_LEVEL
+        /// All of the primary goals have been completed.
+        const ALL_PRIMARIES_DONE = 0x02; // LF_ALL_PRIMARIES_DONE
+    }
+}
+
+/// What a goal item refers to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GoalItemType {
+    TerrainCell, // LIT_TERRAIN_CELL
+    InternalRoom, // LIT_INTERNAL_ROOM
+    Object, // LIT_OBJECT
+    Trigger, // LIT_TRIGGER
+    AnyMine, // LIT_ANY_MINE
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GoalItem {
+    pub item_type: GoalItemType,
+    pub handle: usize,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelGoal {
+    pub name: D3String,
+    pub item_name: D3String,
+    pub description: D3String,
+    pub completion_message: D3String,
+    pub items: Vec<GoalItem>,
+
+    /// Lower values are more important and get listed first
+    pub priority: i32,
+    pub goal_list: u8,
+    flags: LevelGoalFlags,
+
+    /// In a multiplayer game the client's flags can get cleared and set again,
+    /// so this keeps the goal from being completed more than once
+    goal_completed: bool,
+}
+
+impl Default for LevelGoal {
+    fn default() -> Self {
+        Self {
+            name: D3String::new(),
+            item_name: D3String::new(),
+            description: D3String::new(),
+            completion_message: D3String::new(),
+            items: Vec::new(),
+            priority: 0,
+            goal_list: 0,
+            flags: LevelGoalFlags::ENABLED | LevelGoalFlags::TELCOM_LISTS,
+            goal_completed: false,
+        }
+    }
+}
+
+impl LevelGoal {
+    pub fn new(name: D3String) -> Self {
+        Self {
+            name,
+            ..LevelGoal::default()
+        }
+    }
+
+    pub fn flags(&self) -> LevelGoalFlags {
+        self.flags
+    }
+
+    pub fn is_primary(&self) -> bool {
+        !self.flags.contains(LevelGoalFlags::SECONDARY_GOAL)
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.flags.contains(LevelGoalFlags::COMPLETED)
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.flags.contains(LevelGoalFlags::FAILED)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.flags.contains(LevelGoalFlags::ENABLED)
+            && !self.flags.intersects(LevelGoalFlags::COMPLETED | LevelGoalFlags::FAILED)
+    }
+
+    pub fn add_item(&mut self, item_type: GoalItemType, handle: usize) -> Result<usize> {
+        if self.items.len() >= MAX_GOAL_ITEMS {
+            return Err(anyhow!("goal already has {} items", MAX_GOAL_ITEMS));
+        }
+
+        self.items.push(GoalItem {
+            item_type,
+            handle,
+            done: false,
+        });
+
+        Ok(self.items.len() - 1)
+    }
+
+    fn all_items_done(&self) -> bool {
+        self.items.iter().all(|i| i.done)
+    }
+}
+
+/// Things that happened to the goals, to be passed on to scripts, the HUD and the telcom.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LevelGoalEvent {
+    ItemComplete(usize),
+    GoalComplete(usize),
+    GoalFailed(usize),
+    AllPrimariesComplete,
+}
+
+impl LevelGoalEvent {
+    /// The script event this maps to, if any
+    pub fn event_type(&self) -> Option<EventType> {
+        match self {
+            LevelGoalEvent::ItemComplete(_) => Some(EventType::LevelGoalItemComplete),
+            LevelGoalEvent::GoalComplete(_) => Some(EventType::LevelGoalComplete),
+            LevelGoalEvent::AllPrimariesComplete => Some(EventType::AllLevelGoalsComplete),
+            LevelGoalEvent::GoalFailed(_) => None,
+        }
+    }
+}
+
+/// A line in the telcom's goal list.
+#[derive(Debug, Clone)]
+pub struct TelcomGoalEntry {
+    pub index: usize,
+    pub name: D3String,
+    pub description: D3String,
+    pub primary: bool,
+    pub completed: bool,
+    pub failed: bool,
+}
+
+/// Goal tallies shown on the end of level screen.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LevelGoalSummary {
+    pub primaries_completed: usize,
+    pub primaries_total: usize,
+    pub secondaries_completed: usize,
+    pub secondaries_total: usize,
+    pub failed: usize,
+}
+
+/// Goals are sorted by priority and completion
+#[derive(Debug, Clone, Default)]
+pub struct LevelGoals {
+    goals: Vec<LevelGoal>,
+    pub flags: LevelFlags,
+    events: VecDeque<LevelGoalEvent>,
+}
+
+impl LevelGoals {
+    pub fn add_goal(&mut self, goal: LevelGoal) -> Result<usize> {
+        if self.goals.len() >= MAX_LEVEL_GOALS {
+            return Err(anyhow!("level already has {} goals", MAX_LEVEL_GOALS));
+        }
+
+        self.goals.push(goal);
+        Ok(self.goals.len() - 1)
+    }
+
+    pub fn goal(&self, index: usize) -> Option<&LevelGoal> {
+        self.goals.get(index)
+    }
+
+    pub fn goal_mut(&mut self, index: usize) -> Option<&mut LevelGoal> {
+        self.goals.get_mut(index)
+    }
+
+    pub fn goals(&self) -> &[LevelGoal] {
+        &self.goals
+    }
+
+    pub fn find_goal(&self, name: &str) -> Option<usize> {
+        self.goals
+            .iter()
+            .position(|g| g.name.to_string().map(|n| n.eq_ignore_ascii_case(name)).unwrap_or(false))
+    }
+
+    /// Sets flags on a goal, taking care of the completion bookkeeping
+    pub fn set_flags(&mut self, index: usize, flags: LevelGoalFlags) -> Result<()> {
+        let goal = self.goals.get_mut(index).ok_or_else(|| anyhow!("invalid goal index {}", index))?;
+        goal.flags |= flags;
+
+        if flags.contains(LevelGoalFlags::COMPLETED) && !goal.goal_completed {
+            goal.goal_completed = true;
+
+            debug!(target: "game", "level goal {} completed", index);
+            self.events.push_back(LevelGoalEvent::GoalComplete(index));
+            self.check_primaries();
+        }
+
+        if flags.contains(LevelGoalFlags::FAILED) {
+            self.events.push_back(LevelGoalEvent::GoalFailed(index));
+        }
+
+        Ok(())
+    }
+
+    pub fn clear_flags(&mut self, index: usize, flags: LevelGoalFlags) -> Result<()> {
+        let goal = self.goals.get_mut(index).ok_or_else(|| anyhow!("invalid goal index {}", index))?;
+        goal.flags.remove(flags);
+        Ok(())
+    }
+
+    pub fn complete_goal(&mut self, index: usize) -> Result<()> {
+        self.set_flags(index, LevelGoalFlags::COMPLETED)
+    }
+
+    pub fn fail_goal(&mut self, index: usize) -> Result<()> {
+        self.set_flags(index, LevelGoalFlags::FAILED)
+    }
+
+    /// Tells the goals that something happened to an item, `comp_type` is how it happened
+    pub fn inform(&mut self, item_type: GoalItemType, comp_type: LevelGoalFlags, handle: usize) {
+        let mut completed = Vec::new();
+
+        for (i, goal) in self.goals.iter_mut().enumerate() {
+            let player_driven = comp_type.intersects(
+                LevelGoalFlags::COMP_ACTIVATE
+                    | LevelGoalFlags::COMP_ENTER
+                    | LevelGoalFlags::COMP_PLAYER_WEAPON
+                    | LevelGoalFlags::COMP_PLAYER,
+            );
+
+            if !goal.flags.contains(LevelGoalFlags::ENABLED) && player_driven {
+                continue;
+            }
+
+            let cur_comp_type = goal.flags & LevelGoalFlags::COMP_MASK;
+
+            if cur_comp_type != LevelGoalFlags::COMP_DALLAS && cur_comp_type != comp_type {
+                continue;
+            }
+
+            let mut changed = false;
+
+            for item in goal.items.iter_mut() {
+                if item.done || item.item_type != item_type || item.handle != handle {
+                    continue;
+                }
+
+                item.done = true;
+                changed = true;
+
+                self.events.push_back(LevelGoalEvent::ItemComplete(i));
+            }
+
+            if changed && goal.all_items_done() {
+                completed.push(i);
+            }
+        }
+
+        for i in completed {
+            let _ = self.complete_goal(i);
+        }
+    }
+
+    fn check_primaries(&mut self) {
+        if self.flags.contains(LevelFlags::ALL_PRIMARIES_DONE) {
+            return;
+        }
+
+        let all_done = self
+            .goals
+            .iter()
+            .filter(|g| g.is_primary() && g.flags.contains(LevelGoalFlags::ENABLED))
+            .all(|g| g.is_completed());
+
+        if all_done {
+            self.flags |= LevelFlags::ALL_PRIMARIES_DONE;
+            self.events.push_back(LevelGoalEvent::AllPrimariesComplete);
+        }
+    }
+
+    /// True when the level should end on its own
+    pub fn should_end_level(&self) -> bool {
+        self.flags.contains(LevelFlags::AUTO_END_LEVEL | LevelFlags::ALL_PRIMARIES_DONE)
+    }
+
+    fn active_by_kind(&self, primary: bool) -> Vec<usize> {
+        let mut active: Vec<usize> = (0..self.goals.len())
+            .filter(|i| self.goals[*i].is_active() && self.goals[*i].is_primary() == primary)
+            .collect();
+
+        active.sort_by_key(|i| self.goals[*i].priority);
+        active
+    }
+
+    pub fn active_primaries(&self) -> Vec<usize> {
+        self.active_by_kind(true)
+    }
+
+    pub fn active_secondaries(&self) -> Vec<usize> {
+        self.active_by_kind(false)
+    }
+
+    /// The message to show on the HUD for an event, if it has one
+    pub fn hud_message(&self, event: &LevelGoalEvent) -> Option<&D3String> {
+        match event {
+            LevelGoalEvent::GoalComplete(i) => self
+                .goals
+                .get(*i)
+                .map(|g| &g.completion_message)
+                .filter(|m| !m.is_empty()),
+            _ => None,
+        }
+    }
+
+    /// The goals shown in the telcom, primaries first, then by priority
+    pub fn telcom_entries(&self) -> Vec<TelcomGoalEntry> {
+        let mut entries: Vec<TelcomGoalEntry> = self
+            .goals
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.flags.contains(LevelGoalFlags::ENABLED | LevelGoalFlags::TELCOM_LISTS))
+            .map(|(index, g)| TelcomGoalEntry {
+                index,
+                name: g.name.clone(),
+                description: g.description.clone(),
+                primary: g.is_primary(),
+                completed: g.is_completed(),
+                failed: g.is_failed(),
+            })
+            .collect();
+
+        entries.sort_by_key(|e| (!e.primary, self.goals[e.index].priority));
+        entries
+    }
+
+    pub fn summary(&self) -> LevelGoalSummary {
+        let mut summary = LevelGoalSummary::default();
+
+        for goal in self.goals.iter().filter(|g| g.flags.contains(LevelGoalFlags::ENABLED)) {
+            if goal.is_primary() {
+                summary.primaries_total += 1;
+                summary.primaries_completed += goal.is_completed() as usize;
+            } else {
+                summary.secondaries_total += 1;
+                summary.secondaries_completed += goal.is_completed() as usize;
+            }
+
+            summary.failed += goal.is_failed() as usize;
+        }
+
+        summary
+    }
+
+    pub fn drain_events(&mut self) -> impl Iterator<Item = LevelGoalEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    pub fn cleanup_after_level(&mut self) {
+        self.goals.clear();
+        self.flags = LevelFlags::empty();
+        self.events.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn destroying_items_completes_goal() {
+        crate::test_common::setup();
+
+        let mut goals = LevelGoals::default();
+        goals.flags |= LevelFlags::AUTO_END_LEVEL;
+
+        let mut reactor = LevelGoal::new(D3String::from("Destroy the reactor"));
+        reactor.flags |= LevelGoalFlags::COMP_DESTROY;
+        reactor.add_item(GoalItemType::Object, 10).unwrap();
+        reactor.add_item(GoalItemType::Object, 11).unwrap();
+        let reactor = goals.add_goal(reactor).unwrap();
+
+        let mut secondary = LevelGoal::new(D3String::from("Find the data"));
+        secondary.flags |= LevelGoalFlags::SECONDARY_GOAL | LevelGoalFlags::COMP_DALLAS;
+        goals.add_goal(secondary).unwrap();
+
+        goals.inform(GoalItemType::Object, LevelGoalFlags::COMP_DESTROY, 10);
+        assert!(!goals.goal(reactor).unwrap().is_completed());
+
+        goals.inform(GoalItemType::Object, LevelGoalFlags::COMP_DESTROY, 11);
+        assert!(goals.goal(reactor).unwrap().is_completed());
+        assert!(goals.should_end_level());
+
+        let events: Vec<LevelGoalEvent> = goals.drain_events().collect();
+        assert_eq!(
+            events,
+            vec![
+                LevelGoalEvent::ItemComplete(reactor),
+                LevelGoalEvent::ItemComplete(reactor),
+                LevelGoalEvent::GoalComplete(reactor),
+                LevelGoalEvent::AllPrimariesComplete,
+            ]
+        );
+
+        let summary = goals.summary();
+        assert_eq!(summary.primaries_completed, 1);
+        assert_eq!(summary.secondaries_total, 1);
+        assert_eq!(goals.active_secondaries(), vec![1]);
+    }
+}
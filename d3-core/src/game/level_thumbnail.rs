@@ -0,0 +1,412 @@
+/*
+
+Level thumbnails
+
+A still of a level for the mission browser and for tools, drawn once on the
+CPU with the headless renderer so it works on a server or in a build step
+with no window. The camera goes where it's told or, left to itself, backs off
+above one corner of the level until all of it fits in view.
+
+Faces turned away from the camera are skipped, so looking in from outside the
+near walls drop away and the insides of the rooms show. Faces whose texture
+was handed over with `add_texture` are mapped with it, the rest are a flat
+gray, and either way they're darker the less squarely they face the camera.
+
+*/
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::{
+    gr_rgb,
+    graphics::{
+        bitmap::{Bitmap16, MemBitmap32},
+        ddgr_color,
+        drawing_3d::{
+            projection::{Fov, Projection},
+            Camera, CameraBuilder, ScreenViewPort,
+        },
+        headless_renderer::HeadlessRenderer,
+        rendering::{AlphaType, LightStateType, RenderVertex, Renderer},
+        BitmapId, TextureHandle, UVCoord, GR_BLACK,
+    },
+    math::{bounds::Aabb, vector::Vector},
+};
+
+use super::{prelude::*, room::RoomStore};
+
+/// Anything closer to the eye than this gets clipped away
+const THUMBNAIL_NEAR_Z: f32 = 1.0;
+
+/// Faces side on to the camera still get this much light
+const THUMBNAIL_AMBIENT: f32 = 0.3;
+
+pub const THUMBNAIL_UNTEXTURED_COLOR: ddgr_color = gr_rgb!(170, 170, 170);
+
+/// Where a thumbnail is taken from
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ThumbnailView {
+    /// Far enough back from the level to take in all of it
+    #[default]
+    Overview,
+    LookAt { position: Vector, target: Vector },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ThumbnailOptions {
+    pub width: usize,
+    pub height: usize,
+    pub view: ThumbnailView,
+    pub fov: Fov,
+    pub background: ddgr_color,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            width: 160,
+            height: 120,
+            view: ThumbnailView::Overview,
+            fov: Fov::Degrees(72.0),
+            background: GR_BLACK,
+        }
+    }
+}
+
+/// Bounds of every vertex in the level, empty if there are none
+pub fn level_bounds(rooms: &RoomStore) -> Aabb {
+    let mut bounds = Aabb::EMPTY;
+
+    for (_, room) in rooms.iter() {
+        for vertex in room.borrow().vertices.iter() {
+            bounds.add_point(vertex);
+        }
+    }
+
+    bounds
+}
+
+/// A camera position and target that take in all of `bounds`, looking down from above one corner
+pub fn overview_vantage(bounds: &Aabb, fov: Fov, aspect: f32) -> (Vector, Vector) {
+    let target = bounds.center();
+    let radius = Vector::magnitude(&bounds.extents()).max(THUMBNAIL_NEAR_Z);
+
+    // Whichever of the two FOVs is narrower decides how far back the bounding sphere fits
+    let zoom = fov.zoom();
+    let half_fov = zoom.min(zoom / aspect.max(f32::EPSILON)).atan();
+    let distance = radius / half_fov.sin();
+
+    (target + Vector::new(-1.0, 1.0, -1.0).normalized() * distance, target)
+}
+
+/// Draws thumbnails of levels, holding on to the textures between them
+pub struct LevelThumbnailer {
+    renderer: HeadlessRenderer,
+    options: ThumbnailOptions,
+    textures: HashSet<usize>,
+}
+
+impl LevelThumbnailer {
+    pub fn new(options: ThumbnailOptions) -> Self {
+        Self {
+            renderer: HeadlessRenderer::new(options.width, options.height),
+            options,
+            textures: HashSet::new(),
+        }
+    }
+
+    pub fn options(&self) -> &ThumbnailOptions {
+        &self.options
+    }
+
+    /// Maps faces using level texture `tmap` with `bitmap`
+    pub fn add_texture(&mut self, tmap: usize, bitmap: &dyn Bitmap16) {
+        self.renderer.add_texture(TextureHandle::Bitmap(BitmapId(tmap)), bitmap);
+        self.textures.insert(tmap);
+    }
+
+    pub fn render(&mut self, rooms: &RoomStore) -> Result<MemBitmap32> {
+        let (width, height) = (self.options.width, self.options.height);
+
+        if width == 0 || height == 0 {
+            return Err(anyhow!("a {}x{} thumbnail has nothing to draw into", width, height));
+        }
+
+        let bounds = level_bounds(rooms);
+
+        if bounds.is_empty() {
+            return Err(anyhow!("the level has no geometry to take a thumbnail of"));
+        }
+
+        let viewport = ScreenViewPort { x: 0, y: 0, width, height, aspect: width as f32 / height as f32 };
+
+        let (position, target) = match self.options.view {
+            ThumbnailView::Overview => overview_vantage(&bounds, self.options.fov, viewport.aspect),
+            ThumbnailView::LookAt { position, target } => (position, target),
+        };
+
+        if position.approx_eq(&target, f32::EPSILON) {
+            return Err(anyhow!("the thumbnail camera is looking at its own position {:?}", position));
+        }
+
+        let camera = CameraBuilder::default().position(position).look_at(target).fov(self.options.fov).build()?;
+        let (fx, fy) = Projection::default().focal_lengths(&viewport, camera.zoom);
+
+        self.renderer.clear(self.options.background);
+        self.renderer.set_lighting(LightStateType::Gouraud);
+        self.renderer.set_alpha_type(AlphaType::ALWAYS);
+        self.renderer.set_zbuffer_state(1);
+        self.renderer.set_flat_color(THUMBNAIL_UNTEXTURED_COLOR);
+
+        let half_w = width as f32 / 2.0;
+        let half_h = height as f32 / 2.0;
+        let to_screen = |point: &ViewPoint, light: f32| RenderVertex {
+            screen_x: half_w + (point.view.x / point.view.z) * fx * half_w,
+            screen_y: half_h - (point.view.y / point.view.z) * fy * half_h,
+            z: point.view.z,
+            u: point.uv.u,
+            v: point.uv.v,
+            r: light,
+            g: light,
+            b: light,
+            a: 1.0,
+        };
+
+        for (_, room) in rooms.iter() {
+            let room = room.borrow();
+
+            for face in room.faces.iter() {
+                // Open portals have nothing to draw, the room beyond fills them in
+                if face.portal.as_ref().is_some_and(|p| !p.renders_face()) {
+                    continue;
+                }
+
+                let Some(points) = face_points(&room.vertices, &face.face_verts, &face.face_uvls, &camera) else {
+                    continue;
+                };
+
+                if face.normal.dot(camera.position - room.vertices[face.face_verts[0]]) <= 0.0 {
+                    continue;
+                }
+
+                // Lit as if by a lamp on the camera, so walls straight ahead are brightest
+                let squareness = (-face.normal.dot(camera.orientation.forward)).clamp(0.0, 1.0);
+                let light = THUMBNAIL_AMBIENT + (1.0 - THUMBNAIL_AMBIENT) * squareness;
+
+                let clipped = clip_near(&points);
+
+                if clipped.len() < 3 {
+                    continue;
+                }
+
+                let texture = face.tmap.filter(|tmap| self.textures.contains(tmap));
+                self.renderer.set_texture(texture.map(|tmap| TextureHandle::Bitmap(BitmapId(tmap))));
+
+                let vertices: Vec<RenderVertex> = clipped.iter().map(|p| to_screen(p, light)).collect();
+                self.renderer.draw_polygon(&vertices);
+            }
+        }
+
+        debug!(target: "render", "level thumbnail drew {} faces from {:?}", self.renderer.polygons_drawn(), position);
+
+        Ok(self.renderer.target().clone())
+    }
+}
+
+/// A corner of a face in view space
+#[derive(Debug, Copy, Clone)]
+struct ViewPoint {
+    view: Vector,
+    uv: UVCoord,
+}
+
+/// A face's corners in view space, none if it has too few or points at vertices the room doesn't have
+fn face_points(vertices: &[Vector], face_verts: &[usize], uvls: &[UVCoord], camera: &Camera) -> Option<Vec<ViewPoint>> {
+    if face_verts.len() < 3 {
+        return None;
+    }
+
+    face_verts
+        .iter()
+        .enumerate()
+        .map(|(i, &index)| {
+            let vertex = vertices.get(index)?;
+
+            Some(ViewPoint {
+                view: (*vertex - camera.position) * camera.orientation,
+                uv: uvls.get(i).copied().unwrap_or(UVCoord { u: 0.0, v: 0.0 }),
+            })
+        })
+        .collect()
+}
+
+/// Cuts off the part of a polygon nearer the eye than the near plane
+fn clip_near(points: &[ViewPoint]) -> Vec<ViewPoint> {
+    let mut clipped = Vec::with_capacity(points.len() + 1);
+
+    for (i, a) in points.iter().enumerate() {
+        let b = &points[(i + 1) % points.len()];
+        let (a_in, b_in) = (a.view.z >= THUMBNAIL_NEAR_Z, b.view.z >= THUMBNAIL_NEAR_Z);
+
+        if a_in {
+            clipped.push(*a);
+        }
+
+        if a_in != b_in {
+            let t = (THUMBNAIL_NEAR_Z - a.view.z) / (b.view.z - a.view.z);
+
+            clipped.push(ViewPoint {
+                view: a.view + (b.view - a.view) * t,
+                uv: UVCoord {
+                    u: a.uv.u + (b.uv.u - a.uv.u) * t,
+                    v: a.uv.v + (b.uv.v - a.uv.v) * t,
+                },
+            });
+        }
+    }
+
+    clipped
+}
+
+/// Draws one thumbnail of a level with no textures
+pub fn render_level_thumbnail(rooms: &RoomStore, options: ThumbnailOptions) -> Result<MemBitmap32> {
+    LevelThumbnailer::new(options).render(rooms)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        game::room::{Face, FaceFlags, Room},
+        graphics::{color_conversion::pixel_4444_to_32, generic_bitmap::GenericBitmap16},
+    };
+
+    use super::*;
+
+    const BACKGROUND: u32 = 0xFF000000;
+
+    /// A closed box from `min` to `max`, its faces facing in like a room's do
+    fn add_box_room(rooms: &mut RoomStore, min: Vector, max: Vector, tmap: Option<usize>) {
+        let corner = |i: usize| {
+            Vector::new(
+                if i & 1 != 0 { max.x } else { min.x },
+                if i & 2 != 0 { max.y } else { min.y },
+                if i & 4 != 0 { max.z } else { min.z },
+            )
+        };
+
+        let sides = [
+            ([0, 2, 6, 4], Vector::new(1.0, 0.0, 0.0)),
+            ([1, 5, 7, 3], Vector::new(-1.0, 0.0, 0.0)),
+            ([0, 4, 5, 1], Vector::new(0.0, 1.0, 0.0)),
+            ([2, 3, 7, 6], Vector::new(0.0, -1.0, 0.0)),
+            ([0, 1, 3, 2], Vector::new(0.0, 0.0, 1.0)),
+            ([4, 6, 7, 5], Vector::new(0.0, 0.0, -1.0)),
+        ];
+
+        let id = rooms.insert(Room::default());
+        let room = rooms.get(id).unwrap().clone();
+        let mut room = room.borrow_mut();
+
+        room.vertices = (0..8).map(corner).collect();
+        room.faces = sides
+            .iter()
+            .map(|(verts, normal)| Face {
+                flags: FaceFlags::empty(),
+                num_verts: 4,
+                portal: None,
+                face_verts: verts.to_vec(),
+                face_uvls: vec![
+                    UVCoord { u: 0.0, v: 0.0 },
+                    UVCoord { u: 1.0, v: 0.0 },
+                    UVCoord { u: 1.0, v: 1.0 },
+                    UVCoord { u: 0.0, v: 1.0 },
+                ],
+                normal: *normal,
+                lightmap: None,
+                special_faces: (),
+                render_frame: (),
+                tmap,
+                light_muliple: 0,
+                min_xyz: min,
+                max_xyz: max,
+            })
+            .collect();
+
+        room.min_xyz = min;
+        room.max_xyz = max;
+    }
+
+    fn covered(thumbnail: &MemBitmap32) -> usize {
+        thumbnail.data().iter().filter(|&&p| p != BACKGROUND).count()
+    }
+
+    #[test]
+    fn thumbnail_overview_test() {
+        crate::test_common::setup();
+
+        let mut rooms = RoomStore::new();
+        assert!(render_level_thumbnail(&rooms, ThumbnailOptions::default()).is_err());
+
+        let min = Vector::new(-50.0, 0.0, 20.0);
+        let max = Vector::new(50.0, 40.0, 120.0);
+        add_box_room(&mut rooms, min, max, None);
+
+        let bounds = level_bounds(&rooms);
+        assert_eq!((bounds.min, bounds.max), (min, max));
+
+        let (position, target) = overview_vantage(&bounds, Fov::Degrees(72.0), 4.0 / 3.0);
+        assert_eq!(target, bounds.center());
+        assert!(!bounds.contains_point(&position));
+
+        let thumbnail = render_level_thumbnail(&rooms, ThumbnailOptions::default()).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (160, 120));
+
+        // The level's in the middle with room to spare around it
+        assert_ne!(thumbnail.pixel(80, 60), Some(BACKGROUND));
+        assert_eq!(thumbnail.pixel(0, 0), Some(BACKGROUND));
+        assert!(covered(&thumbnail) < 160 * 120);
+
+        // Only the three far walls face the camera, the near ones are left out
+        let mut thumbnailer = LevelThumbnailer::new(ThumbnailOptions::default());
+        thumbnailer.render(&rooms).unwrap();
+        assert_eq!(thumbnailer.renderer.polygons_drawn(), 3);
+
+        assert!(LevelThumbnailer::new(ThumbnailOptions { width: 0, ..Default::default() }).render(&rooms).is_err());
+    }
+
+    #[test]
+    fn thumbnail_look_at_test() {
+        crate::test_common::setup();
+
+        let mut rooms = RoomStore::new();
+        add_box_room(&mut rooms, Vector::new(-50.0, -50.0, -50.0), Vector::new(50.0, 50.0, 50.0), Some(3));
+
+        // From inside the room the walls are all around, clipped where they pass the eye. Standing
+        // near the left wall puts it at the edge of the view
+        let options = ThumbnailOptions {
+            width: 32,
+            height: 24,
+            view: ThumbnailView::LookAt { position: Vector::new(-40.0, 0.0, 0.0), target: Vector::new(-40.0, 0.0, 10.0) },
+            ..Default::default()
+        };
+
+        let thumbnail = render_level_thumbnail(&rooms, options).unwrap();
+        assert_eq!(covered(&thumbnail), 32 * 24);
+
+        // The wall straight ahead is square on, brighter than the ones off to the side
+        let gray = |p: Option<u32>| p.unwrap() & 0xFF;
+        assert!(gray(thumbnail.pixel(16, 12)) > gray(thumbnail.pixel(0, 12)));
+
+        // Faces with their texture handed over are mapped with it
+        let red = 0xFF00;
+        let mut thumbnailer = LevelThumbnailer::new(options);
+        thumbnailer.add_texture(3, &GenericBitmap16::new(vec![red; 4], 2, 2));
+
+        let thumbnail = thumbnailer.render(&rooms).unwrap();
+        assert_eq!(thumbnail.pixel(16, 12), Some(pixel_4444_to_32(red)));
+
+        let looking_at_itself = ThumbnailOptions { view: ThumbnailView::LookAt { position: Vector::default(), target: Vector::default() }, ..options };
+        assert!(render_level_thumbnail(&rooms, looking_at_itself).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+/*
+
+Markers
+
+Beacons a player drops to find their way back, each with a line of text they
+typed. Every player gets a handful, dropping one more takes back their
+oldest. Markers show on the automap with their text, and the HUD shows the
+text of the one the player is looking at.
+
+In multiplayer the server tells everyone where a marker went, a marker packet
+for a slot that's already taken moves it rather than adding another.
+
+*/
+
+use std::io::Cursor;
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{
+    automap::{AutomapMarker, AUTOMAP_MARKER_COLOR},
+    prelude::*,
+    room::{Room, RoomId, RoomStore},
+};
+use crate::{
+    common::WeakHandle,
+    net::{end_packet, read_net_string, read_packet_header, start_packet, write_net_string, PacketType, PlayerSlot},
+};
+use vector::Vector;
+
+/// How many markers a player can have out at once
+pub const MAX_MARKERS_PER_PLAYER: usize = 8;
+
+/// Longest text a marker can carry, in bytes
+pub const MAX_MARKER_MESSAGE_LENGTH: usize = 40;
+
+/// Size of the marker object
+pub const MARKER_SIZE: f32 = 2.0;
+
+/// How close to straight ahead a marker has to be for the HUD to show its text, as the cosine of the angle off
+const MARKER_HUD_DOT: f32 = 0.97;
+
+/// How far away the HUD still shows a marker's text
+const MARKER_HUD_DISTANCE: f32 = 200.0;
+
+#[derive(Debug, Clone)]
+pub struct Marker {
+    pub owner: PlayerSlot,
+    /// Which of the owner's markers this is
+    pub slot: u8,
+    pub position: Vector,
+    pub room: WeakHandle<Room>,
+    pub text: String,
+    /// The object standing in the level for this marker
+    pub object: Option<SharedMutRef<Object>>,
+}
+
+impl Marker {
+    fn room_id(&self) -> Option<RoomId> {
+        self.room.with(|room| room.id())
+    }
+
+    /// A marker object for the level to hold, at the marker's position
+    pub fn create_object(&self) -> Object {
+        let mut typedef = ObjectTypeDef::new("Marker", ObjectClass::Marker);
+        typedef.size = MARKER_SIZE;
+
+        let mut object = Object::new(typedef);
+        object.position = self.position;
+        object.last_position = self.position;
+        object.parent_room = self.room.clone();
+        object
+    }
+
+    /// Moves `object` to the marker
+    pub fn teleport(&self, object: &mut Object) {
+        object.position = self.position;
+        object.last_position = self.position;
+        object.parent_room = self.room.clone();
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MarkerList {
+    /// Oldest first
+    markers: Vec<Marker>,
+}
+
+impl MarkerList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Marker> {
+        self.markers.iter()
+    }
+
+    pub fn owned_by(&self, owner: PlayerSlot) -> impl Iterator<Item = &Marker> {
+        self.markers.iter().filter(move |m| m.owner == owner)
+    }
+
+    /// Drops a marker for `owner`, taking back their oldest if they're out of them.
+    /// Returns the marker to send to the other players and make an object for
+    pub fn drop_marker(&mut self, owner: PlayerSlot, position: Vector, room: Option<&SharedMutRef<Room>>, text: &str) -> Result<&Marker> {
+        if text.len() > MAX_MARKER_MESSAGE_LENGTH {
+            return Err(anyhow!("marker text is {} bytes, at most {} fit", text.len(), MAX_MARKER_MESSAGE_LENGTH));
+        }
+
+        let used: Vec<u8> = self.owned_by(owner).map(|m| m.slot).collect();
+
+        let slot = if used.len() < MAX_MARKERS_PER_PLAYER {
+            (0..MAX_MARKERS_PER_PLAYER as u8).find(|s| !used.contains(s)).unwrap()
+        } else {
+            used[0]
+        };
+
+        self.place(Marker {
+            owner,
+            slot,
+            position,
+            room: room.map(WeakHandle::to).unwrap_or_default(),
+            text: text.to_string(),
+            object: None,
+        });
+
+        Ok(self.markers.last().unwrap())
+    }
+
+    /// Puts a marker in, replacing whatever the owner had in that slot.
+    /// The old marker is returned so its object can be taken out of the level
+    pub fn place(&mut self, marker: Marker) -> Option<Marker> {
+        let old = self
+            .markers
+            .iter()
+            .position(|m| m.owner == marker.owner && m.slot == marker.slot)
+            .map(|index| self.markers.remove(index));
+
+        trace!(target: "game", "player {} marker {} placed: {}", marker.owner, marker.slot, marker.text);
+        self.markers.push(marker);
+        old
+    }
+
+    pub fn get_mut(&mut self, owner: PlayerSlot, slot: u8) -> Option<&mut Marker> {
+        self.markers.iter_mut().find(|m| m.owner == owner && m.slot == slot)
+    }
+
+    /// Takes away a player's markers, when they leave the game
+    pub fn remove_owner(&mut self, owner: PlayerSlot) -> Vec<Marker> {
+        let (removed, kept) = std::mem::take(&mut self.markers).into_iter().partition(|m| m.owner == owner);
+        self.markers = kept;
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.markers.clear();
+    }
+
+    /// Where the teleport cheat sends `owner`, their newest marker or failing that anyone's
+    pub fn warp_target(&self, owner: PlayerSlot) -> Option<&Marker> {
+        self.owned_by(owner).last().or_else(|| self.markers.last())
+    }
+
+    /// The marker the HUD should show the text of, the nearest one nearly straight ahead
+    pub fn in_sight(&self, eye: &Vector, forward: &Vector) -> Option<&Marker> {
+        self.markers
+            .iter()
+            .filter_map(|marker| {
+                let to = marker.position - *eye;
+                let distance = Vector::distance(&marker.position, eye);
+
+                if distance > MARKER_HUD_DISTANCE {
+                    return None;
+                }
+
+                (distance <= MARKER_SIZE || (to / distance) * *forward >= MARKER_HUD_DOT).then_some((marker, distance))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(marker, _)| marker)
+    }
+
+    pub fn automap_markers(&self) -> Vec<AutomapMarker> {
+        self.markers
+            .iter()
+            .map(|marker| AutomapMarker {
+                position: marker.position,
+                color: AUTOMAP_MARKER_COLOR,
+                label: Some(marker.text.clone()),
+            })
+            .collect()
+    }
+}
+
+pub fn encode_marker(marker: &Marker) -> Result<Vec<u8>> {
+    let mut cursor = start_packet(PacketType::MarkerPlace);
+    cursor.write_u8(marker.owner)?;
+    cursor.write_u8(marker.slot)?;
+    cursor.write_f32::<LittleEndian>(marker.position.x)?;
+    cursor.write_f32::<LittleEndian>(marker.position.y)?;
+    cursor.write_f32::<LittleEndian>(marker.position.z)?;
+    cursor.write_i32::<LittleEndian>(marker.room_id().map_or(-1, |id| id as i32))?;
+    write_net_string(&mut cursor, &marker.text)?;
+    end_packet(cursor)
+}
+
+/// Reads a marker packet, looking its room up in `rooms`
+pub fn decode_marker(data: &[u8], rooms: &RoomStore) -> Result<Marker> {
+    let (packet_type, mut cursor): (_, Cursor<&[u8]>) = read_packet_header(data)?;
+
+    if packet_type != PacketType::MarkerPlace {
+        return Err(anyhow!("{:?} is not a marker packet", packet_type));
+    }
+
+    let owner = cursor.read_u8()?;
+    let slot = cursor.read_u8()?;
+
+    if slot as usize >= MAX_MARKERS_PER_PLAYER {
+        return Err(anyhow!("invalid marker slot {}", slot));
+    }
+
+    let position = Vector {
+        x: cursor.read_f32::<LittleEndian>()?,
+        y: cursor.read_f32::<LittleEndian>()?,
+        z: cursor.read_f32::<LittleEndian>()?,
+    };
+
+    let room = match cursor.read_i32::<LittleEndian>()? {
+        -1 => WeakHandle::new(),
+        id => WeakHandle::to(rooms.get(id as RoomId).ok_or_else(|| anyhow!("marker in unknown room {}", id))?),
+    };
+
+    let mut text = read_net_string(&mut cursor)?;
+    text.truncate(MAX_MARKER_MESSAGE_LENGTH);
+
+    Ok(Marker { owner, slot, position, room, text, object: None })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn at(x: f32) -> Vector {
+        Vector { x, y: 0.0, z: 0.0 }
+    }
+
+    #[test]
+    fn marker_list_test() {
+        crate::test_common::setup();
+
+        let mut markers = MarkerList::new();
+
+        for i in 0..MAX_MARKERS_PER_PLAYER {
+            markers.drop_marker(1, at(i as f32 * 10.0), None, &format!("marker {}", i)).unwrap();
+        }
+        markers.drop_marker(2, at(-50.0), None, "theirs").unwrap();
+
+        // One too many takes back the oldest
+        let marker = markers.drop_marker(1, at(500.0), None, "newest").unwrap();
+        assert_eq!(marker.slot, 0);
+        assert_eq!(markers.owned_by(1).count(), MAX_MARKERS_PER_PLAYER);
+        assert!(markers.owned_by(1).all(|m| m.text != "marker 0"));
+        assert!(markers.drop_marker(1, at(0.0), None, &"x".repeat(MAX_MARKER_MESSAGE_LENGTH + 1)).is_err());
+
+        assert_eq!(markers.warp_target(1).unwrap().text, "newest");
+        assert_eq!(markers.warp_target(3).unwrap().text, "newest");
+
+        // The nearest one ahead, the one behind doesn't count
+        let ahead = markers.in_sight(&at(5.0), &at(1.0)).unwrap();
+        assert_eq!(ahead.text, "marker 1");
+        assert_eq!(markers.in_sight(&at(-60.0), &at(-1.0)).map(|m| m.text.as_str()), None);
+
+        let mut ship = ahead.create_object();
+        assert_eq!(ship.typedef().class, ObjectClass::Marker);
+        markers.warp_target(1).unwrap().teleport(&mut ship);
+        assert_eq!(ship.position, at(500.0));
+
+        assert_eq!(markers.automap_markers().len(), MAX_MARKERS_PER_PLAYER + 1);
+        assert_eq!(markers.remove_owner(2).len(), 1);
+    }
+
+    #[test]
+    fn marker_packet_test() {
+        crate::test_common::setup();
+
+        let mut rooms = RoomStore::new();
+        let id = rooms.insert(Room::default());
+        let room = rooms.get(id).unwrap().clone();
+
+        let mut markers = MarkerList::new();
+        let marker = markers.drop_marker(3, at(12.5), Some(&room), "over here").unwrap();
+        let data = encode_marker(marker).unwrap();
+
+        let received = decode_marker(&data, &rooms).unwrap();
+        assert_eq!((received.owner, received.slot, received.position), (3, 0, at(12.5)));
+        assert!(received.room.is(&room));
+        assert_eq!(received.text, "over here");
+
+        // Placing it again elsewhere moves it
+        let mut remote = MarkerList::new();
+        assert!(remote.place(received.clone()).is_none());
+        assert!(remote.place(Marker { position: at(20.0), ..received }).is_some());
+        assert_eq!(remote.iter().count(), 1);
+
+        assert!(decode_marker(&data, &RoomStore::new()).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{
+    player::{InventoryItem, Player, INITIAL_ENERGY},
+    prelude::*,
+    ship::MAX_PLAYER_WEAPONS,
+};
+
+/// What every ship starts a mission with, just the laser
+pub const STARTING_WEAPON_FLAGS: u32 = 1;
+
+bitflags! {
+    /// What a level takes away from the player on the way in, set per level in the mission file
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct CarryOverFlags: u8 {
+        const RESET_WEAPONS = 1 << 0;
+        const RESET_AMMO = 1 << 1;
+        const RESET_INVENTORY = 1 << 2;
+        const RESET_ENERGY = 1 << 3;
+        const RESET_ALL = Self::RESET_WEAPONS.bits() | Self::RESET_AMMO.bits() | Self::RESET_INVENTORY.bits() | Self::RESET_ENERGY.bits();
+    }
+}
+
+/// What the player is carrying between levels
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerLoadout {
+    pub weapon_flags: u32,
+    pub laser_level: u8,
+    pub weapon_ammo: [u16; MAX_PLAYER_WEAPONS],
+    pub inventory: Vec<InventoryItem>,
+    pub energy: f32,
+}
+
+impl Default for PlayerLoadout {
+    fn default() -> Self {
+        Self {
+            weapon_flags: STARTING_WEAPON_FLAGS,
+            laser_level: 0,
+            weapon_ammo: [0; MAX_PLAYER_WEAPONS],
+            inventory: Vec::new(),
+            energy: INITIAL_ENERGY,
+        }
+    }
+}
+
+impl PlayerLoadout {
+    pub fn capture(player: &Player) -> Self {
+        Self {
+            weapon_flags: player.weapon_flags,
+            laser_level: player.laser_level,
+            weapon_ammo: player.weapon_ammo,
+            inventory: player.inventory.clone(),
+            energy: player.energy,
+        }
+    }
+
+    /// Gives the player this loadout, with whatever `reset` covers back to the mission start
+    pub fn apply(&self, player: &mut Player, reset: CarryOverFlags) {
+        let fresh = PlayerLoadout::default();
+
+        let weapons = if reset.contains(CarryOverFlags::RESET_WEAPONS) { &fresh } else { self };
+        player.weapon_flags = weapons.weapon_flags;
+        player.laser_level = weapons.laser_level;
+
+        let ammo = if reset.contains(CarryOverFlags::RESET_AMMO) { &fresh } else { self };
+        player.weapon_ammo = ammo.weapon_ammo;
+
+        let inventory = if reset.contains(CarryOverFlags::RESET_INVENTORY) { &fresh } else { self };
+        player.inventory = inventory.inventory.clone();
+
+        let energy = if reset.contains(CarryOverFlags::RESET_ENERGY) { &fresh } else { self };
+        player.energy = energy.energy;
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<LittleEndian>(self.weapon_flags)?;
+        writer.write_u8(self.laser_level)?;
+
+        for ammo in &self.weapon_ammo {
+            writer.write_u16::<LittleEndian>(*ammo)?;
+        }
+
+        writer.write_u16::<LittleEndian>(self.inventory.len() as u16)?;
+
+        for item in &self.inventory {
+            writer.write_u32::<LittleEndian>(item.id as u32)?;
+            writer.write_u32::<LittleEndian>(item.count)?;
+        }
+
+        writer.write_f32::<LittleEndian>(self.energy)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let weapon_flags = reader.read_u32::<LittleEndian>()?;
+        let laser_level = reader.read_u8()?;
+
+        let mut weapon_ammo = [0; MAX_PLAYER_WEAPONS];
+        for ammo in weapon_ammo.iter_mut() {
+            *ammo = reader.read_u16::<LittleEndian>()?;
+        }
+
+        let count = reader.read_u16::<LittleEndian>()? as usize;
+        let mut inventory = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            inventory.push(InventoryItem {
+                id: reader.read_u32::<LittleEndian>()? as usize,
+                count: reader.read_u32::<LittleEndian>()?,
+            });
+        }
+
+        Ok(Self {
+            weapon_flags,
+            laser_level,
+            weapon_ammo,
+            inventory,
+            energy: reader.read_f32::<LittleEndian>()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissionLevel {
+    pub filename: D3String,
+    pub reset: CarryOverFlags,
+}
+
+/// A run of levels played one after another, carrying the player's gear between them
+#[derive(Debug, Clone)]
+pub struct Mission {
+    pub name: D3String,
+    pub levels: Vec<MissionLevel>,
+    current_level: Option<usize>,
+    /// What the player had at the end of the last level
+    carried: PlayerLoadout,
+}
+
+impl Mission {
+    pub fn new(name: D3String, levels: Vec<MissionLevel>) -> Self {
+        Self {
+            name,
+            levels,
+            current_level: None,
+            carried: PlayerLoadout::default(),
+        }
+    }
+
+    pub fn current_level(&self) -> Option<usize> {
+        self.current_level
+    }
+
+    pub fn carried(&self) -> &PlayerLoadout {
+        &self.carried
+    }
+
+    /// Sets the player up for a level, the first level of a mission always starts fresh
+    pub fn start_level(&mut self, index: usize, player: &mut Player) -> Result<()> {
+        let Some(level) = self.levels.get(index) else {
+            return Err(anyhow!("mission {} has no level {}", self.name, index + 1));
+        };
+
+        let reset = if index == 0 { CarryOverFlags::RESET_ALL } else { level.reset };
+        self.carried.apply(player, reset);
+        self.current_level = Some(index);
+
+        Ok(())
+    }
+
+    /// Holds on to the player's gear for the next level, returning its index if there is one
+    pub fn end_level(&mut self, player: &Player) -> Option<usize> {
+        self.carried = PlayerLoadout::capture(player);
+
+        let next = self.current_level.map(|i| i + 1).unwrap_or(0);
+        (next < self.levels.len()).then_some(next)
+    }
+
+    /// Writes the mission progress out for a savegame
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_i16::<LittleEndian>(self.current_level.map(|i| i as i16).unwrap_or(-1))?;
+        self.carried.write_to(writer)
+    }
+
+    /// Restores progress from a savegame into a mission loaded from the same mission file
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> Result<()> {
+        let level = reader.read_i16::<LittleEndian>()?;
+
+        if level >= self.levels.len() as i16 {
+            return Err(anyhow!("saved level {} is not in mission {}", level + 1, self.name));
+        }
+
+        self.current_level = if level < 0 { None } else { Some(level as usize) };
+        self.carried = PlayerLoadout::read_from(reader)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn mission() -> Mission {
+        Mission::new(
+            D3String::from("Test Mission"),
+            vec![
+                MissionLevel {
+                    filename: D3String::from("level1.d3l"),
+                    reset: CarryOverFlags::empty(),
+                },
+                MissionLevel {
+                    filename: D3String::from("level2.d3l"),
+                    reset: CarryOverFlags::empty(),
+                },
+                MissionLevel {
+                    filename: D3String::from("level3.d3l"),
+                    reset: CarryOverFlags::RESET_WEAPONS | CarryOverFlags::RESET_AMMO,
+                },
+            ],
+        )
+    }
+
+    fn armed(player: &mut Player) {
+        player.weapon_flags = 0b1011;
+        player.laser_level = 2;
+        player.weapon_ammo[10] = 40;
+        player.add_item(7, 1);
+        player.energy = 150.0;
+    }
+
+    #[test]
+    fn mission_carry_over_test() {
+        crate::test_common::setup();
+
+        let mut mission = mission();
+        let mut player = Player::default();
+        armed(&mut player);
+
+        // Whatever was left over from before, a mission starts with just the laser
+        mission.start_level(0, &mut player).unwrap();
+        assert_eq!(player.weapon_flags, STARTING_WEAPON_FLAGS);
+        assert!(player.inventory.is_empty());
+
+        armed(&mut player);
+        assert_eq!(mission.end_level(&player), Some(1));
+
+        let mut player = Player::default();
+        mission.start_level(1, &mut player).unwrap();
+        assert_eq!(player.weapon_flags, 0b1011);
+        assert_eq!(player.weapon_ammo[10], 40);
+        assert_eq!(player.inventory.len(), 1);
+
+        // Level 3 takes the guns but leaves the rest
+        assert_eq!(mission.end_level(&player), Some(2));
+        mission.start_level(2, &mut player).unwrap();
+        assert_eq!(player.weapon_flags, STARTING_WEAPON_FLAGS);
+        assert_eq!(player.weapon_ammo[10], 0);
+        assert_eq!(player.inventory.len(), 1);
+        assert_eq!(player.energy, 150.0);
+
+        assert_eq!(mission.end_level(&player), None);
+        assert!(mission.start_level(3, &mut player).is_err());
+    }
+
+    #[test]
+    fn mission_save_test() {
+        crate::test_common::setup();
+
+        let mut mission = mission();
+        let mut player = Player::default();
+        mission.start_level(0, &mut player).unwrap();
+        armed(&mut player);
+        mission.end_level(&player);
+
+        let mut cursor = Cursor::new(Vec::new());
+        mission.write_to(&mut cursor).unwrap();
+        cursor.set_position(0);
+
+        let mut loaded = self::mission();
+        loaded.read_from(&mut cursor).unwrap();
+        assert_eq!(loaded.current_level(), Some(0));
+        assert_eq!(loaded.carried(), mission.carried());
+    }
+}
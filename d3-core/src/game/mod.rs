@@ -6,6 +6,7 @@ pub mod context;
 pub mod prelude;
 pub mod ambient_life;
 pub mod object;
+pub mod object_query;
 pub mod object_physics;
 pub mod ai;
 pub mod weapon;
@@ -16,13 +17,60 @@ pub mod room;
 pub mod geometry;
 pub mod door;
 pub mod scripting;
+pub mod events;
+pub mod cheats;
+pub mod automap;
+pub mod marker;
+pub mod respawn;
+pub mod team;
+pub mod level_goals;
+pub mod player;
+pub mod energy_center;
+pub mod countermeasure;
+pub mod turret;
+pub mod squad;
 pub mod audio;
+pub mod sound_occlusion;
+pub mod sound_table;
+pub mod voice_manager;
+pub mod audio_stream;
+pub mod audio_mixer;
+pub mod audio_bus;
+pub mod caption;
 pub mod core;
+pub mod timestep;
+pub mod game_time;
 pub mod node;
 pub mod terrain;
+pub mod terrain_edit;
 pub mod weather;
 pub mod physics;
+pub mod portal_visibility;
 pub mod visual_effects;
+pub mod debug_dump;
+pub mod scorch;
+pub mod decal;
+pub mod surface_effects;
+pub mod shadow;
+pub mod exploder;
+pub mod area_damage;
+pub mod ship;
+pub mod observer;
+pub mod statistics;
+pub mod robot_animation;
+pub mod boss;
+pub mod thief;
+pub mod weapon_render;
+pub mod mission;
+pub mod spew;
+pub mod force_field;
+pub mod platform;
+pub mod viewer;
+pub mod external_room;
+pub mod terrain_texture;
+pub mod sky;
+pub mod level_thumbnail;
+pub mod aim_assist;
 
 pub enum RegionRef {
     Room(SharedMutRef<Room>),
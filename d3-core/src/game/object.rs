@@ -2,7 +2,7 @@ use super::{object_dynamic_behavior::DynBehaviorTable, prelude::*};
 
 use core::{any::Any, cell::RefCell, marker::PhantomData, ops::Range};
 use std::{collections::{HashMap, HashSet}, rc::{Rc, Weak}};
-use crate::{graphics::lightmap::LightMap16, math::{matrix::Matrix, vector::Vector}, PAGENAME_LEN};
+use crate::{common::WeakHandle, graphics::lightmap::LightMap16, math::{bounds::Aabb, matrix::Matrix, vector::Vector}, PAGENAME_LEN};
 
 use super::object_static_behavior::BehaviorTable;
 
@@ -42,6 +42,42 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// What each machine in a multiplayer game works out for itself instead of being told by the server.
+    /// Anything not listed is the server's to decide and gets sent
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct NetSimFlags: u8 {
+        const NONE = 0;
+        /// Movement is simulated locally once created, debris tumbling about doesn't need to match
+        const CLIENT_MOVEMENT = 0x01;
+        /// The object spins or bobs for show, its orientation is local
+        const CLIENT_ORIENTATION = 0x02;
+        /// Dies on its own when its life runs out rather than when the server says
+        const CLIENT_LIFETIME = 0x04;
+    }
+}
+
+impl NetSimFlags {
+    /// What objects of a class work out for themselves
+    pub fn for_class(class: ObjectClass) -> Self {
+        match class {
+            ObjectClass::Fireball
+            | ObjectClass::Debris
+            | ObjectClass::Shard
+            | ObjectClass::Splinter
+            | ObjectClass::Particle
+            | ObjectClass::Shockwave => Self::CLIENT_MOVEMENT | Self::CLIENT_ORIENTATION | Self::CLIENT_LIFETIME,
+            ObjectClass::Powerup => Self::CLIENT_ORIENTATION,
+            _ => Self::NONE,
+        }
+    }
+
+    /// Nothing about the object is the server's, it's never sent after being created
+    pub fn is_client_only(&self) -> bool {
+        self.contains(Self::CLIENT_MOVEMENT | Self::CLIENT_ORIENTATION | Self::CLIENT_LIFETIME)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ObjectTypeDef {
     pub name: D3String,
@@ -52,6 +88,19 @@ pub struct ObjectTypeDef {
     pub behavior: BehaviorTable
 }
 
+impl ObjectTypeDef {
+    pub fn new(name: &str, class: ObjectClass) -> Self {
+        Self {
+            name: D3String::from(name.to_string()),
+            size: 0.0,
+            flags: BehaviorFlags::NONE,
+            score: 0,
+            class,
+            behavior: BehaviorTable::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, GameType)]
 pub struct Object {
     typedef: ObjectTypeDef,
@@ -66,7 +115,9 @@ pub struct Object {
 
     pub position: Vector,
     pub orientation: Matrix,
+    /// Where the object was at the start of the last physics tick, for interpolation
     pub last_position: Vector,
+    pub last_orientation: Matrix,
 
     pub renderframe: u16,
 
@@ -83,8 +134,8 @@ pub struct Object {
     pub lifetime: f32,
 
     // TODO: Attachment stuff
-    pub link_prev_obj: Option<SharedMutRef<Object>>,
-    pub link_next_obj: Option<SharedMutRef<Object>>,
+    pub link_prev_obj: WeakHandle<Object>,
+    pub link_next_obj: WeakHandle<Object>,
 
     pub weapon_fire_flags: (),
 
@@ -106,14 +157,119 @@ pub struct Object {
     // because some people are incapable of commented their code.
     pub position_counter: u16,
 
-    pub parent_room: Weak<RefCell<super::room::Room>>
+    pub parent_room: WeakHandle<super::room::Room>,
+
+    /// Which parts of the object clients simulate themselves in multiplayer
+    pub net_sim: NetSimFlags,
 }
 
 impl Object {
-    pub fn new() -> Self { todo!() }
+    /// A fresh object of type `typedef`, at the origin and behaving like nothing yet
+    pub fn new(typedef: ObjectTypeDef) -> Self {
+        let (class, typedef_flags) = (typedef.class, typedef.flags);
+
+        Self {
+            name: typedef.name.clone(),
+            size: typedef.size,
+            typedef,
+            dyn_behavior: DynBehaviorTable::default(),
+            control_type: (),
+            render_type: (),
+            lighting_type: (),
+            room_num: Rc::new(()),
+            position: Vector::ZERO,
+            orientation: Matrix::IDENTITY,
+            last_position: Vector::ZERO,
+            last_orientation: Matrix::IDENTITY,
+            renderframe: 0,
+            wall_sphere_offset: Vector::ZERO,
+            anim_sphere_offset: Vector::ZERO,
+            shields: 0.0,
+            contains: HashMap::new(),
+            creation_time: 0.0,
+            lifeleft: 0.0,
+            lifetime: 0.0,
+            link_prev_obj: WeakHandle::new(),
+            link_next_obj: WeakHandle::new(),
+            weapon_fire_flags: (),
+            min_xzy: Vector::ZERO,
+            max_xzy: Vector::ZERO,
+            change_flags: 0,
+            generic_nonvis_flags: 0,
+            generic_sent_nonvis: 0,
+            lightmap: LightMap16::new(&[], 0, 0),
+            position_counter: 0,
+            parent_room: WeakHandle::new(),
+            net_sim: if typedef_flags.contains(BehaviorFlags::AMBIENT_OBJECT) {
+                NetSimFlags::all()
+            } else {
+                NetSimFlags::for_class(class)
+            },
+        }
+    }
+
     pub fn typedef(&self) -> &ObjectTypeDef {
         &self.typedef
     }
+
+    /// Collision bounds
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(self.min_xzy, self.max_xzy)
+    }
+
+    /// Call before each physics tick so the renderer can blend from where the object was
+    pub fn store_previous_transform(&mut self) {
+        self.last_position = self.position;
+        self.last_orientation = self.orientation;
+    }
+
+    /// Where to draw the object `alpha` (0 to 1) of the way between the last two physics ticks
+    pub fn interpolated_transform(&self, alpha: f32) -> Transform {
+        Transform {
+            position: self.last_position,
+            orientation: self.last_orientation,
+        }
+        .interpolate(
+            &Transform {
+                position: self.position,
+                orientation: self.orientation,
+            },
+            alpha,
+        )
+    }
+}
+
+/// A position and orientation
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub position: Vector,
+    pub orientation: Matrix,
+}
+
+impl Transform {
+    /// Blends toward `next`.  The orientation is blended a row at a time then rebuilt so it
+    /// stays a rotation.
+    pub fn interpolate(&self, next: &Transform, alpha: f32) -> Transform {
+        let alpha = alpha.clamp(0.0, 1.0);
+
+        if alpha <= 0.0 {
+            return *self;
+        }
+
+        if alpha >= 1.0 {
+            return *next;
+        }
+
+        let lerp = |a: &Vector, b: &Vector| *a + (*b - *a) * alpha;
+
+        let forward = lerp(&self.orientation.forward, &next.orientation.forward);
+        let up = lerp(&self.orientation.up, &next.orientation.up);
+
+        Transform {
+            position: lerp(&self.position, &next.position),
+            orientation: Matrix::from_vector(Some(&forward), Some(&up), None),
+        }
+    }
 }
 
 
@@ -1,9 +1,9 @@
 use std::rc::Rc;
-use crate::{graphics::{bitmap::Bitmap16, ddgr_color}, math::vector::Vector};
+use crate::{common::WeakHandle, config::DetailSettings, graphics::{bitmap::Bitmap16, ddgr_color}, math::vector::Vector};
 
-use super::{effects::*, object::Object, object_static_behavior::{Autonomous, Light, Physical}, weapon::{DynamicWeaponBatteryFlags, MAX_TURRETS}};
+use super::{effects::*, object::Object, object_static_behavior::{Autonomous, Drawable, Light, ModelDetail, Physical}, weapon::{DynamicWeaponBatteryFlags, MAX_TURRETS}};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DynBehaviorTable {
     pub movement: Option<MovementType>,
     pub weapon_battery: Option<DynamicWeaponBattery>,
@@ -25,12 +25,12 @@ pub struct DynBehaviorTable {
 
 #[derive(Debug, Clone)]
 pub struct ShockwaveEmitter {
-    pub damaged: Vec<Rc<Object>>
+    pub damaged: Vec<WeakHandle<Object>>
 }
 
 #[derive(Debug, Clone)]
 pub struct Attachment {
-    pub parent: Rc<Object>,
+    pub parent: WeakHandle<Object>,
     pub forward: Vector,
     pub up: Vector,
     pub position: Vector
@@ -74,12 +74,12 @@ pub struct Explosive {
 
 #[derive(Debug, Clone)]
 pub struct LaserEmitter {
-    pub parent: Rc<Object>,
+    pub parent: WeakHandle<Object>,
     pub src_gunpoint: Rc<()>,
 
     /// For persistent weapons (survive object collision), object it most recently hit.
     pub last_hit_handle: (),
-    pub tracking: Option<Rc<Object>>,
+    pub tracking: WeakHandle<Object>,
 
     /// Last track time (see if an object is visible)
     pub last_track_time: f32,
@@ -114,7 +114,7 @@ pub struct Powerup {
 
 #[derive(Debug, Clone)]
 pub struct Splinter {
-    pub child: Rc<Object>,
+    pub child: WeakHandle<Object>,
     pub facenum: i16,
     pub verticies: Vec<Vector>,
     pub center: Vector
@@ -132,7 +132,7 @@ pub struct DeathEmitter {
     /// How long until object dies
     pub delay_time: f32,
     /// The player who wille this object, or -1 if not a player
-    pub killer_player: Option<Rc<Object>>,
+    pub killer_player: WeakHandle<Object>,
 
     pub last_spark_time: f32,
     pub last_fireball_time: f32,
@@ -203,7 +203,17 @@ pub struct DrawablePolyModel {
     pub animation: CustomAnimationType,
     pub multi_turret: MultiTurrent,
     pub child_flags: (),
-    pub tmap_override: Option<()>
+    pub tmap_override: Option<()>,
+    /// Which of the models was drawn last frame
+    pub detail: ModelDetail,
+}
+
+impl DrawablePolyModel {
+    /// Picks this frame's model detail for an object `distance` away from the viewer
+    pub fn update_detail<T>(&mut self, drawable: &Drawable<T>, distance: f32, settings: &DetailSettings) -> ModelDetail {
+        self.detail = drawable.select_detail(distance, settings.object_complexity, self.detail);
+        self.detail
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,184 @@
+/*
+
+Object queries
+
+Finding objects by what they are and where they are, chained like iterator
+adapters: `context.objects.query().of_class(ObjectClass::Robot).within(&center, 50.0)`.
+Start from a room's own object list when the room is known, it's much shorter
+than the level's. Results are objects, `handles` turns them into weak handles
+for keeping around.
+
+*/
+
+use super::{
+    context::BindingStore,
+    prelude::*,
+    room::Room,
+};
+use crate::common::WeakHandle;
+use vector::Vector;
+
+/// What a query needs to know about an object
+pub trait QueryObject {
+    fn class(&self) -> ObjectClass;
+
+    fn position(&self) -> Vector;
+
+    fn size(&self) -> f32;
+
+    /// Id of the room it's in
+    fn room_id(&self) -> Option<usize>;
+}
+
+impl QueryObject for Object {
+    fn class(&self) -> ObjectClass {
+        self.typedef().class
+    }
+
+    fn position(&self) -> Vector {
+        self.position
+    }
+
+    fn size(&self) -> f32 {
+        self.size
+    }
+
+    fn room_id(&self) -> Option<usize> {
+        self.parent_room.with(|room| room.id())
+    }
+}
+
+/// Objects narrowed down step by step, iterate it for the objects that made it through
+pub struct ObjectQuery<I> {
+    iter: I,
+}
+
+impl<'a, T, I> ObjectQuery<I>
+where
+    T: QueryObject + 'a,
+    I: Iterator<Item = &'a SharedMutRef<T>>,
+{
+    pub fn new(iter: I) -> Self {
+        Self { iter }
+    }
+
+    /// Only objects `predicate` says yes to
+    pub fn matching(self, predicate: impl Fn(&T) -> bool) -> ObjectQuery<impl Iterator<Item = &'a SharedMutRef<T>>> {
+        ObjectQuery::new(self.iter.filter(move |object| predicate(&object.borrow())))
+    }
+
+    pub fn of_class(self, class: ObjectClass) -> ObjectQuery<impl Iterator<Item = &'a SharedMutRef<T>>> {
+        self.matching(move |object| object.class() == class)
+    }
+
+    pub fn in_room(self, room_id: usize) -> ObjectQuery<impl Iterator<Item = &'a SharedMutRef<T>>> {
+        self.matching(move |object| object.room_id() == Some(room_id))
+    }
+
+    /// Objects any part of which is within `radius` of `center`
+    pub fn within(self, center: &Vector, radius: f32) -> ObjectQuery<impl Iterator<Item = &'a SharedMutRef<T>>> {
+        let center = *center;
+        self.matching(move |object| Vector::distance(&object.position(), &center) - object.size() <= radius)
+    }
+
+    /// The closest object by center, with its distance
+    pub fn nearest(self, center: &Vector) -> Option<(&'a SharedMutRef<T>, f32)> {
+        self.iter
+            .map(|object| (object, Vector::distance(&object.borrow().position(), center)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    pub fn handles(self) -> impl Iterator<Item = WeakHandle<T>> {
+        self.iter.map(WeakHandle::to)
+    }
+}
+
+impl<'a, T: 'a, I: Iterator<Item = &'a SharedMutRef<T>>> Iterator for ObjectQuery<I> {
+    type Item = &'a SharedMutRef<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+impl BindingStore<Object> {
+    /// Every object in the level
+    pub fn query(&self) -> ObjectQuery<impl Iterator<Item = &SharedMutRef<Object>>> {
+        ObjectQuery::new(self.bindings().iter().map(|binding| binding.inner()))
+    }
+}
+
+impl Room {
+    /// The objects in this room, faster than filtering the whole level by room
+    pub fn query_objects(&self) -> ObjectQuery<impl Iterator<Item = &SharedMutRef<Object>>> {
+        ObjectQuery::new(self.objects.iter())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestObject {
+        class: ObjectClass,
+        position: Vector,
+        room: usize,
+    }
+
+    impl QueryObject for TestObject {
+        fn class(&self) -> ObjectClass {
+            self.class
+        }
+
+        fn position(&self) -> Vector {
+            self.position
+        }
+
+        fn size(&self) -> f32 {
+            2.0
+        }
+
+        fn room_id(&self) -> Option<usize> {
+            Some(self.room)
+        }
+    }
+
+    fn object(class: ObjectClass, x: f32, room: usize) -> SharedMutRef<TestObject> {
+        new_shared_mut_ref(TestObject {
+            class,
+            position: Vector { x, y: 0.0, z: 0.0 },
+            room,
+        })
+    }
+
+    #[test]
+    fn object_query_test() {
+        crate::test_common::setup();
+
+        let objects = vec![
+            object(ObjectClass::Robot, 0.0, 1),
+            object(ObjectClass::Robot, 11.0, 1),
+            object(ObjectClass::Powerup, 5.0, 1),
+            object(ObjectClass::Robot, 20.0, 2),
+        ];
+
+        let origin = Vector::default();
+        let query = || ObjectQuery::new(objects.iter());
+
+        assert_eq!(query().of_class(ObjectClass::Robot).count(), 3);
+        assert_eq!(query().of_class(ObjectClass::Robot).in_room(1).count(), 2);
+
+        // Measured to the edge, the robot 11 away reaches 9
+        let near: Vec<_> = query().of_class(ObjectClass::Robot).within(&origin, 9.0).collect();
+        assert_eq!(near.len(), 2);
+        assert!(Rc::ptr_eq(near[1], &objects[1]));
+
+        let (nearest, distance) = query().of_class(ObjectClass::Powerup).nearest(&Vector { x: 8.0, y: 0.0, z: 0.0 }).unwrap();
+        assert!(Rc::ptr_eq(nearest, &objects[2]));
+        assert_eq!(distance, 3.0);
+
+        let handles: Vec<_> = query().in_room(2).handles().collect();
+        assert!(handles.len() == 1 && handles[0].is(&objects[3]));
+    }
+}
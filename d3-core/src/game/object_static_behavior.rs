@@ -58,7 +58,7 @@ use super::weapon::{
 //     };
 // }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct BehaviorTable {
     pub drawable: Option<Drawable<Rc<dyn Any>>>,
     pub light: Option<Light>,
@@ -83,6 +83,62 @@ pub struct Drawable<T> {
     pub lod_distance_l: f32,
 }
 
+/// How far past a switch distance a model has to get before it changes detail, as a fraction of the distance
+pub const LOD_HYSTERESIS: f32 = 0.1;
+
+/// Which of an object's models gets drawn, ordered from most to least detailed
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum ModelDetail {
+    #[default]
+    High,
+    Medium,
+    Low,
+}
+
+/// Scales the switch distances by the object complexity detail setting, higher keeps detail further out
+pub fn lod_distance_scale(object_complexity: u8) -> f32 {
+    match object_complexity {
+        0 => 0.5,
+        1 => 1.0,
+        2 => 1.5,
+        _ => 2.0,
+    }
+}
+
+impl<T> Drawable<T> {
+    pub fn model(&self, detail: ModelDetail) -> &Rc<T> {
+        match detail {
+            ModelDetail::High => &self.resolution_h,
+            ModelDetail::Medium => &self.resolution_m,
+            ModelDetail::Low => &self.resolution_l,
+        }
+    }
+
+    /// The detail to draw at `distance` from the viewer. The switch points are pushed away from
+    /// `current` so an object sitting right on one doesn't pop back and forth.
+    /// A switch distance of 0 means the object has no model at that detail.
+    pub fn select_detail(&self, distance: f32, object_complexity: u8, current: ModelDetail) -> ModelDetail {
+        let scale = lod_distance_scale(object_complexity);
+
+        let past = |switch: f32, coarser: ModelDetail| {
+            if switch <= 0.0 {
+                return false;
+            }
+
+            let band = if current >= coarser { 1.0 - LOD_HYSTERESIS } else { 1.0 + LOD_HYSTERESIS };
+            distance > switch * scale * band
+        };
+
+        if past(self.lod_distance_l, ModelDetail::Low) {
+            ModelDetail::Low
+        } else if past(self.lod_distance_m, ModelDetail::Medium) {
+            ModelDetail::Medium
+        } else {
+            ModelDetail::High
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Light {
     pub flags: i32,
@@ -127,6 +183,26 @@ pub struct AnimationEntry {
     sound_index: usize, // TODO
 }
 
+impl AnimationEntry {
+    pub fn new(range: Range<u16>, spc: f32) -> Self {
+        Self {
+            range,
+            spc,
+            sound_index: 0,
+        }
+    }
+
+    /// Keyframes the animation runs through
+    pub fn range(&self) -> &Range<u16> {
+        &self.range
+    }
+
+    /// Seconds to play the range once
+    pub fn spc(&self) -> f32 {
+        self.spc
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Multiplayer {
     pub respawn: f32,
@@ -137,6 +213,18 @@ pub struct Animated {
     entries: Box<[AnimationEntry]>,
 }
 
+impl Animated {
+    pub fn new(entries: Vec<AnimationEntry>) -> Self {
+        Self {
+            entries: entries.into_boxed_slice(),
+        }
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&AnimationEntry> {
+        self.entries.get(index)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Scripted {
     name: String,
@@ -452,3 +540,42 @@ pub struct Autonomous {
     pub biased_flight_min: f32,
     pub biased_flight_max: f32,
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn drawable() -> Drawable<u8> {
+        Drawable {
+            resolution_h: Rc::new(0),
+            resolution_m: Rc::new(1),
+            resolution_l: Rc::new(2),
+            lod_distance_m: 100.0,
+            lod_distance_l: 200.0,
+        }
+    }
+
+    #[test]
+    fn model_lod_test() {
+        crate::test_common::setup();
+
+        let drawable = drawable();
+        assert_eq!(drawable.select_detail(50.0, 1, ModelDetail::High), ModelDetail::High);
+        assert_eq!(drawable.select_detail(150.0, 1, ModelDetail::High), ModelDetail::Medium);
+        assert_eq!(drawable.select_detail(500.0, 1, ModelDetail::High), ModelDetail::Low);
+        assert_eq!(**drawable.model(ModelDetail::Low), 2);
+
+        // Hovering around the switch distance holds on to whatever it was
+        assert_eq!(drawable.select_detail(105.0, 1, ModelDetail::High), ModelDetail::High);
+        assert_eq!(drawable.select_detail(95.0, 1, ModelDetail::Medium), ModelDetail::Medium);
+        assert_eq!(drawable.select_detail(115.0, 1, ModelDetail::High), ModelDetail::Medium);
+        assert_eq!(drawable.select_detail(85.0, 1, ModelDetail::Medium), ModelDetail::High);
+
+        // More complexity keeps the detail further out
+        assert_eq!(drawable.select_detail(150.0, 3, ModelDetail::High), ModelDetail::High);
+
+        // No low detail model
+        let drawable = Drawable { lod_distance_l: 0.0, ..drawable };
+        assert_eq!(drawable.select_detail(5000.0, 1, ModelDetail::Medium), ModelDetail::Medium);
+    }
+}
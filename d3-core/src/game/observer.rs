@@ -0,0 +1,302 @@
+use angle::{Angle, EulerAngle};
+use matrix::Matrix;
+use vector::Vector;
+
+use crate::graphics::drawing_3d::{Camera, CameraBuilder, ScreenViewPort};
+
+use super::{
+    player::{Player, PlayerFlags},
+    prelude::*,
+};
+
+/// How far behind the chased ship the observer sits
+pub const OBSERVER_CHASE_DISTANCE: f32 = 20.0;
+/// And how far above it
+pub const OBSERVER_CHASE_HEIGHT: f32 = 5.0;
+/// How quickly the chase camera catches up, higher is stiffer
+pub const OBSERVER_CHASE_STIFFNESS: f32 = 6.0;
+/// Free fly speed at full thrust
+pub const OBSERVER_FLY_SPEED: f32 = 60.0;
+
+/// Width of the rearview window as a fraction of the screen
+pub const REARVIEW_WIDTH: f32 = 0.25;
+/// Gap between the top of the screen and the rearview window, in pixels
+const REARVIEW_MARGIN: usize = 8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObserverMode {
+    /// Flying around on its own
+    FreeFly,
+    /// Following behind a player's ship
+    Chase { target: usize },
+}
+
+/// A ship that can be watched, from the live game or a demo being played back
+#[derive(Debug, Copy, Clone)]
+pub struct ObserverTarget {
+    pub handle: usize,
+    pub position: Vector,
+    pub orientation: Matrix,
+}
+
+#[derive(Debug, Clone)]
+pub struct Observer {
+    pub mode: ObserverMode,
+    pub position: Vector,
+    pub heading: Angle,
+    pub pitch: Angle,
+    /// Orientation while chasing, free fly uses heading and pitch
+    chase_orientation: Matrix,
+}
+
+impl Observer {
+    pub fn new(position: &Vector) -> Self {
+        Self {
+            mode: ObserverMode::FreeFly,
+            position: *position,
+            heading: Angle(0),
+            pitch: Angle(0),
+            chase_orientation: Matrix::IDENTITY,
+        }
+    }
+
+    pub fn target(&self) -> Option<usize> {
+        match self.mode {
+            ObserverMode::Chase { target } => Some(target),
+            ObserverMode::FreeFly => None,
+        }
+    }
+
+    pub fn orientation(&self) -> Matrix {
+        match self.mode {
+            ObserverMode::FreeFly => Matrix::compute_rotation_3d(&EulerAngle {
+                pitch: self.pitch,
+                heading: self.heading,
+                bank: Angle(0),
+            }),
+            ObserverMode::Chase { .. } => self.chase_orientation,
+        }
+    }
+
+    /// Lets go of whoever was being chased, staying where the camera is
+    pub fn free_fly(&mut self) {
+        self.mode = ObserverMode::FreeFly;
+    }
+
+    /// Starts chasing a ship, false if it isn't one of the targets
+    pub fn chase(&mut self, handle: usize, targets: &[ObserverTarget]) -> bool {
+        let Some(target) = targets.iter().find(|t| t.handle == handle) else {
+            return false;
+        };
+
+        self.mode = ObserverMode::Chase { target: handle };
+
+        // Jump straight there, easing in across the level looks wrong
+        self.position = chase_position(target);
+        self.chase_orientation = chase_orientation(&self.position, target);
+
+        true
+    }
+
+    /// Moves on to the next (or previous) ship by handle, from free fly this picks the first one
+    pub fn cycle_target(&mut self, targets: &[ObserverTarget], forward: bool) -> Option<usize> {
+        let mut handles: Vec<usize> = targets.iter().map(|t| t.handle).collect();
+        handles.sort_unstable();
+
+        if !forward {
+            handles.reverse();
+        }
+
+        let next = match self.target() {
+            Some(current) => handles
+                .iter()
+                .find(|&&h| if forward { h > current } else { h < current })
+                .or(handles.first())
+                .copied(),
+            None => handles.first().copied(),
+        }?;
+
+        self.chase(next, targets);
+
+        Some(next)
+    }
+
+    /// Free fly controls, `thrust` is -1 to 1 on each of the camera's own axes and turning is in angle units
+    pub fn fly(&mut self, thrust: &Vector, heading: i32, pitch: i32, frametime: f32) {
+        if self.mode != ObserverMode::FreeFly {
+            return;
+        }
+
+        self.heading = Angle(self.heading.0.wrapping_add(heading as u16));
+        self.pitch = Angle(self.pitch.0.wrapping_add(pitch as u16));
+
+        let m = self.orientation();
+        let velocity = m.right * thrust.x + m.up * thrust.y + m.forward * thrust.z;
+        self.position += velocity * (OBSERVER_FLY_SPEED * frametime);
+    }
+
+    /// Follows the chased ship, moving on to another if it left the game
+    pub fn update(&mut self, targets: &[ObserverTarget], frametime: f32) {
+        let Some(handle) = self.target() else {
+            return;
+        };
+
+        let Some(target) = targets.iter().find(|t| t.handle == handle) else {
+            if self.cycle_target(targets, true).is_none() {
+                self.free_fly();
+            }
+            return;
+        };
+
+        let desired = chase_position(target);
+        let t = (frametime * OBSERVER_CHASE_STIFFNESS).min(1.0);
+
+        self.position = self.position + (desired - self.position) * t;
+        self.chase_orientation = chase_orientation(&self.position, target);
+    }
+
+    pub fn camera(&self, zoom: f32) -> Camera {
+        CameraBuilder::default()
+            .position(self.position)
+            .rotation(self.orientation())
+            .zoom(zoom)
+            .build()
+            .unwrap()
+    }
+}
+
+fn chase_position(target: &ObserverTarget) -> Vector {
+    target.position - target.orientation.forward * OBSERVER_CHASE_DISTANCE + target.orientation.up * OBSERVER_CHASE_HEIGHT
+}
+
+fn chase_orientation(position: &Vector, target: &ObserverTarget) -> Matrix {
+    let forward = target.position - *position;
+    Matrix::from_vector(Some(&forward), Some(&target.orientation.up), None)
+}
+
+/// Camera looking out the back of the ship, turned around about its up axis
+pub fn rearview_camera(position: &Vector, orientation: &Matrix, zoom: f32) -> Camera {
+    let m = Matrix {
+        right: -orientation.right,
+        up: orientation.up,
+        forward: -orientation.forward,
+    };
+
+    CameraBuilder::default().position(*position).rotation(m).zoom(zoom).build().unwrap()
+}
+
+/// The rearview camera if the player has it turned on
+pub fn player_rearview(player: &Player, position: &Vector, orientation: &Matrix, zoom: f32) -> Option<Camera> {
+    player
+        .flags
+        .contains(PlayerFlags::REARVIEW)
+        .then(|| rearview_camera(position, orientation, zoom))
+}
+
+/// Small window centered at the top of the screen for the rearview, keeping the screen's aspect
+pub fn rearview_viewport(screen: &ScreenViewPort) -> ScreenViewPort {
+    let width = ((screen.width as f32 * REARVIEW_WIDTH) as usize).max(1);
+    let height = ((width as f32 / screen.aspect.max(f32::EPSILON)) as usize).clamp(1, screen.height.max(1));
+
+    ScreenViewPort {
+        x: screen.x + screen.width.saturating_sub(width) / 2,
+        y: screen.y + REARVIEW_MARGIN.min(screen.height.saturating_sub(height)),
+        width,
+        height,
+        aspect: width as f32 / height as f32,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn ships() -> Vec<ObserverTarget> {
+        [3, 1, 7]
+            .iter()
+            .map(|&handle| ObserverTarget {
+                handle,
+                position: Vector::new(handle as f32 * 100.0, 0.0, 0.0),
+                orientation: Matrix::IDENTITY,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn observer_cycle_test() {
+        crate::test_common::setup();
+
+        let targets = ships();
+        let mut observer = Observer::new(&Vector::new(0.0, 0.0, 0.0));
+
+        // From free fly the lowest handle comes first, then in order, wrapping around
+        assert_eq!(observer.cycle_target(&targets, true), Some(1));
+        assert_eq!(observer.cycle_target(&targets, true), Some(3));
+        assert_eq!(observer.cycle_target(&targets, true), Some(7));
+        assert_eq!(observer.cycle_target(&targets, true), Some(1));
+        assert_eq!(observer.cycle_target(&targets, false), Some(7));
+
+        assert!(!observer.chase(42, &targets));
+        assert_eq!(observer.cycle_target(&[], true), None);
+    }
+
+    #[test]
+    fn observer_chase_test() {
+        crate::test_common::setup();
+
+        let mut targets = ships();
+        let mut observer = Observer::new(&Vector::new(0.0, 0.0, 0.0));
+        observer.chase(3, &targets);
+
+        // Sits behind and above, looking at the ship
+        assert!(observer.position.z < targets[0].position.z);
+        assert!(observer.position.y > targets[0].position.y);
+        assert!((observer.orientation().forward * (targets[0].position - observer.position).normalized()) > 0.99);
+
+        // Catches up when the ship moves
+        targets[0].position.z += 50.0;
+        let before = observer.position.z;
+        observer.update(&targets, 0.05);
+        assert!(observer.position.z > before);
+
+        // The ship leaves and the observer moves on
+        targets.remove(0);
+        observer.update(&targets, 0.05);
+        assert_eq!(observer.target(), Some(7));
+
+        observer.update(&[], 0.05);
+        assert_eq!(observer.mode, ObserverMode::FreeFly);
+    }
+
+    #[test]
+    fn observer_free_fly_test() {
+        crate::test_common::setup();
+
+        let mut observer = Observer::new(&Vector::new(0.0, 0.0, 0.0));
+        observer.fly(&Vector::new(0.0, 0.0, 1.0), 0, 0, 1.0);
+
+        assert!((observer.position.z - OBSERVER_FLY_SPEED).abs() < 0.1);
+    }
+
+    #[test]
+    fn rearview_test() {
+        crate::test_common::setup();
+
+        let camera = rearview_camera(&Vector::new(1.0, 2.0, 3.0), &Matrix::IDENTITY, 1.0);
+        assert_eq!(camera.orientation.forward, -Matrix::IDENTITY.forward);
+        assert_eq!(camera.orientation.up, Matrix::IDENTITY.up);
+
+        let mut player = Player::default();
+        assert!(player_rearview(&player, &Vector::new(0.0, 0.0, 0.0), &Matrix::IDENTITY, 1.0).is_none());
+        player.flags |= PlayerFlags::REARVIEW;
+        assert!(player_rearview(&player, &Vector::new(0.0, 0.0, 0.0), &Matrix::IDENTITY, 1.0).is_some());
+
+        let screen = ScreenViewPort { x: 0, y: 0, width: 640, height: 480, aspect: 640.0 / 480.0 };
+        let view = rearview_viewport(&screen);
+        assert_eq!((view.x, view.y, view.width, view.height), (240, 8, 160, 120));
+
+        // A screen with nothing to it still gets a pixel, pinned to its corner
+        let view = rearview_viewport(&ScreenViewPort { x: 10, y: 20, width: 0, height: 0, aspect: 1.0 });
+        assert_eq!((view.x, view.y, view.width, view.height), (10, 20, 1, 1));
+    }
+}
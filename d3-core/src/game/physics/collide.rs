@@ -42,6 +42,11 @@ pub struct CollisionMap {
 }
 
 impl CollisionMap {
+    /// How a ray is checked against objects of `class`, `Nothing` if rays pass through them
+    pub fn ray_result(&self, class: ObjectClass) -> CollisionResultType {
+        self.ray_result[class as usize]
+    }
+
     fn set_ray_result(&mut self, class: ObjectClass, result: CollisionResultType) {
         self.ray_result[class as usize] = result;
     }
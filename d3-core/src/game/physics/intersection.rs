@@ -1,10 +1,10 @@
 use core::{any::Any, ptr::addr_of};
 use std::{
-    collections::{HashSet, VecDeque},
-    os::unix::process,
+    collections::VecDeque,
     rc, vec,
 };
 
+use anyhow::Result;
 use angle::Angle;
 use matrix::Matrix;
 use vector::Vector;
@@ -13,11 +13,16 @@ use vector2d::Vector2D;
 use crate::{
     game::{object_dynamic_behavior::MovementType, room::FaceFlags, terrain::TERRAIN_SIZE},
     graphics::polymodel::PolyModel,
+    math::{
+        bounds::{Aabb, Sphere},
+        plane::Plane,
+    },
 };
 
 use super::{
+    collide::{CollisionMap, CollisionResultType},
     super::prelude::*,
-    super::room::{self, Face, Room, MAX_ROOMS},
+    super::room::{self, Face, Portal, Room, RoomFlags, RoomSet},
     super::terrain::{Terrain, TERRAIN_DEPTH, TERRAIN_WIDTH},
 };
 
@@ -103,7 +108,7 @@ bitflags! {
         const ROBOTS_AS_SPHERE = 1 << 23;
         /// Ignores clutter collisions
         const IGNORE_CLUTTER_COLLISIONS = 1 << 24;
-        /// Ignores rendering through portals
+        /// Goes past drawn portal faces (grates, windows) instead of stopping at them, for line of sight
         const IGNORE_RENDER_THROUGH_PORTALS = 1 << 25;
     }
 }
@@ -112,7 +117,6 @@ const PLAYER_SIZE_SCALAR: f32 = 0.8;
 const CELLS_PER_COL_CELL: usize = 1;
 const COL_TERRAIN_SIZE: f32 = super::super::terrain::TERRAIN_SIZE * CELLS_PER_COL_CELL as f32;
 const MIN_BIG_OBJ_RAD: f32 = COL_TERRAIN_SIZE;
-const MAX_CELLS_VISITED: usize = TERRAIN_DEPTH * TERRAIN_WIDTH;
 
 // XXX:
 // chrishack -- we could turn backface, on and off so that we
@@ -159,6 +163,9 @@ pub struct IntersectionFinder {
     /// Whether the FVI call has zero radius for collision checks.
     zero_rad: bool,
 
+    /// Rooms reached so far while walking portals.
+    rooms_visited: RoomSet,
+
     /// Unordered list of terrain cells visited during this FVI call.
     cells_visited: Vec<u16>,
 
@@ -198,20 +205,14 @@ pub struct IntersectionFinder {
     /// The best distance of the collision found during this FVI call.
     collision_dist: f32,
 
-    /// Maximum bounds for movement in Axis-Aligned Bounding Box (AABB) format.
-    max_xyz: Vector,
-
-    /// Minimum bounds for movement in Axis-Aligned Bounding Box (AABB) format.
-    min_xyz: Vector,
+    /// Bounds of the movement for object checks.
+    movement_bounds: Aabb,
 
     /// Movement delta for this FVI call, representing how much movement occurred.
     movement_delta: Vector,
 
-    /// Maximum bounds for wall movement in Axis-Aligned Bounding Box (AABB) format.
-    wall_max_xyz: Vector,
-
-    /// Minimum bounds for wall movement in Axis-Aligned Bounding Box (AABB) format.
-    wall_min_xyz: Vector,
+    /// Bounds of the movement for wall checks.
+    wall_bounds: Aabb,
 
     /// The current object being processed by the FVI call.
     curobj: i32,
@@ -223,63 +224,67 @@ pub struct IntersectionFinder {
     recorded_faces: Vec<()>,
 }
 
-impl Default for IntersectionFinder {
-    fn default() -> Self {
-        Self {
+impl IntersectionFinder {
+    /// A finder for terrain `terrain_dims` (width, depth) cells big, the visit lists are sized to fit
+    pub fn new(terrain_dims: (usize, usize)) -> Result<Self> {
+        let (width, depth) = terrain_dims;
+        let cell_count = width * depth;
+
+        if cell_count == 0 {
+            return Err(anyhow!("terrain of {}x{} cells has no cells to visit", width, depth));
+        }
+
+        // Visited cells are kept as u16 indices
+        if cell_count > u16::MAX as usize + 1 {
+            return Err(anyhow!("terrain of {}x{} cells is too big to index its cells", width, depth));
+        }
+
+        Ok(Self {
             ceiling_height: super::super::terrain::MAX_TERRAIN_HEIGHT,
             always_check_ceiling: false,
-            terrain_visit_list: vec![0u8; (TERRAIN_DEPTH * TERRAIN_WIDTH) / 8 + 1],
-            terrain_obj_visit_list: vec![0u8; (TERRAIN_DEPTH * TERRAIN_WIDTH) / 8 + 1],
-            // rooms_visited: vec![0usize; MAX_ROOMS],
-            cells_visited: vec![0u16; MAX_CELLS_VISITED],
-            cells_obj_visited: vec![0u16; MAX_CELLS_VISITED],
+            terrain_visit_list: vec![0u8; cell_count / 8 + 1],
+            terrain_obj_visit_list: vec![0u8; cell_count / 8 + 1],
+            rooms_visited: RoomSet::default(),
+            cells_visited: vec![0u16; cell_count],
+            cells_obj_visited: vec![0u16; cell_count],
             recorded_faces: vec![(); 200],
-            ..Default::default()
-        }
+            check_terrain: false,
+            zero_rad: false,
+            wall_sphere_rad: 0.0,
+            wall_sphere_offset: Vector::ZERO,
+            wall_sphere_p0: Vector::ZERO,
+            wall_sphere_p1: Vector::ZERO,
+            anim_sphere_rad: 0.0,
+            anim_sphere_offset: Vector::ZERO,
+            anim_sphere_p0: Vector::ZERO,
+            anim_sphere_p1: Vector::ZERO,
+            hit_data: None,
+            query: None,
+            collision_dist: 0.0,
+            movement_bounds: Aabb::default(),
+            movement_delta: Vector::ZERO,
+            wall_bounds: Aabb::default(),
+            curobj: -1,
+            moveobj: -1,
+        })
     }
 }
 
 impl IntersectionFinder {
-    pub fn compute_movement_AABB(&mut self, query: &Query) {
-        let delta_movement = self.hit_data.as_ref().unwrap().hit_point - query.p0;
-
-        self.min_xyz = query.p0.clone();
-        self.max_xyz = query.p0.clone();
-
-        if delta_movement.x > 0.0 {
-            self.max_xyz.x += delta_movement.x;
-        } else {
-            self.max_xyz.x += delta_movement.x;
-        }
-
-        if delta_movement.y > 0.0 {
-            self.max_xyz.y += delta_movement.y;
-        } else {
-            self.max_xyz.y += delta_movement.y;
-        }
+    /// Sizes the per room bookkeeping for a level with ids up to `room_count`, call once it's loaded
+    pub fn set_room_count(&mut self, room_count: usize) {
+        self.rooms_visited.resize(room_count);
+    }
 
-        if delta_movement.z > 0.0 {
-            self.max_xyz.z += delta_movement.z;
-        } else {
-            self.max_xyz.z += delta_movement.z;
-        }
+    pub fn compute_movement_AABB(&mut self, query: &Query) {
+        let hit_point = self.hit_data.as_ref().unwrap().hit_point;
 
-        self.wall_min_xyz = self.min_xyz.clone();
-        self.wall_max_xyz = self.max_xyz.clone();
+        let mut bounds = Aabb::from_point(&query.p0);
+        bounds.add_point(&hit_point);
 
         if !self.zero_rad {
             if query.this_obj.is_none() {
-                let offset_vec = Vector {
-                    x: query.rad,
-                    y: query.rad,
-                    z: query.rad,
-                };
-
-                self.min_xyz -= offset_vec;
-                self.max_xyz += offset_vec;
-
-                self.wall_min_xyz = self.min_xyz.clone();
-                self.wall_max_xyz = self.max_xyz.clone();
+                bounds.expand(query.rad);
             } else {
                 let object_ref = query.this_obj.as_ref().unwrap();
                 let object = object_ref.borrow();
@@ -287,41 +292,20 @@ impl IntersectionFinder {
                 let max_offset = object.max_xzy - object.position;
                 let min_offset = object.min_xzy - object.position;
 
-                self.max_xyz += max_offset;
-                self.min_xyz += min_offset;
-
-                self.wall_min_xyz = self.min_xyz.clone();
-                self.wall_max_xyz = self.max_xyz.clone();
+                bounds.expand_by(&min_offset, &max_offset);
             }
         }
+
+        self.movement_bounds = bounds;
+        self.wall_bounds = bounds;
     }
 
     pub fn object_movement_AABB(&self, obj: &Object) -> bool {
-        if obj.max_xzy.x < self.min_xyz.x
-            || self.max_xyz.x < obj.min_xzy.x
-            || obj.max_xzy.z < self.min_xyz.z
-            || self.max_xyz.z < obj.min_xzy.z
-            || obj.max_xzy.y < self.min_xyz.y
-            || self.max_xyz.y < obj.min_xzy.y
-        {
-            return false;
-        }
-
-        true
+        self.movement_bounds.overlaps(&obj.bounds())
     }
 
     pub fn room_movement_AABB(&self, face: &Face) -> bool {
-        if self.wall_max_xyz.y < face.min_xyz.y
-            || face.max_xyz.y < self.wall_min_xyz.y
-            || self.wall_max_xyz.x < face.min_xyz.x
-            || face.max_xyz.x < self.wall_min_xyz.x
-            || self.wall_max_xyz.z < face.min_xyz.z
-            || face.max_xyz.z < self.wall_min_xyz.z
-        {
-            return false;
-        }
-
-        true
+        self.wall_bounds.overlaps(&face.bounds())
     }
 
     /// Returns the number of faces that are approximately within the specified radius
@@ -336,17 +320,7 @@ impl IntersectionFinder {
         debug_assert!(rad >= 0.0);
 
         // Quick volume
-        let min_xyz = Vector {
-            x: position.x - rad,
-            y: position.y - rad,
-            z: position.z - rad,
-        };
-
-        let max_xyz = Vector {
-            x: position.x + rad,
-            y: position.y + rad,
-            z: position.z + rad,
-        };
+        let volume = Aabb::from_center_radius(position, rad);
 
         let mut qfl = match quick_face_list {
             Some(x) => Some(x),
@@ -359,9 +333,8 @@ impl IntersectionFinder {
 
         let mut head = 0usize;
 
-        let mut rooms_visited: HashSet<usize> = HashSet::new();
-
-        rooms_visited.insert(initial_room.borrow().id());
+        self.rooms_visited.clear();
+        self.rooms_visited.insert(initial_room.borrow().id());
 
         let mut num_faces = 0;
 
@@ -375,27 +348,27 @@ impl IntersectionFinder {
             let mut m_sector = 0u8;
             let bb_range = current_room.bounding_box.range.clone();
 
-            if min_xyz.x <= bb_range.min.x {
+            if volume.min.x <= bb_range.min.x {
                 m_sector |= 0x01;
             }
 
-            if min_xyz.y <= bb_range.min.y {
+            if volume.min.y <= bb_range.min.y {
                 m_sector |= 0x02;
             }
 
-            if min_xyz.z <= bb_range.min.x {
+            if volume.min.z <= bb_range.min.x {
                 m_sector |= 0x04;
             }
 
-            if max_xyz.x >= bb_range.max.x {
+            if volume.max.x >= bb_range.max.x {
                 m_sector |= 0x08;
             }
 
-            if max_xyz.y >= bb_range.max.y {
+            if volume.max.y >= bb_range.max.y {
                 m_sector |= 0x10;
             }
 
-            if max_xyz.z >= bb_range.max.z {
+            if volume.max.z >= bb_range.max.z {
                 m_sector |= 0x20;
             }
 
@@ -406,20 +379,14 @@ impl IntersectionFinder {
                 let region_range = &bbf_list.range;
 
                 if (bbf_list.sector & m_sector) == bbf_list.sector {
-                    if region_range.min.x > max_xyz.x
-                        || region_range.min.y > max_xyz.y
-                        || region_range.min.z > max_xyz.z
-                        || region_range.max.x < min_xyz.x
-                        || region_range.max.y < min_xyz.y
-                        || region_range.max.z < min_xyz.z
-                    {
+                    if !volume.overlaps(&Aabb::from(region_range)) {
                         continue;
                     }
 
                     for face_index in &bbf_list.faces {
                         let face = &current_room.faces[*face_index];
 
-                        if !room_manual_AABB(&face, &min_xyz, &max_xyz) {
+                        if !volume.overlaps(&face.bounds()) {
                             continue;
                         }
 
@@ -455,9 +422,7 @@ impl IntersectionFinder {
                                 let connected_room_ref = connected_room.unwrap();
                                 let connected_room = connected_room_ref.borrow();
 
-                                if !rooms_visited.contains(&connected_room.id()) {
-                                    rooms_visited.insert(connected_room.id());
-
+                                if self.rooms_visited.insert(connected_room.id()) {
                                     next_rooms.push_back(connected_room_ref.clone());
                                 }
                             }
@@ -517,36 +482,71 @@ impl IntersectionFinder {
         num_cells
     }
 
+    /// Whether `object` is one `quick_dist_object_list` is after, leaving the bounds check aside
+    fn quick_dist_object_wanted(
+        object: &Object,
+        collisions: &CollisionMap,
+        lightmap_only: bool,
+        only_players_and_ais: bool,
+        include_non_collide_objects: bool,
+    ) -> bool {
+        let class = object.typedef().class;
+
+        if !include_non_collide_objects && collisions.ray_result(class) == CollisionResultType::Nothing {
+            return false;
+        }
+
+        if only_players_and_ais && class != ObjectClass::Player && object.dyn_behavior.autonomous.is_none() {
+            return false;
+        }
+
+        // Rooms are lit by lightmaps even without one of their own
+        if lightmap_only && object.lightmap.width() == 0 && class != ObjectClass::Room {
+            return false;
+        }
+
+        true
+    }
+
+    /// Fills `object_list` with objects whose bounds are within `rad` of `position`, up to
+    /// `max_elements` of them, and returns how many were found.
+    ///
+    /// Outside it walks the objects linked into the terrain cells around `initial_room_ref.1`,
+    /// then `big_objects`, which are too big to be linked into just one cell. Inside it spreads
+    /// out from the room through portals that face the volume.
     pub fn quick_dist_object_list(
         &mut self,
         position: &Vector,
         initial_room_ref: (&SharedMutRef<Room>, usize),
         rad: f32,
-        object_list: &[usize],
+        object_list: &mut Vec<SharedMutRef<Object>>,
+        max_elements: usize,
+        big_objects: &[SharedMutRef<Object>],
+        collisions: &CollisionMap,
         lightmap_only: bool,
         only_players_and_ais: bool,
         include_non_collide_objects: bool,
         stop_at_closed_doors: bool,
         terrain: &Terrain
-    ) {
-        //Quick volume
-        let delta = Vector {
-            x: rad,
-            y: rad,
-            z: rad,
-        };
+    ) -> usize {
+        debug_assert!(rad >= 0.0);
 
-        self.min_xyz = position.clone() - delta;
-        self.max_xyz = position.clone() + delta;
-        self.wall_min_xyz = self.min_xyz.clone();
-        self.wall_max_xyz = self.max_xyz.clone();
+        //Quick volume
+        self.movement_bounds = Aabb::from_center_radius(position, rad);
+        self.wall_bounds = self.movement_bounds;
 
-        let initial_room = initial_room_ref.0.borrow();
+        object_list.clear();
 
+        // Out of self for the walk, `wanted` holds on to the rest of it
+        let mut rooms_visited = std::mem::take(&mut self.rooms_visited);
+        rooms_visited.clear();
 
-        let mut num_objects = 0;
+        let wanted = |object: &Object| {
+            Self::quick_dist_object_wanted(object, collisions, lightmap_only, only_players_and_ais, include_non_collide_objects)
+                && self.object_movement_AABB(object)
+        };
 
-        if initial_room.is_outside {
+        if initial_room_ref.0.borrow().is_outside {
             process_cells(
                 initial_room_ref.1,
                 position,
@@ -555,44 +555,89 @@ impl IntersectionFinder {
                 |current_node: usize| -> bool {
                     let mut current_object_optional_ref = terrain.segments[current_node].object_ref.clone();
 
-                    while current_object_optional_ref.is_some() {
-                        let current_object_ref = current_object_optional_ref.unwrap();
-                        let current_object = current_object_ref.borrow();
-
-                        if num_objects >= object_list.len() {
+                    while let Some(current_object_ref) = current_object_optional_ref {
+                        if object_list.len() >= max_elements {
                             return false; // Stop if we've reached the max number of objects
                         }
-        
-                        todo!();
 
-                        if include_non_collide_objects {
-                            
+                        let current_object = current_object_ref.borrow();
+
+                        // Big objects are gone through once below rather than in every cell they cover
+                        if current_object.size < MIN_BIG_OBJ_RAD && wanted(&current_object) {
+                            object_list.push(current_object_ref.clone());
                         }
-        
-                        // if f_include_non_collide_objects || object.collision_result != RESULT_NOTHING {
-                        //     if !f_only_players_and_ais || object.object_type == OBJ_PLAYER || object.ai_info.is_some() {
-                        //         if !(f_lightmap_only && object.lighting_render_type != LRT_LIGHTMAPS && object.object_type != OBJ_ROOM) {
-                        //             if object_movement_AABB(object) && (object.flags & OF_BIG_OBJECT) == 0 {
-                        //                 // Add object to the list
-                        //                 object_index_list[*num_objects] = cur_obj_index;
-                        //                 *num_objects += 1;
-        
-                        //                 // Ensure we haven't exceeded the limit
-                        //                 assert!(*num_objects <= max_elements);
-                        //             }
-                        //         }
-                        //     }
-                        // }
-        
-                        current_object_optional_ref = current_object.link_next_obj.clone(); // Move to the next object
+
+                        current_object_optional_ref = current_object.link_next_obj.upgrade(); // Move to the next object
                     }
-        
+
                     true // Continue processing cells
                 },
             );
+
+            for object_ref in big_objects {
+                if object_list.len() >= max_elements {
+                    break;
+                }
+
+                if wanted(&object_ref.borrow()) {
+                    object_list.push(object_ref.clone());
+                }
+            }
+
+            self.rooms_visited = rooms_visited;
+            return object_list.len();
         }
 
-        todo!()
+        let mut next_rooms: VecDeque<SharedMutRef<Room>> = VecDeque::new();
+
+        next_rooms.push_back(initial_room_ref.0.clone());
+        rooms_visited.insert(initial_room_ref.0.borrow().id());
+
+        'rooms: while let Some(current_room_ref) = next_rooms.pop_front() {
+            let current_room = current_room_ref.borrow();
+
+            for object_ref in &current_room.objects {
+                if object_list.len() >= max_elements {
+                    break 'rooms;
+                }
+
+                if wanted(&object_ref.borrow()) {
+                    object_list.push(object_ref.clone());
+                }
+            }
+
+            // Nothing gets past a shut door
+            if stop_at_closed_doors && current_room.flags.contains(RoomFlags::DOOR) {
+                if let Some(door) = &current_room.assigned_door_data {
+                    if door.doorway().borrow().position() == 0.0 {
+                        continue;
+                    }
+                }
+            }
+
+            for portal in &current_room.portals {
+                let Some(connected_room_ref) = &portal.connected_room else {
+                    continue;
+                };
+
+                if stop_at_closed_doors && portal.is_solid(false) {
+                    continue;
+                }
+
+                if let Some(face) = &portal.portal_face {
+                    if !self.room_movement_AABB(&face.borrow()) {
+                        continue;
+                    }
+                }
+
+                if rooms_visited.insert(connected_room_ref.borrow().id()) {
+                    next_rooms.push_back(connected_room_ref.clone());
+                }
+            }
+        }
+
+        self.rooms_visited = rooms_visited;
+        object_list.len()
     }
 }
 
@@ -643,7 +688,11 @@ impl Default for IntersectionFinderResult {
             hit_object: vec![None; MAX_HITS],
             hit_sub_object: vec![None; MAX_HITS],
             room_list: vec![None; MAX_SEGS],
-            ..Default::default()
+            hit_point: Vector::ZERO,
+            hit_room: None,
+            hit_distance: 0.0,
+            hit_count: 0,
+            room_count: 0,
         }
     }
 }
@@ -667,23 +716,30 @@ pub struct Query {
     pub frametime: f32,
 }
 
+impl Query {
+    /// Whether the query stops at `portal` rather than carrying on into the connected room
+    pub fn stops_at_portal(&self, portal: &Portal) -> bool {
+        portal.is_solid(self.flags.contains(FqFlags::IGNORE_RENDER_THROUGH_PORTALS))
+    }
+}
+
 // find the point on the specified plane where the line intersects
 // returns true if point found, false if line parallel to plane
 // new_pnt is the found point on the plane
-// plane_pnt & plane_norm describe the plane
 // p0 & p1 are the ends of the line
 // Assumes that the initial point is not intersecting the plane
 pub fn find_plane_line_intersection(
     intp: &mut Vector,
     colp: &mut Vector,
-    plane_point: &Vector,
-    plane_normal: &Vector,
+    plane: &Plane,
     p0: &Vector,
     p1: &Vector,
     rad: f32,
 ) -> bool {
     assert!(rad >= 0.0);
 
+    let plane_normal = &plane.normal;
+
     // Line direction
     let line_vec = *p1 - *p0;
 
@@ -695,9 +751,8 @@ pub fn find_plane_line_intersection(
         return false;
     }
 
-    //  Vector from p0 to a point on the plane
-    let point_plane_vec = *plane_point - *p0;
-    let mut proj_dist_point_plane = plane_normal.dot(point_plane_vec);
+    // Distance from p0 to the plane, positive when p0 is behind it
+    let mut proj_dist_point_plane = -plane.distance(p0);
 
     // Throw out any sphere who's centerpoint is initially behind the face
     if proj_dist_point_plane > 0.0 {
@@ -724,7 +779,7 @@ pub fn find_plane_line_intersection(
     // I picked .00000001 from my head.  It would be pretty parallel of a pretty short movement and
     // the linear combination below might not product a nice answer
     if proj_dist_line.abs() <= 0.00000000001 {
-        let plane_dist = (*p1 - *plane_point).dot(*plane_normal);
+        let plane_dist = plane.distance(p1);
 
         if plane_dist >= rad {
             return false;
@@ -733,7 +788,7 @@ pub fn find_plane_line_intersection(
         *intp = *p1 + (rad - plane_dist) * *plane_normal;
 
         // Make sure the computed new position is not behind the wall.
-        assert!((*intp - *plane_point).dot(*plane_normal) >= -0.01);
+        assert!(plane.distance(intp) >= -0.01);
     } else {
         // The intersection of the line and the plane is a simple linear combination
         *intp = *p0 + (proj_dist_point_plane / proj_dist_line) * line_vec;
@@ -881,8 +936,8 @@ pub fn check_point_to_face(
         edge_vec.x = v1[i] - v0[i];
         edge_vec.y = v1[j] - v0[j];
 
-        check_vec.x -= v0[i];
-        check_vec.y -= v0[j];
+        check_vec.x = colp_arr[i] - v0[i];
+        check_vec.y = colp_arr[j] - v0[j];
 
         let d = check_vec.x * edge_vec.y - check_vec.y * edge_vec.x;
 
@@ -906,11 +961,13 @@ pub fn check_vector_to_sphere(
     col_dist: &mut f32,
     p0: &Vector,
     p1: &Vector,
-    sphere_pos: &Vector,
-    sphere_rad: f32,
+    sphere: &Sphere,
     correcting: bool,
     init_collisions: bool,
 ) -> bool {
+    let sphere_pos = &sphere.center;
+    let sphere_rad = sphere.radius;
+
     // Vector direction of line from p0 to p1
     let line_vec = *p1 - *p0;
 
@@ -967,7 +1024,7 @@ pub fn check_vector_to_sphere(
     // Pathagorithm Theorom -- the radius is the hypothenus, the other two sides are the distance
     // from the point to the line, and the amount we should subtract from the line to account
     // for the sphere overlapping the line at the closest approach point
-    let shorten = sphere_rad.powi(2) - closest_mag_to_center.powi(2);
+    let shorten = (sphere_rad.powi(2) - closest_mag_to_center.powi(2)).sqrt();
     *col_dist = closet_point_dist - shorten;
 
     if *col_dist > mag_line {
@@ -1042,143 +1099,142 @@ pub fn check_vector_to_cylinder(
         &mvec3d,
         &mut init_collide,
     ) {
-        let edge_orient = Matrix::from_vector(Some(&edgevec), None, None);
+        // Starting inside the cylinder only counts when moving toward the edge
+        if init_collide {
+            *col_dist = 0.0;
+            *wall_norm = init_normal.clone();
+            *colp = *p0 - init_normal * rad;
+            *intp = p0.clone();
 
-        let mut po0 = (*p0 - *ep0) * edge_orient;
-        let mut po1 = (*p1 - *ep0) * edge_orient;
+            return true;
+        } else {
+            return false;
+        }
+    }
 
-        po0.z = 0.0;
-        po1.z = 0.0;
+    let edge_orient = Matrix::from_vector(Some(&edgevec), None, None);
 
-        let mut mvec = po1 - po0;
-        let vector_len = Vector::normalize(&mut mvec);
+    let mut po0 = (*p0 - *ep0) * edge_orient;
+    let mut po1 = (*p1 - *ep0) * edge_orient;
 
-        let dist = -(mvec.dot(po0));
+    po0.z = 0.0;
+    po1.z = 0.0;
 
-        let closet_point = po0 + dist * mvec;
+    let mut mvec = po1 - po0;
+    let vector_len = Vector::normalize(&mut mvec);
 
-        let dist_from_origin = Vector::magnitude(&closet_point);
+    let dist = -(mvec.dot(po0));
 
-        if dist_from_origin >= rad {
-            return false;
-        }
+    let closet_point = po0 + dist * mvec;
 
-        let dist_to_intersection = (rad.powi(2) - dist_from_origin.powi(2)).sqrt();
+    let dist_from_origin = Vector::magnitude(&closet_point);
 
-        let mut t = [0f32; 4];
-        let mut valid_t = [false; 4];
-        let mut valid_hit = false;
+    if dist_from_origin >= rad {
+        return false;
+    }
 
-        let mut ivertex = [Vector::ZERO; 4];
-        let mut cole_dist = [0f32; 4];
-        let mut inte = [Vector::ZERO; 4];
+    let dist_to_intersection = (rad.powi(2) - dist_from_origin.powi(2)).sqrt();
 
-        // (0.0 to 1.0) is on line
-        t[0] = (dist + dist_to_intersection) / vector_len;
-        t[1] = (dist - dist_to_intersection) / vector_len;
+    let mut t = [0f32; 4];
+    let mut valid_t = [false; 4];
+    let mut valid_hit = false;
 
-        valid_t[0] = t[0] >= 0.0 && t[0] <= 1.0;
-        valid_t[1] = t[1] >= 0.0 && t[1] <= 1.0;
+    let mut ivertex = [Vector::ZERO; 4];
+    let mut cole_dist = [0f32; 4];
+    let mut inte = [Vector::ZERO; 4];
 
-        for i in 0..2 {
-            if valid_t[i] {
-                ivertex[i] = *p0 + mvec3d * (vector_len3d * t[i]);
+    // (0.0 to 1.0) is on line
+    t[0] = (dist + dist_to_intersection) / vector_len;
+    t[1] = (dist - dist_to_intersection) / vector_len;
 
-                let t_edge = (ivertex[i] - *ep0).dot(edgevec) / edge_len;
+    valid_t[0] = t[0] >= 0.0 && t[0] <= 1.0;
+    valid_t[1] = t[1] >= 0.0 && t[1] <= 1.0;
 
-                if t_edge >= 0.0 && t_edge <= 1.0 {
-                    cole_dist[i] = vector_len3d * t[i];
-                    inte[i] = *ep0 + (ivertex[i] - *ep0).dot(edgevec) * edgevec;
-                    valid_hit = true;
-                } else {
-                    valid_t[i] = false;
-                }
+    for i in 0..2 {
+        if valid_t[i] {
+            ivertex[i] = *p0 + mvec3d * (vector_len3d * t[i]);
+
+            let t_edge = (ivertex[i] - *ep0).dot(edgevec) / edge_len;
+
+            if t_edge >= 0.0 && t_edge <= 1.0 {
+                cole_dist[i] = vector_len3d * t[i];
+                inte[i] = *ep0 + (ivertex[i] - *ep0).dot(edgevec) * edgevec;
+                valid_hit = true;
+            } else {
+                valid_t[i] = false;
             }
         }
+    }
 
-        let mut d_vec: Vector;
+    let mut d_vec: Vector;
 
-        // Check end spheres
-        if check_vector_to_sphere(
-            &mut ivertex[2],
-            &mut cole_dist[2],
-            p0,
-            p1,
-            ep0,
-            rad,
-            false,
-            true,
-        ) {
-            t[2] = cole_dist[2] / vector_len3d;
-            valid_t[2] = true;
-            valid_hit = true;
-            d_vec = *ep1 - ivertex[2];
-            Vector::normalize(&mut d_vec);
-            inte[2] = ivertex[2] + rad * d_vec;
-        } else {
-            valid_t[2] = false;
-        }
+    // Check end spheres
+    if check_vector_to_sphere(
+        &mut ivertex[2],
+        &mut cole_dist[2],
+        p0,
+        p1,
+        &Sphere::new(*ep0, rad),
+        false,
+        true,
+    ) {
+        t[2] = cole_dist[2] / vector_len3d;
+        valid_t[2] = true;
+        valid_hit = true;
+        d_vec = *ep0 - ivertex[2];
+        Vector::normalize(&mut d_vec);
+        inte[2] = ivertex[2] + rad * d_vec;
+    } else {
+        valid_t[2] = false;
+    }
 
-        if check_vector_to_sphere(
-            &mut ivertex[3],
-            &mut cole_dist[3],
-            p0,
-            p1,
-            ep1,
-            rad,
-            false,
-            true,
-        ) {
-            t[3] = cole_dist[3] / vector_len3d;
-            valid_t[3] = true;
-            valid_hit = true;
-            d_vec = *ep1 - ivertex[3];
-            Vector::normalize(&mut d_vec);
-            inte[3] = ivertex[3] + rad * d_vec;
-        } else {
-            valid_t[3] = false;
-        }
+    if check_vector_to_sphere(
+        &mut ivertex[3],
+        &mut cole_dist[3],
+        p0,
+        p1,
+        &Sphere::new(*ep1, rad),
+        false,
+        true,
+    ) {
+        t[3] = cole_dist[3] / vector_len3d;
+        valid_t[3] = true;
+        valid_hit = true;
+        d_vec = *ep1 - ivertex[3];
+        Vector::normalize(&mut d_vec);
+        inte[3] = ivertex[3] + rad * d_vec;
+    } else {
+        valid_t[3] = false;
+    }
 
-        if !valid_hit {
-            return false;
-        }
+    if !valid_hit {
+        return false;
+    }
 
-        let mut best_hit_index: Option<usize> = None;
+    let mut best_hit_index: Option<usize> = None;
 
-        for i in 0..4 {
-            if valid_t[i] {
-                match best_hit_index {
-                    None => best_hit_index = Some(i),
-                    Some(x) => {
-                        if cole_dist[i] < cole_dist[x] {
-                            best_hit_index = Some(i);
-                        }
+    for i in 0..4 {
+        if valid_t[i] {
+            match best_hit_index {
+                None => best_hit_index = Some(i),
+                Some(x) => {
+                    if cole_dist[i] < cole_dist[x] {
+                        best_hit_index = Some(i);
                     }
                 }
             }
         }
+    }
 
-        let index = best_hit_index.unwrap();
-
-        *colp = inte[index];
-        *intp = ivertex[index];
-        *col_dist = cole_dist[index];
-        *wall_norm = *intp - *colp;
-        Vector::normalize(wall_norm);
+    let index = best_hit_index.unwrap();
 
-        return true;
-    } else {
-        if init_collide {
-            *col_dist = 0.0;
-            *wall_norm = init_normal.clone();
-            *colp = *p0 - init_normal * rad;
-            *intp = p0.clone();
+    *colp = inte[index];
+    *intp = ivertex[index];
+    *col_dist = cole_dist[index];
+    *wall_norm = *intp - *colp;
+    Vector::normalize(wall_norm);
 
-            return true;
-        } else {
-            return false;
-        }
-    }
+    return true;
 }
 
 /// check if a sphere intersects a face
@@ -1272,8 +1328,8 @@ pub fn check_line_to_face(
 
     // Determine the intersection point between the plane(of the face) and the line
     // This point is the center of the circle (not the edge)
-    let pli =
-        find_plane_line_intersection(newp, colp, &vector_list[vertnum], face_normal, p0, p1, rad);
+    let plane = Plane::from_point_normal(&vector_list[vertnum], face_normal);
+    let pli = find_plane_line_intersection(newp, colp, &plane, p0, p1, rad);
 
     if !pli {
         return false;
@@ -1291,8 +1347,6 @@ pub fn check_line_to_face(
         rad,
         vector_list,
     );
-
-    todo!()
 }
 
 // chrishack -- check this later
@@ -1399,50 +1453,21 @@ pub fn check_vector_to_object(
 
     let total_size = still_size + rad;
 
-    return check_vector_to_sphere(intp, col_dist, p0, p1, &still_pos, total_size, false, true);
+    let still_sphere = Sphere::new(still_pos, total_size);
+
+    return check_vector_to_sphere(intp, col_dist, p0, p1, &still_sphere, false, true);
 }
 
 pub fn object_object_AABB(a: &Object, b: &Object) -> bool {
-    if a.max_xzy.x < b.min_xzy.x
-        || b.max_xzy.x < a.min_xzy.x
-        || a.max_xzy.z < b.min_xzy.z
-        || b.max_xzy.z < a.min_xzy.z
-        || a.max_xzy.y < b.min_xzy.y
-        || b.max_xzy.y < a.min_xzy.y
-    {
-        // no overlap
-        return false;
-    }
-
-    true
+    a.bounds().overlaps(&b.bounds())
 }
 
 pub fn object_room_AABB(obj: &Object, face: &Face) -> bool {
-    if obj.max_xzy.y < face.min_xyz.z
-        || face.max_xyz.y < obj.min_xzy.y
-        || obj.max_xzy.x < face.min_xyz.x
-        || face.max_xyz.x < obj.min_xzy.x
-        || obj.max_xzy.z < face.min_xyz.z
-        || face.max_xyz.z < obj.min_xzy.z
-    {
-        return false;
-    }
-
-    true
+    obj.bounds().overlaps(&face.bounds())
 }
 
 pub fn room_manual_AABB(face: &Face, min_xyz: &Vector, max_xyz: &Vector) -> bool {
-    if max_xyz.y < face.min_xyz.y
-        || face.max_xyz.y < min_xyz.y
-        || max_xyz.x < face.min_xyz.x
-        || face.max_xyz.x < min_xyz.x
-        || max_xyz.z < face.min_xyz.z
-        || face.max_xyz.z < min_xyz.z
-    {
-        return false;
-    }
-
-    true
+    face.bounds().overlaps(&Aabb::new(*min_xyz, *max_xyz))
 }
 
 pub fn process_cells<F>(
@@ -1475,9 +1500,11 @@ where
     // Iterate over the cells within the calculated boundaries
     for _y in y_start..=y_end {
         for _x in x_start..=x_end {
-            // Call the closure with the current node
+            num_cells += 1;
+
+            // Call the closure with the current node, it says when to stop
             if !condition(current_node) {
-                break;
+                return num_cells;
             }
             current_node += 1;
         }
@@ -1,6 +1,9 @@
 pub mod intersection;
 pub mod collide;
 
+#[cfg(test)]
+pub mod tests;
+
 use vector::Vector;
 
 use super::prelude::*;
@@ -0,0 +1,511 @@
+use proptest::prelude::*;
+
+use super::{collide::CollisionMap, intersection::*};
+use crate::{
+    common::{SharedMutRef, WeakHandle, new_shared_mut_ref},
+    game::{
+        object::{Object, ObjectClass, ObjectTypeDef},
+        room::{Face, FaceFlags, Portal, PortalFlags, Room, RoomStore},
+        terrain::{TERRAIN_WIDTH, Terrain},
+    },
+    math::{CrossProduct, DotProduct, bounds::Sphere, plane::Plane, vector::Vector},
+};
+
+/// Everything is built from f32 math over coordinates up to a few hundred units
+const EPSILON: f32 = 0.01;
+
+fn vector(range: core::ops::Range<f32>) -> impl Strategy<Value = Vector> {
+    (range.clone(), range.clone(), range).prop_map(|(x, y, z)| Vector::new(x, y, z))
+}
+
+fn unit_vector() -> impl Strategy<Value = Vector> {
+    vector(-1.0..1.0)
+        .prop_filter("too short to normalize", |v| Vector::magnitude(v) > 0.1)
+        .prop_map(|v| v.normalized())
+}
+
+fn is_finite(v: &Vector) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+/// Scales the tolerance with the size of the values being compared
+fn tolerance(scale: f32) -> f32 {
+    EPSILON * (1.0 + scale)
+}
+
+fn distance_to_segment(point: &Vector, a: &Vector, b: &Vector) -> f32 {
+    let ab = *b - *a;
+    let t = ((*point - *a).dot(ab) / ab.dot(ab)).clamp(0.0, 1.0);
+    Vector::distance(point, &(*a + ab * t))
+}
+
+/// Textbook ray/sphere solve, the first time along `dir` where the ray enters the sphere
+fn ray_sphere(origin: &Vector, dir: &Vector, sphere: &Sphere) -> Option<f32> {
+    let oc = *origin - sphere.center;
+    let b = oc.dot(*dir);
+    let c = oc.dot(oc) - sphere.radius * sphere.radius;
+    let disc = b * b - c;
+
+    if disc < 0.0 {
+        return None;
+    }
+
+    let t = -b - disc.sqrt();
+    if t < 0.0 { None } else { Some(t) }
+}
+
+/// A square face of half size `size` around `center`, wound clockwise around `normal`
+fn square_face(center: &Vector, normal: &Vector, size: f32) -> (Vector, Vector, [Vector; 4]) {
+    let axis = if normal.x.abs() < 0.9 { Vector::new(1.0, 0.0, 0.0) } else { Vector::new(0.0, 0.0, 1.0) };
+    let right = axis.cross(normal).normalized();
+    let forward = right.cross(normal);
+
+    let verts = [
+        *center + (-right - forward) * size,
+        *center + (-right + forward) * size,
+        *center + (right + forward) * size,
+        *center + (right - forward) * size,
+    ];
+
+    (right, forward, verts)
+}
+
+proptest! {
+    #[test]
+    fn vector_to_sphere_hits_surface(
+        center in vector(-100.0..100.0),
+        radius in 0.5f32..20.0,
+        start_dir in unit_vector(),
+        start_gap in 0.5f32..100.0,
+        dir in unit_vector(),
+        len in 0.1f32..200.0,
+    ) {
+        crate::test_common::setup();
+
+        let sphere = Sphere::new(center, radius);
+        let p0 = center + start_dir * (radius + start_gap);
+        let p1 = p0 + dir * len;
+
+        let mut intp = Vector::ZERO;
+        let mut col_dist = -1.0;
+        let hit = check_vector_to_sphere(&mut intp, &mut col_dist, &p0, &p1, &sphere, false, true);
+        let tol = tolerance(radius + start_gap + len);
+
+        if hit {
+            prop_assert!(is_finite(&intp) && col_dist.is_finite());
+            prop_assert!((Vector::distance(&intp, &center) - radius).abs() < tol);
+            prop_assert!(col_dist >= -tol && col_dist <= len + tol);
+            prop_assert!(Vector::distance(&intp, &(p0 + dir * col_dist)) < tol);
+        }
+
+        // Agree with the closed form everywhere but grazing hits and ones right at the end of the vector
+        match ray_sphere(&p0, &dir, &sphere) {
+            Some(t) if t < len - tol => {
+                let closest = p0 + dir * (center - p0).dot(dir);
+                let grazing = (Vector::distance(&closest, &center) - radius).abs() < tol;
+
+                if !grazing {
+                    prop_assert!(hit);
+                    prop_assert!((col_dist - t).abs() < tol);
+                }
+            }
+            Some(t) if t < len + tol => {}
+            _ => prop_assert!(!hit),
+        }
+    }
+
+    #[test]
+    fn vector_to_sphere_distance_is_monotonic(
+        center in vector(-100.0..100.0),
+        radius in 0.5f32..20.0,
+        aim in vector(-0.4..0.4),
+        start_gap in 0.5f32..100.0,
+        extra in 0.0f32..100.0,
+        back in 0.0f32..50.0,
+    ) {
+        crate::test_common::setup();
+
+        let sphere = Sphere::new(center, radius);
+        let start_dir = Vector::new(0.0, 0.0, -1.0);
+        let target = center + aim * radius;
+        let p0 = center - start_dir * (radius + start_gap);
+        let dir = (target - p0).normalized();
+        let p1 = target + dir * radius;
+
+        let mut intp = Vector::ZERO;
+        let mut col_dist = 0.0;
+        prop_assume!(check_vector_to_sphere(&mut intp, &mut col_dist, &p0, &p1, &sphere, false, true));
+
+        let tol = tolerance(radius + start_gap + extra + back);
+
+        // Moving the end further along doesn't change where we hit
+        let mut far_intp = Vector::ZERO;
+        let mut far_dist = 0.0;
+        prop_assert!(check_vector_to_sphere(&mut far_intp, &mut far_dist, &p0, &(p1 + dir * extra), &sphere, false, true));
+        prop_assert!((far_dist - col_dist).abs() < tol);
+
+        // Starting further back hits the same point, further along
+        let mut back_intp = Vector::ZERO;
+        let mut back_dist = 0.0;
+        prop_assert!(check_vector_to_sphere(&mut back_intp, &mut back_dist, &(p0 - dir * back), &p1, &sphere, false, true));
+        prop_assert!((back_dist - (col_dist + back)).abs() < tol);
+        prop_assert!(Vector::distance(&back_intp, &intp) < tol);
+    }
+
+    #[test]
+    fn plane_line_intersection_touches_plane(
+        normal in unit_vector(),
+        d in -100.0f32..100.0,
+        rad in 0.0f32..10.0,
+        height in 0.1f32..100.0,
+        lateral in vector(-100.0..100.0),
+        dir in unit_vector(),
+        len in 0.1f32..200.0,
+    ) {
+        crate::test_common::setup();
+
+        let plane = Plane::new(normal, d);
+        let p0 = plane.project_point(&lateral) + normal * (rad + height);
+        let p1 = p0 + dir * len;
+
+        let mut intp = Vector::ZERO;
+        let mut colp = Vector::ZERO;
+        let hit = find_plane_line_intersection(&mut intp, &mut colp, &plane, &p0, &p1, rad);
+
+        let approach = -normal.dot(dir) * len;
+        let tol = tolerance(d.abs() + rad + height + len);
+
+        if hit {
+            prop_assert!(is_finite(&intp) && is_finite(&colp));
+            prop_assert!((plane.distance(&intp) - rad).abs() < tol);
+            prop_assert!(plane.distance(&colp).abs() < tol);
+
+            // The stopping point is somewhere along the line
+            let along = Vector::distance(&p0, &intp) + Vector::distance(&intp, &p1);
+            prop_assert!((along - len).abs() < tol);
+        }
+
+        if normal.dot(dir) >= 0.0 || approach < height - tol {
+            prop_assert!(!hit);
+        } else if approach > height + tol {
+            prop_assert!(hit);
+        }
+    }
+
+    #[test]
+    fn vector_to_cylinder_hits_surface(
+        ep0 in vector(-100.0..100.0),
+        edge_dir in unit_vector(),
+        edge_len in 1.0f32..50.0,
+        rad in 0.5f32..10.0,
+        p0 in vector(-150.0..150.0),
+        p1 in vector(-150.0..150.0),
+    ) {
+        crate::test_common::setup();
+
+        let ep1 = ep0 + edge_dir * edge_len;
+        prop_assume!(distance_to_segment(&p0, &ep0, &ep1) > rad + 0.1);
+        prop_assume!(Vector::distance(&p0, &p1) > 0.1);
+
+        let mut colp = Vector::ZERO;
+        let mut intp = Vector::ZERO;
+        let mut col_dist = 0.0;
+        let mut wall_norm = Vector::ZERO;
+
+        let hit = check_vector_to_cylinder(&mut colp, &mut intp, &mut col_dist, &mut wall_norm, &p0, &p1, rad, &ep0, &ep1);
+
+        let len = Vector::distance(&p0, &p1);
+        let tol = tolerance(rad + edge_len + len);
+
+        if hit {
+            prop_assert!(is_finite(&colp) && is_finite(&intp) && is_finite(&wall_norm) && col_dist.is_finite());
+
+            // Stopped a radius away from a point on the edge
+            prop_assert!(distance_to_segment(&colp, &ep0, &ep1) < tol);
+            prop_assert!((Vector::distance(&intp, &colp) - rad).abs() < tol);
+            prop_assert!((distance_to_segment(&intp, &ep0, &ep1) - rad).abs() < tol);
+            prop_assert!((Vector::magnitude(&wall_norm) - 1.0).abs() < EPSILON);
+
+            prop_assert!(col_dist >= -tol && col_dist <= len + tol);
+            prop_assert!(Vector::distance(&intp, &(p0 + (p1 - p0).normalized() * col_dist)) < tol);
+        }
+    }
+
+    #[test]
+    fn vector_to_cylinder_initial_overlap(
+        ep0 in vector(-100.0..100.0),
+        edge_dir in unit_vector(),
+        edge_len in 1.0f32..50.0,
+        rad in 0.5f32..10.0,
+        along in 0.05f32..0.95,
+        offset_dir in unit_vector(),
+        depth in 0.1f32..0.9,
+        len in 0.1f32..50.0,
+    ) {
+        crate::test_common::setup();
+
+        let ep1 = ep0 + edge_dir * edge_len;
+        let side = offset_dir - edge_dir * offset_dir.dot(edge_dir);
+        prop_assume!(Vector::magnitude(&side) > 0.1);
+        let side = side.normalized();
+
+        let axis_point = ep0 + edge_dir * (edge_len * along);
+        let p0 = axis_point + side * (rad * depth);
+
+        let mut colp = Vector::ZERO;
+        let mut intp = Vector::ZERO;
+        let mut col_dist = -1.0;
+        let mut wall_norm = Vector::ZERO;
+
+        // Heading toward the edge collides right away
+        let toward = p0 - side * len;
+        prop_assert!(check_vector_to_cylinder(&mut colp, &mut intp, &mut col_dist, &mut wall_norm, &p0, &toward, rad, &ep0, &ep1));
+        prop_assert_eq!(col_dist, 0.0);
+        prop_assert_eq!(intp, p0);
+
+        // Heading away lets us out
+        let away = p0 + side * len;
+        prop_assert!(!check_vector_to_cylinder(&mut colp, &mut intp, &mut col_dist, &mut wall_norm, &p0, &away, rad, &ep0, &ep1));
+    }
+
+    #[test]
+    fn line_to_face_hits_inside(
+        center in vector(-100.0..100.0),
+        normal in unit_vector(),
+        size in 1.0f32..50.0,
+        start in (-0.9f32..0.9, -0.9f32..0.9),
+        end in (-0.9f32..0.9, -0.9f32..0.9),
+        h0 in 0.1f32..100.0,
+        h1 in 0.1f32..100.0,
+    ) {
+        crate::test_common::setup();
+
+        let (right, forward, verts) = square_face(&center, &normal, size);
+
+        let p0 = center + right * (start.0 * size) + forward * (start.1 * size) + normal * h0;
+        let p1 = center + right * (end.0 * size) + forward * (end.1 * size) - normal * h1;
+
+        let mut newp = Vector::ZERO;
+        let mut colp = Vector::ZERO;
+        let mut col_dist = 0.0;
+        let mut wall_norm = Vector::ZERO;
+        let mut face_normal = normal;
+
+        let hit = check_line_to_face(&mut newp, &mut colp, &mut col_dist, &mut wall_norm, &p0, &p1, &mut face_normal, &verts, verts.len(), 0.0);
+
+        let len = Vector::distance(&p0, &p1);
+        let tol = tolerance(size + h0 + h1);
+        let plane = Plane::from_point_normal(&center, &normal);
+
+        prop_assert!(hit);
+        prop_assert!(is_finite(&newp) && is_finite(&colp) && col_dist.is_finite());
+        prop_assert!(plane.distance(&newp).abs() < tol);
+        prop_assert!(Vector::distance(&newp, &colp) < tol);
+        prop_assert!((col_dist - len * h0 / (h0 + h1)).abs() < tol);
+        prop_assert_eq!(wall_norm, normal);
+    }
+
+    #[test]
+    fn line_to_face_misses_outside(
+        center in vector(-100.0..100.0),
+        normal in unit_vector(),
+        size in 1.0f32..50.0,
+        side in 0usize..4,
+        spread in (1.1f32..3.0, -3.0f32..3.0),
+        h0 in 0.1f32..100.0,
+        h1 in 0.1f32..100.0,
+        rad in 0.0f32..0.05,
+    ) {
+        crate::test_common::setup();
+
+        let (right, forward, verts) = square_face(&center, &normal, size);
+
+        // Both ends past the same edge, so the crossing is outside the face
+        let (u, v) = match side {
+            0 => (right, forward),
+            1 => (-right, forward),
+            2 => (forward, right),
+            _ => (-forward, right),
+        };
+        let lateral = center + u * (spread.0 * size) + v * (spread.1 * size);
+
+        let p0 = lateral + normal * h0;
+        let p1 = lateral - normal * h1;
+
+        let mut newp = Vector::ZERO;
+        let mut colp = Vector::ZERO;
+        let mut col_dist = 0.0;
+        let mut wall_norm = Vector::ZERO;
+        let mut face_normal = normal;
+
+        let hit = check_line_to_face(&mut newp, &mut colp, &mut col_dist, &mut wall_norm, &p0, &p1, &mut face_normal, &verts, verts.len(), rad);
+
+        prop_assert!(!hit);
+
+        // The plane point is still filled in
+        let plane = Plane::from_point_normal(&center, &normal);
+        prop_assert!(is_finite(&newp));
+        prop_assert!((plane.distance(&newp) - rad).abs() < tolerance(size + h0 + h1));
+    }
+}
+
+/// An object of `class` with cube bounds `size` either side of `position`
+fn test_object(class: ObjectClass, position: Vector, size: f32) -> SharedMutRef<Object> {
+    let mut typedef = ObjectTypeDef::new("test", class);
+    typedef.size = size;
+
+    let mut object = Object::new(typedef);
+    let extent = Vector::new(size, size, size);
+    object.position = position;
+    object.min_xzy = position - extent;
+    object.max_xzy = position + extent;
+
+    new_shared_mut_ref(object)
+}
+
+/// A portal into `room` through a face covering `min` to `max`
+fn test_portal(room: &SharedMutRef<Room>, flags: PortalFlags, min: Vector, max: Vector) -> Portal {
+    let face = Face {
+        flags: FaceFlags::empty(),
+        num_verts: 0,
+        portal: None,
+        face_verts: Vec::new(),
+        face_uvls: Vec::new(),
+        normal: Vector::new(1.0, 0.0, 0.0),
+        lightmap: None,
+        special_faces: (),
+        render_frame: (),
+        tmap: None,
+        light_muliple: 0,
+        min_xyz: min,
+        max_xyz: max,
+    };
+
+    Portal {
+        flags,
+        portal_face: Some(new_shared_mut_ref(face)),
+        connected_room: Some(room.clone()),
+        connected_portal: None,
+        bnode_index: (),
+        combine_master: (),
+        path_point: Vector::ZERO,
+    }
+}
+
+fn contains(list: &[SharedMutRef<Object>], object: &SharedMutRef<Object>) -> bool {
+    list.iter().any(|o| std::rc::Rc::ptr_eq(o, object))
+}
+
+#[test]
+fn quick_dist_object_list_rooms_test() {
+    crate::test_common::setup();
+
+    let collisions = CollisionMap::default();
+    let terrain = Terrain::default();
+
+    // Rooms get told apart by the ids the store gives them
+    let mut rooms = RoomStore::new();
+    let [start, next, blocked, beyond_view] = [(); 4].map(|_| {
+        let id = rooms.insert(Room::default());
+        rooms.get(id).unwrap().clone()
+    });
+
+    let robot = test_object(ObjectClass::Robot, Vector::new(5.0, 0.0, 0.0), 1.0);
+    let far_robot = test_object(ObjectClass::Robot, Vector::new(100.0, 0.0, 0.0), 1.0);
+    let fireball = test_object(ObjectClass::Fireball, Vector::new(-5.0, 0.0, 0.0), 1.0);
+    let powerup = test_object(ObjectClass::Powerup, Vector::new(15.0, 0.0, 0.0), 1.0);
+    let player = test_object(ObjectClass::Player, Vector::new(15.0, 0.0, 0.0), 1.0);
+    let blocked_robot = test_object(ObjectClass::Robot, Vector::new(0.0, 5.0, 0.0), 1.0);
+    let unseen_robot = test_object(ObjectClass::Robot, Vector::new(0.0, -5.0, 0.0), 1.0);
+
+    start.borrow_mut().objects = vec![robot.clone(), far_robot.clone(), fireball.clone()];
+    next.borrow_mut().objects = vec![powerup.clone(), player.clone()];
+    blocked.borrow_mut().objects = vec![blocked_robot.clone()];
+    beyond_view.borrow_mut().objects = vec![unseen_robot.clone()];
+
+    start.borrow_mut().portals = vec![
+        test_portal(&next, PortalFlags::empty(), Vector::new(10.0, -5.0, -5.0), Vector::new(10.0, 5.0, 5.0)),
+        test_portal(&blocked, PortalFlags::BLOCK, Vector::new(-5.0, 3.0, -5.0), Vector::new(5.0, 3.0, 5.0)),
+        // A portal face out past the volume
+        test_portal(&beyond_view, PortalFlags::empty(), Vector::new(-5.0, -50.0, -5.0), Vector::new(5.0, -50.0, 5.0)),
+    ];
+
+    let mut finder = IntersectionFinder::new(terrain.dims()).unwrap();
+    finder.set_room_count(rooms.id_bound());
+    let mut list = Vec::new();
+    let position = Vector::ZERO;
+
+    let found = finder.quick_dist_object_list(&position, (&start, 0), 20.0, &mut list, 10, &[], &collisions, false, false, false, true, &terrain);
+
+    assert_eq!(found, 3);
+    assert!(contains(&list, &robot) && contains(&list, &powerup) && contains(&list, &player));
+    assert!(!contains(&list, &far_robot));
+    assert!(!contains(&list, &fireball));
+
+    // Through the blocked portal when doors don't matter, and including things rays pass through
+    let found = finder.quick_dist_object_list(&position, (&start, 0), 20.0, &mut list, 10, &[], &collisions, false, false, true, false, &terrain);
+    assert_eq!(found, 5);
+    assert!(contains(&list, &fireball) && contains(&list, &blocked_robot));
+    assert!(!contains(&list, &unseen_robot));
+
+    let found = finder.quick_dist_object_list(&position, (&start, 0), 20.0, &mut list, 10, &[], &collisions, false, true, false, true, &terrain);
+    assert_eq!(found, 1);
+    assert!(contains(&list, &player));
+
+    // Nothing here has a lightmap
+    assert_eq!(finder.quick_dist_object_list(&position, (&start, 0), 20.0, &mut list, 10, &[], &collisions, true, false, false, true, &terrain), 0);
+
+    // Stops once the list is full
+    assert_eq!(finder.quick_dist_object_list(&position, (&start, 0), 20.0, &mut list, 2, &[], &collisions, false, false, false, true, &terrain), 2);
+}
+
+#[test]
+fn quick_dist_object_list_terrain_test() {
+    crate::test_common::setup();
+
+    let collisions = CollisionMap::default();
+    let mut terrain = Terrain::default();
+
+    let outside = new_shared_mut_ref(Room::default());
+    outside.borrow_mut().is_outside = true;
+
+    let cell = TERRAIN_WIDTH * 10 + 10;
+    let position = Vector::new(100.0, 0.0, 100.0);
+
+    let first = test_object(ObjectClass::Robot, position + Vector::new(2.0, 0.0, 0.0), 1.0);
+    let second = test_object(ObjectClass::Clutter, position + Vector::new(-2.0, 0.0, 0.0), 1.0);
+    let far = test_object(ObjectClass::Robot, position + Vector::new(0.0, 200.0, 0.0), 1.0);
+    let big = test_object(ObjectClass::Building, position, 100.0);
+
+    // A cell links its objects one after another
+    first.borrow_mut().link_next_obj = WeakHandle::to(&second);
+    second.borrow_mut().link_next_obj = WeakHandle::to(&far);
+    far.borrow_mut().link_next_obj = WeakHandle::to(&big);
+    terrain.segments[cell].object_ref = Some(first.clone());
+    terrain.segments[cell + 1].object_ref = Some(big.clone());
+
+    let mut finder = IntersectionFinder::new(terrain.dims()).unwrap();
+    let mut list = Vec::new();
+    let big_objects = [big.clone()];
+
+    let found = finder.quick_dist_object_list(&position, (&outside, cell), 10.0, &mut list, 10, &big_objects, &collisions, false, false, false, false, &terrain);
+
+    // The big object is only counted once, from the big object list
+    assert_eq!(found, 3);
+    assert!(std::rc::Rc::ptr_eq(&list[0], &first) && std::rc::Rc::ptr_eq(&list[1], &second) && std::rc::Rc::ptr_eq(&list[2], &big));
+    assert!(!contains(&list, &far));
+}
+
+#[test]
+fn intersection_finder_new_test() {
+    crate::test_common::setup();
+
+    let terrain = Terrain::default();
+    let (width, depth) = terrain.dims();
+    assert_eq!(width * depth, terrain.segments.len());
+    assert!(IntersectionFinder::new(terrain.dims()).is_ok());
+
+    assert!(IntersectionFinder::new((TERRAIN_WIDTH, TERRAIN_WIDTH)).is_ok());
+    assert!(IntersectionFinder::new((0, 16)).is_err());
+    assert!(IntersectionFinder::new((512, 512)).is_err());
+}
@@ -0,0 +1,463 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::prelude::*;
+use spline::{ArcLengthTable, CatmullRom, Spline};
+use vector::Vector;
+
+/// Contacts with a normal pointing up at least this much count as standing on the platform
+pub const PLATFORM_REST_NORMAL_Y: f32 = 0.7;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PathShape {
+    /// Straight lines between waypoints
+    #[default]
+    Linear,
+    /// A Catmull-Rom spline through the waypoints
+    Spline,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PathEnd {
+    /// Stops at the last waypoint, like an elevator
+    #[default]
+    Stop,
+    /// Turns around and heads back
+    PingPong,
+    /// Goes back around to the first waypoint
+    Loop,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Waypoint {
+    pub position: Vector,
+    /// How long to wait here before moving on
+    pub pause: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyframedPath {
+    pub waypoints: Vec<Waypoint>,
+    /// Units per second
+    pub speed: f32,
+    pub shape: PathShape,
+    pub end: PathEnd,
+    /// Slow down into and speed up out of each waypoint
+    pub ease: bool,
+}
+
+impl KeyframedPath {
+    /// The spline through every waypoint, joined back to the first on a looping path
+    pub fn spline(&self) -> CatmullRom {
+        CatmullRom::new(self.waypoints.iter().map(|w| w.position).collect(), self.end == PathEnd::Loop)
+    }
+
+    /// Distance along the path from waypoint `from` to `from + 1`
+    pub fn segment_length(&self, from: usize) -> f32 {
+        match self.shape {
+            PathShape::Linear => Vector::distance(&self.waypoint(from), &self.waypoint(from + 1)),
+            PathShape::Spline => ArcLengthTable::new(&PathSegment::new(&self.spline(), from)).length(),
+        }
+    }
+
+    /// Position `t` (0 to 1) of the distance from waypoint `from` to `from + 1`
+    pub fn position_on_segment(&self, from: usize, t: f32) -> Vector {
+        match self.shape {
+            PathShape::Linear => {
+                let p1 = self.waypoint(from);
+                let p2 = self.waypoint(from + 1);
+
+                p1 + (p2 - p1) * t
+            }
+            PathShape::Spline => {
+                let spline = self.spline();
+                let segment = PathSegment::new(&spline, from);
+
+                // The spline bunches up where waypoints are close, go by distance so the speed holds steady
+                segment.point(ArcLengthTable::new(&segment).param_at_fraction(t))
+            }
+        }
+    }
+
+    fn waypoint(&self, index: usize) -> Vector {
+        let count = self.waypoints.len();
+
+        let index = match self.end {
+            PathEnd::Loop => index % count,
+            _ => index.min(count - 1),
+        };
+
+        self.waypoints[index].position
+    }
+}
+
+/// One waypoint to the next of a path's spline, as a curve of its own
+struct PathSegment<'a> {
+    spline: &'a CatmullRom,
+    from: usize,
+}
+
+impl<'a> PathSegment<'a> {
+    fn new(spline: &'a CatmullRom, from: usize) -> Self {
+        Self { spline, from }
+    }
+
+    fn spline_t(&self, t: f32) -> f32 {
+        (self.from as f32 + t.clamp(0.0, 1.0)) / self.spline.segment_count() as f32
+    }
+}
+
+impl Spline for PathSegment<'_> {
+    fn point(&self, t: f32) -> Vector {
+        self.spline.point(self.spline_t(t))
+    }
+
+    fn tangent(&self, t: f32) -> Vector {
+        self.spline.tangent(self.spline_t(t)) / self.spline.segment_count() as f32
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PlatformState {
+    Stopped,
+    Moving,
+    /// Waiting at a waypoint, with the time left
+    Paused(f32),
+}
+
+/// An object moved along a path, elevators and the like
+#[derive(Debug, Clone)]
+pub struct MovingPlatform {
+    pub handle: usize,
+    pub path: KeyframedPath,
+    pub state: PlatformState,
+    /// The waypoint it last left
+    segment: usize,
+    /// Seconds into the current segment
+    segment_time: f32,
+    /// Heading back towards the first waypoint
+    reversed: bool,
+    position: Vector,
+}
+
+impl MovingPlatform {
+    pub fn new(handle: usize, path: KeyframedPath) -> Self {
+        let position = path.waypoints.first().map(|w| w.position).unwrap_or_default();
+
+        Self {
+            handle,
+            path,
+            state: PlatformState::Stopped,
+            segment: 0,
+            segment_time: 0.0,
+            reversed: false,
+            position,
+        }
+    }
+
+    pub fn position(&self) -> Vector {
+        self.position
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Sets off from wherever it is, an elevator sitting at the end of its path has to be reversed first
+    pub fn start(&mut self) {
+        if self.path.waypoints.len() < 2 || (self.path.end == PathEnd::Stop && self.at_end()) {
+            return;
+        }
+
+        self.state = PlatformState::Moving;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = PlatformState::Stopped;
+    }
+
+    /// Heads back the way it came from wherever it is
+    pub fn reverse(&mut self) {
+        if self.path.waypoints.len() < 2 {
+            return;
+        }
+
+        // Same place on the same segment, measured from the other end
+        let next = self.next_waypoint();
+        let duration = self.segment_duration();
+
+        self.reversed = !self.reversed;
+        self.segment = next;
+        self.segment_time = (duration - self.segment_time).max(0.0);
+    }
+
+    fn at_end(&self) -> bool {
+        if self.reversed {
+            self.segment == 0
+        } else {
+            self.segment == self.path.waypoints.len() - 1
+        }
+    }
+
+    fn next_waypoint(&self) -> usize {
+        let count = self.path.waypoints.len();
+
+        if self.reversed {
+            (self.segment + count - 1) % count
+        } else {
+            (self.segment + 1) % count
+        }
+    }
+
+    fn segment_duration(&self) -> f32 {
+        let from = if self.reversed { self.next_waypoint() } else { self.segment };
+
+        self.path.segment_length(from) / self.path.speed.max(f32::EPSILON)
+    }
+
+    fn segment_position(&self, s: f32) -> Vector {
+        let t = if self.path.ease { s * s * (3.0 - 2.0 * s) } else { s };
+
+        if self.reversed {
+            self.path.position_on_segment(self.next_waypoint(), 1.0 - t)
+        } else {
+            self.path.position_on_segment(self.segment, t)
+        }
+    }
+
+    /// Moves along the path, returning how far it went so riders can be carried
+    pub fn update(&mut self, frametime: f32) -> Vector {
+        let start = self.position;
+        let mut time = frametime;
+        // Waypoints sitting on top of each other take no time to cross, don't spin on them forever
+        let mut arrivals = 0;
+
+        while time > 0.0 && arrivals <= self.path.waypoints.len() {
+            match self.state {
+                PlatformState::Stopped => break,
+                PlatformState::Paused(left) => {
+                    if left > time {
+                        self.state = PlatformState::Paused(left - time);
+                        break;
+                    }
+
+                    time -= left;
+                    self.state = PlatformState::Moving;
+                }
+                PlatformState::Moving => {
+                    let duration = self.segment_duration();
+                    let step = time.min(duration - self.segment_time);
+                    self.segment_time += step;
+                    time -= step;
+
+                    let s = if duration > 0.0 { self.segment_time / duration } else { 1.0 };
+                    self.position = self.segment_position(s.min(1.0));
+
+                    if self.segment_time >= duration {
+                        self.arrive();
+                        arrivals += 1;
+                    }
+                }
+            }
+        }
+
+        self.position - start
+    }
+
+    fn arrive(&mut self) {
+        self.segment = self.next_waypoint();
+        self.segment_time = 0.0;
+
+        if self.at_end() {
+            match self.path.end {
+                PathEnd::Stop => {
+                    self.state = PlatformState::Stopped;
+                    return;
+                }
+                PathEnd::PingPong => self.reversed = !self.reversed,
+                PathEnd::Loop => {}
+            }
+        }
+
+        let pause = self.path.waypoints[self.segment].pause;
+
+        if pause > 0.0 {
+            self.state = PlatformState::Paused(pause);
+        }
+    }
+}
+
+/// Which objects are touching which platforms this frame, filled in by the collision code
+#[derive(Debug, Clone, Default)]
+pub struct PlatformContacts {
+    riders: BTreeMap<usize, BTreeSet<usize>>,
+    /// Objects against the side of a platform, with the contact normal
+    touching: BTreeMap<usize, Vec<(usize, Vector)>>,
+}
+
+impl PlatformContacts {
+    /// Records a collision, `normal` points from the platform out to the object
+    pub fn record(&mut self, platform: usize, object: usize, normal: &Vector) {
+        if normal.y >= PLATFORM_REST_NORMAL_Y {
+            self.riders.entry(platform).or_default().insert(object);
+        } else {
+            self.touching.entry(platform).or_default().push((object, *normal));
+        }
+    }
+
+    pub fn riders(&self, platform: usize) -> impl Iterator<Item = usize> + '_ {
+        self.riders.get(&platform).into_iter().flat_map(|r| r.iter().copied())
+    }
+
+    /// How much to move each object touching a platform that moved by `delta`.
+    /// Riders go along for the ride, anything in the way gets shoved out of it.
+    pub fn carry(&self, platform: usize, delta: &Vector) -> Vec<(usize, Vector)> {
+        let mut moves: Vec<(usize, Vector)> = self.riders(platform).map(|o| (o, *delta)).collect();
+
+        for (object, normal) in self.touching.get(&platform).into_iter().flatten() {
+            let push = *delta * *normal;
+
+            if push > 0.0 && !moves.iter().any(|(o, _)| o == object) {
+                moves.push((*object, *normal * push));
+            }
+        }
+
+        moves
+    }
+
+    /// Contacts only last a frame
+    pub fn clear(&mut self) {
+        self.riders.clear();
+        self.touching.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn elevator(end: PathEnd) -> MovingPlatform {
+        MovingPlatform::new(
+            1,
+            KeyframedPath {
+                waypoints: vec![
+                    Waypoint { position: Vector::new(0.0, 0.0, 0.0), pause: 0.0 },
+                    Waypoint { position: Vector::new(0.0, 10.0, 0.0), pause: 1.0 },
+                    Waypoint { position: Vector::new(0.0, 20.0, 0.0), pause: 0.0 },
+                ],
+                speed: 10.0,
+                shape: PathShape::Linear,
+                end,
+                ease: false,
+            },
+        )
+    }
+
+    #[test]
+    fn platform_path_test() {
+        crate::test_common::setup();
+
+        let mut platform = elevator(PathEnd::Stop);
+
+        // Doesn't go anywhere until started
+        assert_eq!(platform.update(1.0), Vector::default());
+        platform.start();
+
+        let delta = platform.update(0.5);
+        assert!((delta.y - 5.0).abs() < 0.001);
+
+        // Waits a second at the middle waypoint
+        platform.update(0.5);
+        assert!(matches!(platform.state, PlatformState::Paused(_)));
+        platform.update(1.0);
+        assert!((platform.position().y - 10.0).abs() < 0.001);
+
+        platform.update(5.0);
+        assert!((platform.position().y - 20.0).abs() < 0.001);
+        assert_eq!(platform.state, PlatformState::Stopped);
+    }
+
+    #[test]
+    fn platform_reverse_test() {
+        crate::test_common::setup();
+
+        let mut platform = elevator(PathEnd::PingPong);
+        platform.start();
+        platform.update(0.4);
+
+        // Goes back down from where it is
+        platform.reverse();
+        platform.update(0.2);
+        assert!((platform.position().y - 2.0).abs() < 0.001);
+
+        // Bounces at the bottom
+        platform.update(0.3);
+        assert!(!platform.is_reversed());
+        assert!((platform.position().y - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn platform_ease_test() {
+        crate::test_common::setup();
+
+        let mut platform = elevator(PathEnd::Stop);
+        platform.path.ease = true;
+        platform.path.shape = PathShape::Spline;
+        platform.start();
+
+        // Slow out of the start, still ends up on the waypoint
+        let delta = platform.update(0.1);
+        assert!(delta.y < 1.0 && delta.y > 0.0);
+        platform.update(0.9);
+        assert!((platform.position().y - 10.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn platform_spline_speed_test() {
+        crate::test_common::setup();
+
+        let path = KeyframedPath {
+            waypoints: [(0.0, 0.0), (10.0, 0.0), (12.0, 2.0), (12.0, 20.0)]
+                .iter()
+                .map(|&(x, y)| Waypoint { position: Vector::new(x, y, 0.0), pause: 0.0 })
+                .collect(),
+            speed: 10.0,
+            shape: PathShape::Spline,
+            end: PathEnd::Stop,
+            ease: false,
+        };
+
+        // Goes through the waypoints, taking the long way round between them
+        assert!(path.position_on_segment(1, 0.0).approx_eq(&Vector::new(10.0, 0.0, 0.0), 0.001));
+        assert!(path.position_on_segment(1, 1.0).approx_eq(&Vector::new(12.0, 2.0, 0.0), 0.001));
+        assert!(path.segment_length(1) > Vector::distance(&Vector::new(10.0, 0.0, 0.0), &Vector::new(12.0, 2.0, 0.0)));
+
+        // Even steps in t cover even distances, even around the tight bend
+        let steps = 40;
+        let expected = path.segment_length(1) / steps as f32;
+
+        for i in 0..steps {
+            let a = path.position_on_segment(1, i as f32 / steps as f32);
+            let b = path.position_on_segment(1, (i + 1) as f32 / steps as f32);
+            assert!((Vector::distance(&a, &b) - expected).abs() < expected * 0.1);
+        }
+    }
+
+    #[test]
+    fn platform_contacts_test() {
+        crate::test_common::setup();
+
+        let mut contacts = PlatformContacts::default();
+        contacts.record(1, 5, &Vector::new(0.0, 1.0, 0.0));
+        contacts.record(1, 6, &Vector::new(1.0, 0.0, 0.0));
+        contacts.record(1, 7, &Vector::new(-1.0, 0.0, 0.0));
+
+        // The rider comes along, the one in front gets pushed and the one behind is left
+        let moves = contacts.carry(1, &Vector::new(2.0, 0.0, 0.0));
+        assert_eq!(moves.len(), 2);
+        assert!(moves.contains(&(5, Vector::new(2.0, 0.0, 0.0))));
+        assert!(moves.contains(&(6, Vector::new(2.0, 0.0, 0.0))));
+
+        contacts.clear();
+        assert!(contacts.carry(1, &Vector::new(2.0, 0.0, 0.0)).is_empty());
+    }
+}
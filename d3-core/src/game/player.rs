@@ -0,0 +1,166 @@
+use super::{door::KeyFlags, prelude::*, ship::MAX_PLAYER_WEAPONS};
+use crate::graphics::bitmap::Bitmap16;
+use matrix::Matrix;
+use vector::Vector;
+
+pub const N_PLAYER_GUNS: usize = 8;
+
+// Initial player stat values
+/// 100% energy to start
+pub const INITIAL_ENERGY: f32 = 100.0;
+/// 100% shields to start
+pub const INITIAL_SHIELDS: f32 = 100.0;
+
+/// go up to 200
+pub const MAX_ENERGY: f32 = 200.0;
+pub const MAX_SHIELDS: f32 = 200.0;
+
+bitflags! {
+    /// Special flags for a player.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct PlayerFlags: u32 {
+        /// Player is invincible.
+        const INVULNERABLE = 1; // PLAYER_FLAGS_INVULNERABLE
+        /// Is this player in the middle of dying?
+        const DYING = 4; // PLAYER_FLAGS_DYING
+        /// The player is just sitting there dead.
+        const DEAD = 8; // PLAYER_FLAGS_DEAD
+        /// Player has an afterburner.
+        const AFTERBURNER = 4096; // PLAYER_FLAGS_AFTERBURNER
+        /// Player has headlight boost.
+        const HEADLIGHT = 8192; // PLAYER_FLAGS_HEADLIGHT
+        /// Is the headlight stolen?
+        const HEADLIGHT_STOLEN = 16384; // PLAYER_FLAGS_HEADLIGHT_STOLEN
+        /// Player afterburner is engaged.
+        const AFTERBURN_ON = 32768; // PLAYER_FLAGS_AFTERBURN_ON
+        /// Player has a custom texture.
+        const CUSTOM_TEXTURE = 65536; // PLAYER_FLAGS_CUSTOM_TEXTURE
+        /// Player has thrusted this frame.
+        const THRUSTED = 1 << 17; // PLAYER_FLAGS_THRUSTED
+        /// Bullseye reticle should light up.
+        const BULLSEYE = 1 << 18; // PLAYER_FLAGS_BULLSEYE
+        const ZOOMED = 1 << 19; // PLAYER_FLAGS_ZOOMED
+        /// Player is using rearview.
+        const REARVIEW = 1 << 20; // PLAYER_FLAGS_REARVIEW
+        /// We need to tell the server about our movement.
+        const SEND_MOVEMENT = 1 << 21; // PLAYER_FLAGS_SEND_MOVEMENT
+        /// A sound and hud message should be displayed when invulnerability wears off.
+        const PLAY_SOUND_MSG_FOR_INVULN = 1 << 22; // PLAYER_FLAGS_PLAYSOUNDMSGFORINVULN
+    }
+}
+
+/// A stack of one kind of item the player is carrying
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InventoryItem {
+    /// Generic object id of the item
+    pub id: usize,
+    pub count: u32,
+}
+
+/// The structure for a player.  Most of this data will be for multiplayer
+#[derive(Debug, Clone)]
+pub struct Player {
+    // positional data for player starts
+    pub start_index: usize,
+    /// where the player starts
+    pub start_pos: Vector,
+    pub start_orient: Matrix,
+
+    /// the index into the ships that this player is flying
+    pub ship_index: usize,
+
+    /// The callsign of this player, for net purposes.
+    pub callsign: D3String,
+
+    pub flags: PlayerFlags,
+    pub score: i32,
+
+    /// for shield effects
+    pub damage_magnitude: f32,
+    /// for energy drain effects
+    pub edrain_magnitude: f32,
+    /// for invulnerability effects
+    pub invul_magnitude: f32,
+
+    /// Amount of energy remaining.
+    pub energy: f32,
+    /// Lives remaining, 0 = game over.
+    pub lives: u8,
+    /// Which keys the player has
+    pub keys: KeyFlags,
+    /// Time left invulnerable
+    pub invulnerable_time: f32,
+
+    /// The team number this guy is on
+    pub team: Option<u8>,
+
+    /// Mask of currently owned weapons
+    pub weapon_flags: u32,
+    /// Current level of the laser.
+    pub laser_level: u8,
+    /// Ammo left for each weapon, unused by energy weapons
+    pub weapon_ammo: [u16; MAX_PLAYER_WEAPONS],
+
+    /// The ship object this player is flying
+    pub object: Option<SharedMutRef<Object>>,
+
+    /// Logo shown on the ship, only used with `CUSTOM_TEXTURE`
+    pub custom_logo: Option<SharedMutRef<dyn Bitmap16>>,
+
+    /// Items picked up and not used yet, in the order they were picked up
+    pub inventory: Vec<InventoryItem>,
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self {
+            start_index: 0,
+            start_pos: Vector::ZERO,
+            start_orient: Matrix::IDENTITY,
+            ship_index: 0,
+            callsign: D3String::new(),
+            flags: PlayerFlags::empty(),
+            score: 0,
+            damage_magnitude: 0.0,
+            edrain_magnitude: 0.0,
+            invul_magnitude: 0.0,
+            energy: INITIAL_ENERGY,
+            lives: 3,
+            keys: KeyFlags::NONE,
+            invulnerable_time: 0.0,
+            team: None,
+            weapon_flags: 0,
+            laser_level: 0,
+            weapon_ammo: [0; MAX_PLAYER_WEAPONS],
+            object: None,
+            custom_logo: None,
+            inventory: Vec::new(),
+        }
+    }
+}
+
+impl Player {
+    pub fn add_item(&mut self, id: usize, count: u32) {
+        match self.inventory.iter_mut().find(|i| i.id == id) {
+            Some(item) => item.count += count,
+            None => self.inventory.push(InventoryItem { id, count }),
+        }
+    }
+
+    /// Takes up to `count` of an item, returning how many there were
+    pub fn remove_item(&mut self, id: usize, count: u32) -> u32 {
+        let Some(index) = self.inventory.iter().position(|i| i.id == id) else {
+            return 0;
+        };
+
+        let item = &mut self.inventory[index];
+        let taken = item.count.min(count);
+        item.count -= taken;
+
+        if item.count == 0 {
+            self.inventory.remove(index);
+        }
+
+        taken
+    }
+}
@@ -0,0 +1,186 @@
+/*
+
+Portal visibility
+
+Which rooms can be seen from the viewer's room, found by walking out through
+the portals that face the viewer. Most portals are open holes, but some have
+their face drawn, grates and windows: sight carries on through those while
+things moving through stop at them, unless the portal allows flythrough.
+
+A drawn portal face is see-through, so it can't go in with the opaque room
+faces. It's drawn in the transparent pass over whatever is behind it, farthest
+first so a window seen through another window comes out right.
+
+*/
+
+use super::{
+    prelude::*,
+    room::{Face, Room, RoomId, RoomSet},
+};
+use vector::Vector;
+
+/// A drawn portal face for the transparent pass
+#[derive(Debug, Clone)]
+pub struct PortalFaceDraw {
+    /// The room on the viewer's side of the portal
+    pub room: RoomId,
+    pub face: SharedMutRef<Face>,
+    pub distance: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RoomVisibility {
+    /// Rooms in the order they were reached, the viewer's room first
+    pub rooms: Vec<RoomId>,
+    /// Farthest first
+    pub portal_faces: Vec<PortalFaceDraw>,
+}
+
+impl RoomVisibility {
+    pub fn is_visible(&self, room: RoomId) -> bool {
+        self.rooms.contains(&room)
+    }
+}
+
+/// Walks out from `start` through the see-through portals facing `eye`, no more than `max_depth` portals deep
+pub fn find_visible_rooms(start: &SharedMutRef<Room>, eye: &Vector, max_depth: usize) -> RoomVisibility {
+    let mut visibility = RoomVisibility::default();
+    let mut visited = RoomSet::default();
+    let mut current = vec![start.clone()];
+
+    visited.insert(start.borrow().id());
+    visibility.rooms.push(start.borrow().id());
+
+    for _ in 0..max_depth {
+        let mut next = Vec::new();
+
+        for room_ref in &current {
+            let room = room_ref.borrow();
+
+            for portal in &room.portals {
+                let Some(connected_ref) = &portal.connected_room else {
+                    continue;
+                };
+
+                if !portal.is_see_through() {
+                    continue;
+                }
+
+                // Portal faces face into their room, one the eye is behind can't be seen through
+                if let Some(face_ref) = &portal.portal_face {
+                    let face = face_ref.borrow();
+                    let center = face.bounds().center();
+
+                    if (*eye - center) * face.normal < 0.0 {
+                        continue;
+                    }
+
+                    if portal.renders_face() {
+                        visibility.portal_faces.push(PortalFaceDraw {
+                            room: room.id(),
+                            face: face_ref.clone(),
+                            distance: Vector::distance(eye, &center),
+                        });
+                    }
+                }
+
+                let connected_id = connected_ref.borrow().id();
+
+                if visited.insert(connected_id) {
+                    visibility.rooms.push(connected_id);
+                    next.push(connected_ref.clone());
+                }
+            }
+        }
+
+        if next.is_empty() {
+            break;
+        }
+
+        current = next;
+    }
+
+    visibility.portal_faces.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+
+    visibility
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::room::{FaceFlags, Portal, PortalFlags, RoomStore};
+
+    /// A portal into `room` through a face at `z`, facing back along `normal_z`
+    fn portal(room: &SharedMutRef<Room>, flags: PortalFlags, z: f32, normal_z: f32) -> Portal {
+        let face = Face {
+            flags: FaceFlags::empty(),
+            num_verts: 0,
+            portal: None,
+            face_verts: Vec::new(),
+            face_uvls: Vec::new(),
+            normal: Vector { x: 0.0, y: 0.0, z: normal_z },
+            lightmap: None,
+            special_faces: (),
+            render_frame: (),
+            tmap: None,
+            light_muliple: 0,
+            min_xyz: Vector { x: -5.0, y: -5.0, z },
+            max_xyz: Vector { x: 5.0, y: 5.0, z },
+        };
+
+        Portal {
+            flags,
+            portal_face: Some(new_shared_mut_ref(face)),
+            connected_room: Some(room.clone()),
+            connected_portal: None,
+            bnode_index: (),
+            combine_master: (),
+            path_point: Vector::ZERO,
+        }
+    }
+
+    #[test]
+    fn portal_visibility_test() {
+        crate::test_common::setup();
+
+        let mut rooms = RoomStore::new();
+        let [start, hall, beyond_window, behind, walled_off, side] = [(); 6].map(|_| {
+            let id = rooms.insert(Room::default());
+            rooms.get(id).unwrap().clone()
+        });
+
+        let window = PortalFlags::RENDER_FACES;
+
+        start.borrow_mut().portals = vec![
+            portal(&hall, PortalFlags::empty(), 10.0, -1.0),
+            portal(&side, window, 5.0, -1.0),
+            // Faces away, the eye is behind it
+            portal(&behind, PortalFlags::empty(), -10.0, -1.0),
+        ];
+        hall.borrow_mut().portals = vec![
+            portal(&start, PortalFlags::empty(), 10.0, 1.0),
+            portal(&beyond_window, window, 20.0, -1.0),
+            portal(&walled_off, PortalFlags::BLOCK, 15.0, -1.0),
+        ];
+
+        let id = |room: &SharedMutRef<Room>| room.borrow().id();
+        let eye = Vector::default();
+        let visibility = find_visible_rooms(&start, &eye, 8);
+
+        assert_eq!(visibility.rooms, vec![id(&start), id(&hall), id(&side), id(&beyond_window)]);
+        assert!(!visibility.is_visible(id(&behind)) && !visibility.is_visible(id(&walled_off)));
+
+        // Both windows drawn, the far one first
+        let faces: Vec<_> = visibility.portal_faces.iter().map(|f| (f.room, f.distance)).collect();
+        assert_eq!(faces, vec![(id(&hall), 20.0), (id(&start), 5.0)]);
+
+        // One portal deep stops short of the far window
+        assert_eq!(find_visible_rooms(&start, &eye, 1).portal_faces.len(), 1);
+
+        // Windows are solid unless they allow flythrough or the check is for line of sight
+        let window_portal = &hall.borrow().portals[1];
+        assert!(window_portal.is_solid(false) && !window_portal.is_solid(true));
+        assert!(!portal(&hall, window | PortalFlags::RENDERED_FLYTHROUGH, 0.0, 1.0).is_solid(false));
+        assert!(portal(&hall, PortalFlags::BLOCK, 0.0, 1.0).is_solid(true));
+    }
+}
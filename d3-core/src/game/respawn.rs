@@ -0,0 +1,240 @@
+/*
+
+Respawning
+
+Where a ship comes back in after dying in a multiplayer game. The level
+places player start objects around the map, the one picked is the one
+farthest from the nearest enemy so nobody spawns into a gun barrel. A start
+with something sitting on it is passed over, it's checked with a sphere the
+size of the ship through the same object search the physics uses.
+
+A freshly spawned ship can't be hurt for a few seconds. The shell it wears
+while protected pulses through the player's `invul_magnitude`, which the
+renderer draws it from, and speeds up just before it wears off.
+
+*/
+
+use matrix::Matrix;
+use vector::Vector;
+
+use super::{
+    physics::{collide::CollisionMap, intersection::IntersectionFinder},
+    player::{Player, PlayerFlags},
+    prelude::*,
+    room::Room,
+    terrain::{cell_index_at, Terrain},
+};
+use crate::common::WeakHandle;
+
+/// Seconds a ship can't be hurt after spawning
+pub const SPAWN_INVULNERABLE_TIME: f32 = 3.0;
+
+/// For the last this many seconds of protection the shell pulses faster, as a warning
+const SPAWN_WARNING_TIME: f32 = 1.0;
+
+/// Pulses per second of the protection shell
+const SHELL_PULSE_RATE: f32 = 2.0;
+
+/// Most objects looked at when checking a start is clear
+const SPAWN_CHECK_MAX_OBJECTS: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct SpawnPoint {
+    pub position: Vector,
+    pub orientation: Matrix,
+    pub room: SharedMutRef<Room>,
+}
+
+impl SpawnPoint {
+    /// A spawn point for a player start object, `None` for anything else or one that isn't in a room
+    pub fn from_object(object: &Object) -> Option<Self> {
+        if object.typedef().class != ObjectClass::Player {
+            return None;
+        }
+
+        Some(Self {
+            position: object.position,
+            orientation: object.orientation,
+            room: object.parent_room.upgrade()?,
+        })
+    }
+
+    /// Whether a ship of `radius` fits here without overlapping anything but `ignore`, its own ship
+    pub fn is_clear(
+        &self,
+        radius: f32,
+        ignore: Option<&SharedMutRef<Object>>,
+        finder: &mut IntersectionFinder,
+        big_objects: &[SharedMutRef<Object>],
+        collisions: &CollisionMap,
+        terrain: &Terrain,
+    ) -> bool {
+        let cell = if self.room.borrow().is_outside {
+            match cell_index_at(&self.position) {
+                Some(cell) => cell,
+                None => return false,
+            }
+        } else {
+            0
+        };
+
+        let mut nearby = Vec::new();
+        finder.quick_dist_object_list(
+            &self.position,
+            (&self.room, cell),
+            radius,
+            &mut nearby,
+            SPAWN_CHECK_MAX_OBJECTS,
+            big_objects,
+            collisions,
+            false,
+            false,
+            false,
+            false,
+            terrain,
+        );
+
+        // The search goes by boxes, this is the sphere check proper
+        nearby.iter().all(|object_ref| {
+            if ignore.is_some_and(|ignore| Rc::ptr_eq(ignore, object_ref)) {
+                return true;
+            }
+
+            let object = object_ref.borrow();
+            Vector::distance(&object.position, &self.position) >= radius + object.size
+        })
+    }
+}
+
+/// The spawn point farthest from its nearest enemy, among those `is_clear` lets through.
+/// With no enemies about it's the first clear one
+pub fn choose_spawn_point(points: &[SpawnPoint], enemies: &[Vector], mut is_clear: impl FnMut(&SpawnPoint) -> bool) -> Option<usize> {
+    points
+        .iter()
+        .enumerate()
+        .filter(|(_, point)| is_clear(point))
+        .map(|(index, point)| {
+            let nearest = enemies
+                .iter()
+                .map(|enemy| Vector::distance(enemy, &point.position))
+                .fold(f32::INFINITY, f32::min);
+
+            (index, nearest)
+        })
+        .fold(None, |best: Option<(usize, f32)>, (index, nearest)| match best {
+            Some((_, best_nearest)) if best_nearest >= nearest => best,
+            _ => Some((index, nearest)),
+        })
+        .map(|(index, _)| index)
+}
+
+/// Puts the player's ship on a spawn point and makes it invulnerable for a while
+pub fn respawn_at(player: &mut Player, ship: &mut Object, point: &SpawnPoint) {
+    let extent = Vector::new(ship.size, ship.size, ship.size);
+
+    ship.position = point.position;
+    ship.last_position = point.position;
+    ship.orientation = point.orientation;
+    ship.last_orientation = point.orientation;
+    ship.min_xzy = point.position - extent;
+    ship.max_xzy = point.position + extent;
+    ship.parent_room = WeakHandle::to(&point.room);
+
+    player.flags.remove(PlayerFlags::DYING | PlayerFlags::DEAD);
+    grant_spawn_protection(player, SPAWN_INVULNERABLE_TIME);
+
+    debug!(target: "game", "player {} respawned at {:?}", player.callsign, point.position);
+}
+
+pub fn grant_spawn_protection(player: &mut Player, time: f32) {
+    player.flags |= PlayerFlags::INVULNERABLE | PlayerFlags::PLAY_SOUND_MSG_FOR_INVULN;
+    player.invulnerable_time = time;
+    player.invul_magnitude = 1.0;
+}
+
+/// Counts the protection down and pulses the shell, true on the frame it wears off
+pub fn update_spawn_protection(player: &mut Player, frametime: f32) -> bool {
+    if !player.flags.contains(PlayerFlags::INVULNERABLE) || player.invulnerable_time <= 0.0 {
+        return false;
+    }
+
+    player.invulnerable_time -= frametime;
+
+    if player.invulnerable_time <= 0.0 {
+        player.invulnerable_time = 0.0;
+        player.invul_magnitude = 0.0;
+        player.flags.remove(PlayerFlags::INVULNERABLE);
+        return true;
+    }
+
+    let rate = if player.invulnerable_time < SPAWN_WARNING_TIME { SHELL_PULSE_RATE * 3.0 } else { SHELL_PULSE_RATE };
+    let phase = player.invulnerable_time * rate * std::f32::consts::TAU;
+    player.invul_magnitude = 0.5 + 0.5 * phase.cos();
+
+    false
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::room::RoomStore;
+
+    fn object(class: ObjectClass, position: Vector, size: f32, room: &SharedMutRef<Room>) -> SharedMutRef<Object> {
+        let mut typedef = ObjectTypeDef::new("test", class);
+        typedef.size = size;
+
+        let mut object = Object::new(typedef);
+        let extent = Vector::new(size, size, size);
+        object.position = position;
+        object.min_xzy = position - extent;
+        object.max_xzy = position + extent;
+        object.parent_room = WeakHandle::to(room);
+
+        new_shared_mut_ref(object)
+    }
+
+    #[test]
+    fn respawn_test() {
+        crate::test_common::setup();
+
+        let terrain = Terrain::default();
+        let collisions = CollisionMap::default();
+        let mut rooms = RoomStore::new();
+        let id = rooms.insert(Room::default());
+        let room = rooms.get(id).unwrap().clone();
+
+        let starts: Vec<_> = [0.0, 50.0, 100.0]
+            .map(|x| object(ObjectClass::Player, Vector::new(x, 0.0, 0.0), 4.0, &room))
+            .into_iter()
+            .collect();
+        let points: Vec<_> = starts.iter().map(|s| SpawnPoint::from_object(&s.borrow()).unwrap()).collect();
+        assert!(SpawnPoint::from_object(&Object::new(ObjectTypeDef::new("robot", ObjectClass::Robot))).is_none());
+
+        // The farthest start is blocked by a robot parked on it
+        let robot = object(ObjectClass::Robot, Vector::new(102.0, 0.0, 0.0), 3.0, &room);
+        room.borrow_mut().objects = vec![robot.clone()];
+
+        let mut finder = IntersectionFinder::new(terrain.dims()).unwrap();
+        finder.set_room_count(rooms.id_bound());
+        let mut is_clear = |point: &SpawnPoint| point.is_clear(4.0, None, &mut finder, &[], &collisions, &terrain);
+
+        let enemies = [Vector::new(-10.0, 0.0, 0.0)];
+        assert_eq!(choose_spawn_point(&points, &enemies, &mut is_clear), Some(1));
+        assert_eq!(choose_spawn_point(&points, &[], &mut is_clear), Some(0));
+
+        // Its own ship doesn't count against it
+        assert!(points[2].is_clear(4.0, Some(&robot), &mut finder, &[], &collisions, &terrain));
+
+        let mut player = Player::default();
+        let ship = object(ObjectClass::Player, Vector::new(500.0, 0.0, 0.0), 4.0, &room);
+        respawn_at(&mut player, &mut ship.borrow_mut(), &points[1]);
+        assert_eq!(ship.borrow().position, Vector::new(50.0, 0.0, 0.0));
+        assert!(player.flags.contains(PlayerFlags::INVULNERABLE));
+
+        assert!(!update_spawn_protection(&mut player, SPAWN_INVULNERABLE_TIME - 0.5));
+        assert!(player.invul_magnitude >= 0.0 && player.invul_magnitude <= 1.0);
+        assert!(update_spawn_protection(&mut player, 1.0));
+        assert!(!player.flags.contains(PlayerFlags::INVULNERABLE));
+        assert_eq!(player.invul_magnitude, 0.0);
+    }
+}
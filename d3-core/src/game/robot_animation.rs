@@ -0,0 +1,356 @@
+use super::{
+    object_static_behavior::{Animated, AnimationEntry, StaticWeaponBattery},
+    prelude::*,
+};
+
+/// How long it takes to ease from one range into the next
+pub const ANIM_BLEND_TIME: f32 = 0.15;
+
+/// What the AI is up to, each one plays its own range from the robot's table entry
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RobotAnimState {
+    Idle = 0,
+    Alert = 1,
+    Attack = 2,
+    Melee = 3,
+    Flee = 4,
+    Flinch = 5,
+    Death = 6,
+}
+
+impl RobotAnimState {
+    /// Index of the range in `Animated`
+    pub fn table_index(&self) -> usize {
+        *self as usize
+    }
+
+    /// States that keep cycling until the AI picks something else
+    pub fn is_looping(&self) -> bool {
+        matches!(self, RobotAnimState::Idle | RobotAnimState::Alert | RobotAnimState::Flee)
+    }
+
+    /// Where a one shot animation goes once it has played, death just holds on the last frame
+    pub fn follow_up(&self) -> Option<RobotAnimState> {
+        match self {
+            RobotAnimState::Attack | RobotAnimState::Melee | RobotAnimState::Flinch => Some(RobotAnimState::Alert),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnimEventKind {
+    /// Let go of the shots for a firing mask
+    FireWeapon { mask: usize },
+    PlaySound { sound: usize },
+    /// A one shot range reached its end
+    Finished { state: RobotAnimState },
+}
+
+/// Something that happens when an animation passes a keyframe
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnimEvent {
+    pub state: RobotAnimState,
+    pub frame: f32,
+    pub kind: AnimEventKind,
+}
+
+/// Fire events for each firing mask, keyed off the fire frames in the weapon battery
+pub fn weapon_fire_events(battery: &StaticWeaponBattery) -> Vec<AnimEvent> {
+    (0..battery.num_masks.min(battery.anim_fire_frame.len()))
+        .map(|mask| AnimEvent {
+            state: RobotAnimState::Attack,
+            frame: battery.anim_fire_frame[mask],
+            kind: AnimEventKind::FireWeapon { mask },
+        })
+        .collect()
+}
+
+/// The frame to draw, easing out of `previous` while a blend is running
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AnimPose {
+    pub frame: f32,
+    pub previous: Option<f32>,
+    /// How much of `frame` to use, 1 once the blend is done
+    pub weight: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Blend {
+    from_frame: f32,
+    elapsed: f32,
+}
+
+/// Plays a robot's animation ranges as its AI changes state
+#[derive(Debug, Clone)]
+pub struct RobotAnimator {
+    state: RobotAnimState,
+    frame: f32,
+    /// Set on entering a state so events sitting on the first frame still go off
+    entered: bool,
+    finished: bool,
+    blend: Option<Blend>,
+}
+
+impl RobotAnimator {
+    pub fn new(state: RobotAnimState, animated: &Animated) -> Self {
+        Self {
+            state,
+            frame: start_frame(animated, state),
+            entered: true,
+            finished: false,
+            blend: None,
+        }
+    }
+
+    pub fn state(&self) -> RobotAnimState {
+        self.state
+    }
+
+    pub fn frame(&self) -> f32 {
+        self.frame
+    }
+
+    /// Switches ranges, blending from wherever the model is now, false once the robot is dying
+    pub fn set_state(&mut self, state: RobotAnimState, animated: &Animated) -> bool {
+        if self.state == RobotAnimState::Death {
+            return false;
+        }
+
+        if self.state == state {
+            return true;
+        }
+
+        self.blend = Some(Blend {
+            from_frame: self.frame,
+            elapsed: 0.0,
+        });
+
+        self.state = state;
+        self.frame = start_frame(animated, state);
+        self.entered = true;
+        self.finished = false;
+
+        true
+    }
+
+    /// Advances the animation, returning whatever it passed over this frame
+    pub fn update(&mut self, frametime: f32, animated: &Animated, events: &[AnimEvent]) -> Vec<AnimEventKind> {
+        let mut fired = Vec::new();
+
+        if let Some(blend) = &mut self.blend {
+            blend.elapsed += frametime;
+
+            if blend.elapsed >= ANIM_BLEND_TIME {
+                self.blend = None;
+            }
+        }
+
+        if self.finished {
+            return fired;
+        }
+
+        let Some(entry) = animated.entry(self.state.table_index()) else {
+            return fired;
+        };
+
+        let (from, to) = (entry.range().start as f32, entry.range().end as f32);
+        let prev = self.frame;
+        let mut next = match frames_per_second(entry) {
+            Some(fps) => prev + fps * frametime,
+            // No time to play it in, a loop holds its first frame and anything else skips to the end
+            None if self.state.is_looping() => from,
+            None => to,
+        };
+        let mut wrapped = false;
+
+        if next >= to {
+            if self.state.is_looping() && to > from {
+                next = from + (next - from) % (to - from);
+                wrapped = true;
+            } else {
+                next = to;
+                self.finished = true;
+            }
+        }
+
+        let crossed = |f: f32| {
+            let after_prev = if self.entered { f >= prev } else { f > prev };
+
+            if wrapped {
+                (after_prev && f <= to) || (f >= from && f <= next)
+            } else {
+                after_prev && f <= next
+            }
+        };
+
+        fired.extend(
+            events
+                .iter()
+                .filter(|e| e.state == self.state && crossed(e.frame))
+                .map(|e| e.kind),
+        );
+
+        self.frame = next;
+        self.entered = false;
+
+        if self.finished {
+            fired.push(AnimEventKind::Finished { state: self.state });
+
+            if let Some(state) = self.state.follow_up() {
+                self.set_state(state, animated);
+            }
+        }
+
+        fired
+    }
+
+    pub fn pose(&self) -> AnimPose {
+        match &self.blend {
+            Some(blend) => AnimPose {
+                frame: self.frame,
+                previous: Some(blend.from_frame),
+                weight: (blend.elapsed / ANIM_BLEND_TIME).clamp(0.0, 1.0),
+            },
+            None => AnimPose {
+                frame: self.frame,
+                previous: None,
+                weight: 1.0,
+            },
+        }
+    }
+}
+
+fn start_frame(animated: &Animated, state: RobotAnimState) -> f32 {
+    animated
+        .entry(state.table_index())
+        .map(|e| e.range().start as f32)
+        .unwrap_or(0.0)
+}
+
+/// None for an entry with no time to play it in
+fn frames_per_second(entry: &AnimationEntry) -> Option<f32> {
+    if entry.spc() <= 0.0 {
+        return None;
+    }
+
+    let span = entry.range().end.saturating_sub(entry.range().start) as f32;
+
+    Some(span / entry.spc())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn robot() -> Animated {
+        Animated::new(vec![
+            AnimationEntry::new(0..10, 1.0),
+            AnimationEntry::new(10..20, 1.0),
+            AnimationEntry::new(20..30, 0.5),
+            AnimationEntry::new(30..35, 0.5),
+            AnimationEntry::new(35..45, 1.0),
+            AnimationEntry::new(45..50, 0.25),
+            AnimationEntry::new(50..70, 2.0),
+        ])
+    }
+
+    #[test]
+    fn robot_anim_loop_test() {
+        crate::test_common::setup();
+
+        let animated = robot();
+        let mut animator = RobotAnimator::new(RobotAnimState::Idle, &animated);
+
+        animator.update(0.5, &animated, &[]);
+        assert_eq!(animator.frame(), 5.0);
+
+        // Idle goes round and round
+        animator.update(0.7, &animated, &[]);
+        assert!((animator.frame() - 2.0).abs() < 0.01);
+        assert_eq!(animator.state(), RobotAnimState::Idle);
+    }
+
+    #[test]
+    fn robot_anim_blend_test() {
+        crate::test_common::setup();
+
+        let animated = robot();
+        let mut animator = RobotAnimator::new(RobotAnimState::Idle, &animated);
+        animator.update(0.3, &animated, &[]);
+        animator.set_state(RobotAnimState::Alert, &animated);
+
+        let pose = animator.pose();
+        assert_eq!(pose.frame, 10.0);
+        assert_eq!(pose.previous, Some(3.0));
+        assert_eq!(pose.weight, 0.0);
+
+        animator.update(ANIM_BLEND_TIME, &animated, &[]);
+        assert!(animator.pose().previous.is_none());
+
+        // Once dying there's no going back
+        animator.set_state(RobotAnimState::Death, &animated);
+        assert!(!animator.set_state(RobotAnimState::Alert, &animated));
+        let events = animator.update(5.0, &animated, &[]);
+        assert_eq!(events, vec![AnimEventKind::Finished { state: RobotAnimState::Death }]);
+        assert_eq!(animator.frame(), 70.0);
+        assert!(animator.update(1.0, &animated, &[]).is_empty());
+    }
+
+    #[test]
+    fn robot_anim_event_test() {
+        crate::test_common::setup();
+
+        let animated = robot();
+        let events = [
+            AnimEvent {
+                state: RobotAnimState::Attack,
+                frame: 20.0,
+                kind: AnimEventKind::PlaySound { sound: 3 },
+            },
+            AnimEvent {
+                state: RobotAnimState::Attack,
+                frame: 25.0,
+                kind: AnimEventKind::FireWeapon { mask: 0 },
+            },
+        ];
+
+        let mut animator = RobotAnimator::new(RobotAnimState::Alert, &animated);
+        animator.set_state(RobotAnimState::Attack, &animated);
+
+        // The sound on the first frame goes off right away, the shot waits for frame 25
+        assert_eq!(animator.update(0.1, &animated, &events), vec![AnimEventKind::PlaySound { sound: 3 }]);
+        assert_eq!(animator.update(0.2, &animated, &events), vec![AnimEventKind::FireWeapon { mask: 0 }]);
+
+        // Attacks finish and drop back to alert
+        let fired = animator.update(0.5, &animated, &events);
+        assert_eq!(fired, vec![AnimEventKind::Finished { state: RobotAnimState::Attack }]);
+        assert_eq!(animator.state(), RobotAnimState::Alert);
+    }
+
+    #[test]
+    fn robot_anim_instant_test() {
+        crate::test_common::setup();
+
+        let animated = Animated::new(vec![
+            AnimationEntry::new(0..10, 0.0),
+            AnimationEntry::new(10..20, 1.0),
+            AnimationEntry::new(20..30, 0.5),
+            AnimationEntry::new(30..35, 0.5),
+            AnimationEntry::new(35..45, 1.0),
+            AnimationEntry::new(45..50, 0.25),
+            AnimationEntry::new(50..70, 0.0),
+        ]);
+
+        // A loop with no time to play in sits on its first frame
+        let mut animator = RobotAnimator::new(RobotAnimState::Idle, &animated);
+        animator.update(0.5, &animated, &[]);
+        assert_eq!(animator.frame(), 0.0);
+
+        // Anything else is over straight away
+        animator.set_state(RobotAnimState::Death, &animated);
+        let events = animator.update(0.1, &animated, &[]);
+        assert_eq!(events, vec![AnimEventKind::Finished { state: RobotAnimState::Death }]);
+        assert_eq!(animator.frame(), 70.0);
+    }
+}
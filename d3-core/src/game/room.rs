@@ -1,11 +1,10 @@
-use core::sync::atomic::{AtomicUsize, Ordering};
 use std::cell::RefCell;
 use std::collections::LinkedList;
 use std::{ops::Range, rc::Rc};
-use crate::common::SharedMutRef;
+use crate::common::{new_shared_mut_ref, SharedMutRef};
 use crate::graphics::UVCoord;
 use crate::string::D3String;
-use crate::{graphics::lightmap::LightMap16, math::vector::Vector};
+use crate::{graphics::lightmap::LightMap16, math::{bounds::Aabb, vector::Vector}};
 use bitflags::bitflags;
 use super::context::GameType;
 
@@ -15,9 +14,8 @@ use super::object::Object;
 use super::visual_effects::VisualEffect;
 use super::{context::BindingStore, door::Doorway};
 
-pub const MAX_ROOMS: usize = 400;
-
-static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+/// Where a room is in the level's `RoomStore`, it stays the same for as long as the room is around
+pub type RoomId = usize;
 
 pub struct RoomChanges {
     room: Rc<Room>,
@@ -73,7 +71,7 @@ bitflags! {
 bitflags! {
     /// Flags representing various properties of a portal.
     #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-    struct PortalFlags: u32 {
+    pub struct PortalFlags: u32 {
         /// Render the face(s) in the portal.
         const RENDER_FACES            = 0x0001;
         /// Allow flythrough of rendered faces.
@@ -143,6 +141,8 @@ bitflags! {
         const SECRET                 = 0x10000000;
         /// This room does not get lit.
         const NO_LIGHT               = 0x20000000;
+        /// Room is a shield recharging center (not in the original, for mods).
+        const SHIELDCEN              = 0x40000000;
     }
 }
 
@@ -163,15 +163,22 @@ pub struct Face {
     pub face_verts: Vec<usize>,
     pub face_uvls: Vec<UVCoord>,
     pub normal: Vector,
-    pub lightmap: Option<Rc<LightMap16>>,
+    pub lightmap: Option<SharedMutRef<LightMap16>>,
     pub special_faces: (),
     pub render_frame: (),
-    pub tmap: (),
+    /// The face's texture, an index into the level's textures
+    pub tmap: Option<usize>,
     pub light_muliple: u8,
     pub min_xyz: Vector,
     pub max_xyz: Vector
 }
 
+impl Face {
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(self.min_xyz, self.max_xyz)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Portal {
     pub flags: PortalFlags,
@@ -185,12 +192,40 @@ pub struct Portal {
     pub path_point: Vector,
 }
 
+impl Portal {
+    /// The portal's face is drawn, a grate or a window, but sight still carries on through
+    pub fn renders_face(&self) -> bool {
+        self.flags.contains(PortalFlags::RENDER_FACES)
+    }
+
+    /// Whether the connected room can be seen through the portal
+    pub fn is_see_through(&self) -> bool {
+        !self.flags.contains(PortalFlags::BLOCK)
+    }
+
+    /// Whether things moving through stop at the portal. A drawn face stops them unless it
+    /// allows flythrough, `ignore_render_through` lets line of sight checks past it anyway
+    pub fn is_solid(&self, ignore_render_through: bool) -> bool {
+        if self.flags.contains(PortalFlags::BLOCK) {
+            return true;
+        }
+
+        self.renders_face() && !self.flags.contains(PortalFlags::RENDERED_FLYTHROUGH) && !ignore_render_through
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VecRange {
     pub min: Vector,
     pub max: Vector
 }
 
+impl From<&VecRange> for Aabb {
+    fn from(range: &VecRange) -> Self {
+        Aabb::new(range.min, range.max)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BoundingBoxFaceList {
     pub faces: Vec<usize>,
@@ -212,7 +247,8 @@ pub struct BoundingBoxHierarchy {
 
 #[derive(Debug, GameType)]
 pub struct Room {
-    id: usize,
+    /// Given by the `RoomStore` the room goes into
+    id: RoomId,
     pub flags: RoomFlags,
 
     /// Polygon count
@@ -249,18 +285,41 @@ pub struct Room {
 impl Default for Room {
     fn default() -> Self {
         Self { 
-            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            id: 0,
+            flags: RoomFlags::empty(),
+            face_count: 0,
+            portal_count: 0,
+            vert_count: 0,
+            faces: Vec::new(),
+            portals: Vec::new(),
+            vertices: Vec::new(),
             assigned_door_data: None,
-            ..Default::default()
+            name: None,
+            objects: Vec::new(),
+            max_xyz: Vector::ZERO,
+            min_xyz: Vector::ZERO,
+            last_drawn: 0.0,
+            bounding_box: BoundingBoxHierarchy {
+                range: VecRange { min: Vector::ZERO, max: Vector::ZERO },
+                regions: Vec::new(),
+            },
+            nodes: new_shared_mut_ref(Vec::new()),
+            is_outside: false,
+            visual_effects: Vec::new(),
         }
     }
 }
 
 impl Room {
-    pub fn id(&self) -> usize {
+    pub fn id(&self) -> RoomId {
         self.id
     }
 
+    /// Bounds used for external room visibility checking
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(self.min_xyz, self.max_xyz)
+    }
+
     pub fn assign_door(&mut self, value: RoomDoorData) {
         self.assigned_door_data = Some(value);
     }
@@ -308,6 +367,113 @@ impl RoomDoorData {
     }
 }
 
+/// The level's rooms. As many as the level has, a room's id is its slot and slots
+/// aren't shifted or reused when rooms go, so ids held elsewhere stay good
+#[derive(Debug, Default)]
+pub struct RoomStore {
+    slots: Vec<Option<SharedMutRef<Room>>>,
+    count: usize,
+}
+
+impl RoomStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the room in and gives it its id
+    pub fn insert(&mut self, mut room: Room) -> RoomId {
+        let id = self.slots.len();
+        room.id = id;
+
+        self.slots.push(Some(new_shared_mut_ref(room)));
+        self.count += 1;
+
+        id
+    }
+
+    pub fn get(&self, id: RoomId) -> Option<&SharedMutRef<Room>> {
+        self.slots.get(id).and_then(|slot| slot.as_ref())
+    }
+
+    pub fn remove(&mut self, id: RoomId) -> Option<SharedMutRef<Room>> {
+        let room = self.slots.get_mut(id).and_then(|slot| slot.take());
+
+        if room.is_some() {
+            self.count -= 1;
+        }
+
+        room
+    }
+
+    /// Rooms in the store
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// One past the highest id given out, what tables kept per room need to be sized to
+    pub fn id_bound(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RoomId, &SharedMutRef<Room>)> {
+        self.slots.iter().enumerate().filter_map(|(id, slot)| slot.as_ref().map(|room| (id, room)))
+    }
+
+    /// Empties the store for the next level, ids start over
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.count = 0;
+    }
+}
+
+/// A set of rooms kept as a bit per id, for marking rooms visited while walking portals.
+/// Size it from `RoomStore::id_bound` when a level loads, it grows if a room past that turns up
+#[derive(Debug, Clone, Default)]
+pub struct RoomSet {
+    bits: Vec<u64>,
+}
+
+impl RoomSet {
+    pub fn with_room_count(room_count: usize) -> Self {
+        let mut set = Self::default();
+        set.resize(room_count);
+        set
+    }
+
+    /// Makes room for ids below `room_count`, never shrinks
+    pub fn resize(&mut self, room_count: usize) {
+        let words = room_count.div_ceil(64);
+
+        if words > self.bits.len() {
+            self.bits.resize(words, 0);
+        }
+    }
+
+    /// Adds the room, true if it wasn't in the set already
+    pub fn insert(&mut self, id: RoomId) -> bool {
+        self.resize(id + 1);
+
+        let (word, bit) = (id / 64, 1u64 << (id % 64));
+        let added = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+
+        added
+    }
+
+    pub fn contains(&self, id: RoomId) -> bool {
+        self.bits.get(id / 64).is_some_and(|word| word & (1u64 << (id % 64)) != 0)
+    }
+
+    /// Empties the set, keeping its size
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+}
+
 // TODO: These functions I dislike, but need for now...
 pub fn is_room_outside(x: usize) -> bool {
     (x & 0x80000000) != 0
@@ -363,4 +529,48 @@ pub fn get_ij(normal: &Vector, ii: &mut usize, jj: &mut usize) {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn room_store_test() {
+        crate::test_common::setup();
+
+        let mut store = RoomStore::new();
+        let first = store.insert(Room::default());
+        let second = store.insert(Room::default());
+        let third = store.insert(Room::default());
+
+        assert_eq!(store.get(second).unwrap().borrow().id(), second);
+
+        // Taking a room out leaves the others where they were
+        assert!(store.remove(second).is_some());
+        assert!(store.get(second).is_none());
+        assert_eq!(store.get(third).unwrap().borrow().id(), third);
+        assert_eq!(store.iter().map(|(id, _)| id).collect::<Vec<_>>(), vec![first, third]);
+        assert_eq!((store.len(), store.id_bound()), (2, 3));
+
+        // No holes are filled
+        assert_eq!(store.insert(Room::default()), 3);
+    }
+
+    #[test]
+    fn room_set_test() {
+        crate::test_common::setup();
+
+        let mut set = RoomSet::with_room_count(10);
+        assert!(set.insert(3));
+        assert!(!set.insert(3));
+        assert!(set.contains(3) && !set.contains(4));
+
+        // Past the size it was made for
+        assert!(set.insert(500));
+        assert!(set.contains(500) && !set.contains(1000));
+
+        set.clear();
+        assert!(!set.contains(3));
+    }
+}
@@ -0,0 +1,472 @@
+/*
+
+Scorch marks burned into face lightmaps
+
+Weapon impacts darken the lightmap texels around where they hit instead
+of adding geometry. The face is flattened onto a plane to find where the
+hit lands on its lightmap and a soft round splat is darkened in. The
+untouched lightmap is kept aside so marks can fade out again, and only
+the area a mark covers is flagged for upload.
+
+*/
+
+use std::collections::VecDeque;
+
+use super::prelude::*;
+use super::room::{Face, Room};
+use vector::Vector;
+use crate::graphics::lightmap::LightMap16;
+
+/// Marks a single face keeps, the oldest is dropped when another lands
+pub const MAX_BURNS_PER_FACE: usize = 8;
+
+/// Faces that can carry marks at once, the one that was hit longest ago is cleaned up first
+pub const MAX_BURNED_FACES: usize = 64;
+
+/// How long a mark stays, including its fade
+pub const BURN_LIFETIME: f32 = 30.0;
+
+/// Marks fade back to the original lighting over the end of their life
+pub const BURN_FADE_TIME: f32 = 5.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Burn {
+    /// Center in lightmap texels
+    pub x: f32,
+    pub y: f32,
+    /// Radius in lightmap texels
+    pub radius: f32,
+    /// How dark the center gets, 0 to 1
+    pub strength: f32,
+    pub time: f32,
+}
+
+impl Burn {
+    fn fade(&self, gametime: f32) -> f32 {
+        let left = BURN_LIFETIME - (gametime - self.time);
+        (left / BURN_FADE_TIME).clamp(0.0, 1.0)
+    }
+
+    fn is_expired(&self, gametime: f32) -> bool {
+        gametime - self.time >= BURN_LIFETIME
+    }
+
+    fn is_fading(&self, gametime: f32) -> bool {
+        self.fade(gametime) < 1.0
+    }
+
+    /// Texels covered, clamped to the lightmap
+    fn rect(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        let clamp = |v: f32, size: usize| (v.max(0.0) as usize).min(size.saturating_sub(1));
+
+        (
+            clamp((self.x - self.radius).floor(), width),
+            clamp((self.y - self.radius).floor(), height),
+            clamp((self.x + self.radius).ceil(), width),
+            clamp((self.y + self.radius).ceil(), height),
+        )
+    }
+
+    /// Darkening at a texel, falls off smoothly to nothing at the radius
+    fn darkness(&self, x: usize, y: usize, gametime: f32) -> f32 {
+        let dx = x as f32 + 0.5 - self.x;
+        let dy = y as f32 + 0.5 - self.y;
+        let d2 = (dx * dx + dy * dy) / (self.radius * self.radius);
+
+        if d2 >= 1.0 {
+            return 0.0;
+        }
+
+        let falloff = 1.0 - d2;
+        self.strength * falloff * falloff * self.fade(gametime)
+    }
+}
+
+/// Maps points on a face onto its lightmap, by flattening the face onto its plane
+#[derive(Debug, Copy, Clone)]
+pub struct FaceProjection {
+    origin: Vector,
+    u_axis: Vector,
+    v_axis: Vector,
+    u_range: (f32, f32),
+    v_range: (f32, f32),
+}
+
+impl FaceProjection {
+    pub fn new(vertices: &[Vector], face: &Face) -> Option<Self> {
+        let corners: Vec<Vector> = face.face_verts.iter().filter_map(|&i| vertices.get(i).copied()).collect();
+
+        if corners.len() < 3 {
+            return None;
+        }
+
+        let origin = corners[0];
+        let edge = corners[1] - origin;
+
+        if Vector::magnitude(&edge) <= f32::EPSILON {
+            return None;
+        }
+
+        let u_axis = edge.normalized();
+        let v_axis = face.normal.cross(&u_axis).normalized();
+
+        let range = |axis: Vector| {
+            corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), c| {
+                let d = (*c - origin) * axis;
+                (lo.min(d), hi.max(d))
+            })
+        };
+
+        let u_range = range(u_axis);
+        let v_range = range(v_axis);
+
+        if u_range.1 - u_range.0 <= f32::EPSILON || v_range.1 - v_range.0 <= f32::EPSILON {
+            return None;
+        }
+
+        Some(Self { origin, u_axis, v_axis, u_range, v_range })
+    }
+
+    /// Where a point on (or near) the face lands, 0 to 1 across the face's extent
+    pub fn project(&self, point: &Vector) -> (f32, f32) {
+        let d = *point - self.origin;
+        let u = (d * self.u_axis - self.u_range.0) / (self.u_range.1 - self.u_range.0);
+        let v = (d * self.v_axis - self.v_range.0) / (self.v_range.1 - self.v_range.0);
+        (u, v)
+    }
+
+    /// World units per unit of projected u and v
+    pub fn extent(&self) -> (f32, f32) {
+        (self.u_range.1 - self.u_range.0, self.v_range.1 - self.v_range.0)
+    }
+}
+
+#[derive(Debug)]
+struct BurnedFace {
+    room: usize,
+    face: usize,
+    lightmap: SharedMutRef<LightMap16>,
+    /// The lightmap before any marks, marks are always drawn over this
+    pristine: Vec<u16>,
+    burns: VecDeque<Burn>,
+    last_hit: f32,
+}
+
+impl BurnedFace {
+    /// Redraws the texels in a rect from the pristine copy with every mark on top
+    fn redraw(&self, rect: (usize, usize, usize, usize), gametime: f32) {
+        let mut lightmap = self.lightmap.borrow_mut();
+        let width = lightmap.width();
+        let (x1, y1, x2, y2) = rect;
+
+        // Before writing, `data_mut` on its own would flag the whole lightmap for upload
+        lightmap.mark_region_updated(x1, y1, x2, y2);
+
+        {
+            let data = lightmap.data_mut();
+
+            for y in y1..=y2 {
+                for x in x1..=x2 {
+                    let i = y * width + x;
+
+                    if i >= data.len() || i >= self.pristine.len() {
+                        continue;
+                    }
+
+                    let keep = self.burns.iter().fold(1.0, |keep, b| keep * (1.0 - b.darkness(x, y, gametime)));
+                    data[i] = darken_texel(self.pristine[i], keep);
+                }
+            }
+        }
+    }
+
+    fn restore(&self) {
+        let mut lightmap = self.lightmap.borrow_mut();
+        let len = lightmap.data().len().min(self.pristine.len());
+        let (width, height) = (lightmap.width(), lightmap.height());
+
+        lightmap.mark_region_updated(0, 0, width, height);
+        lightmap.data_mut()[..len].copy_from_slice(&self.pristine[..len]);
+    }
+}
+
+/// Scales the color of a 1555 texel, keeping its alpha bit
+fn darken_texel(texel: u16, keep: f32) -> u16 {
+    if keep >= 1.0 {
+        return texel;
+    }
+
+    let keep = keep.max(0.0);
+    let scale = |shift: u16| ((((texel >> shift) & 0x1F) as f32 * keep).round() as u16) << shift;
+
+    (texel & 0x8000) | scale(10) | scale(5) | scale(0)
+}
+
+#[derive(Debug, Default)]
+pub struct ScorchMarks {
+    faces: Vec<BurnedFace>,
+}
+
+impl ScorchMarks {
+    /// Faces that currently have marks on them
+    pub fn burned_face_count(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn burns(&self, room: usize, face: usize) -> impl Iterator<Item = &Burn> {
+        self.faces.iter().filter(move |f| f.room == room && f.face == face).flat_map(|f| f.burns.iter())
+    }
+
+    /// Burns a mark of `radius` world units around where a weapon hit the face, false if the face can't take one
+    pub fn add_burn(&mut self, room: &Room, face_index: usize, point: &Vector, radius: f32, strength: f32, gametime: f32) -> bool {
+        match room.faces.get(face_index) {
+            Some(face) => self.burn_face(room.id(), face_index, &room.vertices, face, point, radius, strength, gametime),
+            None => false,
+        }
+    }
+
+    fn burn_face(
+        &mut self,
+        room: usize,
+        face_index: usize,
+        vertices: &[Vector],
+        face: &Face,
+        point: &Vector,
+        radius: f32,
+        strength: f32,
+        gametime: f32,
+    ) -> bool {
+        let Some(lightmap) = face.lightmap.as_ref() else {
+            return false;
+        };
+
+        let Some(projection) = FaceProjection::new(vertices, face) else {
+            return false;
+        };
+
+        let (width, height) = {
+            let lightmap = lightmap.borrow();
+            (lightmap.width(), lightmap.height())
+        };
+
+        if width == 0 || height == 0 || radius <= 0.0 {
+            return false;
+        }
+
+        let (u, v) = projection.project(point);
+        let (u_extent, v_extent) = projection.extent();
+
+        let burn = Burn {
+            x: u * width as f32,
+            y: v * height as f32,
+            radius: (radius / u_extent * width as f32).max(radius / v_extent * height as f32).max(1.0),
+            strength: strength.clamp(0.0, 1.0),
+            time: gametime,
+        };
+
+        // Missed the lightmap altogether
+        if burn.x + burn.radius < 0.0 || burn.y + burn.radius < 0.0 || burn.x - burn.radius > width as f32 || burn.y - burn.radius > height as f32 {
+            return false;
+        }
+
+        let index = match self.faces.iter().position(|f| f.room == room && f.face == face_index) {
+            Some(i) => i,
+            None => {
+                if self.faces.len() >= MAX_BURNED_FACES {
+                    self.drop_stalest_face();
+                }
+
+                let pristine = lightmap.borrow().data().to_vec();
+
+                self.faces.push(BurnedFace {
+                    room,
+                    face: face_index,
+                    lightmap: lightmap.clone(),
+                    pristine,
+                    burns: VecDeque::new(),
+                    last_hit: gametime,
+                });
+
+                self.faces.len() - 1
+            }
+        };
+
+        let burned = &mut self.faces[index];
+        burned.last_hit = gametime;
+        burned.burns.push_back(burn);
+
+        let mut rect = burn.rect(width, height);
+
+        if burned.burns.len() > MAX_BURNS_PER_FACE {
+            let oldest = burned.burns.pop_front().unwrap();
+            rect = union(rect, oldest.rect(width, height));
+        }
+
+        burned.redraw(rect, gametime);
+
+        true
+    }
+
+    fn drop_stalest_face(&mut self) {
+        let stalest = self
+            .faces
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.last_hit.total_cmp(&b.last_hit))
+            .map(|(i, _)| i);
+
+        if let Some(i) = stalest {
+            self.faces.swap_remove(i).restore();
+        }
+    }
+
+    /// Fades and removes old marks, faces go back to their original lighting once they have none left
+    pub fn update(&mut self, gametime: f32) {
+        self.faces.retain_mut(|face| {
+            let (width, height) = {
+                let lightmap = face.lightmap.borrow();
+                (lightmap.width(), lightmap.height())
+            };
+
+            let dirty = face
+                .burns
+                .iter()
+                .filter(|b| b.is_fading(gametime))
+                .map(|b| b.rect(width, height))
+                .reduce(union);
+
+            face.burns.retain(|b| !b.is_expired(gametime));
+
+            if face.burns.is_empty() {
+                face.restore();
+                return false;
+            }
+
+            if let Some(rect) = dirty {
+                face.redraw(rect, gametime);
+            }
+
+            true
+        });
+    }
+
+    /// Puts every burned face back the way it was
+    pub fn clear(&mut self) {
+        for face in self.faces.drain(..) {
+            face.restore();
+        }
+    }
+}
+
+fn union(a: (usize, usize, usize, usize), b: (usize, usize, usize, usize)) -> (usize, usize, usize, usize) {
+    (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{game::room::FaceFlags, graphics::{lightmap::LightMapFlags, GpuMemoryResource}};
+
+    use super::*;
+
+    const LIT: u16 = 0x8000 | (20 << 10) | (20 << 5) | 20;
+
+    /// 10x10 quad on the z = 0 plane with a 16x16 lightmap
+    fn burnable_face() -> (Vec<Vector>, Face) {
+        let vertices = vec![
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(10.0, 0.0, 0.0),
+            Vector::new(10.0, 10.0, 0.0),
+            Vector::new(0.0, 10.0, 0.0),
+        ];
+
+        let mut lightmap = LightMap16::new(&[LIT; 16 * 16], 16, 16);
+        lightmap.clear_updated();
+
+        let face = Face {
+            flags: FaceFlags::empty(),
+            num_verts: 4,
+            portal: None,
+            face_verts: vec![0, 1, 2, 3],
+            face_uvls: Vec::new(),
+            normal: Vector::new(0.0, 0.0, 1.0),
+            lightmap: Some(new_shared_mut_ref(lightmap)),
+            special_faces: (),
+            render_frame: (),
+            tmap: None,
+            light_muliple: 0,
+            min_xyz: Vector::ZERO,
+            max_xyz: Vector::new(10.0, 10.0, 0.0),
+        };
+
+        (vertices, face)
+    }
+
+    #[test]
+    fn scorch_burn_test() {
+        crate::test_common::setup();
+
+        let (vertices, face) = burnable_face();
+        let lightmap = face.lightmap.clone().unwrap();
+        let mut marks = ScorchMarks::default();
+
+        assert!(marks.burn_face(1, 0, &vertices, &face, &Vector::new(5.0, 5.0, 0.0), 2.0, 1.0, 0.0));
+
+        {
+            let lightmap = lightmap.borrow();
+            let data = lightmap.data();
+
+            // Darkest in the middle, untouched at the edge, alpha kept
+            let burn = marks.burns(1, 0).next().unwrap();
+            let center = burn.y as usize * 16 + burn.x as usize;
+            assert!(data[center] & 0x1F < 20);
+            assert_eq!(data[center] & 0x8000, 0x8000);
+            assert_eq!(data[0], LIT);
+
+            // Only the burned area goes up
+            assert!(lightmap.is_updated());
+            assert!(lightmap.flags().contains(LightMapFlags::Limits));
+            let (x1, y1, x2, y2) = lightmap.deltas();
+            assert!(x1 > 0 && y1 > 0 && x2 < 15 && y2 < 15);
+        }
+
+        // Way off the face
+        assert!(!marks.burn_face(1, 0, &vertices, &face, &Vector::new(100.0, 100.0, 0.0), 2.0, 1.0, 0.0));
+
+        // The cap drops the oldest
+        for i in 0..MAX_BURNS_PER_FACE {
+            marks.burn_face(1, 0, &vertices, &face, &Vector::new(i as f32, 1.0, 0.0), 1.0, 0.5, 1.0);
+        }
+        assert_eq!(marks.burns(1, 0).count(), MAX_BURNS_PER_FACE);
+        assert_eq!(marks.burned_face_count(), 1);
+    }
+
+    #[test]
+    fn scorch_fade_test() {
+        crate::test_common::setup();
+
+        let (vertices, face) = burnable_face();
+        let lightmap = face.lightmap.clone().unwrap();
+        let mut marks = ScorchMarks::default();
+
+        marks.burn_face(1, 0, &vertices, &face, &Vector::new(5.0, 5.0, 0.0), 2.0, 1.0, 0.0);
+        let burned = lightmap.borrow().data().to_vec();
+
+        // Halfway through the fade it's lighter than it was
+        marks.update(BURN_LIFETIME - BURN_FADE_TIME * 0.5);
+        let fading = lightmap.borrow().data().to_vec();
+        assert!(fading.iter().zip(burned.iter()).all(|(f, b)| f & 0x1F >= b & 0x1F));
+        assert_ne!(fading, burned);
+
+        // Then back to the original lighting
+        marks.update(BURN_LIFETIME);
+        assert_eq!(marks.burned_face_count(), 0);
+        assert!(lightmap.borrow().data().iter().all(|&t| t == LIT));
+    }
+
+    #[test]
+    fn scorch_darken_test() {
+        assert_eq!(darken_texel(LIT, 1.0), LIT);
+        assert_eq!(darken_texel(LIT, 0.0), 0x8000);
+        assert_eq!(darken_texel(LIT, 0.5), 0x8000 | (10 << 10) | (10 << 5) | 10);
+    }
+}
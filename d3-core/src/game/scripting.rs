@@ -72,11 +72,16 @@ pub enum EventType {
     PlayerRespawn,
     /// Event for when a player dies.
     PlayerDies,
+    /// Event for when a player enters an energy or shield center.
+    EnergyCenterEnter,
+    /// Event for when a player leaves an energy or shield center.
+    EnergyCenterExit,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub struct EventInfo {
-
+    /// For `Collide` with a wall or the terrain, what the surface is made of
+    pub surface: Option<crate::graphics::texture::SurfaceMaterial>,
 }
 
 
@@ -0,0 +1,355 @@
+/*
+
+Object shadows
+
+Most objects get a blob shadow: a ray is cast straight down from the
+object and a soft dark spot is laid on whatever floor it hits, room or
+terrain. It gets lighter and wider the higher the object is. The player
+ship can instead have its model flattened onto the floor along the light
+direction, which looks better but means drawing the model twice.
+
+Shadows are drawn after the opaque world, like decals.
+
+*/
+
+use super::decal::{DecalVertex, DECAL_OFFSET};
+use super::physics::intersection::{FqFlags, HitType, IntersectionFinderResult, Query};
+use super::prelude::*;
+use super::room::Room;
+use crate::config::DetailSettings;
+use crate::graphics::{generic_bitmap::GenericBitmap16, rendering::AlphaType};
+use angle::Angle;
+use matrix::Matrix;
+use plane::Plane;
+use vector::Vector;
+
+/// How far below an object the floor can be and still get its shadow
+pub const SHADOW_CAST_DISTANCE: f32 = 80.0;
+
+/// Alpha of a blob right under an object sitting on the floor
+pub const BLOB_SHADOW_ALPHA: f32 = 0.6;
+
+/// Blob radius against the object's size, when on the floor
+pub const BLOB_SHADOW_SCALE: f32 = 1.2;
+
+/// How much wider a blob gets at the far end of `SHADOW_CAST_DISTANCE`
+pub const BLOB_SHADOW_SPREAD: f32 = 0.5;
+
+/// Alpha of a projected model shadow
+pub const MODEL_SHADOW_ALPHA: f32 = 0.5;
+
+/// Surfaces steeper than this (the y of their normal) are walls and don't get shadows
+pub const MIN_FLOOR_NORMAL_Y: f32 = 0.4;
+
+/// Where the ray down from an object landed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowHit {
+    pub point: Vector,
+    pub normal: Vector,
+}
+
+impl ShadowHit {
+    /// The first wall or terrain hit of an FVI result
+    pub fn from_fvi(result: &IntersectionFinderResult) -> Option<ShadowHit> {
+        (0..result.hit_count.min(result.hit_type.len()))
+            .find(|&i| matches!(result.hit_type[i], HitType::Wall | HitType::Terrain))
+            .map(|i| ShadowHit {
+                point: result.hit_face_point[i],
+                normal: result.hit_wall_normal[i],
+            })
+    }
+}
+
+/// The FVI query for the ray straight down under an object, only walls and terrain stop it.
+/// Its result goes through `ShadowHit::from_fvi` to feed `ShadowPass::build`
+pub fn shadow_query(start_room: SharedMutRef<Room>, this_obj: Option<SharedMutRef<Object>>, position: &Vector) -> Query {
+    Query {
+        p0: *position,
+        p1: *position - Vector::new(0.0, SHADOW_CAST_DISTANCE, 0.0),
+        start_room,
+        rad: 0.0,
+        this_obj,
+        ignore_obj_list: (),
+        flags: FqFlags::LIGHTING | FqFlags::NO_RELINK,
+        bbox_orientation: Matrix::IDENTITY,
+        bbox_rotvel: Vector::ZERO,
+        bbox_rotthrust: Vector::ZERO,
+        bbox_velocity: Vector::ZERO,
+        bbox_turnroll: Angle(0),
+        bbox_thrust: Vector::ZERO,
+        frametime: 0.0,
+    }
+}
+
+/// Black with the alpha falling off from the middle, what blobs are drawn with
+pub fn blob_shadow_bitmap(size: usize) -> GenericBitmap16 {
+    let half = size as f32 * 0.5;
+    let mut data = vec![0u16; size * size];
+
+    for y in 0..size {
+        for x in 0..size {
+            let dx = (x as f32 + 0.5 - half) / half;
+            let dy = (y as f32 + 0.5 - half) / half;
+            let falloff = (1.0 - (dx * dx + dy * dy)).max(0.0);
+
+            data[y * size + x] = ((falloff * 15.0).round() as u16) << 12;
+        }
+    }
+
+    GenericBitmap16::new(data, size, size)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BlobShadow {
+    pub vertices: [DecalVertex; 4],
+    pub alpha: f32,
+}
+
+/// A model's triangles flattened onto the floor, three points each
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectedShadow {
+    pub points: Vec<Vector>,
+    pub alpha: f32,
+}
+
+/// How high above the floor an object is, as a fraction of the cast distance
+fn height_fraction(position: &Vector, hit: &ShadowHit) -> Option<f32> {
+    if hit.normal.y < MIN_FLOOR_NORMAL_Y {
+        return None;
+    }
+
+    let t = (position.y - hit.point.y) / SHADOW_CAST_DISTANCE;
+
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+/// Casts down from an object and lays a blob on the floor under it
+pub fn blob_shadow(position: &Vector, size: f32, cast_down: impl FnOnce(&Vector, &Vector) -> Option<ShadowHit>) -> Option<BlobShadow> {
+    let hit = cast_down(position, &(*position - Vector::new(0.0, SHADOW_CAST_DISTANCE, 0.0)))?;
+    let t = height_fraction(position, &hit)?;
+
+    let normal = hit.normal.normalized();
+    let radius = size * BLOB_SHADOW_SCALE * (1.0 + BLOB_SHADOW_SPREAD * t);
+
+    // Lined up with the world x axis, the floor's never steep enough for that to be parallel
+    let right = Vector::new(0.0, 0.0, 1.0).cross(&normal).normalized();
+    let forward = normal.cross(&right);
+    let center = hit.point + normal * DECAL_OFFSET;
+
+    let corner = |x: f32, z: f32, u: f32, v: f32| DecalVertex {
+        position: center + right * (x * radius) + forward * (z * radius),
+        u,
+        v,
+    };
+
+    Some(BlobShadow {
+        vertices: [corner(-1.0, -1.0, 0.0, 1.0), corner(1.0, -1.0, 1.0, 1.0), corner(1.0, 1.0, 1.0, 0.0), corner(-1.0, 1.0, 0.0, 0.0)],
+        alpha: BLOB_SHADOW_ALPHA * (1.0 - t),
+    })
+}
+
+/// Flattens a model's triangles onto the floor along `light_dir`, which has to point down onto it
+pub fn model_shadow(position: &Vector, triangles: &[Vector], hit: &ShadowHit, light_dir: &Vector) -> Option<ProjectedShadow> {
+    let t = height_fraction(position, hit)?;
+    let normal = hit.normal.normalized();
+    let plane = Plane::from_point_normal(&(hit.point + normal * DECAL_OFFSET), &normal);
+    let towards = *light_dir * normal;
+
+    if towards > -f32::EPSILON {
+        return None;
+    }
+
+    let points = triangles
+        .iter()
+        .map(|p| {
+            // Anything already under the floor just sits on it
+            let distance = plane.distance(p).max(0.0);
+            *p - *light_dir * (distance / towards)
+        })
+        .collect();
+
+    Some(ProjectedShadow {
+        points,
+        alpha: MODEL_SHADOW_ALPHA * (1.0 - t),
+    })
+}
+
+/// Something that casts a shadow this frame
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowCaster<'a> {
+    pub position: Vector,
+    pub size: f32,
+    /// World space triangles for a projected shadow, only the player ship has these
+    pub model: Option<&'a [Vector]>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShadowDrawList {
+    /// Blobs are drawn with `blob_shadow_bitmap` and their own alpha
+    pub blob_alpha_type: AlphaType,
+    pub blobs: Vec<BlobShadow>,
+    /// Models are drawn flat black
+    pub model_alpha_type: AlphaType,
+    pub models: Vec<ProjectedShadow>,
+}
+
+impl Default for ShadowDrawList {
+    fn default() -> Self {
+        Self {
+            blob_alpha_type: AlphaType::CONSTANT_TEXTURE,
+            blobs: Vec::new(),
+            model_alpha_type: AlphaType::CONSTANT,
+            models: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowPass {
+    pub blobs_enabled: bool,
+    pub ship_shadow_enabled: bool,
+    /// Direction light travels in for projected shadows
+    pub light_dir: Vector,
+}
+
+impl ShadowPass {
+    pub fn new(detail: &DetailSettings) -> Self {
+        Self {
+            blobs_enabled: detail.blob_shadows,
+            ship_shadow_enabled: detail.ship_shadow,
+            light_dir: Vector::new(0.0, -1.0, 0.0),
+        }
+    }
+
+    /// Shadows for the casters, `cast_down` runs the ray from the object down to the floor
+    pub fn build<'a>(
+        &self,
+        casters: impl IntoIterator<Item = ShadowCaster<'a>>,
+        mut cast_down: impl FnMut(&Vector, &Vector) -> Option<ShadowHit>,
+    ) -> ShadowDrawList {
+        let mut list = ShadowDrawList::default();
+
+        if !self.blobs_enabled && !self.ship_shadow_enabled {
+            return list;
+        }
+
+        for caster in casters {
+            match caster.model {
+                Some(triangles) if self.ship_shadow_enabled => {
+                    let to = caster.position - Vector::new(0.0, SHADOW_CAST_DISTANCE, 0.0);
+
+                    if let Some(shadow) = cast_down(&caster.position, &to)
+                        .and_then(|hit| model_shadow(&caster.position, triangles, &hit, &self.light_dir))
+                    {
+                        list.models.push(shadow);
+                    }
+                }
+                _ if self.blobs_enabled => {
+                    if let Some(blob) = blob_shadow(&caster.position, caster.size, &mut cast_down) {
+                        list.blobs.push(blob);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        list
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::graphics::bitmap::Bitmap16;
+
+    use super::*;
+
+    fn floor(_from: &Vector, to: &Vector) -> Option<ShadowHit> {
+        (to.y <= 0.0).then_some(ShadowHit {
+            point: Vector::new(to.x, 0.0, to.z),
+            normal: Vector::new(0.0, 1.0, 0.0),
+        })
+    }
+
+    #[test]
+    fn blob_shadow_test() {
+        crate::test_common::setup();
+
+        let low = blob_shadow(&Vector::new(5.0, 1.0, 5.0), 2.0, floor).unwrap();
+        let high = blob_shadow(&Vector::new(5.0, 40.0, 5.0), 2.0, floor).unwrap();
+
+        // Flat on the floor around the object
+        assert!(low.vertices.iter().all(|v| (v.position.y - DECAL_OFFSET).abs() < 1e-4));
+        let width = (low.vertices[1].position - low.vertices[0].position).normalized();
+        assert!((width * Vector::new(0.0, 1.0, 0.0)).abs() < 1e-4);
+
+        // Higher up it's fainter and wider
+        assert!(high.alpha < low.alpha);
+        let span = |b: &BlobShadow| Vector::distance(&b.vertices[0].position, &b.vertices[2].position);
+        assert!(span(&high) > span(&low));
+
+        // Too high, or nothing but wall underneath
+        assert!(blob_shadow(&Vector::new(5.0, 100.0, 5.0), 2.0, floor).is_none());
+        let wall = |_: &Vector, _: &Vector| Some(ShadowHit { point: Vector::ZERO, normal: Vector::new(1.0, 0.0, 0.0) });
+        assert!(blob_shadow(&Vector::new(5.0, 1.0, 5.0), 2.0, wall).is_none());
+    }
+
+    #[test]
+    fn model_shadow_test() {
+        crate::test_common::setup();
+
+        let position = Vector::new(0.0, 10.0, 0.0);
+        let triangle = [Vector::new(-1.0, 10.0, 0.0), Vector::new(1.0, 10.0, 0.0), Vector::new(0.0, 12.0, 0.0)];
+        let hit = ShadowHit { point: Vector::ZERO, normal: Vector::new(0.0, 1.0, 0.0) };
+
+        // Straight down keeps x and z
+        let shadow = model_shadow(&position, &triangle, &hit, &Vector::new(0.0, -1.0, 0.0)).unwrap();
+        assert!(shadow.points.iter().zip(triangle.iter()).all(|(s, t)| (s.x - t.x).abs() < 1e-4 && (s.y - DECAL_OFFSET).abs() < 1e-4));
+
+        // Slanted light pushes it along
+        let light = Vector::new(1.0, -1.0, 0.0).normalized();
+        let shadow = model_shadow(&position, &triangle, &hit, &light).unwrap();
+        assert!(shadow.points[0].x > triangle[0].x + 9.0);
+        assert!(shadow.points[2].x > shadow.points[0].x);
+
+        // Light from below can't cast onto the floor
+        assert!(model_shadow(&position, &triangle, &hit, &Vector::new(0.0, 1.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn shadow_pass_test() {
+        crate::test_common::setup();
+
+        let triangle = [Vector::new(-1.0, 10.0, 0.0), Vector::new(1.0, 10.0, 0.0), Vector::new(0.0, 12.0, 0.0)];
+        let casters = [
+            ShadowCaster { position: Vector::new(0.0, 10.0, 0.0), size: 3.0, model: Some(&triangle) },
+            ShadowCaster { position: Vector::new(20.0, 5.0, 0.0), size: 1.0, model: None },
+        ];
+
+        let mut detail = DetailSettings::default();
+        detail.ship_shadow = false;
+
+        // Without the ship shadow the player gets a blob like everything else
+        let list = ShadowPass::new(&detail).build(casters, floor);
+        assert_eq!(list.blobs.len(), 2);
+        assert!(list.models.is_empty());
+
+        detail.ship_shadow = true;
+        let list = ShadowPass::new(&detail).build(casters, floor);
+        assert_eq!(list.blobs.len(), 1);
+        assert_eq!(list.models.len(), 1);
+
+        detail.blob_shadows = false;
+        detail.ship_shadow = false;
+        assert!(ShadowPass::new(&detail).build(casters, floor).blobs.is_empty());
+    }
+
+    #[test]
+    fn blob_shadow_bitmap_test() {
+        let bitmap = blob_shadow_bitmap(16);
+        let alpha = |x: usize, y: usize| bitmap.data()[y * 16 + x] >> 12;
+
+        assert!(alpha(8, 8) >= 14);
+        assert_eq!(alpha(0, 0), 0);
+        assert!(bitmap.data().iter().all(|p| p & 0x0FFF == 0));
+    }
+}
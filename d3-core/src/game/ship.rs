@@ -0,0 +1,471 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::{
+    object_dynamic_behavior::DynamicWeaponBattery,
+    player::{Player, PlayerFlags, N_PLAYER_GUNS},
+    prelude::*,
+    weapon::DynamicWeaponBatteryFlags,
+};
+use crate::graphics::bitmap::Bitmap16;
+use vector::Vector;
+
+pub const MAX_SHIPS: usize = 5;
+
+/// Primaries are 0 to 9, secondaries 10 to 19
+pub const MAX_PLAYER_WEAPONS: usize = 20;
+
+/// Pilot logos have to be exactly this size, both ways
+pub const LOGO_SIZE: usize = 64;
+
+pub const DEFAULT_SHIP: &str = "Pyro-GL";
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct ShipFlags: u32 {
+        /// Can be picked in multiplayer, servers can turn ships off
+        const ALLOWED_IN_MULTI = 1;
+        /// Has the fusion cannon charge up effect on its guns
+        const FUSION_CHARGE = 2;
+    }
+}
+
+/// How a ship handles
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShipPhysics {
+    pub mass: f32,
+    pub drag: f32,
+    pub full_thrust: f32,
+    pub rot_drag: f32,
+    pub full_rot_thrust: f32,
+    pub max_turn_roll_rate: f32,
+    pub turn_roll_ratio: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GunPoint {
+    /// Model space position of the muzzle
+    pub position: Vector,
+    /// Direction shots leave in
+    pub normal: Vector,
+}
+
+/// Which gun points a weapon fires from on a ship
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WeaponMount {
+    /// Gun points fired together for each shot, a bit per gun point. Shots cycle through these.
+    pub fire_masks: Vec<u8>,
+    /// Gun points fired at once with the quad upgrade
+    pub quad_fire_mask: u8,
+}
+
+impl WeaponMount {
+    fn single(mask: u8) -> Self {
+        Self {
+            fire_masks: vec![mask],
+            quad_fire_mask: mask,
+        }
+    }
+
+    fn alternating(masks: &[u8], quad: u8) -> Self {
+        Self {
+            fire_masks: masks.to_vec(),
+            quad_fire_mask: quad,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShipInfo {
+    pub name: D3String,
+    pub model_name: D3String,
+    /// Model seen from the cockpit
+    pub cockpit_name: D3String,
+    pub flags: ShipFlags,
+    pub physics: ShipPhysics,
+    pub size: f32,
+    /// Damage taken is scaled by this
+    pub armor_scale: f32,
+    pub gun_points: [Option<GunPoint>; N_PLAYER_GUNS],
+    pub mounts: Vec<WeaponMount>,
+    /// Texture slots on the model replaced by the pilot's logo
+    pub logo_slots: Vec<usize>,
+}
+
+impl ShipInfo {
+    pub fn is_allowed_in_multi(&self) -> bool {
+        self.flags.contains(ShipFlags::ALLOWED_IN_MULTI)
+    }
+
+    pub fn mount(&self, weapon: usize) -> Option<&WeaponMount> {
+        self.mounts.get(weapon)
+    }
+
+    /// The gun points a weapon fires from this shot, moving the battery on to its next mask
+    pub fn next_firing_points(&self, weapon: usize, battery: &mut DynamicWeaponBattery) -> Vec<(usize, GunPoint)> {
+        let Some(mount) = self.mount(weapon) else {
+            return Vec::new();
+        };
+
+        let mask = if battery.flags.contains(DynamicWeaponBatteryFlags::QUAD) && mount.quad_fire_mask != 0 {
+            mount.quad_fire_mask
+        } else {
+            let count = mount.fire_masks.len().max(1);
+            let index = battery.cur_firing_mask as usize % count;
+            battery.cur_firing_mask = ((index + 1) % count) as u8;
+            mount.fire_masks.get(index).copied().unwrap_or(0)
+        };
+
+        (0..N_PLAYER_GUNS)
+            .filter(|i| mask & (1 << i) != 0)
+            .filter_map(|i| self.gun_points[i].map(|gp| (i, gp)))
+            .collect()
+    }
+
+    /// Which textures on the model to swap out for the player's logo
+    pub fn logo_overrides(&self, player: &Player) -> Vec<(usize, SharedMutRef<dyn Bitmap16>)> {
+        match (&player.custom_logo, player.flags.contains(PlayerFlags::CUSTOM_TEXTURE)) {
+            (Some(logo), true) => self.logo_slots.iter().map(|slot| (*slot, logo.clone())).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Checks a pilot logo can go on a ship
+pub fn validate_logo(logo: &dyn Bitmap16) -> Result<()> {
+    if logo.width() != LOGO_SIZE || logo.height() != LOGO_SIZE {
+        return Err(anyhow!(
+            "logo has to be {}x{}, not {}x{}",
+            LOGO_SIZE,
+            LOGO_SIZE,
+            logo.width(),
+            logo.height()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Gives a player a logo to wear, or takes it away
+pub fn set_player_logo(player: &mut Player, logo: Option<SharedMutRef<dyn Bitmap16>>) -> Result<()> {
+    if let Some(logo) = logo.as_ref() {
+        validate_logo(&*logo.borrow())?;
+    }
+
+    player.flags.set(PlayerFlags::CUSTOM_TEXTURE, logo.is_some());
+    player.custom_logo = logo;
+
+    Ok(())
+}
+
+fn gun(x: f32, y: f32, z: f32) -> Option<GunPoint> {
+    Some(GunPoint {
+        position: Vector::new(x, y, z),
+        normal: Vector::new(0.0, 0.0, 1.0),
+    })
+}
+
+/// Mounts shared by every retail ship, they only differ in where the gun points sit.
+/// Guns 0 and 1 are the wing tips, 2 and 3 the inner wings, 4 the nose and 5 and 6 the missile bays.
+fn retail_mounts() -> Vec<WeaponMount> {
+    let mut mounts = vec![WeaponMount::default(); MAX_PLAYER_WEAPONS];
+
+    // Laser, vauss, microwave, plasma, fusion
+    mounts[0] = WeaponMount::single(0b0000_0011);
+    mounts[1] = WeaponMount::alternating(&[0b0000_0001, 0b0000_0010], 0b0000_0011);
+    mounts[2] = WeaponMount::single(0b0001_0000);
+    mounts[3] = WeaponMount::single(0b0000_1100);
+    mounts[4] = WeaponMount::single(0b0001_0000);
+
+    // Super laser, mass driver, napalm, emd, omega
+    mounts[5] = WeaponMount::alternating(&[0b0000_0011], 0b0000_1111);
+    mounts[6] = WeaponMount::single(0b0001_0000);
+    mounts[7] = WeaponMount::single(0b0001_0000);
+    mounts[8] = WeaponMount::single(0b0000_1100);
+    mounts[9] = WeaponMount::single(0b0001_0000);
+
+    // Secondaries come out of the bays in turn
+    for mount in mounts[10..].iter_mut() {
+        *mount = WeaponMount::alternating(&[0b0010_0000, 0b0100_0000], 0);
+    }
+
+    mounts
+}
+
+/// The ships that come with the game
+pub fn retail_ships() -> Vec<ShipInfo> {
+    vec![
+        ShipInfo {
+            name: D3String::from("Pyro-GL"),
+            model_name: D3String::from("pyrogl.oof"),
+            cockpit_name: D3String::from("pyrogl.inf"),
+            flags: ShipFlags::ALLOWED_IN_MULTI,
+            physics: ShipPhysics {
+                mass: 4.0,
+                drag: 0.04,
+                full_thrust: 100.0,
+                rot_drag: 0.06,
+                full_rot_thrust: 130.0,
+                max_turn_roll_rate: 8000.0,
+                turn_roll_ratio: 0.2,
+            },
+            size: 4.0,
+            armor_scale: 1.0,
+            gun_points: [
+                gun(-3.8, -0.2, 1.0),
+                gun(3.8, -0.2, 1.0),
+                gun(-2.0, -0.4, 2.0),
+                gun(2.0, -0.4, 2.0),
+                gun(0.0, -0.6, 3.4),
+                gun(-1.0, -1.0, 1.5),
+                gun(1.0, -1.0, 1.5),
+                None,
+            ],
+            mounts: retail_mounts(),
+            logo_slots: vec![3],
+        },
+        // Quick and light, takes hits badly
+        ShipInfo {
+            name: D3String::from("Phoenix"),
+            model_name: D3String::from("phoenix.oof"),
+            cockpit_name: D3String::from("phoenix.inf"),
+            flags: ShipFlags::ALLOWED_IN_MULTI,
+            physics: ShipPhysics {
+                mass: 3.0,
+                drag: 0.035,
+                full_thrust: 115.0,
+                rot_drag: 0.05,
+                full_rot_thrust: 150.0,
+                max_turn_roll_rate: 9000.0,
+                turn_roll_ratio: 0.25,
+            },
+            size: 3.6,
+            armor_scale: 1.3,
+            gun_points: [
+                gun(-4.4, 0.0, 0.2),
+                gun(4.4, 0.0, 0.2),
+                gun(-1.6, -0.3, 2.6),
+                gun(1.6, -0.3, 2.6),
+                gun(0.0, -0.2, 3.8),
+                gun(-0.7, -0.9, 2.0),
+                gun(0.7, -0.9, 2.0),
+                None,
+            ],
+            mounts: retail_mounts(),
+            logo_slots: vec![2],
+        },
+        // Slow and heavy, built to soak up damage
+        ShipInfo {
+            name: D3String::from("Magnum-AHT"),
+            model_name: D3String::from("magnumaht.oof"),
+            cockpit_name: D3String::from("magnumaht.inf"),
+            flags: ShipFlags::ALLOWED_IN_MULTI | ShipFlags::FUSION_CHARGE,
+            physics: ShipPhysics {
+                mass: 6.0,
+                drag: 0.05,
+                full_thrust: 90.0,
+                rot_drag: 0.08,
+                full_rot_thrust: 110.0,
+                max_turn_roll_rate: 6500.0,
+                turn_roll_ratio: 0.15,
+            },
+            size: 4.6,
+            armor_scale: 0.75,
+            gun_points: [
+                gun(-3.2, -0.6, 1.8),
+                gun(3.2, -0.6, 1.8),
+                gun(-2.4, 0.4, 2.2),
+                gun(2.4, 0.4, 2.2),
+                gun(0.0, -0.9, 3.0),
+                gun(-1.4, -1.2, 1.0),
+                gun(1.4, -1.2, 1.0),
+                None,
+            ],
+            mounts: retail_mounts(),
+            logo_slots: vec![4, 5],
+        },
+    ]
+}
+
+/// The ships players can fly, looked up by index or name
+#[derive(Debug, Clone, Default)]
+pub struct ShipTable {
+    ships: Vec<ShipInfo>,
+    names: HashMap<String, usize>,
+}
+
+impl ShipTable {
+    pub fn with_retail_ships() -> Self {
+        let mut table = Self::default();
+
+        for ship in retail_ships() {
+            table.add(ship).expect("retail ships fit the table");
+        }
+
+        table
+    }
+
+    pub fn add(&mut self, info: ShipInfo) -> Result<usize> {
+        if self.ships.len() >= MAX_SHIPS {
+            return Err(anyhow!("ship table is full"));
+        }
+
+        let name = info
+            .name
+            .to_string()
+            .map_err(|e| anyhow!("bad ship name: {}", e))?
+            .to_lowercase();
+
+        if self.names.contains_key(&name) {
+            return Err(anyhow!("ship {} is already in the table", name));
+        }
+
+        let index = self.ships.len();
+        self.names.insert(name, index);
+        self.ships.push(info);
+
+        Ok(index)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&ShipInfo> {
+        self.ships.get(index)
+    }
+
+    /// Names aren't case sensitive
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.get(&name.to_lowercase()).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ships.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ships.is_empty()
+    }
+
+    /// For servers turning ships on and off for their games
+    pub fn set_allowed_in_multi(&mut self, index: usize, allowed: bool) {
+        if let Some(ship) = self.ships.get_mut(index) {
+            ship.flags.set(ShipFlags::ALLOWED_IN_MULTI, allowed);
+        }
+    }
+
+    /// Puts the player in a ship, multiplayer games only allow the ships the server has on
+    pub fn select_ship(&self, player: &mut Player, index: usize, multiplayer: bool) -> Result<()> {
+        let ship = self.get(index).ok_or_else(|| anyhow!("no ship {}", index))?;
+
+        if multiplayer && !ship.is_allowed_in_multi() {
+            return Err(anyhow!("{} isn't allowed in this game", ship.name));
+        }
+
+        player.ship_index = index;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::graphics::generic_bitmap::GenericBitmap16;
+
+    use super::*;
+
+    fn battery() -> DynamicWeaponBattery {
+        DynamicWeaponBattery {
+            last_fire_time: 0.0,
+            cur_firing_mask: 0,
+            norm_turret_angle: [0.0; 8],
+            turret_next_think_time: [0.0; 8],
+            turret_direction: [0; 8],
+            wb_anim_mask: 0,
+            wb_anim_frame: 0.0,
+            cur_target: Vector::ZERO,
+            upgrade_level: 0,
+            flags: DynamicWeaponBatteryFlags::ENABLED,
+        }
+    }
+
+    #[test]
+    fn retail_ship_table_test() {
+        crate::test_common::setup();
+
+        let table = ShipTable::with_retail_ships();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.find("pyro-gl"), Some(0));
+
+        // They really do fly differently
+        let pyro = table.get(0).unwrap();
+        let magnum = table.get(table.find("Magnum-AHT").unwrap()).unwrap();
+        assert!(magnum.physics.mass > pyro.physics.mass);
+        assert!(magnum.armor_scale < pyro.armor_scale);
+        assert_ne!(pyro.gun_points, magnum.gun_points);
+    }
+
+    #[test]
+    fn ship_firing_points_test() {
+        crate::test_common::setup();
+
+        let ships = retail_ships();
+        let pyro = &ships[0];
+        let mut battery = battery();
+
+        // Lasers fire from both wing tips
+        let points: Vec<usize> = pyro.next_firing_points(0, &mut battery).iter().map(|(i, _)| *i).collect();
+        assert_eq!(points, vec![0, 1]);
+
+        // Vauss alternates
+        battery.cur_firing_mask = 0;
+        assert_eq!(pyro.next_firing_points(1, &mut battery)[0].0, 0);
+        assert_eq!(pyro.next_firing_points(1, &mut battery)[0].0, 1);
+        assert_eq!(pyro.next_firing_points(1, &mut battery)[0].0, 0);
+
+        // Quad lasers use all four wing guns
+        battery.flags |= DynamicWeaponBatteryFlags::QUAD;
+        assert_eq!(pyro.next_firing_points(5, &mut battery).len(), 4);
+
+        // Nothing mounted past the end
+        assert!(pyro.next_firing_points(MAX_PLAYER_WEAPONS, &mut battery).is_empty());
+    }
+
+    #[test]
+    fn ship_selection_test() {
+        crate::test_common::setup();
+
+        let mut table = ShipTable::with_retail_ships();
+        let mut player = Player::default();
+
+        table.select_ship(&mut player, 1, true).unwrap();
+        assert_eq!(player.ship_index, 1);
+
+        // The server turned the Magnum off
+        table.set_allowed_in_multi(2, false);
+        assert!(table.select_ship(&mut player, 2, true).is_err());
+        assert_eq!(player.ship_index, 1);
+        table.select_ship(&mut player, 2, false).unwrap();
+        assert!(table.select_ship(&mut player, 7, false).is_err());
+    }
+
+    #[test]
+    fn ship_logo_test() {
+        crate::test_common::setup();
+
+        let ships = retail_ships();
+        let mut player = Player::default();
+
+        let wrong: SharedMutRef<dyn Bitmap16> = new_shared_mut_ref(GenericBitmap16::new(vec![0; 32 * 32], 32, 32));
+        assert!(set_player_logo(&mut player, Some(wrong)).is_err());
+        assert!(ships[2].logo_overrides(&player).is_empty());
+
+        let logo: SharedMutRef<dyn Bitmap16> = new_shared_mut_ref(GenericBitmap16::new(vec![0; LOGO_SIZE * LOGO_SIZE], LOGO_SIZE, LOGO_SIZE));
+        set_player_logo(&mut player, Some(logo)).unwrap();
+        assert!(player.flags.contains(PlayerFlags::CUSTOM_TEXTURE));
+
+        let slots: Vec<usize> = ships[2].logo_overrides(&player).iter().map(|(slot, _)| *slot).collect();
+        assert_eq!(slots, vec![4, 5]);
+
+        set_player_logo(&mut player, None).unwrap();
+        assert!(ships[2].logo_overrides(&player).is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! Drawing the sky dome. The dome is the horizon pieces set up by `Terrain::setup_sky`,
+//! six rings of points from just under the top down to the horizon. The upper rings are
+//! mapped with the dome texture, which is laid out as a disc with the top of the sky in
+//! the middle, and the last band down to the horizon is always shaded into the horizon color.
+
+use super::terrain::{SkyFlags, Terrain, TerrainSky, MAX_TERRAIN_HEIGHT, TERRAIN_SIZE};
+use crate::{
+    filesystem::loader::{AssetLoader, AssetSource},
+    gr_color_blue, gr_color_green, gr_color_red, gr_rgb,
+    graphics::{bitmap::BitmapFormat, ddgr_color, render_context::RenderContext, TextureHandle, GR_WHITE},
+    math::{ray::Ray, CrossProduct},
+};
+use crate::math::vector::Vector;
+
+/// Rings of horizon points, top to horizon
+pub const SKY_RINGS: usize = 6;
+
+/// Rings with texture coordinates, the band below the last of them is never textured
+pub const SKY_TEXTURED_RINGS: usize = 5;
+
+/// Seconds for a satellite's halo to fade all the way in or out
+pub const SATELLITE_GLOW_FADE_TIME: f32 = 0.5;
+
+/// One quad of the dome, corners going around the piece then down a ring
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkyDomePolygon {
+    /// `None` is gouraud shaded with `colors` alone
+    pub texture: Option<TextureHandle>,
+    pub points: [Vector; 4],
+    pub uvs: [(f32, f32); 4],
+    pub colors: [ddgr_color; 4],
+}
+
+/// Blends `from` into `to`, `amount` going 0 to 1
+fn blend_color(from: ddgr_color, to: ddgr_color, amount: f32) -> ddgr_color {
+    let blend = |a: i32, b: i32| (a as f32 + (b - a) as f32 * amount).round() as i32;
+
+    gr_rgb!(
+        blend(gr_color_red!(from), gr_color_red!(to)),
+        blend(gr_color_green!(from), gr_color_green!(to)),
+        blend(gr_color_blue!(from), gr_color_blue!(to))
+    )
+}
+
+/// Turns texture coordinates about the middle of the dome texture
+fn rotate_uv(u: f32, v: f32, degrees: f32) -> (f32, f32) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (du, dv) = (u - 0.5, v - 0.5);
+
+    (0.5 + du * cos - dv * sin, 0.5 + du * sin + dv * cos)
+}
+
+impl TerrainSky {
+    /// Starts loading the dome bitmap, picked up by `update_dome_texture` once it's in
+    pub fn request_dome_texture(&mut self, context: &mut RenderContext, loader: &AssetLoader, source: AssetSource) {
+        self.dome_texture = None;
+        self.dome_texture_name = Some(source.name());
+        context.request_bitmap(loader, source, BitmapFormat::Fmt1555);
+    }
+
+    /// Looks the dome bitmap up in the cache if it hasn't been found yet
+    pub fn update_dome_texture(&mut self, context: &RenderContext) {
+        if self.dome_texture.is_some() {
+            return;
+        }
+
+        self.dome_texture = self
+            .dome_texture_name
+            .as_deref()
+            .and_then(|name| context.bitmap_id(name))
+            .map(TextureHandle::Bitmap);
+    }
+
+    /// Turns the dome for rotating skies
+    pub fn do_frame(&mut self, frametime: f32) {
+        if self.flags.contains(SkyFlags::ROTATE_SKY) {
+            self.rotation = (self.rotation + self.rotate_rate * frametime).rem_euclid(360.0);
+        }
+    }
+
+    /// Whether the dome gets mapped, otherwise it's shaded from the sky color down to the horizon
+    pub fn dome_is_textured(&self) -> bool {
+        self.is_textured && self.dome_texture.is_some()
+    }
+
+    /// Shade of a ring for the gouraud sky
+    fn ring_color(&self, ring: usize) -> ddgr_color {
+        blend_color(self.sky_color, self.horizon.color, ring as f32 / (SKY_RINGS - 1) as f32)
+    }
+
+    /// The dome as quads around the viewer, relative to the eye
+    pub fn dome_polygons(&self) -> Vec<SkyDomePolygon> {
+        let horizon = &self.horizon;
+        let pieces = horizon.vectors.len();
+        let textured = self.dome_is_textured();
+
+        let mut polygons = Vec::with_capacity(pieces * (SKY_RINGS - 1));
+
+        for ring in 0..SKY_RINGS - 1 {
+            let mapped = textured && ring + 1 < SKY_TEXTURED_RINGS;
+
+            for t in 0..pieces {
+                let next = (t + 1) % pieces;
+                let corners = [(t, ring), (next, ring), (next, ring + 1), (t, ring + 1)];
+
+                let (texture, uvs, colors) = if mapped {
+                    let uvs = corners.map(|(t, i)| rotate_uv(horizon.u[t][i], horizon.v[t][i], self.rotation));
+                    (self.dome_texture, uvs, [GR_WHITE; 4])
+                } else {
+                    // The texture's edge is white, so the band below it starts from there
+                    let top = if textured { GR_WHITE } else { self.ring_color(ring) };
+                    let bottom = self.ring_color(ring + 1);
+                    (None, [(0.0, 0.0); 4], [top, top, bottom, bottom])
+                };
+
+                polygons.push(SkyDomePolygon {
+                    texture,
+                    points: corners.map(|(t, i)| horizon.vectors[t][i]),
+                    uvs,
+                    colors,
+                });
+            }
+        }
+
+        polygons
+    }
+}
+
+impl Terrain {
+    /// Whether a line clears the ground, walked a cell at a time
+    pub fn terrain_line_of_sight(&self, from: &Vector, to: &Vector) -> bool {
+        let (ray, length) = Ray::between(from, to);
+        let mut distance = TERRAIN_SIZE;
+
+        while distance < length {
+            let point = ray.at(distance);
+
+            // Climbing over the highest the ground goes, nothing further on can be in the way
+            if point.y > MAX_TERRAIN_HEIGHT && ray.direction.y >= 0.0 {
+                return true;
+            }
+
+            if self.ground_height(point.x, point.z).is_some_and(|ground| point.y < ground) {
+                return false;
+            }
+
+            distance += TERRAIN_SIZE;
+        }
+
+        true
+    }
+
+    /// How much of each satellite the eye can see, the middle and four points round its edge,
+    /// with the halos fading towards that. `rooms_block` is whether a line hits an external room
+    pub fn update_satellite_glow(&mut self, eye: &Vector, frametime: f32, rooms_block: impl Fn(&Vector, &Vector) -> bool) {
+        let targets: Vec<f32> = self
+            .sky
+            .satellites
+            .iter()
+            .map(|satellite| {
+                let center = *eye + satellite.vector;
+                let direction = satellite.vector.normalized();
+
+                // Satellites are never set straight up, so this always has a side to it
+                let right = direction.cross(&Vector::new(0.0, 1.0, 0.0)).normalized() * satellite.size;
+                let up = right.cross(&direction);
+
+                let samples = [center, center + right, center - right, center + up, center - up];
+                let visible = samples
+                    .iter()
+                    .filter(|p| self.terrain_line_of_sight(eye, p) && !rooms_block(eye, p))
+                    .count();
+
+                visible as f32 / samples.len() as f32
+            })
+            .collect();
+
+        let step = frametime / SATELLITE_GLOW_FADE_TIME;
+
+        for (satellite, target) in self.sky.satellites.iter_mut().zip(targets) {
+            satellite.glow = if satellite.glow < target {
+                (satellite.glow + step).min(target)
+            } else {
+                (satellite.glow - step).max(target)
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::terrain::Terrain;
+    use crate::graphics::BitmapId;
+
+    #[test]
+    fn sky_dome_test() {
+        crate::test_common::setup();
+
+        let mut terrain = Terrain::default();
+        terrain.sky.horizon.color = gr_rgb!(255, 0, 0);
+        terrain.setup_sky(2500.0, SkyFlags::ROTATE_SKY, false);
+        terrain.sky.sky_color = gr_rgb!(0, 0, 255);
+
+        // A quarter of the way around the outer ring is the middle of the bottom edge
+        let sky = &mut terrain.sky;
+        assert!((sky.horizon.u[4][4] - 0.5).abs() < 0.01);
+        assert!((sky.horizon.v[4][4] - 1.0).abs() < 0.01);
+
+        // Untextured it's all gouraud, top to horizon
+        sky.is_textured = true;
+        let polygons = sky.dome_polygons();
+        assert_eq!(polygons.len(), 16 * 5);
+        assert!(polygons.iter().all(|p| p.texture.is_none()));
+        assert_eq!(polygons[0].colors[0], gr_rgb!(0, 0, 255));
+        assert_eq!(polygons.last().unwrap().colors[3], gr_rgb!(255, 0, 0));
+
+        let texture = TextureHandle::Bitmap(BitmapId(3));
+        sky.dome_texture = Some(texture);
+        let polygons = sky.dome_polygons();
+        assert_eq!(polygons.iter().filter(|p| p.texture == Some(texture)).count(), 16 * 4);
+        assert_eq!(polygons.last().unwrap().texture, None);
+
+        // A quarter turn carries the bottom edge of the texture round to the side
+        sky.rotate_rate = 45.0;
+        sky.do_frame(2.0);
+        assert_eq!(sky.rotation, 90.0);
+
+        let turned = sky.dome_polygons()[3 * 16 + 4].uvs[3];
+        assert!((turned.0 - 0.0).abs() < 0.01 && (turned.1 - 0.5).abs() < 0.01);
+
+        // Without the flag it holds still
+        sky.flags = SkyFlags::NONE;
+        sky.do_frame(2.0);
+        assert_eq!(sky.rotation, 90.0);
+    }
+
+    #[test]
+    fn satellite_glow_test() {
+        crate::test_common::setup();
+
+        let mut terrain = Terrain::default();
+        terrain.sky.satellites.truncate(2);
+
+        // One sun low over a ridge to the east, the other low in the open to the west
+        terrain.sky.satellites[0].vector = Vector::new(7500.0, 300.0, 0.0);
+        terrain.sky.satellites[1].vector = Vector::new(-7500.0, 300.0, 0.0);
+
+        for satellite in terrain.sky.satellites.iter_mut() {
+            satellite.size = 500.0;
+        }
+
+        let ridge = (20..=22).flat_map(|x| (0..=15).map(move |z| (x, z, 255)));
+        terrain.set_cell_heights(ridge);
+
+        let eye = Vector::new(100.0, 10.0, 100.0);
+        assert!(!terrain.terrain_line_of_sight(&eye, &(eye + terrain.sky.satellites[0].vector)));
+
+        terrain.update_satellite_glow(&eye, 0.25, |_, _| false);
+        assert_eq!(terrain.sky.satellites[0].glow, 0.5);
+        assert_eq!(terrain.sky.satellites[1].glow, 1.0);
+
+        terrain.update_satellite_glow(&eye, 1.0, |_, _| false);
+        assert_eq!(terrain.sky.satellites[0].glow, 0.0);
+
+        // A building in the way hides the other one
+        terrain.update_satellite_glow(&eye, 0.1, |_, to| to.x < 0.0);
+        assert!(terrain.sky.satellites[1].glow < 1.0);
+        assert_eq!(terrain.sky.satellites[0].glow, 0.0);
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+
+use super::{
+    physics::intersection::{FqFlags, Query},
+    prelude::*,
+    room::{PortalFlags, Room, RoomFlags},
+};
+use angle::Angle;
+use matrix::Matrix;
+use vector::Vector;
+
+/// How much quieter a sound is when something solid is between it and the listener
+pub const OCCLUDED_VOLUME_SCALE: f32 = 0.5;
+
+/// Lowpass cutoff for occluded sounds, in Hz
+pub const OCCLUDED_LOWPASS_CUTOFF: f32 = 2000.0;
+
+/// No filtering
+pub const NO_LOWPASS_CUTOFF: f32 = 22050.0;
+
+/// Closed doors antenuate a lot
+pub const CLOSED_DOOR_VOLUME_SCALE: f32 = 0.2;
+
+/// How many volumes a custom falloff curve is given as
+pub const FALLOFF_CURVE_POINTS: usize = 8;
+
+/// The shape of a sound's falloff between its min and max distance
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum FalloffCurve {
+    /// Straight down from full volume to silence
+    #[default]
+    Linear,
+    /// Drops quickly just past the min distance then trails off, closer to how loudness is heard
+    Logarithmic,
+    /// Volumes spread evenly from the min distance to the max, in between is interpolated
+    Custom([f32; FALLOFF_CURVE_POINTS]),
+}
+
+impl FalloffCurve {
+    /// Volume `t` of the way from the min distance to the max
+    fn volume(&self, t: f32, min_distance: f32, max_distance: f32) -> f32 {
+        match self {
+            FalloffCurve::Linear => 1.0 - t,
+            FalloffCurve::Logarithmic => {
+                // Measured from the min distance, one has to be there or every distance is infinitely far
+                let min = min_distance.max(1.0);
+                let max = max_distance.max(min + 1.0);
+                let distance = min + (max - min) * t;
+
+                1.0 - (distance / min).ln() / (max / min).ln()
+            }
+            FalloffCurve::Custom(points) => {
+                let position = t * (FALLOFF_CURVE_POINTS - 1) as f32;
+                let index = (position as usize).min(FALLOFF_CURVE_POINTS - 2);
+                let fraction = position - index as f32;
+
+                points[index] + (points[index + 1] - points[index]) * fraction
+            }
+        }
+    }
+}
+
+/// How loud a sound is over distance
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SoundRolloff {
+    /// Full volume inside this distance
+    pub min_distance: f32,
+    /// Silent past this distance
+    pub max_distance: f32,
+    pub curve: FalloffCurve,
+}
+
+impl Default for SoundRolloff {
+    fn default() -> Self {
+        Self {
+            min_distance: 20.0,
+            max_distance: 250.0,
+            curve: FalloffCurve::Linear,
+        }
+    }
+}
+
+impl SoundRolloff {
+    pub fn volume_at(&self, distance: f32) -> f32 {
+        if distance <= self.min_distance {
+            return 1.0;
+        }
+
+        if distance >= self.max_distance {
+            return 0.0;
+        }
+
+        let t = (distance - self.min_distance) / (self.max_distance - self.min_distance);
+
+        self.curve.volume(t, self.min_distance, self.max_distance).clamp(0.0, 1.0)
+    }
+}
+
+/// The route a sound takes through the mine to reach the listener
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SoundPath {
+    /// Distance along the portals
+    pub distance: f32,
+    /// Where the sound seems to come from, the last portal it passed through
+    pub apparent_position: Vector,
+    /// What the doors along the way let through
+    pub volume_scale: f32,
+}
+
+/// What to play a 3d sound with after occlusion and rolloff
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SoundPlayInfo {
+    pub volume: f32,
+    /// Playback rate, 1 plays the sample as recorded
+    pub pitch: f32,
+    pub distance: f32,
+    pub position: Vector,
+    pub lowpass_cutoff: f32,
+}
+
+fn door_volume_scale(room: &Room) -> f32 {
+    if !room.flags.contains(RoomFlags::DOOR) {
+        return 1.0;
+    }
+
+    let Some(door) = room.assigned_door_data.as_ref() else {
+        return 1.0;
+    };
+
+    let position = door.doorway().borrow().position();
+
+    if position == 0.0 {
+        CLOSED_DOOR_VOLUME_SCALE
+    } else {
+        0.6 + 0.4 * position
+    }
+}
+
+struct PathNode {
+    room: SharedMutRef<Room>,
+    point: Vector,
+    distance: f32,
+    volume_scale: f32,
+}
+
+/// Finds the shortest route through portals from the sound to the listener.  Returns None when
+/// there is no way through within `max_distance`.
+pub fn find_sound_path(
+    sound_room: &SharedMutRef<Room>,
+    sound_pos: &Vector,
+    ear_room: &SharedMutRef<Room>,
+    ear_pos: &Vector,
+    max_distance: f32,
+) -> Option<SoundPath> {
+    let ear_id = ear_room.borrow().id();
+
+    let mut best: HashMap<usize, f32> = HashMap::new();
+    let mut frontier = vec![PathNode {
+        room: sound_room.clone(),
+        point: *sound_pos,
+        distance: 0.0,
+        volume_scale: 1.0,
+    }];
+
+    best.insert(sound_room.borrow().id(), 0.0);
+
+    while !frontier.is_empty() {
+        let closest = frontier
+            .iter()
+            .enumerate()
+            .min_by(|a, b| a.1.distance.total_cmp(&b.1.distance))
+            .map(|(i, _)| i)?;
+
+        let node = frontier.swap_remove(closest);
+        let room = node.room.borrow();
+
+        if room.id() == ear_id {
+            return Some(SoundPath {
+                distance: node.distance + Vector::distance(&node.point, ear_pos),
+                apparent_position: if Rc::ptr_eq(&node.room, sound_room) { *sound_pos } else { node.point },
+                volume_scale: node.volume_scale,
+            });
+        }
+
+        for portal in room.portals.iter() {
+            if portal.flags.contains(PortalFlags::BLOCK) {
+                continue;
+            }
+
+            let Some(next) = portal.connected_room.as_ref() else {
+                continue;
+            };
+
+            let distance = node.distance + Vector::distance(&node.point, &portal.path_point);
+
+            if distance >= max_distance {
+                continue;
+            }
+
+            let next_id = next.borrow().id();
+
+            if best.get(&next_id).is_some_and(|d| *d <= distance) {
+                continue;
+            }
+
+            best.insert(next_id, distance);
+
+            frontier.push(PathNode {
+                room: next.clone(),
+                point: portal.path_point,
+                distance,
+                volume_scale: node.volume_scale * door_volume_scale(&next.borrow()),
+            });
+        }
+    }
+
+    None
+}
+
+/// The FVI query for a cheap ray from the sound to the listener, the same way lighting casts
+/// them, anything it hits means a wall is in the way.  Its result decides what the
+/// `occluded` check given to `compute_room_sound` returns
+pub fn sound_occlusion_query(sound_room: &SharedMutRef<Room>, sound_pos: &Vector, ear_pos: &Vector) -> Query {
+    Query {
+        p0: *sound_pos,
+        p1: *ear_pos,
+        start_room: sound_room.clone(),
+        rad: 0.0,
+        this_obj: None,
+        ignore_obj_list: (),
+        flags: FqFlags::LIGHTING | FqFlags::NO_RELINK,
+        bbox_orientation: Matrix::IDENTITY,
+        bbox_rotvel: Vector::ZERO,
+        bbox_rotthrust: Vector::ZERO,
+        bbox_velocity: Vector::ZERO,
+        bbox_turnroll: Angle(0),
+        bbox_thrust: Vector::ZERO,
+        frametime: 0.0,
+    }
+}
+
+/// Works out how loud a sound is and what it sounds like by the time it reaches the listener
+pub fn compute_play_info(
+    rolloff: &SoundRolloff,
+    volume: f32,
+    sound_pos: &Vector,
+    path: Option<SoundPath>,
+    ear_pos: &Vector,
+    occluded: bool,
+) -> Option<SoundPlayInfo> {
+    // Outdoors or in the same room, it's just straight distance
+    let path = path.unwrap_or(SoundPath {
+        distance: Vector::distance(sound_pos, ear_pos),
+        apparent_position: *sound_pos,
+        volume_scale: 1.0,
+    });
+
+    let mut info = SoundPlayInfo {
+        volume: volume * path.volume_scale * rolloff.volume_at(path.distance),
+        pitch: 1.0,
+        distance: path.distance,
+        position: path.apparent_position,
+        lowpass_cutoff: NO_LOWPASS_CUTOFF,
+    };
+
+    if occluded {
+        info.volume *= OCCLUDED_VOLUME_SCALE;
+        info.lowpass_cutoff = OCCLUDED_LOWPASS_CUTOFF;
+    }
+
+    if info.volume <= 0.0 {
+        return None;
+    }
+
+    Some(info)
+}
+
+/// Does the whole job for a sound in a room, heard by a listener in a room.  `occluded` casts
+/// the ray from the sound to the listener, see `sound_occlusion_query`
+pub fn compute_room_sound(
+    rolloff: &SoundRolloff,
+    volume: f32,
+    sound_room: &SharedMutRef<Room>,
+    sound_pos: &Vector,
+    ear_room: &SharedMutRef<Room>,
+    ear_pos: &Vector,
+    occluded: impl FnOnce(&Vector, &Vector) -> bool,
+) -> Option<SoundPlayInfo> {
+    let outside = sound_room.borrow().is_outside || ear_room.borrow().is_outside;
+    let same_room = Rc::ptr_eq(sound_room, ear_room);
+
+    let path = if outside || same_room {
+        None
+    } else {
+        Some(find_sound_path(sound_room, sound_pos, ear_room, ear_pos, rolloff.max_distance)?)
+    };
+
+    let occluded = occluded(sound_pos, ear_pos);
+
+    compute_play_info(rolloff, volume, sound_pos, path, ear_pos, occluded)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::room::{Portal, RoomStore};
+
+    #[test]
+    fn occlusion_and_rolloff() {
+        crate::test_common::setup();
+
+        let rolloff = SoundRolloff {
+            min_distance: 10.0,
+            max_distance: 110.0,
+            curve: FalloffCurve::Linear,
+        };
+
+        assert_eq!(rolloff.volume_at(5.0), 1.0);
+        assert_eq!(rolloff.volume_at(60.0), 0.5);
+        assert_eq!(rolloff.volume_at(200.0), 0.0);
+
+        let sound = Vector { x: 0.0, y: 0.0, z: 0.0 };
+        let ear = Vector { x: 0.0, y: 0.0, z: 60.0 };
+
+        let clear = compute_play_info(&rolloff, 1.0, &sound, None, &ear, false).unwrap();
+        assert_eq!(clear.volume, 0.5);
+        assert_eq!(clear.lowpass_cutoff, NO_LOWPASS_CUTOFF);
+
+        let blocked = compute_play_info(&rolloff, 1.0, &sound, None, &ear, true).unwrap();
+        assert_eq!(blocked.volume, 0.5 * OCCLUDED_VOLUME_SCALE);
+        assert_eq!(blocked.lowpass_cutoff, OCCLUDED_LOWPASS_CUTOFF);
+
+        // Going the long way around through the portals makes it quieter
+        let portal = Vector { x: 40.0, y: 0.0, z: 30.0 };
+        let path = SoundPath {
+            distance: 100.0,
+            apparent_position: portal,
+            volume_scale: 1.0,
+        };
+        let around = compute_play_info(&rolloff, 1.0, &sound, Some(path), &ear, false).unwrap();
+        assert!(around.volume < clear.volume);
+        assert_eq!(around.position, portal);
+    }
+
+    #[test]
+    fn room_sound_through_portals() {
+        crate::test_common::setup();
+
+        let rolloff = SoundRolloff {
+            min_distance: 10.0,
+            max_distance: 110.0,
+            curve: FalloffCurve::Linear,
+        };
+
+        // Two rooms joined by a portal off to the side, and a third nothing leads to
+        let mut store = RoomStore::new();
+        let ids = [(); 3].map(|_| store.insert(Room::default()));
+        let [a, b, sealed] = ids.map(|id| store.get(id).unwrap().clone());
+
+        let portal_point = Vector::new(40.0, 0.0, 30.0);
+        let portal = |to: &SharedMutRef<Room>| Portal {
+            flags: PortalFlags::empty(),
+            portal_face: None,
+            connected_room: Some(to.clone()),
+            connected_portal: None,
+            bnode_index: (),
+            combine_master: (),
+            path_point: portal_point,
+        };
+        a.borrow_mut().portals.push(portal(&b));
+        b.borrow_mut().portals.push(portal(&a));
+
+        let sound = Vector::new(0.0, 0.0, 0.0);
+        let ear = Vector::new(0.0, 0.0, 60.0);
+
+        // The ray is cast between the sound and the listener, whatever the path
+        let mut cast = None;
+        let info = compute_room_sound(&rolloff, 1.0, &a, &sound, &b, &ear, |from, to| {
+            cast = Some((*from, *to));
+            false
+        })
+        .unwrap();
+        assert_eq!(cast, Some((sound, ear)));
+        assert_eq!(info.position, portal_point);
+        assert_eq!(info.distance, 50.0 + Vector::distance(&portal_point, &ear));
+        assert_eq!(info.lowpass_cutoff, NO_LOWPASS_CUTOFF);
+
+        // In the same room it's straight distance, muffled when the ray hits something
+        let info = compute_room_sound(&rolloff, 1.0, &a, &sound, &a, &ear, |_, _| true).unwrap();
+        assert_eq!(info.volume, 0.5 * OCCLUDED_VOLUME_SCALE);
+        assert_eq!(info.lowpass_cutoff, OCCLUDED_LOWPASS_CUTOFF);
+
+        // No way through, not heard at all
+        assert!(compute_room_sound(&rolloff, 1.0, &a, &sound, &sealed, &ear, |_, _| false).is_none());
+
+        let query = sound_occlusion_query(&a, &sound, &ear);
+        assert_eq!((query.p0, query.p1), (sound, ear));
+    }
+
+    #[test]
+    fn falloff_curves() {
+        crate::test_common::setup();
+
+        let linear = SoundRolloff {
+            min_distance: 10.0,
+            max_distance: 1000.0,
+            curve: FalloffCurve::Linear,
+        };
+        let log = SoundRolloff { curve: FalloffCurve::Logarithmic, ..linear };
+
+        // Same ends, the log curve is quieter all the way between
+        for rolloff in [linear, log] {
+            assert_eq!(rolloff.volume_at(10.0), 1.0);
+            assert_eq!(rolloff.volume_at(1000.0), 0.0);
+        }
+
+        assert!((log.volume_at(100.0) - 0.5).abs() < 1e-5);
+        assert!(log.volume_at(100.0) < linear.volume_at(100.0));
+        assert!(log.volume_at(500.0) < log.volume_at(400.0));
+
+        // A retail style step down, held at full volume for the first part
+        let custom = SoundRolloff {
+            min_distance: 0.0,
+            max_distance: 70.0,
+            curve: FalloffCurve::Custom([1.0, 1.0, 0.8, 0.6, 0.4, 0.2, 0.1, 0.0]),
+        };
+
+        assert_eq!(custom.volume_at(5.0), 1.0);
+        assert!((custom.volume_at(25.0) - 0.7).abs() < 1e-5);
+        assert!((custom.volume_at(65.0) - 0.05).abs() < 1e-5);
+        assert_eq!(custom.volume_at(80.0), 0.0);
+
+        // Curves that go past full volume are held to it
+        let loud = SoundRolloff { curve: FalloffCurve::Custom([2.0; FALLOFF_CURVE_POINTS]), ..custom };
+        assert_eq!(loud.volume_at(35.0), 1.0);
+    }
+}
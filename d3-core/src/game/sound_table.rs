@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use tinyrand::Rand;
+
+use crate::rand::ps_rand;
+
+use super::{
+    audio::SoundId,
+    audio_bus::AudioBus,
+    caption::SoundCaption,
+    prelude::*,
+    sound_occlusion::{FalloffCurve, SoundRolloff},
+};
+
+pub const MAX_SOUNDS: usize = 1000;
+
+bitflags! {
+    /// Flags for a sound table entry.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct SoundFlags: u32 {
+        /// Sound is looped
+        const LOOPED = 1; // SPF_LOOPED
+        /// No doppler shift
+        const FIXED_FREQ = 2; // SPF_FIXED_FREQ
+        /// Sound updates with attached object movements
+        const OBJ_UPDATE = 4; // SPF_OBJ_UPDATE
+        /// Always plays in high-level, this flag should be ignored in low-level
+        const FOREVER = 8; // SPF_FOREVER
+        const PLAYS_EXCLUSIVELY = 16; // SPF_PLAYS_EXCLUSIVELY
+        const PLAYS_ONCE = 32; // SPF_PLAYS_ONCE
+        const USE_CONE = 64; // SPF_USE_CONE
+        /// Sound updates with listener movements
+        const LISTENER_UPDATE = 128; // SPF_LISTENER_UPDATE
+        const ONCE_PER_OBJ = 256; // SPF_ONCE_PER_OBJ
+    }
+}
+
+/// How important a sound is when the mixer runs out of voices
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum SoundPriority {
+    Lowest = 0, // SND_PRIORITY_LOWEST
+    Low = 1,    // SND_PRIORITY_LOW
+    #[default]
+    Normal = 2, // SND_PRIORITY_NORMAL
+    High = 3,    // SND_PRIORITY_HIGH
+    Highest = 4, // SND_PRIORITY_HIGHEST
+    /// usually streams have this priority, bumps off any other sounds.
+    Critical = 5, // SND_PRIORITY_CRITICAL
+}
+
+/// A range a value is picked from each time a sound plays, both ends included
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SoundRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl SoundRange {
+    /// Always exactly one, the sound as it was recorded
+    pub const ONE: SoundRange = SoundRange { min: 1.0, max: 1.0 };
+
+    pub fn pick(&self, rng: &mut impl Rand) -> f32 {
+        if self.max <= self.min {
+            return self.min;
+        }
+
+        self.min + (self.max - self.min) * (ps_rand(rng) as f32 / 32767.0)
+    }
+}
+
+impl Default for SoundRange {
+    fn default() -> Self {
+        Self::ONE
+    }
+}
+
+/// The volume and pitch picked for one play of a sound
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SoundVariation {
+    pub volume: f32,
+    pub pitch: f32,
+}
+
+/// A sound table entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundInfo {
+    pub name: D3String,
+    /// The sample file this sound plays
+    pub file_name: D3String,
+    pub flags: SoundFlags,
+    /// Start byte of repeated loop for looping samples
+    pub loop_start: usize,
+    /// End byte of repeating loop for looping samples
+    pub loop_end: usize,
+    /// Maximum distance in which a sound is heard
+    pub max_distance: f32,
+    /// Sound gets no louder at min_distance
+    pub min_distance: f32,
+    /// How the volume drops between min_distance and max_distance
+    pub falloff: FalloffCurve,
+    /// Angle in which sound is played at full base volume
+    pub inner_cone_angle: i32,
+    /// Angle in which sound is at its lowest base volume
+    pub outer_cone_angle: i32,
+    /// A sounds lowest base volume level
+    pub outer_cone_volume: f32,
+    /// Volume multiplier
+    pub import_volume: f32,
+    /// Scales the volume by a different amount each play
+    pub volume_range: SoundRange,
+    /// Playback rate picked each play, so repeated sounds don't all come out the same
+    pub pitch_range: SoundRange,
+    /// Priority used when the sound is played without one
+    pub priority: SoundPriority,
+    /// Sounds on the voice bus duck the music and sound effects while they play
+    pub bus: AudioBus,
+    /// Text shown on the HUD while a voice plays, when captions are on
+    pub caption: Option<SoundCaption>,
+}
+
+impl Default for SoundInfo {
+    fn default() -> Self {
+        let rolloff = SoundRolloff::default();
+
+        Self {
+            name: D3String::new(),
+            file_name: D3String::new(),
+            flags: SoundFlags::empty(),
+            loop_start: 0,
+            loop_end: 0,
+            max_distance: rolloff.max_distance,
+            min_distance: rolloff.min_distance,
+            falloff: rolloff.curve,
+            inner_cone_angle: 360,
+            outer_cone_angle: 360,
+            outer_cone_volume: 1.0,
+            import_volume: 1.0,
+            volume_range: SoundRange::ONE,
+            pitch_range: SoundRange::ONE,
+            priority: SoundPriority::Normal,
+            bus: AudioBus::Sfx,
+            caption: None,
+        }
+    }
+}
+
+impl SoundInfo {
+    pub fn is_looped(&self) -> bool {
+        self.flags.contains(SoundFlags::LOOPED)
+    }
+
+    pub fn rolloff(&self) -> SoundRolloff {
+        SoundRolloff {
+            min_distance: self.min_distance,
+            max_distance: self.max_distance,
+            curve: self.falloff,
+        }
+    }
+
+    /// Picks the volume and pitch for playing the sound once
+    pub fn vary(&self, rng: &mut impl Rand) -> SoundVariation {
+        SoundVariation {
+            volume: self.import_volume * self.volume_range.pick(rng),
+            pitch: self.pitch_range.pick(rng),
+        }
+    }
+}
+
+/// All the sounds the game knows about, looked up by id or name
+#[derive(Debug, Clone, Default)]
+pub struct SoundTable {
+    sounds: Vec<SoundInfo>,
+    names: HashMap<String, SoundId>,
+}
+
+impl SoundTable {
+    pub fn add(&mut self, info: SoundInfo) -> Result<SoundId> {
+        if self.sounds.len() >= MAX_SOUNDS {
+            return Err(anyhow!("sound table is full"));
+        }
+
+        let name = info
+            .name
+            .to_string()
+            .map_err(|e| anyhow!("bad sound name: {}", e))?
+            .to_lowercase();
+
+        if self.names.contains_key(&name) {
+            return Err(anyhow!("sound {} is already in the table", name));
+        }
+
+        let id = self.sounds.len();
+        self.names.insert(name, id);
+        self.sounds.push(info);
+
+        Ok(id)
+    }
+
+    pub fn get(&self, id: SoundId) -> Option<&SoundInfo> {
+        self.sounds.get(id)
+    }
+
+    /// Sound names aren't case sensitive
+    pub fn find(&self, name: &str) -> Option<SoundId> {
+        self.names.get(&name.to_lowercase()).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sounds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sounds.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SoundId, &SoundInfo)> {
+        self.sounds.iter().enumerate()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_by_name() {
+        crate::test_common::setup();
+
+        let mut table = SoundTable::default();
+
+        let id = table
+            .add(SoundInfo {
+                name: D3String::from("Refueling"),
+                file_name: D3String::from("refuel.wav"),
+                flags: SoundFlags::LOOPED,
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(table.find("REFUELING"), Some(id));
+        assert!(table.get(id).unwrap().is_looped());
+        assert!(table
+            .add(SoundInfo {
+                name: D3String::from("refueling"),
+                ..Default::default()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn sound_variation() {
+        crate::test_common::setup();
+
+        let mut rng = crate::create_rng();
+
+        // Nothing to pick from by default
+        let plain = SoundInfo { import_volume: 0.5, ..Default::default() };
+        assert_eq!(plain.vary(&mut rng), SoundVariation { volume: 0.5, pitch: 1.0 });
+
+        let varied = SoundInfo {
+            volume_range: SoundRange { min: 0.5, max: 1.0 },
+            pitch_range: SoundRange { min: 0.9, max: 1.1 },
+            ..Default::default()
+        };
+
+        for _ in 0..100 {
+            let variation = varied.vary(&mut rng);
+            assert!((0.5..=1.0).contains(&variation.volume));
+            assert!((0.9..=1.1).contains(&variation.pitch));
+        }
+
+        let rolloff = SoundInfo { falloff: FalloffCurve::Logarithmic, ..Default::default() }.rolloff();
+        assert_eq!(rolloff.curve, FalloffCurve::Logarithmic);
+    }
+}
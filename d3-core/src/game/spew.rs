@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+
+use tinyrand::Rand;
+
+use crate::rand::ps_rand;
+
+use super::prelude::*;
+use matrix::Matrix;
+use vector::Vector;
+
+/// Handle scripts use to stop a spew they started
+pub type SpewHandle = u32;
+
+pub const MAX_SPEWS: usize = 50;
+
+bitflags! {
+    /// Which properties get jittered for each thing spewed out
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct SpewFlags: u8 {
+        const RANDOM_SPEED = 1 << 0;
+        const RANDOM_SIZE = 1 << 1;
+        const RANDOM_LIFETIME = 1 << 2;
+        /// Spewed objects fall instead of floating
+        const GRAVITY = 1 << 3;
+    }
+}
+
+/// Where a spew comes out of
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SpewOrigin {
+    /// A gun point on an object, or its center, following it around
+    Object { handle: usize, gunpoint: Option<usize> },
+    /// A fixed point on a face
+    Point { position: Vector, normal: Vector, room: usize },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpewKind {
+    /// A visual effect, by index into the fireball table
+    Effect(usize),
+    /// A physical object, by generic object id
+    Object(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpewInfo {
+    pub origin: SpewOrigin,
+    pub kind: SpewKind,
+    pub flags: SpewFlags,
+    /// Seconds between each thing spewed out
+    pub interval: f32,
+    /// How long the spew keeps going, 0 for until it's stopped
+    pub duration: f32,
+    /// How long each thing spewed out lives
+    pub lifetime: f32,
+    pub size: f32,
+    pub speed: f32,
+    /// How far off the normal things can fly, as a fraction of the side axes
+    pub spread: f32,
+    pub mass: f32,
+    pub drag: f32,
+}
+
+/// One thing for the game to create this frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpewEmission {
+    pub handle: SpewHandle,
+    pub kind: SpewKind,
+    pub position: Vector,
+    pub velocity: Vector,
+    pub size: f32,
+    pub lifetime: f32,
+    pub mass: f32,
+    pub drag: f32,
+    pub gravity: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ActiveSpew {
+    info: SpewInfo,
+    elapsed: f32,
+    next_emit: f32,
+}
+
+/// Emitters attached to objects and faces, started and stopped by scripts
+#[derive(Debug, Clone, Default)]
+pub struct SpewManager {
+    spews: BTreeMap<SpewHandle, ActiveSpew>,
+    next_handle: SpewHandle,
+}
+
+impl SpewManager {
+    /// Starts a spew, `None` if there are too many going already
+    pub fn start(&mut self, info: SpewInfo) -> Option<SpewHandle> {
+        if self.spews.len() >= MAX_SPEWS {
+            return None;
+        }
+
+        // Handles are never reused so a stale one can't stop someone else's spew
+        self.next_handle = self.next_handle.wrapping_add(1);
+        let handle = self.next_handle;
+
+        self.spews.insert(
+            handle,
+            ActiveSpew {
+                info,
+                elapsed: 0.0,
+                next_emit: 0.0,
+            },
+        );
+
+        Some(handle)
+    }
+
+    pub fn stop(&mut self, handle: SpewHandle) -> bool {
+        self.spews.remove(&handle).is_some()
+    }
+
+    /// Stops everything coming out of an object, for when it dies
+    pub fn stop_object(&mut self, object: usize) {
+        self.spews
+            .retain(|_, s| !matches!(s.info.origin, SpewOrigin::Object { handle, .. } if handle == object));
+    }
+
+    pub fn is_active(&self, handle: SpewHandle) -> bool {
+        self.spews.contains_key(&handle)
+    }
+
+    pub fn len(&self) -> usize {
+        self.spews.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.spews.clear();
+    }
+
+    /// Runs the spews for a frame. `locate` finds the position and direction of an object origin,
+    /// returning `None` once the object is gone which ends the spew.
+    pub fn do_frame(
+        &mut self,
+        frametime: f32,
+        rng: &mut impl Rand,
+        mut locate: impl FnMut(usize, Option<usize>) -> Option<(Vector, Vector)>,
+    ) -> Vec<SpewEmission> {
+        let mut emissions = Vec::new();
+        let mut finished = Vec::new();
+
+        for (&handle, spew) in self.spews.iter_mut() {
+            let info = &spew.info;
+
+            let location = match info.origin {
+                SpewOrigin::Object { handle, gunpoint } => locate(handle, gunpoint),
+                SpewOrigin::Point { position, normal, .. } => Some((position, normal)),
+            };
+
+            let Some((position, normal)) = location else {
+                finished.push(handle);
+                continue;
+            };
+
+            spew.elapsed += frametime;
+
+            // Long frames spew several at once so the rate holds up
+            while spew.next_emit <= spew.elapsed {
+                emissions.push(emit(handle, info, &position, &normal, rng));
+
+                if info.interval <= 0.0 {
+                    spew.next_emit = spew.elapsed + frametime;
+                    break;
+                }
+
+                spew.next_emit += info.interval;
+            }
+
+            if info.duration > 0.0 && spew.elapsed >= info.duration {
+                finished.push(handle);
+            }
+        }
+
+        for handle in finished {
+            self.spews.remove(&handle);
+        }
+
+        emissions
+    }
+}
+
+/// A random scale from 0.5 to 1.5 when `random` is set
+fn jitter(random: bool, rng: &mut impl Rand) -> f32 {
+    if random {
+        0.5 + ps_rand(rng) as f32 / 32767.0
+    } else {
+        1.0
+    }
+}
+
+fn emit(handle: SpewHandle, info: &SpewInfo, position: &Vector, normal: &Vector, rng: &mut impl Rand) -> SpewEmission {
+    let mut dir = *normal;
+
+    if info.spread > 0.0 {
+        let m = Matrix::from_vector(Some(normal), None, None);
+        let mut offset = |axis: &Vector| {
+            let r = (ps_rand(rng) as f32 / 32767.0) * 2.0 - 1.0;
+            *axis * (r * info.spread)
+        };
+
+        dir = dir + offset(&m.right) + offset(&m.up);
+        Vector::normalize(&mut dir);
+    }
+
+    SpewEmission {
+        handle,
+        kind: info.kind,
+        position: *position,
+        velocity: dir * (info.speed * jitter(info.flags.contains(SpewFlags::RANDOM_SPEED), rng)),
+        size: info.size * jitter(info.flags.contains(SpewFlags::RANDOM_SIZE), rng),
+        lifetime: info.lifetime * jitter(info.flags.contains(SpewFlags::RANDOM_LIFETIME), rng),
+        mass: info.mass,
+        drag: info.drag,
+        gravity: info.flags.contains(SpewFlags::GRAVITY),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn drip(origin: SpewOrigin) -> SpewInfo {
+        SpewInfo {
+            origin,
+            kind: SpewKind::Effect(3),
+            flags: SpewFlags::RANDOM_SIZE,
+            interval: 0.25,
+            duration: 0.0,
+            lifetime: 1.0,
+            size: 2.0,
+            speed: 10.0,
+            spread: 0.2,
+            mass: 0.0,
+            drag: 0.0,
+        }
+    }
+
+    #[test]
+    fn spew_emit_test() {
+        crate::test_common::setup();
+
+        let mut rng = crate::create_rng();
+        let mut spews = SpewManager::default();
+
+        let normal = Vector::new(0.0, -1.0, 0.0);
+        let handle = spews
+            .start(drip(SpewOrigin::Point {
+                position: Vector::new(0.0, 10.0, 0.0),
+                normal,
+                room: 0,
+            }))
+            .unwrap();
+
+        // One right away, then one every interval, more if the frame is long
+        assert_eq!(spews.do_frame(0.1, &mut rng, |_, _| None).len(), 1);
+        assert_eq!(spews.do_frame(0.1, &mut rng, |_, _| None).len(), 0);
+        let emissions = spews.do_frame(0.6, &mut rng, |_, _| None);
+        assert_eq!(emissions.len(), 3);
+
+        // Inside the cone and jittered in size
+        for e in &emissions {
+            assert!(e.velocity.normalized() * normal > 0.9);
+            assert!(e.size >= 1.0 && e.size <= 3.0);
+            assert_eq!(e.lifetime, 1.0);
+        }
+
+        assert!(spews.stop(handle));
+        assert!(!spews.stop(handle));
+        assert!(spews.do_frame(1.0, &mut rng, |_, _| None).is_empty());
+    }
+
+    #[test]
+    fn spew_object_test() {
+        crate::test_common::setup();
+
+        let mut rng = crate::create_rng();
+        let mut spews = SpewManager::default();
+
+        let timed = spews
+            .start(SpewInfo {
+                duration: 0.5,
+                ..drip(SpewOrigin::Object { handle: 4, gunpoint: Some(1) })
+            })
+            .unwrap();
+        let forever = spews.start(drip(SpewOrigin::Object { handle: 5, gunpoint: None })).unwrap();
+
+        let at = |handle: usize, _: Option<usize>| Some((Vector::new(handle as f32, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0)));
+        let emissions = spews.do_frame(0.1, &mut rng, at);
+        assert_eq!(emissions.len(), 2);
+        assert_eq!(emissions[0].position.x, 4.0);
+
+        // The timed one runs out
+        spews.do_frame(0.5, &mut rng, at);
+        assert!(!spews.is_active(timed));
+        assert!(spews.is_active(forever));
+
+        // And the other goes when its object does
+        spews.do_frame(0.1, &mut rng, |_, _| None);
+        assert_eq!(spews.len(), 0);
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::prelude::*;
+use matrix::Matrix;
+use vector::Vector;
+
+/// Squadmates closer than this push away from each other
+pub const SQUAD_SEPARATION_DIST: f32 = 10.0;
+
+/// Spacing between the slots handed out when a robot joins without a set offset
+pub const SQUAD_DEFAULT_SPACING: f32 = 15.0;
+
+/// A shared sighting is forgotten after this long
+pub const SQUAD_TARGET_MEMORY: f32 = 10.0;
+
+/// How strongly each boid rule pulls on a squad member
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FlockWeights {
+    /// Pull toward the member's slot in the formation
+    pub cohesion: f32,
+    /// Push away from squadmates that are too close
+    pub separation: f32,
+    /// Match the leader's velocity
+    pub alignment: f32,
+}
+
+impl Default for FlockWeights {
+    fn default() -> Self {
+        Self {
+            cohesion: 1.0,
+            separation: 1.5,
+            alignment: 0.5,
+        }
+    }
+}
+
+/// Where a squad member currently is, supplied by the caller each frame
+#[derive(Debug, Copy, Clone)]
+pub struct FlockBody {
+    pub handle: usize,
+    pub position: Vector,
+    pub orientation: Matrix,
+    pub velocity: Vector,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SquadMember {
+    pub handle: usize,
+    /// Slot relative to the leader, in the leader's frame (right, up, forward)
+    pub offset: Vector,
+}
+
+/// Something a squad member saw that the rest of the squad should know about
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SquadTarget {
+    pub handle: usize,
+    pub position: Vector,
+    /// Gametime the target was last seen
+    pub last_seen: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Squad {
+    name: String,
+    leader: Option<usize>,
+    members: Vec<SquadMember>,
+    pub weights: FlockWeights,
+    target: Option<SquadTarget>,
+}
+
+impl Squad {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            leader: None,
+            members: Vec::new(),
+            weights: FlockWeights::default(),
+            target: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn leader(&self) -> Option<usize> {
+        self.leader
+    }
+
+    pub fn members(&self) -> &[SquadMember] {
+        &self.members
+    }
+
+    pub fn contains(&self, handle: usize) -> bool {
+        self.members.iter().any(|m| m.handle == handle)
+    }
+
+    pub fn member(&self, handle: usize) -> Option<&SquadMember> {
+        self.members.iter().find(|m| m.handle == handle)
+    }
+
+    /// The next slot in a V behind the leader
+    fn default_offset(&self) -> Vector {
+        let n = self.members.iter().filter(|m| Some(m.handle) != self.leader).count();
+        let row = (n / 2 + 1) as f32;
+        let side = if n % 2 == 0 { -1.0 } else { 1.0 };
+
+        Vector {
+            x: side * row * SQUAD_DEFAULT_SPACING,
+            y: 0.0,
+            z: -row * SQUAD_DEFAULT_SPACING,
+        }
+    }
+
+    fn add(&mut self, handle: usize, offset: Option<Vector>) {
+        if self.contains(handle) {
+            return;
+        }
+
+        // The first one in leads
+        if self.leader.is_none() {
+            self.leader = Some(handle);
+            self.members.push(SquadMember {
+                handle,
+                offset: Vector { x: 0.0, y: 0.0, z: 0.0 },
+            });
+            return;
+        }
+
+        let offset = offset.unwrap_or_else(|| self.default_offset());
+        self.members.push(SquadMember { handle, offset });
+    }
+
+    fn remove(&mut self, handle: usize) -> bool {
+        let before = self.members.len();
+        self.members.retain(|m| m.handle != handle);
+
+        // Promote the next member when the leader goes away
+        if self.leader == Some(handle) {
+            self.leader = None;
+
+            if let Some(next) = self.members.first().map(|m| m.handle) {
+                self.promote(next);
+            }
+        }
+
+        before != self.members.len()
+    }
+
+    /// Makes a member the leader. Offsets are moved over to be relative to the new leader,
+    /// so everyone keeps their place in the formation.
+    fn promote(&mut self, handle: usize) {
+        let Some(anchor) = self.member(handle).map(|m| m.offset) else {
+            return;
+        };
+
+        for member in self.members.iter_mut() {
+            member.offset -= anchor;
+        }
+
+        self.leader = Some(handle);
+    }
+
+    /// Where a member's slot is in the world right now
+    pub fn slot_position(&self, handle: usize, leader: &FlockBody) -> Option<Vector> {
+        let member = self.member(handle)?;
+        let o = &leader.orientation;
+
+        Some(leader.position + o.right * member.offset.x + o.up * member.offset.y + o.forward * member.offset.z)
+    }
+
+    /// Works out the velocity a member wants this frame.  `bodies` should hold every squad
+    /// member, including the leader.
+    pub fn steer(&self, handle: usize, bodies: &[FlockBody], max_speed: f32) -> Option<Vector> {
+        let leader = self.leader?;
+
+        if handle == leader {
+            return None;
+        }
+
+        let me = bodies.iter().find(|b| b.handle == handle)?;
+        let leader = bodies.iter().find(|b| b.handle == leader)?;
+        let slot = self.slot_position(handle, leader)?;
+
+        let cohesion = slot - me.position;
+        let alignment = leader.velocity - me.velocity;
+
+        let mut separation = Vector { x: 0.0, y: 0.0, z: 0.0 };
+
+        for other in bodies.iter() {
+            if other.handle == handle || !self.contains(other.handle) {
+                continue;
+            }
+
+            let away = me.position - other.position;
+            let dist = Vector::magnitude(&away);
+
+            if dist > 0.0 && dist < SQUAD_SEPARATION_DIST {
+                // Push harder the closer they are
+                separation += away * ((SQUAD_SEPARATION_DIST - dist) / (dist * SQUAD_SEPARATION_DIST) * max_speed);
+            }
+        }
+
+        let mut desired = cohesion * self.weights.cohesion
+            + separation * self.weights.separation
+            + alignment * self.weights.alignment;
+
+        if Vector::magnitude(&desired) > max_speed {
+            Vector::normalize(&mut desired);
+            desired *= max_speed;
+        }
+
+        Some(desired)
+    }
+
+    /// A member spotted something, the whole squad now knows where it is
+    pub fn report_target(&mut self, handle: usize, position: &Vector, gametime: f32) {
+        self.target = Some(SquadTarget {
+            handle,
+            position: *position,
+            last_seen: gametime,
+        });
+    }
+
+    pub fn target(&self, gametime: f32) -> Option<SquadTarget> {
+        self.target.filter(|t| gametime - t.last_seen <= SQUAD_TARGET_MEMORY)
+    }
+
+    pub fn forget_target(&mut self) {
+        self.target = None;
+    }
+}
+
+/// All the squads in the level, by name.  This is what scripts talk to.
+#[derive(Debug, Clone, Default)]
+pub struct SquadManager {
+    squads: HashMap<String, Squad>,
+}
+
+impl SquadManager {
+    pub fn squad(&self, name: &str) -> Option<&Squad> {
+        self.squads.get(name)
+    }
+
+    pub fn squad_mut(&mut self, name: &str) -> Option<&mut Squad> {
+        self.squads.get_mut(name)
+    }
+
+    pub fn squads(&self) -> impl Iterator<Item = &Squad> {
+        self.squads.values()
+    }
+
+    /// The squad a robot is in
+    pub fn squad_of(&self, handle: usize) -> Option<&Squad> {
+        self.squads.values().find(|s| s.contains(handle))
+    }
+
+    pub fn squad_of_mut(&mut self, handle: usize) -> Option<&mut Squad> {
+        self.squads.values_mut().find(|s| s.contains(handle))
+    }
+
+    /// Puts a robot in a squad, making the squad if needed.  A robot is only ever in one squad,
+    /// so it leaves any squad it was already in.  The first robot in a squad leads it.
+    pub fn assign(&mut self, name: &str, handle: usize, offset: Option<Vector>) {
+        if self.squad_of(handle).is_some_and(|s| s.name() == name) {
+            return;
+        }
+
+        self.remove(handle);
+
+        self.squads
+            .entry(name.to_string())
+            .or_insert_with(|| Squad::new(name))
+            .add(handle, offset);
+
+        debug!(target: "ai", "robot {} joined squad {}", handle, name);
+    }
+
+    pub fn set_leader(&mut self, name: &str, handle: usize) -> Result<()> {
+        let squad = self
+            .squads
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("no squad named {}", name))?;
+
+        if !squad.contains(handle) {
+            return Err(anyhow!("robot {} is not in squad {}", handle, name));
+        }
+
+        squad.promote(handle);
+        Ok(())
+    }
+
+    /// Takes a robot out of its squad, squads that end up empty go away
+    pub fn remove(&mut self, handle: usize) {
+        for squad in self.squads.values_mut() {
+            squad.remove(handle);
+        }
+
+        self.squads.retain(|_, s| !s.members.is_empty());
+    }
+
+    pub fn disband(&mut self, name: &str) {
+        self.squads.remove(name);
+    }
+
+    /// Shares a sighting with the robot's squadmates
+    pub fn report_target(&mut self, spotter: usize, target: usize, position: &Vector, gametime: f32) {
+        if let Some(squad) = self.squad_of_mut(spotter) {
+            squad.report_target(target, position, gametime);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.squads.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn body(handle: usize, x: f32, z: f32) -> FlockBody {
+        FlockBody {
+            handle,
+            position: Vector { x, y: 0.0, z },
+            orientation: Matrix::IDENTITY,
+            velocity: Vector { x: 0.0, y: 0.0, z: 0.0 },
+        }
+    }
+
+    #[test]
+    fn members_steer_to_slots_and_share_targets() {
+        crate::test_common::setup();
+
+        let mut squads = SquadManager::default();
+        squads.assign("alpha", 1, None);
+        squads.assign("alpha", 2, Some(Vector { x: 20.0, y: 0.0, z: 0.0 }));
+
+        let squad = squads.squad("alpha").unwrap();
+        assert_eq!(squad.leader(), Some(1));
+
+        let bodies = [body(1, 0.0, 0.0), body(2, 0.0, -50.0)];
+        let v = squad.steer(2, &bodies, 30.0).unwrap();
+        assert!(v.x > 0.0 && v.z > 0.0);
+        assert!(Vector::magnitude(&v) <= 30.0 + 0.001);
+
+        squads.report_target(2, 99, &Vector { x: 5.0, y: 0.0, z: 5.0 }, 1.0);
+        let squad = squads.squad_of(1).unwrap();
+        assert_eq!(squad.target(2.0).unwrap().handle, 99);
+        assert!(squad.target(1.0 + SQUAD_TARGET_MEMORY + 1.0).is_none());
+
+        // Losing the leader promotes the next robot
+        squads.remove(1);
+        assert_eq!(squads.squad("alpha").unwrap().leader(), Some(2));
+        squads.remove(2);
+        assert!(squads.squad("alpha").is_none());
+    }
+
+    #[test]
+    fn formation_follows_new_leader() {
+        crate::test_common::setup();
+
+        let mut squads = SquadManager::default();
+        squads.assign("alpha", 1, None);
+        squads.assign("alpha", 2, None);
+        squads.assign("alpha", 3, None);
+
+        // A V behind the leader
+        let leader = body(1, 0.0, 0.0);
+        let squad = squads.squad("alpha").unwrap();
+        let left = squad.slot_position(2, &leader).unwrap();
+        let right = squad.slot_position(3, &leader).unwrap();
+        assert_eq!(left, Vector { x: -15.0, y: 0.0, z: -15.0 });
+        assert_eq!(right, Vector { x: 15.0, y: 0.0, z: -15.0 });
+
+        // Killing the leader hands the lead to 2, 3 keeps its slot instead of moving to where it
+        // would be behind the old leader
+        squads.remove(1);
+        let squad = squads.squad("alpha").unwrap();
+        assert_eq!(squad.leader(), Some(2));
+        assert_eq!(squad.member(2).unwrap().offset, Vector::default());
+        assert_eq!(squad.member(3).unwrap().offset, Vector { x: 30.0, y: 0.0, z: 0.0 });
+
+        let new_leader = body(2, left.x, left.z);
+        assert_eq!(squad.slot_position(3, &new_leader), Some(right));
+
+        // Same when the lead is handed over on purpose
+        squads.set_leader("alpha", 3).unwrap();
+        let squad = squads.squad("alpha").unwrap();
+        assert_eq!(squad.leader(), Some(3));
+        assert_eq!(squad.member(3).unwrap().offset, Vector::default());
+        assert_eq!(squad.member(2).unwrap().offset, Vector { x: -30.0, y: 0.0, z: 0.0 });
+        assert!(squad.steer(3, &[body(2, left.x, left.z), body(3, right.x, right.z)], 30.0).is_none());
+    }
+}
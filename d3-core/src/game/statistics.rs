@@ -0,0 +1,448 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::net::{read_net_string, write_net_string, MAX_NET_PLAYERS};
+
+use super::{player::Player, prelude::*, team::same_team};
+
+/// Bumped whenever the layout written by `Statistics::write_to` changes
+pub const STATISTICS_VERSION: u16 = 1;
+
+/// Indices of the kill messages in the string table
+pub const TXT_KILLED_BY: usize = 0;
+pub const TXT_KILLED_BY_WEAPON: usize = 1;
+pub const TXT_SUICIDE: usize = 2;
+pub const TXT_KILLED_BY_ROBOT: usize = 3;
+pub const TXT_KILLED_BY_WORLD: usize = 4;
+
+/// What the retail string table has in those slots, `%s` is filled in left to right
+const RETAIL_KILL_MESSAGES: [&str; 5] = [
+    "%s was killed by %s",
+    "%s was killed by %s's %s",
+    "%s took the easy way out",
+    "%s was killed by a robot",
+    "%s died",
+];
+
+/// Lines of text looked up by index, loaded from a string table file
+#[derive(Debug, Clone, Default)]
+pub struct StringTable {
+    strings: Vec<String>,
+}
+
+impl StringTable {
+    /// One string per line, the way the retail .str files are laid out
+    pub fn parse(text: &str) -> Self {
+        Self {
+            strings: text.lines().map(|l| l.trim_end_matches('\r').to_string()).collect(),
+        }
+    }
+
+    /// Just the kill messages, for when no table was loaded
+    pub fn retail_kill_messages() -> Self {
+        Self {
+            strings: RETAIL_KILL_MESSAGES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Missing entries come back empty rather than failing, a bad table shouldn't stop the game
+    pub fn get(&self, index: usize) -> &str {
+        self.strings.get(index).map(|s| s.as_str()).unwrap_or("")
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Fills in each `%s` in order, anything left over stays blank
+    pub fn format(&self, index: usize, args: &[&str]) -> String {
+        let mut args = args.iter();
+        let mut pieces = self.get(index).split("%s");
+        let mut text = pieces.next().unwrap_or("").to_string();
+
+        for piece in pieces {
+            text.push_str(args.next().copied().unwrap_or(""));
+            text.push_str(piece);
+        }
+
+        text
+    }
+}
+
+/// Who or what was behind a death
+#[derive(Debug, Clone, PartialEq)]
+pub enum KillCause {
+    Player { killer: usize, weapon: Option<String> },
+    Robot,
+    /// Lava, crushing doors and the like
+    World,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub suicides: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32,
+}
+
+impl PlayerStats {
+    /// Fraction of shots that hit something, 0 before the first shot
+    pub fn accuracy(&self) -> f32 {
+        if self.shots_fired == 0 {
+            return 0.0;
+        }
+
+        self.shots_hit as f32 / self.shots_fired as f32
+    }
+
+    /// Anarchy scoring, a point per kill and one back for each suicide
+    pub fn score(&self) -> i32 {
+        self.kills as i32 - self.suicides as i32
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<LittleEndian>(self.kills)?;
+        writer.write_u32::<LittleEndian>(self.deaths)?;
+        writer.write_u32::<LittleEndian>(self.suicides)?;
+        writer.write_u32::<LittleEndian>(self.shots_fired)?;
+        writer.write_u32::<LittleEndian>(self.shots_hit)?;
+        Ok(())
+    }
+
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        Ok(Self {
+            kills: reader.read_u32::<LittleEndian>()?,
+            deaths: reader.read_u32::<LittleEndian>()?,
+            suicides: reader.read_u32::<LittleEndian>()?,
+            shots_fired: reader.read_u32::<LittleEndian>()?,
+            shots_hit: reader.read_u32::<LittleEndian>()?,
+        })
+    }
+}
+
+/// One line of the end of level screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryEntry {
+    pub player: usize,
+    pub callsign: String,
+    pub team: Option<u8>,
+    pub score: i32,
+    pub stats: PlayerStats,
+}
+
+/// What gets shown when a level or match ends, best score first
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchSummary {
+    pub entries: Vec<SummaryEntry>,
+    /// Seconds spent in the level
+    pub level_time: f32,
+}
+
+impl MatchSummary {
+    pub fn winner(&self) -> Option<&SummaryEntry> {
+        self.entries.first()
+    }
+}
+
+/// Kills, deaths and shots for each player slot over a level
+#[derive(Debug, Clone)]
+pub struct Statistics {
+    players: Vec<PlayerStats>,
+    pub level_time: f32,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self {
+            players: vec![PlayerStats::default(); MAX_NET_PLAYERS],
+            level_time: 0.0,
+        }
+    }
+}
+
+impl Statistics {
+    pub fn get(&self, player: usize) -> Option<&PlayerStats> {
+        self.players.get(player)
+    }
+
+    pub fn record_shot(&mut self, player: usize) {
+        if let Some(stats) = self.players.get_mut(player) {
+            stats.shots_fired += 1;
+        }
+    }
+
+    pub fn record_hit(&mut self, player: usize) {
+        if let Some(stats) = self.players.get_mut(player) {
+            stats.shots_hit += 1;
+        }
+    }
+
+    /// Counts a death against `victim` and credits whoever did it, returning the HUD message
+    pub fn record_death(&mut self, victim: usize, cause: &KillCause, players: &[Player], strings: &StringTable) -> String {
+        if let Some(stats) = self.players.get_mut(victim) {
+            stats.deaths += 1;
+        }
+
+        let victim_name = callsign(players, victim);
+
+        match cause {
+            KillCause::Player { killer, .. } if *killer == victim => {
+                if let Some(stats) = self.players.get_mut(victim) {
+                    stats.suicides += 1;
+                }
+
+                strings.format(TXT_SUICIDE, &[&victim_name])
+            }
+            KillCause::Player { killer, weapon } => {
+                // Teammates don't score off each other
+                if !same_team(players, *killer, victim) {
+                    if let Some(stats) = self.players.get_mut(*killer) {
+                        stats.kills += 1;
+                    }
+                }
+
+                let killer_name = callsign(players, *killer);
+
+                match weapon {
+                    Some(weapon) => strings.format(TXT_KILLED_BY_WEAPON, &[&victim_name, &killer_name, weapon]),
+                    None => strings.format(TXT_KILLED_BY, &[&victim_name, &killer_name]),
+                }
+            }
+            KillCause::Robot => strings.format(TXT_KILLED_BY_ROBOT, &[&victim_name]),
+            KillCause::World => strings.format(TXT_KILLED_BY_WORLD, &[&victim_name]),
+        }
+    }
+
+    /// Clears the slot for someone joining mid match
+    pub fn reset_player(&mut self, player: usize) {
+        if let Some(stats) = self.players.get_mut(player) {
+            *stats = PlayerStats::default();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.players.fill(PlayerStats::default());
+        self.level_time = 0.0;
+    }
+
+    /// Builds the end of level summary for the players in the game, indexed by slot
+    pub fn summary(&self, players: &[Player]) -> MatchSummary {
+        let mut entries: Vec<SummaryEntry> = players
+            .iter()
+            .enumerate()
+            .zip(self.players.iter())
+            .map(|((player, p), stats)| SummaryEntry {
+                player,
+                callsign: p.callsign.to_string().unwrap_or_default(),
+                team: p.team,
+                score: stats.score(),
+                stats: *stats,
+            })
+            .collect();
+
+        // Ties go to whoever died less
+        entries.sort_by(|a, b| b.score.cmp(&a.score).then(a.stats.deaths.cmp(&b.stats.deaths)));
+
+        MatchSummary {
+            entries,
+            level_time: self.level_time,
+        }
+    }
+
+    /// Writes the stats out for a demo or savegame
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<LittleEndian>(STATISTICS_VERSION)?;
+        writer.write_f32::<LittleEndian>(self.level_time)?;
+        writer.write_u8(self.players.len() as u8)?;
+
+        for stats in &self.players {
+            stats.write_to(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let version = reader.read_u16::<LittleEndian>()?;
+
+        if version != STATISTICS_VERSION {
+            return Err(anyhow!("unsupported statistics version {}", version));
+        }
+
+        let level_time = reader.read_f32::<LittleEndian>()?;
+        let count = reader.read_u8()? as usize;
+
+        if count > MAX_NET_PLAYERS {
+            return Err(anyhow!("too many players in statistics: {}", count));
+        }
+
+        let mut stats = Self {
+            level_time,
+            ..Default::default()
+        };
+
+        for i in 0..count {
+            stats.players[i] = PlayerStats::read_from(reader)?;
+        }
+
+        Ok(stats)
+    }
+}
+
+impl MatchSummary {
+    /// Written at the end of a demo so playback can show the scores without replaying it
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_f32::<LittleEndian>(self.level_time)?;
+        writer.write_u8(self.entries.len() as u8)?;
+
+        for entry in &self.entries {
+            writer.write_u8(entry.player as u8)?;
+            write_net_string(writer, &entry.callsign)?;
+            writer.write_i8(entry.team.map(|t| t as i8).unwrap_or(-1))?;
+            writer.write_i32::<LittleEndian>(entry.score)?;
+            entry.stats.write_to(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let level_time = reader.read_f32::<LittleEndian>()?;
+        let count = reader.read_u8()? as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let player = reader.read_u8()? as usize;
+            let callsign = read_net_string(reader)?;
+            let team = reader.read_i8()?;
+
+            entries.push(SummaryEntry {
+                player,
+                callsign,
+                team: if team < 0 { None } else { Some(team as u8) },
+                score: reader.read_i32::<LittleEndian>()?,
+                stats: PlayerStats::read_from(reader)?,
+            });
+        }
+
+        Ok(Self { entries, level_time })
+    }
+}
+
+fn callsign(players: &[Player], index: usize) -> String {
+    players
+        .get(index)
+        .and_then(|p| p.callsign.to_string().ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn players() -> Vec<Player> {
+        ["Alpha", "Bravo", "Charlie"]
+            .iter()
+            .map(|&name| Player {
+                callsign: D3String::from(name),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn kill_message_test() {
+        crate::test_common::setup();
+
+        let players = players();
+        let strings = StringTable::retail_kill_messages();
+        let mut stats = Statistics::default();
+
+        let killed = KillCause::Player {
+            killer: 0,
+            weapon: Some("Mass Driver".to_string()),
+        };
+        assert_eq!(stats.record_death(1, &killed, &players, &strings), "Bravo was killed by Alpha's Mass Driver");
+
+        let suicide = KillCause::Player { killer: 2, weapon: None };
+        assert_eq!(stats.record_death(2, &suicide, &players, &strings), "Charlie took the easy way out");
+        assert_eq!(stats.record_death(0, &KillCause::Robot, &players, &strings), "Alpha was killed by a robot");
+
+        assert_eq!(stats.get(0).unwrap().kills, 1);
+        assert_eq!(stats.get(0).unwrap().deaths, 1);
+        assert_eq!(stats.get(2).unwrap().suicides, 1);
+        assert_eq!(stats.get(2).unwrap().score(), -1);
+
+        // Short tables leave the gaps blank
+        assert_eq!(StringTable::parse("%s and %s\n").format(0, &["a"]), "a and ");
+        assert_eq!(StringTable::default().format(3, &["a"]), "");
+    }
+
+    #[test]
+    fn statistics_summary_test() {
+        crate::test_common::setup();
+
+        let players = players();
+        let strings = StringTable::retail_kill_messages();
+        let mut stats = Statistics::default();
+
+        for _ in 0..4 {
+            stats.record_shot(2);
+        }
+        stats.record_hit(2);
+        assert_eq!(stats.get(2).unwrap().accuracy(), 0.25);
+        assert_eq!(stats.get(1).unwrap().accuracy(), 0.0);
+
+        stats.record_death(0, &KillCause::Player { killer: 2, weapon: None }, &players, &strings);
+        stats.record_death(1, &KillCause::World, &players, &strings);
+
+        let summary = stats.summary(&players);
+        assert_eq!(summary.entries.len(), 3);
+        assert_eq!(summary.winner().unwrap().callsign, "Charlie");
+
+        // Alpha and Bravo are level on score and deaths so they keep their slot order
+        assert_eq!(summary.entries[1].callsign, "Alpha");
+    }
+
+    #[test]
+    fn statistics_serialize_test() {
+        crate::test_common::setup();
+
+        let players = players();
+        let mut stats = Statistics::default();
+        stats.level_time = 120.5;
+        stats.record_shot(1);
+        stats.record_death(0, &KillCause::Player { killer: 1, weapon: None }, &players, &StringTable::default());
+
+        let mut cursor = Cursor::new(Vec::new());
+        stats.write_to(&mut cursor).unwrap();
+        cursor.set_position(0);
+        let loaded = Statistics::read_from(&mut cursor).unwrap();
+
+        assert_eq!(loaded.level_time, 120.5);
+        assert_eq!(loaded.get(1), stats.get(1));
+        assert_eq!(loaded.get(0).unwrap().deaths, 1);
+
+        let summary = stats.summary(&players);
+        let mut cursor = Cursor::new(Vec::new());
+        summary.write_to(&mut cursor).unwrap();
+        cursor.set_position(0);
+        assert_eq!(MatchSummary::read_from(&mut cursor).unwrap(), summary);
+
+        // Something from a newer build
+        let mut data = Vec::new();
+        stats.write_to(&mut data).unwrap();
+        data[0] = 0xFF;
+        assert!(Statistics::read_from(&mut Cursor::new(data.as_slice())).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+/*
+
+Surface effects
+
+What a hit on each kind of surface sounds and looks like. A shot sparks off
+metal and kicks up dust from rock, footsteps clank or crunch. The material
+comes from the texture of the face or terrain cell that was hit, and the
+table here says which sound and particles go with it. Materials without an
+entry of their own use the generic one.
+
+*/
+
+use std::collections::HashMap;
+
+use super::{
+    audio::SoundId,
+    physics::intersection::{HitType, IntersectionFinderResult},
+    prelude::*,
+    terrain::Terrain,
+    visual_effects::fireball::FireballEffectType,
+};
+use crate::graphics::texture::{SurfaceMaterial, Texture16};
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct SurfaceEffect {
+    pub impact_sound: Option<SoundId>,
+    pub footstep_sound: Option<SoundId>,
+    /// Thrown off where a shot hits
+    pub particles: Option<FireballEffectType>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SurfaceEffectTable {
+    effects: HashMap<SurfaceMaterial, SurfaceEffect>,
+}
+
+impl SurfaceEffectTable {
+    pub fn set(&mut self, material: SurfaceMaterial, effect: SurfaceEffect) {
+        self.effects.insert(material, effect);
+    }
+
+    /// The effect for `material`, or the generic one if it doesn't have its own
+    pub fn effect(&self, material: SurfaceMaterial) -> SurfaceEffect {
+        self.effects
+            .get(&material)
+            .or_else(|| self.effects.get(&SurfaceMaterial::Generic))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// What the surface at one of the hits from an intersection query is made of, for walls and terrain.
+/// `None` for hits on objects or surfaces without a texture
+pub fn hit_material(
+    hit: &IntersectionFinderResult,
+    index: usize,
+    terrain: Option<&Terrain>,
+    textures: &[SharedMutRef<Texture16>],
+) -> Option<SurfaceMaterial> {
+    if index >= hit.hit_count.min(hit.hit_type.len()) {
+        return None;
+    }
+
+    let face = hit.hit_face[index];
+
+    let texture = match (hit.hit_type[index], &hit.hit_face_room[index], terrain) {
+        (HitType::Wall, Some(room), _) => room.borrow().faces.get(face)?.tmap?,
+        (HitType::Terrain, _, Some(terrain)) => {
+            let segment = terrain.segments.get(face)?;
+            terrain.tex_segments.get(segment.texture_segment_index)?.tex_index?
+        }
+        _ => return None,
+    };
+
+    textures.get(texture).map(|t| t.borrow().material)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        game::room::{Face, FaceFlags, Room, RoomStore},
+        graphics::texture::TextureFlags,
+    };
+    use vector::Vector;
+
+    fn texture(flags: TextureFlags, material: Option<&str>) -> SharedMutRef<Texture16> {
+        let mut texture = Texture16 { flags, ..Texture16::default() };
+        texture.load_material(material).unwrap();
+        new_shared_mut_ref(texture)
+    }
+
+    #[test]
+    fn surface_material_test() {
+        crate::test_common::setup();
+
+        // Named in the table, or going by the flags
+        let textures = vec![texture(TextureFlags::NONE, None), texture(TextureFlags::METAL, None), texture(TextureFlags::METAL, Some("Rock"))];
+        assert_eq!(textures.iter().map(|t| t.borrow().material).collect::<Vec<_>>(), vec![
+            SurfaceMaterial::Generic,
+            SurfaceMaterial::Metal,
+            SurfaceMaterial::Rock
+        ]);
+        assert!(Texture16::default().load_material(Some("cheese")).is_err());
+
+        let mut rooms = RoomStore::new();
+        let id = rooms.insert(Room::default());
+        let room = rooms.get(id).unwrap().clone();
+        room.borrow_mut().faces = (0..3)
+            .map(|i| Face {
+                flags: FaceFlags::empty(),
+                num_verts: 0,
+                portal: None,
+                face_verts: Vec::new(),
+                face_uvls: Vec::new(),
+                normal: Vector::ZERO,
+                lightmap: None,
+                special_faces: (),
+                render_frame: (),
+                tmap: if i == 0 { None } else { Some(i) },
+                light_muliple: 0,
+                min_xyz: Vector::ZERO,
+                max_xyz: Vector::ZERO,
+            })
+            .collect();
+
+        let mut hit = IntersectionFinderResult::default();
+        hit.hit_count = 2;
+        hit.hit_type[0] = HitType::Wall;
+        hit.hit_face_room[0] = Some(room.clone());
+        hit.hit_face[0] = 1;
+        hit.hit_type[1] = HitType::Wall;
+        hit.hit_face_room[1] = Some(room.clone());
+        hit.hit_face[1] = 0;
+
+        assert_eq!(hit_material(&hit, 0, None, &textures), Some(SurfaceMaterial::Metal));
+        assert_eq!(hit_material(&hit, 1, None, &textures), None);
+        assert_eq!(hit_material(&hit, 2, None, &textures), None);
+
+        let mut table = SurfaceEffectTable::default();
+        table.set(SurfaceMaterial::Generic, SurfaceEffect { impact_sound: Some(1), ..Default::default() });
+        table.set(SurfaceMaterial::Metal, SurfaceEffect { impact_sound: Some(2), particles: Some(FireballEffectType::Spark), ..Default::default() });
+
+        assert_eq!(table.effect(SurfaceMaterial::Metal).particles, Some(FireballEffectType::Spark));
+        assert_eq!(table.effect(SurfaceMaterial::Water).impact_sound, Some(1));
+    }
+}
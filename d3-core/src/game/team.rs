@@ -0,0 +1,163 @@
+/*
+
+Teams
+
+Team games split the players into two to four teams. A player joining goes
+on whichever team is smallest, and when players leave the teams are evened
+back out by moving the last to join from the biggest team. Every ship wears
+its team's color, painted over its custom texture by
+`RenderContext::tinted_bitmap`. Chat can be sent to a team alone, and the
+end of level screen adds up each team's score.
+
+*/
+
+use crate::{
+    gr_rgb,
+    graphics::ddgr_color,
+    net::MAX_TEAMS,
+};
+
+use super::{player::Player, statistics::MatchSummary};
+
+pub const TEAM_NAMES: [&str; MAX_TEAMS] = ["Red", "Blue", "Green", "Yellow"];
+
+pub const TEAM_COLORS: [ddgr_color; MAX_TEAMS] = [
+    gr_rgb!(255, 32, 32),
+    gr_rgb!(32, 96, 255),
+    gr_rgb!(32, 224, 32),
+    gr_rgb!(255, 224, 32),
+];
+
+pub fn team_color(team: Option<u8>) -> Option<ddgr_color> {
+    TEAM_COLORS.get(team? as usize).copied()
+}
+
+/// How many players are on each of the first `team_count` teams
+pub fn team_sizes(players: &[Player], team_count: usize) -> Vec<usize> {
+    let mut sizes = vec![0; team_count.min(MAX_TEAMS)];
+
+    for team in players.iter().filter_map(|p| p.team) {
+        if let Some(size) = sizes.get_mut(team as usize) {
+            *size += 1;
+        }
+    }
+
+    sizes
+}
+
+/// The team for someone joining, the smallest one, the first of those on a tie
+pub fn pick_team(players: &[Player], team_count: usize) -> u8 {
+    team_sizes(players, team_count)
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, size)| **size)
+        .map_or(0, |(team, _)| team as u8)
+}
+
+/// Moves players from the biggest team to the smallest until no team has two more than another.
+/// Players are indexed by slot and the highest slot on a team moves first, the moves are returned
+/// so they can be sent out and the chat teams updated
+pub fn balance_teams(players: &mut [Player], team_count: usize) -> Vec<(usize, u8)> {
+    let mut moves = Vec::new();
+
+    loop {
+        let sizes = team_sizes(players, team_count);
+
+        let (Some(largest), Some(smallest)) = (
+            (0..sizes.len()).rev().max_by_key(|&t| sizes[t]),
+            (0..sizes.len()).min_by_key(|&t| sizes[t]),
+        ) else {
+            break;
+        };
+
+        if sizes[largest] <= sizes[smallest] + 1 {
+            break;
+        }
+
+        let Some(slot) = players.iter().rposition(|p| p.team == Some(largest as u8)) else {
+            break;
+        };
+
+        players[slot].team = Some(smallest as u8);
+        moves.push((slot, smallest as u8));
+
+        debug!(target: "game", "moved player {} from the {} team to {}", slot, TEAM_NAMES[largest], TEAM_NAMES[smallest]);
+    }
+
+    moves
+}
+
+/// Whether two players are on the same team, never for players not on one
+pub fn same_team(players: &[Player], a: usize, b: usize) -> bool {
+    match (players.get(a).and_then(|p| p.team), players.get(b).and_then(|p| p.team)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Each team's total score, best first
+pub fn team_scores(summary: &MatchSummary) -> Vec<(u8, i32)> {
+    let mut scores: Vec<(u8, i32)> = Vec::new();
+
+    for entry in &summary.entries {
+        let Some(team) = entry.team else {
+            continue;
+        };
+
+        match scores.iter_mut().find(|(t, _)| *t == team) {
+            Some((_, score)) => *score += entry.score,
+            None => scores.push((team, entry.score)),
+        }
+    }
+
+    scores.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    scores
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::statistics::{KillCause, Statistics, StringTable};
+
+    fn on_teams(teams: &[Option<u8>]) -> Vec<Player> {
+        teams.iter().map(|&team| Player { team, ..Player::default() }).collect()
+    }
+
+    #[test]
+    fn team_balance_test() {
+        crate::test_common::setup();
+
+        let mut players = on_teams(&[Some(0), Some(0), Some(1), Some(0), None, Some(0)]);
+        assert_eq!(team_sizes(&players, 2), vec![4, 1]);
+        assert_eq!(pick_team(&players, 2), 1);
+        assert_eq!(pick_team(&players, 3), 2);
+
+        // The last to join the red team moves
+        assert_eq!(balance_teams(&mut players, 2), vec![(5, 1)]);
+        assert_eq!(team_sizes(&players, 2), vec![3, 2]);
+        assert!(balance_teams(&mut players, 2).is_empty());
+
+        assert_eq!(team_color(Some(1)), Some(TEAM_COLORS[1]));
+        assert_eq!(team_color(None), None);
+        assert!(same_team(&players, 0, 1) && !same_team(&players, 0, 2) && !same_team(&players, 4, 4));
+    }
+
+    #[test]
+    fn team_scoring_test() {
+        crate::test_common::setup();
+
+        let players = on_teams(&[Some(0), Some(0), Some(1)]);
+        let strings = StringTable::retail_kill_messages();
+        let mut stats = Statistics::default();
+
+        stats.record_death(2, &KillCause::Player { killer: 0, weapon: None }, &players, &strings);
+        stats.record_death(0, &KillCause::Player { killer: 2, weapon: None }, &players, &strings);
+        stats.record_death(2, &KillCause::Player { killer: 1, weapon: None }, &players, &strings);
+
+        // Killing a teammate doesn't count as a kill
+        stats.record_death(1, &KillCause::Player { killer: 0, weapon: None }, &players, &strings);
+        assert_eq!(stats.get(0).unwrap().kills, 1);
+
+        assert_eq!(team_scores(&stats.summary(&players)), vec![(0, 2), (1, 1)]);
+    }
+}
@@ -5,11 +5,13 @@ use angle::{Angle, EulerAngle};
 use blake3::Hash;
 use byteorder::{LittleEndian, WriteBytesExt};
 use matrix::Matrix;
+use anyhow::Result;
+use noise::{Fbm, Perlin};
 use vector::Vector;
 
 use crate::{
     gr_color_blue, gr_color_green, gr_color_red, gr_rgb, gr_rgb16, graphics::{
-        bitmap::{self, Bitmap16}, color_conversion::{convert_1555_to_grayscale, convert_4444_to_grayscale}, ddgr_color, lightmap::{LightMap16, LightMapFlags}, GpuMemoryResource, GR_RED, OPAQUE_FLAG
+        bitmap::{self, Bitmap16}, color_conversion::{convert_1555_to_grayscale, convert_4444_to_grayscale}, ddgr_color, lightmap::{LightMap16, LightMapFlags}, GpuMemoryResource, TextureHandle, GR_RED, OPAQUE_FLAG
     }
 };
 
@@ -37,6 +39,18 @@ const TERRAIN_TEX_WIDTH: usize = 32;
 
 pub const MAX_TERRAIN_HEIGHT: f32 = 350.0;
 
+/// Index of the terrain cell under a world position, `None` off the edge of the terrain
+pub fn cell_index_at(position: &Vector) -> Option<usize> {
+    if position.x < 0.0 || position.z < 0.0 {
+        return None;
+    }
+
+    let x = (position.x / TERRAIN_SIZE) as usize;
+    let z = (position.z / TERRAIN_SIZE) as usize;
+
+    (x < TERRAIN_WIDTH && z < TERRAIN_DEPTH).then_some(z * TERRAIN_WIDTH + x)
+}
+
 bitflags::bitflags! {
     #[derive(Debug, Copy, Clone)]
     pub struct TerrainFlags: u32 {
@@ -132,7 +146,7 @@ impl Default for TerrainSegment {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct TerrainTextureSegment {
     pub rotation: u8,
     pub tex_index: Option<usize>,
@@ -192,6 +206,8 @@ pub struct Satellite {
     pub flags: SatelliteFlags,
     pub size: f32,
     pub texture: Option<usize>,
+    /// How strongly the halo shows, 0 once hidden behind the terrain or a building
+    pub glow: f32,
 }
 
 impl Default for Satellite {
@@ -204,6 +220,7 @@ impl Default for Satellite {
             flags: SatelliteFlags::NONE,
             size: Default::default(),
             texture: Default::default(),
+            glow: 1.0,
         }
     }
 }
@@ -231,10 +248,15 @@ pub struct TerrainSky {
 
     pub horizon: Horizon,
 
-    pub dome_texture: (),
+    /// Bitmap the dome is mapped with, `None` until it has loaded
+    pub dome_texture: Option<TextureHandle>,
+    pub dome_texture_name: Option<String>,
 
     pub radius: f32,
+    /// Degrees a second the dome turns with `SkyFlags::ROTATE_SKY`
     pub rotate_rate: f32,
+    /// How far the dome has turned so far, in degrees
+    pub rotation: f32,
 
     pub sky_color: ddgr_color,
     pub fog_color: ddgr_color,
@@ -258,12 +280,14 @@ impl Default for TerrainSky {
             is_textured: Default::default(),
             horizon: Default::default(),
             dome_texture: Default::default(),
+            dome_texture_name: Default::default(),
             radius: Default::default(),
             rotate_rate: Default::default(),
+            rotation: Default::default(),
             sky_color: Default::default(),
             fog_color: Default::default(),
             satellites: vec![Default::default(); MAX_SATELLITES],
-            stars: Default::default(),
+            stars: vec![Default::default(); MAX_STARS],
             light_source: Default::default(),
             light_angle: Default::default(),
             damage_per_second: Default::default(),
@@ -322,6 +346,36 @@ impl Default for TerrainNormalPair {
     }
 }
 
+/// Texels of a quadrant lightmap whose cells changed light since it was last written, inclusive
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LightmapDirtyRect {
+    pub x1: usize,
+    pub y1: usize,
+    pub x2: usize,
+    pub y2: usize,
+}
+
+impl LightmapDirtyRect {
+    const FULL: Self = Self {
+        x1: 0,
+        y1: 0,
+        x2: TERRAIN_WIDTH / 2 - 1,
+        y2: TERRAIN_DEPTH / 2 - 1,
+    };
+
+    fn grow(rect: Option<Self>, x: usize, y: usize) -> Self {
+        match rect {
+            Some(r) => Self {
+                x1: r.x1.min(x),
+                y1: r.y1.min(y),
+                x2: r.x2.max(x),
+                y2: r.y2.max(y),
+            },
+            None => Self { x1: x, y1: y, x2: x, y2: y },
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct EdgeRect {
     pub top: u16,
@@ -383,7 +437,7 @@ impl Default for TerrainRenderInfo {
     }
 }
 
-const MAX_LOD: usize = 4;
+pub const MAX_LOD: usize = 4;
 
 #[derive(Debug, Copy, Clone)]
 pub struct TerrainClipRect {
@@ -436,6 +490,10 @@ pub struct Terrain {
     pub total_depth: usize,
     pub frame_count: usize,
 
+    /// Size in cells, what the cell tables are laid out for
+    width: usize,
+    depth: usize,
+
     pub segments: Vec<TerrainSegment>,
     pub node_lists: Vec<SharedMutRef<Vec<Node>>>,
 
@@ -445,6 +503,8 @@ pub struct Terrain {
 
     // Our lighting maps for the terrain, one for each quadrant (starting at lower left)
     pub ligtmaps: [SharedMutRef<LightMap16>; 4],
+    /// What's changed in each lightmap since `update_lightmaps` last wrote it
+    pub lightmap_dirty: [Option<LightmapDirtyRect>; 4],
     pub edge_test: [[i32; 16]; MAX_LOD],
     pub render_info_list: Vec<TerrainRenderInfo>,
     pub visible_z: f32,
@@ -478,14 +538,75 @@ pub struct Terrain {
     pub search: TerrainSearch,
 }
 
+/// Parameters for `Terrain::generate_height_map`
+#[derive(Debug, Copy, Clone)]
+pub struct HeightMapSettings {
+    pub seed: u64,
+    /// Sampled once per cell, so the frequency is in cycles per cell
+    pub fbm: Fbm,
+    pub min_height: u8,
+    pub max_height: u8,
+}
+
+impl Default for HeightMapSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            fbm: Fbm {
+                octaves: 5,
+                frequency: 1.0 / 64.0,
+                ..Default::default()
+            },
+            min_height: 0,
+            max_height: u8::MAX,
+        }
+    }
+}
+
 impl Default for Terrain {
     fn default() -> Self {
+        let quadrant_size = (TERRAIN_WIDTH / 2) * (TERRAIN_DEPTH / 2);
+        let new_lightmap = || new_shared_mut_ref(LightMap16::new(&vec![0; quadrant_size], TERRAIN_WIDTH / 2, TERRAIN_DEPTH / 2));
+
         let mut terrain = Self {
+            checkum: None,
+            check_portal: 0,
+            last_drawn: 0.0,
+            trans_count: 0,
+            total_depth: 0,
+            frame_count: 0,
+            width: TERRAIN_WIDTH,
+            depth: TERRAIN_DEPTH,
+            segments: vec![TerrainSegment::default(); TERRAIN_WIDTH * TERRAIN_DEPTH],
+            node_lists: (0..8).map(|_| new_shared_mut_ref(Vec::new())).collect(),
+            occlusion_map: [[0; 32]; 256],
+            occlusion_checksum: 0,
+            ligtmaps: [new_lightmap(), new_lightmap(), new_lightmap(), new_lightmap()],
+            lightmap_dirty: [Some(LightmapDirtyRect::FULL); 4],
+            edge_test: [[0; 16]; MAX_LOD],
+            render_info_list: vec![TerrainRenderInfo::default(); TERRAIN_WIDTH * TERRAIN_DEPTH],
+            visible_z: 0.0,
+            average_height: 0.0,
+            clip_scale: TerrainClipRect::default(),
+            from_mine: 0,
+            tex_segments: vec![TerrainTextureSegment::default(); TERRAIN_WIDTH * TERRAIN_DEPTH],
+            dynamic_light_table: vec![0; TERRAIN_WIDTH * TERRAIN_DEPTH],
+            normals: Default::default(),
+            delta_blocks: Default::default(),
+            sky: TerrainSky::default(),
+            lod_engine_offset: 0,
+            texture_distance: DEFAULT_TEXTURE_DISTANCE as f32,
+            join_map: vec![0; TERRAIN_WIDTH * TERRAIN_DEPTH],
+            max_heights: Default::default(),
+            min_heights: Default::default(),
+            fast: 0,
+            flat: 0,
+            show_invisible: false,
+            camera_direction: 0,
+            sort_direction: 0,
             rotate_list: vec![0; TERRAIN_WIDTH * TERRAIN_DEPTH],
             world_point_buffer: vec![(); TERRAIN_WIDTH * TERRAIN_DEPTH],
-            join_map: vec![0; TERRAIN_WIDTH * TERRAIN_DEPTH],
-            node_lists: vec![new_shared_mut_ref(Vec::new()); 8],
-            ..Default::default()
+            search: TerrainSearch::default(),
         };
 
         for i in 0..TERRAIN_DEPTH {
@@ -531,6 +652,13 @@ impl Default for Terrain {
 }
 
 impl Terrain {
+    /// Width and depth in cells, what the cell tables were sized for
+    pub fn dims(&self) -> (usize, usize) {
+        debug_assert_eq!(self.segments.len(), self.width * self.depth);
+
+        (self.width, self.depth)
+    }
+
     fn init_min_max(&mut self) {
         for i in 0..7 {
             let w = 1 << i;
@@ -555,7 +683,7 @@ impl Terrain {
 
     /// Builds the min max quadtree data for terrain VSD
     fn build_mix_max(&mut self) {
-        debug!("Building min/max table");
+        debug!(target: "game", "Building min/max table");
 
         // Calculate our integer y positions (0-255)
         for i in 0..TERRAIN_WIDTH * TERRAIN_DEPTH {
@@ -597,11 +725,12 @@ impl Terrain {
                     let mut min_height = 999i32;
                     let mut max_height = 0i32;
 
-                    if h < TERRAIN_DEPTH {
+                    // Take in the edge shared with the next block, the last blocks have none
+                    if (yoffset + 1) * h < TERRAIN_DEPTH {
                         h += 1;
                     }
 
-                    if w < TERRAIN_DEPTH {
+                    if (xoffset + 1) * w < TERRAIN_WIDTH {
                         w += 1;
                     }
 
@@ -859,88 +988,86 @@ impl Terrain {
             let l = dot.trunc() as u8;
 
             self.segments[i].l = l;
-            self.segments[i].r = l;
-            self.segments[i].g = l;
-            self.segments[i].b = l;
+            self.set_cell_light(i % TERRAIN_WIDTH, i / TERRAIN_WIDTH, l, l, l);
         }
 
         self.update_lightmaps();
     }
 
+    /// The quadrant lightmap a cell's light goes in, and the texel in it (lightmaps run top down)
+    fn lightmap_texel(x: usize, z: usize) -> (usize, usize, usize) {
+        (((z / 128) * 2) + (x / 128), x % 128, 127 - (z % 128))
+    }
+
+    /// Flags a cell's lightmap texel to be rewritten on the next `update_lightmaps`
+    pub fn mark_light_dirty(&mut self, x: usize, z: usize) {
+        let (which, tx, ty) = Self::lightmap_texel(x, z);
+        self.lightmap_dirty[which] = Some(LightmapDirtyRect::grow(self.lightmap_dirty[which], tx, ty));
+    }
+
+    /// Sets a cell's light color, only marking it dirty if it actually changed
+    pub fn set_cell_light(&mut self, x: usize, z: usize, r: u8, g: u8, b: u8) {
+        let seg = &mut self.segments[z * TERRAIN_WIDTH + x];
+
+        if (seg.r, seg.g, seg.b) == (r, g, b) {
+            return;
+        }
+
+        seg.r = r;
+        seg.g = g;
+        seg.b = b;
+        self.mark_light_dirty(x, z);
+    }
+
+    fn copy_cell_light(&mut self, dst: usize, src: usize) {
+        let seg = &self.segments[src];
+        self.set_cell_light(dst % TERRAIN_WIDTH, dst / TERRAIN_WIDTH, seg.r, seg.g, seg.b);
+    }
+
+    /// Writes the changed cells into the lightmaps, only the dirty rectangle of each quadrant
+    /// is rewritten and marked for upload
     fn update_lightmaps(&mut self) {
         // First make the wraparounds work right
-
         for i in 0..128 {
             // Lower-left strip
-            self.segments[i * TERRAIN_WIDTH].r = self.segments[i * TERRAIN_WIDTH + 128].r;
-            self.segments[i * TERRAIN_WIDTH].g = self.segments[i * TERRAIN_WIDTH + 128].g;
-            self.segments[i * TERRAIN_WIDTH].b = self.segments[i * TERRAIN_WIDTH + 128].b;
-
-            self.segments[i].r = self.segments[128 * TERRAIN_WIDTH + i].r;
-            self.segments[i].g = self.segments[128 * TERRAIN_WIDTH + i].g;
-            self.segments[i].b = self.segments[128 * TERRAIN_WIDTH + i].b;
+            self.copy_cell_light(i * TERRAIN_WIDTH, i * TERRAIN_WIDTH + 128);
+            self.copy_cell_light(i, 128 * TERRAIN_WIDTH + i);
 
             // Lower-right strip
-            self.segments[i * TERRAIN_WIDTH + 255].r = self.segments[i * TERRAIN_WIDTH + 127].r;
-            self.segments[i * TERRAIN_WIDTH + 255].g = self.segments[i * TERRAIN_WIDTH + 127].g;
-            self.segments[i * TERRAIN_WIDTH + 255].b = self.segments[i * TERRAIN_WIDTH + 127].b;
-
-            self.segments[i + 128].r = self.segments[128 * TERRAIN_WIDTH + i + 128].r;
-            self.segments[i + 128].g = self.segments[128 * TERRAIN_WIDTH + i + 128].g;
-            self.segments[i + 128].b = self.segments[128 * TERRAIN_WIDTH + i + 128].b;
+            self.copy_cell_light(i * TERRAIN_WIDTH + 255, i * TERRAIN_WIDTH + 127);
+            self.copy_cell_light(i + 128, 128 * TERRAIN_WIDTH + i + 128);
 
             // Upper-left strip
-            self.segments[(i + 128) * TERRAIN_WIDTH].r =
-                self.segments[(i + 128) * TERRAIN_WIDTH + 128].r;
-            self.segments[(i + 128) * TERRAIN_WIDTH].g =
-                self.segments[(i + 128) * TERRAIN_WIDTH + 128].g;
-            self.segments[(i + 128) * TERRAIN_WIDTH].b =
-                self.segments[(i + 128) * TERRAIN_WIDTH + 128].b;
-
-            self.segments[255 * TERRAIN_WIDTH + i].r = self.segments[127 * TERRAIN_WIDTH + i].r;
-            self.segments[255 * TERRAIN_WIDTH + i].g = self.segments[127 * TERRAIN_WIDTH + i].g;
-            self.segments[255 * TERRAIN_WIDTH + i].b = self.segments[127 * TERRAIN_WIDTH + i].b;
+            self.copy_cell_light((i + 128) * TERRAIN_WIDTH, (i + 128) * TERRAIN_WIDTH + 128);
+            self.copy_cell_light(255 * TERRAIN_WIDTH + i, 127 * TERRAIN_WIDTH + i);
 
             // Upper-right strip
-            self.segments[(i + 128) * TERRAIN_WIDTH + 255].r =
-                self.segments[(i + 128) * TERRAIN_WIDTH + 127].r;
-            self.segments[(i + 128) * TERRAIN_WIDTH + 255].g =
-                self.segments[(i + 128) * TERRAIN_WIDTH + 127].g;
-            self.segments[(i + 128) * TERRAIN_WIDTH + 255].b =
-                self.segments[(i + 128) * TERRAIN_WIDTH + 127].b;
-
-            self.segments[255 * TERRAIN_WIDTH + i + 128].r =
-                self.segments[127 * TERRAIN_WIDTH + i + 128].r;
-            self.segments[255 * TERRAIN_WIDTH + i + 128].g =
-                self.segments[127 * TERRAIN_WIDTH + i + 128].g;
-            self.segments[255 * TERRAIN_WIDTH + i + 128].b =
-                self.segments[127 * TERRAIN_WIDTH + i + 128].b;
+            self.copy_cell_light((i + 128) * TERRAIN_WIDTH + 255, (i + 128) * TERRAIN_WIDTH + 127);
+            self.copy_cell_light(255 * TERRAIN_WIDTH + i + 128, 127 * TERRAIN_WIDTH + i + 128);
         }
 
-        let lightmap_ref = &self.ligtmaps[0];
-        let mut lightmap = lightmap_ref.borrow_mut();
-        let w = lightmap.width();
+        for which in 0..4 {
+            let Some(rect) = self.lightmap_dirty[which].take() else {
+                continue;
+            };
 
-        for i in 0..TERRAIN_DEPTH {
-            for t in 0..TERRAIN_WIDTH {
-                let seg = &self.segments[i * TERRAIN_WIDTH + t];
-                let x = t % 128;
-                let y = 127 - (i % 128);
-                let which = ((i / 128) * 2) + (t / 128);
+            let sx = (which % 2) * 128;
+            let sz = (which / 2) * 128;
 
-                let color = gr_rgb16!(seg.r, seg.g, seg.b);
-                let lightmap_ref = &self.ligtmaps[which];
-                let mut lightmap = lightmap_ref.borrow_mut();
-                
-                lightmap.data_mut()[y * w + w] = OPAQUE_FLAG | color;
-            }
-        }
+            let mut lightmap = self.ligtmaps[which].borrow_mut();
+            let w = lightmap.width();
+
+            lightmap.mark_region_updated(rect.x1, rect.y1, rect.x2, rect.y2);
+            let data = lightmap.data_mut();
 
-        for i in 0..4 {
-            let lightmap_ref = &self.ligtmaps[i];
-            let mut lightmap = lightmap_ref.borrow_mut();
-            let flags = lightmap.flags();
-            lightmap.set_flags(flags & !LightMapFlags::Limits);
+            for y in rect.y1..=rect.y2 {
+                let row = (sz + 127 - y) * TERRAIN_WIDTH + sx;
+
+                for x in rect.x1..=rect.x2 {
+                    let seg = &self.segments[row + x];
+                    data[y * w + x] = OPAQUE_FLAG | gr_rgb16!(seg.r, seg.g, seg.b);
+                }
+            }
         }
     }
 
@@ -1000,10 +1127,10 @@ impl Terrain {
         deltas[2] = (self.segments[midy * TERRAIN_WIDTH + x1].y - (((v1 - v0) / 2.0) + v0)).abs();
 
         // top edge
-        deltas[3] = (self.segments[y2 * TERRAIN_WIDTH + midx].y - (((v2 - v1) / 2.0) + v1)).abs();
+        deltas[3] = (self.segments[edgey * TERRAIN_WIDTH + midx].y - (((v2 - v1) / 2.0) + v1)).abs();
 
         // right edge
-        deltas[4] = (self.segments[midy * TERRAIN_WIDTH + x2].y - (((v3 - v2) / 2.0) + v2)).abs();
+        deltas[4] = (self.segments[midy * TERRAIN_WIDTH + edgex].y - (((v3 - v2) / 2.0) + v2)).abs();
 
         // bottom edge
         deltas[5] = (self.segments[y1 * TERRAIN_WIDTH + midx].y - (((v3 - v0) / 2.0) + v0)).abs();
@@ -1154,8 +1281,9 @@ impl Terrain {
             let angle_increment = 65535 / MAX_HORIZON_PIECES;
 
             for t in 0..MAX_HORIZON_PIECES {
-                let mut cur_sin = ((t * angle_increment) as f32).sin() * scalar;
-                let mut cur_cos = ((t * angle_increment) as f32).cos() * scalar;
+                let angle = Angle((t * angle_increment) as u16);
+                let mut cur_sin = angle.sin() * scalar;
+                let mut cur_cos = angle.cos() * scalar;
 
                 cur_sin = (cur_sin + 1.0) / 2.0;
                 cur_cos = (cur_cos + 1.0) / 2.0;
@@ -1229,7 +1357,7 @@ impl Terrain {
 
             let angle = EulerAngle {
                 pitch: Angle((top as u16 + p) % 65336),
-                heading: Angle(((rand.next_u32() * rand.next_u32()) % 65536) as u16),
+                heading: Angle((rand.next_u32().wrapping_mul(rand.next_u32()) % 65536) as u16),
                 bank: Angle(0),
             };
 
@@ -1240,37 +1368,123 @@ impl Terrain {
         }
     }
 
+    /// Sets every cell from an image style height lookup, where row 0 is the far (max z) edge,
+    /// then rebuilds everything that depends on the heights
+    fn set_height_map<F: Fn(usize, usize) -> u8>(&mut self, height_at: F) {
+        for i in 0..TERRAIN_DEPTH {
+            for j in 0..TERRAIN_WIDTH {
+                let seg_offset = ((TERRAIN_WIDTH - 1) - i) * TERRAIN_WIDTH + j;
+                self.segments[seg_offset].y_scalar = height_at(i, j);
+            }
+        }
+
+        self.build_mix_max();
+        self.build_normals();
+        self.generate_light();
+    }
+
+    /// Heights in image order, row 0 is the far (max z) edge
+    fn height_map_rows(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..TERRAIN_DEPTH).flat_map(move |i| {
+            (0..TERRAIN_WIDTH).map(move |j| self.segments[((TERRAIN_WIDTH - 1) - i) * TERRAIN_WIDTH + j].y_scalar)
+        })
+    }
+
     pub fn load_height_map(&mut self, bitmap_ref: &SharedMutRef<dyn Bitmap16>) {
         let bitmap = bitmap_ref.as_ref().borrow();
         let width = bitmap.width();
         let height = bitmap.height();
         let data = bitmap.data();
+        let format = bitmap.format();
 
-        let terrain_map = vec![0u8; width * height];
+        self.set_height_map(|i, j| {
+            let data_offset = ((i % height) * width) + (j % width);
 
-        for i in 0..TERRAIN_DEPTH {
-            for j in 0..TERRAIN_WIDTH {
-                let data_offset = ((i % height) * width) + (j % width);
-                let seg_offset = ((TERRAIN_WIDTH - 1) - i) * TERRAIN_WIDTH + j;
+            match format {
+                bitmap::BitmapFormat::Fmt1555 => convert_1555_to_grayscale(data[data_offset]),
+                bitmap::BitmapFormat::Fmt4444 => convert_4444_to_grayscale(data[data_offset]),
+            }
+        });
+    }
 
-                let grayscale_color;
+    /// Loads an 8-bit headerless height map, one byte per cell in rows of TERRAIN_WIDTH
+    pub fn load_height_map_raw(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() != TERRAIN_WIDTH * TERRAIN_DEPTH {
+            return Err(anyhow!(
+                "raw height map is {} bytes, expected {}",
+                data.len(),
+                TERRAIN_WIDTH * TERRAIN_DEPTH
+            ));
+        }
 
-                match bitmap.format() {
-                    bitmap::BitmapFormat::Fmt1555 => {
-                        grayscale_color = convert_1555_to_grayscale(data[data_offset])
-                    }
-                    bitmap::BitmapFormat::Fmt4444 => {
-                        grayscale_color = convert_4444_to_grayscale(data[data_offset])
-                    }
-                }
+        self.set_height_map(|i, j| data[i * TERRAIN_WIDTH + j]);
 
-                self.segments[seg_offset].y_scalar = grayscale_color;
-            }
+        Ok(())
+    }
+
+    /// The height map as 8-bit headerless data, the same layout `load_height_map_raw` reads
+    pub fn save_height_map_raw(&self) -> Vec<u8> {
+        self.height_map_rows().collect()
+    }
+
+    /// Loads an 8 or 16-bit grayscale PNG. Smaller images are tiled like bitmaps are,
+    /// 16-bit heights keep only their top 8 bits.
+    #[cfg(feature = "heightmap_png")]
+    pub fn load_height_map_png<R: std::io::Read>(&mut self, reader: R) -> Result<()> {
+        let mut png_reader = png::Decoder::new(reader).read_info()?;
+        let mut buffer = vec![0u8; png_reader.output_buffer_size()];
+        let info = png_reader.next_frame(&mut buffer)?;
+
+        if info.color_type != png::ColorType::Grayscale {
+            return Err(anyhow!("height map must be grayscale, got {:?}", info.color_type));
         }
 
-        self.build_mix_max();
-        self.build_normals();
-        self.generate_light();
+        let bytes_per_pixel = match info.bit_depth {
+            png::BitDepth::Eight => 1,
+            // Big endian, the high byte comes first
+            png::BitDepth::Sixteen => 2,
+            depth => return Err(anyhow!("unsupported height map bit depth {:?}", depth)),
+        };
+
+        let width = info.width as usize;
+        let height = info.height as usize;
+        let line_size = info.line_size;
+
+        self.set_height_map(|i, j| buffer[(i % height) * line_size + (j % width) * bytes_per_pixel]);
+
+        Ok(())
+    }
+
+    /// Writes the height map as a 16-bit grayscale PNG
+    #[cfg(feature = "heightmap_png")]
+    pub fn save_height_map_png<W: std::io::Write>(&self, writer: W) -> Result<()> {
+        let mut encoder = png::Encoder::new(writer, TERRAIN_WIDTH as u32, TERRAIN_DEPTH as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+
+        // Spread 0-255 over the whole 16-bit range so it reads back exactly
+        let data: Vec<u8> = self
+            .height_map_rows()
+            .flat_map(|h| (h as u16 * 257).to_be_bytes())
+            .collect();
+
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&data)?;
+
+        Ok(())
+    }
+
+    /// Fills the height map with fractal noise instead of loading it, handy for test levels
+    pub fn generate_height_map(&mut self, settings: &HeightMapSettings) {
+        let noise = Perlin::new(settings.seed);
+        let fbm = settings.fbm;
+        let min = settings.min_height as f32;
+        let range = settings.max_height.saturating_sub(settings.min_height) as f32;
+
+        self.set_height_map(|i, j| {
+            let v = fbm.sample_2d(&noise, j as f32, i as f32);
+            (min + (v * 0.5 + 0.5).clamp(0.0, 1.0) * range) as u8
+        });
     }
 
     pub fn build_normal_for_segment(&mut self, seg: usize) {
@@ -1380,6 +1594,97 @@ impl Terrain {
         }
     }
 
+    /// Height (0-255) of the cell at x, z
+    pub fn cell_height(&self, x: usize, z: usize) -> u8 {
+        self.segments[z * TERRAIN_WIDTH + x].y_scalar
+    }
+
+    /// World space corner of the cell at x, z
+    pub fn cell_position(&self, x: usize, z: usize) -> Vector {
+        Vector {
+            x: x as f32 * TERRAIN_SIZE,
+            y: self.segments[z * TERRAIN_WIDTH + x].y,
+            z: z as f32 * TERRAIN_SIZE,
+        }
+    }
+
+    /// Ground height under a world x, z, blended between the corners of the cell it falls in.
+    /// `None` off the edge of the terrain
+    pub fn ground_height(&self, x: f32, z: f32) -> Option<f32> {
+        let (fx, fz) = (x / TERRAIN_SIZE, z / TERRAIN_SIZE);
+
+        if fx < 0.0 || fz < 0.0 || fx > (TERRAIN_WIDTH - 1) as f32 || fz > (TERRAIN_DEPTH - 1) as f32 {
+            return None;
+        }
+
+        let x0 = (fx as usize).min(TERRAIN_WIDTH - 2);
+        let z0 = (fz as usize).min(TERRAIN_DEPTH - 2);
+        let (tx, tz) = (fx - x0 as f32, fz - z0 as f32);
+
+        let y = |x: usize, z: usize| self.segments[z * TERRAIN_WIDTH + x].y;
+        let near = y(x0, z0) + (y(x0 + 1, z0) - y(x0, z0)) * tx;
+        let far = y(x0, z0 + 1) + (y(x0 + 1, z0 + 1) - y(x0, z0 + 1)) * tx;
+
+        Some(near + (far - near) * tz)
+    }
+
+    /// Normals of the upper left and lower right triangles of the cell at x, z
+    pub fn cell_normals(&self, x: usize, z: usize) -> (Vector, Vector) {
+        let pair = &self.normals[MAX_LOD - 1][z * TERRAIN_WIDTH + x];
+        (pair.upper_left_triangle, pair.lower_right_triangle)
+    }
+
+    /// Height error of the LOD block holding the cell at x, z if it were drawn at `lod`,
+    /// None when the block is shut off. The finest level has no blocks.
+    pub fn lod_delta(&self, lod: usize, x: usize, z: usize) -> Option<f32> {
+        if lod >= MAX_LOD - 1 {
+            return None;
+        }
+
+        let simple_mul = 1 << ((MAX_LOD - 1) - lod);
+        let row_size = TERRAIN_WIDTH / simple_mul;
+        let delta = *self.delta_blocks[lod].get((z / simple_mul) * row_size + x / simple_mul)?;
+
+        if delta >= SHUTOFF_LOD_DELTA {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    /// Changes cell heights then rebuilds min/max, normals and lighting once for the lot
+    pub fn set_cell_heights<I: IntoIterator<Item = (usize, usize, u8)>>(&mut self, cells: I) {
+        for (x, z, height) in cells {
+            self.segments[z * TERRAIN_WIDTH + x].y_scalar = height;
+        }
+
+        self.build_mix_max();
+        self.build_normals();
+        self.generate_light();
+    }
+
+    /// The texture segment covering the cell at x, z, each one spans several cells
+    pub fn cell_texture(&self, x: usize, z: usize) -> TerrainTextureSegment {
+        self.tex_segments[self.segments[z * TERRAIN_WIDTH + x].texture_segment_index]
+    }
+
+    pub fn set_cell_texture(&mut self, x: usize, z: usize, texture: TerrainTextureSegment) {
+        let index = self.segments[z * TERRAIN_WIDTH + x].texture_segment_index;
+        self.tex_segments[index] = texture;
+    }
+
+    /// AI region (0-7) of the cell at x, z
+    pub fn cell_region(&self, x: usize, z: usize) -> u8 {
+        self.lookup_region(z * TERRAIN_WIDTH + x) as u8
+    }
+
+    pub fn set_cell_region(&mut self, x: usize, z: usize, region: u8) {
+        let segment = &mut self.segments[z * TERRAIN_WIDTH + x];
+        let bits = ((region as u32) << 5) & TerrainFlags::REGION_MASK.bits();
+
+        segment.flags = TerrainFlags::from_bits_retain((segment.flags.bits() & !TerrainFlags::REGION_MASK.bits()) | bits);
+    }
+
     pub fn lookup_region(&self, num: usize) -> usize {
         let value = self.segments[num].flags.bits() & TerrainFlags::REGION_MASK.bits();
         (value >> 5) as usize
@@ -1392,4 +1697,109 @@ impl Terrain {
             node_list.clear();
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn lightmap_dirty_rect_test() {
+        crate::test_common::setup();
+
+        let mut terrain = Terrain::default();
+        terrain.update_lightmaps();
+        assert!(terrain.lightmap_dirty.iter().all(|r| r.is_none()));
+
+        for lightmap in &terrain.ligtmaps {
+            lightmap.borrow_mut().clear_updated();
+        }
+
+        // Setting the same light again doesn't dirty anything
+        let seg = terrain.segments[5 * TERRAIN_WIDTH + 3].clone();
+        terrain.set_cell_light(3, 5, seg.r, seg.g, seg.b);
+        assert!(terrain.lightmap_dirty[0].is_none());
+
+        terrain.set_cell_light(3, 5, 255, 0, 0);
+        terrain.set_cell_light(10, 7, 0, 255, 0);
+        assert_eq!(
+            terrain.lightmap_dirty[0],
+            Some(LightmapDirtyRect { x1: 3, y1: 120, x2: 10, y2: 122 })
+        );
+
+        terrain.update_lightmaps();
+
+        // Only the first quadrant goes up, and only the changed area of it
+        let lightmap = terrain.ligtmaps[0].as_ref().borrow();
+        assert!(lightmap.flags().contains(LightMapFlags::Limits));
+        assert_eq!(lightmap.deltas(), (3, 120, 10, 122));
+        assert_eq!(lightmap.data()[122 * 128 + 3], OPAQUE_FLAG | gr_rgb16!(255, 0, 0));
+        assert_eq!(lightmap.data()[120 * 128 + 10], OPAQUE_FLAG | gr_rgb16!(0, 255, 0));
+        assert!(!terrain.ligtmaps[1].as_ref().borrow().is_updated());
+    }
+
+    fn test_height_map() -> Vec<u8> {
+        (0..TERRAIN_WIDTH * TERRAIN_DEPTH).map(|i| (i * 7 + i / TERRAIN_WIDTH * 13) as u8).collect()
+    }
+
+    #[test]
+    fn height_map_raw_round_trip_test() {
+        crate::test_common::setup();
+
+        let data = test_height_map();
+        let mut terrain = Terrain::default();
+        terrain.load_height_map_raw(&data).unwrap();
+        assert_eq!(terrain.save_height_map_raw(), data);
+
+        // Row 0 of the data is the far edge
+        assert_eq!(terrain.cell_height(0, TERRAIN_DEPTH - 1), data[0]);
+        assert_eq!(terrain.cell_height(5, 0), data[(TERRAIN_DEPTH - 1) * TERRAIN_WIDTH + 5]);
+
+        assert!(terrain.load_height_map_raw(&data[1..]).is_err());
+    }
+
+    #[cfg(feature = "heightmap_png")]
+    #[test]
+    fn height_map_png_round_trip_test() {
+        crate::test_common::setup();
+
+        let data = test_height_map();
+        let mut terrain = Terrain::default();
+        terrain.load_height_map_raw(&data).unwrap();
+
+        let mut png = Vec::new();
+        terrain.save_height_map_png(&mut png).unwrap();
+
+        let mut loaded = Terrain::default();
+        loaded.load_height_map_png(png.as_slice()).unwrap();
+        assert_eq!(loaded.save_height_map_raw(), data);
+    }
+
+    #[test]
+    fn generate_height_map_test() {
+        crate::test_common::setup();
+
+        let settings = HeightMapSettings {
+            seed: 1234,
+            min_height: 40,
+            max_height: 200,
+            ..Default::default()
+        };
+
+        let mut terrain = Terrain::default();
+        terrain.generate_height_map(&settings);
+        let heights = terrain.save_height_map_raw();
+        assert!(heights.iter().all(|&h| (40..=200).contains(&h)));
+
+        // Not flat
+        assert!(heights.iter().any(|&h| h != heights[0]));
+
+        // The same seed always gives the same map, a different one doesn't
+        let mut again = Terrain::default();
+        again.generate_height_map(&settings);
+        assert_eq!(again.save_height_map_raw(), heights);
+
+        again.generate_height_map(&HeightMapSettings { seed: 4321, ..settings });
+        assert_ne!(again.save_height_map_raw(), heights);
+    }
+}
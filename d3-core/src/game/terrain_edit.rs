@@ -0,0 +1,396 @@
+//! Undoable terrain editing for tools. Every change goes through an `EditOp` so it can be
+//! taken back, and tools only need the cell level access in `TerrainCells`.
+
+use super::terrain::{Terrain, TerrainTextureSegment, TERRAIN_DEPTH, TERRAIN_WIDTH};
+
+/// How many edits are kept for undo by default
+pub const DEFAULT_MAX_UNDO: usize = 100;
+
+/// Cell level access to a height field, `Terrain` is the real one
+pub trait TerrainCells {
+    fn width(&self) -> usize;
+    fn depth(&self) -> usize;
+
+    fn height(&self, x: usize, z: usize) -> u8;
+    /// Called once per edit with every changed cell so derived data is rebuilt once
+    fn set_heights(&mut self, cells: &[(usize, usize, u8)]);
+
+    fn texture(&self, x: usize, z: usize) -> TerrainTextureSegment;
+    fn set_texture(&mut self, x: usize, z: usize, texture: TerrainTextureSegment);
+
+    fn region(&self, x: usize, z: usize) -> u8;
+    fn set_region(&mut self, x: usize, z: usize, region: u8);
+}
+
+impl TerrainCells for Terrain {
+    fn width(&self) -> usize {
+        TERRAIN_WIDTH
+    }
+
+    fn depth(&self) -> usize {
+        TERRAIN_DEPTH
+    }
+
+    fn height(&self, x: usize, z: usize) -> u8 {
+        self.cell_height(x, z)
+    }
+
+    fn set_heights(&mut self, cells: &[(usize, usize, u8)]) {
+        self.set_cell_heights(cells.iter().copied());
+    }
+
+    fn texture(&self, x: usize, z: usize) -> TerrainTextureSegment {
+        self.cell_texture(x, z)
+    }
+
+    fn set_texture(&mut self, x: usize, z: usize, texture: TerrainTextureSegment) {
+        self.set_cell_texture(x, z, texture);
+    }
+
+    fn region(&self, x: usize, z: usize) -> u8 {
+        self.cell_region(x, z)
+    }
+
+    fn set_region(&mut self, x: usize, z: usize, region: u8) {
+        self.set_cell_region(x, z, region);
+    }
+}
+
+/// A round brush centered on a cell, `radius` is in cells
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Brush {
+    pub x: usize,
+    pub z: usize,
+    pub radius: f32,
+}
+
+impl Brush {
+    pub fn new(x: usize, z: usize, radius: f32) -> Self {
+        Self { x, z, radius }
+    }
+
+    /// Cells under the brush with their strength, 1 in the middle down to 0 at the edge
+    fn cells(&self, width: usize, depth: usize) -> Vec<(usize, usize, f32)> {
+        let radius = self.radius.max(0.0);
+        let reach = radius as usize;
+
+        let x0 = self.x.saturating_sub(reach);
+        let z0 = self.z.saturating_sub(reach);
+        let x1 = (self.x + reach).min(width.saturating_sub(1));
+        let z1 = (self.z + reach).min(depth.saturating_sub(1));
+
+        let mut cells = Vec::new();
+
+        for z in z0..=z1 {
+            for x in x0..=x1 {
+                let dx = x as f32 - self.x as f32;
+                let dz = z as f32 - self.z as f32;
+                let dist = (dx * dx + dz * dz).sqrt();
+
+                if dist > radius {
+                    continue;
+                }
+
+                let strength = if radius > 0.0 { 1.0 - dist / radius } else { 1.0 };
+                cells.push((x, z, strength));
+            }
+        }
+
+        cells
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EditOp {
+    /// Raises cells by up to `amount` (lowers if negative), fading out towards the edge of the brush
+    Raise { brush: Brush, amount: i32 },
+    /// Sets every cell under the brush to one height
+    Flatten { brush: Brush, height: u8 },
+    PaintTexture { brush: Brush, texture: TerrainTextureSegment },
+    /// Sets the AI region (0-7) of every cell under the brush
+    PaintRegion { brush: Brush, region: u8 },
+}
+
+/// What the cells looked like before an edit
+#[derive(Debug, Clone)]
+enum CellBackup {
+    Heights(Vec<(usize, usize, u8)>),
+    Textures(Vec<(usize, usize, TerrainTextureSegment)>),
+    Regions(Vec<(usize, usize, u8)>),
+}
+
+#[derive(Debug, Clone)]
+struct EditRecord {
+    op: EditOp,
+    before: CellBackup,
+}
+
+impl EditOp {
+    fn apply<T: TerrainCells + ?Sized>(&self, terrain: &mut T) -> CellBackup {
+        let (width, depth) = (terrain.width(), terrain.depth());
+
+        match *self {
+            EditOp::Raise { brush, amount } => {
+                let cells = brush.cells(width, depth);
+                let before = cells.iter().map(|&(x, z, _)| (x, z, terrain.height(x, z))).collect();
+
+                let after: Vec<_> = cells
+                    .iter()
+                    .map(|&(x, z, strength)| {
+                        let delta = (amount as f32 * strength).round() as i32;
+                        (x, z, (terrain.height(x, z) as i32 + delta).clamp(0, 255) as u8)
+                    })
+                    .collect();
+
+                terrain.set_heights(&after);
+                CellBackup::Heights(before)
+            }
+            EditOp::Flatten { brush, height } => {
+                let cells = brush.cells(width, depth);
+                let before = cells.iter().map(|&(x, z, _)| (x, z, terrain.height(x, z))).collect();
+                let after: Vec<_> = cells.iter().map(|&(x, z, _)| (x, z, height)).collect();
+
+                terrain.set_heights(&after);
+                CellBackup::Heights(before)
+            }
+            EditOp::PaintTexture { brush, texture } => {
+                let cells = brush.cells(width, depth);
+                let before = cells.iter().map(|&(x, z, _)| (x, z, terrain.texture(x, z))).collect();
+
+                for &(x, z, _) in cells.iter() {
+                    terrain.set_texture(x, z, texture);
+                }
+
+                CellBackup::Textures(before)
+            }
+            EditOp::PaintRegion { brush, region } => {
+                let cells = brush.cells(width, depth);
+                let before = cells.iter().map(|&(x, z, _)| (x, z, terrain.region(x, z))).collect();
+
+                for &(x, z, _) in cells.iter() {
+                    terrain.set_region(x, z, region);
+                }
+
+                CellBackup::Regions(before)
+            }
+        }
+    }
+}
+
+impl CellBackup {
+    fn restore<T: TerrainCells + ?Sized>(&self, terrain: &mut T) {
+        match self {
+            CellBackup::Heights(cells) => terrain.set_heights(cells),
+            // Several cells can share a texture segment, restoring backwards always ends on the oldest
+            CellBackup::Textures(cells) => {
+                for &(x, z, texture) in cells.iter().rev() {
+                    terrain.set_texture(x, z, texture);
+                }
+            }
+            CellBackup::Regions(cells) => {
+                for &(x, z, region) in cells.iter() {
+                    terrain.set_region(x, z, region);
+                }
+            }
+        }
+    }
+}
+
+/// Applies edits and keeps undo and redo stacks for them
+#[derive(Debug, Clone)]
+pub struct TerrainEditor {
+    undo_stack: Vec<EditRecord>,
+    redo_stack: Vec<EditOp>,
+    max_undo: usize,
+}
+
+impl Default for TerrainEditor {
+    fn default() -> Self {
+        TerrainEditor::new(DEFAULT_MAX_UNDO)
+    }
+}
+
+impl TerrainEditor {
+    pub fn new(max_undo: usize) -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            max_undo,
+        }
+    }
+
+    /// Performs the edit. Anything that was undone can no longer be redone.
+    pub fn apply<T: TerrainCells + ?Sized>(&mut self, terrain: &mut T, op: EditOp) {
+        let before = op.apply(terrain);
+
+        self.redo_stack.clear();
+        self.undo_stack.push(EditRecord { op, before });
+
+        if self.undo_stack.len() > self.max_undo {
+            let excess = self.undo_stack.len() - self.max_undo;
+            self.undo_stack.drain(..excess);
+        }
+    }
+
+    /// Takes back the last edit and returns it
+    pub fn undo<T: TerrainCells + ?Sized>(&mut self, terrain: &mut T) -> Option<EditOp> {
+        let record = self.undo_stack.pop()?;
+        record.before.restore(terrain);
+        self.redo_stack.push(record.op);
+
+        Some(record.op)
+    }
+
+    /// Applies the last undone edit again and returns it
+    pub fn redo<T: TerrainCells + ?Sized>(&mut self, terrain: &mut T) -> Option<EditOp> {
+        let op = self.redo_stack.pop()?;
+        let before = op.apply(terrain);
+        self.undo_stack.push(EditRecord { op, before });
+
+        Some(op)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const SIZE: usize = 16;
+
+    /// Terrain::default() builds the whole world, a small grid is enough here
+    struct Grid {
+        heights: Vec<u8>,
+        textures: Vec<TerrainTextureSegment>,
+        regions: Vec<u8>,
+        rebuilds: usize,
+    }
+
+    impl Grid {
+        fn new() -> Self {
+            Self {
+                heights: vec![100; SIZE * SIZE],
+                textures: vec![TerrainTextureSegment { rotation: 0, tex_index: None }; SIZE * SIZE],
+                regions: vec![0; SIZE * SIZE],
+                rebuilds: 0,
+            }
+        }
+    }
+
+    impl TerrainCells for Grid {
+        fn width(&self) -> usize {
+            SIZE
+        }
+
+        fn depth(&self) -> usize {
+            SIZE
+        }
+
+        fn height(&self, x: usize, z: usize) -> u8 {
+            self.heights[z * SIZE + x]
+        }
+
+        fn set_heights(&mut self, cells: &[(usize, usize, u8)]) {
+            for &(x, z, h) in cells {
+                self.heights[z * SIZE + x] = h;
+            }
+
+            self.rebuilds += 1;
+        }
+
+        fn texture(&self, x: usize, z: usize) -> TerrainTextureSegment {
+            self.textures[z * SIZE + x]
+        }
+
+        fn set_texture(&mut self, x: usize, z: usize, texture: TerrainTextureSegment) {
+            self.textures[z * SIZE + x] = texture;
+        }
+
+        fn region(&self, x: usize, z: usize) -> u8 {
+            self.regions[z * SIZE + x]
+        }
+
+        fn set_region(&mut self, x: usize, z: usize, region: u8) {
+            self.regions[z * SIZE + x] = region;
+        }
+    }
+
+    #[test]
+    fn undo_redo_heights() {
+        crate::test_common::setup();
+
+        let mut grid = Grid::new();
+        let mut editor = TerrainEditor::default();
+        let original = grid.heights.clone();
+
+        editor.apply(&mut grid, EditOp::Raise { brush: Brush::new(0, 0, 3.0), amount: 200 });
+        assert_eq!(grid.height(0, 0), 255);
+        assert!(grid.height(2, 0) > 100 && grid.height(2, 0) < 255);
+        assert_eq!(grid.height(5, 5), 100);
+        assert_eq!(grid.rebuilds, 1);
+
+        editor.apply(&mut grid, EditOp::Flatten { brush: Brush::new(8, 8, 2.0), height: 10 });
+        assert_eq!(grid.height(8, 9), 10);
+
+        let raised = {
+            let mut g = grid.heights.clone();
+            for z in 6..=10 {
+                for x in 6..=10 {
+                    g[z * SIZE + x] = original[z * SIZE + x];
+                }
+            }
+            g
+        };
+
+        assert!(matches!(editor.undo(&mut grid), Some(EditOp::Flatten { .. })));
+        assert_eq!(grid.heights, raised);
+
+        editor.undo(&mut grid);
+        assert_eq!(grid.heights, original);
+        assert!(!editor.can_undo());
+
+        editor.redo(&mut grid);
+        assert_eq!(grid.heights, raised);
+        assert!(editor.can_redo());
+
+        // A new edit drops the redo history
+        editor.apply(&mut grid, EditOp::Raise { brush: Brush::new(15, 15, 1.0), amount: -50 });
+        assert_eq!(grid.height(15, 15), 50);
+        assert!(!editor.can_redo());
+    }
+
+    #[test]
+    fn undo_painting() {
+        crate::test_common::setup();
+
+        let mut grid = Grid::new();
+        let mut editor = TerrainEditor::new(1);
+        let texture = TerrainTextureSegment { rotation: 2, tex_index: Some(7) };
+
+        editor.apply(&mut grid, EditOp::PaintTexture { brush: Brush::new(4, 4, 1.5), texture });
+        assert_eq!(grid.texture(5, 4), texture);
+
+        editor.apply(&mut grid, EditOp::PaintRegion { brush: Brush::new(4, 4, 0.0), region: 3 });
+        assert_eq!(grid.region(4, 4), 3);
+        assert_eq!(grid.region(5, 4), 0);
+
+        editor.undo(&mut grid);
+        assert_eq!(grid.region(4, 4), 0);
+
+        // Only one edit is kept
+        assert!(editor.undo(&mut grid).is_none());
+        assert_eq!(grid.texture(5, 4), texture);
+    }
+}
@@ -0,0 +1,152 @@
+//! Texturing for the terrain pass. Each texture segment covers a square of cells and
+//! carries a texture index plus a packed rotation byte: the low nibble is how many quarter
+//! turns the texture is rotated, the high nibble how many times it tiles across the segment.
+
+use super::terrain::{Terrain, TerrainTextureSegment, TERRAIN_WIDTH};
+use crate::graphics::TextureHandle;
+
+/// Cells along each side of a texture segment
+pub const TERRAIN_TEXTURE_CELLS: usize = 8;
+
+/// How much of the texture distance textures take to fade into the flat lightmap color
+pub const TEXTURE_FADE_FRACTION: f32 = 0.2;
+
+/// Quarter turns, 0 to 3
+pub fn texture_rotation(texture: &TerrainTextureSegment) -> u8 {
+    texture.rotation & 0x03
+}
+
+/// Times the texture repeats across its segment, never less than once
+pub fn texture_tiling(texture: &TerrainTextureSegment) -> f32 {
+    (texture.rotation >> 4).max(1) as f32
+}
+
+/// Texture coordinates at the corner of cell `x`, `z`, which can be the far edge of its segment
+pub fn corner_uv(texture: &TerrainTextureSegment, x: usize, z: usize, segment: (usize, usize)) -> (f32, f32) {
+    let tiling = texture_tiling(texture);
+    let cells = TERRAIN_TEXTURE_CELLS as f32;
+
+    let u = (x - segment.0 * TERRAIN_TEXTURE_CELLS) as f32 / cells * tiling;
+    let v = (z - segment.1 * TERRAIN_TEXTURE_CELLS) as f32 / cells * tiling;
+
+    // Turned about the middle of the segment
+    match texture_rotation(texture) {
+        0 => (u, v),
+        1 => (v, tiling - u),
+        2 => (tiling - u, tiling - v),
+        _ => (tiling - v, u),
+    }
+}
+
+/// How much of the texture shows at `distance`, fading to nothing at the texture distance
+pub fn texture_alpha(distance: f32, texture_distance: f32) -> f32 {
+    let fade_start = texture_distance * (1.0 - TEXTURE_FADE_FRACTION);
+
+    if distance <= fade_start {
+        1.0
+    } else if distance >= texture_distance {
+        0.0
+    } else {
+        1.0 - (distance - fade_start) / (texture_distance - fade_start)
+    }
+}
+
+/// What the terrain pass needs to draw one block of cells
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TerrainCellDraw {
+    /// `None` draws the flat lightmap color only
+    pub texture: Option<TextureHandle>,
+    /// Corners in order (x, z), (x + size, z), (x + size, z + size), (x, z + size)
+    pub uvs: [(f32, f32); 4],
+    pub texture_alpha: f32,
+    /// Light of the block's corner cell, for when the texture has faded out
+    pub color: (u8, u8, u8),
+}
+
+impl Terrain {
+    /// Texturing for a block of `size` cells starting at `x`, `z`, as drawn at a LOD where
+    /// a block covers that many. Blocks never straddle texture segments so corners line up
+    /// between neighbouring blocks at any LOD. `resolve` looks a texture index up in the texture table.
+    pub fn cell_draw(
+        &self,
+        x: usize,
+        z: usize,
+        size: usize,
+        distance: f32,
+        resolve: impl Fn(usize) -> Option<TextureHandle>,
+    ) -> TerrainCellDraw {
+        let segment = (x / TERRAIN_TEXTURE_CELLS, z / TERRAIN_TEXTURE_CELLS);
+        let texture = self.cell_texture(x, z);
+        let seg = &self.segments[z * TERRAIN_WIDTH + x];
+        let color = (seg.r, seg.g, seg.b);
+
+        let alpha = texture_alpha(distance, self.texture_distance);
+        let handle = if alpha > 0.0 { texture.tex_index.and_then(&resolve) } else { None };
+
+        let Some(handle) = handle else {
+            return TerrainCellDraw {
+                texture: None,
+                uvs: [(0.0, 0.0); 4],
+                texture_alpha: 0.0,
+                color,
+            };
+        };
+
+        let corners = [(x, z), (x + size, z), (x + size, z + size), (x, z + size)];
+
+        TerrainCellDraw {
+            texture: Some(handle),
+            uvs: corners.map(|(cx, cz)| corner_uv(&texture, cx, cz, segment)),
+            texture_alpha: alpha,
+            color,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphics::BitmapId;
+
+    #[test]
+    fn terrain_texture_uv_test() {
+        crate::test_common::setup();
+
+        let texture = |rotation| TerrainTextureSegment { rotation, tex_index: Some(0) };
+
+        // Corner cells of the second segment along x
+        assert_eq!(corner_uv(&texture(1 << 4), 8, 0, (1, 0)), (0.0, 0.0));
+        assert_eq!(corner_uv(&texture(1 << 4), 12, 8, (1, 0)), (0.5, 1.0));
+
+        // Tiled twice, and a quarter turn
+        assert_eq!(corner_uv(&texture(2 << 4), 12, 8, (1, 0)), (1.0, 2.0));
+        assert_eq!(corner_uv(&texture((1 << 4) | 1), 12, 8, (1, 0)), (1.0, 0.5));
+        assert_eq!(corner_uv(&texture((1 << 4) | 2), 12, 8, (1, 0)), (0.5, 0.0));
+
+        // A zero tiling nibble still tiles once
+        assert_eq!(texture_tiling(&texture(0)), 1.0);
+    }
+
+    #[test]
+    fn terrain_texture_distance_test() {
+        crate::test_common::setup();
+
+        assert_eq!(texture_alpha(100.0, 1000.0), 1.0);
+        assert_eq!(texture_alpha(900.0, 1000.0), 0.5);
+        assert_eq!(texture_alpha(1000.0, 1000.0), 0.0);
+
+        let mut terrain = Terrain::default();
+        terrain.texture_distance = 1000.0;
+        terrain.set_cell_texture(3, 3, TerrainTextureSegment { rotation: 1 << 4, tex_index: Some(5) });
+
+        let resolve = |index: usize| (index == 5).then_some(TextureHandle::Bitmap(BitmapId(5)));
+
+        let near = terrain.cell_draw(0, 0, 2, 10.0, resolve);
+        assert_eq!(near.texture, Some(TextureHandle::Bitmap(BitmapId(5))));
+        assert_eq!(near.uvs[2], (0.25, 0.25));
+
+        // Past the texture distance, or with nothing to resolve to, it's flat color
+        assert_eq!(terrain.cell_draw(0, 0, 2, 2000.0, resolve).texture, None);
+        assert_eq!(terrain.cell_draw(8, 8, 2, 10.0, resolve).texture, None);
+    }
+}
@@ -0,0 +1,303 @@
+use matrix::Matrix;
+use vector::Vector;
+
+use super::{
+    node::{Node, NodePath},
+    player::{InventoryItem, Player, PlayerFlags},
+    prelude::*,
+    room::Room,
+};
+
+/// How far behind the player the thief lines up before it goes in
+pub const THIEF_STALK_DISTANCE: f32 = 15.0;
+/// Angle (as a dot product) behind the player that counts as out of sight
+pub const THIEF_BEHIND_DOT: f32 = -0.5;
+/// How close it has to get to a node on its escape route before moving on to the next
+pub const THIEF_NODE_RADIUS: f32 = 5.0;
+/// How long it keeps running before it gets greedy again
+pub const THIEF_FLEE_TIME: f32 = 10.0;
+
+/// The laser can't be taken, the ship always has it
+const LASER_WEAPON_FLAG: u32 = 1;
+
+/// What the thief AI is trying to do
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ThiefGoal {
+    /// Sneaking up on the player from behind
+    Stalk,
+    /// Running away with the loot
+    Flee,
+}
+
+/// Something taken from a player, dropped again when the thief dies
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StolenItem {
+    /// Index of the weapon in `Player::weapon_flags`
+    Weapon(u8),
+    Item(InventoryItem),
+    Headlight,
+}
+
+impl StolenItem {
+    /// Puts the item back, for when the player picks up what the thief dropped
+    pub fn give_back(&self, player: &mut Player) {
+        match self {
+            StolenItem::Weapon(index) => player.weapon_flags |= 1 << index,
+            StolenItem::Item(item) => player.add_item(item.id, item.count),
+            StolenItem::Headlight => {
+                player.flags.remove(PlayerFlags::HEADLIGHT_STOLEN);
+                player.flags.insert(PlayerFlags::HEADLIGHT);
+            }
+        }
+    }
+}
+
+/// Everything the thief could take from the player right now
+pub fn stealable_items(player: &Player) -> Vec<StolenItem> {
+    let mut items: Vec<StolenItem> = (0..32u8)
+        .filter(|&i| (1u32 << i) & player.weapon_flags & !LASER_WEAPON_FLAG != 0)
+        .map(StolenItem::Weapon)
+        .collect();
+
+    items.extend(player.inventory.iter().map(|item| StolenItem::Item(InventoryItem { id: item.id, count: 1 })));
+
+    if player.flags.contains(PlayerFlags::HEADLIGHT) {
+        items.push(StolenItem::Headlight);
+    }
+
+    items
+}
+
+/// Whether `position` is behind the player, out of their view
+pub fn is_behind(position: &Vector, player_position: &Vector, player_orientation: &Matrix) -> bool {
+    let mut dir = Vector::default();
+
+    if Vector::compute_normalized_direction(&mut dir, position, player_position) <= 0.0 {
+        return false;
+    }
+
+    player_orientation.forward * dir < THIEF_BEHIND_DOT
+}
+
+/// Finds a route from `start_node` to whichever node is farthest from the player
+pub fn plan_escape(
+    nodes: &SharedMutRef<Vec<Node>>,
+    room: &SharedMutRef<Room>,
+    start_node: usize,
+    player_position: &Vector,
+    rad: f32,
+) -> Option<Vec<Vector>> {
+    let goal = nodes
+        .borrow()
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| {
+            Vector::distance(&a.position, player_position).total_cmp(&Vector::distance(&b.position, player_position))
+        })
+        .map(|(i, _)| i)?;
+
+    let mut path = NodePath { nodes: Vec::new() };
+
+    if !path.find_path(nodes, room, Some(start_node), Some(goal), rad) {
+        return None;
+    }
+
+    let nodes = nodes.borrow();
+    Some(path.nodes.iter().map(|&i| nodes[i].position).collect())
+}
+
+/// A thief bot's AI state
+#[derive(Debug, Clone)]
+pub struct Thief {
+    pub goal: ThiefGoal,
+    stolen: Vec<StolenItem>,
+    escape_route: Vec<Vector>,
+    route_index: usize,
+    flee_time: f32,
+}
+
+impl Default for Thief {
+    fn default() -> Self {
+        Self {
+            goal: ThiefGoal::Stalk,
+            stolen: Vec::new(),
+            escape_route: Vec::new(),
+            route_index: 0,
+            flee_time: 0.0,
+        }
+    }
+}
+
+impl Thief {
+    pub fn stolen(&self) -> &[StolenItem] {
+        &self.stolen
+    }
+
+    /// Where it wants to go next, `None` if it has nowhere left to run
+    pub fn goal_position(&mut self, position: &Vector, player_position: &Vector, player_orientation: &Matrix) -> Option<Vector> {
+        match self.goal {
+            ThiefGoal::Stalk => {
+                // Swing around behind first, then close in once out of sight
+                if is_behind(position, player_position, player_orientation) {
+                    Some(*player_position)
+                } else {
+                    Some(*player_position - player_orientation.forward * THIEF_STALK_DISTANCE)
+                }
+            }
+            ThiefGoal::Flee => {
+                while let Some(node) = self.escape_route.get(self.route_index) {
+                    if Vector::distance(node, position) > THIEF_NODE_RADIUS {
+                        return Some(*node);
+                    }
+
+                    self.route_index += 1;
+                }
+
+                None
+            }
+        }
+    }
+
+    /// Called when the thief bumps into the player, `choice` picks what to take (from `ps_rand`)
+    pub fn on_collide(
+        &mut self,
+        player: &mut Player,
+        position: &Vector,
+        player_position: &Vector,
+        player_orientation: &Matrix,
+        choice: u32,
+    ) -> Option<StolenItem> {
+        // Only sneaky grabs count, and it doesn't go back for seconds while running
+        if self.goal != ThiefGoal::Stalk || !is_behind(position, player_position, player_orientation) {
+            return None;
+        }
+
+        let items = stealable_items(player);
+
+        if items.is_empty() {
+            return None;
+        }
+
+        let item = items[choice as usize % items.len()];
+
+        match item {
+            StolenItem::Weapon(index) => player.weapon_flags &= !(1 << index),
+            StolenItem::Item(item) => {
+                player.remove_item(item.id, item.count);
+            }
+            StolenItem::Headlight => {
+                player.flags.remove(PlayerFlags::HEADLIGHT);
+                player.flags.insert(PlayerFlags::HEADLIGHT_STOLEN);
+            }
+        }
+
+        self.stolen.push(item);
+        self.goal = ThiefGoal::Flee;
+        self.flee_time = 0.0;
+        self.escape_route.clear();
+        self.route_index = 0;
+
+        Some(item)
+    }
+
+    /// Hands over the route from `plan_escape`
+    pub fn set_escape_route(&mut self, route: Vec<Vector>) {
+        self.escape_route = route;
+        self.route_index = 0;
+    }
+
+    pub fn update(&mut self, frametime: f32) {
+        if self.goal == ThiefGoal::Flee {
+            self.flee_time += frametime;
+
+            if self.flee_time >= THIEF_FLEE_TIME {
+                self.goal = ThiefGoal::Stalk;
+                self.escape_route.clear();
+            }
+        }
+    }
+
+    /// Everything it was carrying, to be spewed out as powerups
+    pub fn on_death(&mut self) -> Vec<StolenItem> {
+        std::mem::take(&mut self.stolen)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn player() -> Player {
+        let mut player = Player {
+            weapon_flags: LASER_WEAPON_FLAG | (1 << 3),
+            ..Default::default()
+        };
+        player.flags.insert(PlayerFlags::HEADLIGHT);
+        player.add_item(12, 2);
+        player
+    }
+
+    #[test]
+    fn thief_stalk_test() {
+        crate::test_common::setup();
+
+        let player_position = Vector::new(0.0, 0.0, 0.0);
+        let in_front = Vector::new(0.0, 0.0, 30.0);
+        let behind = Vector::new(0.0, 0.0, -30.0);
+
+        assert!(!is_behind(&in_front, &player_position, &Matrix::IDENTITY));
+        assert!(is_behind(&behind, &player_position, &Matrix::IDENTITY));
+
+        // Circles round to the back before closing in
+        let mut thief = Thief::default();
+        let goal = thief.goal_position(&in_front, &player_position, &Matrix::IDENTITY).unwrap();
+        assert!(goal.z < 0.0);
+        assert_eq!(thief.goal_position(&behind, &player_position, &Matrix::IDENTITY), Some(player_position));
+    }
+
+    #[test]
+    fn thief_steal_test() {
+        crate::test_common::setup();
+
+        let mut player = player();
+        let player_position = Vector::new(0.0, 0.0, 0.0);
+        let behind = Vector::new(0.0, 0.0, -3.0);
+
+        // Never the laser
+        let items = stealable_items(&player);
+        assert_eq!(items.len(), 3);
+        assert!(!items.contains(&StolenItem::Weapon(0)));
+
+        // Caught in the act from the front
+        let mut thief = Thief::default();
+        assert!(thief.on_collide(&mut player, &Vector::new(0.0, 0.0, 3.0), &player_position, &Matrix::IDENTITY, 0).is_none());
+
+        let item = thief.on_collide(&mut player, &behind, &player_position, &Matrix::IDENTITY, 1).unwrap();
+        assert_eq!(item, StolenItem::Item(InventoryItem { id: 12, count: 1 }));
+        assert_eq!(player.inventory[0].count, 1);
+        assert_eq!(thief.goal, ThiefGoal::Flee);
+
+        // Runs along its route then gives up running after a while
+        thief.set_escape_route(vec![Vector::new(0.0, 0.0, -50.0), Vector::new(50.0, 0.0, -50.0)]);
+        assert_eq!(thief.goal_position(&behind, &player_position, &Matrix::IDENTITY), Some(Vector::new(0.0, 0.0, -50.0)));
+        assert_eq!(
+            thief.goal_position(&Vector::new(0.0, 0.0, -49.0), &player_position, &Matrix::IDENTITY),
+            Some(Vector::new(50.0, 0.0, -50.0))
+        );
+        thief.update(THIEF_FLEE_TIME);
+        assert_eq!(thief.goal, ThiefGoal::Stalk);
+
+        thief.on_collide(&mut player, &behind, &player_position, &Matrix::IDENTITY, 2);
+        assert!(player.flags.contains(PlayerFlags::HEADLIGHT_STOLEN));
+
+        // Everything comes back out when it dies
+        let dropped = thief.on_death();
+        assert_eq!(dropped.len(), 2);
+        for item in &dropped {
+            item.give_back(&mut player);
+        }
+        assert_eq!(player.inventory[0].count, 2);
+        assert!(player.flags.contains(PlayerFlags::HEADLIGHT));
+        assert!(thief.stolen().is_empty());
+    }
+}
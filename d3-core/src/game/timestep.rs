@@ -0,0 +1,92 @@
+/// Physics runs at this rate no matter how fast frames are drawn
+pub const DEFAULT_TICK_RATE: f32 = 60.0;
+
+/// Don't try to catch up more than this many ticks in one frame, or a long hitch snowballs
+pub const MAX_TICKS_PER_FRAME: usize = 8;
+
+/// Splits frame time into fixed physics ticks.  Whatever is left over is how far the renderer
+/// should blend between the last two ticks, see `Object::interpolated_transform`.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: f32,
+    accumulator: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_RATE)
+    }
+}
+
+impl FixedTimestep {
+    pub fn new(ticks_per_second: f32) -> Self {
+        Self {
+            step: 1.0 / ticks_per_second,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Length of one tick in seconds
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    /// Adds a frame's worth of time, returns how many ticks to simulate
+    pub fn advance(&mut self, frametime: f32) -> usize {
+        self.accumulator += frametime.max(0.0);
+
+        let mut ticks = (self.accumulator / self.step) as usize;
+
+        if ticks > MAX_TICKS_PER_FRAME {
+            trace!(target: "physics", "dropping {} physics ticks", ticks - MAX_TICKS_PER_FRAME);
+            ticks = MAX_TICKS_PER_FRAME;
+            self.accumulator = self.step * ticks as f32;
+        }
+
+        self.accumulator -= self.step * ticks as f32;
+        ticks
+    }
+
+    /// How far between the last tick and the next one we are, 0 to 1
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).clamp(0.0, 1.0)
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::object::Transform;
+    use crate::math::{matrix::Matrix, vector::Vector};
+
+    #[test]
+    fn ticks_and_blend() {
+        crate::test_common::setup();
+
+        let mut timestep = FixedTimestep::new(10.0);
+
+        assert_eq!(timestep.advance(0.25), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 0.001);
+
+        // A huge hitch only runs the capped number of ticks
+        assert_eq!(timestep.advance(10.0), MAX_TICKS_PER_FRAME);
+        assert_eq!(timestep.alpha(), 0.0);
+
+        let from = Transform {
+            position: Vector { x: 0.0, y: 0.0, z: 0.0 },
+            orientation: Matrix::IDENTITY,
+        };
+        let to = Transform {
+            position: Vector { x: 10.0, y: 0.0, z: 0.0 },
+            orientation: Matrix::IDENTITY,
+        };
+
+        let mid = from.interpolate(&to, timestep.alpha() + 0.5);
+        assert!((mid.position.x - 5.0).abs() < 0.001);
+        assert!((mid.orientation.forward.z - 1.0).abs() < 0.001);
+    }
+}
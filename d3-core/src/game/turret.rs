@@ -0,0 +1,367 @@
+use tinyrand::Rand;
+
+use crate::rand::ps_rand;
+
+use super::{
+    object_dynamic_behavior::DynamicWeaponBattery,
+    object_static_behavior::Autonomous,
+    physics::intersection::{FqFlags, HitType, IntersectionFinderResult, Query},
+    prelude::*,
+    room::Room,
+};
+use angle::Angle;
+use matrix::Matrix;
+use vector::Vector;
+
+/// How much to scale robot turret turn speed by, per difficulty level
+pub const DIFF_AI_TURRET_SPEED: [f32; 5] = [0.6, 0.7, 1.0, 1.0, 1.0];
+/// The least amount of fire spread a robot has, per difficulty level
+pub const DIFF_AI_MIN_FIRE_SPREAD: [f32; 5] = [0.30, 0.15, 0.0, 0.0, 0.0];
+
+/// Don't play the turret sound more often than this
+pub const MIN_TURRET_SOUND_TIME: f32 = 0.8;
+
+/// How closely the gun has to point at the target before it will fire (cosine of the angle)
+pub const TURRET_FIRE_DOT: f32 = 0.95;
+
+/// Which way a turret is swinging
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TurretDirection {
+    Still = 0, // WB_MOVE_STILL
+    Right = 1, // WB_MOVE_RIGHT
+    Left = 2,  // WB_MOVE_LEFT
+}
+
+impl From<u8> for TurretDirection {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => TurretDirection::Right,
+            2 => TurretDirection::Left,
+            _ => TurretDirection::Still,
+        }
+    }
+}
+
+/// The turret submodel data, from the polymodel
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TurretInfo {
+    /// Which submodel gets rotated
+    pub submodel: usize,
+    /// Rotations per second
+    pub rps: f32,
+    /// Gimbal limit as a normalized angle, the turret can swing this far either way of
+    /// its rest position.  0 means a dummy turret that never moves.
+    pub fov: f32,
+    /// How often the turret rethinks where to go
+    pub think_interval: f32,
+}
+
+impl TurretInfo {
+    pub fn is_dummy(&self) -> bool {
+        self.fov <= 0.0
+    }
+
+    /// Turrets that can see half way around can spin all the way around
+    pub fn is_constrained(&self) -> bool {
+        self.fov < 0.5
+    }
+
+    /// Keeps a normalized angle out of the range the turret can't reach
+    pub fn constrain(&self, angle: f32, direction: TurretDirection) -> f32 {
+        let min_invalid = self.fov;
+        let max_invalid = 1.0 - self.fov;
+
+        if !self.is_constrained() || angle <= min_invalid || angle >= max_invalid {
+            return angle;
+        }
+
+        match direction {
+            TurretDirection::Right => max_invalid,
+            TurretDirection::Left => min_invalid,
+            TurretDirection::Still => angle,
+        }
+    }
+
+    /// Where the turret ends up after swinging for a frame
+    pub fn step(&self, angle: f32, direction: TurretDirection, rps: f32, frametime: f32) -> f32 {
+        let delta = frametime * rps;
+
+        let angle = match direction {
+            TurretDirection::Still => return angle,
+            TurretDirection::Right => angle - delta,
+            TurretDirection::Left => angle + delta,
+        };
+
+        self.constrain(angle.rem_euclid(1.0), direction)
+    }
+}
+
+/// Turret tuning for a robot, from its table entry and the difficulty level
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TurretTuning {
+    /// Scales the submodel turn speed
+    pub speed_scale: f32,
+    /// How far off (as a fraction of the gun normal) shots may wander
+    pub fire_spread: f32,
+    pub fire_dot: f32,
+}
+
+impl Default for TurretTuning {
+    fn default() -> Self {
+        Self {
+            speed_scale: 1.0,
+            fire_spread: 0.0,
+            fire_dot: TURRET_FIRE_DOT,
+        }
+    }
+}
+
+impl TurretTuning {
+    /// Rebels (friendly robots) always get full speed turrets
+    pub fn from_table(ai: &Autonomous, difficulty: usize, is_rebel: bool) -> Self {
+        let difficulty = difficulty.min(DIFF_AI_TURRET_SPEED.len() - 1);
+
+        Self {
+            speed_scale: if is_rebel { 1.0 } else { DIFF_AI_TURRET_SPEED[difficulty] },
+            fire_spread: ai.fire_spread.max(DIFF_AI_MIN_FIRE_SPREAD[difficulty]),
+            fire_dot: TURRET_FIRE_DOT,
+        }
+    }
+
+    /// Jitters a firing direction by the fire spread
+    pub fn spread_direction(&self, normal: &Vector, orientation: &Matrix, rng: &mut impl Rand) -> Vector {
+        if self.fire_spread <= 0.0 {
+            return *normal;
+        }
+
+        let mut jitter = |axis: &Vector| {
+            let r = (ps_rand(rng) as f32 / 32767.0) * 2.0 - 1.0;
+            *axis * (r * self.fire_spread)
+        };
+
+        let mut dir = *normal + jitter(&orientation.right) + jitter(&orientation.up);
+        Vector::normalize(&mut dir);
+        dir
+    }
+}
+
+/// The result of aiming one turret for a frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TurretAim {
+    /// How closely the aiming gun points at the target after the move
+    pub dot: f32,
+    /// The turret changed the way it is swinging, play the turret sound
+    pub direction_changed: bool,
+}
+
+/// Swings a turret toward the target.  `calc_gun` returns the aiming gun point's position and
+/// normal with the turret set to the given normalized angle.
+pub fn aim_turret(
+    battery: &mut DynamicWeaponBattery,
+    index: usize,
+    info: &TurretInfo,
+    tuning: &TurretTuning,
+    target: &Vector,
+    frametime: f32,
+    mut calc_gun: impl FnMut(f32) -> (Vector, Vector),
+) -> TurretAim {
+    let angle = battery.norm_turret_angle[index];
+
+    let mut dot_for = |angle: f32| {
+        let (point, normal) = calc_gun(angle);
+        let mut dir = Vector::ZERO;
+        Vector::compute_normalized_direction(&mut dir, target, &point);
+        normal.dot(dir)
+    };
+
+    if info.is_dummy() {
+        return TurretAim {
+            dot: dot_for(angle),
+            direction_changed: false,
+        };
+    }
+
+    let rps = info.rps * tuning.speed_scale;
+
+    let candidates = [
+        TurretDirection::Still,
+        TurretDirection::Right,
+        TurretDirection::Left,
+    ]
+    .map(|d| {
+        let a = info.step(angle, d, rps, frametime);
+        (d, a, dot_for(a))
+    });
+
+    let (_, best_angle, best_dot) = candidates
+        .iter()
+        .copied()
+        .fold(candidates[0], |best, c| if c.2 > best.2 { c } else { best });
+
+    battery.norm_turret_angle[index] = best_angle;
+    battery.turret_next_think_time[index] += info.think_interval;
+
+    // Keep swinging toward whichever side looked better, even if we stopped this frame
+    let last_direction = battery.turret_direction[index];
+    let direction = if candidates[1].2 > candidates[2].2 {
+        TurretDirection::Right
+    } else {
+        TurretDirection::Left
+    };
+
+    battery.turret_direction[index] = direction as u8;
+
+    TurretAim {
+        dot: best_dot,
+        direction_changed: last_direction != direction as u8,
+    }
+}
+
+/// Should a turret that ended up at `aim` open fire.  `line_of_sight` is only asked when the gun
+/// is lined up, since it is the expensive part.
+pub fn turret_should_fire(
+    aim: &TurretAim,
+    tuning: &TurretTuning,
+    gun_point: &Vector,
+    target: &Vector,
+    line_of_sight: impl FnOnce(&Vector, &Vector) -> bool,
+) -> bool {
+    // Sloppier robots fire when they're roughly lined up
+    let needed = tuning.fire_dot - tuning.fire_spread;
+
+    aim.dot >= needed && line_of_sight(gun_point, target)
+}
+
+/// Checks the gun has a clear shot at the target, `cast` runs the FVI query.  Hitting the
+/// target itself counts, hitting any other object doesn't.
+pub fn turret_line_of_sight(
+    start_room: SharedMutRef<Room>,
+    this_obj: SharedMutRef<Object>,
+    target_obj: Option<&SharedMutRef<Object>>,
+    gun_point: &Vector,
+    target: &Vector,
+    cast: impl FnOnce(&Query) -> IntersectionFinderResult,
+) -> bool {
+    let query = Query {
+        p0: *gun_point,
+        p1: *target,
+        start_room,
+        rad: 0.0,
+        this_obj: Some(this_obj),
+        ignore_obj_list: (),
+        flags: FqFlags::CHECK_OBJS | FqFlags::IGNORE_POWERUPS,
+        bbox_orientation: Matrix::IDENTITY,
+        bbox_rotvel: Vector::ZERO,
+        bbox_rotthrust: Vector::ZERO,
+        bbox_velocity: Vector::ZERO,
+        bbox_turnroll: Angle(0),
+        bbox_thrust: Vector::ZERO,
+        frametime: 0.0,
+    };
+
+    let result = cast(&query);
+
+    if result.hit_count == 0 {
+        return true;
+    }
+
+    match result.hit_type[0] {
+        HitType::None => true,
+        HitType::Object => match (target_obj, &result.hit_object[0]) {
+            (Some(target_obj), Some(hit)) => Rc::ptr_eq(target_obj, hit),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn turret_respects_gimbal_limits() {
+        crate::test_common::setup();
+
+        let info = TurretInfo {
+            submodel: 0,
+            rps: 0.5,
+            fov: 0.25,
+            think_interval: 0.0,
+        };
+
+        // Swinging left runs into the limit
+        let angle = info.step(0.2, TurretDirection::Left, 0.5, 0.5);
+        assert_eq!(angle, 0.25);
+
+        let angle = info.step(0.8, TurretDirection::Right, 0.5, 0.5);
+        assert_eq!(angle, 0.75);
+
+        // Swinging right wraps around zero
+        let angle = info.step(0.1, TurretDirection::Right, 0.5, 0.5);
+        assert!((angle - 0.85).abs() < 0.0001);
+
+        let spinner = TurretInfo { fov: 0.5, ..info };
+        let angle = spinner.step(0.2, TurretDirection::Left, 0.5, 0.5);
+        assert!((angle - 0.45).abs() < 0.0001);
+    }
+
+    #[test]
+    fn fires_only_when_lined_up_and_clear() {
+        crate::test_common::setup();
+
+        let tuning = TurretTuning::default();
+        let origin = Vector { x: 0.0, y: 0.0, z: 0.0 };
+        let target = Vector { x: 0.0, y: 0.0, z: 10.0 };
+
+        let lined_up = TurretAim {
+            dot: 1.0,
+            direction_changed: false,
+        };
+        let off = TurretAim {
+            dot: 0.5,
+            direction_changed: false,
+        };
+
+        assert!(turret_should_fire(&lined_up, &tuning, &origin, &target, |_, _| true));
+        assert!(!turret_should_fire(&lined_up, &tuning, &origin, &target, |_, _| false));
+        assert!(!turret_should_fire(&off, &tuning, &origin, &target, |_, _| true));
+    }
+
+    #[test]
+    fn line_of_sight_only_through_to_the_target() {
+        crate::test_common::setup();
+
+        let room = new_shared_mut_ref(Room::default());
+        let turret = new_shared_mut_ref(Object::new(ObjectTypeDef::new("turret", ObjectClass::Robot)));
+        let target_obj = new_shared_mut_ref(Object::new(ObjectTypeDef::new("target", ObjectClass::Player)));
+        let other = new_shared_mut_ref(Object::new(ObjectTypeDef::new("other", ObjectClass::Robot)));
+
+        let origin = Vector { x: 0.0, y: 0.0, z: 0.0 };
+        let target = Vector { x: 0.0, y: 0.0, z: 10.0 };
+
+        let hit = |hit_type: HitType, object: Option<&SharedMutRef<Object>>| {
+            let mut result = IntersectionFinderResult::default();
+            result.hit_count = 1;
+            result.hit_type[0] = hit_type;
+            result.hit_object[0] = object.cloned();
+            move |_: &Query| result.clone()
+        };
+
+        let sight = |target_obj: Option<&SharedMutRef<Object>>, cast: &dyn Fn(&Query) -> IntersectionFinderResult| {
+            turret_line_of_sight(room.clone(), turret.clone(), target_obj, &origin, &target, |query| {
+                // The ray goes from the gun to the target, skipping the turret itself
+                assert_eq!((query.p0, query.p1), (origin, target));
+                assert!(Rc::ptr_eq(query.this_obj.as_ref().unwrap(), &turret));
+                cast(query)
+            })
+        };
+
+        assert!(sight(Some(&target_obj), &|_| IntersectionFinderResult::default()));
+        assert!(sight(Some(&target_obj), &hit(HitType::Object, Some(&target_obj))));
+        assert!(!sight(Some(&target_obj), &hit(HitType::Object, Some(&other))));
+        assert!(!sight(None, &hit(HitType::Object, Some(&other))));
+        assert!(!sight(Some(&target_obj), &hit(HitType::Wall, None)));
+    }
+}
@@ -0,0 +1,287 @@
+use super::{prelude::*, terrain::cell_index_at};
+use crate::math::bounds::Aabb;
+use vector::Vector;
+
+/// How far past a portal plane the viewer has to get before it counts as being in the next room
+pub const PORTAL_HYSTERESIS: f32 = 0.5;
+
+/// Seconds to blend things like fog and ambient sound across a room change
+pub const VIEWER_TRANSITION_TIME: f32 = 0.5;
+
+/// Moving further than this in a frame is a teleport, the room is looked up again from scratch
+pub const VIEWER_TELEPORT_DISTANCE: f32 = 100.0;
+
+/// Where the viewer is, a room by id or a terrain cell by index
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ViewerPlace {
+    Room(usize),
+    Terrain(usize),
+}
+
+impl ViewerPlace {
+    pub fn is_outside(&self) -> bool {
+        matches!(self, ViewerPlace::Terrain(_))
+    }
+
+    /// Two terrain cells are the same place as far as transitions go
+    fn same_area(&self, other: &ViewerPlace) -> bool {
+        match (self, other) {
+            (ViewerPlace::Terrain(_), ViewerPlace::Terrain(_)) => true,
+            _ => self == other,
+        }
+    }
+}
+
+/// The viewer's place as seen by the renderer, audio and weather
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewerLocation {
+    pub place: ViewerPlace,
+    /// Where it was before the last room change, until the transition is over
+    pub previous: Option<ViewerPlace>,
+    /// How far through the transition from `previous`, 0 to 1
+    pub blend: f32,
+}
+
+impl ViewerLocation {
+    pub fn room(&self) -> Option<usize> {
+        match self.place {
+            ViewerPlace::Room(room) => Some(room),
+            ViewerPlace::Terrain(_) => None,
+        }
+    }
+
+    pub fn is_outside(&self) -> bool {
+        self.place.is_outside()
+    }
+}
+
+/// A portal as the tracker needs it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewerPortal {
+    pub point: Vector,
+    /// Faces into the room the portal belongs to
+    pub normal: Vector,
+    /// Bounds of the portal face
+    pub bounds: Aabb,
+    /// The room on the other side, `None` for the terrain
+    pub connected_room: Option<usize>,
+}
+
+impl ViewerPortal {
+    /// How far into the portal's room a point is, negative once it's through
+    fn depth(&self, position: &Vector) -> f32 {
+        (*position - self.point) * self.normal
+    }
+
+    /// Whether a point is through the portal, well enough past its plane and lined up with its face
+    fn passed(&self, position: &Vector) -> bool {
+        let depth = self.depth(position);
+        let on_plane = *position - self.normal * depth;
+
+        depth < -PORTAL_HYSTERESIS && self.bounds.expanded(PORTAL_HYSTERESIS).contains_point(&on_plane)
+    }
+}
+
+/// The bits of the level the tracker looks at
+pub trait ViewerWorld {
+    fn portals(&self, room: usize) -> Vec<ViewerPortal>;
+
+    /// The room a point is in from a full search, `None` when it's outside
+    fn find_room(&self, position: &Vector) -> Option<usize>;
+}
+
+/// Follows the viewer from room to room through portals, like an object relink,
+/// but holding on to the room it's in until it's clearly through a portal
+#[derive(Debug, Clone, Default)]
+pub struct ViewerTracker {
+    location: Option<ViewerLocation>,
+    last_position: Vector,
+}
+
+impl ViewerTracker {
+    pub fn location(&self) -> Option<&ViewerLocation> {
+        self.location.as_ref()
+    }
+
+    /// Forgets where the viewer was, for a new level
+    pub fn reset(&mut self) {
+        self.location = None;
+    }
+
+    pub fn update(&mut self, position: &Vector, frametime: f32, world: &impl ViewerWorld) -> Option<&ViewerLocation> {
+        let jumped = Vector::distance(position, &self.last_position) > VIEWER_TELEPORT_DISTANCE;
+        self.last_position = *position;
+
+        let place = match self.location {
+            Some(location) if !jumped => Self::follow(location.place, position, world),
+            _ => {
+                let Some(place) = Self::search(position, world) else {
+                    self.location = None;
+                    return None;
+                };
+
+                // Nothing to blend from after a teleport
+                self.location = Some(ViewerLocation {
+                    place,
+                    previous: None,
+                    blend: 1.0,
+                });
+
+                return self.location.as_ref();
+            }
+        };
+
+        let location = self.location.as_mut().unwrap();
+
+        if !place.same_area(&location.place) {
+            location.previous = Some(location.place);
+            location.blend = 0.0;
+        } else if location.previous.is_some() {
+            location.blend = (location.blend + frametime / VIEWER_TRANSITION_TIME).min(1.0);
+
+            if location.blend >= 1.0 {
+                location.previous = None;
+            }
+        }
+
+        location.place = place;
+        self.location.as_ref()
+    }
+
+    fn search(position: &Vector, world: &impl ViewerWorld) -> Option<ViewerPlace> {
+        match world.find_room(position) {
+            Some(room) => Some(ViewerPlace::Room(room)),
+            None => cell_index_at(position).map(ViewerPlace::Terrain),
+        }
+    }
+
+    /// Steps through whichever portals the viewer is well past
+    fn follow(place: ViewerPlace, position: &Vector, world: &impl ViewerWorld) -> ViewerPlace {
+        let mut place = place;
+
+        // A handful of steps covers flying through a couple of portals in one frame
+        for _ in 0..4 {
+            let next = match place {
+                ViewerPlace::Room(room) => world
+                    .portals(room)
+                    .iter()
+                    .find(|p| p.passed(position))
+                    .map(|p| match p.connected_room {
+                        Some(room) => Some(ViewerPlace::Room(room)),
+                        None => cell_index_at(position).map(ViewerPlace::Terrain),
+                    }),
+                ViewerPlace::Terrain(_) => match world.find_room(position) {
+                    // Only in once it's past the band on every way out to the terrain
+                    Some(room)
+                        if world
+                            .portals(room)
+                            .iter()
+                            .filter(|p| p.connected_room.is_none())
+                            .all(|p| p.depth(position) > PORTAL_HYSTERESIS) =>
+                    {
+                        Some(Some(ViewerPlace::Room(room)))
+                    }
+                    _ => cell_index_at(position).map(|cell| Some(ViewerPlace::Terrain(cell))).filter(|c| *c != Some(place)),
+                },
+            };
+
+            match next {
+                Some(Some(next)) => place = next,
+                // Through a portal to somewhere that doesn't exist, stay put
+                _ => break,
+            }
+        }
+
+        place
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Room 1 runs along z from 0 to 10 into room 2, which opens out onto the terrain at z = 20
+    struct Corridor;
+
+    impl ViewerWorld for Corridor {
+        fn portals(&self, room: usize) -> Vec<ViewerPortal> {
+            let portal = |z: f32, normal: f32, connected_room| ViewerPortal {
+                point: Vector::new(100.0, 100.0, z),
+                normal: Vector::new(0.0, 0.0, normal),
+                bounds: Aabb::new(Vector::new(90.0, 90.0, z), Vector::new(110.0, 110.0, z)),
+                connected_room,
+            };
+
+            match room {
+                1 => vec![portal(10.0, -1.0, Some(2))],
+                2 => vec![portal(10.0, 1.0, Some(1)), portal(20.0, -1.0, None)],
+                _ => vec![],
+            }
+        }
+
+        fn find_room(&self, position: &Vector) -> Option<usize> {
+            match position.z {
+                z if z < 10.0 => Some(1),
+                z if z < 20.0 => Some(2),
+                _ => None,
+            }
+        }
+    }
+
+    fn at(z: f32) -> Vector {
+        Vector::new(100.0, 100.0, z)
+    }
+
+    #[test]
+    fn viewer_portal_hysteresis_test() {
+        crate::test_common::setup();
+
+        let mut tracker = ViewerTracker::default();
+        assert_eq!(tracker.update(&at(5.0), 0.1, &Corridor).unwrap().place, ViewerPlace::Room(1));
+
+        // Hovering just over the portal doesn't flip back and forth
+        for z in [10.2, 9.8, 10.3, 9.9] {
+            assert_eq!(tracker.update(&at(z), 0.1, &Corridor).unwrap().place, ViewerPlace::Room(1));
+        }
+
+        let location = *tracker.update(&at(11.0), 0.1, &Corridor).unwrap();
+        assert_eq!(location.place, ViewerPlace::Room(2));
+        assert_eq!(location.previous, Some(ViewerPlace::Room(1)));
+        assert_eq!(location.blend, 0.0);
+
+        // Back a little isn't enough to go back
+        assert_eq!(tracker.update(&at(9.8), 0.1, &Corridor).unwrap().place, ViewerPlace::Room(2));
+
+        // The transition runs out
+        for _ in 0..5 {
+            tracker.update(&at(12.0), 0.1, &Corridor);
+        }
+        assert_eq!(tracker.location().unwrap().previous, None);
+    }
+
+    #[test]
+    fn viewer_outside_test() {
+        crate::test_common::setup();
+
+        let mut tracker = ViewerTracker::default();
+        tracker.update(&at(15.0), 0.1, &Corridor);
+
+        // Out onto the terrain and across a couple of cells
+        let location = *tracker.update(&at(21.0), 0.1, &Corridor).unwrap();
+        assert!(location.is_outside());
+        assert_eq!(location.place, ViewerPlace::Terrain(cell_index_at(&at(21.0)).unwrap()));
+
+        let location = *tracker.update(&at(40.0), 0.1, &Corridor).unwrap();
+        assert_eq!(location.place, ViewerPlace::Terrain(cell_index_at(&at(40.0)).unwrap()));
+        assert!(location.blend > 0.0);
+
+        // Back in once it's clear of the doorway
+        assert!(tracker.update(&at(19.8), 0.1, &Corridor).unwrap().is_outside());
+        assert_eq!(tracker.update(&at(19.0), 0.1, &Corridor).unwrap().place, ViewerPlace::Room(2));
+
+        // Teleports look the room up again with nothing to blend from
+        let location = *tracker.update(&at(-200.0), 0.1, &Corridor).unwrap();
+        assert_eq!(location.place, ViewerPlace::Room(1));
+        assert_eq!(location.previous, None);
+    }
+}
@@ -4,7 +4,7 @@ use crate::{graphics::{bitmap::videoclip::VideoClip, texture::TextureSizeType},
 
 use super::{ParticleState, VisualEffect, VisualEffectFlags};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FireballEffectType {
     Explosion,
     Smoke,
@@ -3,7 +3,7 @@ pub mod fireball;
 
 use bitflags::bitflags;
 
-use crate::{common::SharedMutRef, create_rng, graphics::bitmap::{videoclip::VideoClip, Bitmap16}, math::vector::Vector, rand::ps_rand};
+use crate::{common::{SharedMutRef, WeakHandle}, create_rng, graphics::bitmap::{videoclip::VideoClip, Bitmap16}, math::vector::Vector, rand::ps_rand};
 
 use super::{
     object::Object, object_dynamic_behavior::MovementType, object_static_behavior::PhysicsFlags,
@@ -30,8 +30,8 @@ bitflags! {
 
 #[derive(Debug, Clone)]
 pub struct VisualEffectAttachInfo {
-    pub object: Option<SharedMutRef<Object>>,
-    pub dest_object: Option<SharedMutRef<Object>>,
+    pub object: WeakHandle<Object>,
+    pub dest_object: WeakHandle<Object>,
 
     pub model: Option<()>,
     pub start_vert: usize,
@@ -0,0 +1,184 @@
+use crate::config::AudioSettings;
+
+use super::{audio::SoundId, sound_table::SoundPriority};
+
+/// A sound the mixer is currently playing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Voice {
+    pub uid: u32,
+    pub sound: SoundId,
+    pub priority: SoundPriority,
+    pub volume: f32,
+    pub start_time: f32,
+    pub looped: bool,
+}
+
+/// The mixer should start `uid`, stopping `evicted` first if it is set
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VoiceAllocation {
+    pub uid: u32,
+    pub evicted: Option<u32>,
+}
+
+/// Hands out the mixer's voices.  When they're all in use the least important one is dropped
+/// to make room, so a new sound is only refused if everything playing matters more.
+#[derive(Debug, Clone)]
+pub struct VoiceManager {
+    max_voices: usize,
+    voices: Vec<Voice>,
+    next_uid: u32,
+}
+
+impl Default for VoiceManager {
+    fn default() -> Self {
+        Self::from_settings(&AudioSettings::default())
+    }
+}
+
+impl VoiceManager {
+    pub fn new(max_voices: usize) -> Self {
+        Self {
+            max_voices,
+            voices: Vec::with_capacity(max_voices),
+            next_uid: 1,
+        }
+    }
+
+    /// As many voices as the audio settings allow
+    pub fn from_settings(settings: &AudioSettings) -> Self {
+        Self::new(settings.max_voices)
+    }
+
+    pub fn max_voices(&self) -> usize {
+        self.max_voices
+    }
+
+    pub fn voices(&self) -> &[Voice] {
+        &self.voices
+    }
+
+    /// Changes the channel budget, returns the voices that no longer fit
+    pub fn set_max_voices(&mut self, max_voices: usize) -> Vec<u32> {
+        self.max_voices = max_voices;
+
+        let mut dropped = Vec::new();
+
+        while self.voices.len() > self.max_voices {
+            let Some(index) = self.lowest_voice() else {
+                break;
+            };
+
+            dropped.push(self.voices.swap_remove(index).uid);
+        }
+
+        dropped
+    }
+
+    /// The voice to give up first: lowest priority, then quietest, then oldest.  Looping
+    /// sounds lose ties with one-shots since they'll just get restarted.
+    fn lowest_voice(&self) -> Option<usize> {
+        self.voices
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                a.priority
+                    .cmp(&b.priority)
+                    .then(a.volume.total_cmp(&b.volume))
+                    .then(b.looped.cmp(&a.looped))
+                    .then(a.start_time.total_cmp(&b.start_time))
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// Finds a voice for a sound, returns None when it isn't worth bumping anything for
+    pub fn allocate(
+        &mut self,
+        sound: SoundId,
+        priority: SoundPriority,
+        volume: f32,
+        looped: bool,
+        gametime: f32,
+    ) -> Option<VoiceAllocation> {
+        if self.max_voices == 0 {
+            return None;
+        }
+
+        let mut evicted = None;
+
+        if self.voices.len() >= self.max_voices {
+            let index = self.lowest_voice()?;
+            let lowest = &self.voices[index];
+
+            let bumps = priority == SoundPriority::Critical
+                || priority > lowest.priority
+                || (priority == lowest.priority && volume > lowest.volume);
+
+            if !bumps {
+                trace!(target: "audio", "no voice for sound {}, all voices are busy", sound);
+                return None;
+            }
+
+            evicted = Some(self.voices.swap_remove(index).uid);
+        }
+
+        let uid = self.next_uid;
+        self.next_uid = self.next_uid.wrapping_add(1).max(1);
+
+        self.voices.push(Voice {
+            uid,
+            sound,
+            priority,
+            volume,
+            start_time: gametime,
+            looped,
+        });
+
+        Some(VoiceAllocation { uid, evicted })
+    }
+
+    /// Call when the mixer stops a sound or it finishes on its own
+    pub fn release(&mut self, uid: u32) {
+        self.voices.retain(|v| v.uid != uid);
+    }
+
+    /// Volume changes from rolloff affect which voice gets dropped next
+    pub fn set_volume(&mut self, uid: u32, volume: f32) {
+        if let Some(voice) = self.voices.iter_mut().find(|v| v.uid == uid) {
+            voice.volume = volume;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.voices.clear();
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_lowest_priority_when_full() {
+        crate::test_common::setup();
+
+        let settings = AudioSettings { max_voices: 2, ..Default::default() };
+        let mut voices = VoiceManager::from_settings(&settings);
+        assert_eq!(voices.max_voices(), 2);
+
+        let low = voices.allocate(1, SoundPriority::Low, 1.0, false, 0.0).unwrap();
+        let normal = voices.allocate(2, SoundPriority::Normal, 1.0, false, 0.0).unwrap();
+        assert_eq!(low.evicted, None);
+
+        // Something less important than everything playing doesn't get in
+        assert!(voices.allocate(3, SoundPriority::Lowest, 1.0, false, 1.0).is_none());
+
+        let high = voices.allocate(4, SoundPriority::High, 1.0, false, 1.0).unwrap();
+        assert_eq!(high.evicted, Some(low.uid));
+
+        let critical = voices.allocate(5, SoundPriority::Critical, 0.1, false, 2.0).unwrap();
+        assert_eq!(critical.evicted, Some(normal.uid));
+
+        voices.release(high.uid);
+        assert_eq!(voices.voices().len(), 1);
+    }
+}
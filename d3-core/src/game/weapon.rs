@@ -37,4 +37,73 @@ bitflags! {
         const QUAD = 16;
         const UPGRADED = 32;
     }
-}
\ No newline at end of file
+}
+
+bitflags! {
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+    pub struct WeaponFlags: u32 {
+        const HUD_ANIMATED = 1 << 0; // WF_HUD_ANIMATED
+        /// Whether or not the firing image is a bitmap or model.
+        const IMAGE_BITMAP = 1 << 1; // WF_IMAGE_BITMAP
+        /// Weapon drops smoke as it moves.
+        const SMOKE = 1 << 2; // WF_SMOKE
+        /// This a matter weapon, as opposed to an energy weapon.
+        const MATTER_WEAPON = 1 << 3; // WF_MATTER_WEAPON
+        /// This weapons fires as an electrical storm.
+        const ELECTRICAL = 1 << 4; // WF_ELECTRICAL
+        /// This weapon fire image is a vclip.
+        const IMAGE_VCLIP = 1 << 5; // WF_IMAGE_VCLIP
+        /// This weapon is a spray, like a flamethrower.
+        const SPRAY = 1 << 6; // WF_SPRAY
+        /// This weapon has a streamer effect attached.
+        const STREAMER = 1 << 7; // WF_STREAMER
+        /// This weapon is invisible.
+        const INVISIBLE = 1 << 8; // WF_INVISIBLE
+        /// This weapon is drawn ring style.
+        const RING = 1 << 9; // WF_RING
+        /// Saturate this bitmap weapon.
+        const SATURATE = 1 << 10; // WF_SATURATE
+        /// Creates a blast ring upon explosion.
+        const BLAST_RING = 1 << 11; // WF_BLAST_RING
+        /// Blast bitmap takes on the walls plane.
+        const PLANAR_BLAST = 1 << 12; // WF_PLANAR_BLAST
+        /// This weapon doesn't always face you.
+        const PLANAR = 1 << 13; // WF_PLANAR
+        /// This weapon can be used for missile camera.
+        const ENABLE_CAMERA = 1 << 14; // WF_ENABLE_CAMERA
+        /// This weapon spawns others on impact.
+        const SPAWNS_IMPACT = 1 << 15; // WF_SPAWNS_IMPACT
+        /// This weapon spawns others when it times out.
+        const SPAWNS_TIMEOUT = 1 << 16; // WF_SPAWNS_TIMEOUT
+        /// This weapon expands when exploding.
+        const EXPAND = 1 << 17; // WF_EXPAND
+        /// This weapon produces a muzzle flash when fired.
+        const MUZZLE = 1 << 18; // WF_MUZZLE
+        /// This weapon makes a microwave effect on the victim.
+        const MICROWAVE = 1 << 19; // WF_MICROWAVE
+        /// This weapon does a napalm effect to objects it touches.
+        const NAPALM = 1 << 20; // WF_NAPALM
+        /// The smoke trail gets smaller as it ages.
+        const REVERSE_SMOKE = 1 << 21; // WF_REVERSE_SMOKE
+        /// This weapon has a gravity field.
+        const GRAVITY_FIELD = 1 << 22; // WF_GRAVITY_FIELD
+        /// This weapon is a countermeasure.
+        const COUNTERMEASURE = 1 << 23; // WF_COUNTERMEASURE
+        /// This weapon spawns a robot upon death.
+        const SPAWNS_ROBOT = 1 << 24; // WF_SPAWNS_ROBOT
+        /// This weapon slows a ship/object down.
+        const FREEZE = 1 << 25; // WF_FREEZE
+        /// This weapon times out like a wall hit.
+        const TIMEOUT_WALL = 1 << 26; // WF_TIMEOUT_WALL
+        /// This weapon has a planar smoke trail instead of a blob.
+        const PLANAR_SMOKE = 1 << 27; // WF_PLANAR_SMOKE
+        /// This weapon does not give a homing lock sound.
+        const SILENT_HOMING = 1 << 28; // WF_SILENT_HOMING
+        /// This weapon homes when it splits.
+        const HOMING_SPLIT = 1 << 29; // WF_HOMING_SPLIT
+        /// This weapon does not rotate as a bitmap.
+        const NO_ROTATE = 1 << 30; // WF_NO_ROTATE
+        /// This weapon uses a custom size.
+        const CUSTOM_SIZE = 1 << 31; // WF_CUSTOM_SIZE
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{prelude::*, weapon::WeaponFlags};
+use crate::graphics::rendering::{AlphaType, LightStateType, Renderer};
+use matrix::Matrix;
+use vector::Vector;
+
+/// Energy bolts add onto whatever is behind them so they glow
+pub const ENERGY_ALPHA_TYPE: AlphaType = AlphaType::SATURATE_TEXTURE;
+pub const MATTER_ALPHA_TYPE: AlphaType = AlphaType::ALWAYS;
+
+/// Distance a matter projectile covers between smoke puffs
+pub const SMOKE_SPACING: f32 = 4.0;
+/// How long a puff hangs around
+pub const SMOKE_LIFE: f32 = 0.8;
+
+/// Which path a weapon's shots are drawn through
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WeaponRenderCategory {
+    /// Glowing billboards that light up what's around them
+    Energy,
+    /// Solid models, some leaving a smoke trail
+    Matter,
+    Invisible,
+}
+
+impl WeaponRenderCategory {
+    pub fn from_flags(flags: WeaponFlags) -> Self {
+        if flags.contains(WeaponFlags::INVISIBLE) {
+            WeaponRenderCategory::Invisible
+        } else if flags.contains(WeaponFlags::MATTER_WEAPON) {
+            WeaponRenderCategory::Matter
+        } else {
+            WeaponRenderCategory::Energy
+        }
+    }
+
+    pub fn alpha_type(&self) -> AlphaType {
+        match self {
+            WeaponRenderCategory::Matter => MATTER_ALPHA_TYPE,
+            _ => ENERGY_ALPHA_TYPE,
+        }
+    }
+
+    /// Sets the renderer up for drawing shots of this kind
+    pub fn apply_render_state(&self, renderer: &mut dyn Renderer) {
+        renderer.set_alpha_type(self.alpha_type());
+
+        match self {
+            // Energy is its own light source
+            WeaponRenderCategory::Energy | WeaponRenderCategory::Invisible => renderer.set_lighting(LightStateType::None),
+            WeaponRenderCategory::Matter => renderer.set_lighting(LightStateType::Gouraud),
+        }
+    }
+}
+
+/// The parts of a weapon's table entry that decide how its shots look
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WeaponVisualInfo {
+    pub flags: WeaponFlags,
+    /// Bitmap for energy weapons, model for matter ones
+    pub image: usize,
+    pub size: f32,
+    pub light_color: [f32; 3],
+    /// 0 for a shot that gives off no light
+    pub light_distance: f32,
+}
+
+/// A shot in flight
+#[derive(Debug, Copy, Clone)]
+pub struct WeaponShot<'a> {
+    pub handle: usize,
+    pub position: Vector,
+    pub orientation: Matrix,
+    pub info: &'a WeaponVisualInfo,
+}
+
+impl<'a> WeaponShot<'a> {
+    /// The shot where it should be drawn, `alpha` of the way between the last two physics ticks
+    pub fn from_object(handle: usize, object: &Object, info: &'a WeaponVisualInfo, alpha: f32) -> Self {
+        let transform = object.interpolated_transform(alpha);
+
+        Self {
+            handle,
+            position: transform.position,
+            orientation: transform.orientation,
+            info,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EnergyBolt {
+    pub position: Vector,
+    pub size: f32,
+    pub texture: usize,
+    /// Set for `PLANAR` shots that keep their own facing instead of turning to the viewer
+    pub facing: Option<Vector>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MatterProjectile {
+    pub position: Vector,
+    pub orientation: Matrix,
+    pub model: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WeaponLight {
+    pub position: Vector,
+    pub color: [f32; 3],
+    pub distance: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SmokePuff {
+    pub position: Vector,
+    pub size: f32,
+    pub life: f32,
+    /// Planar puffs line up with the trail instead of facing the viewer
+    pub planar: bool,
+}
+
+/// Every shot sorted onto its rendering path
+#[derive(Debug, Clone, Default)]
+pub struct WeaponDrawList {
+    pub bolts: Vec<EnergyBolt>,
+    pub projectiles: Vec<MatterProjectile>,
+    pub lights: Vec<WeaponLight>,
+    pub smoke: Vec<SmokePuff>,
+}
+
+/// Keeps track of where each smoking shot last left a puff
+#[derive(Debug, Clone, Default)]
+pub struct WeaponRenderer {
+    trails: HashMap<usize, Vector>,
+}
+
+impl WeaponRenderer {
+    pub fn build(&mut self, shots: &[WeaponShot]) -> WeaponDrawList {
+        let mut list = WeaponDrawList::default();
+        let mut alive = HashSet::new();
+
+        for shot in shots {
+            let info = shot.info;
+
+            match WeaponRenderCategory::from_flags(info.flags) {
+                WeaponRenderCategory::Invisible => continue,
+                WeaponRenderCategory::Energy => {
+                    list.bolts.push(EnergyBolt {
+                        position: shot.position,
+                        size: info.size,
+                        texture: info.image,
+                        facing: info.flags.contains(WeaponFlags::PLANAR).then_some(shot.orientation.forward),
+                    });
+
+                    if info.light_distance > 0.0 {
+                        list.lights.push(WeaponLight {
+                            position: shot.position,
+                            color: info.light_color,
+                            distance: info.light_distance,
+                        });
+                    }
+                }
+                WeaponRenderCategory::Matter => {
+                    list.projectiles.push(MatterProjectile {
+                        position: shot.position,
+                        orientation: shot.orientation,
+                        model: info.image,
+                    });
+
+                    if info.flags.contains(WeaponFlags::SMOKE) {
+                        alive.insert(shot.handle);
+                        self.emit_smoke(shot, &mut list.smoke);
+                    }
+                }
+            }
+        }
+
+        self.trails.retain(|handle, _| alive.contains(handle));
+
+        list
+    }
+
+    fn emit_smoke(&mut self, shot: &WeaponShot, smoke: &mut Vec<SmokePuff>) {
+        let info = shot.info;
+
+        // Fresh shots start their trail where they are
+        let last = *self.trails.entry(shot.handle).or_insert(shot.position);
+        let mut dir = Vector::default();
+        let distance = Vector::compute_normalized_direction(&mut dir, &shot.position, &last);
+        let count = (distance / SMOKE_SPACING) as usize;
+
+        // Puffs are spaced evenly back along the path so fast shots don't leave gaps
+        for i in 1..=count {
+            let size = if info.flags.contains(WeaponFlags::REVERSE_SMOKE) {
+                info.size * 0.5
+            } else {
+                info.size
+            };
+
+            smoke.push(SmokePuff {
+                position: last + dir * (SMOKE_SPACING * i as f32),
+                size,
+                life: SMOKE_LIFE,
+                planar: info.flags.contains(WeaponFlags::PLANAR_SMOKE),
+            });
+        }
+
+        if count > 0 {
+            self.trails.insert(shot.handle, last + dir * (SMOKE_SPACING * count as f32));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn info(flags: WeaponFlags) -> WeaponVisualInfo {
+        WeaponVisualInfo {
+            flags,
+            image: 5,
+            size: 2.0,
+            light_color: [1.0, 0.5, 0.0],
+            light_distance: 20.0,
+        }
+    }
+
+    fn shot(handle: usize, z: f32, info: &WeaponVisualInfo) -> WeaponShot<'_> {
+        WeaponShot {
+            handle,
+            position: Vector::new(0.0, 0.0, z),
+            orientation: Matrix::IDENTITY,
+            info,
+        }
+    }
+
+    #[test]
+    fn weapon_category_test() {
+        assert_eq!(WeaponRenderCategory::from_flags(WeaponFlags::empty()), WeaponRenderCategory::Energy);
+        assert_eq!(WeaponRenderCategory::from_flags(WeaponFlags::MATTER_WEAPON), WeaponRenderCategory::Matter);
+        assert_eq!(
+            WeaponRenderCategory::from_flags(WeaponFlags::MATTER_WEAPON | WeaponFlags::INVISIBLE),
+            WeaponRenderCategory::Invisible
+        );
+        assert_eq!(WeaponRenderCategory::Energy.alpha_type(), ENERGY_ALPHA_TYPE);
+    }
+
+    #[test]
+    fn weapon_shot_interpolation_test() {
+        crate::test_common::setup();
+
+        let laser = info(WeaponFlags::IMAGE_BITMAP);
+        let mut object = Object::new(ObjectTypeDef::new("laser", ObjectClass::Weapon));
+        object.position = Vector::default();
+        object.store_previous_transform();
+        object.position = Vector::new(0.0, 0.0, 10.0);
+
+        // Drawn between the last two ticks, not where the latest one left it
+        let shot = WeaponShot::from_object(1, &object, &laser, 0.25);
+        assert!(shot.position.approx_eq(&Vector::new(0.0, 0.0, 2.5), 0.0001));
+
+        let list = WeaponRenderer::default().build(&[shot]);
+        assert_eq!(list.bolts[0].position, shot.position);
+    }
+
+    #[test]
+    fn weapon_draw_list_test() {
+        crate::test_common::setup();
+
+        let laser = info(WeaponFlags::IMAGE_BITMAP);
+        let missile = info(WeaponFlags::MATTER_WEAPON | WeaponFlags::SMOKE);
+        let cloaked = info(WeaponFlags::INVISIBLE);
+
+        let mut renderer = WeaponRenderer::default();
+        let list = renderer.build(&[shot(1, 0.0, &laser), shot(2, 0.0, &missile), shot(3, 0.0, &cloaked)]);
+
+        // Energy glows, matter is a model, nothing new from the missile yet
+        assert_eq!(list.bolts.len(), 1);
+        assert_eq!(list.lights.len(), 1);
+        assert_eq!(list.projectiles.len(), 1);
+        assert!(list.smoke.is_empty());
+
+        // Moving far enough leaves a trail behind it
+        let list = renderer.build(&[shot(2, SMOKE_SPACING * 2.5, &missile)]);
+        assert_eq!(list.smoke.len(), 2);
+        assert_eq!(list.smoke[1].position.z, SMOKE_SPACING * 2.0);
+
+        // Gone shots are forgotten
+        renderer.build(&[]);
+        assert!(renderer.trails.is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use super::prelude::*;
+use crate::math::noise::Perlin;
 
 const MAX_RAIN_INTENSITY: f32 = 50.0;
 const MAX_SNOW_INTENSITY: f32 = 200.0;
@@ -40,7 +41,30 @@ impl Default for Weather {
 }
 
 impl Weather {
-    
+    /// How fast the storm swells and dies down
+    const INTENSITY_DRIFT_RATE: f32 = 0.1;
+
+    /// Scale (0 to 1) that makes rain and snow come and go over time instead of holding steady
+    fn intensity_variation(noise: &Perlin, gametime: f32, channel: f32) -> f32 {
+        let v = noise.noise_2d(gametime * Self::INTENSITY_DRIFT_RATE, channel);
+        (v + 1.0).clamp(0.0, 1.0)
+    }
+
+    pub fn rain_intensity(&self, noise: &Perlin, gametime: f32) -> f32 {
+        if !self.flags.contains(WeatherFlags::RAIN) {
+            return 0.0;
+        }
+
+        self.rain_intensity_scalar * MAX_RAIN_INTENSITY * Self::intensity_variation(noise, gametime, 0.5)
+    }
+
+    pub fn snow_intensity(&self, noise: &Perlin, gametime: f32) -> f32 {
+        if !self.flags.contains(WeatherFlags::SNOW) {
+            return 0.0;
+        }
+
+        self.snow_intensity_scalar * MAX_SNOW_INTENSITY * Self::intensity_variation(noise, gametime, 10.5)
+    }
 }
 
 impl GameBoundedType<Weather> {
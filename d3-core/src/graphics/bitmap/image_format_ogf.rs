@@ -1,6 +1,6 @@
 use std::{fs::read, io::{BufReader, Read, Seek, SeekFrom}, ops::Deref, ptr};
 use crate::{gr_rgb16, graphics::{NEW_TRANSPARENT_COLOR, OPAQUE_FLAG}, string::D3String};
-use super::{generate_random_color_1555, generate_random_color_4444, Bitmap16, BitmapFlags, BitmapFormat};
+use super::{remaining_len, write_debug_pattern, Bitmap16, BitmapFlags, BitmapFormat, DebugPattern};
 use anyhow::{Context, Error};
 use byteorder::{LittleEndian, ReadBytesExt, BigEndian};
 use anyhow::Result;
@@ -69,7 +69,7 @@ impl OgfBitmap {
             return self.width;
         }
         else {
-            return self.width >> level;
+            return self.width.checked_shr(level as u32).unwrap_or(0);
         }
     }
 
@@ -78,7 +78,7 @@ impl OgfBitmap {
             return self.height;
         }
         else {
-            return self.height >> level;
+            return self.height.checked_shr(level as u32).unwrap_or(0);
         }
     }
 
@@ -172,22 +172,12 @@ impl Bitmap16 for OgfBitmap {
         self.format
     }
     
-    fn make_funny(&mut self) {
-        let image_type = self.format;
+    fn fill_debug_pattern(&mut self, kind: DebugPattern) {
+        let format = self.format;
 
-        let gen_random_color = match image_type {
-            BitmapFormat::Fmt1555 => {
-                generate_random_color_1555
-            },
-            _ => generate_random_color_4444
-        };
-
-        for m in 0..self.mip_levels() {
-            let mut data = self.get_mipped_data_slice_mut(m);
-                
-            for i in 0..data.len() {
-                data[i] = gen_random_color();
-            }
+        for m in 0..self.mip_levels().max(1) {
+            let (width, height) = (self.get_mipmap_width(m), self.get_mipmap_height(m));
+            write_debug_pattern(self.get_mipped_data_slice_mut(m), width, height, format, kind);
         }
     }
 }
@@ -195,9 +185,9 @@ impl Bitmap16 for OgfBitmap {
 impl OgfBitmap {
     // Loads a TGA or OFG into memory
     pub fn new<R: Read + Seek>(reader: &mut BufReader<R>, requested_format: BitmapFormat) -> Result<Self> {
-        let read_image_id_length = reader.read_u8().unwrap();
-        let read_color_map_type = reader.read_u8().unwrap();
-        let read_image_type = reader.read_u8().unwrap();
+        let read_image_id_length = reader.read_u8().context("Failed to read header")?;
+        let read_color_map_type = reader.read_u8().context("Failed to read header")?;
+        let read_image_type = reader.read_u8().context("Failed to read header")?;
 
         // trace!("color map type: {}", read_color_map_type);
 
@@ -213,7 +203,7 @@ impl OgfBitmap {
             _ => {}
         };
 
-        trace!("outrage type: {:?}", outrage_image_type);
+        trace!(target: "render", "outrage type: {:?}", outrage_image_type);
 
         let mut read_name = [0u8; 35];
         let _ = reader.read(read_name.as_mut_slice());
@@ -223,7 +213,7 @@ impl OgfBitmap {
 
         if let Some(pos) = read_name.iter().position(|&c| c == 0) {
             let valid_data = &read_name[..pos];
-            name = std::str::from_utf8(&valid_data).context("Failed to parse ut8 for name").unwrap().to_owned();
+            name = std::str::from_utf8(&valid_data).context("Failed to parse ut8 for name")?.to_owned();
 
             match outrage_image_type {
                 OutrageGraphicsFormat::Compressed4444Mipped |
@@ -242,31 +232,31 @@ impl OgfBitmap {
             OutrageGraphicsFormat::Compressed4444Mipped | 
             OutrageGraphicsFormat::CompressedMipped | 
             OutrageGraphicsFormat::CompressedNewMipped => {
-                reader.read_u8().unwrap() as usize
+                reader.read_u8().context("Failed to read mip count")? as usize
             },
             _ => 1
         };
 
-        trace!("mips {}", num_mips);
+        trace!(target: "render", "mips {}", num_mips);
 
         let is_mipped = num_mips > 1;
 
         /* ignore next bytes */
         let _ = reader.seek(SeekFrom::Current(9));
 
-        let width = reader.read_i16::<LittleEndian>().unwrap();
-        let height = reader.read_i16::<LittleEndian>().unwrap();
-        let pix_size = reader.read_u8().unwrap();
+        let width = reader.read_i16::<LittleEndian>().context("Failed to read width")?;
+        let height = reader.read_i16::<LittleEndian>().context("Failed to read height")?;
+        let pix_size = reader.read_u8().context("Failed to read pixel size")?;
 
-        trace!("width is {}", width);
-        trace!("height is {}", height);
-        trace!("Pix size is {}", pix_size);
+        trace!(target: "render", "width is {}", width);
+        trace!(target: "render", "height is {}", height);
+        trace!(target: "render", "Pix size is {}", pix_size);
 
         if pix_size != 32 && pix_size != 24 {
             return Err(anyhow!("pix size must be 32"));
         }
 
-        let descriptor = reader.read_u8().unwrap();
+        let descriptor = reader.read_u8().context("Failed to read descriptor")?;
 
         match descriptor & 0x0F {
             0 | 8 => {},
@@ -276,6 +266,18 @@ impl OgfBitmap {
         /* Skip over ID */
         let _ = reader.seek(SeekFrom::Current(read_image_id_length as i64));
 
+        if width <= 0 || height <= 0 {
+            return Err(anyhow!("invalid dimensions {}x{}", width, height));
+        }
+
+        let (width, height) = (width as usize, height as usize);
+
+        // Runs hold at most 250 pixels in 3 bytes, more pixels than that is a corrupt header
+        let remaining = remaining_len(reader).context("Failed to get the data size")?;
+        if (width * height) as u64 > remaining.saturating_mul(250) {
+            return Err(anyhow!("image is too large for its data: {}x{} from {} bytes", width, height, remaining));
+        }
+
         let mut flags = BitmapFlags::None;
 
         // Simulate the effects of the old bitmap system
@@ -291,8 +293,8 @@ impl OgfBitmap {
             num_mips as usize * ((width as usize * height as usize * 2) / 3);
 
         let mut bitmap = OgfBitmap {
-            width: width as usize,
-            height: height as usize,
+            width: width,
+            height: height,
             format: match (requested_format, outrage_image_type) {
                 (BitmapFormat::Fmt4444, _) | (_, OutrageGraphicsFormat::Compressed4444Mipped) => {
                     BitmapFormat::Fmt4444
@@ -314,17 +316,21 @@ impl OgfBitmap {
                 let mut total = 0;
 
                 while total < (height * width) {
-                    let command = reader.read_u8().unwrap();
+                    let command = reader.read_u8().context("Failed to read run")?;
                     let len = (command & 127) + 1;
 
+                    if total + len as usize > height * width {
+                        return Err(anyhow!("run goes past the end of the image"));
+                    }
+
                     if command & 128 != 0 {
                         if pix_size == 32 {
-                            pixel = reader.read_u32::<LittleEndian>().unwrap();
+                            pixel = reader.read_u32::<LittleEndian>().context("Failed to read pixel")?;
                         }
                         else {
-                            let r = reader.read_u8().unwrap() as u32;
-                            let g =  reader.read_u8().unwrap() as u32;
-                            let b =  reader.read_u8().unwrap() as u32;
+                            let r = reader.read_u8().context("Failed to read pixel")? as u32;
+                            let g =  reader.read_u8().context("Failed to read pixel")? as u32;
+                            let b =  reader.read_u8().context("Failed to read pixel")? as u32;
                             pixel = (255 << 24) | (r << 16) | (g << 8) | b;
                         }
 
@@ -346,18 +352,39 @@ impl OgfBitmap {
                             total += 1;
                         }
                     }
+                    else {
+                        // Raw packet, each pixel is stored
+                        for _ in 0..len {
+                            if pix_size == 32 {
+                                pixel = reader.read_u32::<LittleEndian>().context("Failed to read pixel")?;
+                            }
+                            else {
+                                let r = reader.read_u8().context("Failed to read pixel")? as u32;
+                                let g =  reader.read_u8().context("Failed to read pixel")? as u32;
+                                let b =  reader.read_u8().context("Failed to read pixel")? as u32;
+                                pixel = (255 << 24) | (r << 16) | (g << 8) | b;
+                            }
+
+                            let i = total / width;
+                            let t = total % width;
+                            let index = if is_upside_down { ((height - 1) - i) * width + t } else { i * width + t };
+
+                            bitmap.data[index] = tga_translate_pixel_16(pixel as i32, bitmap.format);
+                            total += 1;
+                        }
+                    }
                 }
             },
             OutrageGraphicsFormat::UncompressedTga => {
                 for i in 0..height {
                     for t in 0..width {
                         if pix_size == 32 {
-                            pixel = reader.read_u32::<LittleEndian>().unwrap();
+                            pixel = reader.read_u32::<LittleEndian>().context("Failed to read pixel")?;
                         }
                         else {
-                            let r = reader.read_u8().unwrap() as u32;
-                            let g =  reader.read_u8().unwrap() as u32;
-                            let b =  reader.read_u8().unwrap() as u32;
+                            let r = reader.read_u8().context("Failed to read pixel")? as u32;
+                            let g =  reader.read_u8().context("Failed to read pixel")? as u32;
+                            let b =  reader.read_u8().context("Failed to read pixel")? as u32;
                             pixel = (255 << 24) | (r << 16) | (g << 8) | b;
                         }
 
@@ -379,7 +406,7 @@ impl OgfBitmap {
             OutrageGraphicsFormat::CompressedMipped | 
             OutrageGraphicsFormat::Compressed8bit | 
             OutrageGraphicsFormat::CompressedNewMipped => {
-                tga_read_outrage_compressed_16(reader, &mut bitmap, num_mips, outrage_image_type);
+                tga_read_outrage_compressed_16(reader, &mut bitmap, num_mips, outrage_image_type)?;
             }
             _ => { 
                 return Err(anyhow!("failed to load OGF, unknown type!"));
@@ -418,7 +445,7 @@ fn tga_translate_pixel_16(pixel: i32, format: BitmapFormat) -> u16 {
     }
 }
 
-fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bitmap: &mut OgfBitmap, mipmap_count: usize, image_format: OutrageGraphicsFormat) {
+fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bitmap: &mut OgfBitmap, mipmap_count: usize, image_format: OutrageGraphicsFormat) -> Result<()> {
     for m in 0..mipmap_count {
         let width = bitmap.get_mipmap_width(m);
         let height = bitmap.get_mipmap_height(m);
@@ -428,13 +455,11 @@ fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bit
         let dest_data = bitmap.get_mipped_data_slice_mut(m);
 
         while count != total {
-            assert!(count < total);
-
-            let command = reader.read_u8().unwrap();
+            let command = reader.read_u8().context("Failed to read run")?;
 
             match command {
                 0 => { // raw pixel
-                    let mut pixel = reader.read_u16::<LittleEndian>().unwrap();
+                    let mut pixel = reader.read_u16::<LittleEndian>().context("Failed to read pixel")?;
 
                     match image_format {
                         OutrageGraphicsFormat::Compressed1555Mipped => {},
@@ -461,7 +486,7 @@ fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bit
                 },
                 c if c >= 2 && command <= 250 => {
                     // next pixel is run of pixels
-                    let mut pixel = reader.read_u16::<LittleEndian>().unwrap();
+                    let mut pixel = reader.read_u16::<LittleEndian>().context("Failed to read pixel")?;
 
                     match image_format {
                         OutrageGraphicsFormat::Compressed1555Mipped => {},
@@ -480,6 +505,10 @@ fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bit
                         }
                     }
 
+                    if count + command as usize > total {
+                        return Err(anyhow!("run goes past the end of mip level {}", m));
+                    }
+
                     for _ in 0..command {
                         let i = count / width;
                         let t = count % width;
@@ -487,7 +516,7 @@ fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bit
                         count += 1;
                     }
                 },
-                _ => panic!("bad compression run!")
+                _ => return Err(anyhow!("bad compression run: {}", command))
             }
         }
 
@@ -517,6 +546,8 @@ fn tga_read_outrage_compressed_16<R: Read + Seek>(reader: &mut BufReader<R>, bit
             }
         }
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -623,7 +654,7 @@ pub mod tests {
         assert_eq!(bitmap.width(), 256);
         assert_eq!(bitmap.height(), 256);
 
-        bitmap.make_funny();
+        bitmap.fill_debug_pattern(DebugPattern::Noise);
 
         let data_16 = bitmap.get_mipped_data_slice(0);
 
@@ -641,7 +672,7 @@ pub mod tests {
         assert_eq!(bitmap.width(), 256);
         assert_eq!(bitmap.height(), 256);
 
-        bitmap.make_funny();
+        bitmap.fill_debug_pattern(DebugPattern::Noise);
 
         display_1555!(function_name!(), bitmap.get_mipped_data_slice(0), bitmap.get_mipmap_width(0), bitmap.get_mipmap_height(0));
         display_1555!(function_name!(), bitmap.get_mipped_data_slice(1), bitmap.get_mipmap_width(1), bitmap.get_mipmap_height(1));
@@ -649,4 +680,16 @@ pub mod tests {
         display_1555!(function_name!(), bitmap.get_mipped_data_slice(3), bitmap.get_mipmap_width(3), bitmap.get_mipmap_height(3));
         display_1555!(function_name!(), bitmap.get_mipped_data_slice(4), bitmap.get_mipmap_width(4), bitmap.get_mipmap_height(4));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ogf_corrupt_test() {
+        crate::test_common::setup();
+
+        let data = std::fs::read(testdata!("badapple_1555_5mm.ogf")).unwrap();
+
+        for len in [0, 3, 40, data.len() / 2] {
+            let mut reader = BufReader::new(std::io::Cursor::new(&data[..len]));
+            assert!(OgfBitmap::new(&mut reader, BitmapFormat::Fmt1555).is_err());
+        }
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 
 use crate::{gr_rgb16, graphics::{bitmap, NEW_TRANSPARENT_COLOR, OPAQUE_FLAG}, string::{D3String, EMPTY}};
 
-use super::{generate_random_color_1555, Bitmap16, BitmapFlags, BitmapFormat};
+use super::{remaining_len, write_debug_pattern, Bitmap16, BitmapFlags, BitmapFormat, DebugPattern};
 
 #[derive(Debug, Clone)]
 pub struct PcxBitmap {
@@ -42,10 +42,8 @@ impl Bitmap16 for PcxBitmap {
         BitmapFormat::Fmt1555
     }
     
-    fn make_funny(&mut self) {
-        for i in 0..(self.width * self.height) {
-            self.data[i] = generate_random_color_1555();
-        }
+    fn fill_debug_pattern(&mut self, kind: DebugPattern) {
+        write_debug_pattern(&mut self.data, self.width, self.height, BitmapFormat::Fmt1555, kind);
     }
 }
 
@@ -55,6 +53,7 @@ const COLOR_INFO_OFFSET: usize = 65;
 const NUM_BPP_OFFSET: usize = 3;
 const VERSION_OFFSET: usize = 1;
 const PLANE_SIZE_OFFSET: usize = 66;
+const MAX_RUN_LENGTH: u64 = 63;
 
 impl PcxBitmap {
     pub fn new<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<Self> {
@@ -63,7 +62,7 @@ impl PcxBitmap {
         reader.read(&mut temp).context("Failed to read data")?;
         let _ = reader.seek(std::io::SeekFrom::Start(0));
 
-        trace!("Plane(s): {}", temp[COLOR_INFO_OFFSET]);
+        trace!(target: "render", "Plane(s): {}", temp[COLOR_INFO_OFFSET]);
 
         match temp[COLOR_INFO_OFFSET] {
             1 => parse_pcx_8bit(reader), // parse 8 bit
@@ -77,16 +76,13 @@ fn parse_pcx_8bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitmap
     let mut header = [0u8; 4];
     reader.read(&mut header).context("Failed to read header")?;
 
-    trace!("Depth: {}", header[NUM_BPP_OFFSET]);
+    trace!(target: "render", "Depth: {}", header[NUM_BPP_OFFSET]);
 
     if header[NUM_BPP_OFFSET] != 8 {
         return Err(anyhow!("Only 8-bit depth is acceptable"));
     }
 
-    let xmin = reader.read_i16::<LittleEndian>().unwrap();
-    let ymin = reader.read_i16::<LittleEndian>().unwrap();
-    let xmax = reader.read_i16::<LittleEndian>().unwrap();
-    let ymax = reader.read_i16::<LittleEndian>().unwrap();
+    let (width, height) = read_dimensions(reader)?;
 
     let mut read = [0u8; 116];
     reader.read(&mut read).context("Failed to read data")?;
@@ -95,23 +91,25 @@ fn parse_pcx_8bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitmap
         return Err(anyhow!("Must be 8 bit only"));
     }
 
-    let width = (1 + xmax - xmin) as usize;
-    let height = (1 + ymax - ymin) as usize;
     let total = width * height;
+    check_encoded_size(reader, total)?;
 
     let mut data = vec![0u8; total];
     let mut run = 0usize;
 
     while run < total {
-        let read = reader.read_u8().unwrap();
+        let read = reader.read_u8().context("Unexpected end of image data")?;
 
         if read >= 192 {
-            let temp = reader.read_u8().unwrap();
+            let temp = reader.read_u8().context("Unexpected end of image data")?;
+            let count = (read - 192) as usize;
 
-            for _ in 0..(read - 192) {
-                data[run] = temp;
-                run += 1;
+            if run + count > total {
+                return Err(anyhow!("RLE run goes past the end of the image"));
             }
+
+            data[run..run + count].fill(temp);
+            run += count;
         }
         else {
             data[run] = read;
@@ -127,9 +125,9 @@ fn parse_pcx_8bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitmap
     let mut p_green = [0u8; 256];
     let mut p_blue = [0u8; 256];
     for i in 0..256 {
-        p_red[i] = reader.read_u8().unwrap() >> 3;
-        p_green[i] = reader.read_u8().unwrap() >> 3;
-        p_blue[i] = reader.read_u8().unwrap() >> 3;
+        p_red[i] = reader.read_u8().context("Failed to read palette")? >> 3;
+        p_green[i] = reader.read_u8().context("Failed to read palette")? >> 3;
+        p_blue[i] = reader.read_u8().context("Failed to read palette")? >> 3;
     }
 
     let mut bitmap = PcxBitmap {
@@ -172,10 +170,7 @@ fn parse_pcx_24bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitma
         return Err(anyhow!("Only 8bit depth is acceptabled"));
     }
 
-    let xmin = reader.read_i16::<LittleEndian>().unwrap();
-    let ymin = reader.read_i16::<LittleEndian>().unwrap();
-    let xmax = reader.read_i16::<LittleEndian>().unwrap();
-    let ymax = reader.read_i16::<LittleEndian>().unwrap();
+    let (width, height) = read_dimensions(reader)?;
 
     let mut read = [0u8; 116];
     reader.read(&mut read).context("Failed to read data")?;
@@ -184,17 +179,21 @@ fn parse_pcx_24bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitma
         return Err(anyhow!("Must be 3 planes for 24bit encoding"));
     }
 
-    let width = (1 + xmax - xmin) as usize;
-    let height = (1 + ymax - ymin) as usize;
 
     /* Determine the bytes per line */
     let _ = reader.seek(std::io::SeekFrom::Start(PLANE_SIZE_OFFSET as u64));
-    let bytes_per_line = reader.read_i16::<LittleEndian>().unwrap() as usize;
+    let bytes_per_line = reader.read_u16::<LittleEndian>().context("Failed to read bytes per line")? as usize;
     let _ = reader.seek(std::io::SeekFrom::Start(PCX_HEADER_SIZE as u64));
 
+    if bytes_per_line < width {
+        return Err(anyhow!("Scanlines are shorter than the image width"));
+    }
+
     // scanline length
     let total = 3 * bytes_per_line;
 
+    check_encoded_size(reader, total * height)?;
+
     let mut data = vec![0u8; total * height];
 
     // Load in the data
@@ -206,13 +205,13 @@ fn parse_pcx_24bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitma
     // etc.
 
     /* Red scanline */
-    read_color_scanline(reader, &mut data, height, bytes_per_line);
+    read_color_scanline(reader, &mut data, height, bytes_per_line)?;
 
     /* Green scanline */
-    read_color_scanline(reader, &mut data, height, bytes_per_line);
+    read_color_scanline(reader, &mut data, height, bytes_per_line)?;
 
     /* Blue scanline */
-    read_color_scanline(reader, &mut data, height, bytes_per_line);
+    read_color_scanline(reader, &mut data, height, bytes_per_line)?;
 
     let mut bitmap = PcxBitmap {
         width: width,
@@ -233,31 +232,64 @@ fn parse_pcx_24bit<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<PcxBitma
     Ok(bitmap)
 }
 
-fn read_color_scanline<R: Read + Seek>(reader: &mut BufReader<R>, data: &mut [u8], height: usize, bytes_per_line: usize) {
+/// A run packs at most 63 bytes into 2, anything bigger than that is a corrupt header
+fn check_encoded_size<R: Read + Seek>(reader: &mut BufReader<R>, size: usize) -> Result<()> {
+    let remaining = remaining_len(reader).context("Failed to get the data size")?;
+
+    if size as u64 > remaining.saturating_mul(MAX_RUN_LENGTH) {
+        return Err(anyhow!("PCX is too large for its data: {} bytes from {}", size, remaining));
+    }
+
+    Ok(())
+}
+
+fn read_dimensions<R: Read + Seek>(reader: &mut BufReader<R>) -> Result<(usize, usize)> {
+    let xmin = reader.read_i16::<LittleEndian>().context("Failed to read dimensions")? as i32;
+    let ymin = reader.read_i16::<LittleEndian>().context("Failed to read dimensions")? as i32;
+    let xmax = reader.read_i16::<LittleEndian>().context("Failed to read dimensions")? as i32;
+    let ymax = reader.read_i16::<LittleEndian>().context("Failed to read dimensions")? as i32;
+
+    if xmax < xmin || ymax < ymin {
+        return Err(anyhow!("Invalid PCX dimensions: ({}, {}) to ({}, {})", xmin, ymin, xmax, ymax));
+    }
+
+    Ok(((1 + xmax - xmin) as usize, (1 + ymax - ymin) as usize))
+}
+
+fn read_color_scanline<R: Read + Seek>(reader: &mut BufReader<R>, data: &mut [u8], height: usize, bytes_per_line: usize) -> Result<()> {
     let mut offset = 0;
 
     for line in 0..height {
         let mut run = 0;
 
         while run < bytes_per_line {
-            let read = reader.read_u8().unwrap();
+            let read = reader.read_u8().context("Unexpected end of scanline data")?;
 
             if read >= 192 {
-                let temp = reader.read_u8().unwrap();
+                let temp = reader.read_u8().context("Unexpected end of scanline data")?;
+                let count = (read - 192) as usize;
 
-                for _ in 0..(read - 192) {
-                    data[offset] = temp;
-                    run += 1;
-                    offset += 1;
+                if offset + count > data.len() {
+                    return Err(anyhow!("RLE run goes past the end of the image"));
                 }
+
+                data[offset..offset + count].fill(temp);
+                run += count;
+                offset += count;
             }
             else {
+                if offset >= data.len() {
+                    return Err(anyhow!("RLE run goes past the end of the image"));
+                }
+
                 data[offset] = read;
                 run += 1;
                 offset += 1;
             }
         }
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -282,4 +314,22 @@ pub mod tests {
 
         display_1555!(function_name!(), &bitmap.data, bitmap.width(), bitmap.height());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pcx_corrupt_test() {
+        crate::test_common::setup();
+
+        let data = std::fs::read(testdata!("badapple.pcx")).unwrap();
+
+        // Cut off in the middle of the image data
+        let mut reader = BufReader::new(std::io::Cursor::new(&data[..data.len() / 2]));
+        assert!(PcxBitmap::new(&mut reader).is_err());
+
+        // xmax before xmin
+        let mut flipped = data.clone();
+        flipped[8..10].copy_from_slice(&0i16.to_le_bytes());
+        flipped[4..6].copy_from_slice(&100i16.to_le_bytes());
+        let mut reader = BufReader::new(std::io::Cursor::new(flipped));
+        assert!(PcxBitmap::new(&mut reader).is_err());
+    }
+}
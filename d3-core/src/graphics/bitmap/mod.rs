@@ -4,7 +4,7 @@ pub mod image_format_pcx;
 pub mod videoclip;
 
 
-use std::io::BufReader;
+use std::io::{BufReader, Seek, SeekFrom};
 
 use anyhow::Result;
 
@@ -50,7 +50,62 @@ pub trait Bitmap16: std::fmt::Debug {
     fn flags(&self) -> &BitmapFlags;
     fn name(&self) -> &D3String;
     fn format(&self) -> BitmapFormat; // Should be just something for TGA only
-    fn make_funny(&mut self);
+    /// Overwrites the image with a pattern that stands out, for missing or broken textures
+    fn fill_debug_pattern(&mut self, kind: DebugPattern);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugPattern {
+    /// 8x8 magenta and black squares, the usual missing texture look
+    Checkerboard,
+    /// Random colors
+    Noise,
+    /// u as red and v as green, shows how a texture is mapped
+    UvGradient,
+}
+
+fn debug_color(format: BitmapFormat, r: u32, g: u32, b: u32) -> u16 {
+    match format {
+        BitmapFormat::Fmt1555 => (0x8000 | ((r >> 3) << 10) | ((g >> 3) << 5) | (b >> 3)) as u16,
+        BitmapFormat::Fmt4444 => (0xF000 | ((r >> 4) << 8) | ((g >> 4) << 4) | (b >> 4)) as u16,
+    }
+}
+
+/// Fills a `width` x `height` image, a short buffer only gets the rows it has room for
+pub fn write_debug_pattern(data: &mut [u16], width: usize, height: usize, format: BitmapFormat, kind: DebugPattern) {
+    extern crate tinyrand;
+
+    use tinyrand::Rand;
+
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let mut rand = create_rng();
+
+    for (i, pixel) in data.iter_mut().take(width * height).enumerate() {
+        let (x, y) = (i % width, i / width);
+
+        *pixel = match kind {
+            DebugPattern::Checkerboard => {
+                if ((x / 8) + (y / 8)) & 1 == 0 {
+                    debug_color(format, 0xFF, 0x00, 0xFF)
+                }
+                else {
+                    debug_color(format, 0x00, 0x00, 0x00)
+                }
+            },
+            DebugPattern::Noise => {
+                let c = rand.next_u32();
+                debug_color(format, c & 0xFF, (c >> 8) & 0xFF, (c >> 16) & 0xFF)
+            },
+            DebugPattern::UvGradient => {
+                let u = (x * 255 / (width - 1).max(1)) as u32;
+                let v = (y * 255 / (height - 1).max(1)) as u32;
+                debug_color(format, u, v, 0)
+            },
+        };
+    }
 }
 
 pub(crate) trait ScaleableBitmap16 {
@@ -101,20 +156,9 @@ impl Bitmap16 for BitmapChunk16 {
         self.format
     }
 
-    fn make_funny(&mut self) {
-        match self.format() {
-            BitmapFormat::Fmt4444 => {
-                for i in 0..self.square_size * 2 {
-                    self.data[i] = generate_random_color_4444();
-                }
-            },
-            BitmapFormat::Fmt1555 => {
-                for i in 0..self.square_size * 2 {
-                    self.data[i] = generate_random_color_1555();
-                }
-            },
-            _ => {}
-        }
+    fn fill_debug_pattern(&mut self, kind: DebugPattern) {
+        let size = self.square_size;
+        write_debug_pattern(&mut self.data, size, size, self.format, kind);
     }
 }
 
@@ -253,6 +297,15 @@ pub fn generate_random_color_1555() -> u16 {
     ((alpha << 15) | (red << 10) | (green << 5) | blue) as u16
 }
 
+/// Bytes left in the stream, lets loaders reject headers claiming more pixels than the data could hold
+pub(crate) fn remaining_len<R: Seek>(reader: &mut R) -> std::io::Result<u64> {
+    let pos = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(pos))?;
+
+    Ok(end.saturating_sub(pos))
+}
+
 pub fn scale_bitmap_16<B: Bitmap16 + Clone + ScaleableBitmap16>(bitmap: &B, mipped: bool, new_w: usize, new_h: usize, additonal_mem: usize) -> Result<B> {
     let original_data = bitmap.data();
     let source_mipped = bitmap.mip_levels() > 0;
@@ -310,6 +363,12 @@ impl MemBitmap16 {
             name: "".into()
         }
     }
+
+    /// The pixels to draw into, allocated on first use
+    pub fn data_mut(&mut self) -> &mut [u16] {
+        self.data.resize(self.width * self.height, 0);
+        &mut self.data
+    }
 }
 
 impl Bitmap16 for MemBitmap16 {
@@ -341,8 +400,63 @@ impl Bitmap16 for MemBitmap16 {
         BitmapFormat::Fmt4444
     }
 
-    fn make_funny(&mut self) {
-        todo!()
+    fn fill_debug_pattern(&mut self, kind: DebugPattern) {
+        // Allocated with only a capacity until it's drawn to
+        self.data.resize(self.width * self.height, 0);
+        write_debug_pattern(&mut self.data, self.width, self.height, BitmapFormat::Fmt4444, kind);
+    }
+}
+
+/// A 32 bit ARGB surface to draw into, what rendering without a window ends up on
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemBitmap32 {
+    data: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl MemBitmap32 {
+    pub fn new(w: usize, h: usize) -> Self {
+        MemBitmap32 {
+            data: vec![0; w * h],
+            width: w,
+            height: h
+        }
+    }
+
+    pub fn data(&self) -> &[u32] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u32] {
+        &mut self.data
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.data[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    /// A 4444 copy, each channel keeping its top four bits
+    pub fn to_4444(&self) -> MemBitmap16 {
+        let mut bitmap = MemBitmap16::new(self.width, self.height);
+
+        for (dst, &src) in bitmap.data_mut().iter_mut().zip(&self.data) {
+            *dst = (((src >> 28) & 0xF) << 12 | ((src >> 20) & 0xF) << 8 | ((src >> 12) & 0xF) << 4 | ((src >> 4) & 0xF)) as u16;
+        }
+
+        bitmap
     }
 }
 
@@ -354,4 +468,40 @@ impl Bitmap16 for MemBitmap16 {
 // TODO: bm_rowsize
 // TODO: bm_GenerateMipMaps
 // TOOO: clear bitmap
-// TODO: bm_SetBitmapIfTransparent
\ No newline at end of file
+// TODO: bm_SetBitmapIfTransparent
+#[cfg(test)]
+pub mod tests {
+    use crate::graphics::generic_bitmap::GenericBitmap16;
+
+    use super::*;
+
+    #[test]
+    fn debug_pattern_test() {
+        crate::test_common::setup();
+
+        let mut bitmap = GenericBitmap16::new(vec![0u16; 16 * 16], 16, 16);
+
+        bitmap.fill_debug_pattern(DebugPattern::Checkerboard);
+        assert_eq!(bitmap.data()[0], 0xFF0F);
+        assert_eq!(bitmap.data()[8], 0xF000);
+        assert_eq!(bitmap.data()[8 * 16 + 8], 0xFF0F);
+
+        bitmap.fill_debug_pattern(DebugPattern::UvGradient);
+        assert_eq!(bitmap.data()[0], 0xF000);
+        assert_eq!(bitmap.data()[15], 0xFF00);
+        assert_eq!(bitmap.data()[16 * 16 - 1], 0xFFF0);
+
+        // Every pixel written is opaque
+        bitmap.fill_debug_pattern(DebugPattern::Noise);
+        assert!(bitmap.data().iter().all(|&p| p & 0xF000 == 0xF000));
+
+        // Short buffers only get the rows that fit
+        let mut data = vec![0u16; 10];
+        write_debug_pattern(&mut data, 4, 4, BitmapFormat::Fmt1555, DebugPattern::Checkerboard);
+        assert!(data.iter().all(|&p| p == 0xFC1F));
+
+        let mut empty = MemBitmap16::new(8, 8);
+        empty.fill_debug_pattern(DebugPattern::Checkerboard);
+        assert_eq!(empty.data().len(), 64);
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{common::SharedMutRef, game::terrain::TERRAIN_WIDTH, graphics::{bitmap::{scale_bitmap_16}, texture::TextureSizeType, TEXTURE_HEIGHT, TEXTURE_WIDTH}, string::D3String};
 use core::str;
-use std::{io::{BufReader, Read, Seek}, os::unix::raw::off_t};
+use std::io::{BufReader, Read, Seek};
 use byteorder::{LittleEndian, ReadBytesExt, BigEndian};
 
 use super::bitmap::{Bitmap16, BitmapFormat, ScaleableBitmap16};
+use super::image_format_iff::IffFrameStream;
+use crate::graphics::generic_bitmap::GenericBitmap16;
 
 use bitflags::bitflags;
 use anyhow::Result;
@@ -18,13 +20,37 @@ pub enum VideoClipFormat {
     ABM
 }
 
-// TODO: Lazy implementations for frames
+/// Source of frames decoded on demand, so long clips don't sit in memory all at once
+pub trait FrameStream: std::fmt::Debug {
+    /// The next frame, None once the stream has ended
+    fn next_frame(&mut self) -> Result<Option<Box<dyn Bitmap16>>>;
+    fn rewind(&mut self) -> Result<()>;
+}
+
+impl<R: Read + Seek> FrameStream for IffFrameStream<R> {
+    fn next_frame(&mut self) -> Result<Option<Box<dyn Bitmap16>>> {
+        match self.next() {
+            Some(frame) => {
+                let frame = frame?;
+                Ok(Some(Box::new(GenericBitmap16::new(frame.to_4444(), frame.width(), frame.height()))))
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        Ok(IffFrameStream::rewind(self)?)
+    }
+}
 
 #[derive(Debug)]
 pub struct VideoClip {
     name: D3String,
     frames: Vec<Box<dyn Bitmap16>>,
     frame_time: f32, // time (in seconds) of each frame
+    /// Streamed clips decode into `current` instead of holding every frame
+    stream: Option<Box<dyn FrameStream>>,
+    current: Option<Box<dyn Bitmap16>>,
 }
 
 pub type BitmapLoader<B: Bitmap16 + ScaleableBitmap16 + Clone + 'static> = dyn Fn(&str) -> Option<B>;
@@ -41,6 +67,39 @@ impl VideoClip {
         vclip
     }
 
+    /// Wraps a frame stream, only the current frame is kept around
+    pub fn new_streamed(name: D3String, stream: Box<dyn FrameStream>, frame_time: f32) -> Self {
+        Self {
+            name,
+            frames: Vec::new(),
+            frame_time,
+            stream: Some(stream),
+            current: None
+        }
+    }
+
+    pub fn is_streamed(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Advances a streamed clip by one frame, it loops back to the start once the stream ends
+    pub fn next_streamed_frame(&mut self) -> Result<Option<&dyn Bitmap16>> {
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+
+        let mut frame = stream.next_frame()?;
+
+        if frame.is_none() {
+            stream.rewind()?;
+            frame = stream.next_frame()?;
+        }
+
+        self.current = frame;
+
+        Ok(self.current.as_deref())
+    }
+
     pub fn name(&self) -> &D3String {
         &self.name
     }
@@ -53,6 +112,10 @@ impl VideoClip {
         self.frames.as_slice()
     }
 
+    pub fn frames_mut(&mut self) -> &mut [Box<dyn Bitmap16>] {
+        self.frames.as_mut_slice()
+    }
+
     pub fn get_frame_bitmap(&self, frame: usize) -> &Box<dyn Bitmap16> {
         &self.frames[frame]
     }
@@ -157,7 +220,7 @@ fn load_ifvl_clip<R, B>(name: &str, reader: &mut BufReader<R>, len: usize, textu
 
             name = bitmap_name.to_string();
             let name = format!("{}.oaf", name);
-            trace!("bitmap name is {}", &bitmap_name);
+            trace!(target: "render", "bitmap name is {}", &bitmap_name);
 
             let w;
             let h;
@@ -199,6 +262,8 @@ fn load_ifvl_clip<R, B>(name: &str, reader: &mut BufReader<R>, len: usize, textu
     Ok(VideoClip {
         name: D3String::from(name),
         frames: frames,
-        frame_time: DEFAULT_FRAMETIME
+        frame_time: DEFAULT_FRAMETIME,
+        stream: None,
+        current: None
     })
 }
\ No newline at end of file
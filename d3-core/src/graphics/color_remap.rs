@@ -0,0 +1,105 @@
+/*
+
+Color remapping
+
+Recolors a bitmap by brightness alone: each pixel's brightness picks an
+entry from a small palette, a ramp from black up through the wanted color to
+a washed out highlight. Shading and detail in the source survive while the
+hue is replaced, which is how a team game paints every ship's custom texture
+in its team's color.
+
+*/
+
+use super::{
+    bitmap::{Bitmap16, BitmapFormat},
+    color_conversion::{pixel_1555_to_32, pixel_4444_to_32},
+    ddgr_color,
+    generic_bitmap::GenericBitmap16,
+};
+
+/// Entries in a remap palette, one for each brightness step a 4444 pixel can show
+pub const REMAP_LEVELS: usize = 16;
+
+/// How far the brightest entries go from the color toward white
+const HIGHLIGHT_AMOUNT: f32 = 0.6;
+
+/// 4444 colors without alpha, darkest first
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RemapPalette {
+    levels: [u16; REMAP_LEVELS],
+}
+
+impl RemapPalette {
+    /// A ramp with `color` at middle brightness
+    pub fn tint(color: ddgr_color) -> Self {
+        let mut levels = [0u16; REMAP_LEVELS];
+
+        for (i, level) in levels.iter_mut().enumerate() {
+            let brightness = i as f32 / (REMAP_LEVELS - 1) as f32;
+
+            let channel = |shift: u32| {
+                let value = ((color >> shift) & 0xFF) as f32 / 255.0;
+
+                let value = if brightness <= 0.5 {
+                    value * brightness * 2.0
+                } else {
+                    value + (1.0 - value) * (brightness - 0.5) * 2.0 * HIGHLIGHT_AMOUNT
+                };
+
+                (value * 15.0).round() as u16
+            };
+
+            *level = (channel(16) << 8) | (channel(8) << 4) | channel(0);
+        }
+
+        Self { levels }
+    }
+
+    pub fn level(&self, index: usize) -> u16 {
+        self.levels[index.min(REMAP_LEVELS - 1)]
+    }
+
+    /// The palette entry for a 32 bit ARGB pixel, keeping its alpha
+    pub fn remap(&self, argb: u32) -> u16 {
+        let r = (argb >> 16) & 0xFF;
+        let g = (argb >> 8) & 0xFF;
+        let b = argb & 0xFF;
+        let brightness = (r * 30 + g * 59 + b * 11) / 100;
+
+        (((argb >> 28) as u16) << 12) | self.level(brightness as usize * REMAP_LEVELS / 256)
+    }
+}
+
+/// A 4444 copy of `source` put through `palette`
+pub fn remap_bitmap(source: &dyn Bitmap16, palette: &RemapPalette) -> GenericBitmap16 {
+    let to_32 = match source.format() {
+        BitmapFormat::Fmt1555 => pixel_1555_to_32,
+        BitmapFormat::Fmt4444 => pixel_4444_to_32,
+    };
+
+    let data = source.data().iter().map(|&pixel| palette.remap(to_32(pixel))).collect();
+
+    GenericBitmap16::new(data, source.width(), source.height())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphics::{GR_BLACK, GR_RED};
+
+    #[test]
+    fn color_remap_test() {
+        crate::test_common::setup();
+
+        let red = RemapPalette::tint(GR_RED);
+        assert_eq!(red.level(0), 0x000);
+        assert_eq!(red.level(REMAP_LEVELS - 1), 0xF99);
+        assert_eq!(RemapPalette::tint(GR_BLACK).level(REMAP_LEVELS / 2 - 1), 0x000);
+
+        // Brightness and alpha are kept, the hue becomes red
+        let source = GenericBitmap16::new(vec![0xF000, 0xF777, 0x8FFF, 0xF0F0], 2, 2);
+        let tinted = remap_bitmap(&source, &red);
+        assert_eq!(tinted.data(), &[0xF000, 0xFE00, 0x8F99, 0xFF22]);
+        assert_eq!((tinted.width(), tinted.height()), (2, 2));
+    }
+}
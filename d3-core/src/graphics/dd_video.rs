@@ -1,6 +1,13 @@
 /// For now this module is based on the "DirectDraw" video interface
 
-use crate::{game_client};
+use core::fmt;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::{common::{SharedMutRef, WeakSharedMutRef}, config::RenderSettings, game_client};
+
+use super::color_conversion;
 
 trait video_client {
     fn init(game_client: &dyn game_client::game_client, driver: &'static str);
@@ -14,4 +21,349 @@ trait video_client {
      void ddvid_UnlockFrameBuffer();
      */
     fn swap_buffers(&mut self);
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VideoMode {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: u32,
+}
+
+impl VideoMode {
+    pub fn new(width: usize, height: usize, bit_depth: u32) -> Self {
+        Self { width, height, bit_depth }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        if self.height == 0 { 0.0 } else { self.width as f32 / self.height as f32 }
+    }
+}
+
+impl fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.width, self.height, self.bit_depth)
+    }
+}
+
+/// What the platform side (window, surface or swapchain) has to provide
+pub trait VideoBackend {
+    /// Fullscreen modes the display supports
+    fn enumerate_modes(&self) -> Vec<VideoMode>;
+    fn set_mode(&mut self, mode: &VideoMode, windowed: bool) -> Result<()>;
+    fn set_vsync(&mut self, enabled: bool) -> Result<()>;
+    /// Gamma on the swapchain, false if there is none and the 16-bit path has to correct it in software
+    fn set_gamma(&mut self, gamma: f32) -> Result<bool>;
+}
+
+/// Systems that depend on the screen size (viewports, UI layout, render targets)
+pub trait ScreenResizeListener {
+    fn on_screen_resize(&mut self, width: usize, height: usize);
+}
+
+pub const MIN_GAMMA: f32 = 0.1;
+pub const MAX_GAMMA: f32 = 4.0;
+
+/// Software gamma ramp for 8-bit channels
+pub fn compute_gamma_table(gamma: f32) -> [u8; 256] {
+    let exponent = 1.0 / gamma.clamp(MIN_GAMMA, MAX_GAMMA);
+    let mut table = [0u8; 256];
+
+    for (i, v) in table.iter_mut().enumerate() {
+        *v = ((i as f32 / 255.0).powf(exponent) * 255.0).round() as u8;
+    }
+
+    table
+}
+
+/// Owns the display mode, vsync and gamma, and tells viewport-dependent systems when the screen changes size
+pub struct VideoManager<B: VideoBackend> {
+    backend: B,
+    modes: Vec<VideoMode>,
+    mode: Option<VideoMode>,
+    windowed: bool,
+    vsync: bool,
+    gamma: f32,
+    gamma_table: Option<Box<[u8; 256]>>,
+    listeners: Vec<WeakSharedMutRef<dyn ScreenResizeListener>>,
+}
+
+impl<B: VideoBackend> fmt::Debug for VideoManager<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VideoManager")
+            .field("mode", &self.mode)
+            .field("windowed", &self.windowed)
+            .field("vsync", &self.vsync)
+            .field("gamma", &self.gamma)
+            .field("software_gamma", &self.gamma_table.is_some())
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+impl<B: VideoBackend> VideoManager<B> {
+    pub fn new(backend: B) -> Self {
+        let mut video = Self {
+            backend,
+            modes: Vec::new(),
+            mode: None,
+            windowed: false,
+            vsync: true,
+            gamma: 1.0,
+            gamma_table: None,
+            listeners: Vec::new(),
+        };
+
+        video.refresh_modes();
+        video
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    /// Asks the backend for its modes again, sorted from smallest to largest
+    pub fn refresh_modes(&mut self) {
+        self.modes = self.backend.enumerate_modes();
+        self.modes.sort();
+        self.modes.dedup();
+    }
+
+    pub fn modes(&self) -> &[VideoMode] {
+        &self.modes
+    }
+
+    /// The current mode, None before a mode was set
+    pub fn mode(&self) -> Option<VideoMode> {
+        self.mode
+    }
+
+    pub fn is_windowed(&self) -> bool {
+        self.windowed
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.vsync
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    /// Ramp the 16-bit path applies itself, None when the backend does gamma in hardware
+    pub fn gamma_table(&self) -> Option<&[u8; 256]> {
+        self.gamma_table.as_deref()
+    }
+
+    /// Fullscreen mode of this size, with the closest bit depth
+    pub fn find_mode(&self, width: usize, height: usize, bit_depth: u32) -> Option<VideoMode> {
+        self.modes
+            .iter()
+            .filter(|m| m.width == width && m.height == height)
+            .min_by_key(|m| m.bit_depth.abs_diff(bit_depth))
+            .copied()
+    }
+
+    /// Windows can be any size, fullscreen has to be one of the enumerated modes
+    pub fn set_video_mode(&mut self, width: usize, height: usize, bit_depth: u32, windowed: bool) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Err(anyhow!("Invalid video mode {}x{}", width, height));
+        }
+
+        let mode = if windowed {
+            VideoMode::new(width, height, bit_depth)
+        }
+        else {
+            self.find_mode(width, height, bit_depth)
+                .ok_or_else(|| anyhow!("{}x{} is not a supported fullscreen mode", width, height))?
+        };
+
+        self.backend.set_mode(&mode, windowed)?;
+
+        let resized = self.mode.map_or(true, |m| m.width != mode.width || m.height != mode.height);
+
+        info!(target: "render", "video mode set to {} {}", mode, if windowed { "windowed" } else { "fullscreen" });
+
+        self.mode = Some(mode);
+        self.windowed = windowed;
+
+        if resized {
+            self.notify_resize(mode.width, mode.height);
+        }
+
+        Ok(())
+    }
+
+    /// Flips between fullscreen and windowed at the current size
+    pub fn toggle_fullscreen(&mut self) -> Result<()> {
+        let mode = self.mode.ok_or_else(|| anyhow!("No video mode has been set"))?;
+        self.set_video_mode(mode.width, mode.height, mode.bit_depth, !self.windowed)
+    }
+
+    pub fn set_vsync(&mut self, enabled: bool) -> Result<()> {
+        self.backend.set_vsync(enabled)?;
+        self.vsync = enabled;
+        Ok(())
+    }
+
+    pub fn set_gamma(&mut self, gamma: f32) -> Result<()> {
+        let gamma = gamma.clamp(MIN_GAMMA, MAX_GAMMA);
+
+        self.gamma_table = if self.backend.set_gamma(gamma)? {
+            None
+        }
+        else {
+            Some(Box::new(compute_gamma_table(gamma)))
+        };
+
+        // The 16-bit display conversions only correct what the swapchain doesn't
+        let brightness = color_conversion::display_gamma().brightness();
+        color_conversion::set_display_gamma(if self.gamma_table.is_some() { gamma } else { 1.0 }, brightness);
+
+        self.gamma = gamma;
+        Ok(())
+    }
+
+    /// Applies the [render] settings
+    pub fn apply_settings(&mut self, settings: &RenderSettings) -> Result<()> {
+        self.set_video_mode(settings.width as usize, settings.height as usize, settings.bit_depth, settings.windowed)?;
+        self.set_vsync(settings.vsync)?;
+        self.set_gamma(settings.gamma)
+    }
+
+    /// Listeners are held weakly, dropped ones are forgotten on the next resize
+    pub fn add_resize_listener<L: ScreenResizeListener + 'static>(&mut self, listener: &SharedMutRef<L>) {
+        let listener: SharedMutRef<dyn ScreenResizeListener> = listener.clone();
+        self.listeners.push(Rc::downgrade(&listener));
+    }
+
+    /// For when the window was resized by the user rather than through a mode change
+    pub fn on_window_resized(&mut self, width: usize, height: usize) {
+        if let Some(mode) = self.mode.as_mut() {
+            if mode.width == width && mode.height == height {
+                return;
+            }
+
+            mode.width = width;
+            mode.height = height;
+        }
+
+        self.notify_resize(width, height);
+    }
+
+    fn notify_resize(&mut self, width: usize, height: usize) {
+        self.listeners.retain(|l| l.strong_count() > 0);
+
+        for listener in self.listeners.iter().filter_map(|l| l.upgrade()) {
+            match listener.try_borrow_mut() {
+                Ok(mut l) => l.on_screen_resize(width, height),
+                Err(_) => warn!(target: "render", "screen resize listener is busy, it missed {}x{}", width, height),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::common::new_shared_mut_ref;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct TestBackend {
+        applied: Vec<(VideoMode, bool)>,
+        vsync: bool,
+        hardware_gamma: bool,
+    }
+
+    impl VideoBackend for TestBackend {
+        fn enumerate_modes(&self) -> Vec<VideoMode> {
+            vec![VideoMode::new(800, 600, 32), VideoMode::new(640, 480, 16), VideoMode::new(640, 480, 32), VideoMode::new(640, 480, 16)]
+        }
+
+        fn set_mode(&mut self, mode: &VideoMode, windowed: bool) -> Result<()> {
+            self.applied.push((*mode, windowed));
+            Ok(())
+        }
+
+        fn set_vsync(&mut self, enabled: bool) -> Result<()> {
+            self.vsync = enabled;
+            Ok(())
+        }
+
+        fn set_gamma(&mut self, _: f32) -> Result<bool> {
+            Ok(self.hardware_gamma)
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct Sizes(Vec<(usize, usize)>);
+
+    impl ScreenResizeListener for Sizes {
+        fn on_screen_resize(&mut self, width: usize, height: usize) {
+            self.0.push((width, height));
+        }
+    }
+
+    #[test]
+    fn video_mode_test() {
+        crate::test_common::setup();
+
+        let mut video = VideoManager::new(TestBackend::default());
+        assert_eq!(video.modes().len(), 3);
+        assert_eq!(video.modes()[0], VideoMode::new(640, 480, 16));
+        assert_eq!(video.find_mode(640, 480, 24), Some(VideoMode::new(640, 480, 16)));
+
+        let sizes = new_shared_mut_ref(Sizes::default());
+        video.add_resize_listener(&sizes);
+
+        // Only enumerated modes go fullscreen
+        assert!(video.set_video_mode(1024, 768, 16, false).is_err());
+        video.set_video_mode(1024, 768, 16, true).unwrap();
+        video.set_video_mode(800, 600, 32, false).unwrap();
+        assert!(!video.is_windowed());
+
+        // Same size, no resize
+        video.toggle_fullscreen().unwrap();
+        assert!(video.is_windowed());
+
+        video.on_window_resized(1280, 720);
+        video.on_window_resized(1280, 720);
+
+        assert_eq!(sizes.borrow().0, vec![(1024, 768), (800, 600), (1280, 720)]);
+        assert_eq!(video.backend().applied.len(), 3);
+        assert_eq!(video.mode(), Some(VideoMode::new(1280, 720, 32)));
+
+        // Dropped listeners are let go
+        drop(sizes);
+        video.on_window_resized(640, 480);
+    }
+
+    #[test]
+    fn video_gamma_test() {
+        crate::test_common::setup();
+
+        let table = compute_gamma_table(1.0);
+        assert!(table.iter().enumerate().all(|(i, &v)| v as usize == i));
+
+        let bright = compute_gamma_table(2.0);
+        assert_eq!((bright[0], bright[255]), (0, 255));
+        assert!(bright[64] > 64);
+
+        let mut video = VideoManager::new(TestBackend::default());
+        video.set_gamma(2.0).unwrap();
+        assert_eq!(video.gamma_table(), Some(&bright));
+
+        video.backend_mut().hardware_gamma = true;
+        video.set_gamma(10.0).unwrap();
+        assert!(video.gamma_table().is_none());
+        assert_eq!(video.gamma(), MAX_GAMMA);
+
+        video.set_vsync(false).unwrap();
+        assert!(!video.backend().vsync);
+    }
+}
@@ -39,6 +39,41 @@ macro_rules! bites_to_shorts {
 }
 
 
+#[derive(Debug)]
+pub enum FontError {
+    Io(std::io::Error),
+    BadMagic(u32),
+    UnknownFlags(u16),
+    InvalidAsciiRange { min: usize, max: usize },
+    /// Pixel data is shorter than the header says it should be
+    Truncated { expected: usize, found: usize },
+    CharOutOfRange { index: usize, min: usize, max: usize },
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FontError::Io(e) => write!(f, "error reading font: {}", e),
+            FontError::BadMagic(id) => write!(f, "magic in font not valid: {:#x}", id),
+            FontError::UnknownFlags(flags) => write!(f, "unknown font flags: {:#x}", flags),
+            FontError::InvalidAsciiRange { min, max } => write!(f, "invalid ascii range: {} to {}", min, max),
+            FontError::Truncated { expected, found } => write!(f, "pixel data is truncated, expected {} bytes but found {}", expected, found),
+            FontError::CharOutOfRange { index, min, max } => write!(f, "invalid char range for D3 font: char code: {}, max {}, min {}", index, max, min),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+impl From<std::io::Error> for FontError {
+    fn from(e: std::io::Error) -> Self {
+        FontError::Io(e)
+    }
+}
+
+/// Drawn in place of characters the font doesn't have when lenient
+const REPLACEMENT_CHAR: usize = b'?' as usize;
+
 pub struct Font2 {
     pub tracking: i16,
     pub reserved: [u8; 62],
@@ -80,6 +115,8 @@ pub struct Font {
     ffi2: Option<Font2>,
     /// this IS NOT in the file, but a part of the baseline element. (upper 8bits)
     brightness: f32,
+    /// Draw a replacement glyph for chars outside the ascii range instead of panicking
+    lenient: bool,
 }
 
 fn ascii_toupper(c: usize) -> usize {
@@ -187,18 +224,40 @@ impl Default for Font {
             char_widths: None, 
             kern_data: Default::default(), 
             ffi2: Default::default(), 
-            brightness: Default::default() 
+            brightness: Default::default(),
+            lenient: false,
         }
     }
 }
 
 impl Font {
-    fn resolve_char_index(&self, index:usize) -> usize {
+    pub fn try_resolve_char_index(&self, index: usize) -> Result<usize, FontError> {
         if self.min_ascii > index || self.max_ascii < index {
-            panic!("invalid char range for D3 font: char code: {}, max {}, min {}", index, self.max_ascii, self.min_ascii);
+            return Err(FontError::CharOutOfRange { index, min: self.min_ascii, max: self.max_ascii });
         }
 
-        index - self.min_ascii
+        Ok(index - self.min_ascii)
+    }
+
+    fn resolve_char_index(&self, index: usize) -> usize {
+        match self.try_resolve_char_index(index) {
+            Ok(i) => i,
+            Err(_) if self.lenient => self.replacement_index(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// '?' when the font has it, otherwise its first char
+    fn replacement_index(&self) -> usize {
+        self.try_resolve_char_index(REPLACEMENT_CHAR).unwrap_or(0)
+    }
+
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
     }
 
     pub(crate) fn get_raw_char_data(&self, raw_index: usize) -> &[u8] {
@@ -235,31 +294,38 @@ impl Font {
         }
     }
 
-    pub fn new_from_steam<R: Read + Seek>(name: String, reader: &mut BufReader<R>) -> Result<Self> {
+    pub fn new_from_steam<R: Read + Seek>(name: String, reader: &mut BufReader<R>) -> Result<Self, FontError> {
         let mut font = Font::default();
 
         /* verify the ID */
-        let id = reader.read_u32::<LittleEndian>().unwrap();
+        let id = reader.read_u32::<LittleEndian>()?;
 
         if id != 0xFEEDBABA {
-            return Err(anyhow!("magic in font not valid"));
+            return Err(FontError::BadMagic(id));
         }
 
         font.name = name;
-        font.width = reader.read_u16::<LittleEndian>().unwrap() as usize;
-        font.height = reader.read_u16::<LittleEndian>().unwrap() as usize;
-        font.flags = FontFlags::from_bits(reader.read_u16::<LittleEndian>().unwrap()).unwrap();
-        font.baseline = reader.read_u16::<LittleEndian>().unwrap() as i16;
-        font.min_ascii = reader.read_u8().unwrap() as usize;
-        font.max_ascii = reader.read_u8().unwrap() as usize;
+        font.width = reader.read_u16::<LittleEndian>()? as usize;
+        font.height = reader.read_u16::<LittleEndian>()? as usize;
+
+        let flags = reader.read_u16::<LittleEndian>()?;
+        font.flags = FontFlags::from_bits(flags).ok_or(FontError::UnknownFlags(flags))?;
+
+        font.baseline = reader.read_u16::<LittleEndian>()? as i16;
+        font.min_ascii = reader.read_u8()? as usize;
+        font.max_ascii = reader.read_u8()? as usize;
+
+        if font.max_ascii < font.min_ascii {
+            return Err(FontError::InvalidAsciiRange { min: font.min_ascii, max: font.max_ascii });
+        }
 
         /* Skip over embedded font name */
         let _ = reader.seek(std::io::SeekFrom::Current(32));
 
         if font.flags.contains(FontFlags::FFi2) {
             let mut ffi2 = Font2::default();
-            ffi2.tracking = reader.read_i16::<LittleEndian>().unwrap();
-            reader.read_exact(&mut ffi2.reserved).context("Failed to read reserved")?;
+            ffi2.tracking = reader.read_i16::<LittleEndian>()?;
+            reader.read_exact(&mut ffi2.reserved)?;
             font.ffi2 = Some(ffi2);
         }
 
@@ -275,7 +341,7 @@ impl Font {
             let mut widths = vec![0usize; num_chars as usize];
 
             for w in &mut widths {
-                *w = reader.read_i16::<LittleEndian>().unwrap() as u8 as usize;
+                *w = reader.read_i16::<LittleEndian>()? as u8 as usize;
             }
 
             font.char_widths = Some(widths);
@@ -283,14 +349,12 @@ impl Font {
 
         // TODO: Read in kerning data
         if font.flags.contains(FontFlags::Kerned) {
-            let num_pairs = reader.read_u16::<LittleEndian>().unwrap() as usize;
+            let num_pairs = reader.read_u16::<LittleEndian>()? as usize;
 
             let mut kern_data = vec![0u8; 3 * (num_pairs + 1)];
 
             for i in 0..num_pairs {
-                kern_data[i * 3 + 0] = reader.read_u8().unwrap();
-                kern_data[i * 3 + 1] = reader.read_u8().unwrap();
-                kern_data[i * 3 + 2] = reader.read_u8().unwrap();
+                reader.read_exact(&mut kern_data[i * 3..i * 3 + 3])?;
             }
 
             kern_data[num_pairs * 3] = 255;
@@ -306,11 +370,17 @@ impl Font {
         //	for mono fonts, read in byte count, then the data, convert to bits and store
         //		generate character data pointer table
 
-        let byte_size = reader.read_u32::<LittleEndian>().unwrap() as usize;
-        let mut raw_data = vec![0u8; byte_size];
+        let byte_size = reader.read_u32::<LittleEndian>()? as usize;
+        let mut raw_data = Vec::default();
         let mut char_data: Vec<Range<usize>> = Vec::default();
 
-        reader.read_exact(&mut raw_data).context("Failed to read raw_data")?;
+        // Read through take so a bogus size can't allocate more than the stream holds
+        reader.by_ref().take(byte_size as u64).read_to_end(&mut raw_data)?;
+
+        if raw_data.len() != byte_size {
+            return Err(FontError::Truncated { expected: byte_size, found: raw_data.len() });
+        }
+
         font.raw_data = raw_data;
 
 
@@ -346,6 +416,10 @@ impl Font {
             }
         }
 
+        if let Some(last) = char_data.last().filter(|r| r.end > font.raw_data.len()) {
+            return Err(FontError::Truncated { expected: last.end, found: font.raw_data.len() });
+        }
+
         font.char_data = char_data;
   
         Ok(font)
@@ -476,11 +550,7 @@ impl FontGraphic {
     }
 
     fn resolve_ascii_range(&self, index: usize) -> usize {
-        if self.font.min_ascii > index || self.font.max_ascii < index {
-            panic!("char out of supported ASCII range");
-        }
-
-        index - self.font.min_ascii
+        self.font.resolve_char_index(index)
     }
 
     pub fn get_char_tex_source(&self, index: usize) -> CharBitmapTexSrc {
@@ -620,7 +690,7 @@ impl FontGraphic {
         /* Ensure the last one gets pushed too */
         self.char_bitmaps.push(Rc::clone(&bitmap));
 
-        trace!("Font to bitmap mapping: {} bimaps generated(s)", self.char_bitmaps.len());
+        trace!(target: "render", "Font to bitmap mapping: {} bimaps generated(s)", self.char_bitmaps.len());
 
         Ok(())
     }
@@ -662,20 +732,9 @@ impl Bitmap16 for FontBitmap16 {
         self.format
     }
 
-    fn make_funny(&mut self) {
-        match self.format() {
-            BitmapFormat::Fmt4444 => {
-                for i in 0..self.width() * 2 {
-                    self.data[i] = crate::graphics::bitmap::generate_random_color_4444();
-                }
-            },
-            BitmapFormat::Fmt1555 => {
-                for i in 0..self.height() * 2 {
-                    self.data[i] = crate::graphics::bitmap::generate_random_color_1555();
-                }
-            },
-            _ => {}
-        }
+    fn fill_debug_pattern(&mut self, kind: crate::graphics::bitmap::DebugPattern) {
+        let (width, height) = (self.width(), self.height());
+        crate::graphics::bitmap::write_debug_pattern(&mut self.data, width, height, self.format, kind);
     }
 }
 
@@ -939,3 +998,81 @@ impl FontGlyph {
 
 // TODO: void grfont_Spew(int font, int x, int y)
 // TODO: int grfont_KeyToAscii(int font, int key)
+
+#[cfg(test)]
+pub mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use super::*;
+
+    /// Monospaced mono font covering '0' to '?', 4x2 pixels a char
+    fn test_font_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xFEEDBABAu32.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.push(b'0');
+        data.push(b'?');
+        data.extend_from_slice(&[0u8; 32]);
+
+        let num_chars = (b'?' - b'0' + 1) as usize;
+        let byte_size = num_chars * bits_to_bytes!(4) * 2;
+        data.extend_from_slice(&(byte_size as u32).to_le_bytes());
+        data.extend((0..byte_size).map(|i| i as u8));
+
+        data
+    }
+
+    fn load(data: &[u8]) -> Result<Font, FontError> {
+        Font::new_from_steam("test".to_string(), &mut BufReader::new(Cursor::new(data)))
+    }
+
+    #[test]
+    fn font_load_errors_test() {
+        crate::test_common::setup();
+
+        let data = test_font_data();
+        let font = load(&data).unwrap();
+        assert_eq!(font.get_height(), 2);
+        assert_eq!(font.get_char_data(b'1' as usize), &[2, 3]);
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = 0;
+        assert!(matches!(load(&bad_magic), Err(FontError::BadMagic(_))));
+
+        let mut bad_flags = data.clone();
+        bad_flags[8..10].copy_from_slice(&0x100u16.to_le_bytes());
+        assert!(matches!(load(&bad_flags), Err(FontError::UnknownFlags(0x100))));
+
+        let mut bad_range = data.clone();
+        bad_range[12] = b'z';
+        assert!(matches!(load(&bad_range), Err(FontError::InvalidAsciiRange { .. })));
+
+        assert!(matches!(load(&data[..data.len() - 1]), Err(FontError::Truncated { .. })));
+        assert!(matches!(load(&data[..20]), Err(FontError::Io(_))));
+    }
+
+    #[test]
+    fn font_lenient_test() {
+        crate::test_common::setup();
+
+        let mut font = load(&test_font_data()).unwrap();
+
+        assert!(matches!(font.try_resolve_char_index(b'a' as usize), Err(FontError::CharOutOfRange { .. })));
+
+        font.set_lenient(true);
+        assert_eq!(font.get_char_data(b'a' as usize), font.get_char_data(b'?' as usize));
+        assert_eq!(font.get_char_width(b'a' as usize), 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn font_strict_out_of_range_test() {
+        crate::test_common::setup();
+
+        let font = load(&test_font_data()).unwrap();
+        font.get_char_data(b'a' as usize);
+    }
+}
@@ -210,6 +210,15 @@ impl Default for RenderedTextBuf {
 }
 
 impl RenderedTextBuf {
+    /// An empty buffer drawing with `font`, clipped to `clip`
+    pub(crate) fn new(font: Rc<FontGraphic>, clip: RenderedTextRect) -> Self {
+        Self {
+            font: Some(font),
+            clip,
+            ..Default::default()
+        }
+    }
+
     fn flush<T: Renderer>(&mut self, renderer: &mut T) {
         self.render(renderer);
         self.formatted_text.clear();
@@ -321,7 +330,7 @@ impl RenderedTextBuf {
         &self.clip
     }
 
-    fn append_color(&mut self, color: ddgr_color) {
+    pub(crate) fn append_color(&mut self, color: ddgr_color) {
         self.formatted_text.push(TextOpcodes::SetColor(color));
         self.color = color;
     }
@@ -339,7 +348,7 @@ impl RenderedTextBuf {
         self.formatted_text.push(TextOpcodes::FancyColor(c1))
     }
 
-    fn set_alpha(&mut self, alpha: u8) {
+    pub(crate) fn set_alpha(&mut self, alpha: u8) {
         self.formatted_text.push(TextOpcodes::SetAlpha(alpha));
     }
 
@@ -361,7 +370,7 @@ impl RenderedTextBuf {
         &self.font
     }
 
-    fn append_text(&mut self, text: D3String, x: usize, y: usize) {
+    pub(crate) fn append_text(&mut self, text: D3String, x: usize, y: usize) {
         self.formatted_text.push(TextOpcodes::Text { x: x, y: y, text: text});
     }
 
@@ -745,6 +754,14 @@ pub mod tests {
         fn get_projection_screen_rect(&self) -> crate::graphics::drawing_3d::ScreenViewPort {
             todo!()
         }
+
+        fn set_texture(&mut self, texture: Option<crate::graphics::TextureHandle>) {
+
+        }
+
+        fn draw_polygon(&mut self, vertices: &[crate::graphics::rendering::RenderVertex]) {
+
+        }
     }
 
     #[test]
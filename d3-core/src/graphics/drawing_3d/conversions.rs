@@ -1,18 +1,114 @@
-use vek::{Mat4, Vec3, Vec4};
+/*
+
+Interop with vek (and through it, euc)
+
+Matrix4 and Vector4 are already vek types, so these cover the d3 types that
+aren't. Points go over as homogeneous positions (w = 1) using the
+transformed position of a Point3, which is what the renderers draw. Matrices
+are rows of right, up and forward, matching vek's row-major constructors.
+
+The euc pipelines in the playbox use (position, color) tuples as their
+vertex format, those convert straight from and to a Point3 with the light
+values as the color.
+
+*/
+
+use vek::{Mat3, Mat4, Rgba, Vec2, Vec3, Vec4};
 
 use crate::{graphics::drawing_3d::Point3, math::{matrix::Matrix, vector::Vector}};
 
+/// Vertex format of the euc pipelines, a clip space position and a color
+pub type EucColorVertex = (Vec4<f32>, Rgba<f32>);
+
+/// Vertex format for textured euc pipelines, a clip space position and uv
+pub type EucTexturedVertex = (Vec4<f32>, Vec2<f32>);
+
 impl From<Point3> for Vec4<f32> {
     fn from(value: Point3) -> Self {
         Vec4 {
             x: value.x(),
-            y: value.y(), 
+            y: value.y(),
             z: value.z(),
             w: 1.0,
         }
     }
 }
 
+/// Drops w, use `Point3::from` first for positions that still need the divide
+impl From<Vec4<f32>> for Point3 {
+    fn from(value: Vec4<f32>) -> Self {
+        Point3::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<Point3> for Vec3<f32> {
+    fn from(value: Point3) -> Self {
+        Vec3::new(value.x(), value.y(), value.z())
+    }
+}
+
+impl From<Vec3<f32>> for Point3 {
+    fn from(value: Vec3<f32>) -> Self {
+        Point3::new(value.x, value.y, value.z)
+    }
+}
+
+/// The point's light color, or its intensity as grey when the colors were never set
+impl From<Point3> for Rgba<f32> {
+    fn from(value: Point3) -> Self {
+        let uvl = &value.uvl;
+
+        if uvl.light_r == 0.0 && uvl.light_g == 0.0 && uvl.light_b == 0.0 {
+            Rgba::new(uvl.light_intensity, uvl.light_intensity, uvl.light_intensity, 1.0)
+        } else {
+            Rgba::new(uvl.light_r, uvl.light_g, uvl.light_b, uvl.light_a)
+        }
+    }
+}
+
+impl From<Point3> for Vec2<f32> {
+    fn from(value: Point3) -> Self {
+        Vec2::new(value.u(), value.v())
+    }
+}
+
+impl From<Point3> for EucColorVertex {
+    fn from(value: Point3) -> Self {
+        (value.into(), value.into())
+    }
+}
+
+impl From<EucColorVertex> for Point3 {
+    fn from(value: EucColorVertex) -> Self {
+        let (position, color) = value;
+        let mut p = Point3::from(position);
+
+        p.uvl.light_r = color.r;
+        p.uvl.light_g = color.g;
+        p.uvl.light_b = color.b;
+        p.uvl.light_a = color.a;
+        p.uvl.light_intensity = (color.r + color.g + color.b) / 3.0;
+        p
+    }
+}
+
+impl From<Point3> for EucTexturedVertex {
+    fn from(value: Point3) -> Self {
+        (value.into(), value.into())
+    }
+}
+
+impl From<EucTexturedVertex> for Point3 {
+    fn from(value: EucTexturedVertex) -> Self {
+        let (position, uv) = value;
+        let mut p = Point3::from(position);
+
+        p.set_u(uv.x);
+        p.set_v(uv.y);
+        p
+    }
+}
+
 impl From<Mat4<f32>> for Matrix {
     fn from(value: Mat4<f32>) -> Self {
         let rows = value.into_row_arrays();
@@ -31,6 +127,26 @@ impl From<Vec3<f32>> for Vector {
     }
 }
 
+impl From<Vector> for Vec3<f32> {
+    fn from(value: Vector) -> Self {
+        Vec3::new(value.x, value.y, value.z)
+    }
+}
+
+/// As a position, w = 1
+impl From<Vector> for Vec4<f32> {
+    fn from(value: Vector) -> Self {
+        Vec4::new(value.x, value.y, value.z, 1.0)
+    }
+}
+
+/// Drops w without dividing by it
+impl From<Vec4<f32>> for Vector {
+    fn from(value: Vec4<f32>) -> Self {
+        Vector { x: value.x, y: value.y, z: value.z }
+    }
+}
+
 impl From<Matrix> for Mat4<f32> {
     fn from(value: Matrix) -> Self {
         Mat4::<f32>::new(
@@ -40,4 +156,26 @@ impl From<Matrix> for Mat4<f32> {
             0.0, 0.0, 0.0, 1.0, // identity W column (no translation)
         )
     }
-}
\ No newline at end of file
+}
+
+impl From<Matrix> for Mat3<f32> {
+    fn from(value: Matrix) -> Self {
+        Mat3::<f32>::new(
+            value.right.x, value.right.y, value.right.z,
+            value.up.x,    value.up.y,    value.up.z,
+            value.forward.x, value.forward.y, value.forward.z,
+        )
+    }
+}
+
+impl From<Mat3<f32>> for Matrix {
+    fn from(value: Mat3<f32>) -> Self {
+        let rows = value.into_row_arrays();
+
+        Matrix {
+            right:   Vector { x: rows[0][0], y: rows[0][1], z: rows[0][2] },
+            up:      Vector { x: rows[1][0], y: rows[1][1], z: rows[1][2] },
+            forward: Vector { x: rows[2][0], y: rows[2][1], z: rows[2][2] },
+        }
+    }
+}
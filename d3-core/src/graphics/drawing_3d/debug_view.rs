@@ -0,0 +1,336 @@
+/*
+
+Debug visualization modes
+
+DebugRenderer sits between the pipeline and whichever backend is drawing,
+rewriting polygons on their way through so every backend gets the same
+modes without knowing about them:
+
+ - Wireframe draws the edges of every polygon as thin flat quads
+ - LightmapOnly swaps base textures for white so only lighting shows
+ - MipTint colors polygons by the mip level their texel density would pick
+ - Overdraw adds a little heat for every polygon, bright spots get drawn over many times
+
+*/
+
+use std::collections::HashMap;
+
+use crate::graphics::{
+    ddgr_color,
+    drawing_2d::font::{FontGlyph, FontGraphic},
+    rendering::{AlphaType, ColorModelType, LightStateType, OverlayTextureType, RenderVertex, Renderer, TextureType},
+    TextureHandle, GR_WHITE,
+};
+
+use super::ScreenViewPort;
+
+/// Most level textures are 128x128, used for textures with no size registered
+pub const DEFAULT_TEXTURE_SIZE: (usize, usize) = (128, 128);
+
+pub const MAX_DEBUG_MIP_LEVEL: usize = 4;
+
+/// How wide wireframe edges are drawn, in pixels
+pub const WIREFRAME_WIDTH: f32 = 1.0;
+
+/// What every overdrawn polygon adds
+pub const OVERDRAW_HEAT: f32 = 0.08;
+
+/// Tints for mip levels 0 to 4, from full size to smallest
+const MIP_TINTS: [(f32, f32, f32); MAX_DEBUG_MIP_LEVEL + 1] = [
+    (1.0, 1.0, 1.0),
+    (0.3, 0.3, 1.0),
+    (0.3, 1.0, 0.3),
+    (1.0, 1.0, 0.3),
+    (1.0, 0.3, 0.3),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DebugViewMode {
+    #[default]
+    Normal,
+    Wireframe,
+    LightmapOnly,
+    MipTint,
+    Overdraw,
+}
+
+impl DebugViewMode {
+    /// The next mode, for a key that cycles through them
+    pub fn next(self) -> Self {
+        match self {
+            DebugViewMode::Normal => DebugViewMode::Wireframe,
+            DebugViewMode::Wireframe => DebugViewMode::LightmapOnly,
+            DebugViewMode::LightmapOnly => DebugViewMode::MipTint,
+            DebugViewMode::MipTint => DebugViewMode::Overdraw,
+            DebugViewMode::Overdraw => DebugViewMode::Normal,
+        }
+    }
+}
+
+/// The mip level a polygon would use, from how many texels land on each pixel
+pub fn mip_level(vertices: &[RenderVertex], texture_size: (usize, usize)) -> usize {
+    let screen_area = polygon_area(vertices.iter().map(|v| (v.screen_x, v.screen_y)));
+    let uv_area = polygon_area(vertices.iter().map(|v| (v.u, v.v)));
+
+    if screen_area <= f32::EPSILON {
+        return MAX_DEBUG_MIP_LEVEL;
+    }
+
+    let texels = uv_area * (texture_size.0 * texture_size.1) as f32;
+
+    // Each level halves both sides, so quarters the texels per pixel
+    let density = (texels / screen_area).max(1.0);
+    ((density.log2() / 2.0) as usize).min(MAX_DEBUG_MIP_LEVEL)
+}
+
+fn polygon_area(points: impl Iterator<Item = (f32, f32)> + Clone) -> f32 {
+    let next = points.clone().cycle().skip(1);
+    let twice: f32 = points.zip(next).map(|((x0, y0), (x1, y1))| x0 * y1 - x1 * y0).sum();
+
+    twice.abs() * 0.5
+}
+
+/// Wraps a backend, rewriting what's drawn for the current debug mode
+pub struct DebugRenderer<R: Renderer> {
+    inner: R,
+    mode: DebugViewMode,
+    texture: Option<TextureHandle>,
+    texture_sizes: HashMap<TextureHandle, (usize, usize)>,
+    /// Polygons drawn since the frame started
+    polygons: usize,
+    /// A batch was handed on to the backend untouched
+    passthrough_batch: bool,
+}
+
+impl<R: Renderer> DebugRenderer<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            mode: DebugViewMode::Normal,
+            texture: None,
+            texture_sizes: HashMap::new(),
+            polygons: 0,
+            passthrough_batch: false,
+        }
+    }
+
+    pub fn mode(&self) -> DebugViewMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DebugViewMode) {
+        self.mode = mode;
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Lets mip tinting work out the density of a texture that isn't the default size
+    pub fn set_texture_size(&mut self, texture: TextureHandle, width: usize, height: usize) {
+        self.texture_sizes.insert(texture, (width, height));
+    }
+
+    pub fn polygon_count(&self) -> usize {
+        self.polygons
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.polygons = 0;
+    }
+
+    fn draw_wireframe(&mut self, vertices: &[RenderVertex]) {
+        self.inner.set_texture(None);
+        self.inner.set_flat_color(GR_WHITE);
+        self.inner.set_alpha_type(AlphaType::ALWAYS);
+
+        let half = WIREFRAME_WIDTH * 0.5;
+
+        for (i, a) in vertices.iter().enumerate() {
+            let b = &vertices[(i + 1) % vertices.len()];
+
+            let (dx, dy) = (b.screen_x - a.screen_x, b.screen_y - a.screen_y);
+            let length = (dx * dx + dy * dy).sqrt();
+
+            if length <= f32::EPSILON {
+                continue;
+            }
+
+            // Pushed out sideways from the edge by half the width each way
+            let (nx, ny) = (-dy / length * half, dx / length * half);
+            let corner = |v: &RenderVertex, side: f32| RenderVertex {
+                screen_x: v.screen_x + nx * side,
+                screen_y: v.screen_y + ny * side,
+                r: 1.0,
+                g: 1.0,
+                b: 1.0,
+                a: 1.0,
+                ..*v
+            };
+
+            self.inner.draw_polygon(&[corner(a, 1.0), corner(b, 1.0), corner(b, -1.0), corner(a, -1.0)]);
+        }
+    }
+
+    fn draw_tinted(&mut self, vertices: &[RenderVertex], tint: (f32, f32, f32)) {
+        let tinted: Vec<RenderVertex> = vertices
+            .iter()
+            .map(|v| RenderVertex {
+                r: v.r * tint.0,
+                g: v.g * tint.1,
+                b: v.b * tint.2,
+                ..*v
+            })
+            .collect();
+
+        self.inner.draw_polygon(&tinted);
+    }
+
+    fn draw_overdraw(&mut self, vertices: &[RenderVertex]) {
+        self.inner.set_texture(None);
+        self.inner.set_alpha_type(AlphaType::SATURATE_VERTEX);
+
+        let heat: Vec<RenderVertex> = vertices
+            .iter()
+            .map(|v| RenderVertex {
+                r: OVERDRAW_HEAT,
+                g: OVERDRAW_HEAT * 0.5,
+                b: 0.0,
+                a: 1.0,
+                ..*v
+            })
+            .collect();
+
+        self.inner.draw_polygon(&heat);
+    }
+}
+
+impl<R: Renderer> Renderer for DebugRenderer<R> {
+    fn set_flat_color(&mut self, color: ddgr_color) {
+        self.inner.set_flat_color(color);
+    }
+
+    fn draw_font_char(&mut self, font_graphic: &FontGraphic, glyph: &FontGlyph) {
+        self.inner.draw_font_char(font_graphic, glyph);
+    }
+
+    fn set_texture_type(&mut self, texture_type: TextureType) {
+        self.inner.set_texture_type(texture_type);
+    }
+
+    fn set_overlay_type(&mut self, overlay_type: OverlayTextureType) {
+        self.inner.set_overlay_type(overlay_type);
+    }
+
+    fn set_filtering(&mut self, state: i8) {
+        self.inner.set_filtering(state);
+    }
+
+    fn set_lighting(&mut self, state: LightStateType) {
+        self.inner.set_lighting(state);
+    }
+
+    fn set_alpha_type(&mut self, state: AlphaType) {
+        self.inner.set_alpha_type(state);
+    }
+
+    fn set_color_model(&mut self, state: ColorModelType) {
+        self.inner.set_color_model(state);
+    }
+
+    fn set_zbuffer_state(&mut self, state: i8) {
+        self.inner.set_zbuffer_state(state);
+    }
+
+    fn set_alpha_value(&mut self, value: u8) {
+        self.inner.set_alpha_value(value);
+    }
+
+    fn get_projection_screen_rect(&self) -> ScreenViewPort {
+        self.inner.get_projection_screen_rect()
+    }
+
+    fn set_texture(&mut self, texture: Option<TextureHandle>) {
+        self.texture = texture;
+
+        // Lightmaps still go through so the lighting is all that's left
+        let texture = match (self.mode, texture) {
+            (DebugViewMode::LightmapOnly, Some(TextureHandle::Bitmap(_))) => None,
+            _ => texture,
+        };
+
+        self.inner.set_texture(texture);
+    }
+
+    fn draw_polygon(&mut self, vertices: &[RenderVertex]) {
+        if vertices.len() < 3 {
+            return;
+        }
+
+        self.polygons += 1;
+
+        match self.mode {
+            DebugViewMode::Normal => self.inner.draw_polygon(vertices),
+            DebugViewMode::Wireframe => self.draw_wireframe(vertices),
+            DebugViewMode::LightmapOnly => {
+                if matches!(self.texture, Some(TextureHandle::Bitmap(_))) {
+                    self.inner.set_flat_color(GR_WHITE);
+                }
+
+                self.inner.draw_polygon(vertices);
+            }
+            DebugViewMode::MipTint => {
+                let tint = match self.texture {
+                    Some(texture) => {
+                        let size = self.texture_sizes.get(&texture).copied().unwrap_or(DEFAULT_TEXTURE_SIZE);
+                        MIP_TINTS[mip_level(vertices, size)]
+                    }
+                    None => MIP_TINTS[0],
+                };
+
+                self.draw_tinted(vertices, tint);
+            }
+            DebugViewMode::Overdraw => self.draw_overdraw(vertices),
+        }
+    }
+
+    fn begin_batch(&mut self, texture: Option<TextureHandle>, alpha: AlphaType) {
+        // Only a normal view keeps the backend's batching, the rest rewrite each triangle
+        self.passthrough_batch = self.mode == DebugViewMode::Normal;
+
+        if self.passthrough_batch {
+            self.texture = texture;
+            self.inner.begin_batch(texture, alpha);
+        } else {
+            self.set_texture(texture);
+            self.inner.set_alpha_type(alpha);
+        }
+    }
+
+    fn push_vertices(&mut self, vertices: &[RenderVertex]) {
+        if self.passthrough_batch {
+            self.polygons += vertices.len() / 3;
+            self.inner.push_vertices(vertices);
+            return;
+        }
+
+        for triangle in vertices.chunks_exact(3) {
+            self.draw_polygon(triangle);
+        }
+    }
+
+    fn end_batch(&mut self) {
+        if self.passthrough_batch {
+            self.inner.end_batch();
+            self.passthrough_batch = false;
+        }
+    }
+}
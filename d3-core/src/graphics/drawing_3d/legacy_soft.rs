@@ -10,7 +10,7 @@ use crate::math::{
     vector::{Vector, Vector4},
 };
 
-use super::{Camera, ClippingCode, CustomClip, Point3, PointFlags, RenderSetupState, ScreenViewPort};
+use super::{projection::Projection, Camera, ClippingCode, CustomClipStack, Point3, PointFlags, RenderSetupState, ScreenViewPort};
 
 #[derive(Debug, Clone)]
 pub struct Transformation {
@@ -110,7 +110,7 @@ impl TransformPipeline {
 }
 
 mod math {
-    use crate::{graphics::drawing_3d::ScreenViewPort, math::{matrix::{Matrix, Matrix4}, vector::Vector, DotProduct}};
+    use crate::{graphics::drawing_3d::{projection::Projection, ScreenViewPort}, math::{matrix::{Matrix, Matrix4}, vector::Vector, DotProduct}};
 
     pub fn compute_viewport_matrix(viewport: &ScreenViewPort) -> Matrix4 {
         let w2 = viewport.width as f32 * 0.5;
@@ -123,12 +123,9 @@ mod math {
         )
     }
 
-    pub fn compute_projection_matrix(viewport: &ScreenViewPort, zoom: f32) -> Matrix4 {
-        let s = viewport.aspect * viewport.height as f32 / viewport.width as f32;
-
-        // calc 1/tan(fov) which is the focal length
-        let f = 1.0 / zoom;
-        let fs = f * s;
+    pub fn compute_projection_matrix(projection: &Projection, viewport: &ScreenViewPort, zoom: f32) -> Matrix4 {
+        // 1/tan(fov) for each axis, which is the focal length
+        let (f, fs) = projection.focal_lengths(viewport, zoom);
 
         Matrix4::new(
             f, 0.0, 0.0, 0.0, 0.0, fs, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, -1.0, 0.0,
@@ -164,6 +161,7 @@ mod math {
 pub struct SoftRenderSetup {
     pub aspect_override: Option<f32>, // user override stored as w/h
     pub aspect: f32,
+    pub projection: Projection,
     pub window_width: usize,
     pub window_height: usize,
 
@@ -175,9 +173,26 @@ pub struct SoftRenderSetup {
     pub xform_pipeline: TransformPipeline,
     pub xform: Matrix4,
 
-    pub clipper_plane_point: Vector,
     pub clipper_far_z: f32,
-    pub clipper_custom: Option<CustomClip>,
+    pub clipper_custom: CustomClipStack,
+}
+
+impl Default for SoftRenderSetup {
+    fn default() -> Self {
+        Self {
+            aspect_override: None,
+            aspect: 0.0,
+            projection: Projection::default(),
+            window_width: 0,
+            window_height: 0,
+            window_width_2: 0.0,
+            window_height_2: 0.0,
+            xform_pipeline: Default::default(),
+            xform: Matrix4::identity(),
+            clipper_far_z: f32::MAX,
+            clipper_custom: CustomClipStack::default(),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -276,6 +291,17 @@ impl SoftRenderSetup {
         }
     }
 
+    /// The area drawn into this frame, inside the letterbox and with the aspect the projection uses
+    pub fn view_area(&self, viewport: &ScreenViewPort) -> ScreenViewPort {
+        let mut area = *viewport;
+
+        if self.aspect > 0.0 {
+            area.aspect = self._get_aspect_ratio();
+        }
+
+        self.projection.letterboxed(&area)
+    }
+
     fn compute_point_attributes(
         off_point: &Point3,
         on_point: &Point3,
@@ -375,7 +401,7 @@ impl SoftRenderSetup {
                 .clipping_codes
                 .contains(clip_code)
             {
-                trace!("Found vertex point with clip code");
+                trace!(target: "render", "Found vertex point with clip code");
 
                 if !clipping_pointlist
                     .get_point_ref(prev)
@@ -384,7 +410,7 @@ impl SoftRenderSetup {
                 {
                     let mut on = ClipperPoint3Index::Original(prev);
 
-                    trace!("prev point does not have {:?} set", clip_code);
+                    trace!(target: "render", "prev point does not have {:?} set", clip_code);
 
                     temp_1 =
                         Some(self.clipper_clip_edge(clip_code, &mut clipping_pointlist, &on, &off));
@@ -398,7 +424,7 @@ impl SoftRenderSetup {
                 {
                     let mut on = ClipperPoint3Index::Original(next);
 
-                    trace!("next point does not have {:?} set", clip_code);
+                    trace!(target: "render", "next point does not have {:?} set", clip_code);
 
                     temp_2 =
                         Some(self.clipper_clip_edge(clip_code, &mut clipping_pointlist, &on, &off));
@@ -481,8 +507,10 @@ impl SoftRenderSetup {
             return self.clipper_clip_far_edge(pointlist, on_point, off_point);
         }
 
-        if clip_code.contains(ClippingCode::OFF_CUSTOM) && self.clipper_custom.is_some() {
-            return self.clipper_clip_custom_edge(pointlist, on_point, off_point);
+        if let Some(slot) = clip_code.custom_slot() {
+            if self.clipper_custom.is_enabled(slot) {
+                return self.clipper_clip_custom_edge(slot, pointlist, on_point, off_point);
+            }
         }
 
         let (mut a, mut b) = if clip_code.contains(ClippingCode::OFF_RIGHT | ClippingCode::OFF_LEFT)
@@ -539,9 +567,10 @@ impl SoftRenderSetup {
         point
     }
 
-    // Clips an edge against the far plane
+    // Clips an edge against one of the custom planes
     fn clipper_clip_custom_edge(
         &mut self,
+        slot: usize,
         pointlist: &mut ClipperPointList,
         on_point: &ClipperPoint3Index,
         off_point: &ClipperPoint3Index,
@@ -560,23 +589,13 @@ impl SoftRenderSetup {
             )
         };
 
-        let mut ray_direction = off.transform - on.transform;
-        ray_direction.x /= self.xform_pipeline.view.scale.x;
-        ray_direction.y /= self.xform_pipeline.view.scale.y;
-        ray_direction.z /= self.xform_pipeline.view.scale.z;
+        let clip = *self.clipper_custom.get(slot).unwrap();
 
-        let den = -(self.clipper_plane_point * ray_direction);
+        // Where the edge crosses the plane, from the on side
+        let d_on = clip.distance(&on.transform);
+        let den = d_on - clip.distance(&off.transform);
 
-        let k = if den == 0.0 {
-            1.0
-        } else {
-            let mut w = on.transform - self.clipper_plane_point;
-            w.x /= self.xform_pipeline.view.scale.x;
-            w.y /= self.xform_pipeline.view.scale.y;
-            w.z /= self.xform_pipeline.view.scale.z;
-
-            (self.clipper_plane_point * w) / den
-        };
+        let k = if den == 0.0 { 1.0 } else { d_on / den };
 
         let mut point = pointlist.get_temp_point();
         let p = pointlist.get_point_mut_ref(point.into());
@@ -658,15 +677,28 @@ impl RenderSetupState for SoftRenderSetup {
         self.clipper_far_z = value;
     }
 
-    // #[inline]
-    // fn compute_point_transform(&self, point: &Vector4, m: &Matrix4) -> Vector4 {
-    //     *m * *point
-    // }
+    fn compute_viewport_matrix(&self, viewport: &ScreenViewPort) -> Matrix4 {
+        math::compute_viewport_matrix(viewport)
+    }
 
-    // #[inline]
-    // fn compute_matrix_product(&self, a: &Matrix4, b: &Matrix4) -> Matrix4 {
-    //     *a * *b
-    // }
+    fn compute_projection_matrix(&self, viewport: &ScreenViewPort, zoom: f32) -> Matrix4 {
+        math::compute_projection_matrix(&self.projection, viewport, zoom)
+    }
+
+    fn compute_viewmodel_matrix(&self, view: &Camera) -> Matrix4 {
+        math::compute_viewmodel_matrix(&view.position, &view.orientation)
+    }
+
+    /// The matrices are laid out for row vectors, so the point goes on the left
+    #[inline]
+    fn compute_point_transform(&self, point: &Vector4, m: &Matrix4) -> Vector4 {
+        *point * *m
+    }
+
+    #[inline]
+    fn compute_matrix_product(&self, a: &Matrix4, b: &Matrix4) -> Matrix4 {
+        *a * *b
+    }
 
     #[inline]
     fn update_transforms(&mut self) {
@@ -676,7 +708,7 @@ impl RenderSetupState for SoftRenderSetup {
     fn on_frame_start(&mut self, viewport: &ScreenViewPort, view: &Camera) {
         // self.xform_pipeline.viewport = math::compute_viewport_matrix(viewport);
         // self.xform_pipeline.projection = math::compute_projection_matrix(viewport, view.zoom);
-        let mv = math::compute_viewmodel_matrix(&self.xform_pipeline.view.position, &self.xform_pipeline.view.orientation);
+        let mv = self.compute_viewmodel_matrix(&self.xform_pipeline.view);
 
         self.xform_pipeline.modelview_stack.push(Transformation {
             last_view: view.to_owned(),
@@ -686,47 +718,22 @@ impl RenderSetupState for SoftRenderSetup {
 
         self.xform = self.xform_pipeline.compute_final_transform();
 
-        // Setup projection
-        let window_w2 = viewport.width as f32 * 0.5;
-        let window_h2 = viewport.height as f32 * 0.5;
-
-        // Compute aspect ratio for this window
-        let aspect = self._get_aspect_ratio();
-        let s = aspect * viewport.height as f32 / viewport.width as f32;
-
-        // XXX: Should this have been 1.0f?
-        if s <= 0.0 {
-            // Scale X
-            self.xform_pipeline.view.scale.x = s;
-            self.xform_pipeline.view.scale.y = 1.0;
-        } else {
-            self.xform_pipeline.view.scale.x = 1.0;
-            self.xform_pipeline.view.scale.y = 1.0 / s;
-        }
+        // Setup projection, inside the letterbox if there is one
+        let area = self.view_area(viewport);
 
-        self.xform_pipeline.view.scale.z = 1.0;
+        self.window_width = area.width;
+        self.window_height = area.height;
+        self.window_width_2 = area.width as f32 * 0.5;
+        self.window_height_2 = area.height as f32 * 0.5;
 
         // setup view
         self.xform_pipeline.view.position = view.position.to_owned();
         self.xform_pipeline.view.zoom = view.zoom;
         self.xform_pipeline.view.orientation = view.orientation.to_owned();
+        self.xform_pipeline.view.transformation = view.orientation.to_owned();
 
-        let mut scale = Vector {
-            x: 0.0,
-            y: 0.0,
-            z: 1.0,
-        };
-
-        // compyute matrix scale for zoom and aspect ratio
-        if view.zoom <= 1.0 {
-            // Zoom in by scaling z
-            scale.z *= view.zoom;
-        } else {
-            // Zoom out by scaling x and y
-            let focal_len = 1.0 / view.zoom;
-            scale.x *= focal_len;
-            scale.y *= focal_len;
-        }
+        // compute matrix scale for zoom and aspect ratio, shared with the GPU projection
+        let scale = self.projection.view_scale(&area, view.zoom);
 
         // Scale the matrix elements
         self.xform_pipeline.view.transformation.right *= scale.x;
@@ -2,7 +2,10 @@
 mod tests;
 
 pub mod conversions;
+pub mod debug_view;
 pub mod legacy_soft;
+pub mod projection;
+pub mod scene;
 
 use crate::{
     common::SharedMutRef,
@@ -13,10 +16,13 @@ use crate::{
     },
 };
 use bitflags::bitflags;
+use derive_builder::Builder;
 
 use anyhow::Result;
 
-use super::{bitmap::Bitmap16, ddgr_color, rendering::Renderer, MapSourceType16};
+use super::{bitmap::Bitmap16, ddgr_color, rendering::Renderer, TextureHandle};
+
+use projection::Fov;
 
 pub enum Point3Kind {
     Original(Point3),
@@ -48,26 +54,74 @@ bitflags! {
 
 bitflags! {
     #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
-    pub struct ClippingCode: u8 {
-        const OFF_LEFT   = 0b0000_0001; // 1
-        const OFF_RIGHT  = 0b0000_0010; // 2
-        const OFF_BOT    = 0b0000_0100; // 4
-        const OFF_TOP    = 0b0000_1000; // 8
-        const OFF_FAR    = 0b0001_0000; // 16
-        const OFF_CUSTOM = 0b0010_0000; // 32
-        const BEHIND     = 0b1000_0000; // 128
+    pub struct ClippingCode: u16 {
+        const OFF_LEFT     = 0b0000_0000_0000_0001; // 1
+        const OFF_RIGHT    = 0b0000_0000_0000_0010; // 2
+        const OFF_BOT      = 0b0000_0000_0000_0100; // 4
+        const OFF_TOP      = 0b0000_0000_0000_1000; // 8
+        const OFF_FAR      = 0b0000_0000_0001_0000; // 16
+        const BEHIND       = 0b0000_0000_1000_0000; // 128
+        /// One bit per slot of the custom clip plane stack
+        const OFF_CUSTOM_0 = 0b0000_0001_0000_0000; // 256
+        const OFF_CUSTOM_1 = 0b0000_0010_0000_0000; // 512
+        const OFF_CUSTOM_2 = 0b0000_0100_0000_0000; // 1024
+        const OFF_CUSTOM_3 = 0b0000_1000_0000_0000; // 2048
+    }
+}
+
+impl Default for ClippingCode {
+    fn default() -> Self {
+        ClippingCode::empty()
+    }
+}
+
+/// How many custom clip planes can be active at once
+pub const MAX_CUSTOM_CLIP_PLANES: usize = 4;
+
+impl ClippingCode {
+    /// Every custom clip plane bit
+    pub const OFF_CUSTOM: ClippingCode = ClippingCode::from_bits_retain(0b0000_1111_0000_0000);
+
+    /// The bit of the custom clip plane in `slot`
+    pub fn custom(slot: usize) -> ClippingCode {
+        assert!(slot < MAX_CUSTOM_CLIP_PLANES);
+        ClippingCode::from_bits_retain(ClippingCode::OFF_CUSTOM_0.bits() << slot)
+    }
+
+    /// The custom clip plane slot if this is a single custom plane bit
+    pub fn custom_slot(&self) -> Option<usize> {
+        (0..MAX_CUSTOM_CLIP_PLANES).find(|&i| *self == ClippingCode::custom(i))
     }
 }
 
 // X, Y should represent ast top-left corner of the screen
+#[derive(Debug, Copy, Clone, Builder)]
+#[builder(name = "ViewportBuilder", pattern = "owned")]
 pub struct ScreenViewPort {
+    #[builder(default)]
     pub x: usize,
+    #[builder(default)]
     pub y: usize,
     pub width: usize,
     pub height: usize,
+    /// Defaults to the width over the height
+    #[builder(default = "self.default_aspect()")]
     pub aspect: f32,
 }
 
+impl ViewportBuilder {
+    pub fn size(self, width: usize, height: usize) -> Self {
+        self.width(width).height(height)
+    }
+
+    fn default_aspect(&self) -> f32 {
+        match (self.width, self.height) {
+            (Some(w), Some(h)) if h > 0 => w as f32 / h as f32,
+            _ => 1.0,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct UVL {
     pub u: f32,
@@ -238,7 +292,7 @@ impl Point3 {
     ///     - The view orientation as a `&Matrix`
     /// * `clip` - A tuple containing:
     ///     - The Z clip value as `f32`
-    ///     - An optional custom clip definition (`&Option<CustomClip>`)
+    ///     - The custom clip planes (`&CustomClipStack`)
     ///
     /// # Behavior
     ///
@@ -253,13 +307,13 @@ impl Point3 {
     /// let view_position = Vector::zero();
     /// let view_matrix = Matrix::identity();
     /// let mut p = MyPoint::default();
-    /// p.apply_view_transform(&point, (&view_position, &view_matrix), (1.0, &None));
+    /// p.apply_view_transform(&point, (&view_position, &view_matrix), (1.0, &CustomClipStack::default()));
     /// ```
     pub fn apply_view_transform(
         &mut self,
         point: &Vector,
         view: &Camera,
-        clip: (f32, &Option<CustomClip>),
+        clip: (f32, &CustomClipStack),
     ) {
         self.origin = point.clone();
 
@@ -286,13 +340,13 @@ impl Point3 {
         self.flags.insert(PointFlags::PROJECTED);
     }
 
-    pub fn add_delta(&mut self, p: Self, delta: &Vector, clip: (f32, &Option<CustomClip>)) {
+    pub fn add_delta(&mut self, p: Self, delta: &Vector, clip: (f32, &CustomClipStack)) {
         self.transform = p.transform + *delta;
         self.flags = PointFlags::empty();
         self.compute_clipcode(clip.0, clip.1);
     }
 
-    pub fn compute_clipcode(&mut self, clip_far_z: f32, custom_clip: &Option<CustomClip>) {
+    pub fn compute_clipcode(&mut self, clip_far_z: f32, custom_clips: &CustomClipStack) {
         self.clipping_codes = ClippingCode::empty();
 
         if self.x() > self.z() {
@@ -319,20 +373,10 @@ impl Point3 {
             self.clipping_codes.insert(ClippingCode::OFF_FAR);
         }
 
-        match custom_clip {
-            Some(cc) => {
-                let mut vec = self.transform - cc.clipping_plane_point;
-                vec.x /= cc.matrix_scale.x;
-                vec.y /= cc.matrix_scale.y;
-                vec.z /= cc.matrix_scale.z;
-
-                let mut dp = vec * cc.clipping_plane;
-
-                if (dp < -0.005) {
-                    self.clipping_codes.insert(ClippingCode::OFF_CUSTOM);
-                }
+        for (slot, cc) in custom_clips.iter() {
+            if cc.distance(&self.transform) < -0.005 {
+                self.clipping_codes.insert(ClippingCode::custom(slot));
             }
-            None => {}
         }
     }
 }
@@ -351,7 +395,8 @@ impl Default for Point3 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "owned", default)]
 pub struct Camera {
     pub position: Vector,
     pub scale: Vector,
@@ -360,6 +405,36 @@ pub struct Camera {
     pub zoom: f32,
 }
 
+impl CameraBuilder {
+    /// Sets both the orientation and transformation, they only differ once a scale is applied
+    pub fn rotation(self, m: Matrix) -> Self {
+        self.orientation(m).transformation(m)
+    }
+
+    /// Points the camera from its position toward `target`
+    pub fn look_at(self, target: Vector) -> Self {
+        let position = self.position.unwrap_or(Vector::ZERO);
+        let forward = target - position;
+
+        self.rotation(Matrix::from_vector(Some(&forward), None, None))
+    }
+
+    /// Sets the zoom from a retail zoom or a FOV in degrees
+    pub fn fov(self, fov: Fov) -> Self {
+        self.zoom(fov.zoom())
+    }
+}
+
+impl Camera {
+    pub fn fov(&self) -> Fov {
+        Fov::Zoom(self.zoom)
+    }
+
+    pub fn set_fov(&mut self, fov: Fov) {
+        self.zoom = fov.zoom();
+    }
+}
+
 impl Default for Camera {
     fn default() -> Self {
         Self {
@@ -385,11 +460,15 @@ pub trait RenderSetupState {
         self.set_clipping_far_z(f32::MAX);
     }
 
-    // fn compute_viewport_matrix(&self, viewport: &ScreenRect) -> Matrix4;
-    // fn compute_projection_matrix(&self, viewport: &ScreenRect, zoom: f32) -> Matrix4;
-    // fn compute_viewmodel_matrix(&self, view: &Camera) -> Matrix4;
-    // fn compute_point_transform(&self, point: &Vector4, m: &Matrix4) -> Vector4;
-    // fn compute_matrix_product(&self, a: &Matrix4, b: &Matrix4) -> Matrix4;
+    /// Maps normalized device coordinates onto the viewport's pixels
+    fn compute_viewport_matrix(&self, viewport: &ScreenViewPort) -> Matrix4;
+    /// Perspective for the viewport, `zoom` is the retail 1/focal length
+    fn compute_projection_matrix(&self, viewport: &ScreenViewPort, zoom: f32) -> Matrix4;
+    /// World to view space for the camera
+    fn compute_viewmodel_matrix(&self, view: &Camera) -> Matrix4;
+    fn compute_point_transform(&self, point: &Vector4, m: &Matrix4) -> Vector4;
+    /// Composes two transforms in the order the pipeline applies them
+    fn compute_matrix_product(&self, a: &Matrix4, b: &Matrix4) -> Matrix4;
     fn update_transforms(&mut self);
     fn on_frame_start(&mut self, viewport: &ScreenViewPort, view: &Camera);
 }
@@ -401,6 +480,94 @@ pub struct CustomClip {
     pub matrix_scale: Vector,
 }
 
+impl CustomClip {
+    /// Signed distance of a view space point along the plane normal, negative is clipped away
+    pub fn distance(&self, v: &Vector) -> f32 {
+        let mut vec = *v - self.clipping_plane_point;
+        vec.x /= self.matrix_scale.x;
+        vec.y /= self.matrix_scale.y;
+        vec.z /= self.matrix_scale.z;
+
+        vec * self.clipping_plane
+    }
+}
+
+/// Custom clip planes that are clipped against together (mirrors, portal windows, water)
+///
+/// Each slot owns one of the `OFF_CUSTOM_*` bits, slots can be turned off without popping them
+#[derive(Debug, Copy, Clone, Default)]
+pub struct CustomClipStack {
+    planes: [Option<CustomClip>; MAX_CUSTOM_CLIP_PLANES],
+    len: usize,
+    enabled: ClippingCode,
+}
+
+impl CustomClipStack {
+    /// Pushes an enabled plane, returns its slot
+    pub fn push(&mut self, clip: CustomClip) -> Result<usize> {
+        if self.len >= MAX_CUSTOM_CLIP_PLANES {
+            return Err(anyhow!("Only {} custom clip planes are supported", MAX_CUSTOM_CLIP_PLANES));
+        }
+
+        let slot = self.len;
+        self.planes[slot] = Some(clip);
+        self.enabled.insert(ClippingCode::custom(slot));
+        self.len += 1;
+
+        Ok(slot)
+    }
+
+    pub fn pop(&mut self) -> Option<CustomClip> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        self.enabled.remove(ClippingCode::custom(self.len));
+        self.planes[self.len].take()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&CustomClip> {
+        self.planes.get(slot).and_then(|p| p.as_ref())
+    }
+
+    pub fn set_enabled(&mut self, slot: usize, enabled: bool) {
+        if slot < self.len {
+            self.enabled.set(ClippingCode::custom(slot), enabled);
+        }
+    }
+
+    pub fn is_enabled(&self, slot: usize) -> bool {
+        slot < self.len && self.enabled.contains(ClippingCode::custom(slot))
+    }
+
+    /// Bits of the planes that are currently clipped against
+    pub fn enabled_codes(&self) -> ClippingCode {
+        self.enabled
+    }
+
+    /// The enabled planes with their slots
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &CustomClip)> {
+        self.planes[..self.len]
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| self.enabled.contains(ClippingCode::custom(*i)))
+            .filter_map(|(i, p)| p.as_ref().map(|p| (i, p)))
+    }
+}
+
 pub trait RenderPipeline<R: Renderer> {
     fn draw_line(&self, renderer: &mut R, color: ddgr_color, p0: &Point3, p1: Point3)
     -> Result<()>;
@@ -417,10 +584,10 @@ pub trait RenderPipeline<R: Renderer> {
     //g3_CheckNormalFacing
     // DoFacingCheck
 
-    fn draw_poly<B: Bitmap16>(
+    fn draw_poly(
         &self,
         renderer: &mut R,
         pointlist: &[Point3],
-        map_source: Option<MapSourceType16>,
+        map_source: Option<TextureHandle>,
     ) -> Result<Option<usize>>;
 }
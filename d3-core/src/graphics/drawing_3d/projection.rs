@@ -0,0 +1,128 @@
+/*
+
+Field of view and aspect ratio
+
+Retail gives the FOV as a zoom, the tangent of half the horizontal FOV
+on the 4:3 screens it was made for. The focal lengths worked out here
+are shared by the software clipper's view scale and the GPU projection
+matrix so both paths frame the scene the same way.
+
+*/
+
+use crate::math::{matrix::Matrix4, vector::Vector};
+
+use super::ScreenViewPort;
+
+/// Aspect ratio retail FOVs were tuned for
+pub const RETAIL_ASPECT: f32 = 4.0 / 3.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fov {
+    /// Retail zoom, tan(horizontal fov / 2)
+    Zoom(f32),
+    /// Horizontal FOV in degrees
+    Degrees(f32),
+}
+
+impl Fov {
+    pub fn zoom(&self) -> f32 {
+        match *self {
+            Fov::Zoom(zoom) => zoom,
+            Fov::Degrees(degrees) => (degrees.clamp(1.0, 179.0).to_radians() * 0.5).tan(),
+        }
+    }
+
+    pub fn degrees(&self) -> f32 {
+        match *self {
+            Fov::Zoom(zoom) => (zoom.atan() * 2.0).to_degrees(),
+            Fov::Degrees(degrees) => degrees,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AspectMode {
+    /// Keeps the horizontal FOV, wider screens see less vertically
+    #[default]
+    Horizontal,
+    /// Keeps the vertical FOV retail has at 4:3, wider screens see more to the sides
+    Widescreen,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Projection {
+    pub mode: AspectMode,
+    /// Height of each cinematic bar as a fraction of the screen, 0 for none
+    pub letterbox: f32,
+}
+
+impl Projection {
+    fn bar_height(&self, viewport: &ScreenViewPort) -> usize {
+        let bar = (viewport.height as f32 * self.letterbox.clamp(0.0, 0.5)).round() as usize;
+
+        // Always leave at least a row to draw into
+        bar.min(viewport.height.saturating_sub(1) / 2)
+    }
+
+    /// The part of the viewport between the cinematic bars, its aspect follows the new height
+    pub fn letterboxed(&self, viewport: &ScreenViewPort) -> ScreenViewPort {
+        let bar = self.bar_height(viewport);
+
+        if bar == 0 {
+            return *viewport;
+        }
+
+        let height = viewport.height - bar * 2;
+
+        ScreenViewPort {
+            y: viewport.y + bar,
+            height,
+            aspect: viewport.aspect * viewport.height as f32 / height as f32,
+            ..*viewport
+        }
+    }
+
+    /// The top and bottom bars to fill, none when letterboxing is off
+    pub fn letterbox_bars(&self, viewport: &ScreenViewPort) -> Option<[ScreenViewPort; 2]> {
+        let bar = self.bar_height(viewport);
+
+        if bar == 0 {
+            return None;
+        }
+
+        let top = ScreenViewPort { height: bar, ..*viewport };
+        let bottom = ScreenViewPort { y: viewport.y + viewport.height - bar, ..top };
+
+        Some([top, bottom])
+    }
+
+    /// Horizontal and vertical focal lengths, mapping view space onto -1..1 across the viewport
+    pub fn focal_lengths(&self, viewport: &ScreenViewPort, zoom: f32) -> (f32, f32) {
+        let aspect = if viewport.aspect > 0.0 { viewport.aspect } else { RETAIL_ASPECT };
+
+        match self.mode {
+            AspectMode::Horizontal => (1.0 / zoom, aspect / zoom),
+            AspectMode::Widescreen => (RETAIL_ASPECT / (aspect * zoom), RETAIL_ASPECT / zoom),
+        }
+    }
+
+    /// Scale applied to the view matrix for the software clipper
+    ///
+    /// Like retail, zooming in scales z so x and y keep their range
+    pub fn view_scale(&self, viewport: &ScreenViewPort, zoom: f32) -> Vector {
+        let (fx, fy) = self.focal_lengths(viewport, zoom);
+
+        if zoom <= 1.0 {
+            Vector::new(fx * zoom, fy * zoom, zoom)
+        } else {
+            Vector::new(fx, fy, 1.0)
+        }
+    }
+
+    /// Left handed perspective with a 0..1 depth range, laid out for column vectors like vek's own
+    pub fn gpu_matrix(&self, viewport: &ScreenViewPort, zoom: f32, near: f32, far: f32) -> Matrix4 {
+        let (fx, fy) = self.focal_lengths(viewport, zoom);
+
+        Matrix4::perspective_lh_zo((1.0 / fy).atan() * 2.0, fy / fx, near, far)
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+
+use crate::graphics::dd_video::ScreenResizeListener;
+
+use super::{legacy_soft::SoftRenderSetup, projection::Projection, Camera, RenderSetupState, ScreenViewPort};
+
+/// Everything needed to start rendering a frame, the camera and viewport can be changed between frames
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub setup: SoftRenderSetup,
+    pub camera: Camera,
+    pub viewport: ScreenViewPort,
+}
+
+impl Scene {
+    /// Sets up the transforms for the current camera and viewport
+    pub fn begin_frame(&mut self) {
+        self.setup.on_frame_start(&self.viewport, &self.camera);
+    }
+
+    /// Keeps the viewport's aspect in step with the new size
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.viewport.width = width;
+        self.viewport.height = height;
+
+        if height > 0 {
+            self.viewport.aspect = width as f32 / height as f32;
+        }
+    }
+}
+
+impl ScreenResizeListener for Scene {
+    fn on_screen_resize(&mut self, width: usize, height: usize) {
+        self.resize(width, height);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SceneBuilder {
+    camera: Option<Camera>,
+    viewport: Option<ScreenViewPort>,
+    far_z: Option<f32>,
+    aspect_override: Option<f32>,
+    projection: Projection,
+}
+
+impl SceneBuilder {
+    pub fn camera(mut self, camera: Camera) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    pub fn viewport(mut self, viewport: ScreenViewPort) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    /// Far clipping distance, nothing is clipped by distance if not set
+    pub fn far_z(mut self, far_z: f32) -> Self {
+        self.far_z = Some(far_z);
+        self
+    }
+
+    /// Forces the aspect ratio (w/h) instead of using the viewport's
+    pub fn aspect_override(mut self, aspect: f32) -> Self {
+        self.aspect_override = Some(aspect);
+        self
+    }
+
+    /// FOV scaling on wide screens and cinematic bars
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn build(self) -> Result<Scene> {
+        let viewport = self.viewport.ok_or_else(|| anyhow!("A scene needs a viewport"))?;
+
+        if viewport.width == 0 || viewport.height == 0 {
+            return Err(anyhow!("Viewport is empty: {}x{}", viewport.width, viewport.height));
+        }
+
+        let mut setup = SoftRenderSetup {
+            aspect_override: self.aspect_override,
+            projection: self.projection,
+            ..Default::default()
+        };
+
+        setup.set_aspect_ratio(viewport.aspect);
+
+        if let Some(far_z) = self.far_z {
+            setup.set_clipping_far_z(far_z);
+        }
+
+        Ok(Scene {
+            setup,
+            camera: self.camera.unwrap_or_default(),
+            viewport,
+        })
+    }
+}
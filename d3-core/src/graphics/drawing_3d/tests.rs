@@ -1 +1,299 @@
+use crate::prelude::*;
 
+use super::MAX_CUSTOM_CLIP_PLANES;
+
+#[test]
+fn viewport_builder_test() {
+    crate::test_common::setup();
+
+    let viewport = ViewportBuilder::default().size(800, 600).build().unwrap();
+
+    assert_eq!((viewport.x, viewport.y), (0, 0));
+    assert_eq!((viewport.width, viewport.height), (800, 600));
+    assert!((viewport.aspect - 800.0 / 600.0).abs() < 1e-6);
+
+    let viewport = ViewportBuilder::default().size(640, 480).aspect(1.0).build().unwrap();
+    assert_eq!(viewport.aspect, 1.0);
+
+    // Width and height have no defaults
+    assert!(ViewportBuilder::default().width(10).build().is_err());
+}
+
+#[test]
+fn camera_builder_test() {
+    crate::test_common::setup();
+
+    let camera = CameraBuilder::default().build().unwrap();
+    assert_eq!(camera.position, Vector::ZERO);
+    assert_eq!(camera.zoom, 1.0);
+
+    let camera = CameraBuilder::default()
+        .position(Vector::new(0.0, 0.0, -10.0))
+        .look_at(Vector::ZERO)
+        .build()
+        .unwrap();
+
+    assert!(Vector::distance(&camera.orientation.forward, &Vector::new(0.0, 0.0, 1.0)) < 1e-5);
+    assert_eq!(camera.orientation, camera.transformation);
+}
+
+#[test]
+fn scene_builder_test() {
+    crate::test_common::setup();
+
+    assert!(SceneBuilder::default().build().is_err());
+    assert!(SceneBuilder::default()
+        .viewport(ScreenViewPort { x: 0, y: 0, width: 0, height: 10, aspect: 1.0 })
+        .build()
+        .is_err());
+
+    let mut scene = SceneBuilder::default()
+        .viewport(ViewportBuilder::default().size(320, 200).build().unwrap())
+        .camera(CameraBuilder::default().position(Vector::new(1.0, 2.0, 3.0)).build().unwrap())
+        .far_z(100.0)
+        .build()
+        .unwrap();
+
+    assert_eq!(scene.setup.clipper_far_z, 100.0);
+    assert!((scene.setup.get_aspect_ratio() - 1.6).abs() < 1e-6);
+
+    scene.begin_frame();
+    assert_eq!(scene.setup.xform_pipeline.view.position, Vector::new(1.0, 2.0, 3.0));
+
+    scene.resize(100, 50);
+    assert_eq!(scene.viewport.aspect, 2.0);
+}
+
+#[test]
+fn render_setup_matrices_test() {
+    crate::test_common::setup();
+
+    let setup = SoftRenderSetup::default();
+    let viewport = ScreenViewPort { x: 10, y: 20, width: 100, height: 50, aspect: 2.0 };
+
+    // NDC corners land on the viewport's pixels
+    let m = setup.compute_viewport_matrix(&viewport);
+    let center = setup.compute_point_transform(&Vector4::new(0.0, 0.0, 0.0, 1.0), &m);
+    let corner = setup.compute_point_transform(&Vector4::new(1.0, 1.0, 0.0, 1.0), &m);
+    assert_eq!((center.x, center.y), (60.0, 45.0));
+    assert_eq!((corner.x, corner.y), (110.0, 70.0));
+
+    // The view moves the world in front of the camera
+    let camera = CameraBuilder::default().position(Vector::new(0.0, 0.0, -10.0)).build().unwrap();
+    let view = setup.compute_viewmodel_matrix(&camera);
+    let p = setup.compute_point_transform(&Vector4::new(1.0, 2.0, 0.0, 1.0), &view);
+    assert_eq!((p.x, p.y, p.z, p.w), (1.0, 2.0, 10.0, 1.0));
+
+    // Perspective puts the depth in w
+    let square = ScreenViewPort { x: 0, y: 0, width: 100, height: 100, aspect: 1.0 };
+    let projection = setup.compute_projection_matrix(&square, 1.0);
+    let p = setup.compute_point_transform(&Vector4::new(2.0, 4.0, 10.0, 1.0), &projection);
+    assert_eq!((p.x, p.y, p.z, p.w), (2.0, 4.0, 9.0, 10.0));
+
+    // A product applies its left side first
+    let combined = setup.compute_matrix_product(&view, &projection);
+    let point = Vector4::new(1.0, 2.0, 0.0, 1.0);
+    let step = setup.compute_point_transform(&setup.compute_point_transform(&point, &view), &projection);
+    assert_eq!(setup.compute_point_transform(&point, &combined), step);
+}
+
+fn clip_plane(point: Vector, normal: Vector) -> CustomClip {
+    CustomClip { clipping_plane_point: point, clipping_plane: normal, matrix_scale: Vector::new(1.0, 1.0, 1.0) }
+}
+
+#[test]
+fn custom_clip_stack_test() {
+    crate::test_common::setup();
+
+    let mut clips = CustomClipStack::default();
+    assert_eq!(clips.push(clip_plane(Vector::new(0.0, 0.0, 10.0), Vector::new(0.0, 0.0, 1.0))).unwrap(), 0);
+    assert_eq!(clips.push(clip_plane(Vector::ZERO, Vector::new(1.0, 0.0, 0.0))).unwrap(), 1);
+    assert_eq!(clips.push(clip_plane(Vector::ZERO, Vector::new(0.0, 1.0, 0.0))).unwrap(), 2);
+
+    // Closer than the first plane and left of the second, on the third
+    let mut p = Point3::new(-1.0, 0.0, 5.0);
+    p.compute_clipcode(f32::MAX, &clips);
+    assert!(p.clipping_codes.contains(ClippingCode::OFF_CUSTOM_0 | ClippingCode::OFF_CUSTOM_1));
+    assert!(!p.clipping_codes.contains(ClippingCode::OFF_CUSTOM_2));
+
+    // Turned off planes keep their slot but don't clip
+    clips.set_enabled(0, false);
+    p.compute_clipcode(f32::MAX, &clips);
+    assert_eq!(p.clipping_codes & ClippingCode::OFF_CUSTOM, ClippingCode::OFF_CUSTOM_1);
+    assert_eq!(clips.enabled_codes(), ClippingCode::OFF_CUSTOM_1 | ClippingCode::OFF_CUSTOM_2);
+
+    clips.push(clip_plane(Vector::ZERO, Vector::new(0.0, 0.0, 1.0))).unwrap();
+    assert!(clips.push(clip_plane(Vector::ZERO, Vector::new(0.0, 0.0, 1.0))).is_err());
+    assert_eq!(clips.len(), MAX_CUSTOM_CLIP_PLANES);
+
+    assert!(clips.pop().is_some());
+    assert!(!clips.is_enabled(3));
+    assert_eq!(ClippingCode::OFF_CUSTOM_2.custom_slot(), Some(2));
+    assert_eq!(ClippingCode::OFF_FAR.custom_slot(), None);
+}
+
+#[test]
+fn projection_fov_test() {
+    crate::test_common::setup();
+
+    assert!((Fov::Degrees(90.0).zoom() - 1.0).abs() < 1e-6);
+    assert!((Fov::Zoom(1.0).degrees() - 90.0).abs() < 1e-4);
+
+    let camera = CameraBuilder::default().fov(Fov::Degrees(90.0)).build().unwrap();
+    assert!((camera.zoom - 1.0).abs() < 1e-6);
+
+    let retail = ViewportBuilder::default().size(640, 480).build().unwrap();
+    let wide = ViewportBuilder::default().size(1920, 1080).build().unwrap();
+
+    let horizontal = Projection::default();
+    let widescreen = Projection { mode: AspectMode::Widescreen, ..Default::default() };
+
+    // Both modes agree at 4:3
+    assert_eq!(horizontal.focal_lengths(&retail, 1.0), widescreen.focal_lengths(&retail, 1.0));
+
+    // Widescreen keeps the 4:3 vertical fov and sees more to the sides
+    let (fx, fy) = horizontal.focal_lengths(&wide, 1.0);
+    let (wx, wy) = widescreen.focal_lengths(&wide, 1.0);
+    assert!((fx - 1.0).abs() < 1e-6);
+    assert!((wy - 4.0 / 3.0).abs() < 1e-6);
+    assert!(wx < fx && fy > wy);
+}
+
+#[test]
+fn projection_letterbox_test() {
+    crate::test_common::setup();
+
+    let viewport = ViewportBuilder::default().size(640, 480).build().unwrap();
+    let projection = Projection { letterbox: 0.125, ..Default::default() };
+
+    let area = projection.letterboxed(&viewport);
+    assert_eq!((area.x, area.y, area.width, area.height), (0, 60, 640, 360));
+    assert!((area.aspect - 16.0 / 9.0).abs() < 1e-6);
+
+    let [top, bottom] = projection.letterbox_bars(&viewport).unwrap();
+    assert_eq!((top.y, top.height), (0, 60));
+    assert_eq!((bottom.y, bottom.height), (420, 60));
+
+    assert!(Projection::default().letterbox_bars(&viewport).is_none());
+
+    // The soft setup draws between the bars
+    let mut setup = SoftRenderSetup { projection, ..Default::default() };
+    setup.on_frame_start(&viewport, &Camera::default());
+    assert_eq!((setup.window_width, setup.window_height), (640, 360));
+}
+
+#[test]
+fn projection_soft_gpu_agree_test() {
+    crate::test_common::setup();
+
+    let viewport = ViewportBuilder::default().size(1280, 720).build().unwrap();
+    let point = Vector::new(3.0, -2.0, 10.0);
+
+    for mode in [AspectMode::Horizontal, AspectMode::Widescreen] {
+        for zoom in [0.5, 1.0, 2.0] {
+            let projection = Projection { mode, ..Default::default() };
+
+            let scale = projection.view_scale(&viewport, zoom);
+            let soft = (point.x * scale.x / (point.z * scale.z), point.y * scale.y / (point.z * scale.z));
+
+            let clip = projection.gpu_matrix(&viewport, zoom, 0.1, 100.0) * Vector4::new(point.x, point.y, point.z, 1.0);
+            let gpu = (clip.x / clip.w, clip.y / clip.w);
+
+            assert!((soft.0 - gpu.0).abs() < 1e-5 && (soft.1 - gpu.1).abs() < 1e-5, "{:?} {} {:?} {:?}", mode, zoom, soft, gpu);
+        }
+    }
+}
+
+#[test]
+fn conversions_test() {
+    use super::conversions::{EucColorVertex, EucTexturedVertex};
+    use vek::{Mat3, Mat4, Rgba, Vec2, Vec3, Vec4};
+
+    crate::test_common::setup();
+
+    let v = Vector::new(1.0, 2.0, 3.0);
+    assert_eq!(Vector::from(Vec3::from(v)), v);
+    assert_eq!(Vec4::from(v), Vec4::new(1.0, 2.0, 3.0, 1.0));
+    assert_eq!(Vector::from(Vec4::new(1.0, 2.0, 3.0, 5.0)), v);
+
+    let m = Matrix::compute_rotation_3d(&EulerAngle {
+        pitch: Angle(0x1000),
+        heading: Angle(0x2000),
+        bank: Angle(0x3000),
+    });
+    assert_eq!(Matrix::from(Mat4::from(m)), m);
+    assert_eq!(Matrix::from(Mat3::from(m)), m);
+
+    // Same rows going both ways
+    let mat: Mat3<f32> = m.into();
+    assert_eq!(Vector::from(Vec3::from(mat.into_row_arrays()[2])), m.forward);
+
+    let mut p = Point3::new(4.0, 5.0, 6.0);
+    p.set_u(0.25);
+    p.set_v(0.75);
+    p.uvl.light_intensity = 0.5;
+
+    let (position, color): EucColorVertex = p.into();
+    assert_eq!(position, Vec4::new(4.0, 5.0, 6.0, 1.0));
+    assert_eq!(color, Rgba::new(0.5, 0.5, 0.5, 1.0));
+
+    let back = Point3::from((position, Rgba::new(1.0, 0.0, 0.5, 1.0)));
+    assert_eq!((back.x(), back.y(), back.z()), (4.0, 5.0, 6.0));
+    assert_eq!(Rgba::from(back), Rgba::new(1.0, 0.0, 0.5, 1.0));
+
+    let (_, uv): EucTexturedVertex = p.into();
+    assert_eq!(uv, Vec2::new(0.25, 0.75));
+    let back = Point3::from((position, uv));
+    assert_eq!((back.u(), back.v()), (0.25, 0.75));
+}
+
+#[test]
+fn debug_view_test() {
+    use super::debug_view::{mip_level, DebugRenderer, DebugViewMode};
+    use crate::graphics::{
+        rendering::{tests::CountingRenderer, AlphaType, RenderVertex, Renderer},
+        BitmapId, LightMapId, TextureHandle,
+    };
+
+    crate::test_common::setup();
+
+    let vertex = |x: f32, y: f32, u: f32, v: f32| RenderVertex {
+        screen_x: x,
+        screen_y: y,
+        u,
+        v,
+        ..Default::default()
+    };
+
+    // A 128 texture on 128 pixels is full size, squeezed into 32 it's two levels down
+    let full = [vertex(0.0, 0.0, 0.0, 0.0), vertex(128.0, 0.0, 1.0, 0.0), vertex(128.0, 128.0, 1.0, 1.0)];
+    let small = [vertex(0.0, 0.0, 0.0, 0.0), vertex(32.0, 0.0, 1.0, 0.0), vertex(32.0, 32.0, 1.0, 1.0)];
+    assert_eq!(mip_level(&full, (128, 128)), 0);
+    assert_eq!(mip_level(&small, (128, 128)), 2);
+
+    let wall = Some(TextureHandle::Bitmap(BitmapId(0)));
+    let mut renderer = DebugRenderer::new(CountingRenderer::default());
+
+    // Normal passes batches straight through
+    renderer.begin_batch(wall, AlphaType::TEXTURE);
+    renderer.push_vertices(&full);
+    renderer.end_batch();
+    assert_eq!(renderer.inner().polygons, vec![(wall, 3)]);
+
+    // A triangle in wireframe is three untextured edge quads
+    renderer.set_mode(DebugViewMode::Wireframe);
+    renderer.inner_mut().polygons.clear();
+    renderer.set_texture(wall);
+    renderer.draw_polygon(&full);
+    assert_eq!(renderer.inner().polygons, vec![(None, 4); 3]);
+
+    // Lightmap only drops base textures but keeps the lightmaps
+    renderer.set_mode(DebugViewMode::LightmapOnly);
+    renderer.set_texture(wall);
+    assert_eq!(renderer.inner().texture, None);
+    let lightmap = Some(TextureHandle::LightMap(LightMapId(0)));
+    renderer.set_texture(lightmap);
+    assert_eq!(renderer.inner().texture, lightmap);
+
+    assert_eq!(renderer.polygon_count(), 2);
+}
@@ -1,6 +1,6 @@
 use crate::string::D3String;
 
-use super::bitmap::{Bitmap16, BitmapFlags};
+use super::bitmap::{write_debug_pattern, Bitmap16, BitmapFlags, BitmapFormat, DebugPattern};
 
 #[derive(Debug, Clone)]
 pub struct GenericBitmap16 {
@@ -50,7 +50,7 @@ impl Bitmap16 for GenericBitmap16 {
         super::bitmap::BitmapFormat::Fmt4444
     }
 
-    fn make_funny(&mut self) {
-        todo!()
+    fn fill_debug_pattern(&mut self, kind: DebugPattern) {
+        write_debug_pattern(&mut self.data, self.width, self.height, BitmapFormat::Fmt4444, kind);
     }
 }
\ No newline at end of file
@@ -0,0 +1,455 @@
+/*
+
+Headless renderer
+
+A renderer that draws on the CPU into a MemBitmap32 with no window system
+behind it, for image tests in CI and for drawing things like level thumbnails
+on a server. It fills polygons with perspective correct texturing, Gouraud
+lighting, a zbuffer and the constant, texture and vertex alpha types, enough
+to tell a scene is right without trying to match a card pixel for pixel.
+Filtering, overlays and the color model are accepted and ignored.
+
+Textures have to be handed over with `add_texture` before polygons can be
+mapped with them, the renderer keeps its own 32 bit copy.
+
+*/
+
+use std::collections::HashMap;
+
+use super::{
+    bitmap::{Bitmap16, BitmapFormat, MemBitmap32},
+    color_conversion::{alpha_blend, pixel_1555_to_32, pixel_4444_to_32},
+    ddgr_color,
+    drawing_2d::font::{FontGlyph, FontGraphic},
+    drawing_3d::ScreenViewPort,
+    rendering::{AlphaType, ColorModelType, LightStateType, OverlayTextureType, RenderVertex, Renderer, TextureType},
+    TextureHandle,
+};
+
+/// Alpha types that take the constant alpha into account
+const CONSTANT_ALPHA: AlphaType = AlphaType::CONSTANT
+    .union(AlphaType::CONSTANT_TEXTURE)
+    .union(AlphaType::CONSTANT_VERTEX)
+    .union(AlphaType::CONSTANT_TEXTURE_VERTEX)
+    .union(AlphaType::SATURATE_CONSTANT_VERTEX)
+    .union(AlphaType::LIGHTMAP_BLEND_CONSTANT);
+
+/// Alpha types that take the texture's alpha into account
+const TEXTURE_ALPHA: AlphaType = AlphaType::TEXTURE
+    .union(AlphaType::CONSTANT_TEXTURE)
+    .union(AlphaType::TEXTURE_VERTEX)
+    .union(AlphaType::CONSTANT_TEXTURE_VERTEX)
+    .union(AlphaType::SATURATE_TEXTURE)
+    .union(AlphaType::SATURATE_TEXTURE_VERTEX);
+
+/// Alpha types that take the vertex alpha into account
+const VERTEX_ALPHA: AlphaType = AlphaType::VERTEX
+    .union(AlphaType::CONSTANT_VERTEX)
+    .union(AlphaType::TEXTURE_VERTEX)
+    .union(AlphaType::CONSTANT_TEXTURE_VERTEX)
+    .union(AlphaType::SATURATE_VERTEX)
+    .union(AlphaType::SATURATE_CONSTANT_VERTEX)
+    .union(AlphaType::SATURATE_TEXTURE_VERTEX)
+    .union(AlphaType::LIGHTMAP_BLEND_VERTEX);
+
+/// Alpha types that add to what's there instead of blending over it
+const SATURATE_ALPHA: AlphaType = AlphaType::SATURATE_TEXTURE
+    .union(AlphaType::SATURATE_VERTEX)
+    .union(AlphaType::SATURATE_CONSTANT_VERTEX)
+    .union(AlphaType::SATURATE_TEXTURE_VERTEX)
+    .union(AlphaType::LIGHTMAP_BLEND_SATURATE);
+
+#[derive(Debug, Clone)]
+struct HeadlessTexture {
+    data: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl HeadlessTexture {
+    /// Nearest texel, wrapping around outside of 0 to 1
+    fn sample(&self, u: f32, v: f32) -> u32 {
+        let x = ((u - u.floor()) * self.width as f32) as usize;
+        let y = ((v - v.floor()) * self.height as f32) as usize;
+
+        self.data[y.min(self.height - 1) * self.width + x.min(self.width - 1)]
+    }
+}
+
+#[derive(Debug)]
+pub struct HeadlessRenderer {
+    target: MemBitmap32,
+    depth: Vec<f32>,
+    textures: HashMap<TextureHandle, HeadlessTexture>,
+    texture: Option<TextureHandle>,
+    flat_color: ddgr_color,
+    alpha_type: AlphaType,
+    alpha_value: u8,
+    lighting: bool,
+    zbuffer: bool,
+    polygons_drawn: usize,
+}
+
+impl HeadlessRenderer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            target: MemBitmap32::new(width, height),
+            depth: vec![f32::INFINITY; width * height],
+            textures: HashMap::new(),
+            texture: None,
+            flat_color: 0,
+            alpha_type: AlphaType::ALWAYS,
+            alpha_value: 255,
+            lighting: false,
+            zbuffer: true,
+            polygons_drawn: 0,
+        }
+    }
+
+    /// Fills the target with a color and empties the zbuffer, for the start of a frame
+    pub fn clear(&mut self, color: ddgr_color) {
+        self.target.data_mut().fill(0xFF000000 | color);
+        self.depth.fill(f32::INFINITY);
+        self.polygons_drawn = 0;
+    }
+
+    /// Copies a bitmap in for polygons mapped with `handle`
+    pub fn add_texture(&mut self, handle: TextureHandle, bitmap: &dyn Bitmap16) {
+        let to_32 = match bitmap.format() {
+            BitmapFormat::Fmt1555 => pixel_1555_to_32,
+            BitmapFormat::Fmt4444 => pixel_4444_to_32,
+        };
+
+        if bitmap.width() == 0 || bitmap.height() == 0 {
+            return;
+        }
+
+        self.textures.insert(
+            handle,
+            HeadlessTexture {
+                data: bitmap.data().iter().map(|&p| to_32(p)).collect(),
+                width: bitmap.width(),
+                height: bitmap.height(),
+            },
+        );
+    }
+
+    pub fn remove_texture(&mut self, handle: TextureHandle) {
+        self.textures.remove(&handle);
+    }
+
+    pub fn target(&self) -> &MemBitmap32 {
+        &self.target
+    }
+
+    pub fn into_target(self) -> MemBitmap32 {
+        self.target
+    }
+
+    /// Polygons drawn since the last `clear`
+    pub fn polygons_drawn(&self) -> usize {
+        self.polygons_drawn
+    }
+
+    fn draw_triangle(&mut self, a: &RenderVertex, b: &RenderVertex, c: &RenderVertex) {
+        let (width, height) = (self.target.width(), self.target.height());
+
+        let raw_edge = |p: &RenderVertex, q: &RenderVertex, x: f32, y: f32| (q.screen_x - p.screen_x) * (y - p.screen_y) - (q.screen_y - p.screen_y) * (x - p.screen_x);
+
+        // Always worked out from the same end, so two triangles sharing an edge agree exactly on which side a pixel is
+        let edge = |p: &RenderVertex, q: &RenderVertex, x: f32, y: f32| {
+            if (p.screen_x, p.screen_y) > (q.screen_x, q.screen_y) {
+                -raw_edge(q, p, x, y)
+            } else {
+                raw_edge(p, q, x, y)
+            }
+        };
+        let area = edge(a, b, c.screen_x, c.screen_y);
+
+        if area == 0.0 {
+            return;
+        }
+
+        // Pixels right on an edge go to the triangle that has it as a top or left edge, so ones
+        // along the diagonal of a quad aren't drawn twice
+        let owns_edge = |p: &RenderVertex, q: &RenderVertex| {
+            let (inward_x, inward_y) = ((p.screen_y - q.screen_y) * area.signum(), (q.screen_x - p.screen_x) * area.signum());
+            inward_x > 0.0 || (inward_x == 0.0 && inward_y > 0.0)
+        };
+        let covers = |w: f32, p: &RenderVertex, q: &RenderVertex| w > 0.0 || (w == 0.0 && owns_edge(p, q));
+
+        let min_x = a.screen_x.min(b.screen_x).min(c.screen_x).floor().max(0.0) as usize;
+        let min_y = a.screen_y.min(b.screen_y).min(c.screen_y).floor().max(0.0) as usize;
+        let max_x = (a.screen_x.max(b.screen_x).max(c.screen_x).ceil().max(0.0) as usize).min(width);
+        let max_y = (a.screen_y.max(b.screen_y).max(c.screen_y).ceil().max(0.0) as usize).min(height);
+
+        // Perspective correction needs every point in front of the eye, otherwise fall back to affine
+        let perspective = a.z > 0.0 && b.z > 0.0 && c.z > 0.0;
+        let inv_z = |v: &RenderVertex| if perspective { 1.0 / v.z } else { 1.0 };
+
+        let texture = self.texture.and_then(|handle| self.textures.get(&handle));
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+
+                let w0 = edge(b, c, px, py) / area;
+                let w1 = edge(c, a, px, py) / area;
+                let w2 = edge(a, b, px, py) / area;
+
+                if !covers(w0, b, c) || !covers(w1, c, a) || !covers(w2, a, b) {
+                    continue;
+                }
+
+                let (z0, z1, z2) = (w0 * inv_z(a), w1 * inv_z(b), w2 * inv_z(c));
+                let sum = z0 + z1 + z2;
+                let lerp = |f: fn(&RenderVertex) -> f32| (z0 * f(a) + z1 * f(b) + z2 * f(c)) / sum;
+
+                let depth = if perspective { 1.0 / sum } else { lerp(|v| v.z) };
+                let index = y * width + x;
+
+                if self.zbuffer && depth >= self.depth[index] {
+                    continue;
+                }
+
+                let texel = match texture {
+                    Some(texture) => texture.sample(lerp(|v| v.u), lerp(|v| v.v)),
+                    None => 0xFF000000 | self.flat_color,
+                };
+
+                let mut rgb = [(texel >> 16) & 0xFF, (texel >> 8) & 0xFF, texel & 0xFF].map(|c| c as f32);
+
+                if self.lighting {
+                    let light = [lerp(|v| v.r), lerp(|v| v.g), lerp(|v| v.b)];
+
+                    for (channel, light) in rgb.iter_mut().zip(light) {
+                        *channel *= light.clamp(0.0, 1.0);
+                    }
+                }
+
+                let mut alpha = 1.0;
+
+                if self.alpha_type.intersects(CONSTANT_ALPHA) {
+                    alpha *= self.alpha_value as f32 / 255.0;
+                }
+
+                if self.alpha_type.intersects(TEXTURE_ALPHA) {
+                    alpha *= (texel >> 24) as f32 / 255.0;
+                }
+
+                if self.alpha_type.intersects(VERTEX_ALPHA) {
+                    alpha *= lerp(|v| v.a).clamp(0.0, 1.0);
+                }
+
+                let src = 0xFF000000 | (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32;
+                let dst = self.target.data()[index];
+
+                let saturate = self.alpha_type.intersects(SATURATE_ALPHA);
+
+                let mix = |shift: u32| {
+                    let (s, d) = (((src >> shift) & 0xFF) as f32, ((dst >> shift) & 0xFF) as f32);
+                    let value = if saturate { d + s * alpha } else { s * alpha + d * (1.0 - alpha) };
+
+                    (value.round().min(255.0) as u32) << shift
+                };
+
+                self.target.data_mut()[index] = 0xFF000000 | mix(16) | mix(8) | mix(0);
+
+                // See-through pixels leave the zbuffer to what's behind them
+                if self.zbuffer && alpha >= 1.0 && !saturate {
+                    self.depth[index] = depth;
+                }
+            }
+        }
+    }
+}
+
+impl Renderer for HeadlessRenderer {
+    fn set_flat_color(&mut self, color: ddgr_color) {
+        self.flat_color = color & 0xFFFFFF;
+    }
+
+    fn draw_font_char(&mut self, font_graphic: &FontGraphic, glyph: &FontGlyph) {
+        let (data, char_width, _) = font_graphic.clone_char_bitmap(glyph.character_index);
+        let rect = &glyph.draw_rect;
+        let width = self.target.width();
+
+        for y in rect.y1..rect.y2.min(self.target.height()) {
+            for x in rect.x1..rect.x2.min(width) {
+                let Some(&pixel) = data.get((y - rect.y1) * char_width + (x - rect.x1)) else {
+                    continue;
+                };
+
+                let index = y * width + x;
+                let dst = self.target.data()[index];
+                self.target.data_mut()[index] = 0xFF000000 | alpha_blend(pixel_4444_to_32(pixel), dst);
+            }
+        }
+    }
+
+    fn set_texture_type(&mut self, _texture_type: TextureType) {}
+
+    fn set_overlay_type(&mut self, _overlay_type: OverlayTextureType) {}
+
+    fn set_filtering(&mut self, _state: i8) {}
+
+    fn set_lighting(&mut self, state: LightStateType) {
+        self.lighting = !matches!(state, LightStateType::None);
+    }
+
+    fn set_alpha_type(&mut self, state: AlphaType) {
+        self.alpha_type = state;
+    }
+
+    fn set_color_model(&mut self, _state: ColorModelType) {}
+
+    fn set_zbuffer_state(&mut self, state: i8) {
+        self.zbuffer = state != 0;
+    }
+
+    fn set_alpha_value(&mut self, value: u8) {
+        self.alpha_value = value;
+    }
+
+    fn get_projection_screen_rect(&self) -> ScreenViewPort {
+        ScreenViewPort {
+            x: 0,
+            y: 0,
+            width: self.target.width(),
+            height: self.target.height(),
+            aspect: self.target.width() as f32 / self.target.height().max(1) as f32,
+        }
+    }
+
+    fn set_texture(&mut self, texture: Option<TextureHandle>) {
+        self.texture = texture;
+    }
+
+    fn draw_polygon(&mut self, vertices: &[RenderVertex]) {
+        if vertices.len() < 3 {
+            return;
+        }
+
+        for i in 1..vertices.len() - 1 {
+            self.draw_triangle(&vertices[0], &vertices[i], &vertices[i + 1]);
+        }
+
+        self.polygons_drawn += 1;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphics::{generic_bitmap::GenericBitmap16, BitmapId, GR_BLACK, GR_BLUE, GR_GREEN, GR_RED};
+
+    fn vertex(x: f32, y: f32, z: f32, u: f32, v: f32) -> RenderVertex {
+        RenderVertex { screen_x: x, screen_y: y, z, u, v, r: 1.0, g: 1.0, b: 1.0, a: 1.0 }
+    }
+
+    /// The whole of a 4x4 target at one depth, mapped 0 to 1 both ways
+    fn full_screen(z: f32) -> [RenderVertex; 4] {
+        [vertex(0.0, 0.0, z, 0.0, 0.0), vertex(4.0, 0.0, z, 1.0, 0.0), vertex(4.0, 4.0, z, 1.0, 1.0), vertex(0.0, 4.0, z, 0.0, 1.0)]
+    }
+
+    fn count(renderer: &HeadlessRenderer, color: ddgr_color) -> usize {
+        renderer.target().data().iter().filter(|&&p| p == 0xFF000000 | color).count()
+    }
+
+    #[test]
+    fn headless_coverage_test() {
+        crate::test_common::setup();
+
+        let mut renderer = HeadlessRenderer::new(4, 4);
+        renderer.clear(GR_BLACK);
+        renderer.set_flat_color(GR_RED);
+
+        // Pixel centers on the long edge are left to whatever's on the other side of it, either winding
+        renderer.draw_polygon(&[vertex(0.0, 0.0, 1.0, 0.0, 0.0), vertex(4.0, 0.0, 1.0, 0.0, 0.0), vertex(0.0, 4.0, 1.0, 0.0, 0.0)]);
+        assert_eq!(count(&renderer, GR_RED), 6);
+        assert_eq!(renderer.target().pixel(3, 3), Some(0xFF000000));
+
+        renderer.clear(GR_BLACK);
+        renderer.draw_polygon(&[vertex(0.0, 4.0, 1.0, 0.0, 0.0), vertex(4.0, 0.0, 1.0, 0.0, 0.0), vertex(0.0, 0.0, 1.0, 0.0, 0.0)]);
+        assert_eq!(count(&renderer, GR_RED), 6);
+
+        // Off screen and degenerate polygons are fine
+        renderer.draw_polygon(&[vertex(-10.0, -10.0, 1.0, 0.0, 0.0), vertex(-5.0, -10.0, 1.0, 0.0, 0.0), vertex(-5.0, -5.0, 1.0, 0.0, 0.0)]);
+        renderer.draw_polygon(&[vertex(1.0, 1.0, 1.0, 0.0, 0.0); 3]);
+        assert_eq!(count(&renderer, GR_RED), 6);
+        assert_eq!(renderer.polygons_drawn(), 3);
+
+        assert_eq!(renderer.target().to_4444().data()[0], 0xFF00);
+        assert_eq!((renderer.get_projection_screen_rect().width, renderer.get_projection_screen_rect().aspect), (4, 1.0));
+    }
+
+    #[test]
+    fn headless_depth_test() {
+        crate::test_common::setup();
+
+        let mut renderer = HeadlessRenderer::new(4, 4);
+        renderer.clear(GR_BLACK);
+
+        renderer.set_flat_color(GR_RED);
+        renderer.draw_polygon(&full_screen(10.0));
+        renderer.set_flat_color(GR_GREEN);
+        renderer.draw_polygon(&[vertex(0.0, 0.0, 5.0, 0.0, 0.0), vertex(4.0, 0.0, 5.0, 0.0, 0.0), vertex(0.0, 4.0, 5.0, 0.0, 0.0)]);
+
+        // Farther away is hidden
+        renderer.set_flat_color(GR_BLUE);
+        renderer.draw_polygon(&full_screen(20.0));
+        assert_eq!((count(&renderer, GR_GREEN), count(&renderer, GR_RED)), (6, 10));
+
+        renderer.set_zbuffer_state(0);
+        renderer.draw_polygon(&full_screen(20.0));
+        assert_eq!(count(&renderer, GR_BLUE), 16);
+    }
+
+    #[test]
+    fn headless_texture_test() {
+        crate::test_common::setup();
+
+        let handle = TextureHandle::Bitmap(BitmapId(0));
+        let checker = GenericBitmap16::new(vec![0xFF00, 0xF0F0, 0xF0F0, 0xFF00], 2, 2);
+
+        let mut renderer = HeadlessRenderer::new(4, 4);
+        renderer.clear(GR_BLACK);
+        renderer.add_texture(handle, &checker);
+        renderer.set_texture(Some(handle));
+        renderer.draw_polygon(&full_screen(1.0));
+
+        let red = pixel_4444_to_32(0xFF00);
+        let green = pixel_4444_to_32(0xF0F0);
+        assert_eq!(renderer.target().pixel(0, 0), Some(red));
+        assert_eq!(renderer.target().pixel(3, 0), Some(green));
+        assert_eq!(renderer.target().pixel(1, 2), Some(green));
+        assert_eq!(renderer.target().pixel(3, 3), Some(red));
+
+        // Gouraud lighting darkens the texture
+        let mut dark = full_screen(0.5);
+        dark.iter_mut().for_each(|v| v.r = 0.6);
+        renderer.set_lighting(LightStateType::Gouraud);
+        renderer.draw_polygon(&dark);
+        assert_eq!(renderer.target().pixel(0, 0), Some(0xFF990000));
+    }
+
+    #[test]
+    fn headless_alpha_test() {
+        crate::test_common::setup();
+
+        let mut renderer = HeadlessRenderer::new(4, 4);
+        renderer.clear(GR_BLACK);
+        renderer.set_flat_color(GR_RED);
+        renderer.set_alpha_type(AlphaType::CONSTANT);
+        renderer.set_alpha_value(128);
+        renderer.draw_polygon(&full_screen(1.0));
+        assert_eq!(renderer.target().pixel(2, 2), Some(0xFF800000));
+
+        // Saturating adds, and leaves the zbuffer alone
+        renderer.set_flat_color(GR_BLUE);
+        renderer.set_alpha_type(AlphaType::SATURATE_VERTEX);
+        renderer.draw_polygon(&full_screen(2.0));
+        renderer.draw_polygon(&full_screen(2.0));
+        assert_eq!(renderer.target().pixel(2, 2), Some(0xFF8000FF));
+    }
+}
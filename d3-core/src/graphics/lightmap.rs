@@ -107,6 +107,39 @@ impl LightMap16 {
         self.is_updated = true;
         &mut self.data
     }
+
+    /// The changed area (x1, y1, x2, y2), only meaningful with the `Limits` flag
+    pub fn deltas(&self) -> (u8, u8, u8, u8) {
+        (self.x1_delta, self.y1_delta, self.x2_delta, self.y2_delta)
+    }
+
+    /// Marks part of the lightmap as changed so only that area has to be uploaded,
+    /// grows the area if another part already changed this frame
+    pub fn mark_region_updated(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
+        let clamp = |v: usize, size: usize| v.min(size.saturating_sub(1)).min(u8::MAX as usize) as u8;
+        let (mut x1, mut y1, mut x2, mut y2) = (clamp(x1, self.width), clamp(y1, self.height), clamp(x2, self.width), clamp(y2, self.height));
+
+        if self.is_updated {
+            if !self.flags.contains(LightMapFlags::Limits) {
+                // Already going up whole
+                return;
+            }
+
+            x1 = x1.min(self.x1_delta);
+            y1 = y1.min(self.y1_delta);
+            x2 = x2.max(self.x2_delta);
+            y2 = y2.max(self.y2_delta);
+        }
+
+        self.flags.insert(LightMapFlags::Limits);
+        self.set_deltas(x1, y1, x2, y2);
+    }
+
+    /// Called once the renderer has the latest data
+    pub fn clear_updated(&mut self) {
+        self.is_updated = false;
+        self.flags.remove(LightMapFlags::Limits);
+    }
 }
 
 impl GpuMemoryResource for LightMap16 {
@@ -11,8 +11,12 @@ pub mod dd_video;
 pub mod rendering;
 pub mod bitmap;
 pub mod bumpmap;
+pub mod color_remap;
+pub mod headless_renderer;
 pub mod lightmap;
 pub mod render_context;
+pub mod screen_effects;
+pub mod screen_fade;
 pub mod drawing_2d;
 pub mod polymodel;
 pub mod texture;
@@ -75,12 +79,28 @@ pub const GR_WHITE: ddgr_color = 0x00ffffff;
 // ASCII 1 and (r,g,b) changes current text color in string.
 pub const GR_COLOR_CHAR: u32 = 1;
 
-pub enum MapSourceType16 {
-    Bitmap(&SharedMutRef<dyn Bitmap16>),
-    LightMap(&LightMap16),
-    BumpMap(&BumpMap16),
+/// Slot of a bitmap in the render context's cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BitmapId(pub(crate) usize);
+
+/// Slot of a lightmap in the render context's cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightMapId(pub(crate) usize);
+
+/// Slot of a bumpmap in the render context's cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BumpMapId(pub(crate) usize);
+
+/// Something a polygon can be mapped with, looked up through `RenderContext::resolve` when drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureHandle {
+    Bitmap(BitmapId),
+    LightMap(LightMapId),
+    BumpMap(BumpMapId),
 }
 
+pub type MapSourceType16 = TextureHandle;
+
 #[derive(Debug, Clone, Copy)]
 pub struct UVCoord {
     pub u: f32,
@@ -166,6 +186,132 @@ pub trait DrawableResource {
 }
 
 pub mod color_conversion {
+    use std::sync::RwLock;
+
+    use super::{dd_video::compute_gamma_table, OPAQUE_FLAG16};
+
+    /// Display correction for the 16-bit path, applied to each 8-bit channel when converting to 32-bit
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct GammaTable {
+        gamma: f32,
+        brightness: f32,
+        table: [u8; 256],
+    }
+
+    impl Default for GammaTable {
+        fn default() -> Self {
+            Self::new(1.0, 0.0)
+        }
+    }
+
+    impl GammaTable {
+        /// `brightness` is an offset from -1 to 1 added after the gamma curve
+        pub fn new(gamma: f32, brightness: f32) -> Self {
+            let brightness = brightness.clamp(-1.0, 1.0);
+            let mut table = compute_gamma_table(gamma);
+
+            for v in table.iter_mut() {
+                *v = (*v as f32 + brightness * 255.0).round().clamp(0.0, 255.0) as u8;
+            }
+
+            Self { gamma, brightness, table }
+        }
+
+        pub fn gamma(&self) -> f32 {
+            self.gamma
+        }
+
+        pub fn brightness(&self) -> f32 {
+            self.brightness
+        }
+
+        pub fn is_identity(&self) -> bool {
+            self.table.iter().enumerate().all(|(i, &v)| v as usize == i)
+        }
+
+        #[inline]
+        pub fn apply_channel(&self, value: u8) -> u8 {
+            self.table[value as usize]
+        }
+
+        /// Corrects the color channels, alpha is left as is
+        #[inline]
+        pub fn apply(&self, argb: u32) -> u32 {
+            let r = self.table[((argb >> 16) & 0xFF) as usize] as u32;
+            let g = self.table[((argb >> 8) & 0xFF) as usize] as u32;
+            let b = self.table[(argb & 0xFF) as usize] as u32;
+
+            (argb & 0xFF00_0000) | (r << 16) | (g << 8) | b
+        }
+
+        pub fn apply_slice(&self, buffer: &mut [u32]) {
+            if self.is_identity() {
+                return;
+            }
+
+            for color in buffer.iter_mut() {
+                *color = self.apply(*color);
+            }
+        }
+    }
+
+    lazy_static! {
+        static ref DISPLAY_GAMMA: RwLock<GammaTable> = RwLock::new(GammaTable::default());
+    }
+
+    /// Changes the correction used by the `*_for_display` conversions, takes effect on the next conversion
+    pub fn set_display_gamma(gamma: f32, brightness: f32) {
+        *DISPLAY_GAMMA.write().unwrap() = GammaTable::new(gamma, brightness);
+    }
+
+    pub fn display_gamma() -> GammaTable {
+        DISPLAY_GAMMA.read().unwrap().clone()
+    }
+
+    /// `convert_1555_to_32` with the display gamma applied
+    pub fn convert_1555_to_32_for_display(buffer: &[u16]) -> Vec<u32> {
+        let mut buffer_32 = convert_1555_to_32(buffer);
+        DISPLAY_GAMMA.read().unwrap().apply_slice(&mut buffer_32);
+        buffer_32
+    }
+
+    /// `convert_4444_to_32` with the display gamma applied
+    pub fn convert_4444_to_32_for_display(buffer: &[u16]) -> Vec<u32> {
+        let mut buffer_32 = convert_4444_to_32(buffer);
+        DISPLAY_GAMMA.read().unwrap().apply_slice(&mut buffer_32);
+        buffer_32
+    }
+
+    /// 1555 pattern for the brightness calibration screen
+    ///
+    /// The top half is a ramp of `steps` gray bands from black to white. The bottom half
+    /// has alternating black and white lines on the left next to a solid 50% gray on the right,
+    /// both sides look the same once the gamma is right.
+    pub fn gamma_test_pattern(width: usize, height: usize, steps: usize) -> Vec<u16> {
+        let steps = steps.clamp(2, 32);
+        let gray = |level: u16| OPAQUE_FLAG16 | (level << 10) | (level << 5) | level;
+
+        let mut buffer = vec![gray(0); width * height];
+
+        for y in 0..height {
+            for x in 0..width {
+                let color = if y < height / 2 {
+                    let band = x * steps / width.max(1);
+                    gray((band * 31 / (steps - 1)) as u16)
+                }
+                else if x < width / 2 {
+                    if y & 1 == 0 { gray(31) } else { gray(0) }
+                }
+                else {
+                    gray(16)
+                };
+
+                buffer[y * width + x] = color;
+            }
+        }
+
+        buffer
+    }
 
     pub fn alpha_blend(src_color: u32, dst_color: u32) -> u32 {
         // Extract ARGB components from src_color
@@ -215,47 +361,67 @@ pub mod color_conversion {
         (alpha << 24) | (red << 16) | (green << 8) | blue
     }
 
-    pub fn convert_4444_to_32(buffer: &[u16]) -> Vec<u32> {
-        let mut buffer_32 = vec![0u32; buffer.len()];
-        let mut i = 0;
+    pub fn pixel_4444_to_32(color: u16) -> u32 {
+        let a = ((color >> 12) & 0xF) as u32;
+        let r = ((color >> 8) & 0xF) as u32;
+        let g = ((color >> 4) & 0xF) as u32;
+        let b = (color & 0xF) as u32;
 
-        for &color in buffer {
-            let a = ((color >> 12) & 0xF) as u32;
-            let r = ((color >> 8) & 0xF) as u32;
-            let g = ((color >> 4) & 0xF) as u32;
-            let b = (color & 0xF) as u32;
-    
-            // Convert 4-bit colors to 8-bit colors
-            let a = (a * 255 / 15) << 24;
-            let r = (r * 255 / 15) << 16;
-            let g = (g * 255 / 15) << 8;
-            let b = b * 255 / 15;
-    
-            buffer_32[i] = a | r | g | b;
-            i += 1;
+        // Convert 4-bit colors to 8-bit colors
+        let a = (a * 255 / 15) << 24;
+        let r = (r * 255 / 15) << 16;
+        let g = (g * 255 / 15) << 8;
+        let b = b * 255 / 15;
+
+        a | r | g | b
+    }
+
+    pub fn pixel_1555_to_32(color: u16) -> u32 {
+        // Extract individual components from the 15-bit ARGB1555 value
+        let a = if (color & 0x8000) != 0 { 0xFFu32 } else { 0x0032 }; // Alpha: 1-bit
+        let r = ((color as u32 >> 10) & 0x1F) * 255 / 31;              // Red: 5-bit
+        let g = ((color as u32 >> 5) & 0x1F) * 255 / 31;               // Green: 5-bit
+        let b = (color as u32 & 0x1F) * 255 / 31;                      // Blue: 5-bit
+
+        (a << 24) | (r << 16) | (g << 8) | b
+    }
+
+    // Every 16-bit color precomputed, 256K each, so whole surfaces convert with one load per pixel
+    lazy_static! {
+        static ref TABLE_4444_TO_32: Box<[u32]> = (0..=u16::MAX).map(pixel_4444_to_32).collect();
+        static ref TABLE_1555_TO_32: Box<[u32]> = (0..=u16::MAX).map(pixel_1555_to_32).collect();
+    }
+
+    fn convert_with_table(table: &[u32], buffer: &[u16], buffer_32: &mut [u32]) {
+        assert_eq!(buffer.len(), buffer_32.len(), "Source and destination sizes differ");
+
+        for (dst, &color) in buffer_32.iter_mut().zip(buffer) {
+            *dst = table[color as usize];
         }
+    }
 
+    pub fn convert_4444_to_32(buffer: &[u16]) -> Vec<u32> {
+        let mut buffer_32 = vec![0u32; buffer.len()];
+        convert_4444_to_32_into(buffer, &mut buffer_32);
         buffer_32
     }
 
+    /// Converts into the caller's buffer, which has to be the same size as the source
+    pub fn convert_4444_to_32_into(buffer: &[u16], buffer_32: &mut [u32]) {
+        convert_with_table(&TABLE_4444_TO_32, buffer, buffer_32);
+    }
+
     pub fn convert_1555_to_32(buffer: &[u16]) -> Vec<u32> {
         let mut buffer_32 = vec![0u32; buffer.len()];
-        let mut i = 0;
-
-        for &color in buffer {
-            // Extract individual components from the 15-bit ARGB1555 value
-            let a = if (color & 0x8000) != 0 { 0xFFu32 } else { 0x0032 }; // Alpha: 1-bit
-            let r = ((color as u32 >> 10) & 0x1F) * 255 / 31;              // Red: 5-bit
-            let g = ((color as u32 >> 5) & 0x1F) * 255 / 31;               // Green: 5-bit
-            let b = (color as u32 & 0x1F) * 255 / 31;                      // Blue: 5-bit
-    
-            buffer_32[i] = (a << 24) | (r << 16) | (g << 8) | b;
-            i += 1;
-        }
-
+        convert_1555_to_32_into(buffer, &mut buffer_32);
         buffer_32
     }
 
+    /// Converts into the caller's buffer, which has to be the same size as the source
+    pub fn convert_1555_to_32_into(buffer: &[u16], buffer_32: &mut [u32]) {
+        convert_with_table(&TABLE_1555_TO_32, buffer, buffer_32);
+    }
+
     pub fn convert_4444_to_grayscale(color: u16) -> u8 {
         // Extract 4-bit red, green, and blue channels
         let red = ((color >> 12) & 0xF) as u8;   // 4 bits
@@ -308,4 +474,60 @@ pub mod color_conversion {
             })
             .collect()
     }
+
+    #[cfg(test)]
+    pub mod tests {
+        use super::*;
+
+        #[test]
+        fn gamma_table_test() {
+            crate::test_common::setup();
+
+            assert!(GammaTable::default().is_identity());
+
+            let bright = GammaTable::new(2.2, 0.0);
+            assert_eq!(bright.apply(0x80FF_0000) >> 24, 0x80);
+            assert_eq!(bright.apply(0xFF00_00FF) & 0xFF, 0xFF);
+            assert!(bright.apply_channel(0x40) > 0x40);
+
+            let dim = GammaTable::new(1.0, -0.5);
+            assert_eq!(dim.apply_channel(0x40), 0);
+            assert_eq!(dim.apply_channel(0xFF), 0x80);
+
+            // 1555 white stays white and black stays black with any gamma
+            set_display_gamma(2.2, 0.0);
+            assert_eq!(convert_1555_to_32_for_display(&[0xFFFF, 0x8000]), vec![0xFFFF_FFFF, 0xFF00_0000]);
+            set_display_gamma(1.0, 0.0);
+        }
+
+        #[test]
+        fn convert_table_test() {
+            crate::test_common::setup();
+
+            let all: Vec<u16> = (0..=u16::MAX).collect();
+
+            let converted = convert_1555_to_32(&all);
+            assert!(all.iter().all(|&c| converted[c as usize] == pixel_1555_to_32(c)));
+
+            let mut converted = vec![0u32; all.len()];
+            convert_4444_to_32_into(&all, &mut converted);
+            assert!(all.iter().all(|&c| converted[c as usize] == pixel_4444_to_32(c)));
+
+            assert_eq!(convert_1555_to_32(&[0x7C00, 0x801F]), vec![0x32FF_0000, 0xFF00_00FF]);
+            assert_eq!(convert_4444_to_32(&[0xF0F0]), vec![0xFF00_FF00]);
+        }
+
+        #[test]
+        fn gamma_test_pattern_test() {
+            let pattern = gamma_test_pattern(64, 4, 4);
+
+            assert_eq!(pattern[0], 0x8000);
+            assert_eq!(pattern[63], 0xFFFF);
+            assert_eq!(pattern[16] & 0x1F, 10);
+
+            // Lines on the left, 50% gray on the right
+            assert_eq!((pattern[2 * 64], pattern[3 * 64]), (0xFFFF, 0x8000));
+            assert_eq!(pattern[3 * 64 + 40] & 0x1F, 16);
+        }
+    }
 }
\ No newline at end of file
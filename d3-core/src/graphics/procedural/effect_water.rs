@@ -365,7 +365,7 @@ impl EmitterEffect for WaterEffect {
                     }
                 }
                 else {
-                    warn!("Water easter egg source image not correct resolution");
+                    warn!(target: "render", "Water easter egg source image not correct resolution");
                 }
             }
 
@@ -5,7 +5,7 @@ use core::{
 use std::{io::Read, rc::Rc, sync::Arc};
 
 use crate::{
-    common::SharedMutRef, graphics::OPAQUE_FLAG, math::vector2d::Vector2D, rand::ps_rand, string::D3String
+    common::SharedMutRef, graphics::OPAQUE_FLAG, math::{noise::Perlin, vector2d::Vector2D}, rand::ps_rand, string::D3String
 };
 
 use super::{
@@ -23,6 +23,16 @@ use tinyrand::{Rand, StdRand};
 
 use once_cell::sync::Lazy;
 
+use effect_cone::ConeEffect;
+use effect_fall::FallEffect;
+use effect_fire::{FireEffect, FireEmitterEffect, FireModel};
+use effect_fountain::FountainEffect;
+use effect_lightning::{LightningEffect, SphereLightningEffect};
+use effect_random_ember::RandomEmberEffect;
+use effect_rising_ember::RisingEmberEffect;
+use effect_roamer::RoamerEffect;
+use effect_water::WaterEffect;
+
 pub mod effect_cone;
 pub mod effect_fall;
 pub mod effect_fire;
@@ -38,8 +48,6 @@ pub mod water_effects;
 pub mod tests;
 
 const BRIGHT_COLOR: u8 = 254;
-const TABLE_SIZE: usize = 256;
-const TABLE_MASK: usize = TABLE_SIZE - 1;
 const PROC_SIZE: usize = 128;
 const EMITTER_LIMIT: usize = 10;
 
@@ -69,10 +77,6 @@ const fn generate_default_palette() -> [u16; ProcPalette::SIZE] {
     palette
 }
 
-fn lerp(t: f32, x0: f32, x1: f32) -> f32 {
-    x0 + t * (x1 - x0)
-}
-
 // Used for the represented type
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum EmitterType {
@@ -105,6 +109,112 @@ pub enum WaterEmitterType {
     BlobDrops,
 }
 
+impl EmitterType {
+    pub const ALL: [EmitterType; 15] = [
+        EmitterType::Fire(FireEmitterType::LineLightning),
+        EmitterType::Fire(FireEmitterType::SphereLightning),
+        EmitterType::Fire(FireEmitterType::Straight),
+        EmitterType::Fire(FireEmitterType::RisingEmber),
+        EmitterType::Fire(FireEmitterType::RandomEmbers),
+        EmitterType::Fire(FireEmitterType::Spinners),
+        EmitterType::Fire(FireEmitterType::Roamers),
+        EmitterType::Fire(FireEmitterType::Fountain),
+        EmitterType::Fire(FireEmitterType::Cone),
+        EmitterType::Fire(FireEmitterType::FallRight),
+        EmitterType::Fire(FireEmitterType::FallLeft),
+        EmitterType::Water(WaterEmitterType::HeightBlob),
+        EmitterType::Water(WaterEmitterType::SineBlob),
+        EmitterType::Water(WaterEmitterType::RainDrops),
+        EmitterType::Water(WaterEmitterType::BlobDrops),
+    ];
+
+    /// Names as the original editor listed them
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmitterType::Fire(FireEmitterType::LineLightning) => "Line Lightning",
+            EmitterType::Fire(FireEmitterType::SphereLightning) => "Sphere lightning",
+            EmitterType::Fire(FireEmitterType::Straight) => "Straight",
+            EmitterType::Fire(FireEmitterType::RisingEmber) => "Rising Embers",
+            EmitterType::Fire(FireEmitterType::RandomEmbers) => "Random Embers",
+            EmitterType::Fire(FireEmitterType::Spinners) => "Spinners",
+            EmitterType::Fire(FireEmitterType::Roamers) => "Roamers",
+            EmitterType::Fire(FireEmitterType::Fountain) => "Fountain",
+            EmitterType::Fire(FireEmitterType::Cone) => "Cone",
+            EmitterType::Fire(FireEmitterType::FallRight) => "Fall Right",
+            EmitterType::Fire(FireEmitterType::FallLeft) => "Fall Left",
+            EmitterType::Water(WaterEmitterType::HeightBlob) => "Height blob",
+            EmitterType::Water(WaterEmitterType::SineBlob) => "Sine Blob",
+            EmitterType::Water(WaterEmitterType::RainDrops) => "Random Raindrops",
+            EmitterType::Water(WaterEmitterType::BlobDrops) => "Random Blobdrops",
+        }
+    }
+
+    pub fn is_water(&self) -> bool {
+        matches!(self, EmitterType::Water(_))
+    }
+
+    /// None for the types that have no effect written yet
+    fn create_effect(&self, thickness: u8, light: i32) -> Option<Box<dyn EmitterEffect>> {
+        let fire: Box<dyn FireEmitterEffect> = match self {
+            EmitterType::Fire(FireEmitterType::LineLightning) => Box::new(LightningEffect),
+            EmitterType::Fire(FireEmitterType::SphereLightning) => Box::new(SphereLightningEffect),
+            EmitterType::Fire(FireEmitterType::RisingEmber) => Box::new(RisingEmberEffect::default()),
+            EmitterType::Fire(FireEmitterType::RandomEmbers) => Box::new(RandomEmberEffect::default()),
+            EmitterType::Fire(FireEmitterType::Roamers) => Box::new(RoamerEffect::default()),
+            EmitterType::Fire(FireEmitterType::Fountain) => Box::new(FountainEffect::default()),
+            EmitterType::Fire(FireEmitterType::Cone) => Box::new(ConeEffect::default()),
+            EmitterType::Fire(FireEmitterType::FallRight) => Box::new(FallEffect::<0>::default()),
+            EmitterType::Fire(FireEmitterType::FallLeft) => Box::new(FallEffect::<1>::default()),
+            EmitterType::Fire(FireEmitterType::Straight | FireEmitterType::Spinners) => return None,
+            EmitterType::Water(water) => {
+                let mut effect = match water {
+                    WaterEmitterType::HeightBlob => WaterEffect::new(water_effects::HeightBlobWaterEffect),
+                    WaterEmitterType::SineBlob => WaterEffect::new(water_effects::SineBlobWaterEffect),
+                    WaterEmitterType::RainDrops => WaterEffect::new(water_effects::RainDropsWaterEffect),
+                    WaterEmitterType::BlobDrops => WaterEffect::new(water_effects::BlobDropsWaterEffect),
+                };
+
+                effect.set_thickness(thickness);
+                effect.set_light(light);
+
+                return Some(Box::new(effect));
+            }
+        };
+
+        Some(Box::new(FireEffect { effect: fire }))
+    }
+}
+
+/// Plain description of an emitter, what an editor or level file stores
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EmitterSettings {
+    pub kind: EmitterType,
+    pub frequency: usize,
+    pub speed: u8,
+    pub color: u8,
+    pub size: u8,
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+impl Default for EmitterSettings {
+    fn default() -> Self {
+        Self {
+            kind: EmitterType::Fire(FireEmitterType::RisingEmber),
+            frequency: 0,
+            speed: 1,
+            color: BRIGHT_COLOR,
+            size: 1,
+            x1: PROC_SIZE as f32 / 2.0,
+            y1: PROC_SIZE as f32 / 2.0,
+            x2: PROC_SIZE as f32,
+            y2: PROC_SIZE as f32,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 struct DoubleBufferStorage {
     memory: [Option<Vec<u16>>; 2],
@@ -331,10 +441,17 @@ pub struct ProceduralBitmap16 {
 
     #[builder(default=8)]
     osc_value: u8,
+
+    // Related to water emitters
+    #[builder(default)]
+    thickness: u8,
+
+    #[builder(default)]
+    light: i32,
 }
 
 impl ProceduralBitmap16Builder {
-    fn dest_bitmap(mut self, width: usize, height: usize) -> Self {
+    pub fn dest_bitmap(mut self, width: usize, height: usize) -> Self {
         self.dest_bitmap = Some(Some(vec![0u16; width * height]));
         self
     }
@@ -353,6 +470,72 @@ impl ProceduralBitmap16 {
         self.emitters.clear();
     }
 
+    /// Replaces every emitter and picks the fire model when any of them are fire,
+    /// emitters whose type has no effect yet are dropped
+    pub fn set_emitters(&mut self, settings: &[EmitterSettings]) {
+        self.emitters = settings
+            .iter()
+            .take(EMITTER_LIMIT)
+            .filter_map(|s| {
+                let effect = s.kind.create_effect(self.thickness, self.light)?;
+
+                Some(BaseEmitter {
+                    effect: Some(effect),
+                    frequency: s.frequency,
+                    speed: s.speed,
+                    color: s.color,
+                    size: s.size,
+                    x1: s.x1,
+                    y1: s.y1,
+                    x2: s.x2,
+                    y2: s.y2,
+                })
+            })
+            .collect();
+
+        self.model = if settings.iter().any(|s| !s.kind.is_water()) {
+            Some(Box::new(FireModel))
+        } else {
+            None
+        };
+    }
+
+    pub fn emitter_count(&self) -> usize {
+        self.emitters.len()
+    }
+
+    pub fn palette(&self) -> &ProcPalette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: ProcPalette) {
+        self.palette = palette;
+    }
+
+    pub fn heat(&self) -> u8 {
+        self.heat
+    }
+
+    pub fn set_heat(&mut self, heat: u8) {
+        self.heat = heat;
+    }
+
+    /// Water thickness swings towards `value` and back over `time`, 0 turns it off
+    pub fn set_oscillation(&mut self, time: f32, value: u8) {
+        self.osc_time = time;
+        self.osc_value = value;
+    }
+
+    pub fn oscillation(&self) -> (f32, u8) {
+        (self.osc_time, self.osc_value)
+    }
+
+    /// Only applies to water emitters added after the change
+    pub fn set_water(&mut self, thickness: u8, light: i32) {
+        self.thickness = thickness;
+        self.light = light;
+    }
+
     pub fn frame_count(&self) -> usize {
         self.frame_counter_ref.load(core::sync::atomic::Ordering::Relaxed)
     }
@@ -378,7 +561,7 @@ impl ProceduralBitmap16 {
             let bitmap = self.base_bitmap_ref.as_ref().unwrap().borrow();
 
             if bitmap.width() != PROC_SIZE {
-                error!(
+                error!(target: "render", 
                     "Couldn't evaluate procedural because its not {} x {}",
                     PROC_SIZE, PROC_SIZE
                 );
@@ -455,41 +638,23 @@ impl Bitmap16 for ProceduralBitmap16 {
         super::bitmap::BitmapFormat::Fmt1555
     }
 
-    fn make_funny(&mut self) {
-        todo!()
+    /// Only lasts until the next step draws over it
+    fn fill_debug_pattern(&mut self, kind: super::bitmap::DebugPattern) {
+        if let Some(dest) = self.dest_bitmap.as_mut() {
+            super::bitmap::write_debug_pattern(dest, PROC_SIZE, PROC_SIZE, super::bitmap::BitmapFormat::Fmt1555, kind);
+        }
     }
 }
 #[derive(Debug)]
 pub struct ProceduralCommon {
-    perm: [u8; TABLE_SIZE],
-    noise: [f32; TABLE_SIZE * 3],
+    noise: Perlin,
     fade: [u16; 32768],
 }
 
 static COMMON: Lazy<ProceduralCommon> = Lazy::new(|| {
-    let mut perm = [0u8; TABLE_SIZE];
-
-    let mut noise = [0.0f32; TABLE_SIZE * 3];
-
     /* Init the noise */
     let mut rand = crate::create_rng();
-
-    for i in 0..TABLE_SIZE {
-        let r = perm[i] = ps_rand(&mut rand) as u8;
-        let random_float: f32 = ps_rand(&mut rand) as f32 / std::i16::MAX as f32;
-        let z = 1.0 - 2.0 * random_float;
-
-        /* r is radius of x,y circle */
-        let r = (1.0 - z * z).sqrt();
-
-        /* theta is angle in (x,y) */
-        let random_float: f32 = ps_rand(&mut rand) as f32 / std::i16::MAX as f32;
-        let theta = 2.0 * 3.14 * random_float;
-
-        noise[i + 0] = r * theta.cos();
-        noise[i + 1] = r * theta.sin();
-        noise[i + 2] = z;
-    }
+    let noise = Perlin::new(rand.next_u64());
 
     /* Initialize the fade table */
     let mut fade_table = [0u16; 32768];
@@ -508,65 +673,13 @@ static COMMON: Lazy<ProceduralCommon> = Lazy::new(|| {
 
     ProceduralCommon {
         noise: noise,
-        perm: perm,
         fade: fade_table,
     }
 });
 
 impl ProceduralCommon {
-    // TODO: These can be in a different spot
-    // const NAMES: [&str; 11] = [
-    //     "Line Lightning",
-    //     "Sphere lightning",
-    //     "Straight",
-    //     "Rising Embers",
-    //     "Random Embers",
-    //     "Spinners",
-    //     "Roamers",
-    //     "Fountain",
-    //     "Cone",
-    //     "Fall Right",
-    //     "Fall Left",
-    // ];
-
-    // const WATER_PROC_NAMES: [&str; 4] = [
-    //     "Height blob",
-    //     "Sine Blob",
-    //     "Random Raindrops",
-    //     "Random Blobdrops",
-    // ];
-
-    fn perm(&self, x: usize) -> u8 {
-        self.perm[x & 0xFF]
-    }
-
-    fn index(&self, x: usize, y: usize) -> usize {
-        self.perm(x) as usize + self.perm(y) as usize
-    }
-
-    /// Gets a lattice point for our noise
-    fn grad_lattice(&self, x: usize, y: usize, fx: f32, fy: f32) -> f32 {
-        let i = self.index(x, y);
-        self.noise[i] * fx + self.noise[i + 1] * fy
-    }
-
     fn grad_noise(&self, x: f32, y: f32) -> f32 {
-        let ix = x.floor() as usize;
-        let fx0 = x - ix as f32;
-        let fx1 = fx0 - 1.0;
-        let wx = fx0; // smoothstep
-        let iy = y.floor().trunc() as usize;
-        let fy0 = y - iy as f32;
-        let fy1 = fy0 - 1.0;
-        let wy = fy0; // smoothstep
-        let vx0 = self.grad_lattice(ix, iy, fx0, fy0);
-        let vx1 = self.grad_lattice(ix + 1, iy, fx1, fy0);
-        let vy0 = lerp(wx, vx0, vx1);
-        let vx0 = self.grad_lattice(ix, iy + 1, fx0, fy1);
-        let vx1 = self.grad_lattice(ix + 1, iy + 1, fx1, fy1);
-        let vy1 = lerp(wx, vx0, vx1);
-
-        lerp(wy, vy0, vy1)
+        self.noise.noise_2d(x, y)
     }
 }
 
@@ -572,3 +572,47 @@ fn procedurals_test() {
         None,
     );
 }
+
+#[test]
+fn emitter_settings_test() {
+    crate::test_common::setup();
+
+    let base = GenericBitmap16::new(vec![0u16; PROC_SIZE * PROC_SIZE], PROC_SIZE, PROC_SIZE);
+
+    let mut proc_bitmap = ProceduralBitmap16Builder::default()
+        .name("settings_proc")
+        .dest_bitmap(PROC_SIZE, PROC_SIZE)
+        .detail_settings_ref(crate::common::new_shared_mut_ref(DetailSettings {}))
+        .frame_counter_ref(FrameCounter::new(AtomicUsize::new(0)))
+        .base_bitmap_ref(crate::common::new_shared_mut_ref(base))
+        .system_clock_ref(Arc::new(crate::common::StdSystemClock))
+        .build()
+        .unwrap();
+
+    let settings: Vec<EmitterSettings> = EmitterType::ALL
+        .iter()
+        .filter(|k| !k.is_water())
+        .map(|&kind| EmitterSettings { kind, ..Default::default() })
+        .collect();
+
+    // Capped at the limit, and Straight and Spinners have no effect yet
+    proc_bitmap.set_emitters(&settings);
+    assert_eq!(proc_bitmap.emitter_count(), EMITTER_LIMIT - 2);
+    assert!(proc_bitmap.model.is_some());
+
+    for t in 0..10 {
+        proc_bitmap.step(t as f32);
+    }
+
+    let water = EmitterSettings {
+        kind: EmitterType::Water(WaterEmitterType::SineBlob),
+        ..Default::default()
+    };
+
+    proc_bitmap.set_water(4, 8);
+    proc_bitmap.set_emitters(&[water]);
+    assert_eq!(proc_bitmap.emitter_count(), 1);
+    assert!(proc_bitmap.model.is_none());
+
+    proc_bitmap.step(0.0);
+}
@@ -11,9 +11,13 @@ Initialize bumpmaps
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
-use super::bitmap::{self, Bitmap16};
+use super::bitmap::{self, Bitmap16, DebugPattern};
 use super::bumpmap::BumpMap16;
+use super::color_remap::{remap_bitmap, RemapPalette};
 use super::lightmap::LightMap16;
+use super::screen_effects::ScreenEffects;
+use super::{ddgr_color, BitmapId, BumpMapId, LightMapId, TextureHandle};
+use crate::filesystem::loader::{AssetLoader, AssetSource, LoadHandle, LoadedBitmap};
 use anyhow::Result;
 
 // pub trait CachedBitmap<T> {
@@ -21,43 +25,90 @@ use anyhow::Result;
 
 type BitmapEntry= dyn Bitmap16;
 
+/// What a `TextureHandle` points at once looked up
+pub enum ResolvedTexture<'a> {
+    Bitmap(&'a BitmapEntry),
+    LightMap(&'a LightMap16),
+    BumpMap(&'a BumpMap16),
+}
+
 // TODO: We want traits for generic bumpmaps and lightmaps
+#[derive(Default)]
 pub struct RenderContext {
-    bitmap_cache: HashMap<String, Box<BitmapEntry>>,
+    /// Bitmaps by slot, a `BitmapId` is the index
+    bitmap_cache: Vec<Box<BitmapEntry>>,
+    /// Slot of each bitmap by name
+    bitmap_ids: HashMap<String, BitmapId>,
     bumpmap_cache: Vec<BumpMap16>,
-    lightmap_cache: Vec<LightMap16>
+    lightmap_cache: Vec<LightMap16>,
+    /// Bitmaps being decoded in the background, swapped into the cache once ready
+    pending_bitmaps: Vec<LoadHandle<LoadedBitmap>>,
+    /// Laid over the finished frame
+    pub screen_effects: ScreenEffects,
 }
 
-// TODO: We to be able to access the bitmap cache based on name or slot number
-// We will know better on what to do when we futher in development
-
 impl RenderContext {
-    pub fn insert_bitmap(&mut self, id: String, bitmap: Box<BitmapEntry>) {
-        self.bitmap_cache.insert(id, bitmap);
+    /// Caches a bitmap under a name, replacing one with the same name keeps its slot
+    /// so handles already given out see the new one
+    pub fn insert_bitmap(&mut self, id: String, bitmap: Box<BitmapEntry>) -> BitmapId {
+        if let Some(slot) = self.bitmap_ids.get(&id) {
+            self.bitmap_cache[slot.0] = bitmap;
+            return *slot;
+        }
+
+        let slot = BitmapId(self.bitmap_cache.len());
+        self.bitmap_cache.push(bitmap);
+        self.bitmap_ids.insert(id, slot);
+        slot
+    }
+
+    pub fn bitmap_id(&self, id: &str) -> Option<BitmapId> {
+        self.bitmap_ids.get(id).copied()
+    }
+
+    pub fn bitmap(&self, id: BitmapId) -> Option<&BitmapEntry> {
+        self.bitmap_cache.get(id.0).map(|b| b.as_ref())
+    }
+
+    pub fn bitmap_mut(&mut self, id: BitmapId) -> Option<&mut BitmapEntry> {
+        self.bitmap_cache.get_mut(id.0).map(|b| b.as_mut())
     }
 
     pub fn find_bitmap(&self, id: String) -> Option<&BitmapEntry> {
-        let result = self.bitmap_cache.get(&id);
+        let result = self.bitmap_id(&id);
 
         match result {
-            Some(b) => {
-               Some(b.as_ref())
+            Some(slot) => {
+               self.bitmap(slot)
             },
             None => None
         }
     }
 
     pub fn find_bitmap_mut(&mut self, id: String) -> Option<&mut BitmapEntry> {
-        let result = self.bitmap_cache.get_mut(&id);
+        let result = self.bitmap_id(&id);
 
         match result {
-            Some(b) => {
-               Some(b.as_mut())
+            Some(slot) => {
+               self.bitmap_mut(slot)
             },
             None => None
         }
     }
 
+    /// A copy of a bitmap recolored to `color`, made the first time it's asked for and cached after,
+    /// for team colored ship textures
+    pub fn tinted_bitmap(&mut self, source: BitmapId, color: ddgr_color) -> Option<BitmapId> {
+        let id = format!("#tint{}:{:06x}", source.0, color);
+
+        if let Some(slot) = self.bitmap_id(&id) {
+            return Some(slot);
+        }
+
+        let tinted = remap_bitmap(self.bitmap(source)?, &RemapPalette::tint(color));
+        Some(self.insert_bitmap(id, Box::new(tinted)))
+    }
+
     pub fn bitmap_exists(&self, id: String) -> bool {
         match self.find_bitmap(id) {
             Some(_) => true,
@@ -65,6 +116,74 @@ impl RenderContext {
         }
     }
 
+    pub fn insert_lightmap(&mut self, lightmap: LightMap16) -> LightMapId {
+        self.lightmap_cache.push(lightmap);
+        LightMapId(self.lightmap_cache.len() - 1)
+    }
+
+    pub fn lightmap(&self, id: LightMapId) -> Option<&LightMap16> {
+        self.lightmap_cache.get(id.0)
+    }
+
+    pub fn insert_bumpmap(&mut self, bumpmap: BumpMap16) -> BumpMapId {
+        self.bumpmap_cache.push(bumpmap);
+        BumpMapId(self.bumpmap_cache.len() - 1)
+    }
+
+    pub fn bumpmap(&self, id: BumpMapId) -> Option<&BumpMap16> {
+        self.bumpmap_cache.get(id.0)
+    }
+
+    /// Looks up what a handle points at, `None` for a handle from another context
+    pub fn resolve(&self, handle: TextureHandle) -> Option<ResolvedTexture<'_>> {
+        match handle {
+            TextureHandle::Bitmap(id) => self.bitmap(id).map(ResolvedTexture::Bitmap),
+            TextureHandle::LightMap(id) => self.lightmap(id).map(ResolvedTexture::LightMap),
+            TextureHandle::BumpMap(id) => self.bumpmap(id).map(ResolvedTexture::BumpMap),
+        }
+    }
+
+    /// Queues a bitmap on the loader unless it's already cached or on its way
+    pub fn request_bitmap(&mut self, loader: &AssetLoader, source: AssetSource, format: bitmap::BitmapFormat) {
+        let id = source.name();
+
+        if self.bitmap_exists(id.clone()) || self.pending_bitmaps.iter().any(|h| h.name() == id) {
+            return;
+        }
+
+        self.pending_bitmaps.push(loader.load_bitmap(source, format));
+    }
+
+    pub fn pending_bitmap_count(&self) -> usize {
+        self.pending_bitmaps.len()
+    }
+
+    /// Moves finished background loads into the cache, meant to be called once a frame.
+    /// Returns how many bitmaps were swapped in
+    pub fn swap_in_loaded_bitmaps(&mut self) -> usize {
+        let mut swapped = 0;
+        let mut still_pending = Vec::new();
+        let pending = std::mem::take(&mut self.pending_bitmaps);
+
+        for handle in pending {
+            match handle.try_take() {
+                Some(Ok(bitmap)) => {
+                    self.insert_bitmap(handle.name().to_string(), bitmap);
+                    swapped += 1;
+                },
+                Some(Err(e)) => {
+                    // Already logged by the loader, a later request can retry it
+                    debug!(target: "render", "dropping failed bitmap load {}: {}", handle.name(), e);
+                },
+                None => still_pending.push(handle)
+            }
+        }
+
+        self.pending_bitmaps = still_pending;
+
+        swapped
+    }
+
     pub fn change_end_name(id: &mut String, new_name: String) {
         todo!("Won't support this method!");
 
@@ -72,12 +191,12 @@ impl RenderContext {
         // Nor allow changing anything of the bitmap allocated in memory
     }
 
-    pub fn make_funny_bitmap(&mut self, id: String) {
+    pub fn fill_debug_bitmap(&mut self, id: String, kind: DebugPattern) {
         let mut bitmap = self.find_bitmap_mut(id);
 
         match bitmap {
             Some(b) => {
-                b.make_funny();
+                b.fill_debug_pattern(kind);
 
                 // TODO: need to set the update bit
             },
@@ -88,4 +207,31 @@ impl RenderContext {
     // XXX: bm_AllocLoadFileBitmap
     // This function feels like something used by the editor
     // Force the client side to deal with opening a data stream
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphics::generic_bitmap::GenericBitmap16;
+
+    #[test]
+    fn texture_handle_test() {
+        crate::test_common::setup();
+
+        let mut context = RenderContext::default();
+        let wall = context.insert_bitmap("wall.ogf".to_string(), Box::new(GenericBitmap16::new(vec![0; 4], 2, 2)));
+        let floor = context.insert_bitmap("floor.ogf".to_string(), Box::new(GenericBitmap16::new(vec![0; 16], 4, 4)));
+        assert_ne!(wall, floor);
+        assert_eq!(context.bitmap_id("floor.ogf"), Some(floor));
+
+        // Reloading under the same name keeps the handle good
+        let reloaded = context.insert_bitmap("wall.ogf".to_string(), Box::new(GenericBitmap16::new(vec![0; 64], 8, 8)));
+        assert_eq!(reloaded, wall);
+        assert_eq!(context.bitmap(wall).unwrap().width(), 8);
+
+        let lightmap = context.insert_lightmap(LightMap16::new(&[0; 4], 2, 2));
+        assert!(matches!(context.resolve(TextureHandle::LightMap(lightmap)), Some(ResolvedTexture::LightMap(_))));
+        assert!(matches!(context.resolve(TextureHandle::Bitmap(floor)), Some(ResolvedTexture::Bitmap(_))));
+        assert!(context.resolve(TextureHandle::BumpMap(BumpMapId(0))).is_none());
+    }
+}
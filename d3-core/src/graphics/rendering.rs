@@ -1,6 +1,6 @@
 use bitflags::bitflags;
 
-use super::{ddgr_color, drawing_2d::font::FontGlyph};
+use super::{ddgr_color, drawing_2d::font::FontGlyph, drawing_3d::Point3, TextureHandle};
 use crate::graphics::drawing_2d::font::FontGraphic;
 
 bitflags! {
@@ -121,6 +121,46 @@ pub enum ColorModelType {
     Rgb
 }
 
+/// A vertex as handed to the backend, already projected to the screen
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RenderVertex {
+    pub screen_x: f32,
+    pub screen_y: f32,
+    /// View space depth, for the zbuffer and perspective correction
+    pub z: f32,
+    pub u: f32,
+    pub v: f32,
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl From<&Point3> for RenderVertex {
+    fn from(p: &Point3) -> Self {
+        let uvl = &p.uvl;
+
+        // Mono lit points only set the intensity
+        let (r, g, b) = if uvl.light_r == 0.0 && uvl.light_g == 0.0 && uvl.light_b == 0.0 {
+            (uvl.light_intensity, uvl.light_intensity, uvl.light_intensity)
+        } else {
+            (uvl.light_r, uvl.light_g, uvl.light_b)
+        };
+
+        RenderVertex {
+            screen_x: p.screen_x,
+            screen_y: p.screen_y,
+            z: p.z(),
+            u: uvl.u,
+            v: uvl.v,
+            r,
+            g,
+            b,
+            a: uvl.light_a,
+        }
+    }
+}
+
 pub trait Renderer {
     fn set_flat_color(&mut self, color: ddgr_color);
 
@@ -145,4 +185,86 @@ pub trait Renderer {
 
     /// Gets LowerX, TopY, Width and Height coords of the screen
     fn get_projection_screen_rect(&self) -> super::drawing_3d::ScreenViewPort;
+
+    /// Sets the texture polygons get mapped with, `None` for flat polygons
+    fn set_texture(&mut self, texture: Option<TextureHandle>);
+
+    /// Draws a polygon as a fan around its first vertex with the current state
+    fn draw_polygon(&mut self, vertices: &[RenderVertex]);
+
+    /// Starts a run of triangles sharing a texture and alpha state, the state stays set afterwards.
+    /// Backends that build vertex buffers collect until `end_batch`, by default it just sets the state.
+    fn begin_batch(&mut self, texture: Option<TextureHandle>, alpha: AlphaType) {
+        self.set_texture(texture);
+        self.set_alpha_type(alpha);
+    }
+
+    /// Adds a triangle list to the batch, by default each triangle is drawn right away
+    fn push_vertices(&mut self, vertices: &[RenderVertex]) {
+        for triangle in vertices.chunks_exact(3) {
+            self.draw_polygon(triangle);
+        }
+    }
+
+    /// Submits whatever the batch collected
+    fn end_batch(&mut self) {}
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphics::drawing_3d::ScreenViewPort;
+
+    /// Records what it's asked to draw, for testing what sits in front of a backend
+    #[derive(Default)]
+    pub struct CountingRenderer {
+        pub texture: Option<TextureHandle>,
+        pub alpha: Option<AlphaType>,
+        pub polygons: Vec<(Option<TextureHandle>, usize)>,
+    }
+
+    impl Renderer for CountingRenderer {
+        fn set_flat_color(&mut self, _color: ddgr_color) {}
+        fn draw_font_char(&mut self, _font_graphic: &FontGraphic, _glyph: &FontGlyph) {}
+        fn set_texture_type(&mut self, _texture_type: TextureType) {}
+        fn set_overlay_type(&mut self, _overlay_type: OverlayTextureType) {}
+        fn set_filtering(&mut self, _state: i8) {}
+        fn set_lighting(&mut self, _state: LightStateType) {}
+
+        fn set_alpha_type(&mut self, state: AlphaType) {
+            self.alpha = Some(state);
+        }
+
+        fn set_color_model(&mut self, _state: ColorModelType) {}
+        fn set_zbuffer_state(&mut self, _state: i8) {}
+        fn set_alpha_value(&mut self, _value: u8) {}
+
+        fn get_projection_screen_rect(&self) -> ScreenViewPort {
+            ScreenViewPort { x: 0, y: 0, width: 640, height: 480, aspect: 640.0 / 480.0 }
+        }
+
+        fn set_texture(&mut self, texture: Option<TextureHandle>) {
+            self.texture = texture;
+        }
+
+        fn draw_polygon(&mut self, vertices: &[RenderVertex]) {
+            self.polygons.push((self.texture, vertices.len()));
+        }
+    }
+
+    #[test]
+    fn default_batch_test() {
+        crate::test_common::setup();
+
+        let mut renderer = CountingRenderer::default();
+        let texture = Some(TextureHandle::Bitmap(crate::graphics::BitmapId(3)));
+
+        // Two triangles and a stray vertex, which gets dropped
+        renderer.begin_batch(texture, AlphaType::TEXTURE);
+        renderer.push_vertices(&[RenderVertex::default(); 7]);
+        renderer.end_batch();
+
+        assert_eq!(renderer.alpha, Some(AlphaType::TEXTURE));
+        assert_eq!(renderer.polygons, vec![(texture, 3), (texture, 3)]);
+    }
 }
\ No newline at end of file
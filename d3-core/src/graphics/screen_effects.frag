@@ -0,0 +1,50 @@
+// Screen effects laid over the finished frame, the same passes as
+// graphics/screen_effects.rs runs for the software renderer
+
+#version 330 core
+
+uniform sampler2D frame;
+uniform vec2 frame_size;
+
+uniform vec2 flash_direction;
+uniform vec4 flash_color;
+uniform float static_amount;
+uniform uint static_seed;
+uniform float blur_streak;
+uniform vec4 fade_color;
+
+in vec2 uv;
+out vec4 color;
+
+const int BLUR_SAMPLES = 4;
+
+float noise(uvec2 p, uint seed) {
+    uint h = (p.x * 374761393u) ^ (p.y * 668265263u) ^ (seed * 2246822519u);
+    h = (h ^ (h >> 13u)) * 1274126177u;
+    h ^= h >> 16u;
+    return float(h & 0xFFFFu) / 65535.0;
+}
+
+void main() {
+    // Afterburner, smeared in from the middle
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < BLUR_SAMPLES; i++) {
+        float t = 1.0 - blur_streak * float(i) / float(BLUR_SAMPLES - 1);
+        sum += texture(frame, vec2(0.5) + (uv - vec2(0.5)) * t).rgb;
+    }
+    vec3 rgb = sum / float(BLUR_SAMPLES);
+
+    // Shield flash on the side the hit came from, uv runs bottom up
+    vec2 offset = uv * 2.0 - vec2(1.0);
+    float toward = max(dot(offset, flash_direction), 0.0);
+    rgb = mix(rgb, flash_color.rgb, clamp(flash_color.a * toward * toward, 0.0, 1.0));
+
+    // Static, keyed on the pixel the way the software pass is, top down
+    uvec2 pixel = uvec2(gl_FragCoord.x, frame_size.y - gl_FragCoord.y);
+    rgb = mix(rgb, vec3(noise(pixel, static_seed)), static_amount);
+
+    // Fade over everything
+    rgb = mix(rgb, fade_color.rgb, fade_color.a);
+
+    color = vec4(rgb, 1.0);
+}
@@ -0,0 +1,347 @@
+/*
+
+Screen effects
+
+Effects laid over the whole finished frame when the ship takes a beating:
+a flash on the side of the screen a shield hit came from, static crawling
+over the view while the hull is low, and streaks blurring out from the
+middle under afterburner.
+
+Each effect is a pass, and the passes run one after another. The software
+renderer runs them over its ARGB frame here. GPU backends draw a full
+screen quad with `SCREEN_EFFECTS_SHADER` instead, fed by `uniforms()`, so
+the two come out the same. A screen fade, if one is running, goes on last.
+
+*/
+
+use super::{ddgr_color, screen_fade::ScreenFade, GR_WHITE};
+
+/// The fragment shader GPU backends draw the effects with
+pub const SCREEN_EFFECTS_SHADER: &str = include_str!("screen_effects.frag");
+
+/// Seconds a shield hit flash takes to fade away
+pub const SHIELD_FLASH_TIME: f32 = 0.4;
+
+/// Below this much of its hull left the ship's view starts to break up
+pub const STATIC_HULL_THRESHOLD: f32 = 0.3;
+
+/// Seconds the afterburner blur takes to come on fully, and to go again
+pub const AFTERBURNER_RAMP_TIME: f32 = 0.25;
+
+/// How far toward the middle the blur reaches, as a share of the distance there, at full strength
+pub const AFTERBURNER_MAX_STREAK: f32 = 0.08;
+
+const BLUR_SAMPLES: usize = 4;
+
+/// A pass over the finished frame
+pub trait ScreenPass {
+    fn apply(&self, frame: &mut [u32], width: usize, height: usize);
+}
+
+fn blend(argb: u32, color: ddgr_color, alpha: f32) -> u32 {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let channel = |shift: u32| {
+        let from = ((argb >> shift) & 0xFF) as f32;
+        let to = ((color >> shift) & 0xFF) as f32;
+        ((from + (to - from) * alpha).round() as u32) << shift
+    };
+
+    (argb & 0xFF00_0000) | channel(16) | channel(8) | channel(0)
+}
+
+/// Where a pixel is from the middle of the screen, -1 to 1 across and up
+fn screen_offset(x: usize, y: usize, width: usize, height: usize) -> (f32, f32) {
+    let half_width = width as f32 / 2.0;
+    let half_height = height as f32 / 2.0;
+
+    ((x as f32 + 0.5 - half_width) / half_width, (half_height - y as f32 - 0.5) / half_height)
+}
+
+/// The same hash the shader uses, so the static looks alike on both
+fn noise(x: u32, y: u32, seed: u32) -> f32 {
+    let mut h = x.wrapping_mul(374_761_393) ^ y.wrapping_mul(668_265_263) ^ seed.wrapping_mul(2_246_822_519);
+    h = (h ^ (h >> 13)).wrapping_mul(1_274_126_177);
+    h ^= h >> 16;
+
+    (h & 0xFFFF) as f32 / 65535.0
+}
+
+/// Tints the edge of the screen toward where a hit came from
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShieldFlash {
+    /// Direction of the hit on screen, x across and y up
+    pub direction: (f32, f32),
+    pub color: ddgr_color,
+    pub intensity: f32,
+}
+
+impl ScreenPass for ShieldFlash {
+    fn apply(&self, frame: &mut [u32], width: usize, height: usize) {
+        for (i, pixel) in frame.iter_mut().enumerate().take(width * height) {
+            let (x, y) = screen_offset(i % width, i / width, width, height);
+            let toward = (x * self.direction.0 + y * self.direction.1).max(0.0);
+
+            if toward > 0.0 {
+                *pixel = blend(*pixel, self.color, self.intensity * toward * toward);
+            }
+        }
+    }
+}
+
+/// Grey noise over the view, `amount` is how much of the picture it replaces
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Static {
+    pub amount: f32,
+    /// Changed each frame so the noise crawls
+    pub seed: u32,
+}
+
+impl ScreenPass for Static {
+    fn apply(&self, frame: &mut [u32], width: usize, height: usize) {
+        for (i, pixel) in frame.iter_mut().enumerate().take(width * height) {
+            let level = (noise((i % width) as u32, (i / width) as u32, self.seed) * 255.0) as u32;
+            *pixel = blend(*pixel, (level << 16) | (level << 8) | level, self.amount);
+        }
+    }
+}
+
+/// Smears each pixel in from the middle of the screen, more so toward the edges
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AfterburnerBlur {
+    /// 0 to 1
+    pub strength: f32,
+}
+
+impl ScreenPass for AfterburnerBlur {
+    fn apply(&self, frame: &mut [u32], width: usize, height: usize) {
+        let streak = self.strength.clamp(0.0, 1.0) * AFTERBURNER_MAX_STREAK;
+
+        if streak <= 0.0 || width == 0 || height == 0 {
+            return;
+        }
+
+        let source = frame[..width * height].to_vec();
+        let (center_x, center_y) = (width as f32 / 2.0, height as f32 / 2.0);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 3];
+
+                for sample in 0..BLUR_SAMPLES {
+                    let t = 1.0 - streak * sample as f32 / (BLUR_SAMPLES - 1) as f32;
+                    let sx = ((center_x + (x as f32 - center_x) * t) as usize).min(width - 1);
+                    let sy = ((center_y + (y as f32 - center_y) * t) as usize).min(height - 1);
+                    let color = source[sy * width + sx];
+
+                    sum[0] += (color >> 16) & 0xFF;
+                    sum[1] += (color >> 8) & 0xFF;
+                    sum[2] += color & 0xFF;
+                }
+
+                let n = BLUR_SAMPLES as u32;
+                let pixel = &mut frame[y * width + x];
+                *pixel = (*pixel & 0xFF00_0000) | ((sum[0] / n) << 16) | ((sum[1] / n) << 8) | (sum[2] / n);
+            }
+        }
+    }
+}
+
+/// What the shader needs for a frame
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct ScreenEffectUniforms {
+    pub flash_direction: [f32; 2],
+    /// Red, green and blue from 0 to 1, then the intensity
+    pub flash_color: [f32; 4],
+    pub static_amount: f32,
+    pub static_seed: u32,
+    pub blur_streak: f32,
+    /// Red, green and blue from 0 to 1, then how much of the frame it covers
+    pub fade_color: [f32; 4],
+}
+
+/// The effects as they stand this frame, kept up to date by the game and laid over the frame last
+#[derive(Debug, Clone, Default)]
+pub struct ScreenEffects {
+    flash: Option<ShieldFlash>,
+    flash_time: f32,
+    static_amount: f32,
+    frame: u32,
+    afterburner: bool,
+    blur_strength: f32,
+    fade: Option<ScreenFade>,
+}
+
+impl ScreenEffects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A shield hit from `direction` in view space, x right and y up, `strength` 0 to 1.
+    /// A harder hit than the one still showing takes its place
+    pub fn shield_hit(&mut self, direction: (f32, f32), strength: f32, color: ddgr_color) {
+        let length = (direction.0 * direction.0 + direction.1 * direction.1).sqrt();
+        let strength = strength.clamp(0.0, 1.0);
+
+        // Straight on, from behind or in front, the flash goes all the way round
+        let direction = if length > f32::EPSILON { (direction.0 / length, direction.1 / length) } else { (0.0, 0.0) };
+
+        if self.flash.is_some_and(|f| f.intensity > strength) {
+            return;
+        }
+
+        self.flash = Some(ShieldFlash { direction, color, intensity: strength });
+        self.flash_time = SHIELD_FLASH_TIME;
+    }
+
+    /// How much of its hull the ship has left, 0 to 1
+    pub fn set_hull(&mut self, hull: f32) {
+        self.static_amount = if hull < STATIC_HULL_THRESHOLD {
+            0.5 * (1.0 - hull.max(0.0) / STATIC_HULL_THRESHOLD)
+        } else {
+            0.0
+        };
+    }
+
+    pub fn set_afterburner(&mut self, on: bool) {
+        self.afterburner = on;
+    }
+
+    pub fn update(&mut self, frametime: f32) {
+        self.frame = self.frame.wrapping_add(1);
+
+        if let Some(flash) = &mut self.flash {
+            self.flash_time -= frametime;
+
+            if self.flash_time <= 0.0 {
+                self.flash = None;
+            } else {
+                flash.intensity *= self.flash_time / (self.flash_time + frametime);
+            }
+        }
+
+        let step = frametime / AFTERBURNER_RAMP_TIME;
+        let target = if self.afterburner { 1.0 } else { 0.0 };
+        self.blur_strength = if self.blur_strength < target { (self.blur_strength + step).min(target) } else { (self.blur_strength - step).max(target) };
+    }
+
+    /// Starts a fade, taking over from any still running
+    pub fn start_fade(&mut self, fade: ScreenFade) {
+        self.fade = Some(fade);
+    }
+
+    pub fn fade(&self) -> Option<&ScreenFade> {
+        self.fade.as_ref()
+    }
+
+    /// Moves the fade on by real frametime, apart from `update` so a fade keeps going while the game is paused
+    pub fn update_fade(&mut self, real_frametime: f32) {
+        if let Some(fade) = &mut self.fade {
+            fade.update(real_frametime);
+
+            if fade.is_finished() {
+                self.fade = None;
+            }
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.flash.is_some() || self.static_amount > 0.0 || self.blur_strength > 0.0 || self.fade.is_some()
+    }
+
+    /// The passes to run this frame, in order. The blur goes first so the flash and static stay sharp
+    pub fn passes(&self) -> Vec<Box<dyn ScreenPass>> {
+        let mut passes: Vec<Box<dyn ScreenPass>> = Vec::new();
+
+        if self.blur_strength > 0.0 {
+            passes.push(Box::new(AfterburnerBlur { strength: self.blur_strength }));
+        }
+
+        if let Some(flash) = self.flash {
+            passes.push(Box::new(flash));
+        }
+
+        if self.static_amount > 0.0 {
+            passes.push(Box::new(Static { amount: self.static_amount, seed: self.frame }));
+        }
+
+        if let Some(fade) = self.fade {
+            passes.push(Box::new(fade));
+        }
+
+        passes
+    }
+
+    /// Runs every pass over a software frame
+    pub fn apply(&self, frame: &mut [u32], width: usize, height: usize) {
+        for pass in self.passes() {
+            pass.apply(frame, width, height);
+        }
+    }
+
+    pub fn uniforms(&self) -> ScreenEffectUniforms {
+        let flash = self.flash.unwrap_or(ShieldFlash { direction: (0.0, 0.0), color: GR_WHITE, intensity: 0.0 });
+        let channel = |color: ddgr_color, shift: u32| ((color >> shift) & 0xFF) as f32 / 255.0;
+        let (fade_color, fade_amount) = self.fade.map_or((GR_WHITE, 0.0), |fade| (fade.color, fade.amount()));
+
+        ScreenEffectUniforms {
+            flash_direction: [flash.direction.0, flash.direction.1],
+            flash_color: [channel(flash.color, 16), channel(flash.color, 8), channel(flash.color, 0), flash.intensity],
+            static_amount: self.static_amount,
+            static_seed: self.frame,
+            blur_streak: self.blur_strength * AFTERBURNER_MAX_STREAK,
+            fade_color: [channel(fade_color, 16), channel(fade_color, 8), channel(fade_color, 0), fade_amount],
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::graphics::{GR_BLACK, GR_RED};
+
+    #[test]
+    fn screen_effects_test() {
+        crate::test_common::setup();
+
+        let (width, height) = (8, 8);
+        let mut effects = ScreenEffects::new();
+        assert!(!effects.is_active());
+
+        // A hit from the right reddens the right edge and leaves the left alone
+        effects.shield_hit((2.0, 0.0), 1.0, GR_RED);
+        let mut frame = vec![0xFF00_0000 | GR_BLACK; width * height];
+        effects.apply(&mut frame, width, height);
+        assert!((frame[width * 4 + 7] >> 16) & 0xFF > 150);
+        assert_eq!(frame[width * 4], 0xFF00_0000);
+        assert_eq!(effects.uniforms().flash_direction, [1.0, 0.0]);
+
+        // A weaker hit doesn't cover it, and it's gone once faded
+        effects.shield_hit((-1.0, 0.0), 0.2, GR_RED);
+        assert_eq!(effects.uniforms().flash_direction, [1.0, 0.0]);
+        effects.update(SHIELD_FLASH_TIME);
+        assert!(!effects.is_active());
+
+        // Static only once the hull is low, the blur eases in
+        effects.set_hull(0.5);
+        assert_eq!(effects.passes().len(), 0);
+        effects.set_hull(0.0);
+        effects.set_afterburner(true);
+        effects.update(AFTERBURNER_RAMP_TIME / 2.0);
+        assert_eq!(effects.passes().len(), 2);
+        assert!((effects.uniforms().blur_streak - AFTERBURNER_MAX_STREAK / 2.0).abs() < 1e-6);
+        assert_eq!(effects.uniforms().static_amount, 0.5);
+
+        // A flat frame stays flat under the blur
+        let mut frame = vec![0xFF20_4060; width * height];
+        AfterburnerBlur { strength: 1.0 }.apply(&mut frame, width, height);
+        assert!(frame.iter().all(|&p| p == 0xFF20_4060));
+
+        // A fade goes over everything else and runs on its own clock
+        let mut effects = ScreenEffects::new();
+        effects.start_fade(ScreenFade::from_black(1.0));
+        effects.update(1.0);
+        assert_eq!(effects.uniforms().fade_color, [0.0, 0.0, 0.0, 1.0]);
+        effects.update_fade(1.0);
+        assert!(!effects.is_active());
+    }
+}
@@ -0,0 +1,147 @@
+/*
+
+Screen fades
+
+The whole frame fading out to a color or back in from one, black between
+levels and white for a blinding blast, and quick flashes that start at full
+and fade straight away. A fade out holds once it's done, so the screen stays
+black while the next level loads until a fade in takes over.
+
+A fade is the last thing laid over the frame, after the other screen effects
+and the HUD, so nothing shows through it.
+
+*/
+
+use super::{ddgr_color, screen_effects::ScreenPass, GR_BLACK, GR_WHITE};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FadeDirection {
+    /// From the picture to the color, then holds there
+    Out,
+    /// From the color back to the picture
+    In,
+    /// Straight to the color, then back to the picture
+    Flash,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreenFade {
+    pub direction: FadeDirection,
+    pub color: ddgr_color,
+    pub duration: f32,
+    elapsed: f32,
+}
+
+impl ScreenFade {
+    pub fn start(direction: FadeDirection, color: ddgr_color, duration: f32) -> Self {
+        Self {
+            direction,
+            color,
+            duration: duration.max(0.0),
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn to_black(duration: f32) -> Self {
+        Self::start(FadeDirection::Out, GR_BLACK, duration)
+    }
+
+    pub fn from_black(duration: f32) -> Self {
+        Self::start(FadeDirection::In, GR_BLACK, duration)
+    }
+
+    pub fn to_white(duration: f32) -> Self {
+        Self::start(FadeDirection::Out, GR_WHITE, duration)
+    }
+
+    pub fn from_white(duration: f32) -> Self {
+        Self::start(FadeDirection::In, GR_WHITE, duration)
+    }
+
+    pub fn flash(color: ddgr_color, duration: f32) -> Self {
+        Self::start(FadeDirection::Flash, color, duration)
+    }
+
+    /// Moves the fade on. Level transitions should pass real frametime so the fade still runs while the game is paused
+    pub fn update(&mut self, frametime: f32) {
+        self.elapsed = (self.elapsed + frametime.max(0.0)).min(self.duration);
+    }
+
+    /// How far through it is, 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.duration > 0.0 { self.elapsed / self.duration } else { 1.0 }
+    }
+
+    /// How much of the frame is covered by the color, 0 to 1
+    pub fn amount(&self) -> f32 {
+        match self.direction {
+            FadeDirection::Out => self.progress(),
+            FadeDirection::In | FadeDirection::Flash => 1.0 - self.progress(),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Whether there's nothing left to lay over the frame, a finished fade out still covers it
+    pub fn is_finished(&self) -> bool {
+        self.is_done() && self.direction != FadeDirection::Out
+    }
+}
+
+impl ScreenPass for ScreenFade {
+    fn apply(&self, frame: &mut [u32], width: usize, height: usize) {
+        let amount = self.amount();
+
+        if amount <= 0.0 {
+            return;
+        }
+
+        let alpha = (amount * 256.0) as u32;
+        let channel = |argb: u32, shift: u32| {
+            let from = (argb >> shift) & 0xFF;
+            let to = (self.color >> shift) & 0xFF;
+            ((from * (256 - alpha) + to * alpha) >> 8) << shift
+        };
+
+        for pixel in frame.iter_mut().take(width * height) {
+            *pixel = (*pixel & 0xFF00_0000) | channel(*pixel, 16) | channel(*pixel, 8) | channel(*pixel, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_fade_test() {
+        crate::test_common::setup();
+
+        let mut fade = ScreenFade::to_black(1.0);
+        let mut frame = vec![0xFF80_8080; 4];
+        fade.apply(&mut frame, 2, 2);
+        assert!(frame.iter().all(|&p| p == 0xFF80_8080));
+
+        // Halfway out, then holding black
+        fade.update(0.5);
+        fade.apply(&mut frame, 2, 2);
+        assert!(frame.iter().all(|&p| p == 0xFF40_4040));
+        fade.update(2.0);
+        assert!(fade.is_done() && !fade.is_finished());
+        fade.apply(&mut frame, 2, 2);
+        assert!(frame.iter().all(|&p| p == 0xFF00_0000));
+
+        // A flash starts white and is gone once done
+        let mut flash = ScreenFade::flash(GR_WHITE, 0.2);
+        assert_eq!(flash.amount(), 1.0);
+        flash.update(0.1);
+        assert!((flash.amount() - 0.5).abs() < 1e-6);
+        flash.update(0.1);
+        assert!(flash.is_finished());
+
+        // With no time to take it's over at once
+        assert_eq!(ScreenFade::from_white(0.0).amount(), 0.0);
+    }
+}
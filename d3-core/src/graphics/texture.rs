@@ -52,6 +52,62 @@ bitflags! {
     }
 }
 
+/// What a surface is made of, so hits on it can sound and look different
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum SurfaceMaterial {
+    #[default]
+    Generic,
+    Metal,
+    Rock,
+    Water,
+    Lava,
+    Forcefield,
+}
+
+impl SurfaceMaterial {
+    pub const ALL: [SurfaceMaterial; 6] = [
+        SurfaceMaterial::Generic,
+        SurfaceMaterial::Metal,
+        SurfaceMaterial::Rock,
+        SurfaceMaterial::Water,
+        SurfaceMaterial::Lava,
+        SurfaceMaterial::Forcefield,
+    ];
+
+    /// The name the table data uses
+    pub fn name(&self) -> &'static str {
+        match self {
+            SurfaceMaterial::Generic => "generic",
+            SurfaceMaterial::Metal => "metal",
+            SurfaceMaterial::Rock => "rock",
+            SurfaceMaterial::Water => "water",
+            SurfaceMaterial::Lava => "lava",
+            SurfaceMaterial::Forcefield => "forcefield",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Going by a texture's flags, for table entries that don't name a material
+    pub fn from_flags(flags: TextureFlags) -> Self {
+        if flags.intersects(TextureFlags::FORCEFIELD) {
+            SurfaceMaterial::Forcefield
+        } else if flags.intersects(TextureFlags::WATER | TextureFlags::WATER_PROCEDURAL) {
+            SurfaceMaterial::Water
+        } else if flags.intersects(TextureFlags::LAVA) {
+            SurfaceMaterial::Lava
+        } else if flags.intersects(TextureFlags::METAL) {
+            SurfaceMaterial::Metal
+        } else if flags.intersects(TextureFlags::RUBBLE | TextureFlags::MARBLE) {
+            SurfaceMaterial::Rock
+        } else {
+            SurfaceMaterial::Generic
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TextureSizeType {
     None,
@@ -130,8 +186,10 @@ impl Bitmap16 for VideoClipSource {
         self.bitmap.get_frame_bitmap(self.frame_offset).format()
     }
 
-    fn make_funny(&mut self) {
-        todo!()
+    fn fill_debug_pattern(&mut self, kind: super::bitmap::DebugPattern) {
+        for frame in self.bitmap.frames_mut() {
+            frame.fill_debug_pattern(kind);
+        }
     }
 }
 
@@ -181,16 +239,33 @@ pub struct Texture16 {
 
     pub sound: (),
     pub sound_volume: f32,
+
+    pub material: SurfaceMaterial,
 }
 
 impl Default for Texture16 {
     fn default() -> Self {
         Self {
+            name: D3String::new(),
             flags: TextureFlags::NONE,
+            bitmap_source: None,
+            destroy_bitmap_source: None,
+            bump_map: None,
+            updated: false,
+            size: TextureSizeType::Normal,
+            damage: 0,
+            reflectivity: 0.6,
+            corona_type: 0,
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
             alpha: 1.0,
+            slide_u: 0.0,
+            slide_v: 0.0,
             speed: 1.0,
-            reflectivity: 0.6,
-            ..Default::default()
+            sound: (),
+            sound_volume: 0.0,
+            material: SurfaceMaterial::Generic,
         }
     }
 }
@@ -206,6 +281,16 @@ impl GpuMemoryResource for Texture16 {
 }
 
 impl Texture16 {
+    /// Sets the material from the table entry's material name, or from the flags when it doesn't have one
+    pub fn load_material(&mut self, name: Option<&str>) -> Result<()> {
+        self.material = match name.filter(|n| !n.trim().is_empty()) {
+            Some(name) => SurfaceMaterial::from_name(name).ok_or_else(|| anyhow!("unknown surface material {} for texture {}", name, self.name))?,
+            None => SurfaceMaterial::from_flags(self.flags),
+        };
+
+        Ok(())
+    }
+
     pub fn compute_procedural_size(&self) -> (usize, usize) {
         if self.flags.contains(TextureFlags::TEXTURE_64) {
             ( 64, 64 )
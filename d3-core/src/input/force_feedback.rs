@@ -0,0 +1,393 @@
+use anyhow::Result;
+
+use crate::math::{matrix::Matrix, vector::Vector};
+
+/// Wall hits slower than this don't shake the stick
+pub const MIN_WALLHIT_SPEED: f32 = 20.0;
+/// Wall hits this fast or faster give a full jolt
+pub const MAX_WALLHIT_SPEED: f32 = 80.0;
+
+pub const MIN_RECOIL: f32 = 1000.0;
+pub const MAX_RECOIL: f32 = 5000.0;
+/// Weapons with less recoil than this don't kick
+pub const RECOIL_THRESHOLD: f32 = 1100.0;
+
+/// Ship shakes closer together than this are dropped
+pub const SHAKE_TIME: f32 = 0.2;
+
+/// The effects the game plays
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ForceEffectId {
+    TestForce,     // FORCE_TEST_FORCE
+    Microwave,     // FORCE_MICROWAVE
+    WallHit,       // FORCE_WALLHIT
+    WeaponRecoil,  // FORCE_WEAPON_RECOIL
+    VaussRecoil,   // FORCE_VAUSS_RECOIL
+    Afterburner,   // FORCE_AFTERBURNER
+    ShipShake,     // FORCE_SHIPSHAKE
+}
+
+/// The building blocks a device knows how to play.  Strengths are 0 to 1 and directions are
+/// in the ship's frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ForceEffect {
+    /// A short sharp kick
+    Jolt {
+        direction: Vector,
+        strength: f32,
+        duration: f32,
+    },
+    /// A rumble that ramps between two strengths
+    RumbleRamp { start: f32, end: f32, duration: f32 },
+    /// A steady push that lasts until it is stopped
+    ConstantForce { direction: Vector, strength: f32 },
+}
+
+impl ForceEffect {
+    pub fn scaled(&self, gain: f32) -> Self {
+        match *self {
+            ForceEffect::Jolt {
+                direction,
+                strength,
+                duration,
+            } => ForceEffect::Jolt {
+                direction,
+                strength: strength * gain,
+                duration,
+            },
+            ForceEffect::RumbleRamp { start, end, duration } => ForceEffect::RumbleRamp {
+                start: start * gain,
+                end: end * gain,
+                duration,
+            },
+            ForceEffect::ConstantForce { direction, strength } => ForceEffect::ConstantForce {
+                direction,
+                strength: strength * gain,
+            },
+        }
+    }
+}
+
+/// Handle to an effect that is playing on a device
+pub type ForceEffectHandle = u32;
+
+/// Something that can push back on the player, like a force feedback stick or a rumble pad
+pub trait ForceFeedbackDevice {
+    fn is_available(&self) -> bool;
+
+    fn play(&mut self, effect: &ForceEffect) -> Result<ForceEffectHandle>;
+
+    fn stop(&mut self, handle: ForceEffectHandle);
+
+    fn stop_all(&mut self);
+
+    fn set_auto_center(&mut self, _enabled: bool) {}
+}
+
+/// Used when there is no force feedback hardware, or support isn't built in
+#[derive(Debug, Default)]
+pub struct NullForceFeedback;
+
+impl ForceFeedbackDevice for NullForceFeedback {
+    fn is_available(&self) -> bool {
+        false
+    }
+
+    fn play(&mut self, _effect: &ForceEffect) -> Result<ForceEffectHandle> {
+        Ok(0)
+    }
+
+    fn stop(&mut self, _handle: ForceEffectHandle) {}
+
+    fn stop_all(&mut self) {}
+}
+
+/// Game happenings that can be felt
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ForceFeedbackEvent {
+    /// The ship hit a wall.  `wall_normal` is in world space
+    WallHit { speed: f32, wall_normal: Vector },
+    /// A weapon was fired, `offset` is from the ship to the weapon in world space
+    Recoil { recoil_force: f32, offset: Vector, vauss: bool },
+    /// The ship was hit by a weapon, `force` in world space
+    WeaponHit { force: Vector },
+    /// Microwave cannon or similar energy drain
+    Microwave { strength: f32 },
+    Shake { magnitude: f32 },
+    AfterburnerOn,
+    AfterburnerOff,
+}
+
+/// Hi-level force feedback, turns game events into effects on whatever device is attached
+pub struct ForceFeedback {
+    device: Box<dyn ForceFeedbackDevice>,
+    enabled: bool,
+    auto_center: bool,
+    gain: f32,
+    afterburner: Option<ForceEffectHandle>,
+    last_shake_time: f32,
+}
+
+impl Default for ForceFeedback {
+    fn default() -> Self {
+        Self::new(Box::new(NullForceFeedback))
+    }
+}
+
+impl ForceFeedback {
+    pub fn new(device: Box<dyn ForceFeedbackDevice>) -> Self {
+        Self {
+            device,
+            enabled: true,
+            auto_center: true,
+            gain: 1.0,
+            afterburner: None,
+            last_shake_time: f32::MIN,
+        }
+    }
+
+    /// Uses a gamepad through gilrs when support is built in and one is plugged in
+    pub fn detect() -> Self {
+        #[cfg(feature = "force_feedback")]
+        {
+            match super::gilrs_force_feedback::GilrsForceFeedback::new() {
+                Ok(device) if device.is_available() => return Self::new(Box::new(device)),
+                Ok(_) => debug!(target: "input", "no force feedback capable gamepads found"),
+                Err(e) => warn!(target: "input", "could not start force feedback: {}", e),
+            }
+        }
+
+        Self::default()
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.device.is_available()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled && self.device.is_available()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.device.stop_all();
+            self.afterburner = None;
+        }
+
+        self.enabled = enabled;
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Sets the gain of the ForceFeedback system (0 -> 1)
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain.clamp(0.0, 1.0);
+    }
+
+    pub fn is_auto_center(&self) -> bool {
+        self.auto_center
+    }
+
+    pub fn set_auto_center(&mut self, enabled: bool) {
+        self.auto_center = enabled;
+        self.device.set_auto_center(enabled);
+    }
+
+    /// The shape of each game effect
+    pub fn effect_for(id: ForceEffectId, scale: f32, direction: Vector) -> ForceEffect {
+        let scale = scale.clamp(0.0, 1.0);
+
+        match id {
+            ForceEffectId::TestForce => ForceEffect::RumbleRamp {
+                start: 1.0,
+                end: 0.0,
+                duration: 1.0,
+            },
+            ForceEffectId::Microwave => ForceEffect::RumbleRamp {
+                start: scale,
+                end: scale * 0.5,
+                duration: 0.5,
+            },
+            ForceEffectId::WallHit => ForceEffect::Jolt {
+                direction,
+                strength: scale,
+                duration: 0.15,
+            },
+            ForceEffectId::WeaponRecoil => ForceEffect::Jolt {
+                direction,
+                strength: scale,
+                duration: 0.1,
+            },
+            ForceEffectId::VaussRecoil => ForceEffect::Jolt {
+                direction,
+                strength: scale * 0.5,
+                duration: 0.05,
+            },
+            ForceEffectId::Afterburner => ForceEffect::ConstantForce {
+                direction,
+                strength: scale,
+            },
+            ForceEffectId::ShipShake => ForceEffect::RumbleRamp {
+                start: scale,
+                end: 0.0,
+                duration: SHAKE_TIME,
+            },
+        }
+    }
+
+    /// Plays an effect
+    pub fn play(&mut self, id: ForceEffectId, scale: f32, direction: Vector) -> Option<ForceEffectHandle> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let effect = Self::effect_for(id, scale, direction).scaled(self.gain);
+
+        match self.device.play(&effect) {
+            Ok(handle) => Some(handle),
+            Err(e) => {
+                warn!(target: "input", "force effect {:?} failed: {}", id, e);
+                None
+            }
+        }
+    }
+
+    /// Turns a game event into a force.  `orient` is the player ship's orientation.
+    pub fn handle_event(&mut self, event: &ForceFeedbackEvent, orient: &Matrix, gametime: f32) {
+        match *event {
+            ForceFeedbackEvent::WallHit { speed, wall_normal } => {
+                if speed < MIN_WALLHIT_SPEED {
+                    return;
+                }
+
+                let scale = speed / MAX_WALLHIT_SPEED;
+                let local_norm = -(*orient * wall_normal);
+
+                self.play(ForceEffectId::WallHit, scale, local_norm);
+            }
+            ForceFeedbackEvent::Recoil {
+                recoil_force,
+                offset,
+                vauss,
+            } => {
+                if recoil_force < RECOIL_THRESHOLD {
+                    return;
+                }
+
+                let scale = (recoil_force - MIN_RECOIL) / (MAX_RECOIL - MIN_RECOIL);
+                let id = if vauss {
+                    ForceEffectId::VaussRecoil
+                } else {
+                    ForceEffectId::WeaponRecoil
+                };
+
+                self.play(id, scale, *orient * offset);
+            }
+            ForceFeedbackEvent::WeaponHit { force } => {
+                let mut local = *orient * force;
+                let scale = Vector::normalize(&mut local) / MAX_RECOIL;
+
+                self.play(ForceEffectId::WallHit, scale, local);
+            }
+            ForceFeedbackEvent::Microwave { strength } => {
+                self.play(ForceEffectId::Microwave, strength, Vector::ZERO);
+            }
+            ForceFeedbackEvent::Shake { magnitude } => {
+                if gametime >= self.last_shake_time && gametime < self.last_shake_time + SHAKE_TIME {
+                    return;
+                }
+
+                self.last_shake_time = gametime;
+                self.play(ForceEffectId::ShipShake, magnitude, Vector::ZERO);
+            }
+            ForceFeedbackEvent::AfterburnerOn => {
+                if self.afterburner.is_none() {
+                    let back = Vector { x: 0.0, y: 0.0, z: -1.0 };
+                    self.afterburner = self.play(ForceEffectId::Afterburner, 0.5, back);
+                }
+            }
+            ForceFeedbackEvent::AfterburnerOff => {
+                if let Some(handle) = self.afterburner.take() {
+                    self.device.stop(handle);
+                }
+            }
+        }
+    }
+
+    /// Puts force feedback on pause, like when the window loses focus
+    pub fn shutdown(&mut self) {
+        self.device.stop_all();
+        self.afterburner = None;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingDevice {
+        played: Rc<RefCell<Vec<ForceEffect>>>,
+        stopped: Rc<RefCell<Vec<ForceEffectHandle>>>,
+    }
+
+    impl ForceFeedbackDevice for RecordingDevice {
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn play(&mut self, effect: &ForceEffect) -> Result<ForceEffectHandle> {
+            self.played.borrow_mut().push(*effect);
+            Ok(self.played.borrow().len() as ForceEffectHandle)
+        }
+
+        fn stop(&mut self, handle: ForceEffectHandle) {
+            self.stopped.borrow_mut().push(handle);
+        }
+
+        fn stop_all(&mut self) {}
+    }
+
+    #[test]
+    fn events_map_to_effects() {
+        crate::test_common::setup();
+
+        let device = RecordingDevice::default();
+        let played = device.played.clone();
+        let stopped = device.stopped.clone();
+
+        let mut ff = ForceFeedback::new(Box::new(device));
+        ff.set_gain(0.5);
+
+        let normal = Vector { x: 0.0, y: 0.0, z: 1.0 };
+
+        // Too slow to feel
+        ff.handle_event(&ForceFeedbackEvent::WallHit { speed: 10.0, wall_normal: normal }, &Matrix::IDENTITY, 0.0);
+        assert!(played.borrow().is_empty());
+
+        ff.handle_event(&ForceFeedbackEvent::WallHit { speed: 80.0, wall_normal: normal }, &Matrix::IDENTITY, 0.0);
+        assert!(matches!(played.borrow()[0], ForceEffect::Jolt { strength, .. } if strength == 0.5));
+
+        // Shakes too close together are dropped
+        ff.handle_event(&ForceFeedbackEvent::Shake { magnitude: 1.0 }, &Matrix::IDENTITY, 1.0);
+        ff.handle_event(&ForceFeedbackEvent::Shake { magnitude: 1.0 }, &Matrix::IDENTITY, 1.1);
+        assert_eq!(played.borrow().len(), 2);
+
+        ff.handle_event(&ForceFeedbackEvent::AfterburnerOn, &Matrix::IDENTITY, 2.0);
+        ff.handle_event(&ForceFeedbackEvent::AfterburnerOff, &Matrix::IDENTITY, 3.0);
+        assert_eq!(*stopped.borrow(), vec![3]);
+    }
+
+    #[test]
+    fn null_device_is_silent() {
+        crate::test_common::setup();
+
+        let mut ff = ForceFeedback::default();
+        assert!(!ff.is_enabled());
+        assert_eq!(ff.play(ForceEffectId::TestForce, 1.0, Vector::ZERO), None);
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks},
+    GamepadId, Gilrs,
+};
+
+use super::force_feedback::{ForceEffect, ForceEffectHandle, ForceFeedbackDevice};
+
+/// Rumble pads only know strong and weak motors, so directions are dropped
+pub struct GilrsForceFeedback {
+    gilrs: Gilrs,
+    gamepads: Vec<GamepadId>,
+    /// gilrs stops an effect when it is dropped, so they're held until they run out
+    effects: HashMap<ForceEffectHandle, (Effect, Option<Instant>)>,
+    next_handle: ForceEffectHandle,
+}
+
+impl GilrsForceFeedback {
+    pub fn new() -> Result<Self> {
+        let gilrs = Gilrs::new().map_err(|e| anyhow!("gilrs: {}", e))?;
+
+        let gamepads = gilrs
+            .gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+
+        Ok(Self {
+            gilrs,
+            gamepads,
+            effects: HashMap::new(),
+            next_handle: 1,
+        })
+    }
+
+    fn magnitude(strength: f32) -> u16 {
+        (strength.clamp(0.0, 1.0) * u16::MAX as f32) as u16
+    }
+
+    fn base_effects(effect: &ForceEffect) -> Vec<BaseEffect> {
+        let ms = |seconds: f32| Ticks::from_ms((seconds * 1000.0) as u32);
+
+        match *effect {
+            ForceEffect::Jolt { strength, duration, .. } => vec![BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: Self::magnitude(strength),
+                },
+                scheduling: Replay {
+                    play_for: ms(duration),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            // Approximate the ramp with a strong start and weak tail
+            ForceEffect::RumbleRamp { start, end, duration } => vec![
+                BaseEffect {
+                    kind: BaseEffectType::Strong {
+                        magnitude: Self::magnitude(start),
+                    },
+                    scheduling: Replay {
+                        play_for: ms(duration * 0.5),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                BaseEffect {
+                    kind: BaseEffectType::Weak {
+                        magnitude: Self::magnitude(end.max(start * 0.5)),
+                    },
+                    scheduling: Replay {
+                        after: ms(duration * 0.5),
+                        play_for: ms(duration * 0.5),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            ],
+            ForceEffect::ConstantForce { strength, .. } => vec![BaseEffect {
+                kind: BaseEffectType::Weak {
+                    magnitude: Self::magnitude(strength),
+                },
+                ..Default::default()
+            }],
+        }
+    }
+}
+
+impl ForceFeedbackDevice for GilrsForceFeedback {
+    fn is_available(&self) -> bool {
+        !self.gamepads.is_empty()
+    }
+
+    fn play(&mut self, effect: &ForceEffect) -> Result<ForceEffectHandle> {
+        let mut builder = EffectBuilder::new();
+
+        for base in Self::base_effects(effect) {
+            builder.add_effect(base);
+        }
+
+        // Constant forces run until they're stopped
+        if let ForceEffect::ConstantForce { .. } = effect {
+            builder.repeat(gilrs::ff::Repeat::Infinitely);
+        }
+
+        let built = builder
+            .gamepads(&self.gamepads)
+            .finish(&mut self.gilrs)
+            .map_err(|e| anyhow!("gilrs effect: {}", e))?;
+
+        built.play().map_err(|e| anyhow!("gilrs play: {}", e))?;
+
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1).max(1);
+
+        let now = Instant::now();
+        let expires = match *effect {
+            ForceEffect::Jolt { duration, .. } | ForceEffect::RumbleRamp { duration, .. } => {
+                Some(now + Duration::from_secs_f32(duration.max(0.0)))
+            }
+            ForceEffect::ConstantForce { .. } => None,
+        };
+
+        self.effects.retain(|_, (_, e)| e.is_none_or(|t| t > now));
+        self.effects.insert(handle, (built, expires));
+
+        Ok(handle)
+    }
+
+    fn stop(&mut self, handle: ForceEffectHandle) {
+        if let Some((effect, _)) = self.effects.remove(&handle) {
+            let _ = effect.stop();
+        }
+    }
+
+    fn stop_all(&mut self) {
+        for (_, (effect, _)) in self.effects.drain() {
+            let _ = effect.stop();
+        }
+    }
+}
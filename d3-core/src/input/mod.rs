@@ -0,0 +1,4 @@
+pub mod force_feedback;
+
+#[cfg(feature = "force_feedback")]
+pub mod gilrs_force_feedback;
@@ -32,6 +32,12 @@ pub mod game;
 pub mod math;
 pub mod string;
 pub mod rand;
+pub mod net;
+pub mod input;
+pub mod config;
+pub mod ui;
+pub mod diagnostics;
+pub mod prelude;
 
 
 #[cfg(test)]
@@ -49,16 +55,17 @@ pub fn create_rng() -> impl tinyrand::Rand {
     
     use tinyrand::{Rand, StdRand, Seeded};
     
-    #[cfg(feature = "std")]
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
     use tinyrand_std::clock_seed::ClockSeed;
 
-    #[cfg(feature = "std")]
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
     {
         let seed = ClockSeed::default().next_u64();
         StdRand::seed(seed)
     }
 
-    #[cfg(not(feature = "std"))]
+    // The clock seed reads the system time, which browsers don't give to wasm32-unknown-unknown
+    #[cfg(any(not(feature = "std"), target_arch = "wasm32"))]
     {
         StdRand::default()
     }
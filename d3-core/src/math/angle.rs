@@ -11,70 +11,152 @@ impl Default for Angle {
     }
 }
 
+/// Number of angle units in a full circle
+pub const ANGLE_CIRCLE: u32 = 0x10000;
+
+/// 256 steps around the circle plus a quarter turn so cos can share it, plus one for interpolating
+const SINCOS_TABLE_SIZE: usize = 256 + 64 + 1;
+/// 256 steps from 0 to 1 plus one for interpolating
+const ARC_TABLE_SIZE: usize = 256 + 1;
+
+lazy_static! {
+    static ref SINCOS_TABLE: [f32; SINCOS_TABLE_SIZE] = {
+        let mut table = [0.0; SINCOS_TABLE_SIZE];
+
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = (i as f64 * 2.0 * core::f64::consts::PI / 256.0).sin() as f32;
+        }
+
+        table
+    };
+    static ref ASIN_TABLE: [i32; ARC_TABLE_SIZE] = {
+        let mut table = [0; ARC_TABLE_SIZE];
+
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = ((i as f64 / 256.0).asin() * ANGLE_CIRCLE as f64 / (2.0 * core::f64::consts::PI)) as i32;
+        }
+
+        table
+    };
+    static ref ACOS_TABLE: [i32; ARC_TABLE_SIZE] = {
+        let mut table = [0; ARC_TABLE_SIZE];
+
+        for (i, v) in table.iter_mut().enumerate() {
+            *v = ((i as f64 / 256.0).acos() * ANGLE_CIRCLE as f64 / (2.0 * core::f64::consts::PI)) as i32;
+        }
+
+        table
+    };
+}
+
+/// Looks up `index` (top 8 bits) and lerps towards the next entry by `frac` (low 8 bits)
+fn sincos_lookup(index: usize, frac: u16) -> f32 {
+    let s = SINCOS_TABLE[index];
+    s + (SINCOS_TABLE[index + 1] - s) * frac as f32 / 256.0
+}
+
+/// Same as D3, |v| as 16.16 fixed point picks the entry and the fraction lerps in integer steps
+fn arc_lookup(table: &[i32; ARC_TABLE_SIZE], v: f32) -> i32 {
+    let vv = (v.abs() * 65536.0) as i32;
+    let i = ((vv >> 8) & 0xff) as usize;
+    let f = vv & 0xff;
+    let a = table[i];
+    a + (((table[i + 1] - a) * f) >> 8)
+}
+
 impl Angle {
     pub const ZERO: Angle = Angle(0);
+    pub const QUARTER: Angle = Angle(0x4000);
+    pub const HALF: Angle = Angle(0x8000);
+
+    pub fn from_rad(rad: f32) -> Self {
+        let units = (rad * ANGLE_CIRCLE as f32 / (2.0 * PI)).round() as i64;
+        Angle(units.rem_euclid(ANGLE_CIRCLE as i64) as u16)
+    }
+
+    pub fn from_degrees(degrees: f32) -> Self {
+        Angle::from_rad(degrees.to_radians())
+    }
 
     pub fn to_rad(self) -> f32 {
-        let i = (self.0 >> 8) as u8;
-        let f = self.0 as u8;
-        let normalized_f = f as f32 / 256.0;
-        (i as f32 + normalized_f) * 2.0 * PI / 360.0
+        self.0 as f32 * 2.0 * PI / ANGLE_CIRCLE as f32
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        self.0 as f32 * 360.0 / ANGLE_CIRCLE as f32
     }
 
+    /// Table driven like FixSin so results match the original bit for bit
     pub fn sin(&self) -> f32 {
-        self.to_rad().sin()
+        sincos_lookup((self.0 >> 8) as usize, self.0 & 0xff)
     }
 
+    /// Table driven like FixCos, a quarter turn further along the sin table
     pub fn cos(&self) -> f32 {
-        self.to_rad().cos()
+        sincos_lookup((self.0 >> 8) as usize + 64, self.0 & 0xff)
     }
 
-    // pub fn asin(&self) -> f32 {
-    //     self.to_rad().asin()
-    // }
+    /// Values outside -1..1 clamp to a quarter turn either way
+    pub fn asin(v: f32) -> Self {
+        let a = if v.abs() >= 1.0 {
+            Angle::QUARTER.0 as i32
+        } else {
+            arc_lookup(&ASIN_TABLE, v)
+        };
 
-    pub fn acos(v: f32) -> Self {
-        let mut vv = (v.abs() * 65536.0).trunc() as i32;
-
-        if vv > 0x10000 {
-            return Angle::ZERO;
+        if v < 0.0 {
+            Angle((-a) as u16)
+        } else {
+            Angle(a as u16)
         }
+    }
 
-        Angle(vv as u16).to_rad().acos();
+    /// Values outside -1..1 clamp to no turn or a half turn
+    pub fn acos(v: f32) -> Self {
+        let a = if v.abs() >= 1.0 {
+            0
+        } else {
+            arc_lookup(&ACOS_TABLE, v)
+        };
 
-        todo!()
+        if v < 0.0 {
+            Angle((Angle::HALF.0 as i32 - a) as u16)
+        } else {
+            Angle(a as u16)
+        }
     }
 
+    /// The angle with the given cos and sin, which don't need to be normalized
     pub fn atan2(cos: f32, sin: f32) -> Self {
-        let mut angle = Angle(0);
-
-        /* Find the smaller of the 2 */
-        let q = (sin * sin) + (cos * cos);
-        let m = q.sqrt();
+        let m = ((sin * sin) + (cos * cos)).sqrt();
 
         if m == 0.0 {
-            return angle;
+            return Angle::ZERO;
         }
 
+        /* Use whichever of the 2 is smaller, the tables are most accurate there */
         if sin.abs() < cos.abs() {
+            let t = Angle::asin(sin / m);
+
             if cos < 0.0 {
-                angle.0 = 0x8000 - (sin / m).asin().trunc() as u16;
-            }
-            else {
-                angle.0 = (sin / m).asin().trunc() as u16;
+                Angle::HALF - t
+            } else {
+                t
             }
-        }
-        else {
+        } else {
+            let t = Angle::acos(cos / m);
+
             if sin < 0.0 {
-                // From D3 it is fixed point 1.0 (0x10000) - value
-                angle.0 = 0u16.wrapping_sub((sin / m).acos().trunc() as u16);
-            }
-            else {
-                angle.0 = (sin / m).acos().trunc() as u16;
+                Angle::ZERO - t
+            } else {
+                t
             }
         }
+    }
 
-        angle
+    /// Shortest signed distance from `self` to `other` in angle units
+    pub fn delta(self, other: Angle) -> i16 {
+        other.0.wrapping_sub(self.0) as i16
     }
 }
 
@@ -134,4 +216,72 @@ impl EulerAngle {
     }
 }
 
-pub type EularAngle = Vector;
\ No newline at end of file
+pub type EularAngle = Vector;
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn max_delta(a: Angle, b: Angle) -> u16 {
+        a.delta(b).unsigned_abs()
+    }
+
+    #[test]
+    fn sin_cos_match_f32_trig() {
+        crate::test_common::setup();
+
+        // Every angle there is
+        for a in 0..=u16::MAX {
+            let angle = Angle(a);
+            let rad = angle.to_rad();
+
+            assert!((angle.sin() - rad.sin()).abs() < 0.0001, "sin {:#x}", a);
+            assert!((angle.cos() - rad.cos()).abs() < 0.0001, "cos {:#x}", a);
+        }
+
+        assert_eq!(Angle::QUARTER.sin(), 1.0);
+        assert_eq!(Angle::HALF.cos(), -1.0);
+    }
+
+    #[test]
+    fn arcs_match_f32_trig() {
+        crate::test_common::setup();
+
+        for i in -990..=990 {
+            let v = i as f32 / 1000.0;
+
+            assert!(max_delta(Angle::asin(v), Angle::from_rad(v.asin())) <= 16, "asin {}", v);
+            assert!(max_delta(Angle::acos(v), Angle::from_rad(v.acos())) <= 16, "acos {}", v);
+        }
+
+        assert_eq!(Angle::asin(1.0).0, Angle::QUARTER.0);
+        assert_eq!(Angle::asin(-2.0).0, 0xc000);
+        assert_eq!(Angle::acos(1.0).0, 0);
+        assert_eq!(Angle::acos(-1.0).0, Angle::HALF.0);
+    }
+
+    #[test]
+    fn atan2_round_trips() {
+        crate::test_common::setup();
+
+        for a in (0..=u16::MAX).step_by(7) {
+            let rad = Angle(a).to_rad();
+            let scale = 1.0 + (a % 5) as f32;
+            let angle = Angle::atan2(rad.cos() * scale, rad.sin() * scale);
+
+            assert!(max_delta(angle, Angle(a)) <= 4, "atan2 {:#x} gave {:#x}", a, angle.0);
+        }
+
+        assert_eq!(Angle::atan2(0.0, 0.0).0, 0);
+    }
+
+    #[test]
+    fn conversions() {
+        crate::test_common::setup();
+
+        assert_eq!(Angle::HALF.to_rad(), PI);
+        assert_eq!(Angle::QUARTER.to_degrees(), 90.0);
+        assert_eq!(Angle::from_degrees(-90.0).0, 0xc000);
+        assert_eq!(Angle::from_rad(2.0 * PI).0, 0);
+        assert_eq!(Angle(0xfff0).delta(Angle(0x0010)), 0x20);
+    }
+}
@@ -0,0 +1,334 @@
+use super::{vector::Vector, DotProduct};
+
+/// Axis aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+impl Aabb {
+    /// A box with nothing in it, anything merged in replaces it
+    pub const EMPTY: Aabb = Aabb {
+        min: Vector { x: f32::MAX, y: f32::MAX, z: f32::MAX },
+        max: Vector { x: f32::MIN, y: f32::MIN, z: f32::MIN },
+    };
+
+    pub fn new(min: Vector, max: Vector) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_point(point: &Vector) -> Self {
+        Self { min: *point, max: *point }
+    }
+
+    /// A cube that holds a sphere
+    pub fn from_center_radius(center: &Vector, radius: f32) -> Self {
+        let r = Vector { x: radius, y: radius, z: radius };
+        Self {
+            min: *center - r,
+            max: *center + r,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vector {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half the size along each axis
+    pub fn extents(&self) -> Vector {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn contains_point(&self, point: &Vector) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+            && point.z >= self.min.z
+            && point.z <= self.max.z
+    }
+
+    /// True if `other` fits entirely inside this box
+    pub fn contains(&self, other: &Aabb) -> bool {
+        self.contains_point(&other.min) && self.contains_point(&other.max)
+    }
+
+    /// Touching counts as overlapping
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        !(self.max.x < other.min.x
+            || other.max.x < self.min.x
+            || self.max.y < other.min.y
+            || other.max.y < self.min.y
+            || self.max.z < other.min.z
+            || other.max.z < self.min.z)
+    }
+
+    pub fn overlaps_sphere(&self, sphere: &Sphere) -> bool {
+        let closest = Vector {
+            x: sphere.center.x.clamp(self.min.x, self.max.x),
+            y: sphere.center.y.clamp(self.min.y, self.max.y),
+            z: sphere.center.z.clamp(self.min.z, self.max.z),
+        };
+
+        let d = closest - sphere.center;
+        d.dot(d) <= sphere.radius * sphere.radius
+    }
+
+    /// Grows the box by `amount` on every side
+    pub fn expand(&mut self, amount: f32) {
+        let a = Vector { x: amount, y: amount, z: amount };
+        self.min -= a;
+        self.max += a;
+    }
+
+    pub fn expanded(&self, amount: f32) -> Aabb {
+        let mut b = *self;
+        b.expand(amount);
+        b
+    }
+
+    /// Grows the box by a different amount below and above, like an object's extents
+    pub fn expand_by(&mut self, below: &Vector, above: &Vector) {
+        self.min += *below;
+        self.max += *above;
+    }
+
+    pub fn add_point(&mut self, point: &Vector) {
+        self.min.x = self.min.x.min(point.x);
+        self.min.y = self.min.y.min(point.y);
+        self.min.z = self.min.z.min(point.z);
+        self.max.x = self.max.x.max(point.x);
+        self.max.y = self.max.y.max(point.y);
+        self.max.z = self.max.z.max(point.z);
+    }
+
+    pub fn merge(&self, other: &Aabb) -> Aabb {
+        let mut b = *self;
+        b.add_point(&other.min);
+        b.add_point(&other.max);
+        b
+    }
+
+    /// Distance along `dir` where a ray first enters the box, None if it misses.
+    /// Starting inside the box gives 0.
+    pub fn intersect_ray(&self, origin: &Vector, dir: &Vector) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::MAX;
+
+        for axis in 0..3 {
+            let o = origin[axis];
+            let d = dir[axis];
+
+            if d.abs() < f32::EPSILON {
+                if o < self.min[axis] || o > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv = 1.0 / d;
+            let mut t0 = (self.min[axis] - o) * inv;
+            let mut t1 = (self.max[axis] - o) * inv;
+
+            if t0 > t1 {
+                core::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Aabb::EMPTY
+    }
+}
+
+impl From<&[Vector]> for Aabb {
+    fn from(points: &[Vector]) -> Self {
+        let mut b = Aabb::EMPTY;
+
+        for p in points {
+            b.add_point(p);
+        }
+
+        b
+    }
+}
+
+impl From<&Sphere> for Aabb {
+    fn from(sphere: &Sphere) -> Self {
+        Aabb::from_center_radius(&sphere.center, sphere.radius)
+    }
+}
+
+/// Bounding sphere
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sphere {
+    pub center: Vector,
+    pub radius: f32,
+}
+
+impl Sphere {
+    pub fn new(center: Vector, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains_point(&self, point: &Vector) -> bool {
+        let d = *point - self.center;
+        d.dot(d) <= self.radius * self.radius
+    }
+
+    pub fn contains(&self, other: &Sphere) -> bool {
+        Vector::distance(&self.center, &other.center) + other.radius <= self.radius
+    }
+
+    pub fn overlaps(&self, other: &Sphere) -> bool {
+        let d = other.center - self.center;
+        let r = self.radius + other.radius;
+        d.dot(d) <= r * r
+    }
+
+    pub fn overlaps_aabb(&self, aabb: &Aabb) -> bool {
+        aabb.overlaps_sphere(self)
+    }
+
+    pub fn expand(&mut self, amount: f32) {
+        self.radius += amount;
+    }
+
+    /// The smallest sphere holding both
+    pub fn merge(&self, other: &Sphere) -> Sphere {
+        if self.contains(other) {
+            return *self;
+        }
+
+        if other.contains(self) {
+            return *other;
+        }
+
+        let mut dir = other.center - self.center;
+        let dist = Vector::normalize(&mut dir);
+        let radius = (dist + self.radius + other.radius) * 0.5;
+
+        Sphere {
+            center: self.center + dir * (radius - self.radius),
+            radius,
+        }
+    }
+
+    /// Distance along a unit `dir` where a ray first enters the sphere, None if it misses.
+    /// Starting inside gives 0.
+    pub fn intersect_ray(&self, origin: &Vector, dir: &Vector) -> Option<f32> {
+        let m = *origin - self.center;
+        let b = m.dot(*dir);
+        let c = m.dot(m) - self.radius * self.radius;
+
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+
+        // Outside and pointing away
+        if b > 0.0 {
+            return None;
+        }
+
+        let disc = b * b - c;
+
+        if disc < 0.0 {
+            return None;
+        }
+
+        Some(-b - disc.sqrt())
+    }
+}
+
+impl From<&[Vector]> for Sphere {
+    fn from(points: &[Vector]) -> Self {
+        if points.is_empty() {
+            return Sphere::new(Vector::default(), 0.0);
+        }
+
+        let mut center = Vector::default();
+        let radius = Vector::compute_bounding_sphere(&mut center, points);
+        Sphere { center, radius }
+    }
+}
+
+impl From<&Aabb> for Sphere {
+    fn from(aabb: &Aabb) -> Self {
+        let extents = aabb.extents();
+        Sphere {
+            center: aabb.center(),
+            radius: Vector::magnitude(&extents),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn aabb_tests() {
+        crate::test_common::setup();
+
+        let points = [Vector::new(-1.0, 0.0, 2.0), Vector::new(3.0, -2.0, 0.0), Vector::new(0.0, 1.0, 1.0)];
+        let b = Aabb::from(&points[..]);
+
+        assert_eq!(b.min, Vector::new(-1.0, -2.0, 0.0));
+        assert_eq!(b.max, Vector::new(3.0, 1.0, 2.0));
+        assert!(b.contains_point(&Vector::new(0.0, 0.0, 1.0)));
+        assert!(!b.contains_point(&Vector::new(4.0, 0.0, 1.0)));
+
+        let other = Aabb::new(Vector::new(3.0, 1.0, 2.0), Vector::new(5.0, 5.0, 5.0));
+        assert!(b.overlaps(&other));
+        assert!(!b.overlaps(&Aabb::from_point(&Vector::new(10.0, 0.0, 0.0))));
+
+        let merged = b.merge(&other);
+        assert!(merged.contains(&b) && merged.contains(&other));
+
+        assert!(Aabb::EMPTY.is_empty());
+        assert_eq!(Aabb::EMPTY.merge(&b), b);
+
+        let t = b.intersect_ray(&Vector::new(-11.0, 0.0, 1.0), &Vector::new(1.0, 0.0, 0.0));
+        assert_eq!(t, Some(10.0));
+        assert_eq!(b.intersect_ray(&Vector::new(-11.0, 0.0, 1.0), &Vector::new(-1.0, 0.0, 0.0)), None);
+        assert_eq!(b.intersect_ray(&b.center(), &Vector::new(0.0, 1.0, 0.0)), Some(0.0));
+    }
+
+    #[test]
+    fn sphere_tests() {
+        crate::test_common::setup();
+
+        let a = Sphere::new(Vector::new(0.0, 0.0, 0.0), 1.0);
+        let b = Sphere::new(Vector::new(4.0, 0.0, 0.0), 1.0);
+
+        assert!(!a.overlaps(&b));
+        assert!(a.overlaps(&Sphere::new(Vector::new(1.5, 0.0, 0.0), 1.0)));
+
+        let m = a.merge(&b);
+        assert!((m.radius - 3.0).abs() < 0.001);
+        assert!(m.center.approx_eq(&Vector::new(2.0, 0.0, 0.0), Vector::EPSILON));
+
+        let t = b.intersect_ray(&Vector::new(0.0, 0.0, 0.0), &Vector::new(1.0, 0.0, 0.0));
+        assert_eq!(t, Some(3.0));
+
+        let cube = Aabb::new(Vector::new(2.0, -1.0, -1.0), Vector::new(3.0, 1.0, 1.0));
+        assert!(a.overlaps_aabb(&cube.expanded(1.0)));
+        assert!(!a.overlaps_aabb(&cube));
+    }
+}
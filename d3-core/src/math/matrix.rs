@@ -1,4 +1,4 @@
-use core::ops::{Add, Div, Mul, Neg, Sub};
+use core::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use super::{vector::Vector, ScalarDiv, ScalarMul};
 
@@ -44,6 +44,13 @@ impl Matrix {
         forward: Vector { x: 0.0, y: 0.0, z: 0.0 },
     };
 
+    /// Row by row compare within `epsilon`
+    pub fn approx_eq(&self, other: &Matrix, epsilon: f32) -> bool {
+        self.right.approx_eq(&other.right, epsilon)
+            && self.up.approx_eq(&other.up, epsilon)
+            && self.forward.approx_eq(&other.forward, epsilon)
+    }
+
     pub fn into_transposed(self) -> Self {
         self.transpose()
     }
@@ -99,6 +106,12 @@ impl Add for Matrix {
     }
 }
 
+impl AddAssign for Matrix {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
 impl Sub for Matrix {
     type Output = Self;
 
@@ -111,6 +124,55 @@ impl Sub for Matrix {
     }
 }
 
+impl SubAssign for Matrix {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Matrix {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Matrix {
+            right: -self.right,
+            up: -self.up,
+            forward: -self.forward
+        }
+    }
+}
+
+impl PartialEq for Matrix {
+    fn eq(&self, other: &Self) -> bool {
+        self.right == other.right && self.up == other.up && self.forward == other.forward
+    }
+}
+
+/// Rows in order: right, up, forward
+impl Index<usize> for Matrix {
+    type Output = Vector;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.right,
+            1 => &self.up,
+            2 => &self.forward,
+            _ => panic!("matrix row {} out of range", index),
+        }
+    }
+}
+
+impl IndexMut<usize> for Matrix {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match index {
+            0 => &mut self.right,
+            1 => &mut self.up,
+            2 => &mut self.forward,
+            _ => panic!("matrix row {} out of range", index),
+        }
+    }
+}
+
 impl ScalarMul for Matrix {
     fn mul_scalar(self, scalar: f32) -> Self {
         Matrix {
@@ -137,6 +199,12 @@ impl Mul<Matrix> for f32 {
     }
 }
 
+impl MulAssign<f32> for Matrix {
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = self.mul_scalar(rhs);
+    }
+}
+
 impl ScalarDiv for Matrix {
     fn div_scalar(self, scalar: f32) -> Self {
         Matrix {
@@ -155,6 +223,12 @@ impl Div<f32> for Matrix {
     }
 }
 
+impl DivAssign<f32> for Matrix {
+    fn div_assign(&mut self, rhs: f32) {
+        *self = self.div_scalar(rhs);
+    }
+}
+
 impl Div<Matrix> for f32 {
     type Output = Matrix;
 
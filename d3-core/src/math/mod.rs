@@ -1,4 +1,4 @@
-use core::{f32, ops::{Add, Mul, Neg, Sub}, panic};
+use core::{f32, ops::{Add, Mul, MulAssign, Neg, Sub}, panic};
 
 use angle::{Angle, EulerAngle};
 use matrix::{Matrix, Matrix4};
@@ -8,7 +8,12 @@ use vector2d::Vector2D;
 use std::{f32::consts::PI, vec};
 
 pub mod angle;
+pub mod bounds;
 pub mod matrix;
+pub mod noise;
+pub mod plane;
+pub mod ray;
+pub mod spline;
 pub mod vector;
 pub mod vector2d;
 
@@ -78,6 +83,14 @@ impl<'a> Mul<&'a Matrix> for Vector {
     }
 }
 
+impl<'a, 'b> Mul<&'b Vector> for &'a Matrix {
+    type Output = Vector;
+
+    fn mul(self, rhs: &'b Vector) -> Self::Output {
+        *self * *rhs
+    }
+}
+
 impl Angle {
     pub fn into_x_rotation(&self) -> Matrix {
         Matrix::new_rotation_x(self.sin(), self.cos())
@@ -126,7 +139,7 @@ impl Vector {
     }
 
     pub fn add_vectors(result: &mut Vector, a: &Vector, b: &Vector) {
-        let sum = a.add(*b);
+        let sum = *a + *b;
         result.x = sum.x;
         result.y = sum.y;
         result.z = sum.z;
@@ -140,7 +153,7 @@ impl Vector {
     }
 
     pub fn magnitude(vector: &Vector) -> f32 {
-        #[cfg(target_feature = "sse")]
+        #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
         {
             if std::arch::is_x86_feature_detected!("sse") {
                 return Vector::magnitude_sse(vector);
@@ -150,7 +163,7 @@ impl Vector {
         vector.dot(*vector).sqrt()
     }
 
-    #[cfg(target_feature = "sse")]
+    #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
     fn magnitude_sse(vector: &Vector) -> f32 {
         use std::arch::x86_64::*;
 
@@ -180,7 +193,7 @@ impl Vector {
         let mag = Vector::magnitude(vector);
 
         if mag > 0.0 {
-            vector.div_scalar(mag);
+            *vector = vector.div_scalar(mag);
             mag
         } else {
             vector.x = 1.0;
@@ -512,6 +525,12 @@ impl Mul<Matrix> for Matrix {
     }
 }
 
+impl MulAssign<Matrix> for Matrix {
+    fn mul_assign(&mut self, rhs: Matrix) {
+        *self = *self * rhs;
+    }
+}
+
 impl Matrix {
     pub fn orthogonalize(&self) -> Matrix {
         let mut m = self.clone();
@@ -558,7 +577,8 @@ impl Matrix {
                 m.right = t.forward;
             }
         } else {
-            assert!(up.is_some() && right.is_some());
+            // Can only have 1 other vector
+            assert!(!(up.is_some() && right.is_some()));
             Matrix::vector_to_matrix(&mut m, forward, up, right);
         }
 
@@ -612,7 +632,7 @@ impl Matrix {
 
                 *yvec = zvec.cross(&xvec);
 
-                if Vector::normalize(xvec) == 0.0 {
+                if Vector::normalize(yvec) == 0.0 {
                     Matrix::vector_to_matrix_forward_only(xvec, yvec, zvec);
                 }
 
@@ -625,7 +645,7 @@ impl Matrix {
 
             *xvec = yvec.cross(&zvec);
 
-            if Vector::normalize(yvec) == 0.0 {
+            if Vector::normalize(xvec) == 0.0 {
                 Matrix::vector_to_matrix_forward_only(xvec, yvec, zvec);
             }
 
@@ -685,3 +705,84 @@ impl Matrix {
         self.right.z * self.up.x * self.forward.y - self.right.z * self.up.y * self.forward.x
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn vector_ops() {
+        crate::test_common::setup();
+
+        let mut v = Vector::new(1.0, 2.0, 3.0);
+        v += Vector::new(1.0, 1.0, 1.0);
+        v -= Vector::new(0.0, 1.0, 0.0);
+        v *= 2.0;
+        v /= 4.0;
+        assert_eq!(v, Vector::new(1.0, 1.0, 2.0));
+
+        assert_eq!(2.0 * v, v * 2.0);
+        assert_eq!(&v + &v, v * 2.0);
+        assert_eq!(-&v, Vector::new(-1.0, -1.0, -2.0));
+
+        v[2] = 5.0;
+        assert_eq!(v[0], 1.0);
+        assert_eq!(v[2], 5.0);
+
+        let a = Vector::new(1.0, 2.0, 3.0);
+        let b = Vector::new(1.00001, 2.0, 3.0);
+        assert!(a.approx_eq(&b, Vector::EPSILON));
+        assert!(!a.approx_eq(&Vector::new(1.1, 2.0, 3.0), Vector::EPSILON));
+    }
+
+    #[test]
+    fn normalize_scales_the_vector() {
+        crate::test_common::setup();
+
+        let mut v = Vector::new(3.0, 0.0, 4.0);
+        assert_eq!(Vector::normalize(&mut v), 5.0);
+        assert!(v.approx_eq(&Vector::new(0.6, 0.0, 0.8), Vector::EPSILON));
+
+        assert!(Vector::new(0.0, 10.0, 0.0).normalized().approx_eq(&Vector::new(0.0, 1.0, 0.0), Vector::EPSILON));
+    }
+
+    #[test]
+    fn matrix_ops() {
+        crate::test_common::setup();
+
+        let mut m = Matrix::IDENTITY;
+        m *= 2.0;
+        m /= 2.0;
+        assert_eq!(m, Matrix::IDENTITY);
+
+        m += Matrix::IDENTITY;
+        m -= Matrix::IDENTITY;
+        assert!(m.approx_eq(&Matrix::IDENTITY, Vector::EPSILON));
+
+        assert_eq!(-Matrix::IDENTITY, Matrix::INVERSE);
+        assert_eq!(m[2], Matrix::IDENTITY.forward);
+
+        m[0] = Vector::new(0.0, 0.0, -1.0);
+        assert_eq!(m.right.z, -1.0);
+
+        let mut r = Matrix::IDENTITY;
+        r *= Matrix::IDENTITY;
+        assert_eq!(r, Matrix::IDENTITY);
+
+        let v = Vector::new(1.0, 2.0, 3.0);
+        assert_eq!(&Matrix::IDENTITY * &v, v);
+    }
+
+    #[test]
+    fn vector2d_ops() {
+        crate::test_common::setup();
+
+        let mut v = Vector2D::new(1.0, 2.0);
+        v += Vector2D::new(1.0, 1.0);
+        v *= 2.0;
+        assert_eq!(v, Vector2D::new(4.0, 6.0));
+        assert_eq!(v - Vector2D::new(4.0, 6.0), Vector2D::new(0.0, 0.0));
+        assert_eq!(v[1], 6.0);
+        assert!((v / 2.0).approx_eq(&Vector2D::new(2.0, 3.0), Vector::EPSILON));
+    }
+}
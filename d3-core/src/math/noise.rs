@@ -0,0 +1,309 @@
+//! Seedable gradient (Perlin) noise and fractal sums of it.
+//! Only uses `core` so it works without std.
+
+const TABLE_SIZE: usize = 256;
+const TABLE_MASK: i32 = TABLE_SIZE as i32 - 1;
+
+/// Edge midpoints of a cube, the usual set for improved Perlin noise
+const GRADIENTS_3D: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+const FRAC_1_SQRT_2: f32 = core::f32::consts::FRAC_1_SQRT_2;
+
+const GRADIENTS_2D: [[f32; 2]; 8] = [
+    [1.0, 0.0],
+    [-1.0, 0.0],
+    [0.0, 1.0],
+    [0.0, -1.0],
+    [FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+    [-FRAC_1_SQRT_2, FRAC_1_SQRT_2],
+    [FRAC_1_SQRT_2, -FRAC_1_SQRT_2],
+    [-FRAC_1_SQRT_2, -FRAC_1_SQRT_2],
+];
+
+/// `f32::floor` lives in std
+fn floor(x: f32) -> f32 {
+    let t = x as i32 as f32;
+
+    if t > x { t - 1.0 } else { t }
+}
+
+/// Quintic smoothstep so the noise has no seams at lattice points
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, x0: f32, x1: f32) -> f32 {
+    x0 + t * (x1 - x0)
+}
+
+/// splitmix64, just enough to shuffle the permutation table from a seed
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Gradient noise, the same seed always gives the same field.
+/// Output is roughly in -1..1 and is 0 at every integer lattice point.
+#[derive(Clone)]
+pub struct Perlin {
+    /// Permutation repeated twice so lookups don't need wrapping
+    perm: [u8; TABLE_SIZE * 2],
+    seed: u64,
+}
+
+impl core::fmt::Debug for Perlin {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Perlin").field("seed", &self.seed).finish()
+    }
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table = [0u8; TABLE_SIZE];
+
+        for (i, p) in table.iter_mut().enumerate() {
+            *p = i as u8;
+        }
+
+        // Fisher-Yates
+        let mut state = seed;
+        for i in (1..TABLE_SIZE).rev() {
+            let j = (next_random(&mut state) % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+
+        let mut perm = [0u8; TABLE_SIZE * 2];
+
+        for i in 0..perm.len() {
+            perm[i] = table[i & TABLE_MASK as usize];
+        }
+
+        Self { perm, seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn hash(&self, i: i32) -> usize {
+        self.perm[(i & TABLE_MASK) as usize] as usize
+    }
+
+    fn hash2(&self, x: i32, y: i32) -> usize {
+        self.perm[self.hash(x) + (y & TABLE_MASK) as usize] as usize
+    }
+
+    fn hash3(&self, x: i32, y: i32, z: i32) -> usize {
+        self.perm[self.hash2(x, y) + (z & TABLE_MASK) as usize] as usize
+    }
+
+    fn grad2(&self, x: i32, y: i32, fx: f32, fy: f32) -> f32 {
+        let g = GRADIENTS_2D[self.hash2(x, y) & 7];
+        g[0] * fx + g[1] * fy
+    }
+
+    fn grad3(&self, x: i32, y: i32, z: i32, fx: f32, fy: f32, fz: f32) -> f32 {
+        let g = GRADIENTS_3D[self.hash3(x, y, z) % GRADIENTS_3D.len()];
+        g[0] * fx + g[1] * fy + g[2] * fz
+    }
+
+    pub fn noise_2d(&self, x: f32, y: f32) -> f32 {
+        let x0 = floor(x);
+        let y0 = floor(y);
+        let ix = x0 as i32;
+        let iy = y0 as i32;
+
+        let fx0 = x - x0;
+        let fy0 = y - y0;
+        let fx1 = fx0 - 1.0;
+        let fy1 = fy0 - 1.0;
+
+        let wx = fade(fx0);
+        let wy = fade(fy0);
+
+        let v0 = lerp(wx, self.grad2(ix, iy, fx0, fy0), self.grad2(ix + 1, iy, fx1, fy0));
+        let v1 = lerp(wx, self.grad2(ix, iy + 1, fx0, fy1), self.grad2(ix + 1, iy + 1, fx1, fy1));
+
+        lerp(wy, v0, v1)
+    }
+
+    pub fn noise_3d(&self, x: f32, y: f32, z: f32) -> f32 {
+        let x0 = floor(x);
+        let y0 = floor(y);
+        let z0 = floor(z);
+        let ix = x0 as i32;
+        let iy = y0 as i32;
+        let iz = z0 as i32;
+
+        let fx0 = x - x0;
+        let fy0 = y - y0;
+        let fz0 = z - z0;
+        let fx1 = fx0 - 1.0;
+        let fy1 = fy0 - 1.0;
+        let fz1 = fz0 - 1.0;
+
+        let wx = fade(fx0);
+        let wy = fade(fy0);
+        let wz = fade(fz0);
+
+        let v00 = lerp(wx, self.grad3(ix, iy, iz, fx0, fy0, fz0), self.grad3(ix + 1, iy, iz, fx1, fy0, fz0));
+        let v10 = lerp(wx, self.grad3(ix, iy + 1, iz, fx0, fy1, fz0), self.grad3(ix + 1, iy + 1, iz, fx1, fy1, fz0));
+        let v01 = lerp(wx, self.grad3(ix, iy, iz + 1, fx0, fy0, fz1), self.grad3(ix + 1, iy, iz + 1, fx1, fy0, fz1));
+        let v11 = lerp(wx, self.grad3(ix, iy + 1, iz + 1, fx0, fy1, fz1), self.grad3(ix + 1, iy + 1, iz + 1, fx1, fy1, fz1));
+
+        lerp(wz, lerp(wy, v00, v10), lerp(wy, v01, v11))
+    }
+}
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Perlin::new(0)
+    }
+}
+
+/// Fractal Brownian motion, octaves of noise summed at rising frequency and falling amplitude
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fbm {
+    pub octaves: u32,
+    /// Starting frequency
+    pub frequency: f32,
+    /// Frequency multiplier per octave
+    pub lacunarity: f32,
+    /// Amplitude multiplier per octave
+    pub gain: f32,
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self {
+            octaves: 4,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fbm {
+    /// Sum of every octave's amplitude, dividing by it keeps the result in about -1..1
+    fn total_amplitude(&self) -> f32 {
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+
+        for _ in 0..self.octaves {
+            total += amplitude;
+            amplitude *= self.gain;
+        }
+
+        total
+    }
+
+    pub fn sample_2d(&self, noise: &Perlin, x: f32, y: f32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+
+        for _ in 0..self.octaves {
+            sum += noise.noise_2d(x * frequency, y * frequency) * amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+
+        let total = self.total_amplitude();
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    pub fn sample_3d(&self, noise: &Perlin, x: f32, y: f32, z: f32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+
+        for _ in 0..self.octaves {
+            sum += noise.noise_3d(x * frequency, y * frequency, z * frequency) * amplitude;
+            frequency *= self.lacunarity;
+            amplitude *= self.gain;
+        }
+
+        let total = self.total_amplitude();
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn perlin_tests() {
+        crate::test_common::setup();
+
+        let a = Perlin::new(1234);
+        let b = Perlin::new(1234);
+        let c = Perlin::new(4321);
+
+        let mut differs = false;
+
+        for i in 0..200 {
+            let x = i as f32 * 0.173 - 17.0;
+            let y = i as f32 * 0.311 + 3.0;
+            let z = i as f32 * -0.057;
+
+            let n2 = a.noise_2d(x, y);
+            let n3 = a.noise_3d(x, y, z);
+
+            assert_eq!(n2, b.noise_2d(x, y));
+            assert_eq!(n3, b.noise_3d(x, y, z));
+            assert!((-1.5..=1.5).contains(&n2));
+            assert!((-1.5..=1.5).contains(&n3));
+
+            differs |= n3 != c.noise_3d(x, y, z);
+        }
+
+        assert!(differs);
+
+        // Zero on the lattice
+        assert_eq!(a.noise_2d(3.0, -7.0), 0.0);
+        assert_eq!(a.noise_3d(-2.0, 5.0, 9.0), 0.0);
+
+        // Continuous across cell edges
+        let left = a.noise_2d(4.9999, 2.5);
+        let right = a.noise_2d(5.0001, 2.5);
+        assert!((left - right).abs() < 0.001);
+    }
+
+    #[test]
+    fn fbm_tests() {
+        crate::test_common::setup();
+
+        let noise = Perlin::new(99);
+        let fbm = Fbm {
+            octaves: 5,
+            frequency: 0.05,
+            ..Default::default()
+        };
+
+        for i in 0..100 {
+            let v = fbm.sample_2d(&noise, i as f32 * 3.7, i as f32 * 1.3);
+            assert!((-1.0..=1.0).contains(&v));
+        }
+
+        let flat = Fbm { octaves: 0, ..fbm };
+        assert_eq!(flat.sample_3d(&noise, 1.5, 2.5, 3.5), 0.0);
+    }
+}
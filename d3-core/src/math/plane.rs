@@ -0,0 +1,69 @@
+use super::{vector::Vector, DotProduct};
+
+/// Plane in normal/distance form: every point `p` on it satisfies `normal . p == d`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Plane {
+    /// Normalized surface normal, the front of the plane is the side it points to
+    pub normal: Vector,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vector, d: f32) -> Self {
+        Self { normal, d }
+    }
+
+    /// `normal` is expected to be normalized already
+    pub fn from_point_normal(point: &Vector, normal: &Vector) -> Self {
+        Self {
+            normal: *normal,
+            d: normal.dot(*point),
+        }
+    }
+
+    /// Three clockwise vertices, like a face
+    pub fn from_points(a: &Vector, b: &Vector, c: &Vector) -> Self {
+        let mut normal = Vector::default();
+        Vector::compute_normal_vector(&mut normal, a, b, c);
+        Self::from_point_normal(a, &normal)
+    }
+
+    /// The point on the plane closest to the origin
+    pub fn point(&self) -> Vector {
+        self.normal * self.d
+    }
+
+    /// Signed distance, negative is behind the plane
+    pub fn distance(&self, point: &Vector) -> f32 {
+        self.normal.dot(*point) - self.d
+    }
+
+    pub fn is_in_front(&self, point: &Vector) -> bool {
+        self.distance(point) > 0.0
+    }
+
+    pub fn project_point(&self, point: &Vector) -> Vector {
+        *point - self.normal * self.distance(point)
+    }
+
+    /// The same plane facing the other way
+    pub fn flipped(&self) -> Plane {
+        Plane {
+            normal: -self.normal,
+            d: -self.d,
+        }
+    }
+
+    /// Where the segment p0 to p1 crosses the plane, None if it doesn't
+    pub fn intersect_segment(&self, p0: &Vector, p1: &Vector) -> Option<Vector> {
+        let d0 = self.distance(p0);
+        let d1 = self.distance(p1);
+
+        if (d0 > 0.0 && d1 > 0.0) || (d0 < 0.0 && d1 < 0.0) || d0 == d1 {
+            return None;
+        }
+
+        let t = d0 / (d0 - d1);
+        Some(*p0 + (*p1 - *p0) * t)
+    }
+}
@@ -0,0 +1,182 @@
+use super::{bounds::Sphere, plane::Plane, vector::Vector, CrossProduct, DotProduct};
+
+/// Anything closer to parallel than this is treated as a miss
+const PARALLEL_EPSILON: f32 = 0.000001;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Vector,
+    /// Always normalized so hit distances are in world units
+    pub direction: Vector,
+}
+
+/// Where a ray hit a triangle
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriangleHit {
+    pub distance: f32,
+    /// Barycentric weights of the second and third vertices
+    pub u: f32,
+    pub v: f32,
+}
+
+impl Ray {
+    pub fn new(origin: Vector, direction: Vector) -> Self {
+        Self {
+            origin,
+            direction: direction.normalized(),
+        }
+    }
+
+    /// A ray from `start` towards `end` and the distance between them
+    pub fn between(start: &Vector, end: &Vector) -> (Self, f32) {
+        let mut direction = Vector::default();
+        let length = Vector::compute_normalized_direction(&mut direction, end, start);
+
+        (
+            Self {
+                origin: *start,
+                direction,
+            },
+            length,
+        )
+    }
+
+    pub fn at(&self, distance: f32) -> Vector {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance along the ray to the point nearest `point`, can be negative
+    pub fn closest_distance(&self, point: &Vector) -> f32 {
+        (*point - self.origin).dot(self.direction)
+    }
+
+    pub fn distance_to_point(&self, point: &Vector) -> f32 {
+        let t = self.closest_distance(point).max(0.0);
+        Vector::distance(&self.at(t), point)
+    }
+
+    /// Hits the plane from either side, None if parallel or the plane is behind the ray
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let denom = plane.normal.dot(self.direction);
+
+        if denom.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let t = -plane.distance(&self.origin) / denom;
+
+        if t < 0.0 {
+            return None;
+        }
+
+        Some(t)
+    }
+
+    /// Starting inside the sphere is a hit at 0
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        sphere.intersect_ray(&self.origin, &self.direction)
+    }
+
+    /// Moller-Trumbore. `cull_back` skips triangles whose normal (as `compute_normal_vector`
+    /// would give it) faces away from the ray
+    pub fn intersect_triangle(
+        &self,
+        v0: &Vector,
+        v1: &Vector,
+        v2: &Vector,
+        cull_back: bool,
+    ) -> Option<TriangleHit> {
+        let edge1 = *v1 - *v0;
+        let edge2 = *v2 - *v0;
+
+        let p = self.direction.cross(&edge2);
+        let det = edge1.dot(p);
+
+        if cull_back {
+            if det < PARALLEL_EPSILON {
+                return None;
+            }
+        } else if det.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = self.origin - *v0;
+        let u = s.dot(p) * inv_det;
+
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let v = self.direction.dot(q) * inv_det;
+
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge2.dot(q) * inv_det;
+
+        if distance < 0.0 {
+            return None;
+        }
+
+        Some(TriangleHit { distance, u, v })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_tests() {
+        crate::test_common::setup();
+
+        let plane = Plane::from_point_normal(&Vector::new(0.0, 2.0, 0.0), &Vector::new(0.0, 1.0, 0.0));
+
+        assert_eq!(plane.d, 2.0);
+        assert_eq!(plane.distance(&Vector::new(5.0, 5.0, 1.0)), 3.0);
+        assert!(!plane.is_in_front(&Vector::new(0.0, 1.0, 0.0)));
+        assert_eq!(plane.project_point(&Vector::new(1.0, 7.0, 1.0)), Vector::new(1.0, 2.0, 1.0));
+        assert_eq!(plane.flipped().distance(&Vector::new(0.0, 5.0, 0.0)), -3.0);
+
+        let hit = plane.intersect_segment(&Vector::new(0.0, 0.0, 0.0), &Vector::new(0.0, 4.0, 4.0));
+        assert_eq!(hit, Some(Vector::new(0.0, 2.0, 2.0)));
+        assert_eq!(plane.intersect_segment(&Vector::new(0.0, 3.0, 0.0), &Vector::new(0.0, 4.0, 0.0)), None);
+    }
+
+    #[test]
+    fn ray_tests() {
+        crate::test_common::setup();
+
+        let (ray, length) = Ray::between(&Vector::new(0.0, 0.0, -5.0), &Vector::new(0.0, 0.0, 5.0));
+        assert_eq!(length, 10.0);
+        assert_eq!(ray.at(5.0), Vector::new(0.0, 0.0, 0.0));
+        assert_eq!(ray.distance_to_point(&Vector::new(3.0, 0.0, 0.0)), 3.0);
+
+        let plane = Plane::new(Vector::new(0.0, 0.0, -1.0), -1.0);
+        assert_eq!(ray.intersect_plane(&plane), Some(6.0));
+        assert_eq!(ray.intersect_plane(&Plane::new(Vector::new(1.0, 0.0, 0.0), 0.0)), None);
+
+        let sphere = Sphere::new(Vector::new(0.0, 0.0, 0.0), 2.0);
+        assert_eq!(ray.intersect_sphere(&sphere), Some(3.0));
+
+        // Facing back down the ray, as seen by the ray the points go clockwise
+        let v0 = Vector::new(-1.0, -1.0, 0.0);
+        let v1 = Vector::new(0.0, 1.0, 0.0);
+        let v2 = Vector::new(1.0, -1.0, 0.0);
+
+        let mut normal = Vector::default();
+        Vector::compute_normal_vector(&mut normal, &v0, &v1, &v2);
+        assert!(normal.dot(ray.direction) < 0.0);
+
+        let hit = ray.intersect_triangle(&v0, &v1, &v2, true).unwrap();
+        assert!((hit.distance - 5.0).abs() < 0.0001);
+        assert!(ray.intersect_triangle(&v0, &v2, &v1, true).is_none());
+        assert!(ray.intersect_triangle(&v0, &v2, &v1, false).is_some());
+
+        let miss = Ray::new(Vector::new(5.0, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0));
+        assert!(miss.intersect_triangle(&v0, &v1, &v2, false).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use super::{matrix::Matrix, vector::Vector};
+
+/// Default number of samples per segment when measuring a curve
+pub const ARC_LENGTH_SAMPLES: usize = 16;
+
+/// A path through space evaluated over `t` from 0 to 1
+pub trait Spline {
+    fn point(&self, t: f32) -> Vector;
+
+    /// Derivative with respect to `t`, not normalized
+    fn tangent(&self, t: f32) -> Vector;
+
+    /// How many pieces the curve is made of, used to pick how finely to sample it
+    fn segment_count(&self) -> usize {
+        1
+    }
+
+    /// Orientation facing along the curve at `t`. `up` only needs to be roughly up.
+    fn orientation(&self, t: f32, up: &Vector) -> Matrix {
+        let forward = self.tangent(t);
+
+        if Vector::magnitude(&forward) == 0.0 {
+            return Matrix::IDENTITY;
+        }
+
+        Matrix::from_vector(Some(&forward), Some(up), None)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CubicBezier {
+    pub p0: Vector,
+    pub p1: Vector,
+    pub p2: Vector,
+    pub p3: Vector,
+}
+
+impl CubicBezier {
+    pub fn new(p0: Vector, p1: Vector, p2: Vector, p3: Vector) -> Self {
+        Self { p0, p1, p2, p3 }
+    }
+}
+
+impl Spline for CubicBezier {
+    fn point(&self, t: f32) -> Vector {
+        let t = t.clamp(0.0, 1.0);
+        let u = 1.0 - t;
+
+        self.p0 * (u * u * u) + self.p1 * (3.0 * u * u * t) + self.p2 * (3.0 * u * t * t) + self.p3 * (t * t * t)
+    }
+
+    fn tangent(&self, t: f32) -> Vector {
+        let t = t.clamp(0.0, 1.0);
+        let u = 1.0 - t;
+
+        (self.p1 - self.p0) * (3.0 * u * u) + (self.p2 - self.p1) * (6.0 * u * t) + (self.p3 - self.p2) * (3.0 * t * t)
+    }
+}
+
+/// Uniform Catmull-Rom spline passing through every control point
+#[derive(Debug, Clone, Default)]
+pub struct CatmullRom {
+    pub points: Vec<Vector>,
+    /// Joins the last point back to the first
+    pub looped: bool,
+}
+
+impl CatmullRom {
+    pub fn new(points: Vec<Vector>, looped: bool) -> Self {
+        Self { points, looped }
+    }
+
+    fn control_point(&self, index: isize) -> Vector {
+        let count = self.points.len() as isize;
+
+        if self.looped {
+            self.points[index.rem_euclid(count) as usize]
+        } else {
+            // Repeat the end points so the curve starts and stops on them
+            self.points[index.clamp(0, count - 1) as usize]
+        }
+    }
+
+    /// Which segment `t` falls in and how far along it
+    fn locate(&self, t: f32) -> (isize, f32) {
+        let segments = self.segment_count();
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let segment = (scaled as usize).min(segments - 1);
+
+        (segment as isize, scaled - segment as f32)
+    }
+
+    fn segment_points(&self, segment: isize) -> [Vector; 4] {
+        [
+            self.control_point(segment - 1),
+            self.control_point(segment),
+            self.control_point(segment + 1),
+            self.control_point(segment + 2),
+        ]
+    }
+}
+
+impl Spline for CatmullRom {
+    fn point(&self, t: f32) -> Vector {
+        match self.points.len() {
+            0 => return Vector::default(),
+            1 => return self.points[0],
+            _ => {}
+        }
+
+        let (segment, s) = self.locate(t);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+
+        let s2 = s * s;
+        let s3 = s2 * s;
+
+        (p1 * 2.0 + (p2 - p0) * s + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * s2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * s3)
+            * 0.5
+    }
+
+    fn tangent(&self, t: f32) -> Vector {
+        if self.points.len() < 2 {
+            return Vector::default();
+        }
+
+        let (segment, s) = self.locate(t);
+        let [p0, p1, p2, p3] = self.segment_points(segment);
+
+        let ds = (p2 - p0) + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (2.0 * s) + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * (3.0 * s * s);
+
+        // Per unit of the whole curve's t rather than the segment's
+        ds * (0.5 * self.segment_count() as f32)
+    }
+
+    fn segment_count(&self) -> usize {
+        match self.points.len() {
+            0 | 1 => 1,
+            n if self.looped => n,
+            n => n - 1,
+        }
+    }
+}
+
+/// Maps distance along a curve back to `t` so things can move along it at a steady speed
+#[derive(Debug, Clone)]
+pub struct ArcLengthTable {
+    /// Distance travelled at evenly spaced values of `t`
+    lengths: Vec<f32>,
+}
+
+impl ArcLengthTable {
+    pub fn new<S: Spline + ?Sized>(spline: &S) -> Self {
+        Self::with_samples(spline, spline.segment_count() * ARC_LENGTH_SAMPLES)
+    }
+
+    pub fn with_samples<S: Spline + ?Sized>(spline: &S, samples: usize) -> Self {
+        let samples = samples.max(1);
+        let mut lengths = Vec::with_capacity(samples + 1);
+        let mut last = spline.point(0.0);
+        let mut total = 0.0;
+
+        lengths.push(0.0);
+
+        for i in 1..=samples {
+            let p = spline.point(i as f32 / samples as f32);
+            total += Vector::distance(&last, &p);
+            lengths.push(total);
+            last = p;
+        }
+
+        Self { lengths }
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.lengths.last().unwrap()
+    }
+
+    /// `t` for a distance along the curve, clamped to the ends
+    pub fn param_at_distance(&self, distance: f32) -> f32 {
+        let total = self.length();
+
+        if total <= 0.0 || distance <= 0.0 {
+            return 0.0;
+        }
+
+        if distance >= total {
+            return 1.0;
+        }
+
+        // First sample at or past the distance
+        let i = self.lengths.partition_point(|&l| l < distance).max(1);
+        let before = self.lengths[i - 1];
+        let span = self.lengths[i] - before;
+        let frac = if span > 0.0 { (distance - before) / span } else { 0.0 };
+
+        (i as f32 - 1.0 + frac) / (self.lengths.len() - 1) as f32
+    }
+
+    /// `t` for a fraction (0 to 1) of the way along the curve by distance
+    pub fn param_at_fraction(&self, fraction: f32) -> f32 {
+        self.param_at_distance(fraction * self.length())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn bezier_tests() {
+        crate::test_common::setup();
+
+        let curve = CubicBezier::new(
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(0.0, 0.0, 10.0),
+            Vector::new(10.0, 0.0, 10.0),
+            Vector::new(10.0, 0.0, 20.0),
+        );
+
+        assert_eq!(curve.point(0.0), curve.p0);
+        assert_eq!(curve.point(1.0), curve.p3);
+        assert!(curve.point(0.5).approx_eq(&Vector::new(5.0, 0.0, 10.0), Vector::EPSILON));
+
+        let forward = curve.tangent(0.0).normalized();
+        assert!(forward.approx_eq(&Vector::new(0.0, 0.0, 1.0), Vector::EPSILON));
+
+        let m = curve.orientation(0.0, &Vector::new(0.0, 1.0, 0.0));
+        assert!(m.approx_eq(&Matrix::IDENTITY, Vector::EPSILON));
+    }
+
+    #[test]
+    fn catmull_rom_tests() {
+        crate::test_common::setup();
+
+        let points = vec![
+            Vector::new(0.0, 0.0, 0.0),
+            Vector::new(10.0, 0.0, 0.0),
+            Vector::new(10.0, 10.0, 0.0),
+            Vector::new(0.0, 10.0, 0.0),
+        ];
+
+        let open = CatmullRom::new(points.clone(), false);
+        assert_eq!(open.segment_count(), 3);
+
+        // Passes through every control point
+        for (i, p) in points.iter().enumerate() {
+            assert!(open.point(i as f32 / 3.0).approx_eq(p, 0.001));
+        }
+
+        let looped = CatmullRom::new(points.clone(), true);
+        assert_eq!(looped.segment_count(), 4);
+        assert!(looped.point(1.0).approx_eq(&points[0], 0.001));
+        assert!(looped.point(0.75).approx_eq(&points[3], 0.001));
+
+        // Tangent agrees with the slope between nearby points
+        let t = 0.4;
+        let h = 0.0001;
+        let numeric = (open.point(t + h) - open.point(t - h)) * (1.0 / (2.0 * h));
+        assert!(numeric.approx_eq(&open.tangent(t), 0.1));
+    }
+
+    #[test]
+    fn arc_length_tests() {
+        crate::test_common::setup();
+
+        let line = CatmullRom::new(
+            vec![Vector::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 30.0)],
+            false,
+        );
+
+        let table = ArcLengthTable::new(&line);
+        assert!((table.length() - 30.0).abs() < 0.001);
+
+        // The ends ease in and out so t isn't linear in distance
+        let t = table.param_at_distance(15.0);
+        assert!(line.point(t).approx_eq(&Vector::new(0.0, 0.0, 15.0), 0.01));
+
+        let t = table.param_at_fraction(0.1);
+        assert!(line.point(t).approx_eq(&Vector::new(0.0, 0.0, 3.0), 0.1));
+
+        assert_eq!(table.param_at_distance(-1.0), 0.0);
+        assert_eq!(table.param_at_distance(100.0), 1.0);
+    }
+}
@@ -1,4 +1,4 @@
-use core::ops::{Add, AddAssign, BitXor, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use core::ops::{Add, AddAssign, BitXor, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use super::{CrossProduct, DotProduct, ScalarDiv, ScalarMul};
 
@@ -23,12 +23,33 @@ impl Vector {
         z: 0.032
     };
 
+    /// How close two floats need to be for `approx_eq`
+    pub const EPSILON: f32 = 0.0001;
+
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
     pub fn zero_out(&mut self) {
         self.x = 0.0;
         self.y = 0.0;
         self.z = 0.0;
     }
 
+    /// Componentwise compare within `epsilon`
+    pub fn approx_eq(&self, other: &Vector, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon
+            && (self.y - other.y).abs() <= epsilon
+            && (self.z - other.z).abs() <= epsilon
+    }
+
+    /// A unit length copy of this vector
+    pub fn normalized(&self) -> Vector {
+        let mut v = *self;
+        Vector::normalize(&mut v);
+        v
+    }
+
     pub fn as_mut_slice(&mut self) -> &mut [f32] {
         unsafe {
             std::slice::from_raw_parts_mut(self as *mut Vector as *mut f32, 3)
@@ -88,6 +109,14 @@ impl Add for Vector {
     }
 }
 
+impl<'a, 'b> Add<&'b Vector> for &'a Vector {
+    type Output = Vector;
+
+    fn add(self, rhs: &'b Vector) -> Vector {
+        (*self) + (*rhs)
+    }
+}
+
 impl AddAssign for Vector {
     fn add_assign(&mut self, rhs: Self) {
         *self = *self + rhs;
@@ -160,6 +189,14 @@ impl Mul<Vector> for f32 {
     }
 }
 
+impl<'a> Mul<f32> for &'a Vector {
+    type Output = Vector;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        self.mul_scalar(rhs)
+    }
+}
+
 impl DotProduct for Vector {
     fn dot(self, other: Self) -> f32 {
         (self.x * other.x) +
@@ -241,6 +278,38 @@ impl Neg for Vector {
     }
 }
 
+impl<'a> Neg for &'a Vector {
+    type Output = Vector;
+
+    fn neg(self) -> Self::Output {
+        -(*self)
+    }
+}
+
+impl Index<usize> for Vector {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("vector index {} out of range", index),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vector {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("vector index {} out of range", index),
+        }
+    }
+}
+
 
 use vek;
 pub type Vector4 = vek::Vec4<f32>;
@@ -1,3 +1,5 @@
+use core::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
+
 use super::{CrossProduct, DotProduct};
 
 #[derive(Debug, Copy, Clone)]
@@ -12,6 +14,117 @@ impl Default for Vector2D {
     }
 }
 
+impl Vector2D {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+
+    /// Componentwise compare within `epsilon`
+    pub fn approx_eq(&self, other: &Vector2D, epsilon: f32) -> bool {
+        (self.x - other.x).abs() <= epsilon && (self.y - other.y).abs() <= epsilon
+    }
+}
+
+impl PartialEq for Vector2D {
+    fn eq(&self, other: &Self) -> bool {
+        self.x == other.x && self.y == other.y
+    }
+}
+
+impl Add for Vector2D {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Vector2D { x: self.x + rhs.x, y: self.y + rhs.y }
+    }
+}
+
+impl AddAssign for Vector2D {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vector2D {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Vector2D { x: self.x - rhs.x, y: self.y - rhs.y }
+    }
+}
+
+impl SubAssign for Vector2D {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f32> for Vector2D {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Vector2D { x: self.x * rhs, y: self.y * rhs }
+    }
+}
+
+impl Mul<Vector2D> for f32 {
+    type Output = Vector2D;
+
+    fn mul(self, rhs: Vector2D) -> Self::Output {
+        rhs * self
+    }
+}
+
+impl MulAssign<f32> for Vector2D {
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = *self * rhs;
+    }
+}
+
+impl Div<f32> for Vector2D {
+    type Output = Self;
+
+    fn div(self, rhs: f32) -> Self::Output {
+        Vector2D { x: self.x / rhs, y: self.y / rhs }
+    }
+}
+
+impl DivAssign<f32> for Vector2D {
+    fn div_assign(&mut self, rhs: f32) {
+        *self = *self / rhs;
+    }
+}
+
+impl Neg for Vector2D {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Vector2D { x: -self.x, y: -self.y }
+    }
+}
+
+impl Index<usize> for Vector2D {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            _ => panic!("vector index {} out of range", index),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vector2D {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        match index {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            _ => panic!("vector index {} out of range", index),
+        }
+    }
+}
+
 impl CrossProduct for Vector2D {
     type Result = f32;
     
@@ -0,0 +1,618 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    common::SharedMutRef,
+    game::events::EventEmitter,
+    gr_rgb,
+    graphics::ddgr_color,
+};
+
+use super::{
+    end_packet, read_net_string, read_packet_header, start_packet, write_net_string, NetRole,
+    NetTransport, PacketType, PlayerSlot, MAX_NET_PLAYERS, MAX_TEAMS, SERVER_SLOT,
+};
+
+pub const MAX_HUD_MESSAGES: usize = 3;
+pub const HUD_MESSAGE_LENGTH: usize = 200;
+
+/// Number of audio taunt slots a player can have
+pub const MAX_AUDIO_TAUNTS: u8 = 4;
+
+/// Default delay between taunts from the same player, in seconds
+pub const DEFAULT_TAUNT_DELAY: f32 = 5.0;
+
+/// How many messages a player may send inside of `FLOOD_WINDOW` seconds
+pub const FLOOD_MESSAGE_LIMIT: usize = 4;
+pub const FLOOD_WINDOW: f32 = 5.0;
+
+/// Event fired on the event bus when a new message is queued up for the HUD
+pub const EVENT_CHAT_MESSAGE: &str = "chat_message";
+
+/// Event fired on the event bus when an audio taunt should be played
+pub const EVENT_CHAT_TAUNT: &str = "chat_taunt";
+
+/// Color used for messages that the server relays on behalf of a client
+pub const RELAYED_MESSAGE_COLOR: ddgr_color = gr_rgb!(0, 128, 255);
+
+/// Color used for taunt notifications
+pub const TAUNT_MESSAGE_COLOR: ddgr_color = gr_rgb!(255, 255, 0);
+
+/// Who a message is meant for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MessageTarget {
+    /// Everyone in the game.
+    All, // MULTI_SEND_MESSAGE_ALL
+
+    /// Only the players on a team.
+    Team(u8), // MULTI_SEND_MESSAGE_RED_TEAM ... MULTI_SEND_MESSAGE_YELLOW_TEAM
+
+    /// A single player.
+    Player(PlayerSlot),
+}
+
+impl MessageTarget {
+    /// Encodes the target into the signed byte the original netcode uses
+    pub fn to_wire(&self) -> i8 {
+        match *self {
+            MessageTarget::All => -1,
+            MessageTarget::Team(team) => -2 - team as i8,
+            MessageTarget::Player(slot) => slot as i8,
+        }
+    }
+
+    pub fn from_wire(value: i8) -> Result<Self> {
+        match value {
+            -1 => Ok(MessageTarget::All),
+            -5..=-2 => Ok(MessageTarget::Team((-2 - value) as u8)),
+            v if v >= 0 && (v as usize) < MAX_NET_PLAYERS => Ok(MessageTarget::Player(v as u8)),
+            _ => Err(anyhow!("invalid message target {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatMessageKind {
+    /// A line of text typed by a player or sent by the server.
+    Text(String),
+
+    /// One of the sender's audio taunts.
+    Taunt(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    /// The player that sent this message, or None if it came from the server itself
+    pub sender: Option<PlayerSlot>,
+    pub target: MessageTarget,
+    pub color: ddgr_color,
+    pub kind: ChatMessageKind,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ChatParticipant {
+    pub team: Option<u8>,
+}
+
+/// Keeps a player from spamming the channel
+#[derive(Debug, Clone)]
+pub struct FloodLimiter {
+    history: Vec<VecDeque<f32>>,
+    last_taunt: Vec<Option<f32>>,
+    pub taunt_delay: f32,
+}
+
+impl Default for FloodLimiter {
+    fn default() -> Self {
+        Self {
+            history: vec![VecDeque::new(); MAX_NET_PLAYERS],
+            last_taunt: vec![None; MAX_NET_PLAYERS],
+            taunt_delay: DEFAULT_TAUNT_DELAY,
+        }
+    }
+}
+
+impl FloodLimiter {
+    /// Returns true and records the message if the player is allowed to send it
+    pub fn allow_message(&mut self, slot: PlayerSlot, gametime: f32) -> bool {
+        let history = &mut self.history[slot as usize];
+
+        while let Some(&t) = history.front() {
+            if gametime - t >= FLOOD_WINDOW {
+                history.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if history.len() >= FLOOD_MESSAGE_LIMIT {
+            return false;
+        }
+
+        history.push_back(gametime);
+        true
+    }
+
+    /// Returns true and records the taunt if enough time has passed since the player's last one
+    pub fn allow_taunt(&mut self, slot: PlayerSlot, gametime: f32) -> bool {
+        let last = &mut self.last_taunt[slot as usize];
+
+        if let Some(t) = *last {
+            if gametime - t < self.taunt_delay {
+                return false;
+            }
+        }
+
+        *last = Some(gametime);
+        true
+    }
+
+    pub fn reset(&mut self, slot: PlayerSlot) {
+        self.history[slot as usize].clear();
+        self.last_taunt[slot as usize] = None;
+    }
+}
+
+pub fn encode_message_to_server(from: PlayerSlot, target: MessageTarget, text: &str) -> Result<Vec<u8>> {
+    let mut cursor = start_packet(PacketType::MessageToServer);
+    cursor.write_u8(from)?;
+    cursor.write_i8(target.to_wire())?;
+    write_net_string(&mut cursor, text)?;
+    end_packet(cursor)
+}
+
+pub fn encode_message_from_server(message: &ChatMessage) -> Result<Vec<u8>> {
+    let text = match &message.kind {
+        ChatMessageKind::Text(text) => text,
+        ChatMessageKind::Taunt(_) => return Err(anyhow!("taunts are sent with encode_taunt")),
+    };
+
+    let mut cursor = start_packet(PacketType::MessageFromServer);
+    cursor.write_u32::<LittleEndian>(message.color)?;
+    cursor.write_u8(message.sender.map(|s| s as i8).unwrap_or(-1) as u8)?;
+    cursor.write_i8(message.target.to_wire())?;
+    write_net_string(&mut cursor, text)?;
+    end_packet(cursor)
+}
+
+pub fn encode_taunt(packet_type: PacketType, from: PlayerSlot, index: u8) -> Result<Vec<u8>> {
+    let mut cursor = start_packet(packet_type);
+    cursor.write_u8(from)?;
+    cursor.write_u8(index)?;
+    end_packet(cursor)
+}
+
+/// Decodes any of the chat packets into a message
+pub fn decode_message(data: &[u8]) -> Result<(PacketType, ChatMessage)> {
+    let (packet_type, mut cursor) = read_packet_header(data)?;
+
+    let message = match packet_type {
+        PacketType::MessageToServer => {
+            let sender = cursor.read_u8()?;
+            let target = MessageTarget::from_wire(cursor.read_i8()?)?;
+            let text = read_net_string(&mut cursor)?;
+
+            ChatMessage {
+                sender: Some(sender),
+                target,
+                color: RELAYED_MESSAGE_COLOR,
+                kind: ChatMessageKind::Text(text),
+            }
+        }
+        PacketType::MessageFromServer => {
+            let color = cursor.read_u32::<LittleEndian>()?;
+            let sender = cursor.read_i8()?;
+            let target = MessageTarget::from_wire(cursor.read_i8()?)?;
+            let text = read_net_string(&mut cursor)?;
+
+            ChatMessage {
+                sender: if sender < 0 { None } else { Some(sender as u8) },
+                target,
+                color,
+                kind: ChatMessageKind::Text(text),
+            }
+        }
+        PacketType::ClientPlayTaunt | PacketType::ServerPlayTaunt => {
+            let sender = cursor.read_u8()?;
+            let index = cursor.read_u8()?;
+
+            if index >= MAX_AUDIO_TAUNTS {
+                return Err(anyhow!("invalid taunt index {}", index));
+            }
+
+            ChatMessage {
+                sender: Some(sender),
+                target: MessageTarget::All,
+                color: TAUNT_MESSAGE_COLOR,
+                kind: ChatMessageKind::Taunt(index),
+            }
+        }
+        _ => return Err(anyhow!("{:?} is not a chat packet", packet_type)),
+    };
+
+    Ok((packet_type, message))
+}
+
+/// Text and taunt messaging over the multiplayer channel.
+///
+/// Clients send everything to the server, which applies flood limiting and then
+/// routes the message to its recipients. Received messages are queued up for the
+/// HUD and announced on the event bus.
+pub struct ChatSystem {
+    role: NetRole,
+    local_slot: PlayerSlot,
+    participants: [Option<ChatParticipant>; MAX_NET_PLAYERS],
+    flood_limiter: FloodLimiter,
+    hud_messages: VecDeque<ChatMessage>,
+    taunts: VecDeque<(PlayerSlot, u8)>,
+    events: Option<SharedMutRef<EventEmitter>>,
+    pub taunts_enabled: bool,
+}
+
+impl ChatSystem {
+    pub fn new(role: NetRole, local_slot: PlayerSlot) -> Self {
+        Self {
+            role,
+            local_slot,
+            participants: [None; MAX_NET_PLAYERS],
+            flood_limiter: FloodLimiter::default(),
+            hud_messages: VecDeque::new(),
+            taunts: VecDeque::new(),
+            events: None,
+            taunts_enabled: true,
+        }
+    }
+
+    pub fn with_events(mut self, events: SharedMutRef<EventEmitter>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn role(&self) -> NetRole {
+        self.role
+    }
+
+    pub fn local_slot(&self) -> PlayerSlot {
+        self.local_slot
+    }
+
+    pub fn flood_limiter_mut(&mut self) -> &mut FloodLimiter {
+        &mut self.flood_limiter
+    }
+
+    pub fn add_participant(&mut self, slot: PlayerSlot, team: Option<u8>) {
+        self.participants[slot as usize] = Some(ChatParticipant { team });
+        self.flood_limiter.reset(slot);
+    }
+
+    pub fn remove_participant(&mut self, slot: PlayerSlot) {
+        self.participants[slot as usize] = None;
+    }
+
+    pub fn set_team(&mut self, slot: PlayerSlot, team: Option<u8>) {
+        if let Some(participant) = &mut self.participants[slot as usize] {
+            participant.team = team;
+        }
+    }
+
+    /// Returns every connected slot that should receive a message sent to `target`
+    pub fn recipients(&self, target: MessageTarget) -> Vec<PlayerSlot> {
+        self.participants
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, participant)| {
+                let participant = participant.as_ref()?;
+
+                let wanted = match target {
+                    MessageTarget::All => true,
+                    MessageTarget::Team(team) => participant.team == Some(team),
+                    MessageTarget::Player(s) => s as usize == slot,
+                };
+
+                if wanted { Some(slot as PlayerSlot) } else { None }
+            })
+            .collect()
+    }
+
+    /// Sends a line of text from the local player
+    pub fn send_message(
+        &mut self,
+        transport: &mut dyn NetTransport,
+        target: MessageTarget,
+        text: &str,
+        gametime: f32,
+    ) -> Result<()> {
+        if let MessageTarget::Team(team) = target {
+            if team as usize >= MAX_TEAMS {
+                return Err(anyhow!("invalid team {}", team));
+            }
+        }
+
+        let text = truncate_message(text);
+
+        match self.role {
+            NetRole::Client => {
+                let data = encode_message_to_server(self.local_slot, target, text)?;
+                transport.send_reliable(SERVER_SLOT, &data)
+            }
+            NetRole::Server => {
+                if !self.flood_limiter.allow_message(self.local_slot, gametime) {
+                    return Err(anyhow!("message rate limit exceeded"));
+                }
+
+                let message = ChatMessage {
+                    sender: Some(self.local_slot),
+                    target,
+                    color: RELAYED_MESSAGE_COLOR,
+                    kind: ChatMessageKind::Text(text.to_string()),
+                };
+
+                self.route(transport, message)
+            }
+        }
+    }
+
+    /// Sends a message that has no player attached to it, only the server can do this
+    pub fn send_server_message(
+        &mut self,
+        transport: &mut dyn NetTransport,
+        target: MessageTarget,
+        color: ddgr_color,
+        text: &str,
+    ) -> Result<()> {
+        if self.role != NetRole::Server {
+            return Err(anyhow!("only the server can send server messages"));
+        }
+
+        let message = ChatMessage {
+            sender: None,
+            target,
+            color,
+            kind: ChatMessageKind::Text(truncate_message(text).to_string()),
+        };
+
+        self.route(transport, message)
+    }
+
+    /// Plays one of the local player's audio taunts for everyone
+    pub fn send_taunt(&mut self, transport: &mut dyn NetTransport, index: u8, gametime: f32) -> Result<()> {
+        if index >= MAX_AUDIO_TAUNTS {
+            return Err(anyhow!("invalid taunt index {}", index));
+        }
+
+        match self.role {
+            NetRole::Client => {
+                let data = encode_taunt(PacketType::ClientPlayTaunt, self.local_slot, index)?;
+                transport.send_reliable(SERVER_SLOT, &data)
+            }
+            NetRole::Server => {
+                if !self.flood_limiter.allow_taunt(self.local_slot, gametime) {
+                    return Err(anyhow!("taunt delay has not expired"));
+                }
+
+                let message = ChatMessage {
+                    sender: Some(self.local_slot),
+                    target: MessageTarget::All,
+                    color: TAUNT_MESSAGE_COLOR,
+                    kind: ChatMessageKind::Taunt(index),
+                };
+
+                self.route(transport, message)
+            }
+        }
+    }
+
+    /// Handles an incoming chat packet from `from`
+    pub fn receive(
+        &mut self,
+        transport: &mut dyn NetTransport,
+        from: PlayerSlot,
+        data: &[u8],
+        gametime: f32,
+    ) -> Result<()> {
+        let (packet_type, mut message) = decode_message(data)?;
+
+        match (self.role, packet_type) {
+            (NetRole::Server, PacketType::MessageToServer) => {
+                // Don't trust the slot the client claims to be
+                message.sender = Some(from);
+
+                if !self.flood_limiter.allow_message(from, gametime) {
+                    debug!(target: "net", "dropping chat message from slot {}: flooding", from);
+                    return Ok(());
+                }
+
+                self.route(transport, message)
+            }
+            (NetRole::Server, PacketType::ClientPlayTaunt) => {
+                message.sender = Some(from);
+
+                if !self.flood_limiter.allow_taunt(from, gametime) {
+                    debug!(target: "net", "dropping taunt from slot {}: taunt delay", from);
+                    return Ok(());
+                }
+
+                self.route(transport, message)
+            }
+            (NetRole::Client, PacketType::MessageFromServer)
+            | (NetRole::Client, PacketType::ServerPlayTaunt) => {
+                self.deliver(message);
+                Ok(())
+            }
+            _ => Err(anyhow!("unexpected {:?} packet for {:?}", packet_type, self.role)),
+        }
+    }
+
+    /// Server side: sends the message to everyone it is meant for, including ourselves
+    fn route(&mut self, transport: &mut dyn NetTransport, message: ChatMessage) -> Result<()> {
+        let data = match message.kind {
+            ChatMessageKind::Text(_) => encode_message_from_server(&message)?,
+            ChatMessageKind::Taunt(index) => encode_taunt(
+                PacketType::ServerPlayTaunt,
+                message.sender.unwrap_or(SERVER_SLOT),
+                index,
+            )?,
+        };
+
+        let mut recipients = self.recipients(message.target);
+
+        // Whoever sent the message should see it too
+        if let Some(sender) = message.sender {
+            if !recipients.contains(&sender) && self.participants[sender as usize].is_some() {
+                recipients.push(sender);
+            }
+        }
+
+        let mut local = false;
+
+        for slot in recipients {
+            if slot == self.local_slot {
+                local = true;
+                continue;
+            }
+
+            transport.send_reliable(slot, &data)?;
+        }
+
+        if local {
+            self.deliver(message);
+        }
+
+        Ok(())
+    }
+
+    fn deliver(&mut self, message: ChatMessage) {
+        trace!(target: "net", "chat: {:?}", message);
+
+        let event = match message.kind {
+            ChatMessageKind::Taunt(index) => {
+                if !self.taunts_enabled {
+                    return;
+                }
+
+                self.taunts.push_back((message.sender.unwrap_or(SERVER_SLOT), index));
+                EVENT_CHAT_TAUNT
+            }
+            ChatMessageKind::Text(_) => EVENT_CHAT_MESSAGE,
+        };
+
+        self.hud_messages.push_back(message);
+
+        if let Some(events) = &self.events {
+            events.borrow_mut().emit(event);
+        }
+    }
+
+    /// Messages waiting to be drawn by the HUD, oldest first
+    pub fn drain_hud_messages(&mut self) -> impl Iterator<Item = ChatMessage> + '_ {
+        self.hud_messages.drain(..)
+    }
+
+    /// Taunts waiting to be played by the audio system as (player slot, taunt index)
+    pub fn drain_taunts(&mut self) -> impl Iterator<Item = (PlayerSlot, u8)> + '_ {
+        self.taunts.drain(..)
+    }
+}
+
+fn truncate_message(text: &str) -> &str {
+    if text.len() < HUD_MESSAGE_LENGTH {
+        return text;
+    }
+
+    let mut end = HUD_MESSAGE_LENGTH - 1;
+
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &text[..end]
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct LoopbackTransport {
+        sent: Vec<(PlayerSlot, Vec<u8>)>,
+    }
+
+    impl NetTransport for LoopbackTransport {
+        fn send_reliable(&mut self, slot: PlayerSlot, data: &[u8]) -> Result<()> {
+            self.sent.push((slot, data.to_vec()));
+            Ok(())
+        }
+    }
+
+    fn server_with_teams() -> ChatSystem {
+        let mut server = ChatSystem::new(NetRole::Server, SERVER_SLOT);
+        server.add_participant(0, Some(0));
+        server.add_participant(1, Some(0));
+        server.add_participant(2, Some(1));
+        server.add_participant(3, Some(1));
+        server
+    }
+
+    #[test]
+    fn message_round_trip() {
+        crate::test_common::setup();
+
+        let data = encode_message_to_server(3, MessageTarget::Team(1), "hello").unwrap();
+        let (packet_type, message) = decode_message(&data).unwrap();
+
+        assert_eq!(packet_type, PacketType::MessageToServer);
+        assert_eq!(message.sender, Some(3));
+        assert_eq!(message.target, MessageTarget::Team(1));
+        assert_eq!(message.kind, ChatMessageKind::Text("hello".to_string()));
+    }
+
+    #[test]
+    fn team_routing() {
+        crate::test_common::setup();
+
+        let mut server = server_with_teams();
+        let mut transport = LoopbackTransport::default();
+
+        let data = encode_message_to_server(2, MessageTarget::Team(1), "go blue").unwrap();
+        server.receive(&mut transport, 2, &data, 0.0).unwrap();
+
+        let slots: Vec<PlayerSlot> = transport.sent.iter().map(|(s, _)| *s).collect();
+        assert_eq!(slots, vec![2, 3]);
+        assert_eq!(server.drain_hud_messages().count(), 0);
+    }
+
+    #[test]
+    fn flood_limiting() {
+        crate::test_common::setup();
+
+        let mut server = server_with_teams();
+        let mut transport = LoopbackTransport::default();
+        let data = encode_message_to_server(1, MessageTarget::Player(1), "spam").unwrap();
+
+        for _ in 0..FLOOD_MESSAGE_LIMIT + 2 {
+            server.receive(&mut transport, 1, &data, 1.0).unwrap();
+        }
+
+        assert_eq!(transport.sent.len(), FLOOD_MESSAGE_LIMIT);
+
+        server.receive(&mut transport, 1, &data, 1.0 + FLOOD_WINDOW).unwrap();
+        assert_eq!(transport.sent.len(), FLOOD_MESSAGE_LIMIT + 1);
+    }
+
+    #[test]
+    fn taunt_delay() {
+        crate::test_common::setup();
+
+        let mut server = server_with_teams();
+        let mut transport = LoopbackTransport::default();
+        let data = encode_taunt(PacketType::ClientPlayTaunt, 1, 2).unwrap();
+
+        server.receive(&mut transport, 1, &data, 0.0).unwrap();
+        server.receive(&mut transport, 1, &data, 1.0).unwrap();
+
+        let taunts: Vec<(PlayerSlot, u8)> = server.drain_taunts().collect();
+        assert_eq!(taunts, vec![(1, 2)]);
+    }
+}
@@ -0,0 +1,727 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+
+use crate::config::NetworkSettings;
+
+use super::{
+    end_packet, read_packet_header, start_packet, NetTransport, PacketType, PlayerSlot,
+};
+
+/// How much file data goes into a single data packet
+pub const DATA_CHUNK_SIZE: usize = 450;
+
+/// Biggest file a peer may send, missions are the largest and run to a few tens of megabytes
+pub const MAX_TRANSFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// Which of a player's custom files is being transferred.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NetFileId {
+    /// Custom ship texture (logo).
+    ShipTexture, // NETFILE_ID_SHIP_TEX
+
+    /// One of the player's voice taunts.
+    VoiceTaunt(u8), // NETFILE_ID_VOICE_TAUNT1 ... NETFILE_ID_VOICE_TAUNT4
+
+    /// A mission file the client is missing.
+    Mission,
+}
+
+impl NetFileId {
+    pub fn to_wire(&self) -> u16 {
+        match *self {
+            NetFileId::ShipTexture => 1,
+            NetFileId::VoiceTaunt(index) => 2 + index as u16,
+            NetFileId::Mission => 10,
+        }
+    }
+
+    pub fn from_wire(value: u16) -> Result<Self> {
+        match value {
+            1 => Ok(NetFileId::ShipTexture),
+            2..=5 => Ok(NetFileId::VoiceTaunt((value - 2) as u8)),
+            10 => Ok(NetFileId::Mission),
+            _ => Err(anyhow!("invalid net file id {}", value)),
+        }
+    }
+}
+
+/// State of a transfer with a single player.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetFileState {
+    /// Sending a file to someone.
+    Sending, // NETFILE_SENDING
+
+    /// Receiving a file from someone.
+    Receiving, // NETFILE_RECEIVING
+
+    /// Waiting for a response as to if we can get a file.
+    Asking, // NETFILE_ASKING
+}
+
+/// Progress report handed to the progress callback.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TransferProgress {
+    /// The player on the other end of the connection
+    pub peer: PlayerSlot,
+
+    /// The player the file belongs to
+    pub owner: PlayerSlot,
+    pub file: NetFileId,
+    pub state: NetFileState,
+    pub transferred: usize,
+    pub total: usize,
+}
+
+impl TransferProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.transferred as f32 / self.total as f32
+        }
+    }
+}
+
+/// A file that arrived and passed verification.
+#[derive(Debug, Clone)]
+pub struct CompletedFile {
+    pub owner: PlayerSlot,
+    pub file: NetFileId,
+    pub data: Vec<u8>,
+}
+
+/// Why a transfer ended without a completed file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransferFailure {
+    Denied,
+    Cancelled,
+    HashMismatch,
+    /// The sender gave a size over `MAX_TRANSFER_SIZE` or sent more data than it said it would
+    BadSize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FailedTransfer {
+    pub peer: PlayerSlot,
+    pub owner: PlayerSlot,
+    pub file: NetFileId,
+    pub reason: TransferFailure,
+}
+
+/// Where the sending side gets the contents of the files it serves
+pub trait NetFileProvider {
+    fn load(&mut self, owner: PlayerSlot, file: NetFileId) -> Option<Vec<u8>>;
+}
+
+struct OutgoingTransfer {
+    owner: PlayerSlot,
+    file: NetFileId,
+    data: Vec<u8>,
+    hash: blake3::Hash,
+    pos: usize,
+    /// Set when the receiver has acked and the next chunk is waiting on the bandwidth budget
+    ready: bool,
+}
+
+struct IncomingTransfer {
+    owner: PlayerSlot,
+    file: NetFileId,
+    state: NetFileState,
+    data: Vec<u8>,
+    total: usize,
+    hash: Option<[u8; 32]>,
+}
+
+/// Background transfer of custom ship logos, taunts and mission files.
+///
+/// Like the original, only one transfer runs with each peer at a time and every
+/// data chunk must be acked before the next one goes out. Outgoing chunks are
+/// additionally held back by a per second bandwidth cap.
+pub struct FileTransferManager {
+    local_slot: PlayerSlot,
+    outgoing: HashMap<PlayerSlot, OutgoingTransfer>,
+    incoming: HashMap<PlayerSlot, IncomingTransfer>,
+    bandwidth_cap: Option<usize>,
+    budget: f32,
+    progress_callback: Option<Box<dyn FnMut(&TransferProgress)>>,
+    completed: VecDeque<CompletedFile>,
+    failed: VecDeque<FailedTransfer>,
+}
+
+impl FileTransferManager {
+    pub fn new(local_slot: PlayerSlot) -> Self {
+        Self::with_settings(local_slot, &NetworkSettings::default())
+    }
+
+    /// Outgoing file data is capped at the settings' bandwidth cap
+    pub fn with_settings(local_slot: PlayerSlot, settings: &NetworkSettings) -> Self {
+        let cap = settings.bandwidth_cap as usize;
+
+        Self {
+            local_slot,
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+            bandwidth_cap: Some(cap),
+            budget: cap as f32,
+            progress_callback: None,
+            completed: VecDeque::new(),
+            failed: VecDeque::new(),
+        }
+    }
+
+    /// Limits outgoing file data to `bytes_per_second`, None removes the cap
+    pub fn set_bandwidth_cap(&mut self, bytes_per_second: Option<usize>) {
+        self.bandwidth_cap = bytes_per_second;
+        self.budget = bytes_per_second.unwrap_or(0) as f32;
+    }
+
+    pub fn set_progress_callback(&mut self, callback: Box<dyn FnMut(&TransferProgress)>) {
+        self.progress_callback = Some(callback);
+    }
+
+    pub fn is_busy_with(&self, peer: PlayerSlot) -> bool {
+        self.outgoing.contains_key(&peer) || self.incoming.contains_key(&peer)
+    }
+
+    /// Asks `peer` to send us the file belonging to `owner`
+    pub fn request_file(
+        &mut self,
+        transport: &mut dyn NetTransport,
+        peer: PlayerSlot,
+        owner: PlayerSlot,
+        file: NetFileId,
+    ) -> Result<()> {
+        if self.incoming.contains_key(&peer) {
+            return Err(anyhow!("already receiving a file from slot {}", peer));
+        }
+
+        let mut cursor = start_packet(PacketType::FileRequest);
+        cursor.write_u16::<LittleEndian>(file.to_wire())?;
+        cursor.write_u16::<LittleEndian>(owner as u16)?;
+        cursor.write_u16::<LittleEndian>(self.local_slot as u16)?;
+        transport.send_reliable(peer, &end_packet(cursor)?)?;
+
+        self.incoming.insert(
+            peer,
+            IncomingTransfer {
+                owner,
+                file,
+                state: NetFileState::Asking,
+                data: Vec::new(),
+                total: 0,
+                hash: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Cancels whatever transfer is running with `peer`, in either direction
+    pub fn cancel(&mut self, transport: &mut dyn NetTransport, peer: PlayerSlot) -> Result<()> {
+        if let Some(transfer) = self.outgoing.remove(&peer) {
+            send_file_control(transport, PacketType::FileCancel, peer, transfer.file, transfer.owner)?;
+        }
+
+        if let Some(transfer) = self.incoming.remove(&peer) {
+            send_file_control(transport, PacketType::FileCancel, peer, transfer.file, transfer.owner)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops any transfer state for a player that left the game
+    pub fn drop_peer(&mut self, peer: PlayerSlot) {
+        self.outgoing.remove(&peer);
+        self.incoming.remove(&peer);
+    }
+
+    /// Handles an incoming file transfer packet from `from`
+    pub fn receive(
+        &mut self,
+        transport: &mut dyn NetTransport,
+        provider: &mut dyn NetFileProvider,
+        from: PlayerSlot,
+        data: &[u8],
+    ) -> Result<()> {
+        let (packet_type, mut cursor) = read_packet_header(data)?;
+
+        match packet_type {
+            PacketType::FileRequest => {
+                let file = NetFileId::from_wire(cursor.read_u16::<LittleEndian>()?)?;
+                let owner = cursor.read_u16::<LittleEndian>()? as PlayerSlot;
+                let _requester = cursor.read_u16::<LittleEndian>()?;
+
+                if self.outgoing.contains_key(&from) {
+                    debug!(target: "net", "got a file request from {} while one was already in progress", from);
+                    return send_file_control(transport, PacketType::FileDenied, from, file, owner);
+                }
+
+                let Some(contents) = provider.load(owner, file) else {
+                    debug!(target: "net", "got a file request for a file that doesn't exist ({:?} of {})", file, owner);
+                    return send_file_control(transport, PacketType::FileDenied, from, file, owner);
+                };
+
+                self.outgoing.insert(
+                    from,
+                    OutgoingTransfer {
+                        owner,
+                        file,
+                        hash: blake3::hash(&contents),
+                        data: contents,
+                        pos: 0,
+                        ready: true,
+                    },
+                );
+
+                self.pump(transport)
+            }
+            PacketType::FileAck => {
+                let received = cursor.read_u32::<LittleEndian>()? as usize;
+
+                match self.outgoing.get_mut(&from) {
+                    Some(transfer) if transfer.pos == received => {
+                        if transfer.pos >= transfer.data.len() {
+                            // The last chunk made it
+                            let transfer = self.outgoing.remove(&from).unwrap();
+                            trace!(target: "net", "finished sending {:?} to {}", transfer.file, from);
+                        } else {
+                            transfer.ready = true;
+                        }
+                    }
+                    Some(transfer) => {
+                        return Err(anyhow!(
+                            "ack from {} for offset {} but we are at {}",
+                            from,
+                            received,
+                            transfer.pos
+                        ));
+                    }
+                    None => debug!(target: "net", "received an ACK from someone we weren't sending a file to"),
+                }
+
+                self.pump(transport)
+            }
+            PacketType::FileData => {
+                let total = cursor.read_u32::<LittleEndian>()? as usize;
+                let offset = cursor.read_u32::<LittleEndian>()? as usize;
+                let file = NetFileId::from_wire(cursor.read_u16::<LittleEndian>()?)?;
+                let owner = cursor.read_u16::<LittleEndian>()? as PlayerSlot;
+
+                let mut hash = None;
+                if offset == 0 {
+                    let mut bytes = [0u8; 32];
+                    cursor.read_exact(&mut bytes)?;
+                    hash = Some(bytes);
+                }
+
+                let len = cursor.read_u16::<LittleEndian>()? as usize;
+                let mut chunk = vec![0u8; len];
+                cursor.read_exact(&mut chunk)?;
+
+                let Some(transfer) = self.incoming.get_mut(&from) else {
+                    debug!(target: "net", "got file data from {} that we never asked for", from);
+                    return Ok(());
+                };
+
+                if transfer.file != file || transfer.owner != owner || transfer.data.len() != offset {
+                    return Err(anyhow!("file data from {} doesn't match the transfer in progress", from));
+                }
+
+                // The size only comes with the first chunk
+                let expected = if offset == 0 { total } else { transfer.total };
+
+                if expected > MAX_TRANSFER_SIZE || offset + chunk.len() > expected {
+                    self.incoming.remove(&from);
+                    send_file_control(transport, PacketType::FileCancel, from, file, owner)?;
+                    self.failed.push_back(FailedTransfer {
+                        peer: from,
+                        owner,
+                        file,
+                        reason: TransferFailure::BadSize,
+                    });
+
+                    return Err(anyhow!(
+                        "file data from {} runs past the {} byte file it claims",
+                        from,
+                        expected
+                    ));
+                }
+
+                if offset == 0 {
+                    transfer.state = NetFileState::Receiving;
+                    transfer.total = total;
+                    transfer.hash = hash;
+                    transfer.data.reserve(total);
+                }
+
+                transfer.data.extend_from_slice(&chunk);
+
+                let progress = TransferProgress {
+                    peer: from,
+                    owner,
+                    file,
+                    state: transfer.state,
+                    transferred: transfer.data.len(),
+                    total: transfer.total,
+                };
+
+                let received = transfer.data.len();
+                let finished = received >= transfer.total;
+
+                let mut ack = start_packet(PacketType::FileAck);
+                ack.write_u32::<LittleEndian>(received as u32)?;
+                transport.send_reliable(from, &end_packet(ack)?)?;
+
+                self.report(&progress);
+
+                if finished {
+                    let transfer = self.incoming.remove(&from).unwrap();
+                    let valid = transfer
+                        .hash
+                        .map(|h| blake3::hash(&transfer.data) == blake3::Hash::from(h))
+                        .unwrap_or(false);
+
+                    if valid {
+                        self.completed.push_back(CompletedFile {
+                            owner: transfer.owner,
+                            file: transfer.file,
+                            data: transfer.data,
+                        });
+                    } else {
+                        warn!(target: "net", "{:?} of player {} failed verification", transfer.file, transfer.owner);
+                        self.failed.push_back(FailedTransfer {
+                            peer: from,
+                            owner: transfer.owner,
+                            file: transfer.file,
+                            reason: TransferFailure::HashMismatch,
+                        });
+                    }
+                }
+
+                Ok(())
+            }
+            PacketType::FileDenied | PacketType::FileCancel => {
+                let file = NetFileId::from_wire(cursor.read_u16::<LittleEndian>()?)?;
+                let owner = cursor.read_u16::<LittleEndian>()? as PlayerSlot;
+
+                let reason = if packet_type == PacketType::FileDenied {
+                    TransferFailure::Denied
+                } else {
+                    TransferFailure::Cancelled
+                };
+
+                let mut ended = false;
+
+                if self.incoming.get(&from).map(|t| t.file == file && t.owner == owner).unwrap_or(false) {
+                    self.incoming.remove(&from);
+                    ended = true;
+                }
+
+                if self.outgoing.get(&from).map(|t| t.file == file && t.owner == owner).unwrap_or(false) {
+                    self.outgoing.remove(&from);
+                    ended = true;
+                }
+
+                if ended {
+                    self.failed.push_back(FailedTransfer { peer: from, owner, file, reason });
+                }
+
+                Ok(())
+            }
+            _ => Err(anyhow!("{:?} is not a file transfer packet", packet_type)),
+        }
+    }
+
+    /// Called once a frame, refills the bandwidth budget and sends any chunks that were held back
+    pub fn update(&mut self, transport: &mut dyn NetTransport, frametime: f32) -> Result<()> {
+        if let Some(cap) = self.bandwidth_cap {
+            // Don't let an idle connection bank more than a second worth of data, or a single
+            // chunk when the cap is lower than that so slow connections still get through
+            self.budget = (self.budget + cap as f32 * frametime).min(cap.max(DATA_CHUNK_SIZE) as f32);
+        }
+
+        self.pump(transport)
+    }
+
+    /// Sends the next chunk to every peer that is waiting on one, as long as the budget allows
+    fn pump(&mut self, transport: &mut dyn NetTransport) -> Result<()> {
+        let mut peers: Vec<PlayerSlot> = self
+            .outgoing
+            .iter()
+            .filter(|(_, t)| t.ready)
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        peers.sort();
+
+        for peer in peers {
+            let transfer = self.outgoing.get_mut(&peer).unwrap();
+            let len = DATA_CHUNK_SIZE.min(transfer.data.len() - transfer.pos);
+
+            if self.bandwidth_cap.is_some() {
+                if self.budget < len as f32 {
+                    break;
+                }
+
+                self.budget -= len as f32;
+            }
+
+            let mut cursor = start_packet(PacketType::FileData);
+            cursor.write_u32::<LittleEndian>(transfer.data.len() as u32)?;
+            cursor.write_u32::<LittleEndian>(transfer.pos as u32)?;
+            cursor.write_u16::<LittleEndian>(transfer.file.to_wire())?;
+            cursor.write_u16::<LittleEndian>(transfer.owner as u16)?;
+
+            if transfer.pos == 0 {
+                cursor.write_all(transfer.hash.as_bytes())?;
+            }
+
+            cursor.write_u16::<LittleEndian>(len as u16)?;
+            cursor.write_all(&transfer.data[transfer.pos..transfer.pos + len])?;
+
+            transport.send_reliable(peer, &end_packet(cursor)?)?;
+
+            transfer.pos += len;
+            transfer.ready = false;
+
+            let progress = TransferProgress {
+                peer,
+                owner: transfer.owner,
+                file: transfer.file,
+                state: NetFileState::Sending,
+                transferred: transfer.pos,
+                total: transfer.data.len(),
+            };
+
+            self.report(&progress);
+        }
+
+        Ok(())
+    }
+
+    fn report(&mut self, progress: &TransferProgress) {
+        if let Some(callback) = &mut self.progress_callback {
+            callback(progress);
+        }
+    }
+
+    pub fn drain_completed(&mut self) -> impl Iterator<Item = CompletedFile> + '_ {
+        self.completed.drain(..)
+    }
+
+    pub fn drain_failed(&mut self) -> impl Iterator<Item = FailedTransfer> + '_ {
+        self.failed.drain(..)
+    }
+}
+
+fn send_file_control(
+    transport: &mut dyn NetTransport,
+    packet_type: PacketType,
+    peer: PlayerSlot,
+    file: NetFileId,
+    owner: PlayerSlot,
+) -> Result<()> {
+    let mut cursor = start_packet(packet_type);
+    cursor.write_u16::<LittleEndian>(file.to_wire())?;
+    cursor.write_u16::<LittleEndian>(owner as u16)?;
+    transport.send_reliable(peer, &end_packet(cursor)?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct QueueTransport {
+        sent: VecDeque<(PlayerSlot, Vec<u8>)>,
+    }
+
+    impl NetTransport for QueueTransport {
+        fn send_reliable(&mut self, slot: PlayerSlot, data: &[u8]) -> Result<()> {
+            self.sent.push_back((slot, data.to_vec()));
+            Ok(())
+        }
+    }
+
+    struct LogoProvider(Vec<u8>);
+
+    impl NetFileProvider for LogoProvider {
+        fn load(&mut self, _owner: PlayerSlot, file: NetFileId) -> Option<Vec<u8>> {
+            if file == NetFileId::ShipTexture { Some(self.0.clone()) } else { None }
+        }
+    }
+
+    /// Shuttles packets between a server in slot 0 and a client in slot 1 until both go quiet
+    fn run(
+        server: &mut FileTransferManager,
+        client: &mut FileTransferManager,
+        to_server: &mut QueueTransport,
+        to_client: &mut QueueTransport,
+        provider: &mut LogoProvider,
+    ) {
+        loop {
+            server.update(to_client, 1.0).unwrap();
+
+            if to_server.sent.is_empty() && to_client.sent.is_empty() {
+                break;
+            }
+
+            while let Some((_, data)) = to_server.sent.pop_front() {
+                server.receive(to_client, provider, 1, &data).unwrap();
+            }
+
+            while let Some((_, data)) = to_client.sent.pop_front() {
+                client.receive(to_server, provider, 0, &data).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn transfer_ship_logo() {
+        crate::test_common::setup();
+
+        let logo: Vec<u8> = (0..2000).map(|i| (i % 251) as u8).collect();
+        let mut provider = LogoProvider(logo.clone());
+
+        let mut server = FileTransferManager::new(0);
+        let mut client = FileTransferManager::new(1);
+        let mut to_server = QueueTransport::default();
+        let mut to_client = QueueTransport::default();
+
+        client.request_file(&mut to_server, 0, 3, NetFileId::ShipTexture).unwrap();
+        run(&mut server, &mut client, &mut to_server, &mut to_client, &mut provider);
+
+        let completed: Vec<CompletedFile> = client.drain_completed().collect();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].owner, 3);
+        assert_eq!(completed[0].data, logo);
+        assert!(!server.is_busy_with(1));
+    }
+
+    #[test]
+    fn missing_file_is_denied() {
+        crate::test_common::setup();
+
+        let mut provider = LogoProvider(vec![1, 2, 3]);
+        let mut server = FileTransferManager::new(0);
+        let mut client = FileTransferManager::new(1);
+        let mut to_server = QueueTransport::default();
+        let mut to_client = QueueTransport::default();
+
+        client.request_file(&mut to_server, 0, 3, NetFileId::VoiceTaunt(1)).unwrap();
+        run(&mut server, &mut client, &mut to_server, &mut to_client, &mut provider);
+
+        let failed: Vec<FailedTransfer> = client.drain_failed().collect();
+        assert_eq!(failed[0].reason, TransferFailure::Denied);
+        assert!(!client.is_busy_with(0));
+    }
+
+    #[test]
+    fn bandwidth_cap_holds_back_chunks() {
+        crate::test_common::setup();
+
+        let mut provider = LogoProvider(vec![7; DATA_CHUNK_SIZE * 4]);
+        let mut server = FileTransferManager::new(0);
+        let mut to_client = QueueTransport::default();
+
+        server.set_bandwidth_cap(Some(DATA_CHUNK_SIZE));
+
+        let mut request = start_packet(PacketType::FileRequest);
+        request.write_u16::<LittleEndian>(NetFileId::ShipTexture.to_wire()).unwrap();
+        request.write_u16::<LittleEndian>(0).unwrap();
+        request.write_u16::<LittleEndian>(1).unwrap();
+        let request = end_packet(request).unwrap();
+
+        server.receive(&mut to_client, &mut provider, 1, &request).unwrap();
+        assert_eq!(to_client.sent.len(), 1);
+
+        let mut ack = start_packet(PacketType::FileAck);
+        ack.write_u32::<LittleEndian>(DATA_CHUNK_SIZE as u32).unwrap();
+        let ack = end_packet(ack).unwrap();
+
+        // Out of budget, so the next chunk has to wait for the next frame
+        server.receive(&mut to_client, &mut provider, 1, &ack).unwrap();
+        assert_eq!(to_client.sent.len(), 1);
+
+        server.update(&mut to_client, 1.0).unwrap();
+        assert_eq!(to_client.sent.len(), 2);
+    }
+
+    #[test]
+    fn cap_below_a_chunk_still_sends() {
+        crate::test_common::setup();
+
+        let mut provider = LogoProvider(vec![7; DATA_CHUNK_SIZE * 2]);
+        let mut server = FileTransferManager::new(0);
+        let mut to_client = QueueTransport::default();
+
+        server.set_bandwidth_cap(Some(100));
+
+        let mut request = start_packet(PacketType::FileRequest);
+        request.write_u16::<LittleEndian>(NetFileId::ShipTexture.to_wire()).unwrap();
+        request.write_u16::<LittleEndian>(0).unwrap();
+        request.write_u16::<LittleEndian>(1).unwrap();
+        let request = end_packet(request).unwrap();
+
+        server.receive(&mut to_client, &mut provider, 1, &request).unwrap();
+        assert!(to_client.sent.is_empty());
+
+        // The budget saves up over several seconds until a whole chunk fits
+        for _ in 0..3 {
+            server.update(&mut to_client, 1.0).unwrap();
+        }
+        assert!(to_client.sent.is_empty());
+
+        server.update(&mut to_client, 1.0).unwrap();
+        assert_eq!(to_client.sent.len(), 1);
+    }
+
+    #[test]
+    fn oversized_file_data_is_rejected() {
+        crate::test_common::setup();
+
+        let file_data = |total: usize, offset: usize, chunk: &[u8]| {
+            let mut cursor = start_packet(PacketType::FileData);
+            cursor.write_u32::<LittleEndian>(total as u32).unwrap();
+            cursor.write_u32::<LittleEndian>(offset as u32).unwrap();
+            cursor.write_u16::<LittleEndian>(NetFileId::ShipTexture.to_wire()).unwrap();
+            cursor.write_u16::<LittleEndian>(3).unwrap();
+
+            if offset == 0 {
+                cursor.write_all(&[0; 32]).unwrap();
+            }
+
+            cursor.write_u16::<LittleEndian>(chunk.len() as u16).unwrap();
+            cursor.write_all(chunk).unwrap();
+            end_packet(cursor).unwrap()
+        };
+
+        let mut provider = LogoProvider(Vec::new());
+        let mut client = FileTransferManager::new(1);
+        let mut to_server = QueueTransport::default();
+
+        // A size over the limit is turned down before anything is allocated for it
+        client.request_file(&mut to_server, 0, 3, NetFileId::ShipTexture).unwrap();
+        to_server.sent.clear();
+
+        let packet = file_data(MAX_TRANSFER_SIZE + 1, 0, &[1; 10]);
+        assert!(client.receive(&mut to_server, &mut provider, 0, &packet).is_err());
+        assert!(!client.is_busy_with(0));
+        assert_eq!(to_server.sent.len(), 1);
+
+        let failed: Vec<FailedTransfer> = client.drain_failed().collect();
+        assert_eq!(failed[0].reason, TransferFailure::BadSize);
+
+        // So is a chunk that runs past the size it was given
+        client.request_file(&mut to_server, 0, 3, NetFileId::ShipTexture).unwrap();
+        client.receive(&mut to_server, &mut provider, 0, &file_data(12, 0, &[1; 10])).unwrap();
+
+        let packet = file_data(12, 10, &[1; 10]);
+        assert!(client.receive(&mut to_server, &mut provider, 0, &packet).is_err());
+        assert!(!client.is_busy_with(0));
+        assert_eq!(client.drain_failed().next().unwrap().reason, TransferFailure::BadSize);
+    }
+}
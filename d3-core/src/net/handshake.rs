@@ -0,0 +1,258 @@
+/*
+
+Handshake
+
+The first thing a client says to a server is which version of the protocol
+it speaks and which optional features it has, the server answers with what
+the two of them will use or why they can't play together. Builds a version or
+two apart can still meet in the middle: each side names the oldest version it
+will go down to, and a feature only one side has is just left off unless the
+other side can't do without it.
+
+*/
+
+use std::fmt;
+
+use anyhow::Result;
+use bitflags::bitflags;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{end_packet, read_net_string, read_packet_header, start_packet, write_net_string, PacketType};
+
+/// The protocol this build speaks
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// The oldest protocol this build will fall back to
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+bitflags! {
+    /// Optional parts of the protocol
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct NetFeatures: u32 {
+        /// Player markers and their placement packet
+        const MARKERS = 0x01;
+        /// Team games
+        const TEAMS = 0x02;
+        /// Object updates as deltas against acknowledged baselines
+        const DELTA_OBJECT_UPDATES = 0x04;
+        /// Object classes past the retail set
+        const EXTENDED_OBJECT_TYPES = 0x08;
+        /// Weapons past the retail set
+        const NEW_WEAPONS = 0x10;
+    }
+}
+
+/// What one side of a connection can do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: u16,
+    pub min_version: u16,
+    pub supported: NetFeatures,
+    /// Features this side can't play without, a level with new weapons in it say
+    pub required: NetFeatures,
+    /// Shown to the other side when something doesn't match
+    pub build: String,
+}
+
+impl Capabilities {
+    /// This build's
+    pub fn local() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            min_version: MIN_PROTOCOL_VERSION,
+            supported: NetFeatures::all(),
+            required: NetFeatures::empty(),
+            build: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Why a server turned a client away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// The client's protocol is older than the server goes back to
+    ClientTooOld { client: u16, oldest: u16 },
+    /// The server's protocol is older than the client goes back to
+    ClientTooNew { server: u16, oldest: u16 },
+    /// One side needs features the other doesn't have
+    MissingFeatures(NetFeatures),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::ClientTooOld { client, oldest } => {
+                write!(f, "this game is protocol version {}, the server needs {} or newer, update the game", client, oldest)
+            }
+            Rejection::ClientTooNew { server, oldest } => {
+                write!(f, "the server is protocol version {}, this game needs {} or newer, the server needs updating", server, oldest)
+            }
+            Rejection::MissingFeatures(features) => write!(f, "features needed but not supported on both sides: {:?}", features),
+        }
+    }
+}
+
+/// The version and features a server and client will use, or why they can't play
+pub fn negotiate(server: &Capabilities, client: &Capabilities) -> std::result::Result<(u16, NetFeatures), Rejection> {
+    if client.version < server.min_version {
+        return Err(Rejection::ClientTooOld { client: client.version, oldest: server.min_version });
+    }
+
+    if server.version < client.min_version {
+        return Err(Rejection::ClientTooNew { server: server.version, oldest: client.min_version });
+    }
+
+    let missing = (server.required - client.supported) | (client.required - server.supported);
+
+    if !missing.is_empty() {
+        return Err(Rejection::MissingFeatures(missing));
+    }
+
+    Ok((server.version.min(client.version), server.supported & client.supported))
+}
+
+/// The server's answer to a hello
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeReply {
+    Welcome { version: u16, features: NetFeatures },
+    Rejected(String),
+}
+
+pub fn encode_hello(capabilities: &Capabilities) -> Result<Vec<u8>> {
+    let mut cursor = start_packet(PacketType::Hello);
+    cursor.write_u16::<LittleEndian>(capabilities.version)?;
+    cursor.write_u16::<LittleEndian>(capabilities.min_version)?;
+    cursor.write_u32::<LittleEndian>(capabilities.supported.bits())?;
+    cursor.write_u32::<LittleEndian>(capabilities.required.bits())?;
+    write_net_string(&mut cursor, &capabilities.build)?;
+    end_packet(cursor)
+}
+
+pub fn decode_hello(data: &[u8]) -> Result<Capabilities> {
+    let (packet_type, mut cursor) = read_packet_header(data)?;
+
+    if packet_type != PacketType::Hello {
+        return Err(anyhow!("{:?} is not a hello packet", packet_type));
+    }
+
+    Ok(Capabilities {
+        version: cursor.read_u16::<LittleEndian>()?,
+        min_version: cursor.read_u16::<LittleEndian>()?,
+        // Features from a newer build that this one doesn't know are dropped, neither side can use them
+        supported: NetFeatures::from_bits_truncate(cursor.read_u32::<LittleEndian>()?),
+        required: NetFeatures::from_bits_retain(cursor.read_u32::<LittleEndian>()?),
+        build: read_net_string(&mut cursor)?,
+    })
+}
+
+pub fn encode_reply(reply: &HandshakeReply) -> Result<Vec<u8>> {
+    match reply {
+        HandshakeReply::Welcome { version, features } => {
+            let mut cursor = start_packet(PacketType::Welcome);
+            cursor.write_u16::<LittleEndian>(*version)?;
+            cursor.write_u32::<LittleEndian>(features.bits())?;
+            end_packet(cursor)
+        }
+        HandshakeReply::Rejected(reason) => {
+            let mut cursor = start_packet(PacketType::Rejected);
+            write_net_string(&mut cursor, reason)?;
+            end_packet(cursor)
+        }
+    }
+}
+
+pub fn decode_reply(data: &[u8]) -> Result<HandshakeReply> {
+    let (packet_type, mut cursor) = read_packet_header(data)?;
+
+    match packet_type {
+        PacketType::Welcome => Ok(HandshakeReply::Welcome {
+            version: cursor.read_u16::<LittleEndian>()?,
+            features: NetFeatures::from_bits_truncate(cursor.read_u32::<LittleEndian>()?),
+        }),
+        PacketType::Rejected => Ok(HandshakeReply::Rejected(read_net_string(&mut cursor)?)),
+        _ => Err(anyhow!("{:?} is not a handshake reply", packet_type)),
+    }
+}
+
+/// The server's side, answers a client's hello
+pub fn answer_hello(server: &Capabilities, hello: &[u8]) -> Result<HandshakeReply> {
+    let client = decode_hello(hello)?;
+
+    let reply = match negotiate(server, &client) {
+        Ok((version, features)) => HandshakeReply::Welcome { version, features },
+        Err(rejection) => {
+            info!(target: "net", "turned away a client on build {}: {}", client.build, rejection);
+            HandshakeReply::Rejected(rejection.to_string())
+        }
+    };
+
+    Ok(reply)
+}
+
+/// The client's side, what was agreed or an error saying why the server said no
+pub fn accept_reply(reply: &[u8]) -> Result<(u16, NetFeatures)> {
+    match decode_reply(reply)? {
+        HandshakeReply::Welcome { version, features } => Ok((version, features)),
+        HandshakeReply::Rejected(reason) => Err(anyhow!("the server refused the connection: {}", reason)),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn side(version: u16, min_version: u16, supported: NetFeatures, required: NetFeatures) -> Capabilities {
+        Capabilities { version, min_version, supported, required, build: "test".to_string() }
+    }
+
+    #[test]
+    fn handshake_negotiation_test() {
+        crate::test_common::setup();
+
+        let server = side(3, 2, NetFeatures::MARKERS | NetFeatures::TEAMS, NetFeatures::empty());
+
+        // Meet at the older version with the features both have
+        let client = side(2, 1, NetFeatures::TEAMS | NetFeatures::NEW_WEAPONS, NetFeatures::empty());
+        assert_eq!(negotiate(&server, &client), Ok((2, NetFeatures::TEAMS)));
+
+        assert_eq!(
+            negotiate(&server, &side(1, 1, NetFeatures::all(), NetFeatures::empty())),
+            Err(Rejection::ClientTooOld { client: 1, oldest: 2 })
+        );
+        assert_eq!(
+            negotiate(&server, &side(5, 4, NetFeatures::all(), NetFeatures::empty())),
+            Err(Rejection::ClientTooNew { server: 3, oldest: 4 })
+        );
+
+        let needs_weapons = side(3, 1, NetFeatures::all(), NetFeatures::NEW_WEAPONS);
+        assert_eq!(negotiate(&server, &needs_weapons), Err(Rejection::MissingFeatures(NetFeatures::NEW_WEAPONS)));
+    }
+
+    #[test]
+    fn handshake_exchange_test() {
+        crate::test_common::setup();
+
+        let server = Capabilities::local();
+        let hello = encode_hello(&Capabilities::local()).unwrap();
+        assert_eq!(decode_hello(&hello).unwrap(), Capabilities::local());
+
+        let reply = answer_hello(&server, &hello).unwrap();
+        assert_eq!(accept_reply(&encode_reply(&reply).unwrap()).unwrap(), (PROTOCOL_VERSION, NetFeatures::all()));
+
+        // Turned away with the reason spelled out
+        let mut old = Capabilities::local();
+        old.version = 0;
+        old.min_version = 0;
+        let reply = answer_hello(&server, &encode_hello(&old).unwrap()).unwrap();
+        let error = accept_reply(&encode_reply(&reply).unwrap()).unwrap_err().to_string();
+        assert!(error.contains("update the game"), "{}", error);
+
+        // Features from a newer build that it can't do without come through so they can be refused
+        let mut newer = Capabilities::local();
+        newer.supported = NetFeatures::from_bits_retain(0x8000_0000);
+        newer.required = NetFeatures::from_bits_retain(0x8000_0000);
+        let decoded = decode_hello(&encode_hello(&newer).unwrap()).unwrap();
+        assert!(decoded.supported.is_empty());
+        assert!(matches!(negotiate(&server, &decoded), Err(Rejection::MissingFeatures(_))));
+    }
+}
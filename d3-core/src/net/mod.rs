@@ -0,0 +1,180 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Result;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub mod chat;
+pub mod file_transfer;
+pub mod handshake;
+pub mod move_validation;
+pub mod object_update;
+pub mod replication;
+
+#[cfg(test)]
+mod wire_tests;
+
+pub const MAX_NET_PLAYERS: usize = 32;
+pub const MAX_TEAMS: usize = 4;
+
+pub const MAX_PACKET_SIZE: usize = 512;
+pub const MAX_GAME_DATA_SIZE: usize = MAX_PACKET_SIZE - 4;
+
+/// Size of the type byte plus the u16 packet length.
+pub const PACKET_HEADER_SIZE: usize = 3;
+
+/// The server always occupies the first player slot.
+pub const SERVER_SLOT: PlayerSlot = 0;
+
+pub type PlayerSlot = u8;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetRole {
+    /// We are hosting the game and relaying for everyone else.
+    Server, // LR_SERVER
+
+    /// We are connected to a server.
+    Client, // LR_CLIENT
+}
+
+/// Packet types sent across the multiplayer channel.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PacketType {
+    /// A text message from the server.
+    MessageFromServer = 27, // MP_MESSAGE_FROM_SERVER
+
+    /// A message from the client to the server.
+    MessageToServer = 33, // MP_MESSAGE_TO_SERVER
+
+    /// Request a file for a particular player slot.
+    FileRequest = 67, // MP_FILE_REQ
+
+    /// The sender isn't going to send the file that was asked for.
+    FileDenied = 68, // MP_FILE_DENIED
+
+    /// Data chunk, which is part of a file transfer.
+    FileData = 69, // MP_FILE_DATA
+
+    /// Acknowledges a chunk so the sender will send the next one.
+    FileAck = 70, // MP_FILE_ACK
+
+    /// Cancel an existing file transfer, sent by either side.
+    FileCancel = 73, // MP_FILE_CANCEL
+
+    /// Client is requesting to play audio taunt.
+    ClientPlayTaunt = 101, // MP_CLIENT_PLAY_TAUNT
+
+    /// Server is telling clients to play a player's audio taunt.
+    ServerPlayTaunt = 102, // MP_SERVER_PLAY_TAUNT
+
+    /// A player dropped a marker, or moved one by dropping another past their limit.
+    MarkerPlace = 120,
+
+    /// The server's state for a batch of objects.
+    ObjectUpdate = 121,
+
+    /// A client has the object updates up to a sequence.
+    ObjectUpdateAck = 122,
+
+    /// A client's protocol version and features, the first thing it sends.
+    Hello = 123,
+
+    /// The server accepted a hello, with the version and features to use.
+    Welcome = 124,
+
+    /// The server turned a client away, and why.
+    Rejected = 125,
+}
+
+impl TryFrom<u8> for PacketType {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            27 => Ok(PacketType::MessageFromServer),
+            33 => Ok(PacketType::MessageToServer),
+            67 => Ok(PacketType::FileRequest),
+            68 => Ok(PacketType::FileDenied),
+            69 => Ok(PacketType::FileData),
+            70 => Ok(PacketType::FileAck),
+            73 => Ok(PacketType::FileCancel),
+            101 => Ok(PacketType::ClientPlayTaunt),
+            102 => Ok(PacketType::ServerPlayTaunt),
+            120 => Ok(PacketType::MarkerPlace),
+            121 => Ok(PacketType::ObjectUpdate),
+            122 => Ok(PacketType::ObjectUpdateAck),
+            123 => Ok(PacketType::Hello),
+            124 => Ok(PacketType::Welcome),
+            125 => Ok(PacketType::Rejected),
+            _ => Err(anyhow!("unknown packet type {}", value)),
+        }
+    }
+}
+
+/// The low level connection the game sends its packets through.
+pub trait NetTransport {
+    /// Sends a packet that is guaranteed to arrive, in order.
+    fn send_reliable(&mut self, slot: PlayerSlot, data: &[u8]) -> Result<()>;
+}
+
+/// Begins a packet of data, the length is filled in by `end_packet`
+pub fn start_packet(packet_type: PacketType) -> Cursor<Vec<u8>> {
+    let mut cursor = Cursor::new(Vec::with_capacity(MAX_GAME_DATA_SIZE));
+    cursor.write_u8(packet_type as u8).unwrap();
+    cursor.write_u16::<LittleEndian>(0).unwrap();
+    cursor
+}
+
+/// Patches the packet length into the header
+pub fn end_packet(cursor: Cursor<Vec<u8>>) -> Result<Vec<u8>> {
+    let mut data = cursor.into_inner();
+
+    if data.len() > MAX_GAME_DATA_SIZE {
+        return Err(anyhow!("packet too large: {} bytes", data.len()));
+    }
+
+    let len = data.len() as u16;
+    data[1..PACKET_HEADER_SIZE].copy_from_slice(&len.to_le_bytes());
+    Ok(data)
+}
+
+/// Reads the packet header and returns the type along with a cursor positioned at the payload
+pub fn read_packet_header(data: &[u8]) -> Result<(PacketType, Cursor<&[u8]>)> {
+    let mut cursor = Cursor::new(data);
+    let packet_type = PacketType::try_from(cursor.read_u8()?)?;
+    let len = cursor.read_u16::<LittleEndian>()? as usize;
+
+    if len != data.len() {
+        return Err(anyhow!("packet length mismatch: header says {}, got {}", len, data.len()));
+    }
+
+    Ok((packet_type, cursor))
+}
+
+/// Writes a length prefixed, null terminated string the way the original netcode does
+pub fn write_net_string<W: Write>(writer: &mut W, value: &str) -> Result<()> {
+    let bytes = value.as_bytes();
+    let len = bytes.len() + 1;
+
+    if len > u8::MAX as usize {
+        return Err(anyhow!("string too long for packet: {} bytes", bytes.len()));
+    }
+
+    writer.write_u8(len as u8)?;
+    writer.write_all(bytes)?;
+    writer.write_u8(0)?;
+    Ok(())
+}
+
+pub fn read_net_string<R: Read>(reader: &mut R) -> Result<String> {
+    let len = reader.read_u8()? as usize;
+    let mut bytes = vec![0u8; len];
+    reader.read_exact(&mut bytes)?;
+
+    // Strip off the null terminator
+    if let Some(end) = bytes.iter().position(|b| *b == 0) {
+        bytes.truncate(end);
+    }
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
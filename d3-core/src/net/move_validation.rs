@@ -0,0 +1,386 @@
+/*
+
+Move validation
+
+Clients tell the server where their ship is, which a modified client can lie
+about. The server holds each claim up against what the player's ship could
+actually have done since the last one: no faster than its top speed, no
+quicker a change in velocity than its thrust allows, no farther than that
+speed covers in the time, and not through a wall. Whatever breaks the rules is
+pulled back to the nearest legal move and sent back to the client as a
+correction. Each violation is a strike against the player, strikes wear off
+over time so lag spikes are forgiven, and a player with too many is flagged
+for the server to kick or report.
+
+*/
+
+use std::collections::HashMap;
+
+use bitflags::bitflags;
+
+use crate::{
+    common::SharedMutRef,
+    game::{
+        object::Object,
+        physics::intersection::{FqFlags, HitType, Query},
+        room::Room,
+        ship::ShipPhysics,
+    },
+    math::{angle::Angle, matrix::Matrix, vector::Vector},
+};
+
+use super::{
+    object_update::{NetObjectId, ObjectUpdate, ObjectUpdateFields},
+    PlayerSlot,
+};
+
+/// Slack on every limit, for timing jitter between client and server
+pub const MOVE_TOLERANCE: f32 = 1.1;
+
+/// How much more thrust a ship has with its afterburner on
+pub const AFTERBURNER_THRUST_SCALE: f32 = 2.0;
+
+/// Strikes a player can build up before they're flagged
+pub const MAX_MOVE_STRIKES: f32 = 10.0;
+
+/// Strikes worn off per second of moves without violations
+pub const STRIKE_DECAY: f32 = 1.0;
+
+bitflags! {
+    /// What was wrong with a claimed move
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct MoveViolations: u8 {
+        /// Faster than the ship goes
+        const SPEED = 0x01;
+        /// Velocity changed faster than the ship's thrust allows
+        const ACCELERATION = 0x02;
+        /// Farther than the ship could fly in the time
+        const DISTANCE = 0x04;
+        /// The path from the last position goes through a wall
+        const WALL_CLIP = 0x08;
+    }
+}
+
+/// How hard and fast a ship can go
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MoveLimits {
+    pub max_speed: f32,
+    pub max_acceleration: f32,
+}
+
+impl MoveLimits {
+    /// Drag balances full thrust at top speed, and thrust over mass is the hardest the ship can accelerate
+    pub fn for_ship(physics: &ShipPhysics, afterburner: bool) -> Self {
+        let thrust = physics.full_thrust * if afterburner { AFTERBURNER_THRUST_SCALE } else { 1.0 };
+
+        Self {
+            max_speed: if physics.drag > 0.0 { thrust / physics.drag } else { f32::INFINITY },
+            max_acceleration: if physics.mass > 0.0 { thrust / physics.mass } else { f32::INFINITY },
+        }
+    }
+}
+
+/// Where a client says its ship is after `frametime` seconds
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ClientMove {
+    pub position: Vector,
+    pub velocity: Vector,
+    pub frametime: f32,
+}
+
+/// The move the server accepts, which is the claim itself unless something was wrong with it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MoveVerdict {
+    pub violations: MoveViolations,
+    pub position: Vector,
+    pub velocity: Vector,
+}
+
+impl MoveVerdict {
+    pub fn is_legal(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// The update that puts the client back where the server has it, `None` for legal moves
+    pub fn correction(&self, id: NetObjectId) -> Option<ObjectUpdate> {
+        if self.is_legal() {
+            return None;
+        }
+
+        Some(ObjectUpdate {
+            id,
+            fields: ObjectUpdateFields::POSITION,
+            position: self.position,
+            orientation: Matrix::IDENTITY,
+            shields: 0.0,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct AcceptedMove {
+    position: Vector,
+    velocity: Vector,
+    strikes: f32,
+}
+
+/// The last accepted move of each player and the strikes against them
+#[derive(Debug, Clone, Default)]
+pub struct MoveValidator {
+    players: HashMap<PlayerSlot, AcceptedMove>,
+}
+
+impl MoveValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a player over at a position the server put them at, when they spawn or are moved.
+    /// Strikes are kept
+    pub fn reset(&mut self, slot: PlayerSlot, position: Vector) {
+        let strikes = self.strikes(slot);
+
+        self.players.insert(
+            slot,
+            AcceptedMove {
+                position,
+                velocity: Vector::ZERO,
+                strikes,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, slot: PlayerSlot) {
+        self.players.remove(&slot);
+    }
+
+    pub fn strikes(&self, slot: PlayerSlot) -> f32 {
+        self.players.get(&slot).map_or(0.0, |accepted| accepted.strikes)
+    }
+
+    pub fn is_flagged(&self, slot: PlayerSlot) -> bool {
+        self.strikes(slot) >= MAX_MOVE_STRIKES
+    }
+
+    /// Checks a move against the ship's limits and `path_clear`, which says whether a ship can fly
+    /// straight from one point to another, see `move_path_clear`. A player the server hasn't placed
+    /// yet has their first move taken as it is
+    pub fn validate(
+        &mut self,
+        slot: PlayerSlot,
+        claimed: &ClientMove,
+        limits: &MoveLimits,
+        path_clear: impl FnOnce(&Vector, &Vector) -> bool,
+    ) -> MoveVerdict {
+        let Some(last) = self.players.get(&slot).copied() else {
+            self.reset(slot, claimed.position);
+            self.players.get_mut(&slot).unwrap().velocity = claimed.velocity;
+
+            return MoveVerdict {
+                violations: MoveViolations::empty(),
+                position: claimed.position,
+                velocity: claimed.velocity,
+            };
+        };
+
+        let frametime = claimed.frametime.max(0.0);
+        let max_speed = limits.max_speed * MOVE_TOLERANCE;
+        let mut violations = MoveViolations::empty();
+        let mut velocity = claimed.velocity;
+
+        if Vector::magnitude(&velocity) > max_speed {
+            velocity = clamp_length(velocity, max_speed);
+            violations |= MoveViolations::SPEED;
+        }
+
+        let change = velocity - last.velocity;
+        let max_change = limits.max_acceleration * frametime * MOVE_TOLERANCE;
+
+        if Vector::magnitude(&change) > max_change {
+            velocity = last.velocity + clamp_length(change, max_change);
+            violations |= MoveViolations::ACCELERATION;
+        }
+
+        let moved = claimed.position - last.position;
+        let max_distance = max_speed * frametime;
+        let mut position = claimed.position;
+
+        if Vector::magnitude(&moved) > max_distance {
+            position = last.position + clamp_length(moved, max_distance);
+            violations |= MoveViolations::DISTANCE;
+        }
+
+        if position != last.position && !path_clear(&last.position, &position) {
+            position = last.position;
+            velocity = Vector::ZERO;
+            violations |= MoveViolations::WALL_CLIP;
+        }
+
+        let strikes = if violations.is_empty() {
+            (last.strikes - STRIKE_DECAY * frametime).max(0.0)
+        } else {
+            last.strikes + 1.0
+        };
+
+        if strikes >= MAX_MOVE_STRIKES && last.strikes < MAX_MOVE_STRIKES {
+            warn!(target: "net", "player {} flagged for illegal movement ({:?})", slot, violations);
+        }
+
+        self.players.insert(slot, AcceptedMove { position, velocity, strikes });
+
+        MoveVerdict { violations, position, velocity }
+    }
+}
+
+fn clamp_length(vector: Vector, length: f32) -> Vector {
+    let magnitude = Vector::magnitude(&vector);
+
+    if magnitude > length && magnitude > 0.0 {
+        vector * (length / magnitude)
+    } else {
+        vector
+    }
+}
+
+/// Whether the ship could fly straight from `from` to `to`, `cast` runs the FVI query. Only walls
+/// and terrain stop it
+pub fn move_path_clear(
+    start_room: SharedMutRef<Room>,
+    ship: SharedMutRef<Object>,
+    radius: f32,
+    from: &Vector,
+    to: &Vector,
+    cast: impl FnOnce(&Query) -> HitType,
+) -> bool {
+    let query = Query {
+        p0: *from,
+        p1: *to,
+        start_room,
+        rad: radius,
+        this_obj: Some(ship),
+        ignore_obj_list: (),
+        flags: FqFlags::NO_RELINK,
+        bbox_orientation: Matrix::IDENTITY,
+        bbox_rotvel: Vector::ZERO,
+        bbox_rotthrust: Vector::ZERO,
+        bbox_velocity: Vector::ZERO,
+        bbox_turnroll: Angle(0),
+        bbox_thrust: Vector::ZERO,
+        frametime: 0.0,
+    };
+
+    !matches!(cast(&query), HitType::Wall | HitType::Terrain)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::rc::Rc;
+
+    use crate::game::object::{ObjectClass, ObjectTypeDef};
+
+    use super::*;
+
+    fn limits() -> MoveLimits {
+        MoveLimits { max_speed: 50.0, max_acceleration: 100.0 }
+    }
+
+    fn step(position: Vector, velocity: Vector) -> ClientMove {
+        ClientMove { position, velocity, frametime: 0.1 }
+    }
+
+    #[test]
+    fn move_limits_test() {
+        crate::test_common::setup();
+
+        let physics = crate::game::ship::retail_ships()[0].physics;
+        let normal = MoveLimits::for_ship(&physics, false);
+        assert_eq!(normal.max_speed, physics.full_thrust / physics.drag);
+        assert_eq!(normal.max_acceleration, physics.full_thrust / physics.mass);
+        assert!(MoveLimits::for_ship(&physics, true).max_speed > normal.max_speed);
+    }
+
+    #[test]
+    fn move_validation_test() {
+        crate::test_common::setup();
+
+        let mut validator = MoveValidator::new();
+        let clear = |_: &Vector, _: &Vector| true;
+        validator.reset(1, Vector::ZERO);
+
+        // Speeding up within the ship's thrust and covering the distance that takes is fine
+        let verdict = validator.validate(1, &step(Vector::new(0.5, 0.0, 0.0), Vector::new(10.0, 0.0, 0.0)), &limits(), clear);
+        assert!(verdict.is_legal());
+        assert!(verdict.correction(3).is_none());
+
+        // Jumping to top speed at once is held to what the thrust gives
+        let verdict = validator.validate(1, &step(Vector::new(1.5, 0.0, 0.0), Vector::new(500.0, 0.0, 0.0)), &limits(), clear);
+        assert_eq!(verdict.violations, MoveViolations::SPEED | MoveViolations::ACCELERATION);
+        assert!(verdict.velocity.approx_eq(&Vector::new(21.0, 0.0, 0.0), 0.001));
+        assert_eq!(verdict.position, Vector::new(1.5, 0.0, 0.0));
+
+        // A teleport is cut back to how far the ship could have gone
+        let verdict = validator.validate(1, &step(Vector::new(100.0, 0.0, 0.0), Vector::new(25.0, 0.0, 0.0)), &limits(), clear);
+        assert_eq!(verdict.violations, MoveViolations::DISTANCE);
+        assert!(verdict.position.approx_eq(&Vector::new(7.0, 0.0, 0.0), 0.001));
+        assert_eq!(verdict.correction(3).unwrap().position, verdict.position);
+
+        // Through a wall stays put
+        let verdict = validator.validate(1, &step(Vector::new(8.0, 0.0, 0.0), Vector::new(25.0, 0.0, 0.0)), &limits(), |_, _| false);
+        assert_eq!(verdict.violations, MoveViolations::WALL_CLIP);
+        assert_eq!((verdict.position, verdict.velocity), (Vector::new(7.0, 0.0, 0.0), Vector::ZERO));
+        assert_eq!(validator.strikes(1), 3.0);
+    }
+
+    #[test]
+    fn move_wall_clip_test() {
+        crate::test_common::setup();
+
+        let room = crate::common::new_shared_mut_ref(Room::default());
+        let ship = crate::common::new_shared_mut_ref(Object::new(ObjectTypeDef::new("ship", ObjectClass::Player)));
+        let mut validator = MoveValidator::new();
+        validator.reset(1, Vector::ZERO);
+
+        // A wall across x = 4, the ship's radius has to fit on the near side of it
+        let cast = |query: &Query| {
+            assert!(Rc::ptr_eq(query.this_obj.as_ref().unwrap(), &ship));
+            if query.p1.x + query.rad > 4.0 { HitType::Wall } else { HitType::None }
+        };
+        let path_clear = |from: &Vector, to: &Vector| move_path_clear(room.clone(), ship.clone(), 1.0, from, to, cast);
+
+        let verdict = validator.validate(1, &step(Vector::new(2.5, 0.0, 0.0), Vector::new(10.0, 0.0, 0.0)), &limits(), path_clear);
+        assert!(verdict.is_legal());
+
+        let verdict = validator.validate(1, &step(Vector::new(3.5, 0.0, 0.0), Vector::new(10.0, 0.0, 0.0)), &limits(), path_clear);
+        assert_eq!(verdict.violations, MoveViolations::WALL_CLIP);
+        assert_eq!((verdict.position, verdict.velocity), (Vector::new(2.5, 0.0, 0.0), Vector::ZERO));
+        assert_eq!(verdict.correction(3).unwrap().position, Vector::new(2.5, 0.0, 0.0));
+    }
+
+    #[test]
+    fn move_strikes_test() {
+        crate::test_common::setup();
+
+        let mut validator = MoveValidator::new();
+        let far = Vector::new(1000.0, 0.0, 0.0);
+
+        // The first move is taken as it is
+        assert!(validator.validate(2, &step(Vector::ZERO, Vector::ZERO), &limits(), |_, _| true).is_legal());
+
+        for i in 0..MAX_MOVE_STRIKES as usize {
+            assert!(!validator.is_flagged(2));
+            validator.validate(2, &step(far * (i + 1) as f32, Vector::ZERO), &limits(), |_, _| true);
+        }
+
+        assert!(validator.is_flagged(2));
+
+        // Behaving wears the strikes back off, respawning doesn't clear them
+        let position = validator.players[&2].position;
+        validator.validate(2, &ClientMove { position, velocity: Vector::ZERO, frametime: 2.0 }, &limits(), |_, _| true);
+        assert_eq!(validator.strikes(2), MAX_MOVE_STRIKES - 2.0);
+        validator.reset(2, Vector::ZERO);
+        assert_eq!(validator.strikes(2), MAX_MOVE_STRIKES - 2.0);
+
+        validator.remove(2);
+        assert!(!validator.is_flagged(2));
+    }
+}
@@ -0,0 +1,306 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Result;
+use bitflags::bitflags;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    game::object::{NetSimFlags, Object},
+    math::{matrix::Matrix, vector::Vector},
+};
+
+use super::{end_packet, read_packet_header, start_packet, PacketType, MAX_GAME_DATA_SIZE, PACKET_HEADER_SIZE};
+
+/// The server's number for an object, the same on every machine in the game
+pub type NetObjectId = u16;
+
+/// Numbers each batch of updates sent to a client so it can say which it got
+pub type UpdateSequence = u32;
+
+/// Bytes at the front of an update packet, the header and the sequence
+pub const OBJECT_UPDATE_HEADER_SIZE: usize = PACKET_HEADER_SIZE + 4;
+
+bitflags! {
+    /// Which parts of an object an update carries
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct ObjectUpdateFields: u8 {
+        const POSITION = 0x01;
+        const ORIENTATION = 0x02;
+        const SHIELDS = 0x04;
+    }
+}
+
+impl ObjectUpdateFields {
+    /// The parts of an object the server sends, leaving out what clients simulate themselves
+    pub fn server_owned(net_sim: NetSimFlags) -> Self {
+        let mut fields = Self::all();
+
+        if net_sim.contains(NetSimFlags::CLIENT_MOVEMENT) {
+            fields.remove(Self::POSITION);
+        }
+
+        if net_sim.contains(NetSimFlags::CLIENT_ORIENTATION) {
+            fields.remove(Self::ORIENTATION);
+        }
+
+        // Shields go along with the object being the server's at all
+        if net_sim.is_client_only() {
+            fields.remove(Self::SHIELDS);
+        }
+
+        fields
+    }
+}
+
+/// The server's word on an object's state, only the fields in `fields` mean anything
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ObjectUpdate {
+    pub id: NetObjectId,
+    pub fields: ObjectUpdateFields,
+    pub position: Vector,
+    pub orientation: Matrix,
+    pub shields: f32,
+}
+
+impl ObjectUpdate {
+    /// Everything the server owns of `object`, `None` for objects clients simulate entirely
+    pub fn capture(id: NetObjectId, object: &Object) -> Option<Self> {
+        let fields = ObjectUpdateFields::server_owned(object.net_sim);
+
+        if fields.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            id,
+            fields,
+            position: object.position,
+            orientation: object.orientation,
+            shields: object.shields,
+        })
+    }
+
+    /// Puts the update on a client's copy of the object. Fields the client simulates itself are
+    /// left alone even if the server sent them
+    pub fn apply(&self, object: &mut Object) {
+        let fields = self.fields & ObjectUpdateFields::server_owned(object.net_sim);
+
+        if fields.contains(ObjectUpdateFields::POSITION) {
+            object.last_position = object.position;
+            object.position = self.position;
+        }
+
+        if fields.contains(ObjectUpdateFields::ORIENTATION) {
+            object.last_orientation = object.orientation;
+            object.orientation = self.orientation;
+        }
+
+        if fields.contains(ObjectUpdateFields::SHIELDS) {
+            object.shields = self.shields;
+        }
+    }
+
+    /// Bytes the update takes in a packet
+    pub fn wire_size(&self) -> usize {
+        let mut size = 3;
+
+        if self.fields.contains(ObjectUpdateFields::POSITION) {
+            size += 12;
+        }
+
+        if self.fields.contains(ObjectUpdateFields::ORIENTATION) {
+            size += 36;
+        }
+
+        if self.fields.contains(ObjectUpdateFields::SHIELDS) {
+            size += 4;
+        }
+
+        size
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u16::<LittleEndian>(self.id)?;
+        writer.write_u8(self.fields.bits())?;
+
+        if self.fields.contains(ObjectUpdateFields::POSITION) {
+            write_vector(writer, &self.position)?;
+        }
+
+        if self.fields.contains(ObjectUpdateFields::ORIENTATION) {
+            write_vector(writer, &self.orientation.right)?;
+            write_vector(writer, &self.orientation.up)?;
+            write_vector(writer, &self.orientation.forward)?;
+        }
+
+        if self.fields.contains(ObjectUpdateFields::SHIELDS) {
+            writer.write_f32::<LittleEndian>(self.shields)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let id = reader.read_u16::<LittleEndian>()?;
+        let bits = reader.read_u8()?;
+        let fields = ObjectUpdateFields::from_bits(bits).ok_or_else(|| anyhow!("invalid object update fields {:#x}", bits))?;
+
+        let mut update = Self {
+            id,
+            fields,
+            position: Vector::ZERO,
+            orientation: Matrix::IDENTITY,
+            shields: 0.0,
+        };
+
+        if fields.contains(ObjectUpdateFields::POSITION) {
+            update.position = read_vector(reader)?;
+        }
+
+        if fields.contains(ObjectUpdateFields::ORIENTATION) {
+            update.orientation = Matrix {
+                right: read_vector(reader)?,
+                up: read_vector(reader)?,
+                forward: read_vector(reader)?,
+            };
+        }
+
+        if fields.contains(ObjectUpdateFields::SHIELDS) {
+            update.shields = reader.read_f32::<LittleEndian>()?;
+        }
+
+        Ok(update)
+    }
+}
+
+fn write_vector<W: Write>(writer: &mut W, vector: &Vector) -> Result<()> {
+    writer.write_f32::<LittleEndian>(vector.x)?;
+    writer.write_f32::<LittleEndian>(vector.y)?;
+    writer.write_f32::<LittleEndian>(vector.z)?;
+    Ok(())
+}
+
+fn read_vector<R: Read>(reader: &mut R) -> Result<Vector> {
+    Ok(Vector {
+        x: reader.read_f32::<LittleEndian>()?,
+        y: reader.read_f32::<LittleEndian>()?,
+        z: reader.read_f32::<LittleEndian>()?,
+    })
+}
+
+fn start_update_packet(sequence: UpdateSequence) -> Result<Cursor<Vec<u8>>> {
+    let mut cursor = start_packet(PacketType::ObjectUpdate);
+    cursor.write_u32::<LittleEndian>(sequence)?;
+    Ok(cursor)
+}
+
+/// Packs a batch of updates into as few packets as they fit in, all under the one sequence
+pub fn encode_object_updates(sequence: UpdateSequence, updates: &[ObjectUpdate]) -> Result<Vec<Vec<u8>>> {
+    let mut packets = Vec::new();
+    let mut cursor = start_update_packet(sequence)?;
+
+    for update in updates {
+        // There's no count, updates run to the end of the packet
+        if cursor.get_ref().len() + update.wire_size() > MAX_GAME_DATA_SIZE {
+            packets.push(end_packet(cursor)?);
+            cursor = start_update_packet(sequence)?;
+        }
+
+        update.write_to(&mut cursor)?;
+    }
+
+    if cursor.get_ref().len() > OBJECT_UPDATE_HEADER_SIZE {
+        packets.push(end_packet(cursor)?);
+    }
+
+    Ok(packets)
+}
+
+pub fn decode_object_updates(data: &[u8]) -> Result<(UpdateSequence, Vec<ObjectUpdate>)> {
+    let (packet_type, mut cursor) = read_packet_header(data)?;
+
+    if packet_type != PacketType::ObjectUpdate {
+        return Err(anyhow!("{:?} is not an object update packet", packet_type));
+    }
+
+    let sequence = cursor.read_u32::<LittleEndian>()?;
+    let mut updates = Vec::new();
+
+    while (cursor.position() as usize) < data.len() {
+        updates.push(ObjectUpdate::read_from(&mut cursor)?);
+    }
+
+    Ok((sequence, updates))
+}
+
+/// A client telling the server it has every update up to `sequence`
+pub fn encode_update_ack(sequence: UpdateSequence) -> Result<Vec<u8>> {
+    let mut cursor = start_packet(PacketType::ObjectUpdateAck);
+    cursor.write_u32::<LittleEndian>(sequence)?;
+    end_packet(cursor)
+}
+
+pub fn decode_update_ack(data: &[u8]) -> Result<UpdateSequence> {
+    let (packet_type, mut cursor) = read_packet_header(data)?;
+
+    if packet_type != PacketType::ObjectUpdateAck {
+        return Err(anyhow!("{:?} is not an update ack packet", packet_type));
+    }
+
+    Ok(cursor.read_u32::<LittleEndian>()?)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::game::object::{ObjectClass, ObjectTypeDef};
+
+    fn object(class: ObjectClass) -> Object {
+        let mut object = Object::new(ObjectTypeDef::new("test", class));
+        object.position = Vector::new(1.0, 2.0, 3.0);
+        object.shields = 50.0;
+        object
+    }
+
+    #[test]
+    fn object_update_authority_test() {
+        crate::test_common::setup();
+
+        // Robots are all the server's, powerups spin on their own, debris is never sent
+        let robot = ObjectUpdate::capture(1, &object(ObjectClass::Robot)).unwrap();
+        assert_eq!(robot.fields, ObjectUpdateFields::all());
+        let powerup = ObjectUpdate::capture(2, &object(ObjectClass::Powerup)).unwrap();
+        assert_eq!(powerup.fields, ObjectUpdateFields::POSITION | ObjectUpdateFields::SHIELDS);
+        assert!(ObjectUpdate::capture(3, &object(ObjectClass::Debris)).is_none());
+
+        let mut ambient = ObjectTypeDef::new("bird", ObjectClass::Clutter);
+        ambient.flags = crate::game::object::BehaviorFlags::AMBIENT_OBJECT;
+        assert!(ObjectUpdate::capture(4, &Object::new(ambient)).is_none());
+
+        // A client keeps its own spin even when told otherwise
+        let mut local = object(ObjectClass::Powerup);
+        let spun = Matrix { right: Vector::new(0.0, 0.0, 1.0), up: Vector::new(0.0, 1.0, 0.0), forward: Vector::new(-1.0, 0.0, 0.0) };
+        local.orientation = spun;
+        let update = ObjectUpdate { fields: ObjectUpdateFields::all(), position: Vector::new(9.0, 9.0, 9.0), ..robot };
+        update.apply(&mut local);
+        assert_eq!(local.position, Vector::new(9.0, 9.0, 9.0));
+        assert_eq!(local.orientation.forward, spun.forward);
+    }
+
+    #[test]
+    fn object_update_packet_test() {
+        crate::test_common::setup();
+
+        let updates: Vec<_> = (0..40).map(|id| ObjectUpdate::capture(id, &object(ObjectClass::Robot)).unwrap()).collect();
+        let packets = encode_object_updates(7, &updates).unwrap();
+        assert!(packets.len() > 1);
+        assert!(packets.iter().all(|p| p.len() <= MAX_GAME_DATA_SIZE));
+
+        let decoded: Vec<_> = packets.iter().map(|p| decode_object_updates(p).unwrap()).collect();
+        assert!(decoded.iter().all(|(sequence, _)| *sequence == 7));
+        assert_eq!(decoded.into_iter().flat_map(|(_, u)| u).collect::<Vec<_>>(), updates);
+
+        assert!(encode_object_updates(7, &[]).unwrap().is_empty());
+        assert_eq!(decode_update_ack(&encode_update_ack(7).unwrap()).unwrap(), 7);
+    }
+}
@@ -0,0 +1,300 @@
+/*
+
+Object replication
+
+Keeping each client's copy of the level's objects in step with the server's
+without sending everything every frame. For each client the server keeps the
+last state of each object that client said it received, its baseline, and
+only sends the fields that have moved on from it. Updates that haven't been
+acknowledged yet are sent again until they are, since they're always taken
+against the acknowledged baseline a lost packet costs nothing extra.
+
+Each client has a budget of bytes per second. When there's more to send than
+fits, objects in rooms the client's viewer can see go first, then the
+closest, and anything left out waits its turn with a better place in line
+next frame so far off objects still get updated now and then.
+
+*/
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    game::{portal_visibility::RoomVisibility, room::RoomId},
+    math::vector::Vector,
+};
+
+use super::object_update::{NetObjectId, ObjectUpdate, ObjectUpdateFields, UpdateSequence, OBJECT_UPDATE_HEADER_SIZE};
+
+/// Bytes per second a client gets if nothing else is set
+pub const DEFAULT_CLIENT_BANDWIDTH: usize = 6000;
+
+/// Movement smaller than this isn't worth sending
+pub const POSITION_EPSILON: f32 = 0.01;
+
+/// Nor is turning less than this, per component of each axis
+pub const ORIENTATION_EPSILON: f32 = 0.001;
+
+/// Unacknowledged batches kept for a client, any older are given up on
+const MAX_PENDING_BATCHES: usize = 64;
+
+/// Objects out of sight count as this many times farther away
+const OUT_OF_SIGHT_PENALTY: f32 = 4.0;
+
+/// An object the server could send this frame
+#[derive(Debug, Copy, Clone)]
+pub struct ReplicationCandidate {
+    /// Everything the server owns of the object, from `ObjectUpdate::capture`
+    pub update: ObjectUpdate,
+    pub position: Vector,
+    pub room: Option<RoomId>,
+}
+
+/// What the server knows of one client's copy of the objects
+#[derive(Debug, Clone)]
+pub struct ClientReplication {
+    baselines: HashMap<NetObjectId, ObjectUpdate>,
+    pending: VecDeque<(UpdateSequence, Vec<ObjectUpdate>)>,
+    next_sequence: UpdateSequence,
+    /// Frames each object with changes has been left out for
+    waiting: HashMap<NetObjectId, u32>,
+    pub bytes_per_second: usize,
+    /// Bytes that can go out now, refilled each frame and never more than a second's worth
+    allowance: f32,
+}
+
+impl Default for ClientReplication {
+    fn default() -> Self {
+        Self::new(DEFAULT_CLIENT_BANDWIDTH)
+    }
+}
+
+impl ClientReplication {
+    pub fn new(bytes_per_second: usize) -> Self {
+        Self {
+            baselines: HashMap::new(),
+            pending: VecDeque::new(),
+            next_sequence: 1,
+            waiting: HashMap::new(),
+            bytes_per_second,
+            allowance: 0.0,
+        }
+    }
+
+    pub fn baseline(&self, id: NetObjectId) -> Option<&ObjectUpdate> {
+        self.baselines.get(&id)
+    }
+
+    /// The fields of `full` the client doesn't have yet, `None` if it's up to date
+    pub fn delta(&self, full: &ObjectUpdate) -> Option<ObjectUpdate> {
+        let Some(baseline) = self.baselines.get(&full.id) else {
+            return Some(*full);
+        };
+
+        let mut fields = ObjectUpdateFields::empty();
+
+        if full.fields.contains(ObjectUpdateFields::POSITION)
+            && (!baseline.fields.contains(ObjectUpdateFields::POSITION) || !full.position.approx_eq(&baseline.position, POSITION_EPSILON))
+        {
+            fields |= ObjectUpdateFields::POSITION;
+        }
+
+        if full.fields.contains(ObjectUpdateFields::ORIENTATION) {
+            let (a, b) = (&full.orientation, &baseline.orientation);
+            let turned = !a.right.approx_eq(&b.right, ORIENTATION_EPSILON)
+                || !a.up.approx_eq(&b.up, ORIENTATION_EPSILON)
+                || !a.forward.approx_eq(&b.forward, ORIENTATION_EPSILON);
+
+            if turned || !baseline.fields.contains(ObjectUpdateFields::ORIENTATION) {
+                fields |= ObjectUpdateFields::ORIENTATION;
+            }
+        }
+
+        if full.fields.contains(ObjectUpdateFields::SHIELDS)
+            && (!baseline.fields.contains(ObjectUpdateFields::SHIELDS) || full.shields != baseline.shields)
+        {
+            fields |= ObjectUpdateFields::SHIELDS;
+        }
+
+        if fields.is_empty() {
+            None
+        } else {
+            Some(ObjectUpdate { fields, ..*full })
+        }
+    }
+
+    /// Picks what to send this frame within the budget, best first. The batch is numbered so the
+    /// client can acknowledge it, pass both to `encode_object_updates`
+    pub fn build_batch(
+        &mut self,
+        frametime: f32,
+        candidates: &[ReplicationCandidate],
+        viewer: &Vector,
+        visibility: &RoomVisibility,
+    ) -> (UpdateSequence, Vec<ObjectUpdate>) {
+        let second = self.bytes_per_second as f32;
+        self.allowance = (self.allowance + second * frametime).min(second);
+
+        let mut changed: Vec<(f32, ObjectUpdate)> = candidates
+            .iter()
+            .filter_map(|candidate| {
+                let delta = self.delta(&candidate.update)?;
+                let visible = candidate.room.is_some_and(|room| visibility.is_visible(room));
+                let distance = Vector::distance(&candidate.position, viewer) * if visible { 1.0 } else { OUT_OF_SIGHT_PENALTY };
+                let waited = self.waiting.get(&delta.id).copied().unwrap_or(0);
+
+                Some((distance / (1 + waited) as f32, delta))
+            })
+            .collect();
+
+        changed.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut batch = Vec::new();
+        let mut bytes = OBJECT_UPDATE_HEADER_SIZE;
+
+        for (_, update) in changed {
+            if bytes + update.wire_size() <= self.allowance as usize {
+                bytes += update.wire_size();
+                self.waiting.remove(&update.id);
+                batch.push(update);
+            } else {
+                *self.waiting.entry(update.id).or_default() += 1;
+            }
+        }
+
+        let sequence = self.next_sequence;
+
+        if !batch.is_empty() {
+            self.allowance -= bytes as f32;
+            self.next_sequence = self.next_sequence.wrapping_add(1);
+            self.pending.push_back((sequence, batch.clone()));
+
+            if self.pending.len() > MAX_PENDING_BATCHES {
+                self.pending.pop_front();
+            }
+        }
+
+        (sequence, batch)
+    }
+
+    /// The client has everything up to `sequence`, the updates in those batches become its baselines
+    pub fn acknowledge(&mut self, sequence: UpdateSequence) {
+        while let Some((pending_sequence, _)) = self.pending.front() {
+            if pending_sequence.wrapping_sub(sequence) as i32 > 0 {
+                break;
+            }
+
+            let (_, batch) = self.pending.pop_front().unwrap();
+
+            for update in batch {
+                let baseline = self.baselines.entry(update.id).or_insert(ObjectUpdate {
+                    fields: ObjectUpdateFields::empty(),
+                    ..update
+                });
+
+                if update.fields.contains(ObjectUpdateFields::POSITION) {
+                    baseline.position = update.position;
+                }
+
+                if update.fields.contains(ObjectUpdateFields::ORIENTATION) {
+                    baseline.orientation = update.orientation;
+                }
+
+                if update.fields.contains(ObjectUpdateFields::SHIELDS) {
+                    baseline.shields = update.shields;
+                }
+
+                baseline.fields |= update.fields;
+            }
+        }
+    }
+
+    /// The object is gone, or the client lost track of it and needs all of it again
+    pub fn forget(&mut self, id: NetObjectId) {
+        self.baselines.remove(&id);
+        self.waiting.remove(&id);
+    }
+
+    /// Starts the client over, for a new level
+    pub fn reset(&mut self) {
+        self.baselines.clear();
+        self.pending.clear();
+        self.waiting.clear();
+        self.allowance = 0.0;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::math::matrix::Matrix;
+
+    fn candidate(id: NetObjectId, x: f32, room: RoomId) -> ReplicationCandidate {
+        let position = Vector::new(x, 0.0, 0.0);
+
+        ReplicationCandidate {
+            update: ObjectUpdate {
+                id,
+                fields: ObjectUpdateFields::all(),
+                position,
+                orientation: Matrix::IDENTITY,
+                shields: 100.0,
+            },
+            position,
+            room: Some(room),
+        }
+    }
+
+    #[test]
+    fn replication_delta_test() {
+        crate::test_common::setup();
+
+        let mut client = ClientReplication::new(100_000);
+        let visibility = RoomVisibility { rooms: vec![0], portal_faces: Vec::new() };
+        let mut objects = vec![candidate(1, 10.0, 0), candidate(2, 20.0, 0)];
+
+        // Everything the first time, nothing once acknowledged and unchanged
+        let (sequence, batch) = client.build_batch(0.1, &objects, &Vector::ZERO, &visibility);
+        assert_eq!(batch.len(), 2);
+        assert!(batch.iter().all(|u| u.fields == ObjectUpdateFields::all()));
+        client.acknowledge(sequence);
+        assert!(client.build_batch(0.1, &objects, &Vector::ZERO, &visibility).1.is_empty());
+
+        // Only what changed, resent until acknowledged
+        objects[0].update.shields = 40.0;
+        objects[0].update.position.x += POSITION_EPSILON / 2.0;
+        let (first, batch) = client.build_batch(0.1, &objects, &Vector::ZERO, &visibility);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].fields, ObjectUpdateFields::SHIELDS);
+        let (second, batch) = client.build_batch(0.1, &objects, &Vector::ZERO, &visibility);
+        assert_eq!(batch[0].fields, ObjectUpdateFields::SHIELDS);
+        assert_eq!(second, first + 1);
+
+        client.acknowledge(first);
+        assert_eq!(client.baseline(1).unwrap().shields, 40.0);
+        assert!(client.build_batch(0.1, &objects, &Vector::ZERO, &visibility).1.is_empty());
+
+        client.forget(2);
+        assert_eq!(client.build_batch(0.1, &objects, &Vector::ZERO, &visibility).1[0].id, 2);
+    }
+
+    #[test]
+    fn replication_budget_test() {
+        crate::test_common::setup();
+
+        let full_size = candidate(0, 0.0, 0).update.wire_size();
+        let frame_bytes = OBJECT_UPDATE_HEADER_SIZE + full_size * 2;
+
+        // Two updates a frame at ten frames a second
+        let mut client = ClientReplication::new(frame_bytes * 10);
+        let visibility = RoomVisibility { rooms: vec![0], portal_faces: Vec::new() };
+
+        // The near one out of sight loses to the farther one in sight
+        let objects = vec![candidate(1, 4.0, 1), candidate(2, 10.0, 0), candidate(3, 15.0, 0), candidate(4, 1000.0, 0)];
+        let (_, batch) = client.build_batch(0.1, &objects, &Vector::ZERO, &visibility);
+        assert_eq!(batch.iter().map(|u| u.id).collect::<Vec<_>>(), vec![2, 3]);
+
+        // Left out once, the out of sight one moves up the line
+        let (_, batch) = client.build_batch(0.1, &objects, &Vector::ZERO, &visibility);
+        assert_eq!(batch.iter().map(|u| u.id).collect::<Vec<_>>(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,190 @@
+/*
+
+Wire compatibility
+
+Every packet type checked byte for byte against a known good encoding. Other
+builds have to be able to read what this one sends, so a change that moves
+any of these bytes is a protocol change: bump `handshake::PROTOCOL_VERSION`
+and give the new layout its own golden rather than editing the old one.
+
+*/
+
+use std::collections::VecDeque;
+
+use super::{
+    chat::{decode_message, encode_message_from_server, encode_message_to_server, encode_taunt, ChatMessage, ChatMessageKind, MessageTarget},
+    file_transfer::{FileTransferManager, NetFileId},
+    handshake::{decode_hello, decode_reply, encode_hello, encode_reply, Capabilities, HandshakeReply, NetFeatures},
+    object_update::{decode_object_updates, decode_update_ack, encode_object_updates, encode_update_ack, ObjectUpdate, ObjectUpdateFields},
+    read_packet_header, NetTransport, PacketType, PlayerSlot,
+};
+use crate::{
+    common::WeakHandle,
+    game::{
+        marker::{decode_marker, encode_marker, Marker},
+        room::RoomStore,
+    },
+    math::{matrix::Matrix, vector::Vector},
+};
+
+#[derive(Default)]
+struct RecordingTransport {
+    sent: VecDeque<(PlayerSlot, Vec<u8>)>,
+}
+
+impl NetTransport for RecordingTransport {
+    fn send_reliable(&mut self, slot: PlayerSlot, data: &[u8]) -> anyhow::Result<()> {
+        self.sent.push_back((slot, data.to_vec()));
+        Ok(())
+    }
+}
+
+#[test]
+fn chat_wire_test() {
+    crate::test_common::setup();
+
+    let to_server = encode_message_to_server(2, MessageTarget::Team(1), "hi").unwrap();
+    assert_eq!(to_server, [33, 9, 0, 2, 0xFD, 3, b'h', b'i', 0]);
+    let (_, message) = decode_message(&to_server).unwrap();
+    assert_eq!((message.sender, message.target), (Some(2), MessageTarget::Team(1)));
+
+    let from_server = encode_message_from_server(&ChatMessage {
+        sender: None,
+        target: MessageTarget::All,
+        color: 0x00FF8000,
+        kind: ChatMessageKind::Text("ok".to_string()),
+    })
+    .unwrap();
+    assert_eq!(from_server, [27, 13, 0, 0x00, 0x80, 0xFF, 0x00, 0xFF, 0xFF, 3, b'o', b'k', 0]);
+    assert!(decode_message(&from_server).is_ok());
+
+    let taunt = encode_taunt(PacketType::ClientPlayTaunt, 3, 1).unwrap();
+    assert_eq!(taunt, [101, 5, 0, 3, 1]);
+    assert_eq!(encode_taunt(PacketType::ServerPlayTaunt, 3, 1).unwrap()[0], 102);
+}
+
+#[test]
+fn file_transfer_wire_test() {
+    crate::test_common::setup();
+
+    let mut transport = RecordingTransport::default();
+    let mut files = FileTransferManager::new(1);
+
+    files.request_file(&mut transport, 0, 4, NetFileId::ShipTexture).unwrap();
+    assert_eq!(transport.sent.pop_front().unwrap(), (0, vec![67, 9, 0, 1, 0, 4, 0, 1, 0]));
+
+    files.cancel(&mut transport, 0).unwrap();
+    assert_eq!(transport.sent.pop_front().unwrap(), (0, vec![73, 7, 0, 1, 0, 4, 0]));
+
+    assert_eq!(NetFileId::VoiceTaunt(3).to_wire(), 5);
+    assert_eq!(NetFileId::Mission.to_wire(), 10);
+}
+
+#[test]
+fn marker_wire_test() {
+    crate::test_common::setup();
+
+    let marker = Marker {
+        owner: 1,
+        slot: 2,
+        position: Vector::new(1.0, -2.0, 0.5),
+        room: WeakHandle::new(),
+        text: "go".to_string(),
+        object: None,
+    };
+
+    let data = encode_marker(&marker).unwrap();
+    #[rustfmt::skip]
+    assert_eq!(data, [
+        120, 25, 0,
+        1, 2,
+        0x00, 0x00, 0x80, 0x3F, 0x00, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x3F,
+        0xFF, 0xFF, 0xFF, 0xFF,
+        3, b'g', b'o', 0,
+    ]);
+
+    let decoded = decode_marker(&data, &RoomStore::new()).unwrap();
+    assert_eq!((decoded.owner, decoded.slot, decoded.position, decoded.text.as_str()), (1, 2, marker.position, "go"));
+}
+
+#[test]
+fn object_update_wire_test() {
+    crate::test_common::setup();
+
+    let update = ObjectUpdate {
+        id: 0x0102,
+        fields: ObjectUpdateFields::POSITION | ObjectUpdateFields::SHIELDS,
+        position: Vector::new(1.0, 2.0, 3.0),
+        orientation: Matrix::IDENTITY,
+        shields: 50.0,
+    };
+
+    let packets = encode_object_updates(7, &[update]).unwrap();
+    #[rustfmt::skip]
+    assert_eq!(packets, [vec![
+        121, 26, 0,
+        7, 0, 0, 0,
+        0x02, 0x01, 0x05,
+        0x00, 0x00, 0x80, 0x3F, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x40, 0x40,
+        0x00, 0x00, 0x48, 0x42,
+    ]]);
+    assert_eq!(decode_object_updates(&packets[0]).unwrap(), (7, vec![update]));
+
+    let ack = encode_update_ack(7).unwrap();
+    assert_eq!(ack, [122, 7, 0, 7, 0, 0, 0]);
+    assert_eq!(decode_update_ack(&ack).unwrap(), 7);
+}
+
+#[test]
+fn handshake_wire_test() {
+    crate::test_common::setup();
+
+    let capabilities = Capabilities {
+        version: 1,
+        min_version: 1,
+        supported: NetFeatures::from_bits_retain(0x1F),
+        required: NetFeatures::empty(),
+        build: "x".to_string(),
+    };
+
+    let hello = encode_hello(&capabilities).unwrap();
+    #[rustfmt::skip]
+    assert_eq!(hello, [
+        123, 18, 0,
+        1, 0, 1, 0,
+        0x1F, 0, 0, 0,
+        0, 0, 0, 0,
+        2, b'x', 0,
+    ]);
+    assert_eq!(decode_hello(&hello).unwrap(), capabilities);
+
+    let welcome = HandshakeReply::Welcome { version: 1, features: NetFeatures::MARKERS | NetFeatures::TEAMS };
+    let data = encode_reply(&welcome).unwrap();
+    assert_eq!(data, [124, 9, 0, 1, 0, 3, 0, 0, 0]);
+    assert_eq!(decode_reply(&data).unwrap(), welcome);
+
+    let rejected = HandshakeReply::Rejected("no".to_string());
+    let data = encode_reply(&rejected).unwrap();
+    assert_eq!(data, [125, 7, 0, 3, b'n', b'o', 0]);
+    assert_eq!(decode_reply(&data).unwrap(), rejected);
+}
+
+#[test]
+fn packet_type_wire_test() {
+    crate::test_common::setup();
+
+    // Every type reads back as itself, and nothing else is taken for one
+    #[rustfmt::skip]
+    let known = [27, 33, 67, 68, 69, 70, 73, 101, 102, 120, 121, 122, 123, 124, 125];
+
+    for value in 0..=u8::MAX {
+        let data = [value, 3, 0];
+
+        match read_packet_header(&data) {
+            Ok((packet_type, _)) => assert_eq!(packet_type as u8, value),
+            Err(_) => assert!(!known.contains(&value), "packet type {} no longer decodes", value),
+        }
+    }
+
+    assert!(known.iter().all(|&value| read_packet_header(&[value, 3, 0]).is_ok()));
+}
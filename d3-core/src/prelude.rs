@@ -0,0 +1,37 @@
+//! Common types for apps built on top of the engine, `use d3_core::prelude::*` to get going
+
+pub use crate::common::{new_shared_mut_ref, ManualSystemClock, SharedMutRef, SharedRef, SystemClock};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::common::StdSystemClock;
+
+pub use crate::math::{
+    angle::{Angle, EulerAngle},
+    bounds::Aabb,
+    matrix::{Matrix, Matrix4},
+    plane::Plane,
+    vector::{Vector, Vector4},
+    vector2d::Vector2D,
+    CrossProduct, DotProduct,
+};
+
+pub use crate::graphics::{
+    bitmap::{Bitmap16, BitmapFormat},
+    dd_video::{ScreenResizeListener, VideoBackend, VideoManager, VideoMode},
+    drawing_3d::{
+        legacy_soft::SoftRenderSetup,
+        projection::{AspectMode, Fov, Projection},
+        scene::{Scene, SceneBuilder},
+        Camera, CameraBuilder, ClippingCode, CustomClip, CustomClipStack, Point3, RenderSetupState, ScreenViewPort,
+        ViewportBuilder,
+    },
+    generic_bitmap::GenericBitmap16,
+};
+
+pub use crate::filesystem::{
+    hog::Hog,
+    loader::{AssetLoader, AssetSource, LoadHandle},
+    memory::MemoryFileProvider,
+};
+
+pub use crate::string::D3String;
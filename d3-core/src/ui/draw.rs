@@ -0,0 +1,300 @@
+use std::rc::Rc;
+
+use super::{UiRect, UiWidget, UiWindow};
+use crate::{
+    gr_color_blue, gr_color_green, gr_color_red, gr_rgb,
+    graphics::{
+        ddgr_color,
+        drawing_2d::{
+            font::FontGraphic,
+            text::{RenderedTextBuf, RenderedTextRect},
+        },
+        rendering::{AlphaType, RenderVertex, Renderer},
+        TextureHandle,
+    },
+};
+
+/// Space around text inside a widget's row, in pixels
+pub const UI_PADDING: usize = 4;
+
+/// What layout needs to know about a font
+pub trait UiFont {
+    fn text_width(&self, text: &str) -> usize;
+
+    fn line_height(&self) -> usize;
+}
+
+impl UiFont for FontGraphic {
+    fn text_width(&self, text: &str) -> usize {
+        let range = self.get_font().get_ascii_range();
+
+        // One pixel between characters, the text buffer's default spacing
+        text.bytes()
+            .filter(|b| range.contains(&(*b as usize)))
+            .map(|b| self.get_char_width(b as usize) + 1)
+            .sum()
+    }
+
+    fn line_height(&self) -> usize {
+        self.get_height()
+    }
+}
+
+/// Colors the UI is drawn with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UiTheme {
+    pub window: ddgr_color,
+    pub title: ddgr_color,
+    pub text: ddgr_color,
+    pub disabled: ddgr_color,
+    pub focus: ddgr_color,
+    pub selected: ddgr_color,
+    pub field: ddgr_color,
+    pub bar: ddgr_color,
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        Self {
+            window: gr_rgb!(16, 24, 40),
+            title: gr_rgb!(255, 220, 120),
+            text: gr_rgb!(200, 220, 255),
+            disabled: gr_rgb!(90, 100, 120),
+            focus: gr_rgb!(40, 70, 120),
+            selected: gr_rgb!(60, 110, 60),
+            field: gr_rgb!(8, 12, 20),
+            bar: gr_rgb!(120, 160, 240),
+        }
+    }
+}
+
+/// One thing to draw, images go under rects and text always goes over both
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiDraw {
+    Image { rect: UiRect, texture: TextureHandle },
+    Rect { rect: UiRect, color: ddgr_color },
+    Text { x: usize, y: usize, text: String, color: ddgr_color },
+}
+
+/// Lays a window out top to bottom, a row per line of text
+pub fn layout_window(window: &UiWindow, font: &impl UiFont, theme: &UiTheme) -> Vec<UiDraw> {
+    let line = font.line_height();
+    let row = line + UI_PADDING * 2;
+    let rect = window.rect;
+    let inner_width = rect.width.saturating_sub(UI_PADDING * 2);
+
+    let mut draws = vec![UiDraw::Rect { rect, color: theme.window }];
+
+    let text = |x: usize, y: usize, text: &str, color: ddgr_color| UiDraw::Text {
+        x,
+        y,
+        text: text.to_string(),
+        color,
+    };
+
+    let centered = |y: usize, value: &str, color: ddgr_color| {
+        let x = rect.x + rect.width.saturating_sub(font.text_width(value)) / 2;
+        text(x, y, value, color)
+    };
+
+    draws.push(centered(rect.y + UI_PADDING, &window.title, theme.title));
+
+    let mut y = rect.y + row + UI_PADDING;
+    let x = rect.x + UI_PADDING;
+    let focused = window.focused();
+
+    for item in window.items() {
+        let height = row * item.widget.rows();
+        let color = if item.enabled { theme.text } else { theme.disabled };
+        let text_y = y + UI_PADDING;
+
+        if focused == Some(item.id) {
+            draws.push(UiDraw::Rect {
+                rect: UiRect::new(x, y, inner_width, height),
+                color: theme.focus,
+            });
+        }
+
+        match &item.widget {
+            UiWidget::Label(value) => draws.push(text(x + UI_PADDING, text_y, value, color)),
+            UiWidget::Button(value) => draws.push(centered(text_y, value, color)),
+            UiWidget::Checkbox { text: value, checked } => {
+                let mark = if *checked { "[x] " } else { "[ ] " };
+                draws.push(text(x + UI_PADDING, text_y, &format!("{}{}", mark, value), color));
+            }
+            UiWidget::Choice { text: value, options, selected } => {
+                draws.push(text(x + UI_PADDING, text_y, value, color));
+
+                let option = options.get(*selected).map(|o| o.as_str()).unwrap_or("");
+                draws.push(text(x + inner_width / 2, text_y, &format!("< {} >", option), color));
+            }
+            UiWidget::Slider { text: value, value: amount, min, max, .. } => {
+                draws.push(text(x + UI_PADDING, text_y, value, color));
+
+                // The right half of the row is the bar
+                let bar = UiRect::new(x + inner_width / 2, y + UI_PADDING, (inner_width / 2).saturating_sub(UI_PADDING), line);
+                let fraction = if max > min { (amount - min) / (max - min) } else { 0.0 };
+
+                draws.push(UiDraw::Rect { rect: bar, color: theme.field });
+                draws.push(UiDraw::Rect {
+                    rect: UiRect { width: (bar.width as f32 * fraction) as usize, ..bar },
+                    color: theme.bar,
+                });
+            }
+            UiWidget::List { items, selected, scroll, rows } => {
+                for (i, entry) in items.iter().enumerate().skip(*scroll).take(*rows) {
+                    let entry_y = y + (i - scroll) * row;
+
+                    if i == *selected {
+                        draws.push(UiDraw::Rect {
+                            rect: UiRect::new(x + UI_PADDING, entry_y, inner_width.saturating_sub(UI_PADDING * 2), row),
+                            color: theme.selected,
+                        });
+                    }
+
+                    draws.push(text(x + UI_PADDING * 2, entry_y + UI_PADDING, entry, color));
+                }
+            }
+            UiWidget::TextEntry { text: value, value: entry, .. } => {
+                draws.push(text(x + UI_PADDING, text_y, value, color));
+
+                let field = UiRect::new(x + inner_width / 3, y + UI_PADDING / 2, (inner_width * 2 / 3).saturating_sub(UI_PADDING), line + UI_PADDING);
+                let caret = if focused == Some(item.id) { "_" } else { "" };
+
+                draws.push(UiDraw::Rect { rect: field, color: theme.field });
+                draws.push(text(field.x + UI_PADDING, text_y, &format!("{}{}", entry, caret), color));
+            }
+        }
+
+        y += height;
+    }
+
+    draws
+}
+
+fn color_vertex(x: usize, y: usize, color: ddgr_color) -> RenderVertex {
+    RenderVertex {
+        screen_x: x as f32,
+        screen_y: y as f32,
+        r: gr_color_red!(color) as f32 / 255.0,
+        g: gr_color_green!(color) as f32 / 255.0,
+        b: gr_color_blue!(color) as f32 / 255.0,
+        a: 1.0,
+        ..Default::default()
+    }
+}
+
+/// Draws a laid out window, images, flat rects then the text on top
+pub fn render_ui<R: Renderer>(draws: &[UiDraw], renderer: &mut R, font: &Rc<FontGraphic>) {
+    renderer.set_alpha_type(AlphaType::TEXTURE);
+
+    for draw in draws {
+        if let UiDraw::Image { rect, texture } = draw {
+            let corner = |x: usize, y: usize, u: f32, v: f32| RenderVertex {
+                u,
+                v,
+                ..color_vertex(x, y, gr_rgb!(255, 255, 255))
+            };
+
+            renderer.set_texture(Some(*texture));
+            renderer.draw_polygon(&[
+                corner(rect.x, rect.y, 0.0, 0.0),
+                corner(rect.right(), rect.y, 1.0, 0.0),
+                corner(rect.right(), rect.bottom(), 1.0, 1.0),
+                corner(rect.x, rect.bottom(), 0.0, 1.0),
+            ]);
+        }
+    }
+
+    renderer.set_texture(None);
+    renderer.set_alpha_type(AlphaType::ALWAYS);
+
+    for draw in draws {
+        if let UiDraw::Rect { rect, color } = draw {
+            renderer.set_flat_color(*color);
+            renderer.draw_polygon(&[
+                color_vertex(rect.x, rect.y, *color),
+                color_vertex(rect.right(), rect.y, *color),
+                color_vertex(rect.right(), rect.bottom(), *color),
+                color_vertex(rect.x, rect.bottom(), *color),
+            ]);
+        }
+    }
+
+    let screen = renderer.get_projection_screen_rect();
+    let mut text = RenderedTextBuf::new(
+        font.clone(),
+        RenderedTextRect {
+            left: 0,
+            top: 0,
+            right: screen.width,
+            bottom: screen.height,
+        },
+    );
+
+    for draw in draws {
+        if let UiDraw::Text { x, y, text: value, color } = draw {
+            text.append_color(*color);
+            text.append_text(value.clone().into(), *x, *y);
+        }
+    }
+
+    text.render(renderer);
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::ui::{UiInput, UiWidget};
+
+    /// Every character 8 wide, lines 10 high
+    struct FixedFont;
+
+    impl UiFont for FixedFont {
+        fn text_width(&self, text: &str) -> usize {
+            text.len() * 8
+        }
+
+        fn line_height(&self) -> usize {
+            10
+        }
+    }
+
+    #[test]
+    fn ui_layout_test() {
+        crate::test_common::setup();
+
+        let mut window = UiWindow::new("Options", UiRect::new(100, 50, 200, 150));
+        window
+            .add(1, UiWidget::slider("Volume", 0.25, 0.0, 1.0, 0.25))
+            .add(2, UiWidget::Button("Done".to_string()));
+
+        let theme = UiTheme::default();
+        let draws = layout_window(&window, &FixedFont, &theme);
+
+        // Title centered, "Options" is 56 wide
+        assert_eq!(
+            draws[1],
+            UiDraw::Text { x: 172, y: 54, text: "Options".to_string(), color: theme.title }
+        );
+
+        // A quarter of the slider bar filled in, under the focus highlight
+        let rects: Vec<&UiDraw> = draws.iter().filter(|d| matches!(d, UiDraw::Rect { .. })).collect();
+        assert!(matches!(rects[1], UiDraw::Rect { rect, color } if *color == theme.focus && rect.y == 72));
+        assert!(matches!(rects[3], UiDraw::Rect { rect, color } if *color == theme.bar && rect.width == 23));
+
+        window.handle_input(UiInput::Down);
+        let draws = layout_window(&window, &FixedFont, &theme);
+        assert!(draws.contains(&UiDraw::Rect { rect: UiRect::new(104, 90, 192, 18), color: theme.focus }));
+
+        // Too narrow for the padding, the bars and fields shrink to nothing rather than wrapping around
+        let mut narrow = UiWindow::new("Tiny", UiRect::new(0, 0, 6, 150));
+        narrow
+            .add(1, UiWidget::slider("Volume", 0.5, 0.0, 1.0, 0.25))
+            .add(2, UiWidget::list(vec!["One".to_string(), "Two".to_string()], 2))
+            .add(3, UiWidget::text_entry("Name", "Pilot", 8));
+
+        let draws = layout_window(&narrow, &FixedFont, &theme);
+        assert!(draws.iter().all(|d| !matches!(d, UiDraw::Rect { rect, .. } if rect.width > 6)));
+    }
+}
@@ -0,0 +1,156 @@
+/*
+
+Loading screen
+
+Shown while a level loads, the level's title art with a progress bar
+under it and a line saying what's being worked on. The load itself runs
+on the asset loader's workers; the game loop keeps calling `update` and
+drawing this each frame until the progress says it's done, rather than
+blocking on the handles and leaving the window frozen.
+
+*/
+use std::rc::Rc;
+
+use super::{
+    draw::{render_ui, UiDraw, UiFont, UiTheme, UI_PADDING},
+    UiRect,
+};
+use crate::{
+    filesystem::progress::LoadProgress,
+    graphics::{drawing_2d::font::FontGraphic, rendering::Renderer, TextureHandle},
+};
+
+/// Height of the progress bar, in pixels
+pub const LOADING_BAR_HEIGHT: usize = 12;
+
+/// The bar's width as a share of the screen's
+pub const LOADING_BAR_WIDTH: f32 = 0.6;
+
+#[derive(Debug)]
+pub struct LoadingScreen {
+    pub title: String,
+    /// The level's title art, filling the screen behind everything else
+    pub art: Option<TextureHandle>,
+    progress: LoadProgress,
+}
+
+impl LoadingScreen {
+    pub fn new(title: &str, progress: LoadProgress) -> Self {
+        Self {
+            title: title.to_string(),
+            art: None,
+            progress,
+        }
+    }
+
+    pub fn progress(&self) -> &LoadProgress {
+        &self.progress
+    }
+
+    /// Takes in the progress reported since last frame
+    pub fn update(&mut self) {
+        self.progress.poll();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress.is_done()
+    }
+
+    /// Title near the top, the bar and what's loading a little under the middle
+    pub fn layout(&self, screen: UiRect, font: &impl UiFont, theme: &UiTheme) -> Vec<UiDraw> {
+        let mut draws = Vec::new();
+
+        match self.art {
+            Some(texture) => draws.push(UiDraw::Image { rect: screen, texture }),
+            None => draws.push(UiDraw::Rect { rect: screen, color: theme.window }),
+        }
+
+        let centered = |y: usize, text: &str, color| UiDraw::Text {
+            x: screen.x + screen.width.saturating_sub(font.text_width(text)) / 2,
+            y,
+            text: text.to_string(),
+            color,
+        };
+
+        draws.push(centered(screen.y + screen.height / 8, &self.title, theme.title));
+
+        let bar_width = (screen.width as f32 * LOADING_BAR_WIDTH) as usize;
+        let bar = UiRect::new(
+            screen.x + (screen.width - bar_width) / 2,
+            screen.y + screen.height * 2 / 3,
+            bar_width,
+            LOADING_BAR_HEIGHT,
+        );
+
+        draws.push(UiDraw::Rect { rect: bar, color: theme.field });
+        draws.push(UiDraw::Rect {
+            rect: UiRect {
+                width: (bar.width as f32 * self.progress.fraction()) as usize,
+                ..bar
+            },
+            color: theme.bar,
+        });
+
+        if let Some(stage) = self.progress.current_stage() {
+            draws.push(centered(bar.bottom() + UI_PADDING, stage.name(), theme.text));
+        }
+
+        draws
+    }
+
+    /// Lays out over the whole screen and draws
+    pub fn render<R: Renderer>(&self, renderer: &mut R, font: &Rc<FontGraphic>, theme: &UiTheme) {
+        let screen = renderer.get_projection_screen_rect();
+        let draws = self.layout(UiRect::new(0, 0, screen.width, screen.height), font.as_ref(), theme);
+
+        render_ui(&draws, renderer, font);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        filesystem::progress::{progress_channel, LoadStage},
+        graphics::BitmapId,
+    };
+
+    struct FixedFont;
+
+    impl UiFont for FixedFont {
+        fn text_width(&self, text: &str) -> usize {
+            text.len() * 8
+        }
+
+        fn line_height(&self) -> usize {
+            10
+        }
+    }
+
+    #[test]
+    fn loading_screen_test() {
+        crate::test_common::setup();
+
+        let (reporter, progress) = progress_channel();
+        let mut screen = LoadingScreen::new("Level 1", progress);
+        screen.art = Some(TextureHandle::Bitmap(BitmapId(2)));
+
+        reporter.queued(LoadStage::Models, 4);
+        reporter.finished(LoadStage::Models, 1);
+        screen.update();
+
+        let theme = UiTheme::default();
+        let draws = screen.layout(UiRect::new(0, 0, 640, 480), &FixedFont, &theme);
+
+        assert_eq!(draws[0], UiDraw::Image { rect: UiRect::new(0, 0, 640, 480), texture: TextureHandle::Bitmap(BitmapId(2)) });
+
+        // A quarter of a 384 wide bar
+        assert!(draws.contains(&UiDraw::Rect { rect: UiRect::new(128, 320, 96, LOADING_BAR_HEIGHT), color: theme.bar }));
+        assert!(draws.iter().any(|d| matches!(d, UiDraw::Text { text, y: 336, .. } if text == "Loading models")));
+        assert!(!screen.is_done());
+
+        reporter.finished(LoadStage::Models, 3);
+        screen.update();
+        assert!(screen.is_done());
+    }
+}
@@ -0,0 +1,436 @@
+/*
+
+Game UI
+
+A small retained mode UI for the main menu, options and multiplayer
+screens, in the spirit of the retail newui. A window holds a column of
+widgets with one of them focused; keyboard and controller input both come
+in as UiInput so every screen can be driven by either. Windows stack, the
+top one gets the input, and screens answer the events it hands back.
+
+Drawing goes through the 2D layer with the D3 fonts, see draw.rs. The egui
+overlay is a developer tool and has nothing to do with this.
+
+*/
+
+pub mod draw;
+pub mod loading;
+pub mod options;
+
+/// Names a widget within its window, screens pick their own
+pub type UiId = u32;
+
+/// Where something sits on the screen, in pixels
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct UiRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl UiRect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+}
+
+/// Navigation as the UI sees it. Arrow keys and the dpad move, enter and the A button accept,
+/// escape and the B button go back, tab and the shoulder buttons skip between fields
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UiInput {
+    Up,
+    Down,
+    Left,
+    Right,
+    Accept,
+    Back,
+    NextField,
+    PrevField,
+    Char(char),
+    Backspace,
+}
+
+/// What a window tells its screen after some input
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UiEvent {
+    /// A button, list entry or text entry was accepted
+    Pressed(UiId),
+    /// A checkbox, choice, slider, list selection or text entry changed
+    Changed(UiId),
+    /// Back was pressed, usually the screen closes
+    Back,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiWidget {
+    Label(String),
+    Button(String),
+    Checkbox {
+        text: String,
+        checked: bool,
+    },
+    /// One of a few options, stepped through with left and right
+    Choice {
+        text: String,
+        options: Vec<String>,
+        selected: usize,
+    },
+    Slider {
+        text: String,
+        value: f32,
+        min: f32,
+        max: f32,
+        step: f32,
+    },
+    List {
+        items: Vec<String>,
+        selected: usize,
+        /// First item showing
+        scroll: usize,
+        /// How many items show at once
+        rows: usize,
+    },
+    TextEntry {
+        text: String,
+        value: String,
+        max_len: usize,
+    },
+}
+
+impl UiWidget {
+    pub fn slider(text: &str, value: f32, min: f32, max: f32, step: f32) -> Self {
+        UiWidget::Slider {
+            text: text.to_string(),
+            value: value.clamp(min, max),
+            min,
+            max,
+            step,
+        }
+    }
+
+    pub fn list(items: Vec<String>, rows: usize) -> Self {
+        UiWidget::List {
+            items,
+            selected: 0,
+            scroll: 0,
+            rows: rows.max(1),
+        }
+    }
+
+    pub fn text_entry(text: &str, value: &str, max_len: usize) -> Self {
+        UiWidget::TextEntry {
+            text: text.to_string(),
+            value: value.chars().take(max_len).collect(),
+            max_len,
+        }
+    }
+
+    pub fn is_focusable(&self) -> bool {
+        !matches!(self, UiWidget::Label(_))
+    }
+
+    /// Lines of text it takes up
+    pub fn rows(&self) -> usize {
+        match self {
+            UiWidget::List { rows, .. } => *rows,
+            _ => 1,
+        }
+    }
+
+    /// Moves a list's selection, keeping it scrolled into view. False at either end
+    fn move_selection(&mut self, down: bool) -> bool {
+        let UiWidget::List { items, selected, scroll, rows } = self else {
+            return false;
+        };
+
+        let next = match down {
+            true if *selected + 1 < items.len() => *selected + 1,
+            false if *selected > 0 => *selected - 1,
+            _ => return false,
+        };
+
+        *selected = next;
+        *scroll = (*scroll).min(next).max((next + 1).saturating_sub(*rows));
+
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiItem {
+    pub id: UiId,
+    pub widget: UiWidget,
+    /// Disabled items are greyed out and skipped over by focus
+    pub enabled: bool,
+}
+
+impl UiItem {
+    fn takes_focus(&self) -> bool {
+        self.enabled && self.widget.is_focusable()
+    }
+}
+
+/// A screen's worth of widgets in a column
+#[derive(Debug, Clone, PartialEq)]
+pub struct UiWindow {
+    pub title: String,
+    pub rect: UiRect,
+    items: Vec<UiItem>,
+    focus: Option<usize>,
+}
+
+impl UiWindow {
+    pub fn new(title: &str, rect: UiRect) -> Self {
+        Self {
+            title: title.to_string(),
+            rect,
+            items: Vec::new(),
+            focus: None,
+        }
+    }
+
+    /// Adds a widget under the last one, the first that can take focus gets it
+    pub fn add(&mut self, id: UiId, widget: UiWidget) -> &mut Self {
+        self.items.push(UiItem { id, widget, enabled: true });
+
+        if self.focus.is_none() && self.items.last().unwrap().takes_focus() {
+            self.focus = Some(self.items.len() - 1);
+        }
+
+        self
+    }
+
+    pub fn items(&self) -> &[UiItem] {
+        &self.items
+    }
+
+    pub fn get(&self, id: UiId) -> Option<&UiWidget> {
+        self.items.iter().find(|i| i.id == id).map(|i| &i.widget)
+    }
+
+    pub fn get_mut(&mut self, id: UiId) -> Option<&mut UiWidget> {
+        self.items.iter_mut().find(|i| i.id == id).map(|i| &mut i.widget)
+    }
+
+    pub fn set_enabled(&mut self, id: UiId, enabled: bool) {
+        if let Some(index) = self.items.iter().position(|i| i.id == id) {
+            self.items[index].enabled = enabled;
+
+            if !enabled && self.focus == Some(index) {
+                self.move_focus(true);
+            }
+        }
+    }
+
+    pub fn focused(&self) -> Option<UiId> {
+        self.focus.map(|i| self.items[i].id)
+    }
+
+    pub fn set_focus(&mut self, id: UiId) {
+        if let Some(index) = self.items.iter().position(|i| i.id == id && i.takes_focus()) {
+            self.focus = Some(index);
+        }
+    }
+
+    /// Steps focus to the next or previous widget that takes it, wrapping around
+    fn move_focus(&mut self, forward: bool) {
+        let count = self.items.len();
+        let start = self.focus.unwrap_or(if forward { count - 1 } else { 0 });
+
+        self.focus = (1..=count)
+            .map(|step| if forward { (start + step) % count } else { (start + count - step) % count })
+            .find(|&i| self.items[i].takes_focus());
+    }
+
+    pub fn handle_input(&mut self, input: UiInput) -> Option<UiEvent> {
+        if input == UiInput::Back {
+            return Some(UiEvent::Back);
+        }
+
+        if self.items.is_empty() {
+            return None;
+        }
+
+        let Some(index) = self.focus else {
+            self.move_focus(true);
+            return None;
+        };
+
+        let id = self.items[index].id;
+
+        // Lists use up and down themselves until they reach an end
+        if matches!(input, UiInput::Up | UiInput::Down) && self.items[index].widget.move_selection(input == UiInput::Down) {
+            return Some(UiEvent::Changed(id));
+        }
+
+        match input {
+            UiInput::Up | UiInput::PrevField => {
+                self.move_focus(false);
+                return None;
+            }
+            UiInput::Down | UiInput::NextField => {
+                self.move_focus(true);
+                return None;
+            }
+            _ => {}
+        }
+
+        match (input, &mut self.items[index].widget) {
+            (UiInput::Left | UiInput::Right, UiWidget::Slider { value, min, max, step, .. }) => {
+                let delta = if input == UiInput::Right { *step } else { -*step };
+                let next = (*value + delta).clamp(*min, *max);
+
+                (next != *value).then(|| {
+                    *value = next;
+                    UiEvent::Changed(id)
+                })
+            }
+            (UiInput::Left | UiInput::Right, UiWidget::Choice { options, selected, .. }) if !options.is_empty() => {
+                let count = options.len();
+                *selected = if input == UiInput::Right { (*selected + 1) % count } else { (*selected + count - 1) % count };
+                Some(UiEvent::Changed(id))
+            }
+            (UiInput::Accept, UiWidget::Checkbox { checked, .. }) => {
+                *checked = !*checked;
+                Some(UiEvent::Changed(id))
+            }
+            (UiInput::Accept, UiWidget::Button(_) | UiWidget::List { .. } | UiWidget::TextEntry { .. }) => Some(UiEvent::Pressed(id)),
+            // The fonts only have ascii
+            (UiInput::Char(c), UiWidget::TextEntry { value, max_len, .. }) if c.is_ascii() && !c.is_ascii_control() && value.len() < *max_len => {
+                value.push(c);
+                Some(UiEvent::Changed(id))
+            }
+            (UiInput::Backspace, UiWidget::TextEntry { value, .. }) => value.pop().map(|_| UiEvent::Changed(id)),
+            _ => None,
+        }
+    }
+}
+
+/// Windows on top of each other, input goes to the top one
+#[derive(Debug, Clone, Default)]
+pub struct UiStack {
+    windows: Vec<UiWindow>,
+}
+
+impl UiStack {
+    pub fn push(&mut self, window: UiWindow) {
+        self.windows.push(window);
+    }
+
+    pub fn pop(&mut self) -> Option<UiWindow> {
+        self.windows.pop()
+    }
+
+    pub fn top(&self) -> Option<&UiWindow> {
+        self.windows.last()
+    }
+
+    pub fn top_mut(&mut self) -> Option<&mut UiWindow> {
+        self.windows.last_mut()
+    }
+
+    pub fn windows(&self) -> &[UiWindow] {
+        &self.windows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn handle_input(&mut self, input: UiInput) -> Option<UiEvent> {
+        self.windows.last_mut()?.handle_input(input)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const TITLE: UiId = 0;
+    const NAME: UiId = 1;
+    const VOLUME: UiId = 2;
+    const LEVELS: UiId = 3;
+    const START: UiId = 4;
+
+    fn menu() -> UiWindow {
+        let mut window = UiWindow::new("New Game", UiRect::new(0, 0, 320, 240));
+
+        window
+            .add(TITLE, UiWidget::Label("Pick a level".to_string()))
+            .add(NAME, UiWidget::text_entry("Pilot", "Dravis", 8))
+            .add(VOLUME, UiWidget::slider("Volume", 0.5, 0.0, 1.0, 0.25))
+            .add(LEVELS, UiWidget::list((1..=5).map(|i| format!("Level {}", i)).collect(), 2))
+            .add(START, UiWidget::Button("Start".to_string()));
+
+        window
+    }
+
+    #[test]
+    fn ui_focus_test() {
+        crate::test_common::setup();
+
+        let mut window = menu();
+
+        // Labels never take focus
+        assert_eq!(window.focused(), Some(NAME));
+        window.handle_input(UiInput::Up);
+        assert_eq!(window.focused(), Some(START));
+        window.handle_input(UiInput::NextField);
+        assert_eq!(window.focused(), Some(NAME));
+
+        // Disabled items get skipped, and give up focus
+        window.set_focus(VOLUME);
+        window.set_enabled(VOLUME, false);
+        assert_eq!(window.focused(), Some(LEVELS));
+        window.handle_input(UiInput::PrevField);
+        assert_eq!(window.focused(), Some(NAME));
+
+        assert_eq!(window.handle_input(UiInput::Back), Some(UiEvent::Back));
+
+        let mut stack = UiStack::default();
+        assert_eq!(stack.handle_input(UiInput::Accept), None);
+        stack.push(window);
+        assert_eq!(stack.handle_input(UiInput::Accept), Some(UiEvent::Pressed(NAME)));
+    }
+
+    #[test]
+    fn ui_widget_test() {
+        crate::test_common::setup();
+
+        let mut window = menu();
+
+        // Typing stops at the length limit
+        assert_eq!(window.handle_input(UiInput::Char('!')), Some(UiEvent::Changed(NAME)));
+        assert_eq!(window.handle_input(UiInput::Char('!')), Some(UiEvent::Changed(NAME)));
+        assert_eq!(window.handle_input(UiInput::Char('!')), None);
+        window.handle_input(UiInput::Backspace);
+        assert!(matches!(window.get(NAME), Some(UiWidget::TextEntry { value, .. }) if value == "Dravis!"));
+
+        // Sliders step and clamp
+        window.set_focus(VOLUME);
+        assert_eq!(window.handle_input(UiInput::Right), Some(UiEvent::Changed(VOLUME)));
+        window.handle_input(UiInput::Right);
+        assert_eq!(window.handle_input(UiInput::Right), None);
+        assert!(matches!(window.get(VOLUME), Some(UiWidget::Slider { value, .. }) if *value == 1.0));
+
+        // Lists take up and down until they run out, scrolling along
+        window.set_focus(LEVELS);
+        for _ in 0..4 {
+            assert_eq!(window.handle_input(UiInput::Down), Some(UiEvent::Changed(LEVELS)));
+        }
+        assert!(matches!(window.get(LEVELS), Some(UiWidget::List { selected: 4, scroll: 3, .. })));
+
+        assert_eq!(window.handle_input(UiInput::Down), None);
+        assert_eq!(window.focused(), Some(START));
+        assert_eq!(window.handle_input(UiInput::Accept), Some(UiEvent::Pressed(START)));
+    }
+}
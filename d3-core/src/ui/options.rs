@@ -0,0 +1,379 @@
+/*
+
+Options screens
+
+Binds settings to UI controls so each frontend doesn't wire up its own.
+Every binding knows how to show its setting as a widget and how to read it
+back. Changes go into a pending copy of the settings first and are checked
+there; the cheap ones (volumes, detail, gamma) go through to the live config
+straight away so you can hear and see them, the rest wait for accept.
+Cancel puts every bound setting back to how it was when the screen opened.
+
+*/
+
+use anyhow::Result;
+
+use super::{UiEvent, UiId, UiInput, UiRect, UiWidget, UiWindow};
+use crate::config::{Config, ConfigSection, ConfigValue, Settings};
+
+/// Resolutions the video options offer
+pub const RESOLUTIONS: [(u32, u32); 6] = [(512, 384), (640, 480), (800, 600), (960, 720), (1024, 768), (1280, 960)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionKind {
+    Toggle,
+    Range { min: f32, max: f32, step: f32 },
+    /// Labelled values the setting can take
+    Choice(Vec<(String, ConfigValue)>),
+    /// Sets the render width and height together
+    Resolution(Vec<(u32, u32)>),
+    /// A key name, pressing the control waits for the next key
+    KeyBind,
+}
+
+/// A setting shown as a control
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionBinding {
+    pub label: String,
+    pub section: ConfigSection,
+    pub key: &'static str,
+    pub kind: OptionKind,
+    /// Applied as soon as it changes rather than on accept
+    pub live: bool,
+}
+
+impl OptionBinding {
+    pub fn new(label: &str, section: ConfigSection, key: &'static str, kind: OptionKind) -> Self {
+        Self {
+            label: label.to_string(),
+            section,
+            key,
+            kind,
+            live: true,
+        }
+    }
+
+    /// Only applied on accept, for things like the video mode that are costly to change
+    pub fn deferred(mut self) -> Self {
+        self.live = false;
+        self
+    }
+
+    /// The settings this binding writes
+    fn keys(&self) -> Vec<&'static str> {
+        match self.kind {
+            OptionKind::Resolution(_) => vec!["width", "height"],
+            _ => vec![self.key],
+        }
+    }
+
+    fn widget(&self, settings: &Settings) -> UiWidget {
+        let value = settings.get(self.section, self.key);
+        let text = self.label.clone();
+
+        match &self.kind {
+            OptionKind::Toggle => UiWidget::Checkbox {
+                text,
+                checked: value.and_then(|v| v.as_bool()).unwrap_or(false),
+            },
+            OptionKind::Range { min, max, step } => {
+                let value = value.and_then(|v| v.as_float()).unwrap_or(*min as f64) as f32;
+                UiWidget::slider(&self.label, value, *min, *max, *step)
+            }
+            OptionKind::Choice(options) => UiWidget::Choice {
+                text,
+                selected: options.iter().position(|(_, v)| Some(v) == value.as_ref()).unwrap_or(0),
+                options: options.iter().map(|(label, _)| label.clone()).collect(),
+            },
+            OptionKind::Resolution(modes) => {
+                let current = (settings.render.width, settings.render.height);
+
+                UiWidget::Choice {
+                    text,
+                    selected: modes.iter().position(|m| *m == current).unwrap_or(0),
+                    options: modes.iter().map(|(w, h)| format!("{}x{}", w, h)).collect(),
+                }
+            }
+            OptionKind::KeyBind => UiWidget::Button(format!("{}: {}", self.label, value.map(|v| v.to_string()).unwrap_or_default())),
+        }
+    }
+
+    /// What the control says the settings should be, `None` if it doesn't fit this binding
+    fn values(&self, widget: &UiWidget) -> Option<Vec<(&'static str, ConfigValue)>> {
+        match (&self.kind, widget) {
+            (OptionKind::Toggle, UiWidget::Checkbox { checked, .. }) => Some(vec![(self.key, ConfigValue::Bool(*checked))]),
+            (OptionKind::Range { .. }, UiWidget::Slider { value, .. }) => Some(vec![(self.key, ConfigValue::Float(*value as f64))]),
+            (OptionKind::Choice(options), UiWidget::Choice { selected, .. }) => {
+                options.get(*selected).map(|(_, v)| vec![(self.key, v.clone())])
+            }
+            (OptionKind::Resolution(modes), UiWidget::Choice { selected, .. }) => modes
+                .get(*selected)
+                .map(|(w, h)| vec![("width", ConfigValue::Int(*w as i64)), ("height", ConfigValue::Int(*h as i64))]),
+            _ => None,
+        }
+    }
+}
+
+/// The options most screens want, grouped video, detail, audio then controls
+pub fn default_bindings() -> Vec<OptionBinding> {
+    use ConfigSection::*;
+
+    let detail_presets = ["Low", "Medium", "High", "Very High", "Custom"]
+        .iter()
+        .enumerate()
+        .map(|(i, label)| (label.to_string(), ConfigValue::Int(i as i64)))
+        .collect();
+
+    vec![
+        OptionBinding::new("Resolution", Render, "width", OptionKind::Resolution(RESOLUTIONS.to_vec())).deferred(),
+        OptionBinding::new("Windowed", Render, "windowed", OptionKind::Toggle).deferred(),
+        OptionBinding::new("Gamma", Render, "gamma", OptionKind::Range { min: 0.5, max: 2.0, step: 0.1 }),
+        OptionBinding::new("Detail", Detail, "preset", OptionKind::Choice(detail_presets)),
+        OptionBinding::new("Terrain Distance", Detail, "terrain_render_distance", OptionKind::Range { min: 40.0, max: 200.0, step: 10.0 }),
+        OptionBinding::new("Fog", Detail, "fog_enabled", OptionKind::Toggle),
+        OptionBinding::new("Master Volume", Audio, "master_volume", OptionKind::Range { min: 0.0, max: 1.0, step: 0.05 }),
+        OptionBinding::new("Sound Volume", Audio, "sfx_volume", OptionKind::Range { min: 0.0, max: 1.0, step: 0.05 }),
+        OptionBinding::new("Music Volume", Audio, "music_volume", OptionKind::Range { min: 0.0, max: 1.0, step: 0.05 }),
+        OptionBinding::new("Captions", Audio, "captions", OptionKind::Toggle),
+        OptionBinding::new("Forward", Input, "key_forward", OptionKind::KeyBind),
+        OptionBinding::new("Reverse", Input, "key_reverse", OptionKind::KeyBind),
+        OptionBinding::new("Fire Primary", Input, "key_fire_primary", OptionKind::KeyBind),
+        OptionBinding::new("Fire Secondary", Input, "key_fire_secondary", OptionKind::KeyBind),
+    ]
+}
+
+/// Copies the settings `bindings` cover from one set of settings to another
+fn copy_bound(bindings: &[OptionBinding], from: &Settings, to: &mut Settings) {
+    for binding in bindings {
+        for key in binding.keys() {
+            if let Some(value) = from.get(binding.section, key) {
+                let _ = to.set(binding.section, key, &value);
+            }
+        }
+    }
+}
+
+/// An options screen's settings while it's open, widget ids are the binding indices
+#[derive(Debug, Clone)]
+pub struct OptionsModel {
+    bindings: Vec<OptionBinding>,
+    original: Settings,
+    pending: Settings,
+    capturing: Option<UiId>,
+}
+
+impl OptionsModel {
+    pub fn new(bindings: Vec<OptionBinding>, settings: &Settings) -> Self {
+        Self {
+            bindings,
+            original: settings.clone(),
+            pending: settings.clone(),
+            capturing: None,
+        }
+    }
+
+    pub fn bindings(&self) -> &[OptionBinding] {
+        &self.bindings
+    }
+
+    pub fn pending(&self) -> &Settings {
+        &self.pending
+    }
+
+    /// Something changed since the screen opened or was last accepted
+    pub fn is_dirty(&self) -> bool {
+        self.pending != self.original
+    }
+
+    /// The key bind waiting for a key, if any
+    pub fn capturing(&self) -> Option<UiId> {
+        self.capturing
+    }
+
+    pub fn window(&self, title: &str, rect: UiRect) -> UiWindow {
+        let mut window = UiWindow::new(title, rect);
+
+        for (id, binding) in self.bindings.iter().enumerate() {
+            window.add(id as UiId, binding.widget(&self.pending));
+        }
+
+        window
+    }
+
+    /// Puts every control back in line with the pending settings
+    fn refresh(&self, window: &mut UiWindow) {
+        for (id, binding) in self.bindings.iter().enumerate() {
+            if let Some(widget) = window.get_mut(id as UiId) {
+                *widget = binding.widget(&self.pending);
+            }
+        }
+    }
+
+    /// Sets values on the pending settings, all or none, and on the config too if the binding is live
+    fn apply(&mut self, id: UiId, values: &[(&'static str, ConfigValue)], config: &mut Config) -> Result<()> {
+        let binding = &self.bindings[id as usize];
+        let mut pending = self.pending.clone();
+
+        for (key, value) in values {
+            pending.set(binding.section, key, value)?;
+        }
+
+        if binding.live {
+            let section = binding.section;
+            config.update(|s| {
+                for (key, value) in values {
+                    let _ = s.set(section, key, value);
+                }
+            });
+        }
+
+        self.pending = pending;
+        Ok(())
+    }
+
+    /// Passes input to the window and keeps the settings in step with it. A control that
+    /// was set to something that doesn't validate goes back to what it was, with the error
+    pub fn handle_input(&mut self, window: &mut UiWindow, input: UiInput, config: &mut Config) -> Result<Option<UiEvent>> {
+        if self.capturing.is_some() {
+            match input {
+                UiInput::Back => self.capturing = None,
+                UiInput::Char(' ') => self.capture_key(window, "space", config)?,
+                UiInput::Char(c) if c.is_ascii_graphic() => self.capture_key(window, &c.to_ascii_lowercase().to_string(), config)?,
+                _ => {}
+            }
+
+            return Ok(None);
+        }
+
+        let event = window.handle_input(input);
+
+        match event {
+            Some(UiEvent::Changed(id)) => {
+                let values = window.get(id).and_then(|w| self.bindings.get(id as usize)?.values(w));
+
+                if let Some(values) = values {
+                    if let Err(e) = self.apply(id, &values, config) {
+                        self.refresh(window);
+                        return Err(e);
+                    }
+                }
+            }
+            Some(UiEvent::Pressed(id)) if self.bindings.get(id as usize).is_some_and(|b| b.kind == OptionKind::KeyBind) => {
+                self.capturing = Some(id);
+            }
+            _ => {}
+        }
+
+        Ok(event)
+    }
+
+    /// Binds the waiting key bind to a key by name, for keys that don't come through as characters.
+    /// A key already bound elsewhere swaps over to this bind's old key
+    pub fn capture_key(&mut self, window: &mut UiWindow, name: &str, config: &mut Config) -> Result<()> {
+        let Some(id) = self.capturing.take() else {
+            return Ok(());
+        };
+
+        let binding = &self.bindings[id as usize];
+        let old = self.pending.get(binding.section, binding.key).unwrap_or(ConfigValue::String(String::new()));
+        let new = ConfigValue::String(name.to_string());
+
+        let taken = self.bindings.iter().enumerate().position(|(other, b)| {
+            other != id as usize && b.kind == OptionKind::KeyBind && self.pending.get(b.section, b.key).as_ref() == Some(&new)
+        });
+
+        if let Some(other) = taken {
+            let key = self.bindings[other].key;
+            self.apply(other as UiId, &[(key, old)], config)?;
+        }
+
+        let key = self.bindings[id as usize].key;
+        self.apply(id, &[(key, new)], config)?;
+        self.refresh(window);
+
+        Ok(())
+    }
+
+    /// Applies everything pending, returns the sections that changed
+    pub fn accept(&mut self, config: &mut Config) -> Vec<ConfigSection> {
+        let (bindings, pending) = (&self.bindings, &self.pending);
+        let changed = config.update(|s| copy_bound(bindings, pending, s));
+
+        self.original = self.pending.clone();
+        changed
+    }
+
+    /// Puts the bound settings back to how they were when the screen opened
+    pub fn cancel(&mut self, window: &mut UiWindow, config: &mut Config) -> Vec<ConfigSection> {
+        let (bindings, original) = (&self.bindings, &self.original);
+        let changed = config.update(|s| copy_bound(bindings, original, s));
+
+        self.pending = self.original.clone();
+        self.capturing = None;
+        self.refresh(window);
+
+        changed
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const RESOLUTION: UiId = 0;
+    const GAMMA: UiId = 2;
+    const MUSIC: UiId = 8;
+    const FORWARD: UiId = 10;
+    const REVERSE: UiId = 11;
+
+    #[test]
+    fn options_live_apply_test() {
+        crate::test_common::setup();
+
+        let mut config = Config::default();
+        let mut options = OptionsModel::new(default_bindings(), config.settings());
+        let mut window = options.window("Options", UiRect::new(0, 0, 400, 400));
+
+        // Volume goes through straight away
+        window.set_focus(MUSIC);
+        options.handle_input(&mut window, UiInput::Right, &mut config).unwrap();
+        assert!((config.settings().audio.music_volume - 0.55).abs() < 1e-6);
+
+        // The video mode waits for accept
+        window.set_focus(RESOLUTION);
+        options.handle_input(&mut window, UiInput::Right, &mut config).unwrap();
+        assert_eq!(options.pending().render.width, 800);
+        assert_eq!(config.settings().render.width, 640);
+        assert!(options.is_dirty());
+
+        assert_eq!(options.accept(&mut config), vec![ConfigSection::Render]);
+        assert_eq!((config.settings().render.width, config.settings().render.height), (800, 600));
+        assert!(!options.is_dirty());
+    }
+
+    #[test]
+    fn options_revert_test() {
+        crate::test_common::setup();
+
+        let mut config = Config::default();
+        let mut options = OptionsModel::new(default_bindings(), config.settings());
+        let mut window = options.window("Options", UiRect::new(0, 0, 400, 400));
+
+        window.set_focus(GAMMA);
+        options.handle_input(&mut window, UiInput::Right, &mut config).unwrap();
+        assert!(config.settings().render.gamma > 1.0);
+
+        // Binding forward to z swaps reverse over to a
+        window.set_focus(FORWARD);
+        options.handle_input(&mut window, UiInput::Accept, &mut config).unwrap();
+        assert_eq!(options.capturing(), Some(FORWARD));
+        options.handle_input(&mut window, UiInput::Char('Z'), &mut config).unwrap();
+        assert_eq!(config.settings().input.key_forward, "z");
+        assert_eq!(config.settings().input.key_reverse, "a");
+        assert_eq!(window.get(REVERSE), Some(&UiWidget::Button("Reverse: a".to_string())));
+
+        options.cancel(&mut window, &mut config);
+        assert_eq!(config.settings(), &Settings::default());
+        assert!(matches!(window.get(GAMMA), Some(UiWidget::Slider { value, .. }) if *value == 1.0));
+    }
+}
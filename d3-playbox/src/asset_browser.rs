@@ -0,0 +1,512 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    rc::Rc,
+};
+
+use anyhow::{Result, anyhow};
+use d3_core::{
+    filesystem::hog::{Hog, HogEntry},
+    graphics::{
+        bitmap::{
+            Bitmap16, BitmapFormat, DebugPattern, image_format_iff, image_format_ogf::OgfBitmap,
+        },
+        color_conversion::{convert_1555_to_32, convert_4444_to_32},
+        drawing_2d::font::{Font, FontGraphic},
+        generic_bitmap::GenericBitmap16,
+    },
+};
+use egui::{ColorImage, TextureHandle, TextureOptions, Ui};
+
+/// Frame time used when a clip doesn't say otherwise
+const DEFAULT_FRAMETIME: f64 = 0.07;
+
+/// What kind of asset an entry is, going by its extension
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AssetKind {
+    Bitmap,
+    Animation,
+    VideoClip,
+    Font,
+    Model,
+    Other,
+}
+
+impl AssetKind {
+    pub fn from_name(name: &str) -> Self {
+        let ext = name.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+
+        match ext.as_str() {
+            "ogf" | "tga" => AssetKind::Bitmap,
+            "iff" | "ilbm" | "anim" => AssetKind::Animation,
+            "ifl" | "oaf" => AssetKind::VideoClip,
+            "fnt" => AssetKind::Font,
+            "oof" => AssetKind::Model,
+            _ => AssetKind::Other,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AssetKind::Bitmap => "Bitmap",
+            AssetKind::Animation => "Animation",
+            AssetKind::VideoClip => "Video Clip",
+            AssetKind::Font => "Font",
+            AssetKind::Model => "Model",
+            AssetKind::Other => "Data",
+        }
+    }
+}
+
+enum Preview {
+    None,
+    Image(TextureHandle),
+    Frames {
+        frames: Vec<TextureHandle>,
+        frame_time: f64,
+    },
+    Font {
+        sheets: Vec<TextureHandle>,
+        height: usize,
+    },
+    Model,
+    Raw {
+        size: usize,
+        flags: u32,
+    },
+}
+
+struct MountedHog {
+    path: String,
+    hog: Hog,
+    /// Entry names sorted so the list doesn't shuffle between frames
+    names: Vec<String>,
+}
+
+pub struct AssetBrowser {
+    pub open: bool,
+    hog_path: String,
+    filter: String,
+    hogs: Vec<MountedHog>,
+    selected: Option<(usize, String)>,
+    preview: Preview,
+    status: String,
+    zoom: f32,
+    playing: bool,
+    frame: usize,
+    frame_elapsed: f64,
+}
+
+impl Default for AssetBrowser {
+    fn default() -> Self {
+        Self {
+            open: false,
+            hog_path: String::new(),
+            filter: String::new(),
+            hogs: Vec::new(),
+            selected: None,
+            preview: Preview::None,
+            status: String::new(),
+            zoom: 2.0,
+            playing: true,
+            frame: 0,
+            frame_elapsed: 0.0,
+        }
+    }
+}
+
+/// ARGB pixels as an egui image
+fn argb_to_image(width: usize, height: usize, pixels: &[u32]) -> ColorImage {
+    let rgba: Vec<u8> = pixels
+        .iter()
+        .flat_map(|&c| [(c >> 16) as u8, (c >> 8) as u8, c as u8, (c >> 24) as u8])
+        .collect();
+
+    ColorImage::from_rgba_unmultiplied([width, height], &rgba)
+}
+
+fn bitmap16_to_image(bitmap: &dyn Bitmap16) -> ColorImage {
+    // Mipped bitmaps carry their smaller levels after the first
+    let pixels = &bitmap.data()[..bitmap.width() * bitmap.height()];
+
+    let argb = match bitmap.format() {
+        BitmapFormat::Fmt1555 => convert_1555_to_32(pixels),
+        BitmapFormat::Fmt4444 => convert_4444_to_32(pixels),
+    };
+
+    argb_to_image(bitmap.width(), bitmap.height(), &argb)
+}
+
+impl AssetBrowser {
+    /// Whether the 3D view should be spinning the selected model
+    pub fn wants_spin(&self) -> bool {
+        self.open && matches!(self.preview, Preview::Model)
+    }
+
+    fn mount(&mut self, path: &str) -> Result<()> {
+        if self.hogs.iter().any(|h| h.path == path) {
+            return Err(anyhow!("{} is already mounted", path));
+        }
+
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+        let hog = Hog::new_from_stream(&mut reader, path.to_string())?;
+
+        let mut names: Vec<String> = hog.borrow_entries().keys().cloned().collect();
+        names.sort_by_key(|n| n.to_ascii_lowercase());
+
+        self.hogs.push(MountedHog {
+            path: path.to_string(),
+            hog,
+            names,
+        });
+
+        Ok(())
+    }
+
+    fn unmount(&mut self, index: usize) {
+        self.hogs.remove(index);
+
+        match self.selected {
+            Some((hog, _)) if hog == index => {
+                self.selected = None;
+                self.preview = Preview::None;
+            }
+            Some((ref mut hog, _)) if *hog > index => *hog -= 1,
+            _ => {}
+        }
+    }
+
+    /// Looks a file up through every mounted hog, the first mounted wins
+    fn find_entry(&self, name: &str) -> Option<&HogEntry> {
+        self.hogs.iter().find_map(|h| {
+            h.hog
+                .borrow_entries()
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, e)| e)
+        })
+    }
+
+    fn load_ogf(data: &[u8]) -> Result<OgfBitmap> {
+        let mut reader = BufReader::new(Cursor::new(data));
+        OgfBitmap::new(&mut reader, BitmapFormat::Fmt1555)
+    }
+
+    /// IFL clips are a text list of frame bitmaps, `$TIME=` sets the whole clip's length
+    fn load_ifl(&self, ctx: &egui::Context, name: &str, data: &[u8]) -> Result<Preview> {
+        let text = String::from_utf8_lossy(data);
+        let mut frames = Vec::new();
+        let mut play_time = None;
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(command) = line.strip_prefix('$') {
+                if let Some((key, value)) = command.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("TIME") {
+                        play_time = value.trim().parse::<f64>().ok();
+                    }
+                }
+                continue;
+            }
+
+            // Frames may be listed with a path, only the file name is in the hog
+            let frame_name = line.rsplit(['\\', '/']).next().unwrap_or(line);
+
+            let entry = self
+                .find_entry(frame_name)
+                .ok_or_else(|| anyhow!("{} is missing frame {}", name, frame_name))?;
+            let bitmap = Self::load_ogf(&entry.data)?;
+
+            frames.push(ctx.load_texture(
+                format!("{}:{}", name, frames.len()),
+                bitmap16_to_image(&bitmap),
+                TextureOptions::NEAREST,
+            ));
+        }
+
+        if frames.is_empty() {
+            return Err(anyhow!("{} has no frames", name));
+        }
+
+        let frame_time = match play_time {
+            Some(t) if t > 0.0 => t / frames.len() as f64,
+            _ => DEFAULT_FRAMETIME,
+        };
+
+        Ok(Preview::Frames { frames, frame_time })
+    }
+
+    fn load_iff(ctx: &egui::Context, name: &str, data: &[u8]) -> Result<Preview> {
+        let mut reader = BufReader::new(Cursor::new(data));
+        let resource =
+            image_format_iff::new(&mut reader, data.len() as u64).map_err(|e| anyhow!("{}", e))?;
+
+        let frames = resource
+            .frames()
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                ctx.load_texture(
+                    format!("{}:{}", name, i),
+                    argb_to_image(f.width(), f.height(), &f.to_argb32()),
+                    TextureOptions::NEAREST,
+                )
+            })
+            .collect();
+
+        Ok(Preview::Frames {
+            frames,
+            frame_time: DEFAULT_FRAMETIME,
+        })
+    }
+
+    /// Every distinct glyph page the font was packed into
+    fn load_font(ctx: &egui::Context, name: &str, data: &[u8]) -> Result<Preview> {
+        let mut reader = BufReader::new(Cursor::new(data));
+        let font = Font::new_from_steam(name.to_string(), &mut reader)?;
+        let range = font.get_ascii_range();
+        let graphic = FontGraphic::new(font);
+
+        let mut pages: Vec<Rc<dyn Bitmap16>> = Vec::new();
+
+        for index in range {
+            let src = graphic.get_char_tex_source(index);
+
+            if !pages.iter().any(|p| Rc::ptr_eq(p, &src.bitmap_src)) {
+                pages.push(src.bitmap_src);
+            }
+        }
+
+        let sheets = pages
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                ctx.load_texture(
+                    format!("{}:page{}", name, i),
+                    bitmap16_to_image(p.as_ref()),
+                    TextureOptions::NEAREST,
+                )
+            })
+            .collect();
+
+        Ok(Preview::Font {
+            sheets,
+            height: graphic.get_height(),
+        })
+    }
+
+    fn load_preview(&self, ctx: &egui::Context, name: &str, entry: &HogEntry) -> Result<Preview> {
+        let data = &entry.data;
+
+        match AssetKind::from_name(name) {
+            AssetKind::Bitmap => {
+                let bitmap = Self::load_ogf(data)?;
+
+                Ok(Preview::Image(ctx.load_texture(
+                    name,
+                    bitmap16_to_image(&bitmap),
+                    TextureOptions::NEAREST,
+                )))
+            }
+            AssetKind::Animation => Self::load_iff(ctx, name, data),
+            AssetKind::VideoClip => self.load_ifl(ctx, name, data),
+            AssetKind::Font => Self::load_font(ctx, name, data),
+            AssetKind::Model => Ok(Preview::Model),
+            AssetKind::Other => Ok(Preview::Raw {
+                size: data.len(),
+                flags: entry.flags,
+            }),
+        }
+    }
+
+    fn missing_texture(ctx: &egui::Context, name: &str) -> Preview {
+        let mut bitmap = GenericBitmap16::new(vec![0u16; 64 * 64], 64, 64);
+        bitmap.fill_debug_pattern(DebugPattern::Checkerboard);
+
+        Preview::Image(ctx.load_texture(
+            format!("{}:missing", name),
+            bitmap16_to_image(&bitmap),
+            TextureOptions::NEAREST,
+        ))
+    }
+
+    fn select(&mut self, ctx: &egui::Context, hog: usize, name: String) {
+        self.frame = 0;
+        self.frame_elapsed = 0.0;
+        self.status.clear();
+
+        let entry = &self.hogs[hog].hog.borrow_entries()[&name];
+
+        self.preview = match self.load_preview(ctx, &name, entry) {
+            Ok(preview) => preview,
+            Err(e) => {
+                self.status = format!("failed to load {}: {}", name, e);
+
+                // Broken bitmaps show up the way a missing texture would in game
+                if AssetKind::from_name(&name) == AssetKind::Bitmap {
+                    Self::missing_texture(ctx, &name)
+                } else {
+                    Preview::None
+                }
+            }
+        };
+
+        self.selected = Some((hog, name));
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+
+        egui::SidePanel::left("asset_browser")
+            .resizable(true)
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                ui.heading("Assets");
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.hog_path);
+
+                    if ui.button("Mount").clicked() && !self.hog_path.is_empty() {
+                        let path = self.hog_path.clone();
+
+                        self.status = match self.mount(&path) {
+                            Ok(()) => format!("mounted {}", path),
+                            Err(e) => format!("failed to mount {}: {}", path, e),
+                        };
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Filter:");
+                    ui.text_edit_singleline(&mut self.filter);
+                });
+
+                if !self.status.is_empty() {
+                    ui.label(&self.status);
+                }
+
+                ui.separator();
+
+                self.show_entries(ui);
+
+                ui.separator();
+
+                self.show_preview(ui);
+            });
+    }
+
+    fn show_entries(&mut self, ui: &mut Ui) {
+        let filter = self.filter.to_ascii_lowercase();
+        let mut clicked = None;
+        let mut unmount = None;
+
+        egui::ScrollArea::vertical()
+            .id_salt("asset_entries")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                for (i, mounted) in self.hogs.iter().enumerate() {
+                    egui::CollapsingHeader::new(format!(
+                        "{} ({})",
+                        mounted.path,
+                        mounted.names.len()
+                    ))
+                    .id_salt(&mounted.path)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        if ui.small_button("Unmount").clicked() {
+                            unmount = Some(i);
+                        }
+
+                        for name in &mounted.names {
+                            if !filter.is_empty() && !name.to_ascii_lowercase().contains(&filter) {
+                                continue;
+                            }
+
+                            let selected =
+                                matches!(&self.selected, Some((h, n)) if *h == i && n == name);
+                            let label =
+                                format!("{}  [{}]", name, AssetKind::from_name(name).label());
+
+                            if ui.selectable_label(selected, label).clicked() {
+                                clicked = Some((i, name.clone()));
+                            }
+                        }
+                    });
+                }
+            });
+
+        if let Some(index) = unmount {
+            self.unmount(index);
+        } else if let Some((hog, name)) = clicked {
+            self.select(ui.ctx(), hog, name);
+        }
+    }
+
+    fn show_preview(&mut self, ui: &mut Ui) {
+        let Some((_, name)) = &self.selected else {
+            ui.label("Nothing selected");
+            return;
+        };
+
+        ui.label(name);
+        ui.add(egui::Slider::new(&mut self.zoom, 1.0..=8.0).text("Zoom"));
+
+        let zoom = self.zoom;
+
+        match &self.preview {
+            Preview::None => {}
+            Preview::Image(tex) => {
+                ui.label(format!("{}x{}", tex.size()[0], tex.size()[1]));
+                ui.image((tex.id(), tex.size_vec2() * zoom));
+            }
+            Preview::Frames { frames, frame_time } => {
+                let dt = ui.input(|i| i.stable_dt) as f64;
+
+                if self.playing && frames.len() > 1 {
+                    self.frame_elapsed += dt;
+
+                    while self.frame_elapsed >= *frame_time {
+                        self.frame_elapsed -= frame_time;
+                        self.frame = (self.frame + 1) % frames.len();
+                    }
+
+                    ui.ctx().request_repaint();
+                }
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.playing, "Play");
+                    ui.label(format!("Frame {}/{}", self.frame + 1, frames.len()));
+                });
+
+                if !frames.is_empty() {
+                    let tex = &frames[self.frame.min(frames.len() - 1)];
+                    ui.image((tex.id(), tex.size_vec2() * zoom));
+                }
+            }
+            Preview::Font { sheets, height } => {
+                ui.label(format!("Height {}, {} glyph pages", height, sheets.len()));
+
+                for tex in sheets {
+                    ui.image((tex.id(), tex.size_vec2() * zoom));
+                }
+            }
+            Preview::Model => {
+                // PolyModel has no loader yet, the 3D view spins its stand-in mesh instead
+                ui.label(
+                    "Polymodel loading isn't implemented, spinning the placeholder in the 3D view",
+                );
+            }
+            Preview::Raw { size, flags } => {
+                ui.label(format!("{} bytes, flags {:#x}", size, flags));
+            }
+        }
+    }
+}
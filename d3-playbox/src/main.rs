@@ -1,23 +1,25 @@
 use std::default;
-
-use d3_core::{
-    graphics::drawing_3d::{
-        Camera, ClippingCode, Point3, RenderSetupState, ScreenViewPort,
-        legacy_soft::SoftRenderSetup,
-    },
-    math::{
-        matrix::{Matrix, Matrix4},
-        vector::Vector,
-    },
-};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+use asset_browser::AssetBrowser;
+use d3_core::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use d3_core::config::Config;
+#[cfg(not(target_arch = "wasm32"))]
+use d3_core::diagnostics::{self, logging::{self, RingLogger}};
 use egui::{TextureOptions, Ui};
 use euc::{Buffer2d, LineTriangleList, Pipeline, Target};
-use minifb::{Key, Window, WindowOptions};
 use once_cell::sync::Lazy;
+use proc_editor::ProcEditor;
 use rend_soft_options::SoftRenderOptions;
+use terrain_viewer::TerrainViewer;
 use vek::{Mat4, Rgba, Vec3, Vec4};
 
+mod asset_browser;
+mod proc_editor;
 mod rend_soft_options;
+mod terrain_viewer;
 mod ui;
 
 struct Cube {
@@ -83,10 +85,35 @@ static FLATTEN_CUBE: Lazy<Box<[Point3]>> = Lazy::new(|| {
 
 use eframe::egui;
 
+/// Where crash reports are written, next to where the playbox was run from
+#[cfg(not(target_arch = "wasm32"))]
+const CRASH_DIR: &str = "crashes";
+
+/// Settings file, also next to where the playbox was run from
+#[cfg(not(target_arch = "wasm32"))]
+const CONFIG_FILE: &str = "d3playbox.toml";
+
+#[cfg(not(target_arch = "wasm32"))]
 fn main() -> eframe::Result {
-    env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+    // Log to stderr and the ring crash reports take their tail from, the categories do the filtering
+    let stderr = env_logger::Builder::new().filter_level(log::LevelFilter::Trace).build();
+    RingLogger::new(Box::new(stderr)).install(log::LevelFilter::Info).expect("a logger was already installed");
+    logging::apply_env_log_spec();
+    diagnostics::install_panic_hook(PathBuf::from(CRASH_DIR));
+
+    let mut config = Config::load(Path::new(CONFIG_FILE)).unwrap_or_else(|e| {
+        log::warn!("couldn't load {}, using defaults: {:#}", CONFIG_FILE, e);
+        Config::default()
+    });
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    for arg in config.apply_args(&args) {
+        log::warn!("unknown argument {}", arg);
+    }
+
+    let render = &config.settings().render;
     let options = eframe::NativeOptions {
-        viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 600.0]),
+        viewport: egui::ViewportBuilder::default().with_inner_size([render.width as f32, render.height as f32]),
         ..Default::default()
     };
 
@@ -102,7 +129,50 @@ fn main() -> eframe::Result {
     )
 }
 
+/// Browser entry point, build with `trunk serve` from this directory. eframe draws through
+/// WebGL and the soft T&L demo runs on the CPU the same as it does natively
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use eframe::wasm_bindgen::JsCast;
+
+    // The console in the browser's dev tools, through the same categories as natively
+    let console = eframe::WebLogger::new(log::LevelFilter::Trace);
+    d3_core::diagnostics::logging::RingLogger::new(Box::new(console)).install(log::LevelFilter::Debug).ok();
+
+    wasm_bindgen_futures::spawn_local(async {
+        let canvas = eframe::web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("d3_playbox_canvas"))
+            .and_then(|element| element.dyn_into::<eframe::web_sys::HtmlCanvasElement>().ok())
+            .expect("Page has no d3_playbox_canvas canvas");
+
+        let result = eframe::WebRunner::new()
+            .start(
+                canvas,
+                eframe::WebOptions::default(),
+                Box::new(|cc| {
+                    egui_extras::install_image_loaders(&cc.egui_ctx);
+
+                    Ok(Box::<D3PlayboxApp>::default())
+                }),
+            )
+            .await;
+
+        if let Err(e) = result {
+            log::error!("Failed to start the playbox: {:?}", e);
+        }
+    });
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Scene {
+    Cube,
+    Terrain,
+}
+
 struct D3PlayboxApp {
+    scene: Scene,
+
     backbuffer: Option<egui::TextureHandle>,
     width: usize,
     height: usize,
@@ -120,11 +190,16 @@ struct D3PlayboxApp {
     // D3 Rendering
     soft_setup: SoftRenderSetup,
     d3_rend_soft_options: SoftRenderOptions,
+
+    asset_browser: AssetBrowser,
+    proc_editor: ProcEditor,
+    terrain_viewer: TerrainViewer,
 }
 
 impl Default for D3PlayboxApp {
     fn default() -> Self {
         Self {
+            scene: Scene::Cube,
             backbuffer: None,
             width: 800,
             height: 600,
@@ -138,23 +213,13 @@ impl Default for D3PlayboxApp {
             user_rotate_pitch: 0,
             user_pan_z: 0,
             d3_rend_soft_options: SoftRenderOptions::default(),
+            asset_browser: AssetBrowser::default(),
+            proc_editor: ProcEditor::default(),
+            terrain_viewer: TerrainViewer::default(),
 
             soft_setup: SoftRenderSetup {
-                aspect_override: None,
-                aspect: 0.0,
-                window_width: 0,
-                window_height: 0,
-                window_width_2: 0.0,
-                window_height_2: 0.0,
-                clipper_custom: None,
-                clipper_plane_point: Vector {
-                    x: 0.0,
-                    y: 0.0,
-                    z: 0.0,
-                },
-                xform_pipeline: Default::default(),
-                xform: Matrix4::identity(),
                 clipper_far_z: 100.0,
+                ..Default::default()
             },
         }
     }
@@ -191,10 +256,25 @@ impl D3PlayboxApp {
             self.user_pan_z = self.user_pan_z.wrapping_sub(15);
         }
 
-        let far_z = 100.0;
+        // Keep turning while the browser is previewing a model
+        if self.asset_browser.wants_spin() {
+            self.user_rotate_yaw = self.user_rotate_yaw.wrapping_add(10);
+            ui.ctx().request_repaint();
+        }
 
-        let projection =
-            Mat4::perspective_fov_lh_zo(1.3, self.width as f32, self.height as f32, 0.01, far_z);
+        let far_z = 100.0;
+        let fov = Fov::Degrees(90.0);
+
+        let viewport = ViewportBuilder::default()
+            .size(self.width, self.height)
+            .build()
+            .unwrap();
+
+        // Frames the scene the same way as the soft clipper
+        let projection = self
+            .soft_setup
+            .projection
+            .gpu_matrix(&viewport, fov.zoom(), 0.01, far_z);
         let camera_position = Vec3::new(0.0, 0.0, 3.0 + (self.user_pan_z as f32 * 0.0005));
         let scaling = Vec3::new(1.0, -1.0, 1.0);
 
@@ -203,23 +283,15 @@ impl D3PlayboxApp {
             * Mat4::rotation_z(0.0);
 
         if self.d3_rend_soft_options.enable {
-            let mut camera = Camera::default();
-
-            camera.position = camera_position.to_owned().into();
-            camera.transformation = camera_rot.to_owned().into();
-            camera.orientation = camera_rot.to_owned().into();
-            camera.scale = scaling.to_owned().into();
-
-            self.soft_setup.on_frame_start(
-                &ScreenViewPort {
-                    x: 0,
-                    y: 0,
-                    width: self.width,
-                    height: self.height,
-                    aspect: 1.3,
-                },
-                &camera,
-            );
+            let camera = CameraBuilder::default()
+                .position(camera_position.into())
+                .rotation(camera_rot.into())
+                .scale(scaling.into())
+                .fov(fov)
+                .build()
+                .unwrap();
+
+            self.soft_setup.on_frame_start(&viewport, &camera);
         }
 
         let view = Mat4::<f32>::translation_3d(camera_position)
@@ -317,6 +389,10 @@ impl eframe::App for D3PlayboxApp {
 
         egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
             ui.horizontal(|ui| {
+                ui.selectable_value(&mut self.scene, Scene::Cube, "Cube");
+                ui.selectable_value(&mut self.scene, Scene::Terrain, "Terrain");
+                ui.separator();
+
                 ui.menu_button("Legacy T&L", |ui| {
                     ui.label("D3 Legacy T&L:");
                     ui.checkbox(
@@ -336,9 +412,21 @@ impl eframe::App for D3PlayboxApp {
                     ui.checkbox(&mut self.d3_rend_soft_options.use_clip_right, "Clip Right");
                     ui.checkbox(&mut self.d3_rend_soft_options.use_clip_far, "Clip Far");
                 });
+
+                ui.toggle_value(&mut self.asset_browser.open, "Assets");
+                ui.toggle_value(&mut self.proc_editor.open, "Procedurals");
             });
         });
+
+        self.asset_browser.show(ctx);
+        self.proc_editor.show(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
+            if self.scene == Scene::Terrain {
+                self.terrain_viewer.show(ui);
+                return;
+            }
+
             self.render_3d(ui);
 
             ui.heading("D3 Software Test");
@@ -0,0 +1,375 @@
+use std::sync::{Arc, atomic::AtomicUsize};
+
+use d3_core::{
+    common::{ManualSystemClock, new_shared_mut_ref},
+    graphics::{
+        FrameCounter,
+        bitmap::Bitmap16,
+        color_conversion::convert_1555_to_32,
+        detail_settings::DetailSettings,
+        generic_bitmap::GenericBitmap16,
+        procedural::{
+            EmitterSettings, EmitterType, ProcPalette, ProceduralBitmap16,
+            ProceduralBitmap16Builder,
+        },
+    },
+};
+use egui::{ColorImage, TextureHandle, TextureOptions, Ui};
+
+const PROC_SIZE: usize = 128;
+
+/// Procedurals are evaluated at 30Hz like the game does
+const STEP_TIME: f64 = 1.0 / 30.0;
+
+/// Palettes to pick from, each is a list of (index, color) stops blended between
+const PALETTES: &[(&str, &[(usize, [u8; 3])])] = &[
+    ("Default", &[]),
+    (
+        "Fire",
+        &[
+            (0, [0, 0, 0]),
+            (96, [160, 0, 0]),
+            (176, [255, 160, 0]),
+            (255, [255, 255, 255]),
+        ],
+    ),
+    (
+        "Plasma",
+        &[(0, [0, 0, 0]), (128, [120, 0, 200]), (255, [255, 200, 255])],
+    ),
+    (
+        "Ice",
+        &[
+            (0, [0, 0, 32]),
+            (128, [0, 128, 255]),
+            (255, [255, 255, 255]),
+        ],
+    ),
+    (
+        "Toxic",
+        &[(0, [255, 255, 255]), (128, [0, 255, 0]), (255, [0, 0, 0])],
+    ),
+];
+
+fn build_palette(index: usize) -> ProcPalette {
+    let stops = PALETTES[index].1;
+
+    if stops.is_empty() {
+        return ProcPalette::DEFAULT;
+    }
+
+    let mut r = [0u8; ProcPalette::SIZE];
+    let mut g = [0u8; ProcPalette::SIZE];
+    let mut b = [0u8; ProcPalette::SIZE];
+
+    for pair in stops.windows(2) {
+        let (start, from) = pair[0];
+        let (end, to) = pair[1];
+
+        for i in start..=end {
+            let t = (i - start) as f32 / (end - start).max(1) as f32;
+            let lerp = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) as u8;
+
+            r[i] = lerp(from[0], to[0]);
+            g[i] = lerp(from[1], to[1]);
+            b[i] = lerp(from[2], to[2]);
+        }
+    }
+
+    ProcPalette::new(&r, &g, &b)
+}
+
+pub struct ProcEditor {
+    pub open: bool,
+    bitmap: ProceduralBitmap16,
+    frame_counter: FrameCounter,
+    /// Follows egui's clock so the editor runs the same natively and in a browser
+    clock: Arc<ManualSystemClock>,
+    emitters: Vec<EmitterSettings>,
+    palette: usize,
+    heat: u8,
+    osc_time: f32,
+    osc_value: u8,
+    thickness: u8,
+    light: i32,
+    paused: bool,
+    /// Emitters need rebuilding before the next step
+    dirty: bool,
+    elapsed: f64,
+    gametime: f32,
+    zoom: f32,
+    texture: Option<TextureHandle>,
+}
+
+impl Default for ProcEditor {
+    fn default() -> Self {
+        let frame_counter = FrameCounter::new(AtomicUsize::new(0));
+        let clock = Arc::new(ManualSystemClock::new());
+
+        // Water effects draw over the base bitmap, a blank one keeps them visible on their own
+        let base = GenericBitmap16::new(vec![0u16; PROC_SIZE * PROC_SIZE], PROC_SIZE, PROC_SIZE);
+
+        let bitmap = ProceduralBitmap16Builder::default()
+            .name("playbox_proc")
+            .dest_bitmap(PROC_SIZE, PROC_SIZE)
+            .detail_settings_ref(new_shared_mut_ref(DetailSettings {}))
+            .frame_counter_ref(frame_counter.clone())
+            .base_bitmap_ref(new_shared_mut_ref(base))
+            .system_clock_ref(clock.clone())
+            .build()
+            .unwrap();
+
+        let (osc_time, osc_value) = bitmap.oscillation();
+
+        Self {
+            open: false,
+            heat: bitmap.heat(),
+            bitmap,
+            frame_counter,
+            clock,
+            emitters: vec![EmitterSettings::default()],
+            palette: 0,
+            osc_time,
+            osc_value,
+            thickness: 4,
+            light: 0,
+            paused: false,
+            dirty: true,
+            elapsed: 0.0,
+            gametime: 0.0,
+            zoom: 3.0,
+            texture: None,
+        }
+    }
+}
+
+impl ProcEditor {
+    fn apply(&mut self) {
+        self.bitmap.set_palette(build_palette(self.palette));
+        self.bitmap.set_heat(self.heat);
+        self.bitmap.set_oscillation(self.osc_time, self.osc_value);
+        self.bitmap.set_water(self.thickness, self.light);
+        self.bitmap.set_emitters(&self.emitters);
+        self.dirty = false;
+    }
+
+    fn step(&mut self) {
+        if self.dirty {
+            self.apply();
+        }
+
+        self.bitmap.step(self.gametime);
+        self.gametime += STEP_TIME as f32;
+        self.frame_counter
+            .fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+    }
+
+    fn update_texture(&mut self, ctx: &egui::Context) {
+        let argb = convert_1555_to_32(self.bitmap.data());
+        let rgba: Vec<u8> = argb
+            .iter()
+            .flat_map(|&c| [(c >> 16) as u8, (c >> 8) as u8, c as u8, 0xFF])
+            .collect();
+
+        let image = ColorImage::from_rgba_unmultiplied([PROC_SIZE, PROC_SIZE], &rgba);
+
+        match self.texture {
+            Some(ref mut tex) => tex.set(image, TextureOptions::NEAREST),
+            None => {
+                self.texture = Some(ctx.load_texture("proc_editor", image, TextureOptions::NEAREST))
+            }
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.open {
+            return;
+        }
+
+        self.clock.set_seconds(ctx.input(|i| i.time));
+
+        if !self.paused {
+            self.elapsed += ctx.input(|i| i.stable_dt) as f64;
+
+            let mut stepped = false;
+
+            while self.elapsed >= STEP_TIME {
+                self.elapsed -= STEP_TIME;
+                self.step();
+                stepped = true;
+            }
+
+            if stepped || self.texture.is_none() {
+                self.update_texture(ctx);
+            }
+
+            ctx.request_repaint();
+        }
+
+        let mut open = self.open;
+
+        egui::Window::new("Procedural Editor")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                ui.horizontal_top(|ui| {
+                    ui.vertical(|ui| {
+                        if let Some(tex) = &self.texture {
+                            ui.image((tex.id(), tex.size_vec2() * self.zoom));
+                        }
+
+                        ui.add(egui::Slider::new(&mut self.zoom, 1.0..=4.0).text("Zoom"));
+
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.paused, "Pause");
+
+                            if ui.button("Step").clicked() {
+                                self.step();
+                                self.update_texture(ui.ctx());
+                            }
+
+                            if ui.button("Restart").clicked() {
+                                self.dirty = true;
+                            }
+                        });
+                    });
+
+                    ui.vertical(|ui| {
+                        self.show_settings(ui);
+                    });
+                });
+
+                ui.separator();
+
+                self.show_emitters(ui);
+            });
+
+        self.open = open;
+    }
+
+    fn show_settings(&mut self, ui: &mut Ui) {
+        let mut changed = false;
+
+        egui::ComboBox::from_label("Palette")
+            .selected_text(PALETTES[self.palette].0)
+            .show_ui(ui, |ui| {
+                for (i, (name, _)) in PALETTES.iter().enumerate() {
+                    changed |= ui.selectable_value(&mut self.palette, i, *name).changed();
+                }
+            });
+
+        ui.label("Fire");
+        changed |= ui
+            .add(egui::Slider::new(&mut self.heat, 0..=255).text("Heat"))
+            .changed();
+
+        ui.label("Water");
+        changed |= ui
+            .add(egui::Slider::new(&mut self.thickness, 0..=31).text("Thickness"))
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(&mut self.light, 0..=31).text("Light"))
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(&mut self.osc_time, 0.0..=10.0).text("Osc Time"))
+            .changed();
+        changed |= ui
+            .add(egui::Slider::new(&mut self.osc_value, 0..=31).text("Osc Value"))
+            .changed();
+
+        self.dirty |= changed;
+    }
+
+    fn show_emitters(&mut self, ui: &mut Ui) {
+        let mut changed = false;
+        let mut remove = None;
+
+        ui.horizontal(|ui| {
+            ui.heading(format!("Emitters ({})", self.emitters.len()));
+
+            if ui.button("Add").clicked() {
+                self.emitters.push(EmitterSettings::default());
+                changed = true;
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_salt("proc_emitters")
+            .max_height(260.0)
+            .show(ui, |ui| {
+                for (i, e) in self.emitters.iter_mut().enumerate() {
+                    egui::CollapsingHeader::new(format!("{}: {}", i, e.kind.name()))
+                        .id_salt(i)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            egui::ComboBox::from_id_salt(("emitter_kind", i))
+                                .selected_text(e.kind.name())
+                                .show_ui(ui, |ui| {
+                                    for kind in EmitterType::ALL {
+                                        changed |= ui
+                                            .selectable_value(&mut e.kind, kind, kind.name())
+                                            .changed();
+                                    }
+                                });
+
+                            changed |= ui
+                                .add(egui::Slider::new(&mut e.frequency, 0..=30).text("Frequency"))
+                                .changed();
+                            changed |= ui
+                                .add(egui::Slider::new(&mut e.speed, 0..=255).text("Speed"))
+                                .changed();
+                            changed |= ui
+                                .add(egui::Slider::new(&mut e.size, 0..=255).text("Size"))
+                                .changed();
+                            changed |= ui
+                                .add(egui::Slider::new(&mut e.color, 0..=255).text("Color"))
+                                .changed();
+
+                            let max = PROC_SIZE as f32;
+
+                            ui.horizontal(|ui| {
+                                changed |= ui
+                                    .add(
+                                        egui::DragValue::new(&mut e.x1)
+                                            .range(0.0..=max)
+                                            .prefix("x1: "),
+                                    )
+                                    .changed();
+                                changed |= ui
+                                    .add(
+                                        egui::DragValue::new(&mut e.y1)
+                                            .range(0.0..=max)
+                                            .prefix("y1: "),
+                                    )
+                                    .changed();
+                                changed |= ui
+                                    .add(
+                                        egui::DragValue::new(&mut e.x2)
+                                            .range(0.0..=max)
+                                            .prefix("x2: "),
+                                    )
+                                    .changed();
+                                changed |= ui
+                                    .add(
+                                        egui::DragValue::new(&mut e.y2)
+                                            .range(0.0..=max)
+                                            .prefix("y2: "),
+                                    )
+                                    .changed();
+                            });
+
+                            if ui.small_button("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                }
+            });
+
+        if let Some(i) = remove {
+            self.emitters.remove(i);
+            changed = true;
+        }
+
+        self.dirty |= changed;
+    }
+}
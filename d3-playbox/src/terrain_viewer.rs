@@ -0,0 +1,431 @@
+use anyhow::Result;
+use d3_core::{
+    game::terrain::{
+        HeightMapSettings, MAX_LOD, TERRAIN_DEPTH, TERRAIN_SIZE, TERRAIN_WIDTH, Terrain,
+    },
+    math::vector::Vector,
+};
+use egui::{Key, TextureHandle, TextureOptions, Ui};
+use euc::{Buffer2d, CullMode, DepthMode, LineTriangleList, Pipeline, Target, TriangleList};
+use vek::{Mat4, Rgba, Vec3, Vec4};
+
+const FAR_Z: f32 = 8000.0;
+
+/// Direction the light comes from when shading by normals
+const LIGHT_DIR: Vec3<f32> = Vec3::new(-0.5, -0.7, 0.5);
+
+type TerrainVertex = (Vec4<f32>, Rgba<f32>);
+
+struct SolidTerrain {
+    mvp: Mat4<f32>,
+}
+
+impl<'r> Pipeline<'r> for SolidTerrain {
+    type Vertex = TerrainVertex;
+    type VertexData = Rgba<f32>;
+    type Primitives = TriangleList;
+    type Pixel = u32;
+    type Fragment = Rgba<f32>;
+
+    fn depth_mode(&self) -> DepthMode {
+        DepthMode::LESS_WRITE
+    }
+
+    fn rasterizer_config(&self) -> CullMode {
+        CullMode::None
+    }
+
+    #[inline(always)]
+    fn vertex(&self, (pos, color): &Self::Vertex) -> ([f32; 4], Self::VertexData) {
+        ((self.mvp * *pos).into_array(), *color)
+    }
+
+    #[inline(always)]
+    fn fragment(&self, color: Self::VertexData) -> Self::Fragment {
+        color
+    }
+
+    fn blend(&self, _: Self::Pixel, color: Self::Fragment) -> Self::Pixel {
+        u32::from_le_bytes((color * 255.0).as_().into_array())
+    }
+}
+
+struct WireTerrain {
+    mvp: Mat4<f32>,
+}
+
+impl<'r> Pipeline<'r> for WireTerrain {
+    type Vertex = TerrainVertex;
+    type VertexData = Rgba<f32>;
+    type Primitives = LineTriangleList;
+    type Pixel = u32;
+    type Fragment = Rgba<f32>;
+
+    fn depth_mode(&self) -> DepthMode {
+        DepthMode::LESS_WRITE
+    }
+
+    #[inline(always)]
+    fn vertex(&self, (pos, color): &Self::Vertex) -> ([f32; 4], Self::VertexData) {
+        ((self.mvp * *pos).into_array(), *color)
+    }
+
+    #[inline(always)]
+    fn fragment(&self, color: Self::VertexData) -> Self::Fragment {
+        color
+    }
+
+    fn blend(&self, _: Self::Pixel, color: Self::Fragment) -> Self::Pixel {
+        u32::from_le_bytes((color * 255.0).as_().into_array())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shading {
+    Height,
+    Normals,
+    LodDelta,
+}
+
+/// WASD fly camera, yaw and pitch in radians
+struct FlyCamera {
+    position: Vec3<f32>,
+    yaw: f32,
+    pitch: f32,
+    speed: f32,
+}
+
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(TERRAIN_WIDTH as f32 * TERRAIN_SIZE / 2.0, 600.0, -200.0),
+            yaw: 0.0,
+            pitch: -0.3,
+            speed: 800.0,
+        }
+    }
+}
+
+impl FlyCamera {
+    fn rotation(&self) -> Mat4<f32> {
+        Mat4::rotation_y(self.yaw) * Mat4::rotation_x(self.pitch)
+    }
+
+    fn view(&self) -> Mat4<f32> {
+        Mat4::rotation_x(-self.pitch)
+            * Mat4::rotation_y(-self.yaw)
+            * Mat4::translation_3d(-self.position)
+    }
+
+    fn update(&mut self, ui: &Ui, dt: f32) {
+        let rotation = self.rotation();
+        let forward = (rotation * Vec4::new(0.0, 0.0, 1.0, 0.0)).xyz();
+        let right = (rotation * Vec4::new(1.0, 0.0, 0.0, 0.0)).xyz();
+        let up = Vec3::unit_y();
+
+        let mut movement = Vec3::zero();
+
+        ui.input(|i| {
+            if i.key_down(Key::W) {
+                movement += forward;
+            }
+            if i.key_down(Key::S) {
+                movement -= forward;
+            }
+            if i.key_down(Key::D) {
+                movement += right;
+            }
+            if i.key_down(Key::A) {
+                movement -= right;
+            }
+            if i.key_down(Key::E) {
+                movement += up;
+            }
+            if i.key_down(Key::Q) {
+                movement -= up;
+            }
+
+            let boost = if i.modifiers.shift { 4.0 } else { 1.0 };
+
+            self.position += movement * self.speed * boost * dt;
+        });
+    }
+
+    fn look(&mut self, delta: egui::Vec2) {
+        const SENSITIVITY: f32 = 0.005;
+
+        self.yaw += delta.x * SENSITIVITY;
+        self.pitch = (self.pitch - delta.y * SENSITIVITY).clamp(-1.5, 1.5);
+    }
+}
+
+pub struct TerrainViewer {
+    terrain: Option<Box<Terrain>>,
+    settings: HeightMapSettings,
+    raw_path: String,
+    status: String,
+
+    camera: FlyCamera,
+    wireframe: bool,
+    shading: Shading,
+    /// Which LOD the mesh is built at, MAX_LOD - 1 is every cell
+    lod: usize,
+
+    mesh: Vec<TerrainVertex>,
+    mesh_dirty: bool,
+
+    width: usize,
+    height: usize,
+    color: Buffer2d<u32>,
+    depth: Buffer2d<f32>,
+    texture: Option<TextureHandle>,
+}
+
+impl Default for TerrainViewer {
+    fn default() -> Self {
+        Self {
+            terrain: None,
+            settings: HeightMapSettings::default(),
+            raw_path: String::new(),
+            status: String::new(),
+            camera: FlyCamera::default(),
+            wireframe: false,
+            shading: Shading::Normals,
+            lod: 1,
+            mesh: Vec::new(),
+            mesh_dirty: true,
+            width: 0,
+            height: 0,
+            color: Buffer2d::fill([1, 1], 0),
+            depth: Buffer2d::fill([1, 1], 1.0),
+            texture: None,
+        }
+    }
+}
+
+fn height_color(height: u8) -> Rgba<f32> {
+    let h = height as f32 / 255.0;
+    Rgba::new(h * 0.6 + 0.2, h * 0.8 + 0.2, h * 0.5 + 0.1, 1.0)
+}
+
+fn shade_normal(normal: &Vector) -> Rgba<f32> {
+    let n = Vec3::from(*normal);
+    let light = n.dot(-LIGHT_DIR.normalized()).max(0.0) * 0.8 + 0.2;
+    Rgba::new(light, light, light, 1.0)
+}
+
+/// Green when the block is nearly flat, red as its error grows, blue when shut off
+fn delta_color(delta: Option<f32>, max_delta: f32) -> Rgba<f32> {
+    match delta {
+        Some(d) => {
+            let t = (d / max_delta.max(0.001)).clamp(0.0, 1.0);
+            Rgba::new(t, 1.0 - t, 0.0, 1.0)
+        }
+        None => Rgba::new(0.0, 0.0, 1.0, 1.0),
+    }
+}
+
+impl TerrainViewer {
+    fn terrain(&mut self) -> &mut Terrain {
+        self.terrain.get_or_insert_with(|| {
+            let mut terrain = Box::new(Terrain::default());
+            terrain.generate_height_map(&HeightMapSettings::default());
+            terrain
+        })
+    }
+
+    fn generate(&mut self) {
+        let settings = self.settings;
+        self.terrain().generate_height_map(&settings);
+        self.mesh_dirty = true;
+    }
+
+    fn load_raw(&mut self) -> Result<()> {
+        let data = std::fs::read(&self.raw_path)?;
+        self.terrain().load_height_map_raw(&data)?;
+        self.mesh_dirty = true;
+        Ok(())
+    }
+
+    fn build_mesh(&mut self) {
+        let step = 1 << ((MAX_LOD - 1) - self.lod);
+        let lod = self.lod;
+        let shading = self.shading;
+        let terrain = self.terrain.as_deref().unwrap();
+
+        let max_delta = (0..TERRAIN_DEPTH)
+            .step_by(step)
+            .flat_map(|z| (0..TERRAIN_WIDTH).step_by(step).map(move |x| (x, z)))
+            .filter_map(|(x, z)| terrain.lod_delta(lod, x, z))
+            .fold(0.0f32, f32::max);
+
+        self.mesh.clear();
+
+        for z in (0..TERRAIN_DEPTH - step).step_by(step) {
+            for x in (0..TERRAIN_WIDTH - step).step_by(step) {
+                let corners = [(x, z), (x, z + step), (x + step, z + step), (x + step, z)];
+                let points = corners.map(|(x, z)| Vec4::from(terrain.cell_position(x, z)));
+
+                // Same split as the normal builder: upper left is 0 1 2, lower right is 0 2 3
+                let (upper_left, lower_right) = terrain.cell_normals(x, z);
+
+                let (first, second) = match shading {
+                    Shading::Height => {
+                        let c = corners.map(|(x, z)| height_color(terrain.cell_height(x, z)));
+                        ([c[0], c[1], c[2]], [c[0], c[2], c[3]])
+                    }
+                    Shading::Normals => (
+                        [shade_normal(&upper_left); 3],
+                        [shade_normal(&lower_right); 3],
+                    ),
+                    Shading::LodDelta => {
+                        let c = delta_color(terrain.lod_delta(lod, x, z), max_delta);
+                        ([c; 3], [c; 3])
+                    }
+                };
+
+                for (i, color) in [0, 1, 2].into_iter().zip(first) {
+                    self.mesh.push((points[i], color));
+                }
+
+                for (i, color) in [0, 2, 3].into_iter().zip(second) {
+                    self.mesh.push((points[i], color));
+                }
+            }
+        }
+
+        self.mesh_dirty = false;
+    }
+
+    fn render(&mut self, width: usize, height: usize) {
+        if width != self.width || height != self.height {
+            self.width = width;
+            self.height = height;
+            self.color = Buffer2d::fill([width, height], 0);
+            self.depth = Buffer2d::fill([width, height], 1.0);
+        }
+
+        let projection = Mat4::perspective_fov_lh_zo(1.3, width as f32, height as f32, 1.0, FAR_Z);
+        let mvp = Mat4::<f32>::scaling_3d(Vec3::new(1.0, -1.0, 1.0)) * projection * self.camera.view();
+
+        self.color.clear(0);
+        self.depth.clear(1.0);
+
+        if self.wireframe {
+            WireTerrain { mvp }.render(self.mesh.as_slice(), &mut self.color, &mut self.depth);
+        } else {
+            SolidTerrain { mvp }.render(self.mesh.as_slice(), &mut self.color, &mut self.depth);
+        }
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui) {
+        let mut rebuild = false;
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.wireframe, "Wireframe");
+
+            ui.label("Shading:");
+            rebuild |= ui
+                .selectable_value(&mut self.shading, Shading::Height, "Height")
+                .changed();
+            rebuild |= ui
+                .selectable_value(&mut self.shading, Shading::Normals, "Normals")
+                .changed();
+            rebuild |= ui
+                .selectable_value(&mut self.shading, Shading::LodDelta, "LOD Delta")
+                .changed();
+
+            rebuild |= ui
+                .add(egui::Slider::new(&mut self.lod, 0..=MAX_LOD - 1).text("LOD"))
+                .changed();
+
+            ui.add(egui::Slider::new(&mut self.camera.speed, 50.0..=4000.0).text("Speed"));
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.settings.seed).prefix("Seed: "));
+            ui.add(egui::Slider::new(&mut self.settings.fbm.octaves, 1..=8).text("Octaves"));
+            ui.add(
+                egui::Slider::new(&mut self.settings.fbm.frequency, 0.001..=0.1)
+                    .logarithmic(true)
+                    .text("Frequency"),
+            );
+            ui.add(egui::Slider::new(&mut self.settings.min_height, 0..=255).text("Min"));
+            ui.add(egui::Slider::new(&mut self.settings.max_height, 0..=255).text("Max"));
+
+            if ui.button("Generate").clicked() {
+                self.generate();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Raw height map:");
+            ui.text_edit_singleline(&mut self.raw_path);
+
+            if ui.button("Load").clicked() {
+                self.status = match self.load_raw() {
+                    Ok(()) => format!("loaded {}", self.raw_path),
+                    Err(e) => format!("failed to load {}: {}", self.raw_path, e),
+                };
+            }
+
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+        });
+
+        self.mesh_dirty |= rebuild;
+    }
+
+    pub fn show(&mut self, ui: &mut Ui) {
+        self.terrain();
+
+        self.show_controls(ui);
+
+        if self.mesh_dirty {
+            self.build_mesh();
+        }
+
+        if !ui.ctx().wants_keyboard_input() {
+            self.camera.update(ui, ui.input(|i| i.stable_dt));
+        }
+
+        let p = self.camera.position;
+        ui.label(format!(
+            "WASD to fly, Q/E down/up, shift to speed up, drag to look.  {:.0} {:.0} {:.0}, {} triangles",
+            p.x,
+            p.y,
+            p.z,
+            self.mesh.len() / 3
+        ));
+
+        let size = ui.available_size();
+        let width = (size.x as usize).max(1);
+        let height = (size.y as usize).max(1);
+
+        self.render(width, height);
+
+        let rgba_slice: &[u8] = bytemuck::cast_slice(self.color.raw());
+        let image = egui::ColorImage::from_rgba_unmultiplied([width, height], rgba_slice);
+
+        match self.texture {
+            Some(ref mut tex) => tex.set(image, TextureOptions::NEAREST),
+            None => {
+                self.texture = Some(ui.ctx().load_texture(
+                    "terrain_view",
+                    image,
+                    TextureOptions::NEAREST,
+                ))
+            }
+        }
+
+        let tex = self.texture.as_ref().unwrap();
+        let response = ui.add(egui::Image::new(tex).sense(egui::Sense::drag()));
+
+        if response.dragged() {
+            self.camera.look(response.drag_delta());
+        }
+
+        ui.ctx().request_repaint();
+    }
+}
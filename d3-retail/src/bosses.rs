@@ -0,0 +1,57 @@
+use d3_core::game::boss::{BossInfo, BossPhase, BossTrigger, DeathStep, TeleportRule};
+use d3_core::game::prelude::*;
+
+use crate::RetailFireballEffectType;
+
+/// The Mercenary boss, teleporting between `nodes` once it's hurt and calling in help from `matcens`
+///
+/// Node and matcen indices come from the level it's placed in.
+pub fn merc_boss(nodes: Vec<usize>, matcens: Vec<usize>) -> BossInfo<RetailFireballEffectType> {
+    BossInfo {
+        name: D3String::from("Merc Boss"),
+        phases: vec![
+            // Shielded while the intro plays out
+            BossPhase {
+                invulnerable_time: 3.0,
+                ..Default::default()
+            },
+            BossPhase {
+                trigger: BossTrigger::HealthBelow(0.66),
+                teleport: Some(TeleportRule {
+                    nodes: nodes.clone(),
+                    interval: 8.0,
+                    effect: Some(RetailFireballEffectType::BlueBlastRing),
+                }),
+                summon: matcens.clone(),
+                ..Default::default()
+            },
+            // Goes to ground and calls everything in before the last stand
+            BossPhase {
+                trigger: BossTrigger::HealthBelow(0.33),
+                invulnerable_time: 5.0,
+                teleport: Some(TeleportRule {
+                    nodes,
+                    interval: 4.0,
+                    effect: Some(RetailFireballEffectType::BlueBlastRing),
+                }),
+                summon: matcens,
+            },
+        ],
+        death_sequence: vec![
+            merc_death_step(0.0, RetailFireballEffectType::MercBossMassDriverEffect, 20.0),
+            merc_death_step(0.5, RetailFireballEffectType::MedExplosion, 15.0),
+            merc_death_step(1.0, RetailFireballEffectType::MedExplosion3, 20.0),
+            merc_death_step(1.5, RetailFireballEffectType::BlastRing, 40.0),
+            merc_death_step(2.0, RetailFireballEffectType::BigExplosion, 50.0),
+        ],
+    }
+}
+
+fn merc_death_step(delay: f32, effect: RetailFireballEffectType, size: f32) -> DeathStep<RetailFireballEffectType> {
+    DeathStep {
+        delay,
+        effect,
+        size,
+        sound: None,
+    }
+}
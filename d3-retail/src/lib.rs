@@ -2,5 +2,6 @@
 // TODO: REMOVE THIS EVENTUALLY!
 #![allow(warnings)]
 
+pub mod bosses;
 pub mod visual_effects;
 